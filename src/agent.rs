@@ -4,12 +4,57 @@
 //! has a human-readable name and tracks its session history.
 
 use chrono::{DateTime, Utc};
+use clap::ValueEnum;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use sqlx::SqlitePool;
 use uuid::Uuid;
 
+use crate::attribution::Attribution;
+use crate::git::GitContext;
+use crate::launcher;
+use crate::storage::add_column_if_missing;
+
+#[derive(Debug, thiserror::Error)]
+pub enum AgentError {
+    #[error("no agent named '{0}'")]
+    NotFound(String),
+    #[error("an agent named '{0}' already exists")]
+    NameTaken(String),
+    #[error("invalid name or tag {0}")]
+    Invalid(String),
+    #[error(transparent)]
+    Db(#[from] sqlx::Error),
+}
+
+/// Header naming the agent, read with `--agent-naming header`
+pub const AGENT_NAME_HEADER: &str = "x-sentinel-agent-name";
+/// Room left after a directory name for its counter
+const MAX_DIRECTORY_NAME_LEN: usize = 56;
+
+/// How an agent first seen by its session is named (`--agent-naming`)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum Naming {
+    /// A random adjective and noun, like "swift-fox"
+    #[default]
+    AdjectiveNoun,
+    /// The working directory's name and a counter, like "sentinel-3"
+    Directory,
+    /// Adjective-noun, kept for configs that asked for the client's
+    /// x-sentinel-agent-name, which is now honored under every strategy
+    Header,
+}
+
+/// Tables that record events under the agent's name
+const NAMED_TABLES: &[&str] = &[
+    "usage_rollups",
+    "tool_calls",
+    "code_blocks",
+    "run_agents",
+];
+
 /// An agent represents a logical Claude Code instance that can span multiple sessions.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct Agent {
     pub id: Uuid,
     pub name: String,
@@ -19,10 +64,33 @@ pub struct Agent {
     pub last_seen_at: DateTime<Utc>,
     pub status: AgentStatus,
     pub topic: Option<String>,
+    /// Labels given with `sentinel tag` or x-sentinel-tag, sorted
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// Project named by the latest x-sentinel-project header
+    #[serde(default)]
+    pub project: Option<String>,
+    /// Repository state at the latest request, when the working directory is
+    /// a git checkout on this machine
+    #[serde(default)]
+    pub git: Option<GitContext>,
+    /// The agent whose Task call started this one as a subagent
+    #[serde(default)]
+    pub parent_id: Option<Uuid>,
 }
 
+/// A session that had a commit checked out, for `sentinel agents --commit`
+#[derive(Debug, Clone)]
+pub struct CommitSession {
+    pub agent: String,
+    pub session_id: String,
+    pub commit: String,
+    pub branch: Option<String>,
+    /// First request of the session with the commit at HEAD
+    pub first_seen_at: DateTime<Utc>,
+}
 
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 pub enum AgentStatus {
     Active,
@@ -65,15 +133,47 @@ pub fn generate_name() -> String {
     format!("{}-{}", ADJECTIVES[adj_idx], NOUNS[noun_idx])
 }
 
+/// The last component of a working directory as the start of an agent name,
+/// e.g. "sentinel" for "/home/me/src/sentinel"
+fn directory_name(working_directory: &str) -> Option<String> {
+    let last = working_directory
+        .trim_end_matches(['/', '\\'])
+        .rsplit(['/', '\\'])
+        .next()?;
+    let name: String = last
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.') {
+                c.to_ascii_lowercase()
+            } else {
+                '-'
+            }
+        })
+        .take(MAX_DIRECTORY_NAME_LEN)
+        .collect();
+    let name = name.trim_matches(['-', '.']);
+    (!name.is_empty()).then(|| name.to_string())
+}
+
 /// Agent storage operations
 #[derive(Clone)]
 pub struct AgentStore {
     pool: SqlitePool,
+    naming: Naming,
 }
 
 impl AgentStore {
     pub fn new(pool: SqlitePool) -> Self {
-        Self { pool }
+        Self {
+            pool,
+            naming: Naming::default(),
+        }
+    }
+
+    /// Name new agents this way rather than adjective-noun
+    pub fn with_naming(mut self, naming: Naming) -> Self {
+        self.naming = naming;
+        self
     }
 
     pub async fn init_schema(&self) -> Result<(), sqlx::Error> {
@@ -85,9 +185,16 @@ impl AgentStore {
                 session_id TEXT NOT NULL,
                 working_directory TEXT,
                 topic TEXT,
+                project TEXT,
+                git_remote TEXT,
+                git_branch TEXT,
+                git_commit TEXT,
                 created_at TEXT NOT NULL,
                 last_seen_at TEXT NOT NULL,
-                status TEXT NOT NULL
+                status TEXT NOT NULL,
+                parent_id TEXT,
+                parent_tool_use_id TEXT,
+                parent_linked_by TEXT
             )
             "#,
         )
@@ -111,12 +218,46 @@ impl AgentStore {
         .await?;
 
         // Migration: add topic column if missing (existing databases)
+        add_column_if_missing(&self.pool, "agents", "topic", "TEXT").await?;
+        add_column_if_missing(&self.pool, "agents", "project", "TEXT").await?;
+        for column in ["git_remote", "git_branch", "git_commit"] {
+            add_column_if_missing(&self.pool, "agents", column, "TEXT").await?;
+        }
+        for column in ["parent_id", "parent_tool_use_id", "parent_linked_by"] {
+            add_column_if_missing(&self.pool, "agents", column, "TEXT").await?;
+        }
+
+        // Each commit a session has seen at HEAD, from the first request it did
         sqlx::query(
-            r#"ALTER TABLE agents ADD COLUMN topic TEXT"#,
+            r#"
+            CREATE TABLE IF NOT EXISTS agent_commits (
+                commit_sha TEXT NOT NULL,
+                agent_id TEXT NOT NULL,
+                session_id TEXT NOT NULL,
+                branch TEXT,
+                first_seen_at TEXT NOT NULL,
+                PRIMARY KEY (commit_sha, agent_id, session_id)
+            )
+            "#,
         )
         .execute(&self.pool)
-        .await
-        .ok();
+        .await?;
+
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS agent_tags (
+                agent_id TEXT NOT NULL,
+                tag TEXT NOT NULL,
+                PRIMARY KEY (agent_id, tag)
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        sqlx::query("CREATE INDEX IF NOT EXISTS idx_agent_tags_tag ON agent_tags(tag)")
+            .execute(&self.pool)
+            .await?;
 
         Ok(())
     }
@@ -142,7 +283,53 @@ impl AgentStore {
             return Ok(agent);
         }
 
-        // Create new agent with generated name
+        let now = Utc::now();
+        let mut agent = Agent {
+            id: Uuid::new_v4(),
+            name: self.new_name(working_directory).await?,
+            session_id: session_id.to_string(),
+            working_directory: working_directory.map(String::from),
+            created_at: now,
+            last_seen_at: now,
+            status: AgentStatus::Active,
+            topic: None,
+            tags: Vec::new(),
+            project: None,
+            git: None,
+            parent_id: None,
+        };
+
+        // Sessions starting together can be given the same name; all but the
+        // first to be inserted are named again
+        let mut attempts = 1;
+        loop {
+            match self.insert(&agent).await {
+                Ok(()) => break,
+                Err(sqlx::Error::Database(e)) if e.is_unique_violation() && attempts < 10 => {
+                    attempts += 1;
+                    agent.name = self.new_name(working_directory).await?;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+
+        tracing::info!(
+            "New agent '{}' created for session {}",
+            agent.name,
+            session_id
+        );
+
+        Ok(agent)
+    }
+
+    /// A name for a new agent, by the configured naming
+    async fn new_name(&self, working_directory: Option<&str>) -> Result<String, sqlx::Error> {
+        if self.naming == Naming::Directory {
+            if let Some(base) = working_directory.and_then(directory_name) {
+                return self.next_numbered(&base).await;
+            }
+        }
+
         let mut name = generate_name();
 
         // Ensure name is unique (rare collision case)
@@ -151,35 +338,76 @@ impl AgentStore {
             name = generate_name();
             attempts += 1;
         }
+        Ok(name)
+    }
+
+    /// `<base>-<n>`, one past the highest n given so far
+    async fn next_numbered(&self, base: &str) -> Result<String, sqlx::Error> {
+        let prefix = format!("{}-", base);
+        let names: Vec<String> =
+            sqlx::query_scalar("SELECT name FROM agents WHERE substr(name, 1, ?) = ?")
+                .bind(prefix.len() as i64)
+                .bind(&prefix)
+                .fetch_all(&self.pool)
+                .await?;
+        let last = names
+            .iter()
+            .filter_map(|name| name[prefix.len()..].parse::<u64>().ok())
+            .max()
+            .unwrap_or(0);
+        Ok(format!("{}{}", prefix, last + 1))
+    }
+
+    /// Find or create the agent named by `sentinel run --agent`, or by the
+    /// client's x-sentinel-agent-name header. The name is kept across
+    /// sessions; the agent follows the latest one it's seen with.
+    pub async fn get_or_create_named_agent(
+        &self,
+        name: &str,
+        session_id: Option<&str>,
+        working_directory: Option<&str>,
+    ) -> Result<Agent, sqlx::Error> {
+        if let Some(mut agent) = self.find_by_name(name).await? {
+            self.update_last_seen(&agent.id, AgentStatus::Active)
+                .await?;
+            if let Some(session_id) = session_id.filter(|s| *s != agent.session_id) {
+                self.update_session_id(&agent.id, session_id).await?;
+                agent.session_id = session_id.to_string();
+            }
+            if working_directory.is_some() && agent.working_directory.is_none() {
+                self.update_working_directory(&agent.id, working_directory)
+                    .await?;
+                agent.working_directory = working_directory.map(String::from);
+            }
+            return Ok(agent);
+        }
 
         let now = Utc::now();
         let agent = Agent {
             id: Uuid::new_v4(),
-            name,
-            session_id: session_id.to_string(),
+            name: name.to_string(),
+            // Clients other than Claude Code send no session of their own
+            session_id: session_id.unwrap_or(name).to_string(),
             working_directory: working_directory.map(String::from),
             created_at: now,
             last_seen_at: now,
             status: AgentStatus::Active,
             topic: None,
+            tags: Vec::new(),
+            project: None,
+            git: None,
+            parent_id: None,
         };
-
         self.insert(&agent).await?;
-
-        tracing::info!(
-            "New agent '{}' created for session {}",
-            agent.name,
-            session_id
-        );
-
+        tracing::info!("New agent '{}' created by name", agent.name);
         Ok(agent)
     }
 
     async fn insert(&self, agent: &Agent) -> Result<(), sqlx::Error> {
         sqlx::query(
             r#"
-            INSERT INTO agents (id, name, session_id, working_directory, topic, created_at, last_seen_at, status)
-            VALUES (?, ?, ?, ?, ?, ?, ?, ?)
+            INSERT INTO agents (id, name, session_id, working_directory, topic, project, git_remote, git_branch, git_commit, created_at, last_seen_at, status, parent_id)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
             "#,
         )
         .bind(agent.id.to_string())
@@ -187,19 +415,59 @@ impl AgentStore {
         .bind(&agent.session_id)
         .bind(&agent.working_directory)
         .bind(&agent.topic)
+        .bind(&agent.project)
+        .bind(agent.git.as_ref().and_then(|git| git.remote.as_ref()))
+        .bind(agent.git.as_ref().and_then(|git| git.branch.as_ref()))
+        .bind(agent.git.as_ref().map(|git| &git.commit))
         .bind(agent.created_at.to_rfc3339())
         .bind(agent.last_seen_at.to_rfc3339())
         .bind(agent.status.to_string())
+        .bind(agent.parent_id.map(|id| id.to_string()))
         .execute(&self.pool)
         .await?;
 
         Ok(())
     }
 
+    /// Add an agent exported from another database, keeping its id and name.
+    /// False if its session is already known, or its id or name is taken.
+    pub async fn import(&self, agent: &Agent) -> Result<bool, sqlx::Error> {
+        if self.find_by_session_id(&agent.session_id).await?.is_some() {
+            return Ok(false);
+        }
+        let result = sqlx::query(
+            r#"
+            INSERT OR IGNORE INTO agents (id, name, session_id, working_directory, topic, project, git_remote, git_branch, git_commit, created_at, last_seen_at, status, parent_id)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+            "#,
+        )
+        .bind(agent.id.to_string())
+        .bind(&agent.name)
+        .bind(&agent.session_id)
+        .bind(&agent.working_directory)
+        .bind(&agent.topic)
+        .bind(&agent.project)
+        .bind(agent.git.as_ref().and_then(|git| git.remote.as_ref()))
+        .bind(agent.git.as_ref().and_then(|git| git.branch.as_ref()))
+        .bind(agent.git.as_ref().map(|git| &git.commit))
+        .bind(agent.created_at.to_rfc3339())
+        .bind(agent.last_seen_at.to_rfc3339())
+        .bind(agent.status.to_string())
+        .bind(agent.parent_id.map(|id| id.to_string()))
+        .execute(&self.pool)
+        .await?;
+        if result.rows_affected() == 0 {
+            return Ok(false);
+        }
+        self.insert_tags(&agent.id, &agent.tags).await?;
+        Ok(true)
+    }
+
     pub async fn find_by_session_id(&self, session_id: &str) -> Result<Option<Agent>, sqlx::Error> {
         let row: Option<AgentRow> = sqlx::query_as(
             r#"
-                SELECT id, name, session_id, working_directory, topic, created_at, last_seen_at, status
+                SELECT id, name, session_id, working_directory, topic, project, git_remote, git_branch, git_commit, created_at, last_seen_at, status, parent_id,
+                    (SELECT group_concat(tag, ',') FROM agent_tags WHERE agent_id = agents.id) AS tags
                 FROM agents
                 WHERE session_id = ?
                 "#,
@@ -208,13 +476,14 @@ impl AgentStore {
         .fetch_optional(&self.pool)
         .await?;
 
-        Ok(row.and_then(Self::row_to_agent))
+        Ok(row.and_then(parse_row))
     }
 
     pub async fn find_by_name(&self, name: &str) -> Result<Option<Agent>, sqlx::Error> {
         let row: Option<AgentRow> = sqlx::query_as(
             r#"
-                SELECT id, name, session_id, working_directory, topic, created_at, last_seen_at, status
+                SELECT id, name, session_id, working_directory, topic, project, git_remote, git_branch, git_commit, created_at, last_seen_at, status, parent_id,
+                    (SELECT group_concat(tag, ',') FROM agent_tags WHERE agent_id = agents.id) AS tags
                 FROM agents
                 WHERE name = ?
                 "#,
@@ -223,13 +492,14 @@ impl AgentStore {
         .fetch_optional(&self.pool)
         .await?;
 
-        Ok(row.and_then(Self::row_to_agent))
+        Ok(row.and_then(parse_row))
     }
 
     pub async fn list_all(&self) -> Result<Vec<Agent>, sqlx::Error> {
         let rows: Vec<AgentRow> = sqlx::query_as(
             r#"
-                SELECT id, name, session_id, working_directory, topic, created_at, last_seen_at, status
+                SELECT id, name, session_id, working_directory, topic, project, git_remote, git_branch, git_commit, created_at, last_seen_at, status, parent_id,
+                    (SELECT group_concat(tag, ',') FROM agent_tags WHERE agent_id = agents.id) AS tags
                 FROM agents
                 ORDER BY last_seen_at DESC
                 "#,
@@ -237,7 +507,46 @@ impl AgentStore {
         .fetch_all(&self.pool)
         .await?;
 
-        Ok(rows.into_iter().filter_map(Self::row_to_agent).collect())
+        Ok(rows.into_iter().filter_map(parse_row).collect())
+    }
+
+    /// Active agents not seen since `cutoff`
+    pub async fn idle_since(&self, cutoff: DateTime<Utc>) -> Result<Vec<Agent>, sqlx::Error> {
+        let rows: Vec<AgentRow> = sqlx::query_as(
+            r#"
+                SELECT id, name, session_id, working_directory, topic, project, git_remote, git_branch, git_commit, created_at, last_seen_at, status, parent_id,
+                    (SELECT group_concat(tag, ',') FROM agent_tags WHERE agent_id = agents.id) AS tags
+                FROM agents
+                WHERE status = 'active' AND last_seen_at < ?
+                "#,
+        )
+        .bind(cutoff.to_rfc3339())
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows.into_iter().filter_map(parse_row).collect())
+    }
+
+    /// Mark an active agent inactive; false if it already was, or came back
+    /// (and was seen again) since `last_seen_at`
+    pub async fn mark_inactive(
+        &self,
+        id: &Uuid,
+        last_seen_at: DateTime<Utc>,
+    ) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query(
+            r#"
+            UPDATE agents SET status = ? WHERE id = ? AND status = ? AND last_seen_at = ?
+            "#,
+        )
+        .bind(AgentStatus::Inactive.to_string())
+        .bind(id.to_string())
+        .bind(AgentStatus::Active.to_string())
+        .bind(last_seen_at.to_rfc3339())
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
     }
 
     async fn update_last_seen(&self, id: &Uuid, status: AgentStatus) -> Result<(), sqlx::Error> {
@@ -273,22 +582,228 @@ impl AgentStore {
         Ok(())
     }
 
-    /// Mark an agent as inactive by session_id
-    pub async fn mark_inactive(&self, session_id: &str) -> Result<(), sqlx::Error> {
+    async fn update_session_id(&self, id: &Uuid, session_id: &str) -> Result<(), sqlx::Error> {
         sqlx::query(
             r#"
-            UPDATE agents SET status = ?, last_seen_at = ? WHERE session_id = ?
+            UPDATE agents SET session_id = ? WHERE id = ?
             "#,
         )
-        .bind(AgentStatus::Inactive.to_string())
-        .bind(Utc::now().to_rfc3339())
         .bind(session_id)
+        .bind(id.to_string())
         .execute(&self.pool)
         .await?;
 
         Ok(())
     }
 
+    /// Give an agent a new name, carrying its recorded history over. Events
+    /// store the name they were recorded under, so they are rewritten too,
+    /// in every attached monthly shard.
+    pub async fn rename(&self, old: &str, new: &str) -> Result<Agent, AgentError> {
+        launcher::parse_name(new).map_err(AgentError::Invalid)?;
+        let mut conn = self.pool.acquire().await?;
+        let shards: Vec<String> = sqlx::query_scalar(
+            "SELECT name FROM pragma_database_list WHERE name LIKE 'shard\\_%' ESCAPE '\\'",
+        )
+        .fetch_all(&mut *conn)
+        .await?;
+        // Tables of features that haven't run yet don't exist
+        let mut named = Vec::new();
+        for table in NAMED_TABLES {
+            let exists: bool = sqlx::query_scalar(
+                "SELECT EXISTS (SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = ?)",
+            )
+            .bind(table)
+            .fetch_one(&mut *conn)
+            .await?;
+            if exists {
+                named.push(format!("main.{}", table));
+            }
+        }
+
+        let mut tx = sqlx::Connection::begin(&mut *conn).await?;
+        let renamed = match sqlx::query("UPDATE agents SET name = ? WHERE name = ?")
+            .bind(new)
+            .bind(old)
+            .execute(&mut *tx)
+            .await
+        {
+            Ok(result) => result.rows_affected() > 0,
+            Err(sqlx::Error::Database(e)) if e.is_unique_violation() => {
+                return Err(AgentError::NameTaken(new.to_string()))
+            }
+            Err(e) => return Err(e.into()),
+        };
+        if !renamed {
+            return Err(AgentError::NotFound(old.to_string()));
+        }
+        let tables = std::iter::once("main.observability_events".to_string())
+            .chain(shards.iter().map(|s| format!("{}.observability_events", s)))
+            .chain(named);
+        for table in tables {
+            sqlx::query(&format!("UPDATE {} SET agent = ? WHERE agent = ?", table))
+                .bind(new)
+                .bind(old)
+                .execute(&mut *tx)
+                .await?;
+        }
+        tx.commit().await?;
+        drop(conn);
+
+        self.find_by_name(new)
+            .await?
+            .ok_or_else(|| AgentError::NotFound(new.to_string()))
+    }
+
+    async fn insert_tags(&self, id: &Uuid, tags: &[String]) -> Result<(), sqlx::Error> {
+        for tag in tags {
+            sqlx::query("INSERT OR IGNORE INTO agent_tags (agent_id, tag) VALUES (?, ?)")
+                .bind(id.to_string())
+                .bind(tag)
+                .execute(&self.pool)
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// Add tags to an agent, or take them off with `remove`
+    pub async fn tag(&self, name: &str, tags: &[String], remove: bool) -> Result<Agent, AgentError> {
+        if let Some(tag) = tags.iter().find(|t| launcher::parse_name(t).is_err()) {
+            return Err(AgentError::Invalid(format!(
+                "'{}': tags are letters, digits, '-', '_', or '.'",
+                tag
+            )));
+        }
+        let agent = self
+            .find_by_name(name)
+            .await?
+            .ok_or_else(|| AgentError::NotFound(name.to_string()))?;
+        if remove {
+            for tag in tags {
+                sqlx::query("DELETE FROM agent_tags WHERE agent_id = ? AND tag = ?")
+                    .bind(agent.id.to_string())
+                    .bind(tag)
+                    .execute(&self.pool)
+                    .await?;
+            }
+        } else {
+            self.insert_tags(&agent.id, tags).await?;
+        }
+        self.find_by_name(name)
+            .await?
+            .ok_or_else(|| AgentError::NotFound(name.to_string()))
+    }
+
+    /// Record what a request's x-sentinel-* headers say about its agent:
+    /// tags are added, and the project replaces the one before
+    pub async fn attribute(
+        &self,
+        agent: &mut Agent,
+        attribution: &Attribution,
+    ) -> Result<(), sqlx::Error> {
+        if let Some(ref project) = attribution.project {
+            if agent.project.as_ref() != Some(project) {
+                sqlx::query("UPDATE agents SET project = ? WHERE id = ?")
+                    .bind(project)
+                    .bind(agent.id.to_string())
+                    .execute(&self.pool)
+                    .await?;
+                agent.project = Some(project.clone());
+            }
+        }
+        let new: Vec<String> = attribution
+            .tags
+            .iter()
+            .filter(|tag| !agent.tags.contains(tag))
+            .cloned()
+            .collect();
+        if !new.is_empty() {
+            self.insert_tags(&agent.id, &new).await?;
+            agent.tags.extend(new);
+            agent.tags.sort();
+        }
+        Ok(())
+    }
+
+    /// Store where the agent is in its repository, noting the commit for the
+    /// agent's session the first time the session sees it at HEAD
+    pub async fn record_git(&self, agent: &Agent, git: &GitContext) -> Result<(), sqlx::Error> {
+        if agent.git.as_ref() == Some(git) {
+            return Ok(());
+        }
+        sqlx::query(
+            "UPDATE agents SET git_remote = ?, git_branch = ?, git_commit = ? WHERE id = ?",
+        )
+        .bind(&git.remote)
+        .bind(&git.branch)
+        .bind(&git.commit)
+        .bind(agent.id.to_string())
+        .execute(&self.pool)
+        .await?;
+        sqlx::query(
+            r#"
+            INSERT OR IGNORE INTO agent_commits (commit_sha, agent_id, session_id, branch, first_seen_at)
+            VALUES (?, ?, ?, ?, ?)
+            "#,
+        )
+        .bind(&git.commit)
+        .bind(agent.id.to_string())
+        .bind(&agent.session_id)
+        .bind(&git.branch)
+        .bind(Utc::now().to_rfc3339())
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Sessions that had a commit starting with `prefix` at HEAD, earliest
+    /// first: the first is the one working when it was made
+    pub async fn sessions_at_commit(&self, prefix: &str) -> Result<Vec<CommitSession>, sqlx::Error> {
+        let prefix = prefix.to_ascii_lowercase();
+        let rows: Vec<(String, String, String, Option<String>, String)> = sqlx::query_as(
+            r#"
+            SELECT a.name, c.session_id, c.commit_sha, c.branch, c.first_seen_at
+            FROM agent_commits c JOIN agents a ON a.id = c.agent_id
+            WHERE substr(c.commit_sha, 1, ?) = ?
+            ORDER BY c.first_seen_at
+            "#,
+        )
+        .bind(prefix.len() as i64)
+        .bind(&prefix)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rows
+            .into_iter()
+            .filter_map(|(agent, session_id, commit, branch, first_seen_at)| {
+                Some(CommitSession {
+                    agent,
+                    session_id,
+                    commit,
+                    branch,
+                    first_seen_at: DateTime::parse_from_rfc3339(&first_seen_at)
+                        .ok()?
+                        .with_timezone(&Utc),
+                })
+            })
+            .collect())
+    }
+
+    /// Whether the agent with this name carries `tag`
+    pub async fn has_tag(&self, name: &str, tag: &str) -> Result<bool, sqlx::Error> {
+        sqlx::query_scalar(
+            r#"
+            SELECT EXISTS (
+                SELECT 1 FROM agents a JOIN agent_tags t ON t.agent_id = a.id
+                WHERE a.name = ? AND t.tag = ?
+            )
+            "#,
+        )
+        .bind(name)
+        .bind(tag)
+        .fetch_one(&self.pool)
+        .await
+    }
+
     pub async fn update_topic(&self, id: &Uuid, topic: &str) -> Result<(), sqlx::Error> {
         sqlx::query(
             r#"
@@ -302,37 +817,219 @@ impl AgentStore {
 
         Ok(())
     }
+}
 
-    fn row_to_agent(row: AgentRow) -> Option<Agent> {
-        let (id, name, session_id, working_directory, topic, created_at, last_seen_at, status) = row;
-        Some(Agent {
-            id: id.parse().ok()?,
-            name,
-            session_id,
-            working_directory,
-            created_at: DateTime::parse_from_rfc3339(&created_at)
-                .ok()?
-                .with_timezone(&Utc),
-            last_seen_at: DateTime::parse_from_rfc3339(&last_seen_at)
-                .ok()?
-                .with_timezone(&Utc),
-            status: match status.as_str() {
-                "active" => AgentStatus::Active,
-                "inactive" => AgentStatus::Inactive,
-                _ => return None,
-            },
-            topic,
-        })
-    }
+#[derive(sqlx::FromRow)]
+struct AgentRow {
+    id: String,
+    name: String,
+    session_id: String,
+    working_directory: Option<String>,
+    topic: Option<String>,
+    project: Option<String>,
+    git_remote: Option<String>,
+    git_branch: Option<String>,
+    git_commit: Option<String>,
+    created_at: String,
+    last_seen_at: String,
+    status: String,
+    parent_id: Option<String>,
+    /// Comma-separated
+    tags: Option<String>,
 }
 
-type AgentRow = (
-    String,
-    String,
-    String,
-    Option<String>,
-    Option<String>,
-    String,
-    String,
-    String,
-);
+fn row_to_agent(row: AgentRow) -> Result<Agent, String> {
+    let mut tags: Vec<String> = row
+        .tags
+        .map(|tags| tags.split(',').map(String::from).collect())
+        .unwrap_or_default();
+    tags.sort();
+    let time = |at: &str| {
+        DateTime::parse_from_rfc3339(at)
+            .map(|at| at.with_timezone(&Utc))
+            .map_err(|e| format!("invalid timestamp: {}", e))
+    };
+    Ok(Agent {
+        id: row.id.parse().map_err(|e| format!("invalid id: {}", e))?,
+        name: row.name,
+        session_id: row.session_id,
+        working_directory: row.working_directory,
+        created_at: time(&row.created_at)?,
+        last_seen_at: time(&row.last_seen_at)?,
+        status: match row.status.as_str() {
+            "active" => AgentStatus::Active,
+            "inactive" => AgentStatus::Inactive,
+            other => return Err(format!("invalid status: {}", other)),
+        },
+        topic: row.topic,
+        tags,
+        project: row.project,
+        git: row.git_commit.map(|commit| GitContext {
+            remote: row.git_remote,
+            branch: row.git_branch,
+            commit,
+        }),
+        parent_id: row
+            .parent_id
+            .map(|id| id.parse().map_err(|e| format!("invalid parent id: {}", e)))
+            .transpose()?,
+    })
+}
+
+/// An agent read from its row, or none, with a warning, if the row is bad
+fn parse_row(row: AgentRow) -> Option<Agent> {
+    let name = row.name.clone();
+    row_to_agent(row)
+        .inspect_err(|e| tracing::warn!("Skipping unparseable agent row '{}': {}", name, e))
+        .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event::{Alert, ObservabilityEvent, Payload};
+    use crate::storage::{EventFilter, Storage};
+
+    #[tokio::test]
+    async fn test_rename_carries_history_and_tags_filter_events() {
+        let path = std::env::temp_dir().join(format!("sentinel-test-{}.db", Uuid::new_v4()));
+        let storage = Storage::new(&path).await.unwrap();
+        let store = AgentStore::new(storage.pool());
+        store.init_schema().await.unwrap();
+        store
+            .get_or_create_named_agent("calm-otter", None, None)
+            .await
+            .unwrap();
+        store
+            .get_or_create_named_agent("swift-fox", None, None)
+            .await
+            .unwrap();
+        storage
+            .insert_observability_event(&ObservabilityEvent {
+                seq: None,
+                id: Uuid::new_v4(),
+                timestamp: Utc::now(),
+                session_id: None,
+                agent: Some("calm-otter".to_string()),
+                topic: None,
+                provider: None,
+                payload: Payload::Alert(Alert {
+                    kind: "test".to_string(),
+                    message: "hi".to_string(),
+                }),
+            })
+            .await
+            .unwrap();
+
+        let agent = store.rename("calm-otter", "reviewer").await.unwrap();
+        assert_eq!(agent.name, "reviewer");
+        assert_eq!(storage.get_agent_events("reviewer", 10).await.unwrap().len(), 1);
+        assert!(matches!(
+            store.rename("reviewer", "swift-fox").await,
+            Err(AgentError::NameTaken(_))
+        ));
+        assert!(matches!(
+            store.rename("calm-otter", "other").await,
+            Err(AgentError::NotFound(_))
+        ));
+        assert!(matches!(
+            store.rename("reviewer", "a b").await,
+            Err(AgentError::Invalid(_))
+        ));
+
+        let tags = ["backend".to_string(), "ci".to_string()];
+        let agent = store.tag("reviewer", &tags, false).await.unwrap();
+        assert_eq!(agent.tags, tags);
+        let agent = store.tag("reviewer", &tags[1..], true).await.unwrap();
+        assert_eq!(agent.tags, vec!["backend".to_string()]);
+        assert!(store.has_tag("reviewer", "backend").await.unwrap());
+        assert!(!store.has_tag("swift-fox", "backend").await.unwrap());
+
+        let mut agent = store.find_by_name("swift-fox").await.unwrap().unwrap();
+        let attribution = Attribution {
+            project: Some("billing".to_string()),
+            run_id: Some("nightly-7".to_string()),
+            tags: vec!["backend".to_string()],
+            ..Default::default()
+        };
+        store.attribute(&mut agent, &attribution).await.unwrap();
+        let stored = store.find_by_name("swift-fox").await.unwrap().unwrap();
+        assert_eq!(stored.project.as_deref(), Some("billing"));
+        assert_eq!(stored.tags, agent.tags);
+
+        let filter = |tag: &str| EventFilter {
+            tag: Some(tag.to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            storage.query_events(&filter("backend"), None, 10).await.unwrap().len(),
+            1
+        );
+        assert!(storage.query_events(&filter("ci"), None, 10).await.unwrap().is_empty());
+
+        // A row that doesn't parse is skipped, not the whole listing
+        sqlx::query(
+            "INSERT INTO agents (id, name, session_id, created_at, last_seen_at, status) VALUES ('x', 'broken', 's', 'never', 'never', 'active')",
+        )
+        .execute(&storage.pool())
+        .await
+        .unwrap();
+        assert_eq!(store.list_all().await.unwrap().len(), 2);
+        assert!(store.find_by_name("broken").await.unwrap().is_none());
+
+        drop(storage);
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[tokio::test]
+    async fn test_directory_naming_counts_up_per_directory() {
+        assert_eq!(
+            directory_name("/home/me/src/My Repo/"),
+            Some("my-repo".to_string())
+        );
+        assert_eq!(directory_name("/"), None);
+
+        let path = std::env::temp_dir().join(format!("sentinel-test-{}.db", Uuid::new_v4()));
+        let storage = Storage::new(&path).await.unwrap();
+        let store = AgentStore::new(storage.pool()).with_naming(Naming::Directory);
+        store.init_schema().await.unwrap();
+        let mut names = Vec::new();
+        for (session, dir) in [("a", "/src/sentinel"), ("b", "/src/sentinel"), ("c", "/src/web")] {
+            let agent = store.get_or_create_agent(session, Some(dir)).await.unwrap();
+            names.push(agent.name);
+        }
+        assert_eq!(names, ["sentinel-1", "sentinel-2", "web-1"]);
+        // Sessions starting at once in one directory still get a number each
+        let sessions = ["e", "f", "g", "h"];
+        let started = futures::future::join_all(
+            sessions.map(|session| store.get_or_create_agent(session, Some("/src/api"))),
+        )
+        .await;
+        let mut names: Vec<String> = started.into_iter().map(|a| a.unwrap().name).collect();
+        names.sort();
+        assert_eq!(names, ["api-1", "api-2", "api-3", "api-4"]);
+        // Without a directory there is nothing to go on
+        let agent = store.get_or_create_agent("d", None).await.unwrap();
+        assert!(!agent.name.starts_with("sentinel"));
+
+        // Each session's commits are kept as HEAD moves
+        let head = |commit: &str| GitContext {
+            remote: Some("git@example.com:me/sentinel.git".to_string()),
+            branch: Some("main".to_string()),
+            commit: commit.to_string(),
+        };
+        for (session, commit) in [("a", "abc123f00"), ("a", "def456f00"), ("b", "abc123f00")] {
+            let agent = store.find_by_session_id(session).await.unwrap().unwrap();
+            store.record_git(&agent, &head(commit)).await.unwrap();
+        }
+        let agent = store.find_by_session_id("a").await.unwrap().unwrap();
+        assert_eq!(agent.git, Some(head("def456f00")));
+        let sessions = store.sessions_at_commit("ABC123").await.unwrap();
+        let seen: Vec<&str> = sessions.iter().map(|s| s.agent.as_str()).collect();
+        assert_eq!(seen, ["sentinel-1", "sentinel-2"]);
+        assert!(store.sessions_at_commit("0000").await.unwrap().is_empty());
+
+        drop(storage);
+        let _ = std::fs::remove_file(&path);
+    }
+}
@@ -0,0 +1,295 @@
+//! Heartbeats and annotations from orchestrators outside the proxy.
+//!
+//! A framework driving its own agent loop can mark what a session is doing
+//! with `POST /api/sessions/{id}/heartbeat` (`{"phase": "executing"}`) and
+//! `POST /api/sessions/{id}/annotations` (`{"message": ..., "phase": ...,
+//! "data": {...}}`). Both keep the session's agent active, so it isn't closed
+//! as idle between model calls. An annotation is always recorded as an event
+//! in the session's timeline; a heartbeat only when it moves the session to
+//! a new phase, so one can be sent every few seconds without flooding it.
+//! Like the other endpoints that change state, both refuse requests from
+//! other sites (see `origin`).
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use axum::extract::{Path, State};
+use axum::http::{HeaderMap, StatusCode};
+use axum::Json;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use uuid::Uuid;
+
+use crate::agent::Agent;
+use crate::event::{Annotation, ObservabilityEvent, Payload};
+use crate::launcher;
+use crate::origin;
+use crate::proxy::ProxyState;
+
+/// Longest phase name accepted
+const MAX_PHASE_LEN: usize = 64;
+/// Longest annotation message accepted
+const MAX_MESSAGE_LEN: usize = 4096;
+/// How long a session's phase is kept after its last heartbeat or annotation
+const FORGET_AFTER: Duration = Duration::from_secs(60 * 60);
+
+type ApiError = (StatusCode, Json<Value>);
+
+fn bad_request(message: String) -> ApiError {
+    (StatusCode::BAD_REQUEST, Json(json!({ "error": message })))
+}
+
+fn check_origin(state: &ProxyState, headers: &HeaderMap, json_body: bool) -> Result<(), ApiError> {
+    origin::check(headers, json_body, &state.allowed_origins)
+        .map_err(|(status, message)| (status, Json(json!({ "error": message }))))
+}
+
+/// The phase each session was last put in, and when it was last heard from
+#[derive(Default)]
+pub struct Phases {
+    current: Mutex<HashMap<String, (String, Instant)>>,
+}
+
+impl Phases {
+    /// Put a session in `phase`; true when that changed it
+    pub fn enter(&self, session_id: &str, phase: &str) -> bool {
+        self.enter_at(session_id, phase, Instant::now())
+    }
+
+    fn enter_at(&self, session_id: &str, phase: &str, now: Instant) -> bool {
+        let mut current = self.current.lock().unwrap_or_else(|e| e.into_inner());
+        // Sessions gone quiet are dropped, so only live ones are kept
+        current.retain(|_, (_, at)| now.duration_since(*at) < FORGET_AFTER);
+        let changed = current.get(session_id).is_none_or(|(p, _)| p != phase);
+        current.insert(session_id.to_string(), (phase.to_string(), now));
+        changed
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct Heartbeat {
+    /// Phase the session is in
+    pub phase: Option<String>,
+    /// Agent to file the session under, when it hasn't been seen yet
+    pub agent: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AnnotationRequest {
+    pub message: Option<String>,
+    pub phase: Option<String>,
+    pub data: Option<Value>,
+    /// Agent to file the session under, when it hasn't been seen yet
+    pub agent: Option<String>,
+}
+
+/// What a heartbeat did
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct HeartbeatAck {
+    pub session_id: String,
+    pub agent: String,
+    pub phase: Option<String>,
+    /// Id of the annotation recorded when the phase changed
+    pub recorded: Option<Uuid>,
+}
+
+fn phase(phase: Option<String>) -> Result<Option<String>, ApiError> {
+    let Some(phase) = phase.map(|p| p.trim().to_string()) else {
+        return Ok(None);
+    };
+    if phase.is_empty() || phase.chars().count() > MAX_PHASE_LEN {
+        return Err(bad_request(format!(
+            "phase must be 1 to {} characters",
+            MAX_PHASE_LEN
+        )));
+    }
+    Ok(Some(phase))
+}
+
+/// The session's agent, marked as seen; created for a session sentinel
+/// hasn't seen a request from yet
+async fn touch(
+    state: &ProxyState,
+    session_id: &str,
+    name: Option<&str>,
+) -> Result<Agent, ApiError> {
+    let agent = match name {
+        Some(name) => {
+            launcher::parse_name(name).map_err(bad_request)?;
+            state
+                .agent_store
+                .get_or_create_named_agent(name, Some(session_id), None)
+                .await
+        }
+        None => {
+            state
+                .agent_store
+                .get_or_create_agent(session_id, None)
+                .await
+        }
+    };
+    agent.map_err(|e| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({ "error": e.to_string() })),
+        )
+    })
+}
+
+async fn record(
+    state: &ProxyState,
+    session_id: &str,
+    agent: &Agent,
+    annotation: Annotation,
+) -> ObservabilityEvent {
+    let event = ObservabilityEvent {
+        seq: None,
+        id: Uuid::new_v4(),
+        timestamp: chrono::Utc::now(),
+        session_id: Some(session_id.to_string()),
+        agent: Some(agent.name.clone()),
+        topic: agent.topic.clone(),
+        provider: None,
+        payload: Payload::Annotation(annotation),
+    };
+    state.publish(event.clone()).await;
+    event
+}
+
+pub async fn heartbeat_handler(
+    State(state): State<Arc<ProxyState>>,
+    Path(session_id): Path<String>,
+    headers: HeaderMap,
+    body: Option<Json<Heartbeat>>,
+) -> Result<Json<HeartbeatAck>, ApiError> {
+    check_origin(&state, &headers, false)?;
+    let heartbeat = body.map(|Json(h)| h).unwrap_or_default();
+    let phase = phase(heartbeat.phase)?;
+    let agent = touch(&state, &session_id, heartbeat.agent.as_deref()).await?;
+    let mut recorded = None;
+    if let Some(ref phase) = phase {
+        if state.phases.enter(&session_id, phase) {
+            let annotation = Annotation {
+                phase: Some(phase.clone()),
+                message: None,
+                data: None,
+            };
+            recorded = Some(record(&state, &session_id, &agent, annotation).await.id);
+        }
+    }
+    Ok(Json(HeartbeatAck {
+        session_id,
+        agent: agent.name,
+        phase,
+        recorded,
+    }))
+}
+
+pub async fn annotation_handler(
+    State(state): State<Arc<ProxyState>>,
+    Path(session_id): Path<String>,
+    headers: HeaderMap,
+    Json(request): Json<AnnotationRequest>,
+) -> Result<(StatusCode, Json<ObservabilityEvent>), ApiError> {
+    check_origin(&state, &headers, true)?;
+    let phase = phase(request.phase)?;
+    let message = request.message.filter(|m| !m.trim().is_empty());
+    if phase.is_none() && message.is_none() {
+        return Err(bad_request(
+            "an annotation needs a message or a phase".to_string(),
+        ));
+    }
+    if message
+        .as_ref()
+        .is_some_and(|m| m.chars().count() > MAX_MESSAGE_LEN)
+    {
+        return Err(bad_request(format!(
+            "message is over {} characters",
+            MAX_MESSAGE_LEN
+        )));
+    }
+    let agent = touch(&state, &session_id, request.agent.as_deref()).await?;
+    if let Some(ref phase) = phase {
+        state.phases.enter(&session_id, phase);
+    }
+    let annotation = Annotation {
+        phase,
+        message,
+        data: request.data,
+    };
+    let event = record(&state, &session_id, &agent, annotation).await;
+    Ok((StatusCode::CREATED, Json(event)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::header;
+
+    #[test]
+    fn test_heartbeats_record_only_phase_changes() {
+        let phases = Phases::default();
+        assert!(phases.enter("s1", "planning"));
+        assert!(!phases.enter("s1", "planning"));
+        assert!(phases.enter("s2", "planning"));
+        assert!(phases.enter("s1", "executing"));
+        assert!(phases.enter("s1", "planning"));
+        // A session not heard from in a while is forgotten
+        let later = Instant::now() + FORGET_AFTER;
+        assert!(phases.enter_at("s1", "planning", later));
+        assert_eq!(phases.current.lock().unwrap().len(), 1);
+
+        assert_eq!(
+            phase(Some("  reviewing ".to_string())).unwrap().as_deref(),
+            Some("reviewing")
+        );
+        assert!(phase(Some(" ".to_string())).is_err());
+        assert!(phase(Some("x".repeat(MAX_PHASE_LEN + 1))).is_err());
+        assert_eq!(phase(None).unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_annotations_are_taken_only_from_this_site() {
+        let state = ProxyState::for_tests().await;
+        let mut json = HeaderMap::new();
+        json.insert(header::CONTENT_TYPE, "application/json".parse().unwrap());
+        let request = || AnnotationRequest {
+            message: Some("Tests pass".to_string()),
+            phase: Some("reviewing".to_string()),
+            data: None,
+            agent: Some("calm-otter".to_string()),
+        };
+        let annotate = |headers| {
+            let path = Path("s1".to_string());
+            annotation_handler(State(state.clone()), path, headers, Json(request()))
+        };
+
+        let mut cross_site = json.clone();
+        cross_site.insert(header::ORIGIN, "https://evil.example".parse().unwrap());
+        assert_eq!(annotate(cross_site).await.unwrap_err().0, StatusCode::FORBIDDEN);
+        assert_eq!(
+            annotate(HeaderMap::new()).await.unwrap_err().0,
+            StatusCode::UNSUPPORTED_MEDIA_TYPE
+        );
+        assert!(state.agent_store.find_by_session_id("s1").await.unwrap().is_none());
+
+        let (status, Json(event)) = annotate(json).await.unwrap();
+        assert_eq!(status, StatusCode::CREATED);
+        assert_eq!(event.agent.as_deref(), Some("calm-otter"));
+        assert!(state.storage.get_event(event.id).await.unwrap().is_some());
+
+        // The annotation moved the session to its phase, so a heartbeat there adds nothing
+        let heartbeat = Heartbeat {
+            phase: Some("reviewing".to_string()),
+            agent: None,
+        };
+        let path = Path("s1".to_string());
+        let heartbeat = Some(Json(heartbeat));
+        let Json(ack) = heartbeat_handler(State(state.clone()), path, HeaderMap::new(), heartbeat)
+            .await
+            .unwrap();
+        assert_eq!((ack.agent.as_str(), ack.recorded), ("calm-otter", None));
+    }
+}
@@ -0,0 +1,243 @@
+//! Anonymized exports and imports (`--anonymize <salt-file>` on `sentinel
+//! export` and `sentinel import`), for shipping events from each machine to a
+//! shared collector, or for a collector to anonymize what it's sent.
+//!
+//! Paths under a home directory (`/home/<user>/...`, `/Users/<user>/...`,
+//! `C:\Users\<user>\...`, `~/...`) have the user name and every component
+//! beneath the home replaced by a keyed hash of it: `/Users/alice/acme/lib.rs`
+//! becomes `/Users/u-<hash>/<hash>/<hash>.rs`. The key is a salt the
+//! team shares, so the same user or file hashes alike from every machine and
+//! can still be grouped on, but can't be recovered or guessed without the
+//! salt. Agents' working directories, event text, tool inputs and results,
+//! and raw bodies are all rewritten, and git remotes, branches, and project
+//! names are replaced by their hash whole; the local database keeps
+//! everything as recorded.
+
+use std::path::Path;
+
+use regex::{Captures, Regex};
+use ring::hmac;
+
+use crate::agent::Agent;
+use crate::event::{ObservabilityEvent, Payload};
+use crate::redact;
+use crate::storage::RawCapture;
+
+/// Hex characters of a hash kept
+const HASH_CHARS: usize = 8;
+/// Shortest salt accepted, in bytes
+const MIN_SALT_LEN: usize = 16;
+
+/// A home directory and whatever path follows it. In JSON bodies Windows
+/// separators arrive escaped, as `\\`.
+const HOME_PATH: &str = r#"(?P<home>/home/|/Users/|[A-Za-z]:(?:\\{1,2})Users(?:\\{1,2})|~/)(?P<rest>[^\s"'`<>|:;,()\[\]{}]*)"#;
+
+#[derive(Debug, thiserror::Error)]
+pub enum AnonymizeError {
+    #[error("failed to read salt file {path}: {source}")]
+    Read {
+        path: String,
+        source: std::io::Error,
+    },
+    #[error("salt in {0} is too short; use at least {MIN_SALT_LEN} random bytes, e.g. `openssl rand -hex 32`")]
+    ShortSalt(String),
+}
+
+pub struct Anonymizer {
+    key: hmac::Key,
+    paths: Regex,
+}
+
+impl Anonymizer {
+    pub fn new(salt: &[u8]) -> Self {
+        Self {
+            key: hmac::Key::new(hmac::HMAC_SHA256, salt),
+            paths: Regex::new(HOME_PATH).expect("home path pattern compiles"),
+        }
+    }
+
+    /// Keyed with the salt in `path`, surrounding whitespace aside
+    pub fn load(path: &Path) -> Result<Self, AnonymizeError> {
+        let salt = std::fs::read(path).map_err(|source| AnonymizeError::Read {
+            path: path.display().to_string(),
+            source,
+        })?;
+        let salt = salt.trim_ascii();
+        if salt.len() < MIN_SALT_LEN {
+            return Err(AnonymizeError::ShortSalt(path.display().to_string()));
+        }
+        Ok(Self::new(salt))
+    }
+
+    fn hash(&self, value: &str) -> String {
+        let tag = hmac::sign(&self.key, value.as_bytes());
+        tag.as_ref()
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect::<String>()[..HASH_CHARS]
+            .to_string()
+    }
+
+    /// A path component's hash, keeping a file's extension
+    fn component(&self, component: &str) -> String {
+        match component.rsplit_once('.') {
+            Some((stem, ext)) if !stem.is_empty() && !ext.is_empty() && ext.len() <= 8 => {
+                format!("{}.{}", self.hash(stem), ext)
+            }
+            _ => self.hash(component),
+        }
+    }
+
+    /// Text with every home directory path in it anonymized
+    pub fn text(&self, text: &str) -> String {
+        self.paths
+            .replace_all(text, |caps: &Captures| {
+                let home = &caps["home"];
+                let mut out = home.to_string();
+                // Separators are copied as found, so escaping survives
+                let mut first = home != "~/";
+                let mut component = String::new();
+                let flush = |component: &mut String, first: &mut bool, out: &mut String| {
+                    if component.is_empty() {
+                        return;
+                    }
+                    if std::mem::take(first) {
+                        out.push_str("u-");
+                        out.push_str(&self.hash(component));
+                    } else {
+                        out.push_str(&self.component(component));
+                    }
+                    component.clear();
+                };
+                for c in caps["rest"].chars() {
+                    if c == '/' || c == '\\' {
+                        flush(&mut component, &mut first, &mut out);
+                        out.push(c);
+                    } else {
+                        component.push(c);
+                    }
+                }
+                flush(&mut component, &mut first, &mut out);
+                out
+            })
+            .into_owned()
+    }
+
+    /// A name that identifies on its own, like a git remote or a project,
+    /// replaced by its hash
+    fn name(&self, name: &mut Option<String>) {
+        if let Some(ref value) = name {
+            *name = Some(self.hash(value));
+        }
+    }
+
+    pub fn event(&self, event: &mut ObservabilityEvent) {
+        redact::rewrite(event, &|s| self.text(s));
+        if let Some(ref topic) = event.topic {
+            event.topic = Some(self.text(topic));
+        }
+        match event.payload {
+            Payload::UserMessage(ref mut message) => self.name(&mut message.project),
+            Payload::AssistantResponse(ref mut response) => self.name(&mut response.project),
+            _ => {}
+        }
+    }
+
+    pub fn agent(&self, agent: &mut Agent) {
+        if let Some(ref dir) = agent.working_directory {
+            agent.working_directory = Some(self.text(dir));
+        }
+        if let Some(ref topic) = agent.topic {
+            agent.topic = Some(self.text(topic));
+        }
+        self.name(&mut agent.project);
+        if let Some(ref mut git) = agent.git {
+            self.name(&mut git.remote);
+            self.name(&mut git.branch);
+        }
+    }
+
+    pub fn raw(&self, raw: &mut RawCapture) {
+        raw.body = self.text(&raw.body);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_home_paths_hash_alike_under_one_salt() {
+        let team = Anonymizer::new(b"team salt, at least sixteen bytes");
+        let text = "Edited /Users/alice/acme/src/lib.rs and /home/alice/acme; see ~/notes/todo.md";
+        let anonymized = team.text(text);
+        assert!(!anonymized.contains("alice") && !anonymized.contains("acme"));
+        assert!(!anonymized.contains("notes") && !anonymized.contains("todo"));
+        let user = format!("u-{}", team.hash("alice"));
+        let project = team.component("acme");
+        assert_eq!(
+            anonymized,
+            format!(
+                "Edited /Users/{user}/{project}/{}/{}.rs and /home/{user}/{project}; see ~/{}/{}.md",
+                team.hash("src"),
+                team.hash("lib"),
+                team.hash("notes"),
+                team.hash("todo"),
+            )
+        );
+        // Escaped Windows paths in a JSON body keep their escaping
+        let json = r#"{"cwd":"C:\\Users\\alice\\acme"}"#;
+        assert_eq!(
+            team.text(json),
+            format!(r#"{{"cwd":"C:\\Users\\{user}\\{project}"}}"#)
+        );
+        // Other paths are left alone, and another team's salt hashes differently
+        assert_eq!(team.text("/usr/lib/libssl.so"), "/usr/lib/libssl.so");
+        let other = Anonymizer::new(b"another team's sixteen-plus byte salt");
+        assert_ne!(other.text(text), anonymized);
+
+        let root = std::env::temp_dir().join(format!("sentinel-test-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&root).unwrap();
+        std::fs::write(root.join("short"), "abc\n").unwrap();
+        assert!(matches!(
+            Anonymizer::load(&root.join("short")),
+            Err(AnonymizeError::ShortSalt(_))
+        ));
+        std::fs::write(root.join("salt"), "0123456789abcdef0123456789abcdef\n").unwrap();
+        assert_eq!(
+            Anonymizer::load(&root.join("salt")).unwrap().text(text),
+            Anonymizer::new(b"0123456789abcdef0123456789abcdef").text(text)
+        );
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_agent_names_are_hashed_whole() {
+        let team = Anonymizer::new(b"team salt, at least sixteen bytes");
+        let now = chrono::Utc::now();
+        let mut agent = Agent {
+            id: uuid::Uuid::new_v4(),
+            name: "calm-otter".to_string(),
+            session_id: "s1".to_string(),
+            working_directory: Some("/Users/alice/acme".to_string()),
+            created_at: now,
+            last_seen_at: now,
+            status: crate::agent::AgentStatus::Active,
+            topic: None,
+            tags: Vec::new(),
+            project: Some("acme-billing".to_string()),
+            git: Some(crate::git::GitContext {
+                remote: Some("git@github.com:alice/acme.git".to_string()),
+                branch: Some("alice/fix-invoices".to_string()),
+                commit: "0123456789abcdef".to_string(),
+            }),
+            parent_id: None,
+        };
+        team.agent(&mut agent);
+        assert_eq!(agent.project, Some(team.hash("acme-billing")));
+        let git = agent.git.unwrap();
+        assert_eq!(git.remote, Some(team.hash("git@github.com:alice/acme.git")));
+        assert_eq!(git.branch, Some(team.hash("alice/fix-invoices")));
+        assert_eq!(git.commit, "0123456789abcdef");
+    }
+}
@@ -0,0 +1,138 @@
+//! `x-sentinel-*` request headers: attribution set by the client.
+//!
+//! Orchestration scripts know things the proxy can't infer: which project a
+//! run belongs to, which pipeline run sent a request, how agents should be
+//! grouped. They say so with headers on each request:
+//!
+//! - `x-sentinel-project`: recorded on the agent and on each exchange
+//! - `x-sentinel-run-id`: recorded on each exchange, and grouping the agents
+//!   sending it into a run (`sentinel runs`)
+//! - `x-sentinel-run-started-by`, `x-sentinel-run-params`: who started the
+//!   run, and its parameters as a JSON object, recorded on the run
+//! - `x-sentinel-parent-run-id`: the run that started this one, so costs roll
+//!   up from sub-runs to the task that spawned them
+//! - `x-sentinel-tag`: tags added to the agent (comma-separated, repeatable)
+//! - `x-sentinel-agent-name`: the agent's name, with `--agent-naming header`
+//!
+//! All of them are optional, and none is forwarded upstream.
+
+use axum::http::HeaderMap;
+use tracing::warn;
+
+use crate::agent::AGENT_NAME_HEADER;
+use crate::launcher;
+
+pub const PROJECT_HEADER: &str = "x-sentinel-project";
+pub const RUN_ID_HEADER: &str = "x-sentinel-run-id";
+pub const TAG_HEADER: &str = "x-sentinel-tag";
+pub const RUN_STARTED_BY_HEADER: &str = "x-sentinel-run-started-by";
+pub const RUN_PARAMS_HEADER: &str = "x-sentinel-run-params";
+pub const PARENT_RUN_HEADER: &str = "x-sentinel-parent-run-id";
+/// Longest project, run id, parent run id, or started-by kept; anything past it is cut off
+const MAX_VALUE_LEN: usize = 200;
+
+/// What a request's headers say about where it came from
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Attribution {
+    pub project: Option<String>,
+    pub run_id: Option<String>,
+    pub tags: Vec<String>,
+    pub started_by: Option<String>,
+    /// A JSON object
+    pub parameters: Option<serde_json::Value>,
+    pub parent_run_id: Option<String>,
+}
+
+fn value(headers: &HeaderMap, name: &str) -> Option<String> {
+    let value = headers.get(name)?.to_str().ok()?.trim();
+    (!value.is_empty()).then(|| value.chars().take(MAX_VALUE_LEN).collect())
+}
+
+impl Attribution {
+    pub fn from_headers(headers: &HeaderMap) -> Self {
+        let mut tags = Vec::new();
+        for tag in headers
+            .get_all(TAG_HEADER)
+            .iter()
+            .filter_map(|v| v.to_str().ok())
+            .flat_map(|v| v.split(','))
+            .map(str::trim)
+            .filter(|t| !t.is_empty())
+        {
+            match launcher::parse_name(tag) {
+                Ok(tag) if !tags.contains(&tag) => tags.push(tag),
+                Ok(_) => {}
+                Err(e) => warn!("Ignoring {} header: {}", TAG_HEADER, e),
+            }
+        }
+        let parameters = headers
+            .get(RUN_PARAMS_HEADER)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| match serde_json::from_str(v) {
+                Ok(params @ serde_json::Value::Object(_)) => Some(params),
+                _ => {
+                    warn!("Ignoring {} header: not a JSON object", RUN_PARAMS_HEADER);
+                    None
+                }
+            });
+        Self {
+            project: value(headers, PROJECT_HEADER),
+            run_id: value(headers, RUN_ID_HEADER),
+            tags,
+            started_by: value(headers, RUN_STARTED_BY_HEADER),
+            parameters,
+            parent_run_id: value(headers, PARENT_RUN_HEADER),
+        }
+    }
+}
+
+/// Whether a request header is one of these, and so not forwarded
+pub fn is_attribution_header(name: &str) -> bool {
+    [
+        AGENT_NAME_HEADER,
+        PROJECT_HEADER,
+        RUN_ID_HEADER,
+        TAG_HEADER,
+        RUN_STARTED_BY_HEADER,
+        RUN_PARAMS_HEADER,
+        PARENT_RUN_HEADER,
+    ]
+    .contains(&name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_attribution_from_headers() {
+        let mut headers = HeaderMap::new();
+        headers.insert(PROJECT_HEADER, " billing ".parse().unwrap());
+        headers.insert(RUN_ID_HEADER, "nightly-1042".parse().unwrap());
+        headers.append(TAG_HEADER, "ci, backend".parse().unwrap());
+        headers.append(TAG_HEADER, "ci,not a tag".parse().unwrap());
+        headers.insert(RUN_PARAMS_HEADER, r#"{"shard": 3}"#.parse().unwrap());
+        headers.insert(PARENT_RUN_HEADER, "nightly".parse().unwrap());
+        assert_eq!(
+            Attribution::from_headers(&headers),
+            Attribution {
+                project: Some("billing".to_string()),
+                run_id: Some("nightly-1042".to_string()),
+                tags: vec!["ci".to_string(), "backend".to_string()],
+                started_by: None,
+                parameters: Some(serde_json::json!({"shard": 3})),
+                parent_run_id: Some("nightly".to_string()),
+            }
+        );
+        assert_eq!(
+            Attribution::from_headers(&HeaderMap::new()),
+            Attribution::default()
+        );
+        assert!(is_attribution_header("x-sentinel-run-id"));
+        assert!(!is_attribution_header("x-sentinel-redo-of"));
+
+        let mut headers = HeaderMap::new();
+        headers.insert(RUN_PARAMS_HEADER, "[1, 2]".parse().unwrap());
+        assert_eq!(Attribution::from_headers(&headers).parameters, None);
+    }
+}
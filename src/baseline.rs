@@ -0,0 +1,294 @@
+//! Baselines: expected tool usage and file scope for an agent role.
+//!
+//! Each tool call in a response is checked against the baselines that apply
+//! to its agent. A tool the role isn't expected to use, or a path outside its
+//! scope, is recorded as a violation. Baselines with `block: true` also stop
+//! the agent there: a streamed response is cut off before the chunk that
+//! completes the call, and the follow-up request carrying a call's result is
+//! refused.
+//!
+//! ```yaml
+//! role: backend-refactor
+//! agents: ["backend-*"]        # agent names; every agent when omitted
+//! tools: [Read, Edit, Grep, Bash]
+//! paths: ["src/**", "tests/**"] # relative to the agent's working directory
+//! path_tools: [Edit, Write]     # tools whose paths are checked; all when omitted
+//! block: false
+//! ```
+
+use std::collections::HashSet;
+use std::path::Path;
+use std::sync::Mutex;
+
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use serde::Deserialize;
+
+use crate::event::Violation;
+use crate::parsers::{AnthropicRequest, ToolCall};
+use crate::scope::{tool_paths, PathScope};
+
+#[derive(Debug, thiserror::Error)]
+pub enum BaselineError {
+    #[error("failed to read {path}: {source}")]
+    Read {
+        path: String,
+        source: std::io::Error,
+    },
+    #[error("invalid baseline {path}: {source}")]
+    Parse {
+        path: String,
+        source: serde_yaml::Error,
+    },
+    #[error("invalid glob in {path}: {source}")]
+    Glob {
+        path: String,
+        source: globset::Error,
+    },
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct BaselineFile {
+    pub role: String,
+    #[serde(default)]
+    pub agents: Option<Vec<String>>,
+    #[serde(default)]
+    pub tools: Option<Vec<String>>,
+    #[serde(default)]
+    pub paths: Option<Vec<String>>,
+    #[serde(default)]
+    pub path_tools: Option<Vec<String>>,
+    #[serde(default)]
+    pub block: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct Baseline {
+    /// What kind of rule this is, for violation sources ("baseline", "sandbox")
+    kind: &'static str,
+    pub role: String,
+    agents: Option<GlobSet>,
+    tools: Option<HashSet<String>>,
+    paths: Option<PathScope>,
+    path_tools: Option<HashSet<String>>,
+    pub block: bool,
+}
+
+impl Baseline {
+    pub fn load(path: &Path) -> Result<Self, BaselineError> {
+        let text = std::fs::read_to_string(path).map_err(|source| BaselineError::Read {
+            path: path.display().to_string(),
+            source,
+        })?;
+        Self::parse(&text, &path.display().to_string())
+    }
+
+    fn parse(yaml: &str, origin: &str) -> Result<Self, BaselineError> {
+        let file: BaselineFile =
+            serde_yaml::from_str(yaml).map_err(|source| BaselineError::Parse {
+                path: origin.to_string(),
+                source,
+            })?;
+        Self::from_file("baseline", file, origin)
+    }
+
+    pub(crate) fn from_file(
+        kind: &'static str,
+        file: BaselineFile,
+        origin: &str,
+    ) -> Result<Self, BaselineError> {
+        let glob_error = |source| BaselineError::Glob {
+            path: origin.to_string(),
+            source,
+        };
+
+        let agents = match file.agents {
+            Some(patterns) => {
+                let mut builder = GlobSetBuilder::new();
+                for pattern in &patterns {
+                    builder.add(Glob::new(pattern).map_err(glob_error)?);
+                }
+                Some(builder.build().map_err(glob_error)?)
+            }
+            None => None,
+        };
+        let paths = match file.paths {
+            Some(patterns) => Some(PathScope::new(&patterns).map_err(glob_error)?),
+            None => None,
+        };
+
+        Ok(Self {
+            kind,
+            role: file.role,
+            agents,
+            tools: file.tools.map(|t| t.into_iter().collect()),
+            paths,
+            path_tools: file.path_tools.map(|t| t.into_iter().collect()),
+            block: file.block,
+        })
+    }
+
+    fn applies_to(&self, agent: Option<&str>) -> bool {
+        match (&self.agents, agent) {
+            (None, _) => true,
+            (Some(globs), Some(agent)) => globs.is_match(agent),
+            (Some(_), None) => false,
+        }
+    }
+
+    /// How a tool call deviates from this baseline, if at all
+    fn deviations(&self, call: &ToolCall, working_dir: Option<&str>) -> Vec<String> {
+        let mut deviations = Vec::new();
+        if let Some(ref tools) = self.tools {
+            if !tools.contains(&call.name) {
+                deviations.push(format!(
+                    "{} is not an expected tool for {}",
+                    call.name, self.role
+                ));
+            }
+        }
+        let checks_paths = self
+            .path_tools
+            .as_ref()
+            .is_none_or(|tools| tools.contains(&call.name));
+        if let (Some(scope), true) = (&self.paths, checks_paths) {
+            for path in tool_paths(call) {
+                if !scope.contains(&path, working_dir) {
+                    deviations.push(format!(
+                        "{} touched {} outside {}'s scope ({})",
+                        call.name,
+                        path,
+                        self.role,
+                        scope.patterns().join(", ")
+                    ));
+                }
+            }
+        }
+        deviations
+    }
+}
+
+pub struct BaselineMonitor {
+    baselines: Vec<Baseline>,
+    /// Tool use ids whose follow-up request is refused
+    blocked: Mutex<HashSet<String>>,
+}
+
+impl BaselineMonitor {
+    pub fn new(baselines: Vec<Baseline>) -> Self {
+        Self {
+            baselines,
+            blocked: Mutex::new(HashSet::new()),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.baselines.is_empty()
+    }
+
+    /// Whether any baseline that applies to `agent` blocks
+    pub fn blocks(&self, agent: Option<&str>) -> bool {
+        self.baselines.iter().any(|b| b.block && b.applies_to(agent))
+    }
+
+    /// Check a response's tool calls, remembering any that should block
+    pub fn check(
+        &self,
+        agent: Option<&str>,
+        working_dir: Option<&str>,
+        calls: &[ToolCall],
+    ) -> Vec<Violation> {
+        let mut violations = Vec::new();
+        for baseline in self.baselines.iter().filter(|b| b.applies_to(agent)) {
+            for call in calls {
+                for message in baseline.deviations(call, working_dir) {
+                    if baseline.block {
+                        self.lock().insert(call.id.clone());
+                    }
+                    violations.push(Violation {
+                        source: format!("{}:{}", baseline.kind, baseline.role),
+                        tool: call.name.clone(),
+                        tool_use_id: Some(call.id.clone()),
+                        message,
+                        blocked: baseline.block,
+                        mismatches: Vec::new(),
+                    });
+                }
+            }
+        }
+        violations
+    }
+
+    /// The blocked tool call whose result this request carries, if any
+    pub fn blocked_call(&self, request: &AnthropicRequest) -> Option<ToolCall> {
+        let blocked = self.lock();
+        request
+            .last_tool_calls()
+            .into_iter()
+            .find(|call| blocked.contains(&call.id))
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashSet<String>> {
+        self.blocked.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn call(name: &str, input: serde_json::Value) -> ToolCall {
+        ToolCall {
+            id: format!("toolu_{}", name),
+            name: name.to_string(),
+            input,
+        }
+    }
+
+    #[test]
+    fn test_flags_new_tools_and_out_of_scope_paths() {
+        let baseline = Baseline::parse(
+            "role: backend\nagents: ['backend-*']\ntools: [Read, Edit]\npaths: ['src/**']\nblock: true\n",
+            "test.yaml",
+        )
+        .unwrap();
+        let monitor = BaselineMonitor::new(vec![baseline]);
+        let cwd = Some("/repo");
+
+        let violations = monitor.check(
+            Some("backend-fox"),
+            cwd,
+            &[
+                call("Edit", serde_json::json!({"file_path": "/repo/src/lib.rs"})),
+                call("Read", serde_json::json!({"file_path": "/repo/.env"})),
+                call(
+                    "WebFetch",
+                    serde_json::json!({"url": "https://example.com"}),
+                ),
+            ],
+        );
+        assert_eq!(violations.len(), 2);
+        assert!(violations.iter().all(|v| v.blocked));
+        assert!(violations.iter().any(|v| v.message.contains(".env")));
+        assert!(violations.iter().any(|v| v.tool == "WebFetch"));
+
+        // Other agents aren't covered by this role
+        assert!(monitor.blocks(Some("backend-fox")) && !monitor.blocks(Some("docs-owl")));
+        assert!(monitor
+            .check(
+                Some("docs-owl"),
+                cwd,
+                &[call("WebFetch", serde_json::json!({}))]
+            )
+            .is_empty());
+
+        let request: AnthropicRequest = serde_json::from_value(serde_json::json!({
+            "model": "claude-sonnet-4",
+            "messages": [{"role": "assistant", "content": [
+                {"type": "tool_use", "id": "toolu_WebFetch", "name": "WebFetch", "input": {}}
+            ]}]
+        }))
+        .unwrap();
+        assert_eq!(monitor.blocked_call(&request).unwrap().name, "WebFetch");
+    }
+}
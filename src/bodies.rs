@@ -0,0 +1,218 @@
+//! Request bodies kept whole without repeating the conversation.
+//!
+//! Every request of a conversation sends the history before it again, so
+//! keeping each body in full would grow storage with the square of the
+//! conversation's length. A request's raw capture is stored instead as a
+//! skeleton: each message, the system prompt, and the tool definitions are
+//! replaced by the SHA-256 of their JSON, and stored once in `body_parts`
+//! however many requests repeat them. Reading the capture puts the body
+//! back together, so `sentinel redo`, replays, and exports see the request
+//! as it was sent (with its keys in sorted order).
+
+use ring::digest::{digest, SHA256};
+use serde_json::Value;
+use sqlx::{SqliteConnection, SqlitePool};
+
+use crate::storage::add_column_if_missing;
+
+/// Starts a raw capture stored as a skeleton
+const SKELETON_PREFIX: &str = "sentinel-parts:";
+/// Key of the object a part is replaced by in a skeleton
+const PART_KEY: &str = "$sentinel_part";
+/// Arrays whose elements are stored one part each
+const SPLIT_ARRAYS: &[&str] = &["messages", "contents"];
+/// Fields stored as one part each
+const SPLIT_FIELDS: &[&str] = &["system", "tools", "systemInstruction"];
+
+pub async fn init_schema(pool: &SqlitePool) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS body_parts (
+            hash TEXT PRIMARY KEY,
+            part TEXT NOT NULL,
+            last_used TEXT
+        )
+        "#,
+    )
+    .execute(pool)
+    .await?;
+    add_column_if_missing(pool, "body_parts", "last_used", "TEXT").await?;
+    Ok(())
+}
+
+/// Delete parts no body stored since `before` uses, once the bodies that
+/// did are gone
+pub async fn prune(conn: &mut SqliteConnection, before: &str) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query("DELETE FROM body_parts WHERE last_used < ?")
+        .bind(before)
+        .execute(conn)
+        .await?;
+    Ok(result.rows_affected())
+}
+
+/// Whether a request body is stored as a skeleton, so needn't be capped
+pub fn splittable(body: &[u8]) -> bool {
+    serde_json::from_slice::<Value>(body).is_ok_and(|value| {
+        value
+            .get("messages")
+            .or_else(|| value.get("contents"))
+            .is_some_and(Value::is_array)
+    })
+}
+
+/// Replace a value with a reference to it, noting it among the parts
+fn part(value: &mut Value, parts: &mut Vec<(String, String)>) {
+    let json = value.to_string();
+    let hash: String = digest(&SHA256, json.as_bytes())
+        .as_ref()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect();
+    *value = serde_json::json!({ PART_KEY: hash });
+    parts.push((hash, json));
+}
+
+/// The skeleton of a request body and the parts taken out of it, or `None`
+/// for bodies stored as they are
+fn split(body: &str) -> Option<(String, Vec<(String, String)>)> {
+    let mut value: Value = serde_json::from_str(body).ok()?;
+    let fields = value.as_object_mut()?;
+    let mut parts = Vec::new();
+    for name in SPLIT_ARRAYS {
+        if let Some(Value::Array(items)) = fields.get_mut(*name) {
+            for item in items {
+                part(item, &mut parts);
+            }
+        }
+    }
+    for name in SPLIT_FIELDS {
+        if let Some(field) = fields.get_mut(*name) {
+            part(field, &mut parts);
+        }
+    }
+    if parts.is_empty() {
+        return None;
+    }
+    Some((format!("{}{}", SKELETON_PREFIX, value), parts))
+}
+
+/// Store a request body's parts, returning the skeleton to keep in its
+/// place; bodies that don't split are returned as they are
+pub async fn store(conn: &mut SqliteConnection, body: &str) -> Result<String, sqlx::Error> {
+    let Some((skeleton, parts)) = split(body) else {
+        return Ok(body.to_string());
+    };
+    let now = chrono::Utc::now().to_rfc3339();
+    for (hash, part) in parts {
+        sqlx::query(
+            r#"
+            INSERT INTO body_parts (hash, part, last_used) VALUES (?, ?, ?)
+            ON CONFLICT(hash) DO UPDATE SET last_used = excluded.last_used
+            "#,
+        )
+        .bind(hash)
+        .bind(part)
+        .bind(&now)
+        .execute(&mut *conn)
+        .await?;
+    }
+    Ok(skeleton)
+}
+
+/// Put the parts back into a skeleton
+async fn fill(pool: &SqlitePool, value: &mut Value) -> Result<(), sqlx::Error> {
+    let reference = value
+        .as_object()
+        .filter(|fields| fields.len() == 1)
+        .and_then(|fields| fields.get(PART_KEY))
+        .and_then(Value::as_str)
+        .map(str::to_string);
+    if let Some(hash) = reference {
+        let part: Option<String> = sqlx::query_scalar("SELECT part FROM body_parts WHERE hash = ?")
+            .bind(&hash)
+            .fetch_optional(pool)
+            .await?;
+        let part = part.ok_or_else(|| {
+            sqlx::Error::Protocol(format!("raw body part {} is missing", hash))
+        })?;
+        *value = serde_json::from_str(&part).unwrap_or(Value::String(part));
+        return Ok(());
+    }
+    let Some(fields) = value.as_object_mut() else {
+        return Ok(());
+    };
+    for name in SPLIT_ARRAYS {
+        if let Some(Value::Array(items)) = fields.get_mut(*name) {
+            for item in items {
+                Box::pin(fill(pool, item)).await?;
+            }
+        }
+    }
+    for name in SPLIT_FIELDS {
+        if let Some(field) = fields.get_mut(*name) {
+            Box::pin(fill(pool, field)).await?;
+        }
+    }
+    Ok(())
+}
+
+/// A raw capture as it was sent, reassembled if it was stored as a skeleton
+pub async fn join(pool: &SqlitePool, raw: String) -> Result<String, sqlx::Error> {
+    let Some(skeleton) = raw.strip_prefix(SKELETON_PREFIX) else {
+        return Ok(raw);
+    };
+    let Ok(mut value) = serde_json::from_str::<Value>(skeleton) else {
+        return Ok(raw);
+    };
+    fill(pool, &mut value).await?;
+    Ok(value.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sqlx::sqlite::SqlitePoolOptions;
+
+    #[tokio::test]
+    async fn test_repeated_history_is_stored_once_and_joined_back() {
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        init_schema(&pool).await.unwrap();
+
+        let first = serde_json::json!({
+            "model": "claude-sonnet-4",
+            "system": "You are terse.",
+            "messages": [{"role": "user", "content": "Hi"}],
+        });
+        let mut second = first.clone();
+        second["messages"]
+            .as_array_mut()
+            .unwrap()
+            .extend([
+                serde_json::json!({"role": "assistant", "content": "Hello."}),
+                serde_json::json!({"role": "user", "content": "Bye"}),
+            ]);
+
+        let mut conn = pool.acquire().await.unwrap();
+        let mut skeletons = Vec::new();
+        for body in [&first, &second] {
+            assert!(splittable(body.to_string().as_bytes()));
+            skeletons.push(store(&mut conn, &body.to_string()).await.unwrap());
+        }
+        drop(conn);
+        let parts: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM body_parts")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(parts, 4);
+        assert!(!skeletons[1].contains("Hello."));
+
+        let joined = join(&pool, skeletons[1].clone()).await.unwrap();
+        assert_eq!(serde_json::from_str::<Value>(&joined).unwrap(), second);
+        assert_eq!(join(&pool, "event: ping".to_string()).await.unwrap(), "event: ping");
+        assert!(!splittable(b"{\"model\":\"m\"}"));
+    }
+}
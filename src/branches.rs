@@ -0,0 +1,240 @@
+//! Conversation branches: retried turns and forks.
+//!
+//! Each request's message history is fingerprinted a message at a time, so
+//! a history's fingerprint after k messages is the fingerprint of its first
+//! k. A request whose whole history opens a new one is the turn the new one
+//! continues. Sent again unchanged, it's a retry; continued a second time,
+//! as when a message is edited or the conversation rewound, the new request
+//! forks it. Both are recorded on the request's event, and the transcript
+//! shows the branches instead of interleaving them. Requests sharing nothing
+//! with earlier ones (a compacted conversation, a side request for a title)
+//! start a tree of their own.
+
+use ring::digest::{Context, SHA256};
+use sqlx::SqlitePool;
+use uuid::Uuid;
+
+use crate::event::{Branch, BranchKind};
+use crate::parsers::AnthropicRequest;
+
+/// Hex characters of a fingerprint kept
+const FINGERPRINT_CHARS: usize = 32;
+/// Histories older than this are pruned at startup; a request retrying or
+/// forking one starts a tree of its own
+const HISTORY_TTL_DAYS: i64 = 30;
+
+/// Fingerprints of each prefix of a request's messages; the kth is of its
+/// first k + 1. Only what the model reads counts, so moving a cache_control
+/// marker doesn't change them.
+pub fn fingerprints(request: &AnthropicRequest) -> Vec<String> {
+    let mut previous = String::new();
+    request
+        .messages
+        .iter()
+        .map(|message| {
+            let mut context = Context::new(&SHA256);
+            context.update(previous.as_bytes());
+            context.update(message.role.as_bytes());
+            context.update(&serde_json::to_vec(&message.content).unwrap_or_default());
+            let hex: String = context
+                .finish()
+                .as_ref()
+                .iter()
+                .map(|b| format!("{:02x}", b))
+                .collect();
+            previous = hex[..FINGERPRINT_CHARS].to_string();
+            previous.clone()
+        })
+        .collect()
+}
+
+#[derive(Clone)]
+pub struct BranchStore {
+    pool: SqlitePool,
+}
+
+impl BranchStore {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool }
+    }
+
+    pub async fn init_schema(&self) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS request_histories (
+                event_id TEXT PRIMARY KEY,
+                session_id TEXT NOT NULL,
+                messages INTEGER NOT NULL,
+                fingerprint TEXT NOT NULL,
+                parent_id TEXT,
+                kind TEXT,
+                timestamp TEXT NOT NULL
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+        sqlx::query(
+            "CREATE INDEX IF NOT EXISTS idx_request_histories_fingerprint ON request_histories(session_id, fingerprint)",
+        )
+        .execute(&self.pool)
+        .await?;
+        sqlx::query(
+            "CREATE INDEX IF NOT EXISTS idx_request_histories_parent ON request_histories(parent_id)",
+        )
+        .execute(&self.pool)
+        .await?;
+
+        let cutoff = (chrono::Utc::now() - chrono::Duration::days(HISTORY_TTL_DAYS)).to_rfc3339();
+        sqlx::query("DELETE FROM request_histories WHERE timestamp < ?")
+            .bind(cutoff)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    /// Record a request's place in its session's conversation tree; the
+    /// branch it starts, if it retries or forks an earlier request
+    pub async fn place(
+        &self,
+        event_id: Uuid,
+        session_id: &str,
+        fingerprints: &[String],
+    ) -> Result<Option<Branch>, sqlx::Error> {
+        let Some(own) = fingerprints.last() else {
+            return Ok(None);
+        };
+        // The longest earlier history this one opens with, latest first
+        let placeholders = vec!["?"; fingerprints.len()].join(", ");
+        let sql = format!(
+            r#"
+            SELECT event_id, messages FROM request_histories
+            WHERE session_id = ? AND fingerprint IN ({})
+            ORDER BY messages DESC, timestamp DESC
+            LIMIT 1
+            "#,
+            placeholders
+        );
+        let mut query = sqlx::query_as::<_, (String, i64)>(&sql).bind(session_id);
+        for fingerprint in fingerprints {
+            query = query.bind(fingerprint);
+        }
+        let parent = query.fetch_optional(&self.pool).await?;
+
+        let branch = match parent {
+            Some((ref parent_id, shared)) => {
+                let kind = if shared as usize == fingerprints.len() {
+                    Some(BranchKind::Retry)
+                } else {
+                    let continued: bool = sqlx::query_scalar(
+                        "SELECT EXISTS(SELECT 1 FROM request_histories WHERE parent_id = ? AND kind IS NOT 'retry')",
+                    )
+                    .bind(parent_id)
+                    .fetch_one(&self.pool)
+                    .await?;
+                    continued.then_some(BranchKind::Fork)
+                };
+                kind.and_then(|kind| {
+                    Some(Branch {
+                        kind,
+                        from: parent_id.parse().ok()?,
+                        shared: shared as usize,
+                    })
+                })
+            }
+            None => None,
+        };
+
+        sqlx::query(
+            r#"
+            INSERT OR IGNORE INTO request_histories
+                (event_id, session_id, messages, fingerprint, parent_id, kind, timestamp)
+            VALUES (?, ?, ?, ?, ?, ?, ?)
+            "#,
+        )
+        .bind(event_id.to_string())
+        .bind(session_id)
+        .bind(fingerprints.len() as i64)
+        .bind(own)
+        .bind(parent.as_ref().map(|(id, _)| id))
+        .bind(branch.as_ref().map(|b| match b.kind {
+            BranchKind::Retry => "retry",
+            BranchKind::Fork => "fork",
+        }))
+        .bind(chrono::Utc::now().to_rfc3339())
+        .execute(&self.pool)
+        .await?;
+        Ok(branch)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::Storage;
+
+    fn request(messages: &[&str]) -> AnthropicRequest {
+        let messages: Vec<serde_json::Value> = messages
+            .iter()
+            .enumerate()
+            .map(|(i, text)| {
+                let role = if i % 2 == 0 { "user" } else { "assistant" };
+                serde_json::json!({"role": role, "content": text})
+            })
+            .collect();
+        serde_json::from_value(serde_json::json!({
+            "model": "claude-sonnet-4",
+            "max_tokens": 100,
+            "messages": messages,
+        }))
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_retries_and_edits_branch_the_conversation() {
+        let path = std::env::temp_dir().join(format!("sentinel-test-{}.db", Uuid::new_v4()));
+        let storage = Storage::new(&path).await.unwrap();
+        let store = BranchStore::new(storage.pool());
+        store.init_schema().await.unwrap();
+        let place = |messages: &'static [&'static str]| {
+            let store = store.clone();
+            let id = Uuid::new_v4();
+            async move {
+                let branch = store
+                    .place(id, "s1", &fingerprints(&request(messages)))
+                    .await
+                    .unwrap();
+                (id, branch)
+            }
+        };
+
+        let (first, branch) = place(&["fix the bug"]).await;
+        assert!(branch.is_none());
+        let (second, branch) = place(&["fix the bug", "done", "now test it"]).await;
+        assert!(branch.is_none());
+        let (_, branch) = place(&["fix the bug", "done", "now test it"]).await;
+        let branch = branch.unwrap();
+        assert_eq!((branch.kind, branch.from, branch.shared), (BranchKind::Retry, second, 3));
+        // The last message edited: the first turn is continued again
+        let (_, branch) = place(&["fix the bug", "done", "now lint it"]).await;
+        let branch = branch.unwrap();
+        assert_eq!((branch.kind, branch.from, branch.shared), (BranchKind::Fork, first, 1));
+        // Carrying on along the retried turn's line is no fork
+        let (_, branch) = place(&["fix the bug", "done", "now test it", "ok", "thanks"]).await;
+        assert!(branch.is_none());
+        // Unrelated history starts a tree of its own
+        let (_, branch) = place(&["write a title for this"]).await;
+        assert!(branch.is_none());
+
+        // Long-finished conversations are let go
+        sqlx::query("UPDATE request_histories SET timestamp = '2020-01-01T00:00:00+00:00'")
+            .execute(&storage.pool())
+            .await
+            .unwrap();
+        store.init_schema().await.unwrap();
+        let (_, branch) = place(&["fix the bug", "done", "now test it"]).await;
+        assert!(branch.is_none());
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,475 @@
+//! Token and cost budgets per agent, session, or day (`--budgets`).
+//!
+//! ```yaml
+//! budgets:
+//!   - per: agent             # each agent, per UTC day
+//!     max_usd: 20
+//!   - per: agent
+//!     agents: [nightly-batch]  # only these agents
+//!     max_tokens: 5000000
+//!   - per: session           # each session, over its whole life
+//!     max_usd: 5
+//!     action: warn
+//!   - per: day               # all traffic together, per UTC day
+//!     max_usd: 100
+//! ```
+//!
+//! Every budget that covers a request applies. Cost is each response's
+//! price (see `pricing`), tokens all the tokens it was billed for, cache
+//! reads and writes included. A budget that is used up raises a
+//! `budget_exceeded` alert once. With `action: reject` (the default)
+//! requests it covers are then refused with a 429 until the day turns, or
+//! for a session budget, for the rest of the session; `action: warn` only
+//! alerts. A session idle for a whole UTC day is forgotten.
+
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Mutex;
+
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::Deserialize;
+use sqlx::SqlitePool;
+
+use crate::event::Payload;
+use crate::parsers::Usage;
+use crate::pricing;
+
+pub const BUDGET_ALERT_KIND: &str = "budget_exceeded";
+
+#[derive(Debug, thiserror::Error)]
+pub enum BudgetsError {
+    #[error("failed to read {path}: {source}")]
+    Read {
+        path: String,
+        source: std::io::Error,
+    },
+    #[error("invalid budgets {path}: {source}")]
+    Parse {
+        path: String,
+        source: serde_yaml::Error,
+    },
+    #[error("invalid budgets {path}: {message}")]
+    Invalid { path: String, message: String },
+}
+
+/// What a budget is counted over
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Per {
+    /// Each agent, per UTC day
+    Agent,
+    /// Each session, over its life
+    Session,
+    /// All traffic, per UTC day
+    Day,
+}
+
+/// What happens once a budget is used up
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    /// Alert only
+    Warn,
+    /// Alert, then refuse the requests it covers
+    #[default]
+    Reject,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Budget {
+    pub per: Per,
+    /// Agents the budget covers; all of them when empty
+    #[serde(default)]
+    pub agents: Vec<String>,
+    #[serde(default)]
+    pub max_usd: Option<f64>,
+    #[serde(default)]
+    pub max_tokens: Option<u64>,
+    #[serde(default)]
+    pub action: Action,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct BudgetsFile {
+    budgets: Vec<Budget>,
+}
+
+pub fn load(path: &Path) -> Result<Vec<Budget>, BudgetsError> {
+    let origin = path.display().to_string();
+    let text = std::fs::read_to_string(path).map_err(|source| BudgetsError::Read {
+        path: origin.clone(),
+        source,
+    })?;
+    parse(&text, &origin)
+}
+
+fn parse(yaml: &str, origin: &str) -> Result<Vec<Budget>, BudgetsError> {
+    let file: BudgetsFile = serde_yaml::from_str(yaml).map_err(|source| BudgetsError::Parse {
+        path: origin.to_string(),
+        source,
+    })?;
+    let invalid = |message: &str| BudgetsError::Invalid {
+        path: origin.to_string(),
+        message: message.to_string(),
+    };
+
+    if file.budgets.is_empty() {
+        return Err(invalid("no budgets defined"));
+    }
+    for budget in &file.budgets {
+        if budget.max_usd.is_none() && budget.max_tokens.is_none() {
+            return Err(invalid("each budget needs max_usd, max_tokens, or both"));
+        }
+        if budget.per == Per::Day && !budget.agents.is_empty() {
+            return Err(invalid(
+                "a per: day budget covers all traffic; use per: agent to limit agents",
+            ));
+        }
+    }
+    Ok(file.budgets)
+}
+
+/// Cost and tokens counted against budgets
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Spend {
+    pub usd: f64,
+    pub tokens: u64,
+}
+
+impl Spend {
+    /// What a response with this usage counts for; unpriced models cost $0
+    pub fn of(model: Option<&str>, usage: &Usage) -> Self {
+        Self {
+            usd: pricing::cost_usd(model, usage).unwrap_or(0.0),
+            tokens: usage.total_tokens().max(0) as u64,
+        }
+    }
+
+    fn add(&mut self, other: Spend) {
+        self.usd += other.usd;
+        self.tokens += other.tokens;
+    }
+}
+
+/// Spend so far: today's per agent and in total, each session's over its life
+#[derive(Default)]
+struct Counters {
+    day: Option<NaiveDate>,
+    agents: HashMap<String, Spend>,
+    total: Spend,
+    /// Each session's spend, and the day it was last seen
+    sessions: HashMap<String, (Spend, NaiveDate)>,
+}
+
+impl Counters {
+    /// Start a new day's counts, forgetting sessions idle since before
+    /// yesterday
+    fn roll_day(&mut self, now: DateTime<Utc>) {
+        let today = now.date_naive();
+        if self.day != Some(today) {
+            self.day = Some(today);
+            self.agents.clear();
+            self.total = Spend::default();
+            let yesterday = today.pred_opt().unwrap_or(today);
+            self.sessions.retain(|_, (_, seen)| *seen >= yesterday);
+        }
+    }
+
+    fn add_session(&mut self, session: String, spend: Spend, day: NaiveDate) {
+        let (spent, seen) = self.sessions.entry(session).or_insert((Spend::default(), day));
+        spent.add(spend);
+        *seen = (*seen).max(day);
+    }
+}
+
+/// A request refused for going over a budget
+#[derive(Debug)]
+pub struct Refusal {
+    pub message: String,
+    /// Seconds until the budget resets; none for a session's
+    pub retry_after: Option<u64>,
+}
+
+/// The configured budgets and spend against them; empty when `--budgets`
+/// isn't given
+#[derive(Default)]
+pub struct Budgets {
+    budgets: Vec<Budget>,
+    counters: Mutex<Counters>,
+}
+
+impl Budget {
+    fn covers(&self, agent: Option<&str>) -> bool {
+        self.agents.is_empty() || agent.is_some_and(|a| self.agents.iter().any(|b| b == a))
+    }
+
+    /// Who the budget is counted for, and its spend, if it covers a request
+    fn spent(
+        &self,
+        counters: &Counters,
+        agent: Option<&str>,
+        session: Option<&str>,
+    ) -> Option<(String, Spend)> {
+        if !self.covers(agent) {
+            return None;
+        }
+        match self.per {
+            Per::Agent => {
+                let agent = agent?;
+                let spent = counters.agents.get(agent).copied().unwrap_or_default();
+                Some((format!("Agent '{}' daily budget", agent), spent))
+            }
+            Per::Session => {
+                let session = session?;
+                let spent = counters
+                    .sessions
+                    .get(session)
+                    .map(|(spent, _)| *spent)
+                    .unwrap_or_default();
+                let short: String = session.chars().take(8).collect();
+                Some((format!("Session {} budget", short), spent))
+            }
+            Per::Day => Some(("Daily budget".to_string(), counters.total)),
+        }
+    }
+
+    /// How the budget is used up by `spent`, if it is
+    fn exceeded(&self, spent: Spend) -> Option<String> {
+        let usd = self
+            .max_usd
+            .filter(|max| spent.usd >= *max)
+            .map(|max| format!("${:.2} of ${:.2} spent", spent.usd, max));
+        let tokens = self
+            .max_tokens
+            .filter(|max| spent.tokens >= *max)
+            .map(|max| format!("{} of {} tokens used", spent.tokens, max));
+        match (usd, tokens) {
+            (Some(usd), Some(tokens)) => Some(format!("{}, {}", usd, tokens)),
+            (usd, tokens) => usd.or(tokens),
+        }
+    }
+
+    fn resets(&self) -> &'static str {
+        match self.per {
+            Per::Session => "for the rest of the session",
+            Per::Agent | Per::Day => "until the day turns (UTC)",
+        }
+    }
+}
+
+/// Seconds until the next UTC midnight
+fn until_tomorrow(now: DateTime<Utc>) -> u64 {
+    let tomorrow = now
+        .date_naive()
+        .succ_opt()
+        .and_then(|d| d.and_hms_opt(0, 0, 0));
+    tomorrow.map_or(0, |t| (t.and_utc() - now).num_seconds().max(1) as u64)
+}
+
+impl Budgets {
+    pub fn new(budgets: Vec<Budget>) -> Self {
+        Self {
+            budgets,
+            counters: Mutex::default(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.budgets.is_empty()
+    }
+
+    /// The refusal for a request a used-up rejecting budget covers
+    pub fn check(&self, agent: Option<&str>, session: Option<&str>) -> Option<Refusal> {
+        let now = Utc::now();
+        let mut counters = self.counters.lock().unwrap_or_else(|e| e.into_inner());
+        counters.roll_day(now);
+        self.budgets
+            .iter()
+            .filter(|b| b.action == Action::Reject)
+            .find_map(|budget| {
+                let (label, spent) = budget.spent(&counters, agent, session)?;
+                let exceeded = budget.exceeded(spent)?;
+                Some(Refusal {
+                    message: format!(
+                        "sentinel: {} is used up ({}); requests are refused {}",
+                        label,
+                        exceeded,
+                        budget.resets()
+                    ),
+                    retry_after: (budget.per != Per::Session).then(|| until_tomorrow(now)),
+                })
+            })
+    }
+
+    /// Count a response; the alert message for each budget it used up
+    pub fn record(&self, agent: Option<&str>, session: Option<&str>, spend: Spend) -> Vec<String> {
+        let now = Utc::now();
+        let mut counters = self.counters.lock().unwrap_or_else(|e| e.into_inner());
+        counters.roll_day(now);
+        let before: Vec<bool> = self
+            .budgets
+            .iter()
+            .map(|b| {
+                b.spent(&counters, agent, session)
+                    .is_some_and(|(_, spent)| b.exceeded(spent).is_some())
+            })
+            .collect();
+
+        if let Some(agent) = agent {
+            counters
+                .agents
+                .entry(agent.to_string())
+                .or_default()
+                .add(spend);
+        }
+        if let Some(session) = session {
+            counters.add_session(session.to_string(), spend, now.date_naive());
+        }
+        counters.total.add(spend);
+
+        self.budgets
+            .iter()
+            .zip(before)
+            .filter(|(_, was_exceeded)| !was_exceeded)
+            .filter_map(|(budget, _)| {
+                let (label, spent) = budget.spent(&counters, agent, session)?;
+                let exceeded = budget.exceeded(spent)?;
+                Some(match budget.action {
+                    Action::Warn => format!("{} is used up: {}", label, exceeded),
+                    Action::Reject => format!(
+                        "{} is used up: {}; requests are refused {}",
+                        label,
+                        exceeded,
+                        budget.resets()
+                    ),
+                })
+            })
+            .collect()
+    }
+
+    /// Load today's spend, and that of sessions active since yesterday, from
+    /// recorded responses
+    pub async fn load_spend(&self, pool: &SqlitePool) -> Result<(), sqlx::Error> {
+        let now = Utc::now();
+        let midnight = |day: NaiveDate| day.and_hms_opt(0, 0, 0).map_or(now, |t| t.and_utc());
+        let today = midnight(now.date_naive());
+        let yesterday = midnight(now.date_naive().pred_opt().unwrap_or(now.date_naive()));
+        let rows: Vec<SpendRow> = sqlx::query_as(
+            r#"
+            SELECT id, timestamp, agent, session_id, payload
+            FROM observability_events
+            WHERE json_extract(payload, '$.type') = 'assistant_response'
+              AND (timestamp >= ?1 OR session_id IN (
+                SELECT session_id FROM observability_events
+                WHERE timestamp >= ?2 AND session_id IS NOT NULL
+              ))
+            "#,
+        )
+        .bind(today.to_rfc3339())
+        .bind(yesterday.to_rfc3339())
+        .fetch_all(pool)
+        .await?;
+
+        let mut counters = Counters::default();
+        counters.roll_day(now);
+        for row in rows {
+            let response = match serde_json::from_str(&row.payload) {
+                Ok(Payload::AssistantResponse(response)) => response,
+                Ok(_) => continue,
+                Err(e) => {
+                    tracing::warn!("Skipping event {} in budget spend: {}", row.id, e);
+                    continue;
+                }
+            };
+            let Some(ref usage) = response.usage else {
+                continue;
+            };
+            let spend = Spend::of(response.model.as_deref(), usage);
+            if let Some(session) = row.session_id {
+                let day = DateTime::parse_from_rfc3339(&row.timestamp)
+                    .map_or(now.date_naive(), |t| t.with_timezone(&Utc).date_naive());
+                counters.add_session(session, spend, day);
+            }
+            if row.timestamp >= today.to_rfc3339() {
+                if let Some(agent) = row.agent {
+                    counters.agents.entry(agent).or_default().add(spend);
+                }
+                counters.total.add(spend);
+            }
+        }
+        *self.counters.lock().unwrap_or_else(|e| e.into_inner()) = counters;
+        Ok(())
+    }
+}
+
+#[derive(sqlx::FromRow)]
+struct SpendRow {
+    id: String,
+    timestamp: String,
+    agent: Option<String>,
+    session_id: Option<String>,
+    payload: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_budgets_alert_once_and_reject_what_they_cover() {
+        let budgets = Budgets::new(
+            parse(
+                "budgets:\n  - per: agent\n    agents: [calm-otter]\n    max_usd: 1\n  - per: session\n    max_tokens: 1000\n    action: warn\n",
+                "budgets.yaml",
+            )
+            .unwrap(),
+        );
+        let spend = |usd, tokens| Spend { usd, tokens };
+
+        assert!(budgets
+            .record(Some("calm-otter"), Some("s1"), spend(0.6, 400))
+            .is_empty());
+        assert!(budgets.check(Some("calm-otter"), Some("s1")).is_none());
+
+        let alerts = budgets.record(Some("calm-otter"), Some("s1"), spend(0.6, 700));
+        assert_eq!(alerts.len(), 2);
+        assert!(alerts[0].starts_with("Agent 'calm-otter' daily budget is used up: $1.20 of $1.00"));
+        assert!(alerts[1].contains("1100 of 1000 tokens"));
+
+        let refusal = budgets.check(Some("calm-otter"), Some("s2")).unwrap();
+        assert!(refusal.retry_after.is_some_and(|s| s <= 86_400));
+        // Not covered by the agent budget, and the session one only warns
+        assert!(budgets.check(Some("swift-fox"), Some("s1")).is_none());
+        // Already used up, so no second alert
+        assert!(budgets
+            .record(Some("calm-otter"), Some("s1"), spend(0.1, 10))
+            .is_empty());
+
+        let mut counters = Counters::default();
+        let noon = |day| chrono::TimeZone::with_ymd_and_hms(&Utc, 2026, 1, day, 12, 0, 0).unwrap();
+        counters.roll_day(noon(5));
+        counters.add_session("s1".to_string(), spend(0.1, 10), noon(5).date_naive());
+        counters.roll_day(noon(6));
+        assert!(counters.sessions.contains_key("s1"));
+        counters.roll_day(noon(7));
+        assert!(counters.sessions.is_empty());
+
+        let usage = Usage {
+            input_tokens: Some(10),
+            output_tokens: Some(5),
+            cache_read_tokens: Some(1000),
+            cache_creation_tokens: Some(200),
+            cost_usd: None,
+        };
+        assert_eq!(Spend::of(None, &usage).tokens, 1215);
+
+        assert!(parse("budgets:\n  - per: day\n", "b.yaml").is_err());
+        assert!(parse(
+            "budgets:\n  - per: day\n    agents: [a]\n    max_usd: 1\n",
+            "b.yaml"
+        )
+        .is_err());
+    }
+}
@@ -0,0 +1,217 @@
+//! Missed-traffic detection for `sentinel doctor`.
+//!
+//! Routing through the proxy hangs on an environment variable, and an agent
+//! started without it works just the same, unrecorded. Two checks catch that,
+//! from `/proc` on Linux, for the processes the current user can read:
+//!
+//! - Claude Code processes whose `ANTHROPIC_BASE_URL`/`ANTHROPIC_API_URL`
+//!   is unset or doesn't name a port a sentinel here listens on.
+//! - Any process (sentinel itself aside) with a connection open to
+//!   api.anthropic.com, which only the proxy should have.
+
+use std::net::IpAddr;
+
+pub const ANTHROPIC_API_HOST: &str = "api.anthropic.com";
+/// Variables that point Anthropic clients at a base URL
+const BASE_URL_VARS: &[&str] = &["ANTHROPIC_BASE_URL", "ANTHROPIC_API_URL"];
+/// Claude Code's npm package, in the path of the script node runs
+const CLAUDE_CODE_PACKAGE: &str = "@anthropic-ai/claude-code";
+
+/// A process that talks to Anthropic, or is an agent that should
+#[derive(Debug)]
+pub struct Process {
+    pub pid: u32,
+    pub program: String,
+    /// Its base URL variable; the outer None when the environment couldn't be
+    /// read
+    pub base_url: Option<Option<String>>,
+    /// Connections open straight to the Anthropic API
+    pub direct_connections: usize,
+}
+
+/// Whether a base URL reaches a local sentinel on one of `ports`
+pub fn routes_through(url: &str, ports: &[u16]) -> bool {
+    let Ok(url) = reqwest::Url::parse(url) else {
+        return false;
+    };
+    let local = match url.host_str() {
+        Some("localhost") => true,
+        Some(host) => host
+            .trim_start_matches('[')
+            .trim_end_matches(']')
+            .parse::<IpAddr>()
+            .is_ok_and(|ip| ip.is_loopback() || ip.is_unspecified()),
+        None => false,
+    };
+    local
+        && url
+            .port_or_known_default()
+            .is_some_and(|p| ports.contains(&p))
+}
+
+fn is_agent(args: &[String]) -> bool {
+    // `claude ...`, or `node .../claude-code/cli.js ...`
+    args.iter().take(2).any(|arg| {
+        let name = arg.rsplit('/').next().unwrap_or(arg);
+        name == "claude" || arg.contains(CLAUDE_CODE_PACKAGE)
+    })
+}
+
+/// Established TCP connections in a `/proc/net/tcp{,6}` table, as remote
+/// address, port, and socket inode
+fn parse_tcp_table(text: &str) -> Vec<(IpAddr, u16, u64)> {
+    const ESTABLISHED: &str = "01";
+    text.lines()
+        .skip(1)
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if fields.get(3) != Some(&ESTABLISHED) {
+                return None;
+            }
+            let (addr, port) = fields.get(2)?.split_once(':')?;
+            let port = u16::from_str_radix(port, 16).ok()?;
+            let inode = fields.get(9)?.parse().ok()?;
+            Some((parse_proc_addr(addr)?, port, inode))
+        })
+        .collect()
+}
+
+/// The kernel prints addresses as 32-bit words in host (little-endian) order
+fn parse_proc_addr(hex: &str) -> Option<IpAddr> {
+    let words: Vec<u32> = (0..hex.len() / 8)
+        .map(|i| u32::from_str_radix(&hex[i * 8..i * 8 + 8], 16))
+        .collect::<Result<_, _>>()
+        .ok()?;
+    let bytes: Vec<u8> = words.iter().flat_map(|w| w.to_le_bytes()).collect();
+    match bytes.len() {
+        4 => Some(IpAddr::from(<[u8; 4]>::try_from(bytes).ok()?)),
+        16 => {
+            let v6 = std::net::Ipv6Addr::from(<[u8; 16]>::try_from(bytes).ok()?);
+            Some(v6.to_ipv4_mapped().map_or(IpAddr::V6(v6), IpAddr::V4))
+        }
+        _ => None,
+    }
+}
+
+/// Agents and processes connected to `anthropic`, other than sentinel's own
+#[cfg(target_os = "linux")]
+pub fn scan(anthropic: &[IpAddr]) -> std::io::Result<Vec<Process>> {
+    use std::collections::HashSet;
+
+    let mut inodes = HashSet::new();
+    for table in ["/proc/net/tcp", "/proc/net/tcp6"] {
+        let Ok(text) = std::fs::read_to_string(table) else {
+            continue;
+        };
+        for (addr, port, inode) in parse_tcp_table(&text) {
+            if port == 443 && anthropic.contains(&addr) {
+                inodes.insert(inode);
+            }
+        }
+    }
+
+    let me = std::process::id();
+    let mut processes = Vec::new();
+    for entry in std::fs::read_dir("/proc")?.flatten() {
+        let Some(pid) = entry
+            .file_name()
+            .to_str()
+            .and_then(|s| s.parse::<u32>().ok())
+        else {
+            continue;
+        };
+        let dir = entry.path();
+        let Ok(cmdline) = std::fs::read(dir.join("cmdline")) else {
+            continue;
+        };
+        let args: Vec<String> = cmdline
+            .split(|b| *b == 0)
+            .filter(|a| !a.is_empty())
+            .map(|a| String::from_utf8_lossy(a).into_owned())
+            .collect();
+        let Some(program) = args
+            .first()
+            .map(|a| a.rsplit('/').next().unwrap_or(a).to_string())
+        else {
+            continue;
+        };
+        if pid == me || program == "sentinel" {
+            continue;
+        }
+        // Sockets of other users' processes can't be listed; those are skipped
+        let direct_connections = std::fs::read_dir(dir.join("fd"))
+            .into_iter()
+            .flatten()
+            .flatten()
+            .filter_map(|fd| std::fs::read_link(fd.path()).ok())
+            .filter_map(|link| {
+                let link = link.to_str()?.strip_prefix("socket:[")?.strip_suffix(']')?;
+                link.parse::<u64>().ok()
+            })
+            .filter(|inode| inodes.contains(inode))
+            .count();
+        if direct_connections == 0 && !is_agent(&args) {
+            continue;
+        }
+        let base_url = std::fs::read(dir.join("environ")).ok().map(|environ| {
+            environ.split(|b| *b == 0).find_map(|var| {
+                let var = String::from_utf8_lossy(var);
+                let (name, value) = var.split_once('=')?;
+                (BASE_URL_VARS.contains(&name) && !value.is_empty()).then(|| value.to_string())
+            })
+        });
+        processes.push(Process {
+            pid,
+            program,
+            base_url,
+            direct_connections,
+        });
+    }
+    processes.sort_by_key(|p| p.pid);
+    Ok(processes)
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn scan(_anthropic: &[IpAddr]) -> std::io::Result<Vec<Process>> {
+    Err(std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        "process environments and sockets are only read on Linux",
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tcp_table_routing_and_agent_detection() {
+        // 160.79.104.10:443, established, and a listening socket
+        let table = "  sl  local_address rem_address   st tx_queue rx_queue tr tm->when retrnsmt   uid  timeout inode\n\
+            0: 0100007F:2328 00000000:0000 0A 00000000:00000000 00:00000000 00000000  1000        0 1111 1 0 100 0 0 10 0\n\
+            1: 0F02000A:C350 0A684FA0:01BB 01 00000000:00000000 00:00000000 00000000  1000        0 2222 1 0 20 4 30 10 -1\n";
+        assert_eq!(
+            parse_tcp_table(table),
+            vec![("160.79.104.10".parse().unwrap(), 443, 2222)]
+        );
+        // The same address as an IPv4-mapped IPv6 peer
+        assert_eq!(
+            parse_proc_addr("0000000000000000FFFF00000A684FA0"),
+            Some("160.79.104.10".parse().unwrap())
+        );
+
+        assert!(routes_through("http://localhost:9000", &[9000]));
+        assert!(routes_through(
+            "http://127.0.0.1:9000/agent/reviewer",
+            &[9000]
+        ));
+        assert!(!routes_through("http://localhost:9001", &[9000]));
+        assert!(!routes_through("https://api.anthropic.com", &[443]));
+
+        let args = |s: &str| s.split(' ').map(String::from).collect::<Vec<_>>();
+        assert!(is_agent(&args("claude --resume abc")));
+        assert!(is_agent(&args(
+            "node /usr/lib/node_modules/@anthropic-ai/claude-code/cli.js"
+        )));
+        assert!(!is_agent(&args("python3 agent.py")));
+    }
+}
@@ -0,0 +1,334 @@
+//! Fenced code blocks in assistant text, and `sentinel code`.
+//!
+//! Assistant text is split into prose and fenced code segments (``` or ~~~
+//! fences, with the language from the info string). Each response's code
+//! blocks get a `code_blocks` row in the same transaction as the event, so
+//! the code an agent wrote during a run can be listed or written out without
+//! re-parsing every payload.
+
+use std::path::Path;
+
+use chrono::DateTime;
+use sqlx::{SqliteConnection, SqlitePool};
+
+use crate::derived;
+use crate::event::{ObservabilityEvent, Payload};
+use crate::storage::Storage;
+
+/// A run of assistant text
+#[derive(Debug, PartialEq)]
+pub enum Segment<'a> {
+    Prose(&'a str),
+    Code {
+        /// First word of the fence's info string, e.g. "rust"
+        language: Option<&'a str>,
+        code: &'a str,
+    },
+}
+
+/// An opening fence: its character, length, and info string
+fn fence(line: &str) -> Option<(char, usize, &str)> {
+    let indent = line.len() - line.trim_start_matches(' ').len();
+    if indent > 3 {
+        return None;
+    }
+    let rest = &line[indent..];
+    let marker = rest.chars().next().filter(|c| *c == '`' || *c == '~')?;
+    let len = rest.len() - rest.trim_start_matches(marker).len();
+    if len < 3 {
+        return None;
+    }
+    let info = rest[len..].trim();
+    // Backtick fences can't have backticks in their info string
+    if marker == '`' && info.contains('`') {
+        return None;
+    }
+    Some((marker, len, info))
+}
+
+fn closes(line: &str, marker: char, len: usize) -> bool {
+    matches!(fence(line), Some((m, l, info)) if m == marker && l >= len && info.is_empty())
+}
+
+/// Split text into prose and fenced code; an unclosed fence runs to the end
+pub fn segments(text: &str) -> Vec<Segment<'_>> {
+    let mut segments = Vec::new();
+    let mut prose_start = 0;
+    // Open fence: marker, length, language, where its code starts
+    let mut open: Option<(char, usize, Option<&str>, usize)> = None;
+    let mut offset = 0;
+
+    for line in text.split_inclusive('\n') {
+        let start = offset;
+        offset += line.len();
+        let content = line.trim_end_matches(['\n', '\r']);
+        match open {
+            None => {
+                if let Some((marker, len, info)) = fence(content) {
+                    if start > prose_start {
+                        segments.push(Segment::Prose(&text[prose_start..start]));
+                    }
+                    let language = info.split_whitespace().next();
+                    open = Some((marker, len, language, offset));
+                }
+            }
+            Some((marker, len, language, code_start)) => {
+                if closes(content, marker, len) {
+                    segments.push(Segment::Code {
+                        language,
+                        code: &text[code_start..start],
+                    });
+                    open = None;
+                    prose_start = offset;
+                }
+            }
+        }
+    }
+
+    match open {
+        Some((_, _, language, code_start)) => segments.push(Segment::Code {
+            language,
+            code: &text[code_start..],
+        }),
+        None if prose_start < text.len() => segments.push(Segment::Prose(&text[prose_start..])),
+        None => {}
+    }
+    segments
+}
+
+/// A stored code block
+#[derive(Debug, sqlx::FromRow)]
+pub struct CodeBlock {
+    pub event_id: String,
+    pub language: Option<String>,
+    pub code: String,
+    pub timestamp: String,
+}
+
+/// Record a response's code blocks; part of the event's insert transaction
+pub async fn record(
+    conn: &mut SqliteConnection,
+    event: &ObservabilityEvent,
+) -> Result<(), sqlx::Error> {
+    let Payload::AssistantResponse(ref response) = event.payload else {
+        return Ok(());
+    };
+    let Some(ref text) = response.text else {
+        return Ok(());
+    };
+    let blocks = segments(text)
+        .into_iter()
+        .filter_map(|segment| match segment {
+            Segment::Code { language, code } => Some((language, code)),
+            Segment::Prose(_) => None,
+        });
+    for (idx, (language, code)) in blocks.enumerate() {
+        sqlx::query(
+            r#"
+            INSERT OR IGNORE INTO code_blocks
+                (event_id, idx, session_id, agent, language, code, timestamp)
+            VALUES (?, ?, ?, ?, ?, ?, ?)
+            "#,
+        )
+        .bind(event.id.to_string())
+        .bind(idx as i64)
+        .bind(event.session_id.as_ref())
+        .bind(event.agent.as_ref())
+        .bind(language)
+        .bind(code)
+        .bind(event.timestamp.to_rfc3339())
+        .execute(&mut *conn)
+        .await?;
+    }
+    Ok(())
+}
+
+#[derive(Clone)]
+pub struct CodeBlockStore {
+    pool: SqlitePool,
+}
+
+impl CodeBlockStore {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool }
+    }
+
+    /// Create the table and its indexes; true when it's new and the blocks
+    /// of responses already recorded have to be split out into it
+    pub async fn init_schema(&self) -> Result<bool, sqlx::Error> {
+        let created = derived::create(
+            &self.pool,
+            "code_blocks",
+            r#"
+            CREATE TABLE IF NOT EXISTS code_blocks (
+                event_id TEXT NOT NULL,
+                idx INTEGER NOT NULL,
+                session_id TEXT,
+                agent TEXT,
+                language TEXT,
+                code TEXT NOT NULL,
+                timestamp TEXT NOT NULL,
+                PRIMARY KEY (event_id, idx)
+            )
+            "#,
+            |_| false,
+        )
+        .await?;
+        sqlx::query(
+            "CREATE INDEX IF NOT EXISTS idx_code_blocks_session ON code_blocks(session_id)",
+        )
+        .execute(&self.pool)
+        .await?;
+        sqlx::query("CREATE INDEX IF NOT EXISTS idx_code_blocks_agent ON code_blocks(agent)")
+            .execute(&self.pool)
+            .await?;
+        Ok(created)
+    }
+
+    /// A session's code blocks in order, or an agent's if no session matches
+    pub async fn blocks(
+        &self,
+        key: &str,
+        language: Option<&str>,
+    ) -> Result<Vec<CodeBlock>, sqlx::Error> {
+        for column in ["session_id", "agent"] {
+            let blocks: Vec<CodeBlock> = sqlx::query_as(&format!(
+                r#"
+                SELECT event_id, language, code, timestamp
+                FROM code_blocks
+                WHERE {} = ? AND (? IS NULL OR language = ? COLLATE NOCASE)
+                ORDER BY timestamp, idx
+                "#,
+                column
+            ))
+            .bind(key)
+            .bind(language)
+            .bind(language)
+            .fetch_all(&self.pool)
+            .await?;
+            if !blocks.is_empty() {
+                return Ok(blocks);
+            }
+        }
+        Ok(Vec::new())
+    }
+}
+
+/// File extension for a fence language, falling back to the language itself
+fn extension(language: Option<&str>) -> String {
+    let Some(language) = language else {
+        return "txt".to_string();
+    };
+    let language = language.to_ascii_lowercase();
+    let ext = match language.as_str() {
+        "rust" => "rs",
+        "python" | "py" => "py",
+        "javascript" | "js" => "js",
+        "typescript" | "ts" => "ts",
+        "tsx" => "tsx",
+        "bash" | "sh" | "shell" | "zsh" | "console" => "sh",
+        "yaml" | "yml" => "yaml",
+        "markdown" | "md" => "md",
+        "golang" | "go" => "go",
+        "c++" | "cpp" => "cpp",
+        "csharp" | "c#" => "cs",
+        "ruby" | "rb" => "rb",
+        "text" | "plaintext" => "txt",
+        other => {
+            let ext: String = other.chars().filter(char::is_ascii_alphanumeric).collect();
+            return if ext.is_empty() {
+                "txt".to_string()
+            } else {
+                ext
+            };
+        }
+    };
+    ext.to_string()
+}
+
+pub async fn run(
+    data_dir: &Path,
+    target: &str,
+    language: Option<&str>,
+    extract: Option<&Path>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let db_path = data_dir.join("sentinel.db");
+    if !db_path.exists() {
+        return Err("No recorded traffic. Run 'sentinel start' first.".into());
+    }
+    let storage = Storage::new(&db_path).await?;
+    let blocks = CodeBlockStore::new(storage.pool())
+        .blocks(target, language)
+        .await?;
+    if blocks.is_empty() {
+        println!("No code blocks for session or agent '{}'.", target);
+        return Ok(());
+    }
+
+    if let Some(dir) = extract {
+        std::fs::create_dir_all(dir)?;
+        for (n, block) in blocks.iter().enumerate() {
+            let name = format!(
+                "{:03}-{}.{}",
+                n + 1,
+                block.event_id.chars().take(8).collect::<String>(),
+                extension(block.language.as_deref())
+            );
+            std::fs::write(dir.join(&name), &block.code)?;
+        }
+        println!("Wrote {} code blocks to {}", blocks.len(), dir.display());
+        return Ok(());
+    }
+
+    for (n, block) in blocks.iter().enumerate() {
+        let time = DateTime::parse_from_rfc3339(&block.timestamp)
+            .map(|t| t.format("%H:%M:%S").to_string())
+            .unwrap_or_else(|_| block.timestamp.clone());
+        println!(
+            "── #{} [{}] {} · {} lines · event {}",
+            n + 1,
+            time,
+            block.language.as_deref().unwrap_or("(no language)"),
+            block.code.lines().count(),
+            block.event_id
+        );
+        print!("{}", block.code);
+        if !block.code.ends_with('\n') {
+            println!();
+        }
+        println!();
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_segments_split_prose_and_fenced_code() {
+        let text = "Here's the fix:\n\n```rust\nfn main() {}\n```\nThen run:\n~~~~ bash extra\nls\n```\nstill code\n~~~~\n```\nunclosed";
+        assert_eq!(
+            segments(text),
+            vec![
+                Segment::Prose("Here's the fix:\n\n"),
+                Segment::Code {
+                    language: Some("rust"),
+                    code: "fn main() {}\n",
+                },
+                Segment::Prose("Then run:\n"),
+                Segment::Code {
+                    language: Some("bash"),
+                    code: "ls\n```\nstill code\n",
+                },
+                Segment::Code {
+                    language: None,
+                    code: "unclosed",
+                },
+            ]
+        );
+        assert_eq!(segments("no code"), vec![Segment::Prose("no code")]);
+        assert_eq!(extension(Some("Rust")), "rs");
+        assert_eq!(extension(Some("toml")), "toml");
+    }
+}
@@ -0,0 +1,519 @@
+//! Shell commands in their own table, and `sentinel commands`.
+//!
+//! Each Bash call in a stored response gets a `commands` row in the same
+//! transaction as the event: the command line, the description and timeout
+//! the agent gave, and what a built-in classifier makes of its risk
+//! (recursive deletes, force pushes, piping downloads into a shell, ...).
+//! Who ran it, when, and what came back are on the call's `tool_calls` row,
+//! which `sentinel commands` reads alongside; the exit status is taken from
+//! the start of the result kept there.
+
+use std::path::Path;
+use std::sync::LazyLock;
+
+use chrono::{DateTime, Duration, Utc};
+use regex::Regex;
+use sqlx::{SqliteConnection, SqlitePool};
+
+use crate::derived;
+use crate::event::{ObservabilityEvent, Payload};
+use crate::parsers::ToolCall;
+use crate::scope;
+use crate::storage::Storage;
+
+/// Characters of a command line shown in the table
+const COMMAND_CHARS: usize = 100;
+
+/// Patterns of commands that can do damage that's hard to undo
+const RISKS: &[(&str, &str)] = &[
+    (r"\brm\s+(-\S*\s+)*-\S*[rR]", "recursive delete"),
+    (
+        r"\bgit\s+push\b.*\s(--force\b|--force-with-lease\b|-f\b)",
+        "force push",
+    ),
+    (
+        r"\bgit\s+reset\s+.*--hard\b",
+        "discards uncommitted changes",
+    ),
+    (
+        r"\bgit\s+clean\s+(-\S+\s+)*-\S*f",
+        "deletes untracked files",
+    ),
+    (
+        r"\bgit\s+(checkout|restore)\s+(--\s+)?\.(\s|$)",
+        "discards uncommitted changes",
+    ),
+    (r"--no-verify\b", "skips git hooks"),
+    (r"(^|[;&|]\s*)sudo\b", "runs as root"),
+    (
+        r"\b(curl|wget)\b[^|;&]*\|\s*(sudo\s+)?(ba|z)?sh\b",
+        "pipes a download into a shell",
+    ),
+    (r"\bchmod\s+(-\S+\s+)*0?777\b", "makes files world-writable"),
+    (
+        r"\b(mkfs(\.\w+)?|fdisk|parted)\b|\bdd\b.*\bof=/dev/|>\s*/dev/(sd|nvme|disk)",
+        "writes a disk device",
+    ),
+    (
+        r"(?i)\b(drop\s+(table|database|schema)|truncate\s+table)\b",
+        "drops database data",
+    ),
+    (r"\b(kill\s+-(9|KILL)|pkill|killall)\b", "kills processes"),
+    (
+        r"\b(shutdown|reboot|halt|poweroff)\b",
+        "shuts the machine down",
+    ),
+    (
+        r"\b(npm|yarn|pnpm|cargo)\s+publish\b|\btwine\s+upload\b|\bdocker\s+push\b",
+        "publishes a package",
+    ),
+    (
+        r"\b(terraform|tofu)\s+(apply|destroy)\b|\bkubectl\s+delete\b",
+        "changes infrastructure",
+    ),
+];
+
+/// Where writes mean a change to the system or credentials, not a project
+const SENSITIVE_PATHS: &[&str] = &["/etc/", "/usr/", "/bin/", "/sbin/", "/boot/", "/sys/"];
+const SENSITIVE_HOME: &[&str] = &[
+    ".ssh/", ".aws/", ".gnupg/", ".kube/", ".bashrc", ".zshrc", ".profile",
+];
+/// How a command names its home directory
+const HOME_PREFIXES: &[&str] = &["~/", "$HOME/", "${HOME}/", "/root/"];
+/// Directories each user's home is in
+const HOMES: &[&str] = &["/home/", "/Users/"];
+
+static RISK_PATTERNS: LazyLock<Vec<(Regex, &'static str)>> = LazyLock::new(|| {
+    RISKS
+        .iter()
+        .map(|(pattern, reason)| (Regex::new(pattern).expect("built-in risk pattern"), *reason))
+        .collect()
+});
+
+/// Why a command is risky; empty for one that isn't
+pub fn risks(command: &str) -> Vec<&'static str> {
+    let mut reasons: Vec<&'static str> = Vec::new();
+    for (pattern, reason) in RISK_PATTERNS.iter() {
+        if pattern.is_match(command) && !reasons.contains(reason) {
+            reasons.push(reason);
+        }
+    }
+    let sensitive = |path: &str| {
+        SENSITIVE_PATHS.iter().any(|dir| path.starts_with(dir))
+            || in_home(path).is_some_and(|rest| SENSITIVE_HOME.iter().any(|p| rest.starts_with(p)))
+    };
+    if scope::bash_targets(command)
+        .iter()
+        .any(|path| sensitive(path))
+    {
+        reasons.push("writes system or credential files");
+    }
+    reasons
+}
+
+/// Where a path is in a home directory, whoever's it is: the agent may run
+/// as another user, or on another machine, than sentinel
+fn in_home(path: &str) -> Option<&str> {
+    if let Some(rest) = HOME_PREFIXES.iter().find_map(|home| path.strip_prefix(home)) {
+        return Some(rest);
+    }
+    let user = HOMES.iter().find_map(|homes| path.strip_prefix(homes))?;
+    Some(user.split_once('/')?.1)
+}
+
+/// One shell command read from a Bash call's input
+#[derive(Debug, PartialEq)]
+pub struct Command {
+    pub command: String,
+    pub description: Option<String>,
+    pub timeout_ms: Option<i64>,
+    pub background: bool,
+}
+
+/// The command a Bash call runs; None for other tools
+pub fn command(call: &ToolCall) -> Option<Command> {
+    if call.name != "Bash" {
+        return None;
+    }
+    let input = &call.input;
+    Some(Command {
+        command: input.get("command")?.as_str()?.to_string(),
+        description: input
+            .get("description")
+            .and_then(|v| v.as_str())
+            .map(str::to_string),
+        timeout_ms: input.get("timeout").and_then(|v| v.as_i64()),
+        background: input
+            .get("run_in_background")
+            .and_then(|v| v.as_bool())
+            .unwrap_or(false),
+    })
+}
+
+/// The exit status a result reports: the code an error states ("Exit code
+/// 2"), or 0 for a command that succeeded. None when it doesn't say, as for
+/// one that timed out.
+fn exit_code(failed: bool, output: &str) -> Option<i64> {
+    if !failed {
+        return Some(0);
+    }
+    let first = output.lines().next()?.trim();
+    let first = first.strip_prefix("Error: ").unwrap_or(first);
+    first.strip_prefix("Exit code ")?.trim().parse().ok()
+}
+
+fn clip(text: &str, chars: usize) -> &str {
+    match text.char_indices().nth(chars) {
+        Some((cut, _)) => &text[..cut],
+        None => text,
+    }
+}
+
+/// Record the shell commands of an event's tool calls; part of the event's
+/// insert transaction. Their results are recorded on `tool_calls`.
+pub async fn record(
+    conn: &mut SqliteConnection,
+    event: &ObservabilityEvent,
+) -> Result<(), sqlx::Error> {
+    let Payload::AssistantResponse(ref response) = event.payload else {
+        return Ok(());
+    };
+    for call in &response.tool_calls {
+        let Some(command) = command(call) else {
+            continue;
+        };
+        let risks = risks(&command.command);
+        sqlx::query(
+            r#"
+            INSERT OR IGNORE INTO commands
+                (event_id, tool_use_id, command, description, timeout_ms, background, risk)
+            VALUES (?, ?, ?, ?, ?, ?, ?)
+            "#,
+        )
+        .bind(event.id.to_string())
+        .bind(&call.id)
+        .bind(&command.command)
+        .bind(&command.description)
+        .bind(command.timeout_ms)
+        .bind(command.background)
+        .bind((!risks.is_empty()).then(|| risks.join(", ")))
+        .execute(&mut *conn)
+        .await?;
+    }
+    Ok(())
+}
+
+/// One recorded command
+#[derive(Debug, sqlx::FromRow)]
+pub struct CommandRow {
+    pub session_id: Option<String>,
+    pub command: String,
+    pub description: Option<String>,
+    pub timeout_ms: Option<i64>,
+    pub background: bool,
+    /// Why the classifier flagged it, comma-separated
+    pub risk: Option<String>,
+    pub timestamp: String,
+    /// None until the call's result comes back
+    pub failed: Option<bool>,
+    /// The start of what the command printed
+    pub output: Option<String>,
+    pub output_bytes: Option<i64>,
+}
+
+impl CommandRow {
+    /// The exit status its result reports. A background command's result
+    /// only says it started, so it has none.
+    pub fn exit_code(&self) -> Option<i64> {
+        if self.background {
+            return None;
+        }
+        exit_code(self.failed?, self.output.as_deref().unwrap_or(""))
+    }
+}
+
+#[derive(Clone)]
+pub struct CommandStore {
+    pool: SqlitePool,
+}
+
+impl CommandStore {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool }
+    }
+
+    /// Create the table, rebuilding one that still copied its calls' columns;
+    /// true when it has to be filled from the Bash calls recorded
+    pub async fn init_schema(&self) -> Result<bool, sqlx::Error> {
+        derived::create(
+            &self.pool,
+            "commands",
+            r#"
+            CREATE TABLE IF NOT EXISTS commands (
+                event_id TEXT NOT NULL,
+                tool_use_id TEXT NOT NULL,
+                command TEXT NOT NULL,
+                description TEXT,
+                timeout_ms INTEGER,
+                background INTEGER NOT NULL,
+                risk TEXT,
+                PRIMARY KEY (event_id, tool_use_id)
+            )
+            "#,
+            |columns| columns.iter().any(|column| column == "agent"),
+        )
+        .await
+    }
+
+    /// Commands the agent ran at or after `since`, in order; only risky ones
+    /// with `dangerous_only`
+    pub async fn commands(
+        &self,
+        agent: &str,
+        since: DateTime<Utc>,
+        dangerous_only: bool,
+    ) -> Result<Vec<CommandRow>, sqlx::Error> {
+        sqlx::query_as(
+            r#"
+            SELECT t.session_id, c.command, c.description, c.timeout_ms, c.background,
+                   c.risk, t.timestamp, t.failed, t.result AS output,
+                   t.result_bytes AS output_bytes
+            FROM commands c
+            JOIN tool_calls t ON t.event_id = c.event_id AND t.tool_use_id = c.tool_use_id
+            WHERE t.agent = ? AND t.timestamp >= ? AND (NOT ? OR c.risk IS NOT NULL)
+            ORDER BY t.timestamp, t.rowid
+            "#,
+        )
+        .bind(agent)
+        .bind(since.to_rfc3339())
+        .bind(dangerous_only)
+        .fetch_all(&self.pool)
+        .await
+    }
+}
+
+fn time(timestamp: &str) -> String {
+    DateTime::parse_from_rfc3339(timestamp)
+        .map(|t| t.format("%Y-%m-%d %H:%M:%S").to_string())
+        .unwrap_or_else(|_| timestamp.to_string())
+}
+
+fn status(row: &CommandRow) -> String {
+    match (row.failed, row.exit_code()) {
+        (_, Some(code)) => format!("exit {}", code),
+        (Some(true), None) => "failed".to_string(),
+        _ if row.background => "background".to_string(),
+        (Some(false), None) => "done".to_string(),
+        (None, None) => "pending".to_string(),
+    }
+}
+
+pub async fn run(
+    data_dir: &Path,
+    agent: &str,
+    since: Duration,
+    dangerous_only: bool,
+    output: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let db_path = data_dir.join("sentinel.db");
+    if !db_path.exists() {
+        println!("No events found. Run 'sentinel start' first to capture some traffic.");
+        return Ok(());
+    }
+    let storage = Storage::new(&db_path).await?;
+    let from = Utc::now() - since;
+    let commands = CommandStore::new(storage.pool())
+        .commands(agent, from, dangerous_only)
+        .await?;
+    if commands.is_empty() {
+        println!(
+            "{} ran no {}commands since {}.",
+            agent,
+            if dangerous_only { "risky " } else { "" },
+            from.format("%Y-%m-%d %H:%M")
+        );
+        return Ok(());
+    }
+
+    let mut session: Option<&str> = None;
+    for row in &commands {
+        if row.session_id.as_deref() != session {
+            session = row.session_id.as_deref();
+            println!("\nSession {}", session.unwrap_or("(none)"));
+        }
+        let line = row.command.lines().next().unwrap_or("");
+        let mut shown = clip(line, COMMAND_CHARS).to_string();
+        if shown.len() < row.command.len() {
+            shown.push('…');
+        }
+        println!(
+            "  {}  {:<10} {}{}",
+            time(&row.timestamp),
+            status(row),
+            if row.risk.is_some() { "⚠ " } else { "" },
+            shown
+        );
+        let timeout = row.timeout_ms.map(|ms| format!("timeout {}s", ms / 1000));
+        let note = match (row.description.as_deref(), timeout) {
+            (Some(description), Some(timeout)) => Some(format!("{} ({})", description, timeout)),
+            (Some(description), None) => Some(description.to_string()),
+            (None, timeout) => timeout,
+        };
+        if let Some(note) = note {
+            println!("  {:<30}# {}", "", note);
+        }
+        if let Some(ref risk) = row.risk {
+            println!("  {:<30}risk: {}", "", risk);
+        }
+        if let (true, Some(text)) = (output, row.output.as_deref()) {
+            for line in text.trim_end().lines() {
+                println!("  {:<30}│ {}", "", line);
+            }
+            let bytes = row.output_bytes.unwrap_or(0) as usize;
+            if bytes > text.len() {
+                println!("  {:<30}│ … ({} bytes in all)", "", bytes);
+            }
+        }
+    }
+    let risky = commands.iter().filter(|c| c.risk.is_some()).count();
+    let failed = commands.iter().filter(|c| c.failed == Some(true)).count();
+    println!(
+        "\n({} commands, {} failed, {} flagged risky)",
+        commands.len(),
+        failed,
+        risky
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event::{AssistantResponse, UserMessage};
+    use crate::parsers::ToolResult;
+    use chrono::TimeZone;
+    use serde_json::json;
+    use uuid::Uuid;
+
+    #[test]
+    fn test_classifies_risky_commands() {
+        for (command, reason) in [
+            ("rm -rf build/", "recursive delete"),
+            ("cd /w && rm -f -r node_modules", "recursive delete"),
+            ("git push origin main --force", "force push"),
+            ("git reset --hard HEAD~3", "discards uncommitted changes"),
+            ("git clean -fdx", "deletes untracked files"),
+            (
+                "curl -fsSL https://x.sh/install | sh",
+                "pipes a download into a shell",
+            ),
+            ("sudo apt-get install jq", "runs as root"),
+            ("psql -c 'DROP TABLE users'", "drops database data"),
+            (
+                "echo 'export X=1' >> /etc/profile",
+                "writes system or credential files",
+            ),
+            (
+                "echo ssh-ed25519 AAAA >> ~/.ssh/authorized_keys",
+                "writes system or credential files",
+            ),
+            (
+                "cp creds /home/dev/.aws/credentials",
+                "writes system or credential files",
+            ),
+            ("cargo publish", "publishes a package"),
+        ] {
+            assert!(
+                risks(command).contains(&reason),
+                "{}: {:?}",
+                command,
+                risks(command)
+            );
+        }
+        for command in [
+            "rm build.log",
+            "git push origin feature",
+            "cargo test --workspace",
+            "curl -s localhost:9000/api/agents | jq .",
+            "echo done > /home/dev/app/.ssh-notes",
+        ] {
+            assert!(
+                risks(command).is_empty(),
+                "{}: {:?}",
+                command,
+                risks(command)
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_records_commands_and_exit_status() {
+        let path = std::env::temp_dir().join(format!("sentinel-test-{}.db", Uuid::new_v4()));
+        let storage = Storage::new(&path).await.unwrap();
+        let call = |id: &str, name: &str, input| ToolCall {
+            id: id.to_string(),
+            name: name.to_string(),
+            input,
+        };
+        let response = Payload::AssistantResponse(AssistantResponse {
+            streaming: true,
+            stop_reason: Some("tool_use".to_string()),
+            tool_calls: vec![
+                call(
+                    "t1",
+                    "Bash",
+                    json!({"command": "cargo test", "description": "Run tests", "timeout": 600000}),
+                ),
+                call("t2", "Read", json!({"file_path": "/w/src/lib.rs"})),
+                call("t3", "Bash", json!({"command": "rm -rf target"})),
+                call(
+                    "t4",
+                    "Bash",
+                    json!({"command": "sleep 30", "run_in_background": true}),
+                ),
+            ],
+            ..Default::default()
+        });
+        let result = |id: &str, content: &str, is_error| ToolResult {
+            tool_use_id: id.to_string(),
+            content: content.to_string(),
+            is_error,
+        };
+        let results = Payload::UserMessage(UserMessage {
+            tool_results: vec![
+                result("t1", "Exit code 101\ntest result: FAILED. 1 failed", true),
+                result("t2", "...", false),
+                result("t3", "", false),
+            ],
+            ..Default::default()
+        });
+        for (secs, payload) in [(1, response), (2, results)] {
+            let event = ObservabilityEvent::for_tests(secs, payload);
+            storage.insert_observability_event(&event).await.unwrap();
+        }
+
+        let store = CommandStore::new(storage.pool());
+        let since = Utc.with_ymd_and_hms(2026, 1, 1, 0, 0, 0).unwrap();
+        let rows = store.commands("calm-otter", since, false).await.unwrap();
+        let summary: Vec<(&str, Option<i64>, String)> = rows
+            .iter()
+            .map(|r| (r.command.as_str(), r.exit_code(), status(r)))
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                ("cargo test", Some(101), "exit 101".to_string()),
+                ("rm -rf target", Some(0), "exit 0".to_string()),
+                ("sleep 30", None, "background".to_string()),
+            ]
+        );
+        assert_eq!(rows[0].description.as_deref(), Some("Run tests"));
+        assert_eq!(rows[0].timeout_ms, Some(600_000));
+
+        let risky = store.commands("calm-otter", since, true).await.unwrap();
+        assert_eq!(risky.len(), 1);
+        assert_eq!(risky[0].risk.as_deref(), Some("recursive delete"));
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,89 @@
+//! Upstream compatibility tracking.
+//!
+//! Parsers skip wire-format types they don't know, which is how a new API
+//! feature silently turns into missing data. Each unrecognized type, and each
+//! `anthropic-version` sentinel wasn't written against, raises one warning
+//! per process, recorded as a `compat_warning` alert and summarized by
+//! `sentinel doctor`.
+
+use std::collections::HashSet;
+use std::sync::Mutex;
+
+/// `anthropic-version` values the parser is written against
+pub const KNOWN_ANTHROPIC_VERSIONS: &[&str] = &["2023-06-01", "2023-01-01"];
+
+pub const COMPAT_ALERT_KIND: &str = "compat_warning";
+
+pub struct CompatTracker {
+    warned: Mutex<HashSet<String>>,
+}
+
+impl CompatTracker {
+    pub fn new() -> Self {
+        Self {
+            warned: Mutex::new(HashSet::new()),
+        }
+    }
+
+    /// Warnings for anything in this response not already warned about
+    pub fn check(
+        &self,
+        provider: &str,
+        api_version: Option<&str>,
+        unrecognized: &[String],
+    ) -> Vec<String> {
+        let mut warned = self.warned.lock().unwrap_or_else(|e| e.into_inner());
+        let mut warnings = Vec::new();
+
+        if let Some(version) = api_version {
+            if provider == "anthropic"
+                && !KNOWN_ANTHROPIC_VERSIONS.contains(&version)
+                && warned.insert(format!("version:{}", version))
+            {
+                warnings.push(format!(
+                    "Clients are sending anthropic-version {}, which sentinel's parser predates; check recorded responses for missing fields",
+                    version
+                ));
+            }
+        }
+        for marker in unrecognized {
+            if warned.insert(format!("{}:{}", provider, marker)) {
+                warnings.push(format!(
+                    "{} sent unrecognized {}{}; sentinel is not recording it",
+                    provider,
+                    marker.replacen(':', " type '", 1),
+                    if marker.contains(':') { "'" } else { "" }
+                ));
+            }
+        }
+        warnings
+    }
+}
+
+impl Default for CompatTracker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_warns_once_per_marker() {
+        let tracker = CompatTracker::new();
+        let markers = vec!["content_block:server_tool_use".to_string()];
+
+        let first = tracker.check("anthropic", Some("2099-01-01"), &markers);
+        assert_eq!(first.len(), 2);
+        assert!(first[1].contains("content_block type 'server_tool_use'"));
+
+        assert!(tracker
+            .check("anthropic", Some("2099-01-01"), &markers)
+            .is_empty());
+        assert!(tracker
+            .check("anthropic", Some("2023-06-01"), &[])
+            .is_empty());
+    }
+}
@@ -0,0 +1,137 @@
+//! Shell completions, with agent names read from the database.
+//!
+//! The shell asks sentinel itself for completions (`COMPLETE=bash sentinel
+//! -- ...`), so arguments naming an agent complete from the agents recorded
+//! in the data dir, most recently seen first. `sentinel completions <shell>`
+//! prints the script that hooks this up; sourcing it from the shell's startup
+//! file keeps it in step with the installed binary.
+
+use std::io::Write;
+
+use clap::ValueEnum;
+use clap_complete::env::{Bash, EnvCompleter, Fish, Zsh};
+use clap_complete::{CompleteEnv, CompletionCandidate};
+use sqlx::sqlite::SqliteConnectOptions;
+use sqlx::ConnectOptions;
+
+use crate::cli;
+use crate::profiles;
+
+/// Environment variable the completion scripts set when calling back in
+const VAR: &str = "COMPLETE";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+}
+
+impl Shell {
+    fn completer(self) -> &'static dyn EnvCompleter {
+        match self {
+            Shell::Bash => &Bash,
+            Shell::Zsh => &Zsh,
+            Shell::Fish => &Fish,
+        }
+    }
+}
+
+/// Answer a completion request from the shell and exit; returns when the
+/// process wasn't started for one
+pub fn complete() {
+    // Agent names come from the data dir a profile or the config may move;
+    // without them, the rest still completes
+    if std::env::var_os(VAR).is_some() {
+        let _ = profiles::load_settings(true);
+    }
+    CompleteEnv::with_factory(cli::command).var(VAR).complete();
+}
+
+/// Print the script registering completions with `shell`
+pub fn print(shell: Shell) -> Result<(), Box<dyn std::error::Error>> {
+    // The script calls back into this binary, wherever it's installed
+    let exe = std::env::current_exe()?;
+    let mut out = std::io::stdout().lock();
+    shell.completer().write_registration(
+        VAR,
+        "sentinel",
+        "sentinel",
+        &exe.to_string_lossy(),
+        &mut out,
+    )?;
+    out.flush()?;
+    Ok(())
+}
+
+/// Recorded agent names, most recently seen first; none when the database
+/// doesn't exist or can't be read
+pub fn agent_names() -> Vec<CompletionCandidate> {
+    // Read with the settings loaded for the completion or the palette
+    let db_path = cli::get_data_dir().join("sentinel.db");
+    if !db_path.exists() {
+        return Vec::new();
+    }
+    // Completion runs before the async runtime starts, so it gets its own
+    let Ok(runtime) = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+    else {
+        return Vec::new();
+    };
+    let names: Vec<(String, String)> = runtime.block_on(async {
+        let Ok(mut conn) = SqliteConnectOptions::new()
+            .filename(&db_path)
+            .read_only(true)
+            .connect()
+            .await
+        else {
+            return Vec::new();
+        };
+        sqlx::query_as("SELECT name, last_seen_at FROM agents ORDER BY last_seen_at DESC")
+            .fetch_all(&mut conn)
+            .await
+            .unwrap_or_default()
+    });
+    names
+        .into_iter()
+        .map(|(name, last_seen_at)| {
+            let seen = chrono::DateTime::parse_from_rfc3339(&last_seen_at)
+                .map(|t| format!("last seen {}", t.format("%Y-%m-%d %H:%M")))
+                .unwrap_or_default();
+            CompletionCandidate::new(name).help(Some(seen.into()))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap_complete::ArgValueCandidates;
+
+    #[test]
+    fn test_completes_subcommands_and_agent_arguments() {
+        let mut cmd = cli::command();
+        let complete = |cmd: &mut clap::Command, line: &[&str]| -> Vec<String> {
+            let args = line.iter().map(|a| std::ffi::OsString::from(*a)).collect();
+            clap_complete::engine::complete(cmd, args, line.len() - 1, None)
+                .unwrap()
+                .into_iter()
+                .map(|c| c.get_value().to_string_lossy().into_owned())
+                .collect()
+        };
+        assert_eq!(complete(&mut cmd, &["sentinel", "fil"]), ["files"]);
+        assert!(complete(&mut cmd, &["sentinel", "completions", ""]).contains(&"zsh".to_string()));
+        // Agent arguments complete from the database rather than the filesystem
+        let files = cmd.find_subcommand("files").unwrap();
+        let agent = files.get_arguments().find(|a| a.get_id() == "agent").unwrap();
+        assert!(agent.get::<ArgValueCandidates>().is_some());
+
+        let mut script = Vec::new();
+        Shell::Fish
+            .completer()
+            .write_registration(VAR, "sentinel", "sentinel", "/usr/bin/sentinel", &mut script)
+            .unwrap();
+        assert!(String::from_utf8(script).unwrap().contains("COMPLETE=fish"));
+    }
+}
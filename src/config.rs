@@ -0,0 +1,491 @@
+//! `~/.sentinel/config.toml`: settings for `sentinel start` kept in a file.
+//!
+//! ```toml
+//! port = 9000
+//! upstream_url = "https://llm-gateway.internal/anthropic"
+//! data_dir = "/var/lib/sentinel"
+//! close_idle_after = "1h"
+//! retention = "90d"
+//! agent_naming = "directory"
+//! turn_sla = "5m"
+//! budgets = "budgets.yaml"
+//! policy = "policy.yaml"
+//! policy_enforce = true
+//!
+//! [capture]
+//! max_raw_kb = 256
+//! mirror_dir = "mirror"
+//! withhold_files = ["config/*.yaml"]
+//!
+//! [redaction]
+//! enabled = true
+//! rules = "policy.yaml"
+//! skip = ["email"]
+//!
+//! [encryption]
+//! fields = ["prompt", "response"]
+//! key_file = "sentinel.key"
+//!
+//! [providers]
+//! litellm_url = "http://localhost:4000"
+//! azure_openai_url = "https://my-resource.openai.azure.com"
+//! ```
+//!
+//! Every setting is one a flag or `SENTINEL_*` variable also sets. The file
+//! is read before the command line and its settings become the defaults of
+//! the flags they stand for, so flags win over the environment, which wins
+//! over the file. A default doesn't count as the flag being given, so a
+//! `port` here doesn't rule out `--listeners`. Settings without a flag (the
+//! data dir, provider URLs) are read through [`var`], which looks at the
+//! environment first. Relative paths are resolved against the file's
+//! directory. Unknown keys are an error, so a typo doesn't quietly do
+//! nothing. `SENTINEL_CONFIG` points at another file.
+
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+
+use serde::Deserialize;
+
+#[derive(Debug, thiserror::Error)]
+pub enum ConfigError {
+    #[error("failed to read {path}: {source}")]
+    Read {
+        path: String,
+        source: std::io::Error,
+    },
+    #[error("invalid config {path}: {source}")]
+    Parse {
+        path: String,
+        source: toml::de::Error,
+    },
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    pub port: Option<u16>,
+    pub bind: Option<String>,
+    pub upstream_url: Option<String>,
+    pub data_dir: Option<PathBuf>,
+    /// Listener YAML, for several ports with their own upstreams
+    pub listeners: Option<PathBuf>,
+    pub close_idle_after: Option<String>,
+    /// How long events are kept, e.g. 90d
+    pub retention: Option<String>,
+    /// adjective-noun, directory, or header
+    pub agent_naming: Option<String>,
+    pub turn_sla: Option<String>,
+    #[serde(default)]
+    pub turn_sla_alert: bool,
+    pub notify_waiting: Option<String>,
+    #[serde(default)]
+    pub hot_path: bool,
+    pub latency_budget_ms: Option<u64>,
+    pub queue_workers: Option<usize>,
+    pub queue_capacity: Option<usize>,
+    /// block or drop
+    pub queue_overflow: Option<String>,
+    pub retries: Option<u32>,
+    /// Budgets YAML, for `--budgets`
+    pub budgets: Option<PathBuf>,
+    /// Policy bundle, for `--policy`
+    pub policy: Option<PathBuf>,
+    #[serde(default)]
+    pub policy_alert: bool,
+    #[serde(default)]
+    pub policy_enforce: bool,
+    #[serde(default)]
+    pub capture: Capture,
+    #[serde(default)]
+    pub redaction: Redaction,
+    #[serde(default)]
+    pub encryption: Encryption,
+    #[serde(default)]
+    pub providers: Providers,
+}
+
+/// What is kept of each exchange
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Capture {
+    #[serde(default)]
+    pub full_fidelity: bool,
+    pub max_raw_kb: Option<usize>,
+    pub max_request_kb: Option<usize>,
+    pub mirror_dir: Option<PathBuf>,
+    pub notes_dir: Option<PathBuf>,
+    #[serde(default)]
+    pub withhold_secret_files: bool,
+    #[serde(default)]
+    pub withhold_files: Vec<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Redaction {
+    #[serde(default)]
+    pub enabled: bool,
+    /// Policy bundle whose `redact` rules are also applied
+    pub rules: Option<PathBuf>,
+    /// Built-in detectors to leave off
+    #[serde(default)]
+    pub skip: Vec<String>,
+}
+
+/// Content stored encrypted, with `--encrypt-fields`
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Encryption {
+    #[serde(default)]
+    pub fields: Vec<String>,
+    pub key_file: Option<PathBuf>,
+}
+
+/// Where the non-Anthropic routes go
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Providers {
+    pub litellm_url: Option<String>,
+    pub azure_openai_url: Option<String>,
+    pub azure_api_version: Option<String>,
+    /// `deployment=model` pairs, comma-separated
+    pub azure_deployments: Option<String>,
+}
+
+/// `SENTINEL_CONFIG`, or `config.toml` in the default data dir
+pub fn path() -> Option<PathBuf> {
+    match std::env::var_os("SENTINEL_CONFIG") {
+        Some(path) => Some(PathBuf::from(path)),
+        None => dirs::home_dir().map(|home| home.join(".sentinel").join("config.toml")),
+    }
+}
+
+impl Config {
+    /// The config at `path`; empty if it's the default path and doesn't exist
+    pub fn load(path: &Path) -> Result<Self, ConfigError> {
+        let origin = path.display().to_string();
+        let text = match std::fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e)
+                if e.kind() == std::io::ErrorKind::NotFound
+                    && std::env::var_os("SENTINEL_CONFIG").is_none() =>
+            {
+                return Ok(Self::default())
+            }
+            Err(source) => {
+                return Err(ConfigError::Read {
+                    path: origin,
+                    source,
+                })
+            }
+        };
+        let mut config: Config = toml::from_str(&text).map_err(|source| ConfigError::Parse {
+            path: origin,
+            source,
+        })?;
+        config.resolve_paths(path.parent().unwrap_or(Path::new(".")));
+        Ok(config)
+    }
+
+    fn resolve_paths(&mut self, base: &Path) {
+        for path in [
+            &mut self.data_dir,
+            &mut self.listeners,
+            &mut self.budgets,
+            &mut self.policy,
+            &mut self.capture.mirror_dir,
+            &mut self.capture.notes_dir,
+            &mut self.redaction.rules,
+            &mut self.encryption.key_file,
+        ]
+        .into_iter()
+        .flatten()
+        {
+            *path = base.join(&*path);
+        }
+    }
+
+    /// The `SENTINEL_*` variables this config sets
+    pub fn vars(&self) -> Vec<(&'static str, String)> {
+        let display = |path: &PathBuf| path.display().to_string();
+        let on = |set: bool| set.then(|| "true".to_string());
+        let list = |items: &[String]| (!items.is_empty()).then(|| items.join(","));
+        [
+            ("SENTINEL_PORT", self.port.map(|p| p.to_string())),
+            ("SENTINEL_BIND", self.bind.clone()),
+            ("SENTINEL_UPSTREAM_URL", self.upstream_url.clone()),
+            ("SENTINEL_DATA_DIR", self.data_dir.as_ref().map(display)),
+            ("SENTINEL_LISTENERS", self.listeners.as_ref().map(display)),
+            ("SENTINEL_CLOSE_IDLE_AFTER", self.close_idle_after.clone()),
+            ("SENTINEL_RETENTION", self.retention.clone()),
+            ("SENTINEL_AGENT_NAMING", self.agent_naming.clone()),
+            ("SENTINEL_TURN_SLA", self.turn_sla.clone()),
+            ("SENTINEL_TURN_SLA_ALERT", on(self.turn_sla_alert)),
+            ("SENTINEL_NOTIFY_WAITING", self.notify_waiting.clone()),
+            ("SENTINEL_HOT_PATH", on(self.hot_path)),
+            (
+                "SENTINEL_LATENCY_BUDGET_MS",
+                self.latency_budget_ms.map(|ms| ms.to_string()),
+            ),
+            (
+                "SENTINEL_QUEUE_WORKERS",
+                self.queue_workers.map(|n| n.to_string()),
+            ),
+            (
+                "SENTINEL_QUEUE_CAPACITY",
+                self.queue_capacity.map(|n| n.to_string()),
+            ),
+            ("SENTINEL_QUEUE_OVERFLOW", self.queue_overflow.clone()),
+            ("SENTINEL_RETRIES", self.retries.map(|r| r.to_string())),
+            ("SENTINEL_BUDGETS", self.budgets.as_ref().map(display)),
+            ("SENTINEL_POLICY", self.policy.as_ref().map(display)),
+            ("SENTINEL_POLICY_ALERT", on(self.policy_alert)),
+            ("SENTINEL_POLICY_ENFORCE", on(self.policy_enforce)),
+            ("SENTINEL_FULL_FIDELITY", on(self.capture.full_fidelity)),
+            (
+                "SENTINEL_MAX_RAW_KB",
+                self.capture.max_raw_kb.map(|kb| kb.to_string()),
+            ),
+            (
+                "SENTINEL_MAX_REQUEST_KB",
+                self.capture.max_request_kb.map(|kb| kb.to_string()),
+            ),
+            (
+                "SENTINEL_MIRROR_DIR",
+                self.capture.mirror_dir.as_ref().map(display),
+            ),
+            (
+                "SENTINEL_NOTES_DIR",
+                self.capture.notes_dir.as_ref().map(display),
+            ),
+            (
+                "SENTINEL_WITHHOLD_SECRET_FILES",
+                on(self.capture.withhold_secret_files),
+            ),
+            ("SENTINEL_WITHHOLD_FILE", list(&self.capture.withhold_files)),
+            ("SENTINEL_REDACT", on(self.redaction.enabled)),
+            (
+                "SENTINEL_REDACT_RULES",
+                self.redaction.rules.as_ref().map(display),
+            ),
+            ("SENTINEL_REDACT_SKIP", list(&self.redaction.skip)),
+            ("SENTINEL_ENCRYPT_FIELDS", list(&self.encryption.fields)),
+            (
+                "SENTINEL_ENCRYPTION_KEY_FILE",
+                self.encryption.key_file.as_ref().map(display),
+            ),
+            ("SENTINEL_LITELLM_URL", self.providers.litellm_url.clone()),
+            (
+                "SENTINEL_AZURE_OPENAI_URL",
+                self.providers.azure_openai_url.clone(),
+            ),
+            (
+                "SENTINEL_AZURE_API_VERSION",
+                self.providers.azure_api_version.clone(),
+            ),
+            (
+                "SENTINEL_AZURE_DEPLOYMENTS",
+                self.providers.azure_deployments.clone(),
+            ),
+        ]
+        .into_iter()
+        .filter_map(|(name, value)| Some((name, value?)))
+        .collect()
+    }
+}
+
+/// What the config file (and profile) set, resolved before the command line
+/// is parsed
+#[derive(Debug, Default)]
+pub struct Settings {
+    /// Set over whatever the environment says: a profile's data dir, or a
+    /// snapshot's
+    pinned: Vec<(&'static str, String)>,
+    /// From the config file, under flags and the environment
+    file: Vec<(&'static str, String)>,
+}
+
+fn find(vars: &[(&str, String)], name: &str) -> Option<String> {
+    vars.iter()
+        .find(|(n, _)| *n == name)
+        .map(|(_, value)| value.clone())
+}
+
+static SETTINGS: RwLock<Settings> = RwLock::new(Settings {
+    pinned: Vec::new(),
+    file: Vec::new(),
+});
+
+impl Settings {
+    pub fn new(config: &Config) -> Self {
+        Self {
+            pinned: Vec::new(),
+            file: config.vars(),
+        }
+    }
+
+    /// Set `name` over the environment and the config file
+    pub fn pin(&mut self, name: &'static str, value: String) {
+        self.pinned.retain(|(n, _)| *n != name);
+        self.pinned.push((name, value));
+    }
+
+    /// What the config file sets `name` to
+    pub fn in_file(&self, name: &str) -> Option<String> {
+        find(&self.file, name)
+    }
+
+    fn get(&self, name: &str) -> Option<String> {
+        find(&self.pinned, name)
+            .or_else(|| std::env::var(name).ok())
+            .or_else(|| self.in_file(name))
+    }
+
+    /// `cmd` with the config file's settings as the defaults of the flags
+    /// whose variables they set, in every subcommand
+    pub fn defaults(&self, mut cmd: clap::Command) -> clap::Command {
+        let set: Vec<(String, String, Option<char>)> = cmd
+            .get_arguments()
+            .filter_map(|arg| {
+                let var = arg.get_env()?.to_str()?;
+                let value = self.in_file(var)?;
+                Some((arg.get_id().to_string(), value, arg.get_value_delimiter()))
+            })
+            .collect();
+        for (id, value, delimiter) in set {
+            cmd = cmd.mut_arg(id, |arg| match delimiter {
+                Some(delimiter) => {
+                    arg.default_values(value.split(delimiter).map(str::to_string).collect::<Vec<_>>())
+                }
+                None => arg.default_value(value),
+            });
+        }
+        let subcommands: Vec<String> = cmd
+            .get_subcommands()
+            .map(|sub| sub.get_name().to_string())
+            .collect();
+        for name in subcommands {
+            cmd = cmd.mut_subcommand(name, |sub| self.defaults(sub));
+        }
+        cmd
+    }
+
+    /// Make these the settings every later read sees
+    pub fn install(self) {
+        *SETTINGS.write().unwrap_or_else(|e| e.into_inner()) = self;
+    }
+}
+
+/// A `SENTINEL_*` setting without a flag of its own: pinned by a profile, or
+/// else from the environment, or else from the config file
+pub fn var(name: &str) -> Option<String> {
+    SETTINGS.read().unwrap_or_else(|e| e.into_inner()).get(name)
+}
+
+/// Set `name` over the environment and the config file for the rest of the run
+pub fn pin(name: &'static str, value: String) {
+    SETTINGS.write().unwrap_or_else(|e| e.into_inner()).pin(name, value);
+}
+
+/// `cmd` with the installed settings as its defaults
+pub fn with_defaults(cmd: clap::Command) -> clap::Command {
+    SETTINGS.read().unwrap_or_else(|e| e.into_inner()).defaults(cmd)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sections_become_variables_with_paths_resolved() {
+        let mut config: Config = toml::from_str(
+            r#"
+            port = 9100
+            data_dir = "data"
+
+            [capture]
+            max_raw_kb = 256
+            withhold_files = ["config/*.yaml", "*.key"]
+
+            [redaction]
+            enabled = true
+            skip = ["email"]
+
+            [providers]
+            litellm_url = "http://litellm:4000"
+            "#,
+        )
+        .unwrap();
+        config.resolve_paths(Path::new("/etc/sentinel"));
+
+        let vars = config.vars();
+        let var = |name: &str| {
+            vars.iter()
+                .find(|(n, _)| *n == name)
+                .map(|(_, v)| v.as_str())
+        };
+        assert_eq!(var("SENTINEL_PORT"), Some("9100"));
+        assert_eq!(var("SENTINEL_DATA_DIR"), Some("/etc/sentinel/data"));
+        assert_eq!(var("SENTINEL_MAX_RAW_KB"), Some("256"));
+        assert_eq!(var("SENTINEL_WITHHOLD_FILE"), Some("config/*.yaml,*.key"));
+        assert_eq!(var("SENTINEL_REDACT"), Some("true"));
+        assert_eq!(var("SENTINEL_REDACT_SKIP"), Some("email"));
+        assert_eq!(var("SENTINEL_LITELLM_URL"), Some("http://litellm:4000"));
+        // Unset switches leave the flag's default alone
+        assert_eq!(var("SENTINEL_FULL_FIDELITY"), None);
+
+        assert!(toml::from_str::<Config>("prot = 9100").is_err());
+    }
+
+    #[test]
+    fn test_settings_become_flag_defaults_that_conflict_with_nothing() {
+        use clap::{Arg, ArgAction, Command};
+
+        let config: Config = toml::from_str(
+            r#"
+            port = 9100
+            retention = "90d"
+
+            [capture]
+            withhold_files = ["config/*.yaml", "*.key"]
+            "#,
+        )
+        .unwrap();
+        let start = Command::new("start")
+            .arg(Arg::new("port").long("port").env("SENTINEL_PORT").default_value("9000"))
+            .arg(
+                Arg::new("listeners")
+                    .long("listeners")
+                    .env("SENTINEL_LISTENERS")
+                    .conflicts_with("port"),
+            )
+            .arg(
+                Arg::new("withhold")
+                    .long("withhold-file")
+                    .env("SENTINEL_WITHHOLD_FILE")
+                    .value_delimiter(',')
+                    .action(ArgAction::Append),
+            );
+        let cmd = Settings::new(&config).defaults(Command::new("sentinel").subcommand(start));
+
+        let matches = cmd.clone().try_get_matches_from(["sentinel", "start"]).unwrap();
+        let start = matches.subcommand_matches("start").unwrap();
+        assert_eq!(start.get_one::<String>("port").unwrap(), "9100");
+        let withheld: Vec<&String> = start.get_many("withhold").unwrap().collect();
+        assert_eq!(withheld, ["config/*.yaml", "*.key"]);
+        // The file's port is only a default, so --listeners still goes
+        let matches = cmd
+            .clone()
+            .try_get_matches_from(["sentinel", "start", "--listeners", "ports.yaml"])
+            .unwrap();
+        assert!(matches.subcommand_matches("start").unwrap().contains_id("listeners"));
+        assert!(cmd
+            .try_get_matches_from(["sentinel", "start", "--port", "1", "--listeners", "p.yaml"])
+            .is_err());
+
+        let mut settings = Settings::new(&config);
+        assert_eq!(settings.get("SENTINEL_RETENTION").as_deref(), Some("90d"));
+        settings.pin("SENTINEL_RETENTION", "7d".to_string());
+        assert_eq!(settings.get("SENTINEL_RETENTION").as_deref(), Some("7d"));
+    }
+}
@@ -0,0 +1,45 @@
+//! Container detection, so defaults suit running as a sidecar.
+
+use std::net::{IpAddr, Ipv4Addr};
+use std::path::Path;
+
+/// Files a container runtime leaves at the root
+const MARKER_FILES: &[&str] = &["/.dockerenv", "/run/.containerenv"];
+/// Variables a container runtime or orchestrator sets
+const MARKER_VARS: &[&str] = &["KUBERNETES_SERVICE_HOST", "container"];
+
+/// Whether the marker files and variables found say this is a container
+fn detect(exists: impl Fn(&Path) -> bool, set: impl Fn(&str) -> bool) -> bool {
+    MARKER_FILES.iter().any(|file| exists(Path::new(file)))
+        || MARKER_VARS.iter().any(|var| set(var))
+}
+
+/// Whether sentinel appears to be running inside a container
+pub fn in_container() -> bool {
+    detect(Path::exists, |var| std::env::var_os(var).is_some())
+}
+
+/// Loopback on a workstation; every interface in a container, where other
+/// containers and the host reach sentinel through its network namespace
+pub fn default_bind() -> IpAddr {
+    if in_container() {
+        IpAddr::V4(Ipv4Addr::UNSPECIFIED)
+    } else {
+        IpAddr::V4(Ipv4Addr::LOCALHOST)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_containers_are_detected_by_marker_files_or_variables() {
+        assert!(!detect(|_| false, |_| false));
+        assert!(detect(|path| path == Path::new("/.dockerenv"), |_| false));
+        assert!(detect(|path| path == Path::new("/run/.containerenv"), |_| false));
+        assert!(detect(|_| false, |var| var == "KUBERNETES_SERVICE_HOST"));
+        assert!(detect(|_| false, |var| var == "container"));
+        assert_eq!(default_bind().is_unspecified(), in_container());
+    }
+}
@@ -0,0 +1,157 @@
+//! `sentinel copy`: one recorded answer, or one of its code blocks, to
+//! stdout or the clipboard.
+//!
+//! The event can be named by any unique prefix of its id. A prompt's id
+//! resolves to the response recorded for it. The clipboard is reached
+//! through the platform's own tool (pbcopy, wl-copy, xclip, xsel, clip).
+
+use std::io::Write;
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+use crate::code::{self, Segment};
+use crate::event::{ObservabilityEvent, Payload};
+use crate::storage::Storage;
+
+/// Clipboard tools tried in order, with their arguments
+const CLIPBOARDS: &[(&str, &[&str])] = &[
+    ("pbcopy", &[]),
+    ("wl-copy", &[]),
+    ("xclip", &["-selection", "clipboard"]),
+    ("xsel", &["--clipboard", "--input"]),
+    ("clip.exe", &[]),
+    ("clip", &[]),
+];
+
+/// The response's text, or its `block`th fenced code block (1-based)
+fn answer_text(event: &ObservabilityEvent, block: Option<usize>) -> Result<String, String> {
+    let Payload::AssistantResponse(ref response) = event.payload else {
+        return Err(format!("Event {} is not an assistant response", event.id));
+    };
+    let text = response.text.as_deref().unwrap_or("");
+    let Some(n) = block else {
+        if text.is_empty() {
+            return Err(format!("Response {} has no text", event.id));
+        }
+        return Ok(text.to_string());
+    };
+
+    let blocks: Vec<&str> = code::segments(text)
+        .into_iter()
+        .filter_map(|segment| match segment {
+            Segment::Code { code, .. } => Some(code),
+            Segment::Prose(_) => None,
+        })
+        .collect();
+    match n.checked_sub(1).and_then(|i| blocks.get(i)) {
+        Some(code) => Ok(code.to_string()),
+        None => Err(format!(
+            "Response {} has {} code block(s); there is no block {}",
+            event.id,
+            blocks.len(),
+            n
+        )),
+    }
+}
+
+fn copy_to_clipboard(text: &str) -> Result<&'static str, Box<dyn std::error::Error>> {
+    for (program, args) in CLIPBOARDS {
+        let mut child = match Command::new(program)
+            .args(*args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+        {
+            Ok(child) => child,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(format!("Failed to run {}: {}", program, e).into()),
+        };
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin.write_all(text.as_bytes())?;
+        }
+        let status = child.wait()?;
+        if !status.success() {
+            return Err(format!("{} exited with {}", program, status).into());
+        }
+        return Ok(program);
+    }
+    Err("No clipboard tool found (tried pbcopy, wl-copy, xclip, xsel, clip); leave out --clipboard to print instead".into())
+}
+
+/// The one event whose id starts with `id`
+pub async fn find_event(
+    storage: &Storage,
+    id: &str,
+) -> Result<ObservabilityEvent, Box<dyn std::error::Error>> {
+    let mut matches = storage.find_events_by_id_prefix(id, 2).await?;
+    match (matches.pop(), matches.pop()) {
+        (Some(event), None) => Ok(event),
+        (None, _) => Err(format!("No event with id '{}'", id).into()),
+        (Some(_), Some(_)) => {
+            Err(format!("'{}' matches several events; give more of the id", id).into())
+        }
+    }
+}
+
+pub async fn run(
+    data_dir: &Path,
+    id: &str,
+    block: Option<usize>,
+    clipboard: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let db_path = data_dir.join("sentinel.db");
+    if !db_path.exists() {
+        return Err("No recorded traffic. Run 'sentinel start' first.".into());
+    }
+    let storage = Storage::new(&db_path).await?;
+    let mut event = find_event(&storage, id).await?;
+    if let Payload::UserMessage(ref message) = event.payload {
+        let Some(response_id) = message.response_id else {
+            return Err(format!("Prompt {} has no recorded response", event.id).into());
+        };
+        event = storage.get_event(response_id).await?.ok_or_else(|| {
+            format!(
+                "Response {} to prompt {} wasn't recorded",
+                response_id, event.id
+            )
+        })?;
+    }
+
+    let text = answer_text(&event, block)?;
+    if clipboard {
+        let tool = copy_to_clipboard(&text)?;
+        eprintln!("Copied {} characters with {}", text.chars().count(), tool);
+    } else {
+        print!("{}", text);
+        if !text.ends_with('\n') {
+            println!();
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event::AssistantResponse;
+
+    #[test]
+    fn test_answer_text_picks_code_block() {
+        let event = ObservabilityEvent {
+            session_id: None,
+            agent: None,
+            provider: None,
+            ..ObservabilityEvent::for_tests(0, Payload::AssistantResponse(AssistantResponse {
+                text: Some("Try:\n```sh\nls\n```\nor\n```\npwd\n```\n".to_string()),
+                ..Default::default()
+            }))
+        };
+        assert!(answer_text(&event, None).unwrap().starts_with("Try:"));
+        assert_eq!(answer_text(&event, Some(2)).unwrap(), "pwd\n");
+        assert!(answer_text(&event, Some(0)).is_err());
+        assert!(answer_text(&event, Some(3))
+            .unwrap_err()
+            .contains("has 2 code block(s)"));
+    }
+}
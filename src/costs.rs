@@ -0,0 +1,283 @@
+//! `sentinel costs --what-if`: recorded sessions priced as another model.
+//!
+//! Each response is priced from its own recorded model and usage, then summed
+//! per session. Token counts can be scaled first, since a different model
+//! tokenizes the same prompt differently and answers at a different length.
+//! Cache reads and writes scale with input. Responses that can't be priced
+//! both ways are counted apart rather than as $0.
+
+use std::collections::HashMap;
+use std::path::Path;
+
+use chrono::{Duration, Utc};
+
+use crate::parsers::Usage;
+use crate::pricing;
+use crate::storage::{ResponseUsage, Storage};
+
+/// How token counts change on the other model
+#[derive(Debug, Clone, Copy)]
+pub struct Ratios {
+    pub input: f64,
+    pub output: f64,
+}
+
+/// What `target` would have billed for a response `model` answered
+fn what_if(model: Option<&str>, usage: &Usage, target: &str, ratios: Ratios) -> Option<f64> {
+    let to = pricing::price_for(target)?;
+    // Usage keeps each provider's own convention; bring input to the
+    // uncached count before switching to the target's
+    let cache_read = usage.cache_read_tokens.unwrap_or(0);
+    let cache_write = usage.cache_creation_tokens.unwrap_or(0);
+    let cached = cache_read + cache_write;
+    let from_includes_cache = model
+        .and_then(pricing::price_for)
+        .is_some_and(|p| p.input_includes_cache);
+    let mut input = usage.input_tokens.unwrap_or(0);
+    if from_includes_cache {
+        input = (input - cached).max(0);
+    }
+    if to.input_includes_cache {
+        input += cached;
+    }
+    let scale = |tokens: i64, ratio: f64| (tokens as f64 * ratio).round() as i64;
+    pricing::cost_usd(
+        Some(target),
+        &Usage {
+            input_tokens: Some(scale(input, ratios.input)),
+            output_tokens: Some(scale(usage.output_tokens.unwrap_or(0), ratios.output)),
+            cache_read_tokens: Some(scale(cache_read, ratios.input)),
+            cache_creation_tokens: Some(scale(cache_write, ratios.input)),
+            cost_usd: None,
+        },
+    )
+}
+
+#[derive(Debug, Default)]
+struct SessionCosts {
+    agent: String,
+    /// Responses priced both as recorded and as the target
+    requests: i64,
+    actual: f64,
+    what_if: f64,
+    /// Responses without usage or with an unknown model price, left out of
+    /// both sums
+    unpriced: i64,
+}
+
+/// Responses priced and summed per session, in the order sessions started
+fn by_session(
+    responses: &[ResponseUsage],
+    target: &str,
+    ratios: Ratios,
+) -> Vec<(String, SessionCosts)> {
+    let mut sessions: Vec<(String, SessionCosts)> = Vec::new();
+    let mut index: HashMap<String, usize> = HashMap::new();
+    for response in responses {
+        let session = response.session_id.clone().unwrap_or_default();
+        let i = *index.entry(session.clone()).or_insert_with(|| {
+            sessions.push((
+                session,
+                SessionCosts {
+                    agent: response.agent.clone().unwrap_or_default(),
+                    ..Default::default()
+                },
+            ));
+            sessions.len() - 1
+        });
+        let costs = &mut sessions[i].1;
+        let usage: Option<Usage> = response
+            .usage
+            .as_deref()
+            .and_then(|u| serde_json::from_str(u).ok());
+        let model = response.model.as_deref();
+        let priced = usage.and_then(|usage| {
+            let actual = pricing::cost_usd(model, &usage)?;
+            Some((actual, what_if(model, &usage, target, ratios)?))
+        });
+        match priced {
+            Some((actual, what_if)) => {
+                costs.requests += 1;
+                costs.actual += actual;
+                costs.what_if += what_if;
+            }
+            None => costs.unpriced += 1,
+        }
+    }
+    sessions
+}
+
+pub async fn run(
+    data_dir: &Path,
+    since: Duration,
+    target: &str,
+    ratios: Ratios,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if pricing::price_for(target).is_none() {
+        return Err(format!("No price known for model '{}'", target).into());
+    }
+    if !(ratios.input > 0.0 && ratios.output > 0.0) {
+        return Err("Token ratios must be greater than 0".into());
+    }
+    let db_path = data_dir.join("sentinel.db");
+    if !db_path.exists() {
+        println!("No events found. Run 'sentinel start' first to capture some traffic.");
+        return Ok(());
+    }
+    let storage = Storage::new(&db_path).await?;
+    let from = Utc::now() - since;
+    let responses = storage.response_usage(from).await?;
+    if responses.is_empty() {
+        println!("No requests since {}.", from.format("%Y-%m-%d %H:%M"));
+        return Ok(());
+    }
+
+    let sessions = by_session(&responses, target, ratios);
+    println!(
+        "Sessions since {} as recorded, and as {} (input x{}, output x{})\n",
+        from.format("%Y-%m-%d %H:%M"),
+        target,
+        ratios.input,
+        ratios.output
+    );
+    println!(
+        "{:<14} {:<24} {:>8} {:>12} {:>12} {:>8} {:>8}",
+        "session", "agent", "requests", "actual", "what-if", "change", "unpriced"
+    );
+    let change = |actual: f64, what_if: f64| {
+        if actual > 0.0 {
+            format!("{:+.0}%", (what_if - actual) / actual * 100.0)
+        } else {
+            "-".to_string()
+        }
+    };
+    let label = |name: &str, width: usize| -> String {
+        match name {
+            "" => "(none)".to_string(),
+            name => name.chars().take(width).collect(),
+        }
+    };
+    let mut sum = SessionCosts::default();
+    for (session, costs) in &sessions {
+        println!(
+            "{:<14} {:<24} {:>8} {:>12} {:>12} {:>8} {:>8}",
+            label(session, 14),
+            label(&costs.agent, 24),
+            costs.requests,
+            format!("${:.4}", costs.actual),
+            format!("${:.4}", costs.what_if),
+            change(costs.actual, costs.what_if),
+            costs.unpriced
+        );
+        sum.requests += costs.requests;
+        sum.actual += costs.actual;
+        sum.what_if += costs.what_if;
+        sum.unpriced += costs.unpriced;
+    }
+    println!(
+        "{:<14} {:<24} {:>8} {:>12} {:>12} {:>8} {:>8}",
+        "total",
+        "",
+        sum.requests,
+        format!("${:.4}", sum.actual),
+        format!("${:.4}", sum.what_if),
+        change(sum.actual, sum.what_if),
+        sum.unpriced
+    );
+
+    if sum.unpriced > 0 {
+        println!(
+            "\n{} response(s) had no usage or an unknown model price and aren't in either total",
+            sum.unpriced
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn usage(input: i64, output: i64, cache_read: i64) -> Usage {
+        Usage {
+            input_tokens: Some(input),
+            output_tokens: Some(output),
+            cache_read_tokens: Some(cache_read),
+            cache_creation_tokens: None,
+            cost_usd: None,
+        }
+    }
+
+    #[test]
+    fn test_what_if_reprices_across_conventions() {
+        let same = Ratios {
+            input: 1.0,
+            output: 1.0,
+        };
+        // Sonnet's tokens as Haiku 3.5: $0.80 in, $4 out, $0.08 cache reads
+        let cost = what_if(
+            Some("claude-sonnet-4"),
+            &usage(1_000_000, 100_000, 1_000_000),
+            "claude-3-5-haiku",
+            same,
+        )
+        .unwrap();
+        assert!((cost - (0.8 + 0.4 + 0.08)).abs() < 1e-9);
+
+        // gpt-4o counts cached tokens inside input; Haiku doesn't
+        let cost = what_if(
+            Some("gpt-4o"),
+            &usage(1_000_000, 0, 400_000),
+            "claude-3-5-haiku",
+            same,
+        )
+        .unwrap();
+        assert!((cost - (0.6 * 0.8 + 0.4 * 0.08)).abs() < 1e-9);
+
+        let longer = Ratios {
+            input: 1.0,
+            output: 2.0,
+        };
+        let cost = what_if(
+            Some("claude-sonnet-4"),
+            &usage(0, 100_000, 0),
+            "claude-3-5-haiku",
+            longer,
+        )
+        .unwrap();
+        assert!((cost - 0.8).abs() < 1e-9);
+
+        let sonnet = usage(1, 1, 0);
+        assert!(what_if(Some("claude-sonnet-4"), &sonnet, "mystery-model", same).is_none());
+    }
+
+    #[test]
+    fn test_sessions_are_priced_apart_and_unpriced_responses_left_out() {
+        let response = |session: &str, model: &str, usage: Option<&str>| ResponseUsage {
+            session_id: Some(session.to_string()),
+            agent: Some("calm-otter".to_string()),
+            model: Some(model.to_string()),
+            usage: usage.map(str::to_string),
+        };
+        let million_in = Some(r#"{"input_tokens":1000000,"output_tokens":0}"#);
+        let responses = [
+            response("s2", "claude-sonnet-4", million_in),
+            response("s1", "claude-sonnet-4", million_in),
+            response("s2", "claude-sonnet-4", None),
+            response("s2", "mystery-model", million_in),
+        ];
+        let same = Ratios {
+            input: 1.0,
+            output: 1.0,
+        };
+
+        let sessions = by_session(&responses, "claude-3-5-haiku", same);
+        let names: Vec<&str> = sessions.iter().map(|(s, _)| s.as_str()).collect();
+        assert_eq!(names, vec!["s2", "s1"]);
+        let s2 = &sessions[0].1;
+        assert_eq!((s2.requests, s2.unpriced), (1, 2));
+        assert!((s2.actual - 3.0).abs() < 1e-9);
+        assert!((s2.what_if - 0.8).abs() < 1e-9);
+        assert_eq!((sessions[1].1.requests, sessions[1].1.unpriced), (1, 0));
+    }
+}
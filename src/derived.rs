@@ -0,0 +1,121 @@
+//! Tables derived from events.
+//!
+//! Views read often enough that parsing every payload would be slow have
+//! their own tables, written in the same transaction as each event: hourly
+//! usage rollups, tool calls, code blocks, file changes, commands, and run
+//! responses. A database recorded before one of them existed, or under an
+//! older layout of it, has the table filled from its events when it's
+//! created, once.
+
+use sqlx::{SqliteConnection, SqlitePool};
+
+use crate::code::{self, CodeBlockStore};
+use crate::commands::{self, CommandStore};
+use crate::event::ObservabilityEvent;
+use crate::files::{self, FileChangeStore};
+use crate::rollup::{self, RollupStore};
+use crate::runs::{self, RunStore};
+use crate::tools::{self, ToolCallStore};
+
+/// A table derived from events
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Derived {
+    Rollups,
+    ToolCalls,
+    CodeBlocks,
+    FileChanges,
+    Commands,
+    RunResponses,
+}
+
+impl Derived {
+    /// Every derived table, in the order an event is written to them
+    pub const ALL: [Derived; 6] = [
+        Derived::Rollups,
+        Derived::ToolCalls,
+        Derived::CodeBlocks,
+        Derived::FileChanges,
+        Derived::Commands,
+        Derived::RunResponses,
+    ];
+
+    /// Create the table with its store; true when it has to be filled
+    pub async fn init_schema(self, pool: &SqlitePool) -> Result<bool, sqlx::Error> {
+        let pool = pool.clone();
+        match self {
+            Derived::Rollups => RollupStore::new(pool).init_schema().await,
+            Derived::ToolCalls => ToolCallStore::new(pool).init_schema().await,
+            Derived::CodeBlocks => CodeBlockStore::new(pool).init_schema().await,
+            Derived::FileChanges => FileChangeStore::new(pool).init_schema().await,
+            Derived::Commands => CommandStore::new(pool).init_schema().await,
+            Derived::RunResponses => RunStore::new(pool).init_schema().await,
+        }
+    }
+
+    /// Write what an event adds to the table
+    pub async fn record(
+        self,
+        conn: &mut SqliteConnection,
+        event: &ObservabilityEvent,
+    ) -> Result<(), sqlx::Error> {
+        match self {
+            Derived::Rollups => rollup::record(conn, event).await,
+            Derived::ToolCalls => tools::record(conn, event).await,
+            Derived::CodeBlocks => code::record(conn, event).await,
+            Derived::FileChanges => files::record(conn, event).await,
+            Derived::Commands => commands::record(conn, event).await,
+            Derived::RunResponses => runs::record(conn, event).await,
+        }
+    }
+}
+
+/// Create `table` with `create`, a `CREATE TABLE IF NOT EXISTS`, first
+/// dropping a copy whose columns `outdated` says are from an older layout.
+/// True when the table is new, so its events have to be read into it.
+pub async fn create(
+    pool: &SqlitePool,
+    table: &str,
+    create: &str,
+    outdated: impl Fn(&[String]) -> bool,
+) -> Result<bool, sqlx::Error> {
+    let columns: Vec<String> = sqlx::query_scalar("SELECT name FROM pragma_table_info(?)")
+        .bind(table)
+        .fetch_all(pool)
+        .await?;
+    let rebuild = !columns.is_empty() && outdated(&columns);
+    if rebuild {
+        sqlx::query(&format!("DROP TABLE {}", table))
+            .execute(pool)
+            .await?;
+    }
+    sqlx::query(create).execute(pool).await?;
+    Ok(columns.is_empty() || rebuild)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sqlx::sqlite::SqlitePoolOptions;
+
+    #[tokio::test]
+    async fn test_tables_are_filled_when_new_or_rebuilt() {
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        let old = "CREATE TABLE IF NOT EXISTS calls (id TEXT, agent TEXT)";
+        let new = "CREATE TABLE IF NOT EXISTS calls (id TEXT)";
+        let outdated = |columns: &[String]| columns.iter().any(|c| c == "agent");
+
+        assert!(create(&pool, "calls", old, |_| false).await.unwrap());
+        assert!(!create(&pool, "calls", old, |_| false).await.unwrap());
+        assert!(create(&pool, "calls", new, outdated).await.unwrap());
+        assert!(!create(&pool, "calls", new, outdated).await.unwrap());
+        let columns: Vec<String> = sqlx::query_scalar("SELECT name FROM pragma_table_info('calls')")
+            .fetch_all(&pool)
+            .await
+            .unwrap();
+        assert_eq!(columns, ["id"]);
+    }
+}
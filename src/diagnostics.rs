@@ -0,0 +1,189 @@
+//! Classification of upstream connectivity failures.
+//!
+//! reqwest folds DNS, TLS, and socket errors into one error type; the useful
+//! detail lives in the source chain. Classifying it lets the recorded event
+//! and the client-facing error say what actually went wrong.
+
+use std::error::Error as StdError;
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum FailureCause {
+    Dns,
+    Tls,
+    ConnectTimeout,
+    ConnectionRefused,
+    ConnectionReset,
+    Timeout,
+    Connect,
+    Other,
+}
+
+impl FailureCause {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FailureCause::Dns => "dns",
+            FailureCause::Tls => "tls",
+            FailureCause::ConnectTimeout => "connect_timeout",
+            FailureCause::ConnectionRefused => "connection_refused",
+            FailureCause::ConnectionReset => "connection_reset",
+            FailureCause::Timeout => "timeout",
+            FailureCause::Connect => "connect",
+            FailureCause::Other => "other",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        [
+            FailureCause::Dns,
+            FailureCause::Tls,
+            FailureCause::ConnectTimeout,
+            FailureCause::ConnectionRefused,
+            FailureCause::ConnectionReset,
+            FailureCause::Timeout,
+            FailureCause::Connect,
+            FailureCause::Other,
+        ]
+        .into_iter()
+        .find(|c| c.as_str() == s)
+    }
+
+    /// What to check first
+    pub fn hint(&self) -> &'static str {
+        match self {
+            FailureCause::Dns => "Upstream host could not be resolved; check network access, DNS, and the upstream URL",
+            FailureCause::Tls => "TLS handshake failed; a corporate proxy or missing CA certificate is the usual culprit",
+            FailureCause::ConnectTimeout => "Connecting to the upstream timed out; check firewall rules or outbound proxy settings",
+            FailureCause::ConnectionRefused => "Upstream refused the connection; check the upstream URL and port",
+            FailureCause::ConnectionReset => "Connection was reset mid-request; often a flaky network or an intercepting proxy",
+            FailureCause::Timeout => "Upstream did not respond in time",
+            FailureCause::Connect => "Could not connect to the upstream",
+            FailureCause::Other => "Request to the upstream failed",
+        }
+    }
+}
+
+impl std::fmt::Display for FailureCause {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+pub fn classify(error: &reqwest::Error) -> FailureCause {
+    let chain = error_chain(error).to_lowercase();
+
+    for source in std::iter::successors(error.source(), |&e| e.source()) {
+        if let Some(io) = source.downcast_ref::<std::io::Error>() {
+            match io.kind() {
+                std::io::ErrorKind::ConnectionRefused => return FailureCause::ConnectionRefused,
+                std::io::ErrorKind::ConnectionReset | std::io::ErrorKind::BrokenPipe => {
+                    return FailureCause::ConnectionReset
+                }
+                _ => {}
+            }
+        }
+    }
+
+    if chain.contains("dns error") || chain.contains("failed to lookup address") {
+        FailureCause::Dns
+    } else if chain.contains("certificate") || chain.contains("tls") || chain.contains("handshake")
+    {
+        FailureCause::Tls
+    } else if error.is_connect() && error.is_timeout() {
+        FailureCause::ConnectTimeout
+    } else if chain.contains("connection refused") {
+        FailureCause::ConnectionRefused
+    } else if chain.contains("connection reset") || chain.contains("broken pipe") {
+        FailureCause::ConnectionReset
+    } else if error.is_timeout() {
+        FailureCause::Timeout
+    } else if error.is_connect() {
+        FailureCause::Connect
+    } else {
+        FailureCause::Other
+    }
+}
+
+/// The error and all its sources joined, since reqwest's own message is
+/// usually just "error sending request".
+pub fn error_chain(error: &dyn StdError) -> String {
+    let mut message = error.to_string();
+    for source in std::iter::successors(error.source(), |&e| e.source()) {
+        message.push_str(": ");
+        message.push_str(&source.to_string());
+    }
+    message
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_causes_round_trip_through_their_names() {
+        for cause in [
+            FailureCause::Dns,
+            FailureCause::Tls,
+            FailureCause::ConnectTimeout,
+            FailureCause::ConnectionRefused,
+            FailureCause::ConnectionReset,
+            FailureCause::Timeout,
+            FailureCause::Connect,
+            FailureCause::Other,
+        ] {
+            assert_eq!(FailureCause::parse(cause.as_str()), Some(cause));
+            let json = serde_json::to_string(&cause).unwrap();
+            assert_eq!(json, format!("\"{}\"", cause));
+        }
+        assert_eq!(FailureCause::parse("gremlins"), None);
+    }
+
+    #[tokio::test]
+    async fn test_forward_failures_are_classified_from_the_error_chain() {
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_millis(200))
+            .no_proxy()
+            .build()
+            .unwrap();
+
+        // Nothing listening on a port that was just freed
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        drop(listener);
+        let refused = client
+            .get(format!("http://127.0.0.1:{}/", port))
+            .send()
+            .await
+            .unwrap_err();
+        assert_eq!(classify(&refused), FailureCause::ConnectionRefused);
+
+        // Accepts the connection but never answers
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let silent = tokio::spawn(async move {
+            let (socket, _) = listener.accept().await.unwrap();
+            tokio::time::sleep(Duration::from_secs(5)).await;
+            drop(socket);
+        });
+        let timed_out = client
+            .get(format!("http://127.0.0.1:{}/", port))
+            .send()
+            .await
+            .unwrap_err();
+        assert_eq!(classify(&timed_out), FailureCause::Timeout);
+        silent.abort();
+
+        // .invalid never resolves
+        let unresolved = client
+            .get("http://sentinel-doctor.invalid/")
+            .send()
+            .await
+            .unwrap_err();
+        assert_eq!(classify(&unresolved), FailureCause::Dns);
+        assert!(error_chain(&unresolved).contains("dns error"));
+    }
+}
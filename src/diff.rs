@@ -0,0 +1,346 @@
+//! `sentinel diff`: two sessions, or two responses, side by side.
+//!
+//! Each side is read as its assistant turns: thinking, text, and the tool
+//! calls made. The tool calls of the whole session are compared as one
+//! sequence, then each turn's text and thinking against the turn at the same
+//! place on the other side, so the output says where the two runs first went
+//! different ways (a different tool, an edit to another file) and what each
+//! said there. Made for the same prompt run on two models, or a retry.
+
+use std::fmt::Write;
+use std::path::Path;
+
+use crate::copy::find_event;
+use crate::event::{ObservabilityEvent, Payload};
+use crate::storage::Storage;
+
+/// Characters of a tool call's input compared and shown
+const INPUT_CHARS: usize = 120;
+/// Unchanged lines kept around each change
+const CONTEXT: usize = 2;
+/// Largest line-by-line comparison attempted; beyond it, the differing middle
+/// is shown as replaced whole
+const MAX_CELLS: usize = 4_000_000;
+
+/// One assistant response, as compared
+#[derive(Debug, Default)]
+struct Turn {
+    thinking: String,
+    text: String,
+    calls: Vec<String>,
+}
+
+/// A session or response being compared
+#[derive(Debug)]
+struct Side {
+    label: String,
+    turns: Vec<Turn>,
+}
+
+impl Side {
+    fn new(label: String, events: &[ObservabilityEvent]) -> Self {
+        let turns = events
+            .iter()
+            .filter_map(|event| match &event.payload {
+                Payload::AssistantResponse(response) => Some(Turn {
+                    thinking: response.thinking.clone().unwrap_or_default(),
+                    text: response.text.clone().unwrap_or_default(),
+                    calls: response
+                        .tool_calls
+                        .iter()
+                        .map(|call| {
+                            let input: String =
+                                call.input.to_string().chars().take(INPUT_CHARS).collect();
+                            format!("{} {}", call.name, input)
+                        })
+                        .collect(),
+                }),
+                _ => None,
+            })
+            .collect();
+        Self { label, turns }
+    }
+
+    /// Every tool call, with the turn (from 1) it was made in
+    fn calls(&self) -> Vec<(usize, &str)> {
+        self.turns
+            .iter()
+            .enumerate()
+            .flat_map(|(i, turn)| turn.calls.iter().map(move |c| (i + 1, c.as_str())))
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Op<'a> {
+    Same(&'a str),
+    Removed(&'a str),
+    Added(&'a str),
+}
+
+/// A shortest edit turning `a` into `b`, line by line
+fn lines<'a>(a: &[&'a str], b: &[&'a str]) -> Vec<Op<'a>> {
+    let prefix = a.iter().zip(b).take_while(|(x, y)| x == y).count();
+    let suffix = a[prefix..]
+        .iter()
+        .rev()
+        .zip(b[prefix..].iter().rev())
+        .take_while(|(x, y)| x == y)
+        .count();
+    let (middle_a, middle_b) = (&a[prefix..a.len() - suffix], &b[prefix..b.len() - suffix]);
+
+    let mut ops: Vec<Op> = a[..prefix].iter().map(|l| Op::Same(l)).collect();
+    if middle_a.len() * middle_b.len() > MAX_CELLS {
+        ops.extend(middle_a.iter().map(|l| Op::Removed(l)));
+        ops.extend(middle_b.iter().map(|l| Op::Added(l)));
+    } else {
+        // Longest common subsequence of what's left, from the end
+        let (n, m) = (middle_a.len(), middle_b.len());
+        let mut lcs = vec![0u32; (n + 1) * (m + 1)];
+        for i in (0..n).rev() {
+            for j in (0..m).rev() {
+                lcs[i * (m + 1) + j] = if middle_a[i] == middle_b[j] {
+                    lcs[(i + 1) * (m + 1) + j + 1] + 1
+                } else {
+                    lcs[(i + 1) * (m + 1) + j].max(lcs[i * (m + 1) + j + 1])
+                };
+            }
+        }
+        let (mut i, mut j) = (0, 0);
+        while i < n || j < m {
+            if i < n && j < m && middle_a[i] == middle_b[j] {
+                ops.push(Op::Same(middle_a[i]));
+                i += 1;
+                j += 1;
+            } else if j == m || (i < n && lcs[(i + 1) * (m + 1) + j] >= lcs[i * (m + 1) + j + 1]) {
+                ops.push(Op::Removed(middle_a[i]));
+                i += 1;
+            } else {
+                ops.push(Op::Added(middle_b[j]));
+                j += 1;
+            }
+        }
+    }
+    ops.extend(a[a.len() - suffix..].iter().map(|l| Op::Same(l)));
+    ops
+}
+
+/// Changed lines with a little unchanged context, longer unchanged runs
+/// collapsed
+fn push_ops(out: &mut String, ops: &[Op]) {
+    let changed: Vec<usize> = (0..ops.len())
+        .filter(|&i| !matches!(ops[i], Op::Same(_)))
+        .collect();
+    let near = |i: usize| {
+        changed
+            .iter()
+            .any(|&c| c.abs_diff(i) <= CONTEXT)
+    };
+    let mut skipped = 0;
+    for (i, op) in ops.iter().enumerate() {
+        if let Op::Same(_) = op {
+            if !near(i) {
+                skipped += 1;
+                continue;
+            }
+        }
+        if skipped > 0 {
+            let _ = writeln!(out, "    ⋯ {} unchanged", skipped);
+            skipped = 0;
+        }
+        let _ = match op {
+            Op::Same(line) => writeln!(out, "    {}", line),
+            Op::Removed(line) => writeln!(out, "  - {}", line),
+            Op::Added(line) => writeln!(out, "  + {}", line),
+        };
+    }
+    if skipped > 0 {
+        let _ = writeln!(out, "    ⋯ {} unchanged", skipped);
+    }
+}
+
+/// Where `ops` first differ, counted in lines of `a`
+fn divergence(ops: &[Op]) -> Option<usize> {
+    ops.iter().position(|op| !matches!(op, Op::Same(_)))
+}
+
+/// Each turn's text (or thinking) against the other side's at the same place
+fn push_turns(out: &mut String, what: &str, a: &Side, b: &Side, field: fn(&Turn) -> &str) {
+    let empty = Turn::default();
+    let mut differing = Vec::new();
+    for i in 0..a.turns.len().max(b.turns.len()) {
+        let x = field(a.turns.get(i).unwrap_or(&empty));
+        let y = field(b.turns.get(i).unwrap_or(&empty));
+        if x != y {
+            differing.push((i + 1, x, y));
+        }
+    }
+    let Some(&(first, _, _)) = differing.first() else {
+        let _ = writeln!(out, "\n{}: the same", what);
+        return;
+    };
+    let _ = writeln!(
+        out,
+        "\n{}: differs in {} of {} turns, first in turn {}",
+        what,
+        differing.len(),
+        a.turns.len().max(b.turns.len()),
+        first
+    );
+    for (turn, x, y) in differing {
+        let _ = writeln!(out, "  turn {}", turn);
+        let x: Vec<&str> = x.lines().collect();
+        let y: Vec<&str> = y.lines().collect();
+        push_ops(out, &lines(&x, &y));
+    }
+}
+
+fn render(a: &Side, b: &Side) -> String {
+    let mut out = String::new();
+    let _ = writeln!(out, "--- {}", a.label);
+    let _ = writeln!(out, "+++ {}", b.label);
+
+    let (calls_a, calls_b) = (a.calls(), b.calls());
+    let names_a: Vec<&str> = calls_a.iter().map(|(_, c)| *c).collect();
+    let names_b: Vec<&str> = calls_b.iter().map(|(_, c)| *c).collect();
+    let ops = lines(&names_a, &names_b);
+    match divergence(&ops) {
+        None => {
+            let _ = writeln!(out, "\nTool calls: the same {}", calls_a.len());
+        }
+        Some(at) => {
+            // The turn the first differing call was made in, on either side
+            let turn = calls_a
+                .get(at)
+                .or(calls_b.get(at))
+                .map(|(turn, _)| *turn)
+                .unwrap_or_default();
+            let _ = writeln!(
+                out,
+                "\nTool calls: {} vs {}, diverging at call {} (turn {})",
+                calls_a.len(),
+                calls_b.len(),
+                at + 1,
+                turn
+            );
+            push_ops(&mut out, &ops);
+        }
+    }
+    push_turns(&mut out, "Text", a, b, |t| &t.text);
+    push_turns(&mut out, "Thinking", a, b, |t| &t.thinking);
+    out
+}
+
+/// What an argument names: a session or agent's events, else one response
+async fn resolve(
+    storage: &Storage,
+    target: &str,
+) -> Result<(bool, Side), Box<dyn std::error::Error>> {
+    let events = storage.get_session_or_agent_events(target).await?;
+    if !events.is_empty() {
+        let first = &events[0];
+        let model = events.iter().find_map(|e| match &e.payload {
+            Payload::AssistantResponse(r) => r.model.clone(),
+            _ => None,
+        });
+        let side = Side::new(String::new(), &events);
+        let label = format!(
+            "session {} · agent {} · {} · {} turns",
+            first.session_id.as_deref().unwrap_or("(none)"),
+            first.agent.as_deref().unwrap_or("-"),
+            model.as_deref().unwrap_or("-"),
+            side.turns.len()
+        );
+        return Ok((true, Side { label, ..side }));
+    }
+    let mut event = find_event(storage, target)
+        .await
+        .map_err(|_| format!("No session, agent, or event named '{}'", target))?;
+    // A prompt stands for the response to it
+    if let Payload::UserMessage(ref message) = event.payload {
+        let Some(response_id) = message.response_id else {
+            return Err(format!("Prompt {} has no recorded response", event.id).into());
+        };
+        event = storage
+            .get_event(response_id)
+            .await?
+            .ok_or_else(|| format!("Response to prompt {} wasn't recorded", event.id))?;
+    }
+    let Payload::AssistantResponse(ref response) = event.payload else {
+        return Err(format!("Event {} isn't a prompt or a response", event.id).into());
+    };
+    let label = format!(
+        "response {} · agent {} · {}",
+        event.id,
+        event.agent.as_deref().unwrap_or("-"),
+        response.model.as_deref().unwrap_or("-")
+    );
+    Ok((false, Side::new(label, std::slice::from_ref(&event))))
+}
+
+pub async fn run(data_dir: &Path, a: &str, b: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let db_path = data_dir.join("sentinel.db");
+    if !db_path.exists() {
+        return Err("No recorded traffic. Run 'sentinel start' first.".into());
+    }
+    let storage = Storage::new(&db_path).await?;
+    let (session_a, a) = resolve(&storage, a).await?;
+    let (session_b, b) = resolve(&storage, b).await?;
+    if session_a != session_b {
+        return Err("Compare two sessions or two responses, not one of each".into());
+    }
+    print!("{}", render(&a, &b));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn turn(text: &str, calls: &[&str]) -> Turn {
+        Turn {
+            thinking: String::new(),
+            text: text.to_string(),
+            calls: calls.iter().map(|c| c.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn test_diff_finds_where_two_runs_diverge() {
+        let ops = lines(&["a", "b", "c", "d"], &["a", "c", "x", "d"]);
+        assert_eq!(
+            ops,
+            [
+                Op::Same("a"),
+                Op::Removed("b"),
+                Op::Same("c"),
+                Op::Added("x"),
+                Op::Same("d")
+            ]
+        );
+        assert_eq!(divergence(&ops), Some(1));
+
+        let a = Side {
+            label: "a".to_string(),
+            turns: vec![
+                turn("Looking.", &["Read src/lib.rs", "Grep parse"]),
+                turn("Fixed it.\nAll tests pass.", &["Edit src/lib.rs", "Bash cargo test"]),
+            ],
+        };
+        let b = Side {
+            label: "b".to_string(),
+            turns: vec![
+                turn("Looking.", &["Read src/lib.rs", "Grep parse"]),
+                turn("Rewrote it.\nAll tests pass.", &["Write src/lib.rs", "Bash cargo test"]),
+            ],
+        };
+        let diff = render(&a, &b);
+        assert!(diff.contains("Tool calls: 4 vs 4, diverging at call 3 (turn 2)\n"));
+        assert!(diff.contains("  - Edit src/lib.rs\n  + Write src/lib.rs\n    Bash cargo test\n"));
+        assert!(diff.contains(
+            "Text: differs in 1 of 2 turns, first in turn 2\n  turn 2\n  - Fixed it.\n  + Rewrote it.\n    All tests pass.\n"
+        ));
+        assert!(diff.ends_with("Thinking: the same\n"));
+        assert!(render(&a, &a).contains("Tool calls: the same 4\n"));
+    }
+}
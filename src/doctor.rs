@@ -0,0 +1,223 @@
+//! `sentinel doctor`: setup checks and a summary of recent upstream failures.
+
+use std::path::Path;
+
+use crate::bypass::{self, ANTHROPIC_API_HOST};
+use crate::compat::COMPAT_ALERT_KIND;
+use crate::diagnostics::FailureCause;
+use crate::lock::{self, InstanceRegistry};
+use crate::storage::{FailureCauseCount, Storage};
+
+/// How far back to summarize failures
+const FAILURE_WINDOW_DAYS: i64 = 7;
+
+pub async fn run(data_dir: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    println!("Data dir: {}", data_dir.display());
+    if !data_dir.exists() {
+        println!("  ✗ missing. Run 'sentinel start' to create it.");
+        return Ok(());
+    }
+
+    let db_path = data_dir.join("sentinel.db");
+    if !db_path.exists() {
+        println!("Database: {}", db_path.display());
+        println!("  ✗ missing. Run 'sentinel start' to capture some traffic.");
+        return Ok(());
+    }
+
+    let storage = Storage::new(&db_path).await?;
+    println!(
+        "Database: {} ({} events)",
+        db_path.display(),
+        storage.count_events().await?
+    );
+
+    let registry = InstanceRegistry::new(storage.pool());
+    registry.init_schema().await?;
+    let instances = registry.live().await?;
+    if !instances.is_empty() {
+        println!("Shared instances:");
+        for instance in &instances {
+            println!(
+                "  pid {} on port {}, since {}",
+                instance.pid,
+                instance.ports,
+                format_timestamp(&instance.started_at)
+            );
+        }
+    }
+
+    let mut ports = lock::exclusive_ports(data_dir);
+    for instance in &instances {
+        ports.extend(lock::parse_ports(&instance.ports));
+    }
+    check_routing(&ports).await;
+
+    let since = chrono::Utc::now() - chrono::Duration::days(FAILURE_WINDOW_DAYS);
+    let failures = storage.forward_failure_counts(since).await?;
+
+    println!("\nForward failures (last {} days):", FAILURE_WINDOW_DAYS);
+    for line in failure_lines(&failures) {
+        println!("{}", line);
+    }
+
+    let warnings = storage.alert_counts(COMPAT_ALERT_KIND, since).await?;
+    println!("\nUpstream compatibility (last {} days):", FAILURE_WINDOW_DAYS);
+    if warnings.is_empty() {
+        println!("  ✓ no unrecognized API versions or response types");
+    }
+    for warning in &warnings {
+        println!("  ⚠ {}", warning.message);
+        println!(
+            "    {} times, last {}",
+            warning.count,
+            format_timestamp(&warning.last_seen)
+        );
+    }
+
+    Ok(())
+}
+
+/// Warn about agents whose traffic isn't going through a running sentinel
+async fn check_routing(ports: &[u16]) {
+    println!("\nAgent routing:");
+    if ports.is_empty() {
+        println!("  ⚠ no sentinel is running on this data dir; agent traffic isn't being recorded");
+    }
+    let anthropic: Vec<std::net::IpAddr> =
+        match tokio::net::lookup_host((ANTHROPIC_API_HOST, 443)).await {
+            Ok(addrs) => addrs.map(|a| a.ip()).collect(),
+            Err(e) => {
+                println!(
+                    "  ⚠ couldn't resolve {} ({}); direct connections aren't checked",
+                    ANTHROPIC_API_HOST, e
+                );
+                Vec::new()
+            }
+        };
+    let processes = match bypass::scan(&anthropic) {
+        Ok(processes) => processes,
+        Err(e) => {
+            println!("  ⚠ can't inspect running processes: {}", e);
+            return;
+        }
+    };
+    let mut missed = 0;
+    for process in &processes {
+        let who = format!("pid {} {}", process.pid, process.program);
+        if process.direct_connections > 0 {
+            missed += 1;
+            println!(
+                "  ✗ {}: {} connection(s) straight to {}, bypassing the proxy",
+                who, process.direct_connections, ANTHROPIC_API_HOST
+            );
+            continue;
+        }
+        match process.base_url {
+            Some(Some(ref url)) if bypass::routes_through(url, ports) => {
+                println!("  ✓ {} → {}", who, url)
+            }
+            Some(Some(ref url)) => {
+                missed += 1;
+                println!("  ✗ {} → {}, which isn't a sentinel running here", who, url);
+            }
+            Some(None) => {
+                missed += 1;
+                println!(
+                    "  ✗ {}: no ANTHROPIC_BASE_URL or ANTHROPIC_API_URL; start it with 'sentinel run -- {}'",
+                    who, process.program
+                );
+            }
+            None => println!("  ? {}: environment not readable", who),
+        }
+    }
+    if processes.is_empty() {
+        println!(
+            "  ✓ no agents running, and nothing else talking to {}",
+            ANTHROPIC_API_HOST
+        );
+    } else if missed == 0 {
+        println!("  ✓ every agent found goes through the proxy");
+    }
+}
+
+/// Each failure cause with its count and what to check
+fn failure_lines(failures: &[FailureCauseCount]) -> Vec<String> {
+    if failures.is_empty() {
+        return vec!["  ✓ none".to_string()];
+    }
+    let mut lines = Vec::new();
+    for failure in failures {
+        let cause = failure
+            .cause
+            .as_deref()
+            .and_then(FailureCause::parse)
+            .unwrap_or(FailureCause::Other);
+        lines.push(format!(
+            "  ✗ {:<20} {:>5}  last {}",
+            cause.as_str(),
+            failure.count,
+            format_timestamp(&failure.last_seen)
+        ));
+        lines.push(format!("    {}", cause.hint()));
+    }
+    lines
+}
+
+fn format_timestamp(timestamp: &str) -> String {
+    chrono::DateTime::parse_from_rfc3339(timestamp)
+        .map(|t| t.format("%Y-%m-%d %H:%M").to_string())
+        .unwrap_or_else(|_| timestamp.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event::{ErrorEvent, ErrorKind, ObservabilityEvent, Payload};
+
+    fn forward_error(secs: i64, cause: Option<FailureCause>) -> ObservabilityEvent {
+        ObservabilityEvent::for_tests(
+            secs,
+            Payload::Error(ErrorEvent {
+                kind: ErrorKind::Forward,
+                cause,
+                status: None,
+                message: "error sending request".to_string(),
+                body: None,
+            }),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_forward_failures_are_summed_by_cause_with_hints() {
+        let path =
+            std::env::temp_dir().join(format!("sentinel-doctor-{}.db", uuid::Uuid::new_v4()));
+        let storage = Storage::new(&path).await.unwrap();
+        for event in [
+            forward_error(0, Some(FailureCause::Dns)),
+            forward_error(60, Some(FailureCause::Dns)),
+            forward_error(30, Some(FailureCause::Tls)),
+            // Recorded before causes were classified
+            forward_error(90, None),
+        ] {
+            storage.insert_observability_event(&event).await.unwrap();
+        }
+
+        let since = chrono::DateTime::parse_from_rfc3339("2026-01-01T00:00:00Z")
+            .unwrap()
+            .with_timezone(&chrono::Utc);
+        let failures = storage.forward_failure_counts(since).await.unwrap();
+        let lines = failure_lines(&failures);
+        assert_eq!(lines.len(), 6);
+        assert!(lines[0].contains("dns") && lines[0].contains("    2  last 2026-01-05 10:01"));
+        assert_eq!(lines[1].trim(), FailureCause::Dns.hint());
+        assert!(lines.iter().any(|l| l.contains("other")));
+        assert!(lines.iter().any(|l| l.trim() == FailureCause::Tls.hint()));
+
+        let later = since + chrono::Duration::days(30);
+        let none = storage.forward_failure_counts(later).await.unwrap();
+        assert_eq!(failure_lines(&none), vec!["  ✓ none"]);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
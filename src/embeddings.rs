@@ -0,0 +1,300 @@
+//! Optional transcript embeddings for semantic search.
+//!
+//! Embeddings come from any OpenAI-compatible `/embeddings` endpoint, which
+//! covers hosted APIs as well as local servers like Ollama or llama.cpp.
+//! Vectors are stored as little-endian f32 blobs keyed by event id and model,
+//! and searched by brute-force cosine similarity, which is fast enough for a
+//! single user's recording history.
+
+use serde::Deserialize;
+use sqlx::SqlitePool;
+use uuid::Uuid;
+
+use crate::event::{ObservabilityEvent, Payload};
+use crate::storage::{rows_to_events, EventRow};
+
+const DEFAULT_MODEL: &str = "text-embedding-3-small";
+/// Long transcripts are embedded by their opening; enough to capture the task
+const MAX_EMBED_CHARS: usize = 8000;
+
+#[derive(Debug, thiserror::Error)]
+pub enum EmbeddingError {
+    #[error("semantic search is not configured: set SENTINEL_EMBEDDINGS_URL (e.g. http://localhost:11434/v1 for Ollama)")]
+    NotConfigured,
+    #[error("embedding request failed: {0}")]
+    Request(#[from] reqwest::Error),
+    #[error("embedding endpoint returned {0} vectors for {1} inputs")]
+    Mismatch(usize, usize),
+}
+
+#[derive(Debug, Clone)]
+pub struct EmbeddingClient {
+    http: reqwest::Client,
+    url: String,
+    pub model: String,
+    api_key: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct EmbeddingResponse {
+    data: Vec<EmbeddingData>,
+}
+
+#[derive(Deserialize)]
+struct EmbeddingData {
+    index: usize,
+    embedding: Vec<f32>,
+}
+
+impl EmbeddingClient {
+    /// Configured from SENTINEL_EMBEDDINGS_URL, _MODEL, and _API_KEY
+    pub fn from_env() -> Result<Self, EmbeddingError> {
+        let base =
+            std::env::var("SENTINEL_EMBEDDINGS_URL").map_err(|_| EmbeddingError::NotConfigured)?;
+        Ok(Self {
+            http: reqwest::Client::new(),
+            url: format!("{}/embeddings", base.trim_end_matches('/')),
+            model: std::env::var("SENTINEL_EMBEDDINGS_MODEL")
+                .unwrap_or_else(|_| DEFAULT_MODEL.to_string()),
+            api_key: std::env::var("SENTINEL_EMBEDDINGS_API_KEY").ok(),
+        })
+    }
+
+    pub async fn embed(&self, inputs: &[String]) -> Result<Vec<Vec<f32>>, EmbeddingError> {
+        let mut request = self.http.post(&self.url).json(&serde_json::json!({
+            "model": self.model,
+            "input": inputs,
+        }));
+        if let Some(ref key) = self.api_key {
+            request = request.bearer_auth(key);
+        }
+        let mut response: EmbeddingResponse =
+            request.send().await?.error_for_status()?.json().await?;
+        if response.data.len() != inputs.len() {
+            return Err(EmbeddingError::Mismatch(response.data.len(), inputs.len()));
+        }
+        response.data.sort_by_key(|d| d.index);
+        Ok(response.data.into_iter().map(|d| d.embedding).collect())
+    }
+}
+
+/// The text of an event worth embedding, if any
+pub fn embeddable_text(event: &ObservabilityEvent) -> Option<String> {
+    let text = match &event.payload {
+        Payload::UserMessage(message) => message.text.clone(),
+        Payload::AssistantResponse(response) => {
+            let mut text = response.text.clone().unwrap_or_default();
+            for call in &response.tool_calls {
+                text.push_str(&format!("\n[{}] {}", call.name, call.input));
+            }
+            text
+        }
+        Payload::Alert(_)
+        | Payload::Error(_)
+        | Payload::Violation(_)
+        | Payload::SessionClosed(_)
+        | Payload::StreamDelta(_)
+        | Payload::StreamAborted(_)
+        | Payload::TurnSnapshot(_)
+        | Payload::Annotation(_) => return None,
+    };
+    let text = text.trim();
+    if text.is_empty() {
+        None
+    } else {
+        Some(text.chars().take(MAX_EMBED_CHARS).collect())
+    }
+}
+
+pub fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+    if a.len() != b.len() {
+        return 0.0;
+    }
+    let (mut dot, mut norm_a, mut norm_b) = (0.0f32, 0.0f32, 0.0f32);
+    for (x, y) in a.iter().zip(b) {
+        dot += x * y;
+        norm_a += x * x;
+        norm_b += y * y;
+    }
+    if norm_a == 0.0 || norm_b == 0.0 {
+        0.0
+    } else {
+        dot / (norm_a.sqrt() * norm_b.sqrt())
+    }
+}
+
+fn encode(vector: &[f32]) -> Vec<u8> {
+    vector.iter().flat_map(|v| v.to_le_bytes()).collect()
+}
+
+fn decode(blob: &[u8]) -> Vec<f32> {
+    blob.chunks_exact(4)
+        .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        .collect()
+}
+
+#[derive(sqlx::FromRow)]
+struct EmbeddingRow {
+    event_id: String,
+    session_id: Option<String>,
+    vector: Vec<u8>,
+}
+
+#[derive(Debug, Clone)]
+pub struct StoredEmbedding {
+    pub event_id: Uuid,
+    pub session_id: Option<String>,
+    pub vector: Vec<f32>,
+}
+
+#[derive(Clone)]
+pub struct EmbeddingStore {
+    pool: SqlitePool,
+}
+
+impl EmbeddingStore {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool }
+    }
+
+    pub async fn init_schema(&self) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS event_embeddings (
+                event_id TEXT NOT NULL,
+                model TEXT NOT NULL,
+                dims INTEGER NOT NULL,
+                vector BLOB NOT NULL,
+                PRIMARY KEY (event_id, model)
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Messages and responses with no embedding yet for this model
+    pub async fn unembedded_events(
+        &self,
+        model: &str,
+        limit: i64,
+    ) -> Result<Vec<ObservabilityEvent>, sqlx::Error> {
+        let rows: Vec<EventRow> = sqlx::query_as(
+            r#"
+            SELECT e.seq, e.id, e.timestamp, e.session_id, e.agent, e.topic, e.provider, e.payload
+            FROM observability_events e
+            LEFT JOIN event_embeddings v ON v.event_id = e.id AND v.model = ?
+            WHERE v.event_id IS NULL
+              AND json_extract(e.payload, '$.type') IN ('user_message', 'assistant_response')
+            ORDER BY e.seq ASC
+            LIMIT ?
+            "#,
+        )
+        .bind(model)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rows_to_events(rows))
+    }
+
+    /// Store an embedding. Events with nothing to embed get an empty vector
+    /// so they aren't picked up again.
+    pub async fn insert(
+        &self,
+        event_id: Uuid,
+        model: &str,
+        vector: &[f32],
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            r#"
+            INSERT OR REPLACE INTO event_embeddings (event_id, model, dims, vector)
+            VALUES (?, ?, ?, ?)
+            "#,
+        )
+        .bind(event_id.to_string())
+        .bind(model)
+        .bind(vector.len() as i64)
+        .bind(encode(vector))
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    pub async fn all(&self, model: &str) -> Result<Vec<StoredEmbedding>, sqlx::Error> {
+        let rows: Vec<EmbeddingRow> = sqlx::query_as(
+            r#"
+            SELECT v.event_id, e.session_id, v.vector
+            FROM event_embeddings v
+            JOIN observability_events e ON e.id = v.event_id
+            WHERE v.model = ? AND v.dims > 0
+            ORDER BY e.seq ASC
+            "#,
+        )
+        .bind(model)
+        .fetch_all(&self.pool)
+        .await?;
+
+        let mut embeddings = Vec::with_capacity(rows.len());
+        for row in rows {
+            match row.event_id.parse() {
+                Ok(event_id) => embeddings.push(StoredEmbedding {
+                    event_id,
+                    session_id: row.session_id,
+                    vector: decode(&row.vector),
+                }),
+                Err(e) => tracing::warn!(
+                    "Skipping embedding with invalid event id {}: {}",
+                    row.event_id,
+                    e
+                ),
+            }
+        }
+        Ok(embeddings)
+    }
+
+    /// Embed everything recorded since the last run, in batches
+    pub async fn backfill(
+        &self,
+        client: &EmbeddingClient,
+    ) -> Result<usize, Box<dyn std::error::Error>> {
+        const BATCH: i64 = 64;
+        let mut embedded = 0;
+        loop {
+            let events = self.unembedded_events(&client.model, BATCH).await?;
+            if events.is_empty() {
+                return Ok(embedded);
+            }
+            let (with_text, without): (Vec<_>, Vec<_>) = events
+                .iter()
+                .map(|e| (e.id, embeddable_text(e)))
+                .partition(|(_, text)| text.is_some());
+
+            for (id, _) in without {
+                self.insert(id, &client.model, &[]).await?;
+            }
+            if !with_text.is_empty() {
+                let texts: Vec<String> = with_text.iter().filter_map(|(_, t)| t.clone()).collect();
+                let vectors = client.embed(&texts).await?;
+                for ((id, _), vector) in with_text.iter().zip(&vectors) {
+                    self.insert(*id, &client.model, vector).await?;
+                }
+                embedded += vectors.len();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_vector_round_trip_and_similarity() {
+        let v = vec![0.5f32, -1.25, 3.0];
+        assert_eq!(decode(&encode(&v)), v);
+        assert!((cosine_similarity(&v, &v) - 1.0).abs() < 1e-6);
+        assert_eq!(cosine_similarity(&[1.0, 0.0], &[0.0, 1.0]), 0.0);
+        assert_eq!(cosine_similarity(&[1.0], &[1.0, 0.0]), 0.0);
+    }
+}
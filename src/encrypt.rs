@@ -0,0 +1,363 @@
+//! Field-level encryption of event content (`--encrypt-fields`).
+//!
+//! The chosen fields (prompt text, tool results, response text, thinking,
+//! tool inputs) are sealed with AES-256-GCM under the key in
+//! `--encryption-key-file` before an event is stored or broadcast, and so is
+//! the raw upstream body, which holds the same content. So is each agent's
+//! topic, a summary of its prompts, once when it's set so every event of the
+//! topic carries the same sealed string. Everything else
+//! (agent, model, usage, cost, tool names, timings) stays in plaintext, so
+//! costs, rollups, and leaderboards work as before while reading what was
+//! said takes the key: `sentinel decrypt <event id>` prints an event opened.
+//!
+//! A sealed string is `enc:v1:` followed by the base64 of its nonce and
+//! ciphertext. A sealed tool input is its JSON, sealed as one string.
+//!
+//! Derived tables are written from the stored event, so what they read from
+//! sealed content isn't there to read: sealing tool inputs leaves commands
+//! and file changes empty, and sealing responses leaves code blocks empty.
+//! Writing them from the plaintext would store it again beside the sealed
+//! copy. Error bodies and violation messages aren't sealed.
+
+use std::path::Path;
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use clap::ValueEnum;
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM, NONCE_LEN};
+use ring::rand::{SecureRandom, SystemRandom};
+
+use crate::copy;
+use crate::event::{ObservabilityEvent, Payload};
+use crate::storage::{RawCapture, Storage};
+
+const PREFIX: &str = "enc:v1:";
+
+#[derive(Debug, thiserror::Error)]
+pub enum EncryptError {
+    #[error("failed to read key file {path}: {source}")]
+    ReadKey {
+        path: String,
+        source: std::io::Error,
+    },
+    #[error("key file {0} must hold 32 bytes, raw or base64")]
+    BadKey(String),
+    #[error("a sealed field didn't open; wrong key, or the value was altered")]
+    Open,
+    #[error("a sealed tool input isn't JSON: {0}")]
+    Json(#[from] serde_json::Error),
+}
+
+/// Content that can be sealed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Field {
+    /// The text of each prompt
+    Prompt,
+    /// Tool results sent back with a prompt
+    ToolResults,
+    /// The text of each response
+    Response,
+    Thinking,
+    /// The input of each tool call
+    ToolInputs,
+}
+
+/// Whether a stored string was sealed
+pub fn is_sealed(text: &str) -> bool {
+    text.starts_with(PREFIX)
+}
+
+pub struct FieldCipher {
+    key: Option<LessSafeKey>,
+    fields: Vec<Field>,
+    rng: SystemRandom,
+}
+
+impl Default for FieldCipher {
+    fn default() -> Self {
+        Self {
+            key: None,
+            fields: Vec::new(),
+            rng: SystemRandom::new(),
+        }
+    }
+}
+
+impl FieldCipher {
+    /// A cipher for `fields` under the key in `key_file`
+    pub fn new(key_file: &Path, fields: Vec<Field>) -> Result<Self, EncryptError> {
+        let origin = key_file.display().to_string();
+        let bytes = std::fs::read(key_file).map_err(|source| EncryptError::ReadKey {
+            path: origin.clone(),
+            source,
+        })?;
+        let key = if bytes.len() == 32 {
+            bytes
+        } else {
+            STANDARD
+                .decode(String::from_utf8_lossy(&bytes).trim())
+                .map_err(|_| EncryptError::BadKey(origin.clone()))?
+        };
+        let key = UnboundKey::new(&AES_256_GCM, &key).map_err(|_| EncryptError::BadKey(origin))?;
+        Ok(Self {
+            key: Some(LessSafeKey::new(key)),
+            fields,
+            rng: SystemRandom::new(),
+        })
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.key.is_some() && !self.fields.is_empty()
+    }
+
+    /// The sealed fields as they're spelled on the command line
+    pub fn names(&self) -> Vec<String> {
+        self.fields
+            .iter()
+            .filter_map(|f| Some(f.to_possible_value()?.get_name().to_string()))
+            .collect()
+    }
+
+    fn sealing(&self, field: Field) -> bool {
+        self.is_active() && self.fields.contains(&field)
+    }
+
+    fn seal(&self, text: &str) -> String {
+        let Some(ref key) = self.key else {
+            return text.to_string();
+        };
+        let mut nonce = [0u8; NONCE_LEN];
+        // The system RNG failing leaves nothing sensible to fall back to
+        self.rng
+            .fill(&mut nonce)
+            .expect("the system random number generator failed");
+        let mut sealed = text.as_bytes().to_vec();
+        key.seal_in_place_append_tag(
+            Nonce::assume_unique_for_key(nonce),
+            Aad::empty(),
+            &mut sealed,
+        )
+        .expect("AES-GCM sealing fails only on inputs over 64 GB");
+        let mut out = nonce.to_vec();
+        out.extend_from_slice(&sealed);
+        format!("{}{}", PREFIX, STANDARD.encode(out))
+    }
+
+    /// The plaintext of a sealed string; anything else is returned as is
+    fn open(&self, text: &str) -> Result<String, EncryptError> {
+        let (Some(ref key), Some(encoded)) = (&self.key, text.strip_prefix(PREFIX)) else {
+            return Ok(text.to_string());
+        };
+        let bytes = STANDARD.decode(encoded).map_err(|_| EncryptError::Open)?;
+        if bytes.len() < NONCE_LEN {
+            return Err(EncryptError::Open);
+        }
+        let (nonce, sealed) = bytes.split_at(NONCE_LEN);
+        let nonce = Nonce::try_assume_unique_for_key(nonce).map_err(|_| EncryptError::Open)?;
+        let mut sealed = sealed.to_vec();
+        let plain = key
+            .open_in_place(nonce, Aad::empty(), &mut sealed)
+            .map_err(|_| EncryptError::Open)?;
+        String::from_utf8(plain.to_vec()).map_err(|_| EncryptError::Open)
+    }
+
+    /// A topic sealed for storage; one sealed already is kept as it is
+    pub fn topic(&self, topic: &str) -> String {
+        if self.is_active() && !is_sealed(topic) {
+            self.seal(topic)
+        } else {
+            topic.to_string()
+        }
+    }
+
+    /// Seal the chosen fields of an event
+    pub fn event(&self, event: &mut ObservabilityEvent) {
+        if !self.is_active() {
+            return;
+        }
+        if let Some(ref mut topic) = event.topic {
+            *topic = self.topic(topic);
+        }
+        match &mut event.payload {
+            Payload::UserMessage(message) => {
+                if self.sealing(Field::Prompt) {
+                    message.text = self.seal(&message.text);
+                }
+                if self.sealing(Field::ToolResults) {
+                    for result in &mut message.tool_results {
+                        result.content = self.seal(&result.content);
+                    }
+                }
+            }
+            Payload::AssistantResponse(response) => {
+                if let (true, Some(text)) = (self.sealing(Field::Response), &mut response.text) {
+                    *text = self.seal(text);
+                }
+                if let (true, Some(thinking)) =
+                    (self.sealing(Field::Thinking), &mut response.thinking)
+                {
+                    *thinking = self.seal(thinking);
+                }
+                if self.sealing(Field::ToolInputs) {
+                    for call in &mut response.tool_calls {
+                        call.input = serde_json::Value::String(self.seal(&call.input.to_string()));
+                    }
+                }
+            }
+            Payload::Alert(_)
+            | Payload::Error(_)
+            | Payload::Violation(_)
+            | Payload::SessionClosed(_)
+            | Payload::StreamDelta(_)
+            | Payload::StreamAborted(_)
+            | Payload::TurnSnapshot(_)
+            | Payload::Annotation(_) => {}
+        }
+    }
+
+    /// Seal a raw upstream body, which carries the same content as the fields
+    pub fn raw(&self, raw: &mut RawCapture) {
+        if self.is_active() && !raw.body.is_empty() {
+            raw.body = self.seal(&raw.body);
+        }
+    }
+
+    /// Open every sealed field of a stored event
+    pub fn open_event(&self, event: &mut ObservabilityEvent) -> Result<(), EncryptError> {
+        if let Some(ref mut topic) = event.topic {
+            *topic = self.open(topic)?;
+        }
+        match &mut event.payload {
+            Payload::UserMessage(message) => {
+                message.text = self.open(&message.text)?;
+                for result in &mut message.tool_results {
+                    result.content = self.open(&result.content)?;
+                }
+            }
+            Payload::AssistantResponse(response) => {
+                for text in [&mut response.text, &mut response.thinking]
+                    .into_iter()
+                    .flatten()
+                {
+                    *text = self.open(text)?;
+                }
+                for call in &mut response.tool_calls {
+                    if let Some(sealed) = call.input.as_str().filter(|s| is_sealed(s)) {
+                        call.input = serde_json::from_str(&self.open(sealed)?)?;
+                    }
+                }
+            }
+            Payload::Alert(_)
+            | Payload::Error(_)
+            | Payload::Violation(_)
+            | Payload::SessionClosed(_)
+            | Payload::StreamDelta(_)
+            | Payload::StreamAborted(_)
+            | Payload::TurnSnapshot(_)
+            | Payload::Annotation(_) => {}
+        }
+        Ok(())
+    }
+
+    pub fn open_raw(&self, raw: &mut RawCapture) -> Result<(), EncryptError> {
+        raw.body = self.open(&raw.body)?;
+        Ok(())
+    }
+}
+
+/// `sentinel decrypt`: print a recorded event, and its raw body, opened
+pub async fn run(
+    data_dir: &Path,
+    id: &str,
+    key_file: &Path,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let db_path = data_dir.join("sentinel.db");
+    if !db_path.exists() {
+        return Err("No recorded traffic. Run 'sentinel start' first.".into());
+    }
+    let cipher = FieldCipher::new(key_file, Vec::new())?;
+    let storage = Storage::new(&db_path).await?;
+    let mut event = copy::find_event(&storage, id).await?;
+    cipher.open_event(&mut event)?;
+    println!("{}", serde_json::to_string_pretty(&event)?);
+    if let Some(mut raw) = storage.get_raw_capture(&event.id.to_string()).await? {
+        cipher.open_raw(&mut raw)?;
+        if !raw.body.is_empty() {
+            println!("\n{}", raw.body);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event::AssistantResponse;
+    use crate::parsers::{ToolCall, Usage};
+    use uuid::Uuid;
+
+    #[test]
+    fn test_sealed_fields_open_with_the_key_and_metadata_stays_plain() {
+        let key_file = std::env::temp_dir().join(format!("sentinel-key-{}", Uuid::new_v4()));
+        std::fs::write(&key_file, STANDARD.encode([7u8; 32])).unwrap();
+        let cipher = FieldCipher::new(&key_file, vec![Field::Response, Field::ToolInputs]).unwrap();
+
+        let original = ObservabilityEvent {
+            session_id: None,
+            topic: Some("Fix the parser".to_string()),
+            ..ObservabilityEvent::for_tests(0, Payload::AssistantResponse(AssistantResponse {
+                model: Some("claude-sonnet-4".to_string()),
+                thinking: Some("check the tests".to_string()),
+                text: Some("The fix is in parser.rs".to_string()),
+                tool_calls: vec![ToolCall {
+                    id: "t1".to_string(),
+                    name: "Read".to_string(),
+                    input: serde_json::json!({"file_path": "src/parser.rs"}),
+                }],
+                usage: Some(Usage {
+                    input_tokens: Some(10),
+                    output_tokens: Some(5),
+                    cache_read_tokens: None,
+                    cache_creation_tokens: None,
+                    cost_usd: None,
+                }),
+                ..Default::default()
+            }))
+        };
+        let mut event = original.clone();
+        cipher.event(&mut event);
+
+        let Payload::AssistantResponse(ref sealed) = event.payload else {
+            unreachable!()
+        };
+        assert!(is_sealed(sealed.text.as_deref().unwrap()));
+        assert!(is_sealed(sealed.tool_calls[0].input.as_str().unwrap()));
+        // Not chosen, and metadata, stay readable
+        assert_eq!(sealed.thinking.as_deref(), Some("check the tests"));
+        assert_eq!(sealed.tool_calls[0].name, "Read");
+        assert_eq!(sealed.usage.as_ref().unwrap().output_tokens, Some(5));
+        // The topic summarizes the prompts, so it's sealed whatever was chosen,
+        // and only once
+        let topic = event.topic.clone().unwrap();
+        assert!(is_sealed(&topic));
+        assert_eq!(cipher.topic(&topic), topic);
+
+        cipher.open_event(&mut event).unwrap();
+        assert_eq!(
+            serde_json::to_value(&event.payload).unwrap(),
+            serde_json::to_value(&original.payload).unwrap()
+        );
+        assert_eq!(event.topic, original.topic);
+
+        std::fs::write(&key_file, [8u8; 32]).unwrap();
+        let mut event = original.clone();
+        cipher.event(&mut event);
+        let wrong = FieldCipher::new(&key_file, Vec::new()).unwrap();
+        assert!(matches!(
+            wrong.open_event(&mut event),
+            Err(EncryptError::Open)
+        ));
+
+        std::fs::remove_file(&key_file).unwrap();
+    }
+}
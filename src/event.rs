@@ -1,10 +1,12 @@
 use chrono::{DateTime, Utc};
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
-use crate::parsers::{ParsedResponse, ToolCall, Usage};
+use crate::diagnostics::FailureCause;
+use crate::parsers::{Delta, ParsedResponse, ToolCall, ToolResult, Usage};
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct ObservabilityEvent {
     pub seq: Option<i64>,
     pub id: Uuid,
@@ -12,23 +14,95 @@ pub struct ObservabilityEvent {
     pub session_id: Option<String>,
     pub agent: Option<String>,
     pub topic: Option<String>,
+    /// Upstream provider the event was exchanged with (e.g. "anthropic")
+    #[serde(default)]
+    pub provider: Option<String>,
     pub payload: Payload,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg(test)]
+impl ObservabilityEvent {
+    /// An event of calm-otter's session s1 with anthropic, `secs` seconds
+    /// after 10:00 on 2026-01-05
+    pub(crate) fn for_tests(secs: i64, payload: Payload) -> Self {
+        use chrono::TimeZone;
+
+        let start = Utc.with_ymd_and_hms(2026, 1, 5, 10, 0, 0).unwrap();
+        Self {
+            seq: None,
+            id: Uuid::new_v4(),
+            timestamp: start + chrono::Duration::seconds(secs),
+            session_id: Some("s1".to_string()),
+            agent: Some("calm-otter".to_string()),
+            topic: None,
+            provider: Some("anthropic".to_string()),
+            payload,
+        }
+    }
+}
+
+// Most events are responses, so boxing the large variant would save nothing
+#[allow(clippy::large_enum_variant)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 #[serde(tag = "type", rename_all = "snake_case")]
 pub enum Payload {
     UserMessage(UserMessage),
     AssistantResponse(AssistantResponse),
+    Alert(Alert),
+    Error(ErrorEvent),
+    Violation(Violation),
+    SessionClosed(SessionClosed),
+    StreamDelta(StreamDelta),
+    StreamAborted(StreamAborted),
+    TurnSnapshot(TurnSnapshot),
+    Annotation(Annotation),
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
 pub struct UserMessage {
     pub model: Option<String>,
     pub text: String,
+    /// Results of the previous turn's tool calls, sent back with this message
+    #[serde(default)]
+    pub tool_results: Vec<ToolResult>,
+    /// Id the response to this request (or its forward error) is recorded under
+    #[serde(default)]
+    pub response_id: Option<Uuid>,
+    /// Project named by the request's x-sentinel-project header
+    #[serde(default)]
+    pub project: Option<String>,
+    /// Run named by the request's x-sentinel-run-id header
+    #[serde(default)]
+    pub run_id: Option<String>,
+    /// `thinking.budget_tokens` of a request with extended thinking enabled
+    #[serde(default)]
+    pub thinking_budget: Option<u64>,
+    /// Set when the request retries an earlier one or forks the conversation
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub branch: Option<Branch>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// How a request departs from the session's conversation so far
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct Branch {
+    pub kind: BranchKind,
+    /// Request event the history is shared with
+    pub from: Uuid,
+    /// Messages shared with it
+    pub shared: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum BranchKind {
+    /// The same history sent again
+    Retry,
+    /// Continues a turn another request already continued, as after an
+    /// edited message or a rewind
+    Fork,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
 pub struct AssistantResponse {
     pub streaming: bool,
     pub model: Option<String>,
@@ -38,6 +112,236 @@ pub struct AssistantResponse {
     pub text: Option<String>,
     pub tool_calls: Vec<ToolCall>,
     pub usage: Option<Usage>,
+    /// Time from forwarding the request until the upstream's response headers arrived
+    #[serde(default)]
+    pub first_byte_ms: Option<u64>,
+    /// Time from forwarding the request until the first streamed text,
+    /// thinking, or tool call arrived
+    #[serde(default)]
+    pub first_token_ms: Option<u64>,
+    /// Time from forwarding the request until the full response was read
+    #[serde(default)]
+    pub latency_ms: Option<u64>,
+    /// `idempotency-key` the request was sent upstream with
+    #[serde(default)]
+    pub idempotency_key: Option<String>,
+    /// Id of the user message event recorded for the request this answers
+    #[serde(default)]
+    pub request_id: Option<Uuid>,
+    /// Response this one re-asked for, when sent with `sentinel redo`
+    #[serde(default)]
+    pub redo_of: Option<Uuid>,
+    /// Name of the `--upstream-keys` key the request was sent with
+    #[serde(default)]
+    pub upstream_key: Option<String>,
+    /// Project named by the request's x-sentinel-project header
+    #[serde(default)]
+    pub project: Option<String>,
+    /// Run named by the request's x-sentinel-run-id header
+    #[serde(default)]
+    pub run_id: Option<String>,
+}
+
+/// A piece of a streaming response as it arrives. Broadcast live so
+/// dashboards can show what the agent is writing; never stored, since the
+/// finished response carries it all.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct StreamDelta {
+    /// Id the finished response will be recorded under
+    pub response_id: Uuid,
+    #[serde(flatten)]
+    pub delta: Delta,
+}
+
+/// What a streaming response added since its last progress message. Sent
+/// live to dashboards as it arrives; never stored.
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct ResponseProgress {
+    /// Id the finished response will be recorded under
+    pub event_id: Uuid,
+    pub session_id: Option<String>,
+    pub agent: Option<String>,
+    pub text: String,
+    pub thinking: String,
+    /// Names of tool calls started
+    pub tool_calls: Vec<String>,
+}
+
+impl ResponseProgress {
+    /// The progress a `stream_delta` event makes, if it shows any; a tool
+    /// call's input isn't shown until the response is recorded
+    pub fn of(event: &ObservabilityEvent) -> Option<Self> {
+        let Payload::StreamDelta(ref stream) = event.payload else {
+            return None;
+        };
+        let mut progress = Self {
+            event_id: stream.response_id,
+            session_id: event.session_id.clone(),
+            agent: event.agent.clone(),
+            text: String::new(),
+            thinking: String::new(),
+            tool_calls: Vec::new(),
+        };
+        match stream.delta {
+            Delta::Text { ref text } => progress.text = text.clone(),
+            Delta::Thinking { ref thinking } => progress.thinking = thinking.clone(),
+            Delta::ToolUseStarted { ref name, .. } => progress.tool_calls.push(name.clone()),
+            Delta::ToolInput { .. } => return None,
+        }
+        Some(progress)
+    }
+}
+
+/// The client hung up before a streaming response finished. The upstream
+/// request was cancelled; what arrived until then is recorded as the
+/// response, usage included, since it may still be billed.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct StreamAborted {
+    /// Id the partial response is recorded under
+    pub response_id: Uuid,
+    /// Bytes of the stream received before the client left
+    pub received_bytes: u64,
+    /// Time from forwarding the request until the client left
+    pub elapsed_ms: u64,
+}
+
+/// A turn outlasted `--turn-sla` and was still running; where it stood then.
+/// The turn itself is recorded as usual when it ends.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct TurnSnapshot {
+    /// Id the response will be recorded under
+    pub response_id: Uuid,
+    pub sla_ms: u64,
+    /// Time since the request was first sent upstream
+    pub elapsed_ms: u64,
+    pub phase: TurnPhase,
+    pub model: Option<String>,
+    /// Whether the request asked for a streaming response
+    pub streaming: bool,
+    /// Size of the request body, or of what was read of one streamed upstream
+    pub request_bytes: u64,
+    /// Messages in the conversation sent
+    pub messages: Option<u64>,
+    pub upstream_host: Option<String>,
+    /// Times the request was sent, retries included
+    pub attempts: u32,
+    pub first_byte_ms: Option<u64>,
+    pub first_token_ms: Option<u64>,
+    /// Bytes of a streamed response received so far
+    pub received_bytes: u64,
+    pub chunks: u64,
+    /// Time since anything last arrived from the upstream (or since it was
+    /// sent, if nothing has)
+    pub idle_ms: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum TurnPhase {
+    /// Sent; the upstream hasn't answered with headers
+    WaitingForHeaders,
+    /// Headers arrived; the response is still streaming
+    Streaming,
+    /// Headers arrived; the rest of a non-streaming body hasn't
+    ReadingBody,
+}
+
+/// A note an orchestrator posted to a session's timeline, through
+/// `/api/sessions/:id/annotations` or a heartbeat that changed its phase
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct Annotation {
+    /// Phase the session entered, e.g. "planning" or "reviewing"
+    pub phase: Option<String>,
+    pub message: Option<String>,
+    /// Whatever else the orchestrator attached
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data: Option<serde_json::Value>,
+}
+
+impl Annotation {
+    /// "phase: message", or whichever of the two it has
+    pub fn describe(&self) -> String {
+        match (&self.phase, &self.message) {
+            (Some(phase), Some(message)) => format!("{}: {}", phase, message),
+            (Some(phase), None) => phase.clone(),
+            (None, Some(message)) => message.clone(),
+            (None, None) => String::new(),
+        }
+    }
+}
+
+/// Something an operator should look at, raised by sentinel itself
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct Alert {
+    /// Machine-readable alert kind, e.g. "slo_burn"
+    pub kind: String,
+    pub message: String,
+}
+
+/// A tool call that broke a configured rule
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct Violation {
+    /// What raised it, e.g. "baseline:backend-refactor"
+    pub source: String,
+    pub tool: String,
+    pub tool_use_id: Option<String>,
+    pub message: String,
+    /// Whether sentinel stopped the agent from continuing past it
+    pub blocked: bool,
+    /// Where the output strayed from a declared schema
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub mismatches: Vec<SchemaMismatch>,
+}
+
+/// One place structured output didn't match its schema
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct SchemaMismatch {
+    /// JSON pointer into the output, e.g. "/items/2/price"
+    pub path: String,
+    pub message: String,
+    /// The offending value, as JSON
+    pub found: String,
+}
+
+/// An agent's session went idle; totals cover it since it last closed
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct SessionClosed {
+    pub started_at: DateTime<Utc>,
+    /// When the last event before the idle gap was recorded
+    pub ended_at: DateTime<Utc>,
+    pub duration_secs: i64,
+    /// Prompts sent, including tool-result turns
+    pub turns: i64,
+    /// Responses and failed requests
+    pub requests: i64,
+    pub errors: i64,
+    pub input_tokens: i64,
+    pub output_tokens: i64,
+    pub cost_usd: f64,
+}
+
+/// A request that failed to complete
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct ErrorEvent {
+    pub kind: ErrorKind,
+    /// Classified connectivity failure, for `forward` errors
+    pub cause: Option<FailureCause>,
+    pub status: Option<u16>,
+    pub message: String,
+    /// What the upstream answered with, for `upstream` errors
+    #[serde(default)]
+    pub body: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorKind {
+    /// The request never reached the upstream
+    Forward,
+    /// The upstream answered with a 4xx or 5xx status
+    Upstream,
+    /// The upstream's response broke off before it was complete
+    Interrupted,
 }
 
 impl From<ParsedResponse> for AssistantResponse {
@@ -51,6 +355,15 @@ impl From<ParsedResponse> for AssistantResponse {
             text: parsed.text,
             tool_calls: parsed.tool_calls,
             usage: parsed.usage,
+            first_byte_ms: None,
+            latency_ms: None,
+            first_token_ms: None,
+            idempotency_key: None,
+            request_id: None,
+            redo_of: None,
+            upstream_key: None,
+            project: None,
+            run_id: None,
         }
     }
 }
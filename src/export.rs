@@ -0,0 +1,450 @@
+//! `sentinel export`: write recorded traffic out in other tools' formats.
+
+mod bridge;
+mod chrome_trace;
+pub mod jsonl;
+pub mod markdown;
+mod parquet;
+mod trace_tree;
+
+pub use self::parquet::PartitionBy;
+
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Utc};
+use clap::ValueEnum;
+
+use crate::agent::AgentStore;
+use crate::anonymize::Anonymizer;
+use crate::event::ObservabilityEvent;
+use crate::storage::Storage;
+
+use self::bridge::Bridge;
+use self::jsonl::Record;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ExportFormat {
+    /// Chrome trace-event JSON, for chrome://tracing or ui.perfetto.dev
+    ChromeTrace,
+    /// Columnar Parquet with one typed column per field, for DuckDB or Spark
+    Parquet,
+    /// One JSON agent or event per line, for `sentinel import` or jq
+    Jsonl,
+    /// A readable transcript with YAML front-matter, for docs or notes
+    Markdown,
+    /// A trace per session, pushed to LangSmith (LANGSMITH_API_KEY)
+    Langsmith,
+    /// A trace per session, pushed to W&B Weave (WANDB_API_KEY)
+    Weave,
+}
+
+/// Events read from the database at a time for whole-history exports
+const BATCH: i64 = 10_000;
+/// LangSmith or Weave records held before they're pushed
+const PUSH_BATCH: usize = 1_000;
+
+/// Which events an export includes (`since` and `agent` apply to Parquet,
+/// JSONL, LangSmith, and Weave), and whether they're anonymized
+#[derive(Default)]
+pub struct Filter {
+    pub since: Option<DateTime<Utc>>,
+    pub agent: Option<String>,
+    pub anonymizer: Option<Anonymizer>,
+}
+
+impl Filter {
+    /// Events on their way out, anonymized when asked to
+    fn anonymized(&self, mut events: Vec<ObservabilityEvent>) -> Vec<ObservabilityEvent> {
+        if let Some(ref anonymizer) = self.anonymizer {
+            events.iter_mut().for_each(|event| anonymizer.event(event));
+        }
+        events
+    }
+
+    fn is_empty(&self) -> bool {
+        self.since.is_none() && self.agent.is_none()
+    }
+
+    fn matches(&self, event: &ObservabilityEvent) -> bool {
+        self.since.is_none_or(|since| event.timestamp >= since)
+            && self
+                .agent
+                .as_ref()
+                .is_none_or(|agent| event.agent.as_ref() == Some(agent))
+    }
+}
+
+/// One session's events, or every event in batches, narrowed by a filter
+struct Batches<'a> {
+    storage: &'a Storage,
+    session: Option<&'a str>,
+    filter: &'a Filter,
+    last_seq: i64,
+    done: bool,
+}
+
+impl<'a> Batches<'a> {
+    fn new(storage: &'a Storage, session: Option<&'a str>, filter: &'a Filter) -> Self {
+        Self {
+            storage,
+            session,
+            filter,
+            last_seq: 0,
+            done: false,
+        }
+    }
+
+    async fn next(&mut self) -> Result<Option<Vec<ObservabilityEvent>>, Box<dyn std::error::Error>> {
+        if self.done {
+            return Ok(None);
+        }
+        if let Some(session) = self.session {
+            self.done = true;
+            let events = self.storage.get_session_or_agent_events(session).await?;
+            if events.is_empty() {
+                return Err(format!("No session or agent named '{}'", session).into());
+            }
+            let events = events.into_iter().filter(|e| self.filter.matches(e)).collect();
+            return Ok(Some(self.filter.anonymized(events)));
+        }
+        loop {
+            let events = self.storage.get_events_after_seq(self.last_seq, BATCH).await?;
+            let Some(seq) = events.last().and_then(|e| e.seq) else {
+                self.done = true;
+                return Ok(None);
+            };
+            self.last_seq = seq;
+            let events: Vec<_> = events.into_iter().filter(|e| self.filter.matches(e)).collect();
+            if !events.is_empty() {
+                return Ok(Some(self.filter.anonymized(events)));
+            }
+        }
+    }
+}
+
+pub async fn run(
+    data_dir: &Path,
+    session: Option<&str>,
+    format: ExportFormat,
+    partition_by: Option<PartitionBy>,
+    filter: Filter,
+    project: Option<String>,
+    output: Option<PathBuf>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let db_path = data_dir.join("sentinel.db");
+    if !db_path.exists() {
+        return Err("No recorded traffic. Run 'sentinel start' first.".into());
+    }
+    let storage = Storage::new(&db_path).await?;
+
+    let session = match format {
+        ExportFormat::Parquet => {
+            let output = output.ok_or("Parquet export needs --output")?;
+            return export_parquet(&storage, session, partition_by, &filter, &output).await;
+        }
+        _ if partition_by.is_some() => {
+            return Err("--partition-by only applies to --format parquet".into());
+        }
+        ExportFormat::Langsmith => {
+            let bridge = Bridge::langsmith(project);
+            return export_traces(&storage, session, &filter, &bridge, output.as_deref()).await;
+        }
+        ExportFormat::Weave => {
+            let bridge = Bridge::weave(project).map_err(|e| e.to_string())?;
+            return export_traces(&storage, session, &filter, &bridge, output.as_deref()).await;
+        }
+        _ if project.is_some() => {
+            return Err("--project only applies to --format langsmith or weave".into());
+        }
+        ExportFormat::Jsonl => {
+            return export_jsonl(&storage, session, &filter, output.as_deref()).await;
+        }
+        ExportFormat::ChromeTrace | ExportFormat::Markdown if !filter.is_empty() => {
+            return Err(
+                "--since and --agent only apply to --format parquet, jsonl, langsmith, or weave"
+                    .into(),
+            );
+        }
+        ExportFormat::ChromeTrace | ExportFormat::Markdown => {
+            session.ok_or("Name the session or agent to export")?
+        }
+    };
+    let events = storage.get_session_or_agent_events(session).await?;
+    if events.is_empty() {
+        return Err(format!("No session or agent named '{}'", session).into());
+    }
+    let events = filter.anonymized(events);
+
+    let rendered = match format {
+        ExportFormat::Markdown => markdown::render(&events)?,
+        _ => serde_json::to_string(&chrome_trace::build(&events))? + "\n",
+    };
+
+    match output {
+        Some(path) => {
+            std::fs::write(&path, rendered)?;
+            eprintln!("Wrote {} events to {}", events.len(), path.display());
+        }
+        None => {
+            std::io::stdout().lock().write_all(rendered.as_bytes())?;
+        }
+    }
+    Ok(())
+}
+
+/// One session's events, or every recorded event read in batches
+async fn export_parquet(
+    storage: &Storage,
+    session: Option<&str>,
+    partition_by: Option<PartitionBy>,
+    filter: &Filter,
+    output: &Path,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut writer = parquet::ParquetWriter::new(output, partition_by);
+    let mut batches = Batches::new(storage, session, filter);
+    while let Some(events) = batches.next().await? {
+        writer.write(&events)?;
+    }
+    let rows = writer.finish()?;
+    eprintln!("Wrote {} events to {}", rows, output.display());
+    Ok(())
+}
+
+/// Agents, then events with their raw bodies, one record per line
+async fn export_jsonl(
+    storage: &Storage,
+    session: Option<&str>,
+    filter: &Filter,
+    output: Option<&Path>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut out: Box<dyn Write> = match output {
+        Some(path) => Box::new(std::io::BufWriter::new(std::fs::File::create(path)?)),
+        None => Box::new(std::io::BufWriter::new(std::io::stdout().lock())),
+    };
+
+    let agents = AgentStore::new(storage.pool()).list_all().await?;
+    let mut agent_count = 0;
+    for mut agent in agents {
+        let wanted = match (session, filter.agent.as_deref()) {
+            (Some(key), _) => agent.session_id == key || agent.name == key,
+            (None, Some(name)) => agent.name == name,
+            (None, None) => true,
+        };
+        if wanted && filter.since.is_none_or(|since| agent.last_seen_at >= since) {
+            if let Some(ref anonymizer) = filter.anonymizer {
+                anonymizer.agent(&mut agent);
+            }
+            jsonl::write_record(&mut out, &Record::Agent(Box::new(agent)))?;
+            agent_count += 1;
+        }
+    }
+
+    let mut event_count = 0;
+    let mut batches = Batches::new(storage, session, filter);
+    while let Some(events) = batches.next().await? {
+        for event in events {
+            let mut raw = storage.get_raw_capture(&event.id.to_string()).await?;
+            if let (Some(anonymizer), Some(raw)) = (&filter.anonymizer, raw.as_mut()) {
+                anonymizer.raw(raw);
+            }
+            jsonl::write_record(&mut out, &Record::Event { event: Box::new(event), raw })?;
+            event_count += 1;
+        }
+    }
+    out.flush()?;
+    if let Some(path) = output {
+        eprintln!(
+            "Wrote {} agents and {} events to {}",
+            agent_count,
+            event_count,
+            path.display()
+        );
+    }
+    Ok(())
+}
+
+/// Sessions as traces, pushed to a bridge, or written one record per line
+/// to `output` to look over first
+async fn export_traces(
+    storage: &Storage,
+    session: Option<&str>,
+    filter: &Filter,
+    bridge: &Bridge,
+    output: Option<&Path>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    // A session can span batches, so a first pass finds the event each one
+    // ends with, and the second builds each as soon as that's read, holding
+    // only the sessions still open rather than the whole history
+    let mut ends: HashMap<String, i64> = HashMap::new();
+    let mut batches = Batches::new(storage, session, filter);
+    while let Some(events) = batches.next().await? {
+        for event in events {
+            ends.insert(trace_key(&event), event.seq.unwrap_or_default());
+        }
+    }
+
+    let mut out = TraceOut::new(bridge, output);
+    let mut open: HashMap<String, Vec<ObservabilityEvent>> = HashMap::new();
+    let mut batches = Batches::new(storage, session, filter);
+    while let Some(events) = batches.next().await? {
+        for event in events {
+            let key = trace_key(&event);
+            let ended = ends
+                .get(&key)
+                .is_some_and(|end| event.seq.unwrap_or_default() >= *end);
+            open.entry(key.clone()).or_default().push(event);
+            if ended {
+                let events = open.remove(&key).unwrap_or_default();
+                out.session(&events).await?;
+            }
+        }
+    }
+    // Sessions recorded into since the first pass
+    for events in open.into_values() {
+        out.session(&events).await?;
+    }
+    out.finish().await
+}
+
+/// The session an event is traced under
+fn trace_key(event: &ObservabilityEvent) -> String {
+    event
+        .session_id
+        .clone()
+        .or_else(|| event.agent.clone())
+        .unwrap_or_default()
+}
+
+/// Where LangSmith or Weave records go as each session is built: a file,
+/// or the service a batch at a time
+struct TraceOut<'a> {
+    bridge: &'a Bridge,
+    path: Option<&'a Path>,
+    file: Option<std::io::BufWriter<std::fs::File>>,
+    /// Records waiting to be pushed
+    pending: Vec<serde_json::Value>,
+    sessions: usize,
+    records: usize,
+}
+
+impl<'a> TraceOut<'a> {
+    fn new(bridge: &'a Bridge, path: Option<&'a Path>) -> Self {
+        Self {
+            bridge,
+            path,
+            file: None,
+            pending: Vec::new(),
+            sessions: 0,
+            records: 0,
+        }
+    }
+
+    async fn session(
+        &mut self,
+        events: &[ObservabilityEvent],
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let Some(trace) = trace_tree::build(events) else {
+            return Ok(());
+        };
+        let records = self.bridge.records(&trace);
+        self.sessions += 1;
+        self.records += records.len();
+        let Some(path) = self.path else {
+            self.pending.extend(records);
+            if self.pending.len() >= PUSH_BATCH {
+                self.bridge.push(&self.pending).await.map_err(|e| e.to_string())?;
+                self.pending.clear();
+            }
+            return Ok(());
+        };
+        // Opened with the first session, so an empty export leaves no file
+        if self.file.is_none() {
+            self.file = Some(std::io::BufWriter::new(std::fs::File::create(path)?));
+        }
+        let file = self.file.as_mut().expect("opened above");
+        for record in &records {
+            serde_json::to_writer(&mut *file, record)?;
+            file.write_all(b"\n")?;
+        }
+        Ok(())
+    }
+
+    async fn finish(self) -> Result<(), Box<dyn std::error::Error>> {
+        if self.sessions == 0 {
+            return Err("No requests or responses to export".into());
+        }
+        if let (Some(path), Some(mut file)) = (self.path, self.file) {
+            file.flush()?;
+            eprintln!(
+                "Wrote {} {} records for {} sessions to {}",
+                self.records,
+                self.bridge.service(),
+                self.sessions,
+                path.display()
+            );
+            return Ok(());
+        }
+        self.bridge.push(&self.pending).await.map_err(|e| e.to_string())?;
+        eprintln!(
+            "Pushed {} sessions ({} records) to {} project '{}'",
+            self.sessions,
+            self.records,
+            self.bridge.service(),
+            self.bridge.project()
+        );
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event::{AssistantResponse, Payload, UserMessage};
+    use std::collections::HashSet;
+    use uuid::Uuid;
+
+    #[tokio::test]
+    async fn test_traces_are_built_per_session_as_each_ends() {
+        let root = std::env::temp_dir().join(format!("sentinel-test-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&root).unwrap();
+        let storage = Storage::new(&root.join("sentinel.db")).await.unwrap();
+        let bridge = Bridge::langsmith(None);
+        let output = root.join("runs.jsonl");
+        let filter = Filter::default();
+        let none = export_traces(&storage, None, &filter, &bridge, Some(&output)).await;
+        assert!(none.is_err() && !output.exists());
+
+        // Two sessions' turns interleaved
+        for (secs, session) in [(1, "s1"), (2, "s2"), (3, "s1"), (4, "s2")] {
+            let prompt = Payload::UserMessage(UserMessage {
+                text: "Run the tests".to_string(),
+                ..Default::default()
+            });
+            let answer = Payload::AssistantResponse(AssistantResponse {
+                text: Some("They pass.".to_string()),
+                ..Default::default()
+            });
+            for (offset, payload) in [(0, prompt), (1, answer)] {
+                let event = ObservabilityEvent {
+                    session_id: Some(session.to_string()),
+                    ..ObservabilityEvent::for_tests(secs * 10 + offset, payload)
+                };
+                storage.insert_observability_event(&event).await.unwrap();
+            }
+        }
+
+        export_traces(&storage, None, &filter, &bridge, Some(&output)).await.unwrap();
+        let records: Vec<serde_json::Value> = std::fs::read_to_string(&output)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        let traces: HashSet<&str> = records.iter().filter_map(|r| r["trace_id"].as_str()).collect();
+        assert_eq!(traces.len(), 2);
+        let roots = records.iter().filter(|r| r["parent_run_id"].is_null()).count();
+        assert_eq!(roots, 2);
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
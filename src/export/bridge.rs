@@ -0,0 +1,292 @@
+//! Pushing sessions into LangSmith or W&B Weave.
+//!
+//! For teams that already look at traces there: sentinel records, and each
+//! session is sent as one trace of runs (`trace_tree`). LangSmith gets runs
+//! through `POST /runs/batch`, under a project (`--project`, "sentinel" by
+//! default). Weave gets start and end call records through
+//! `POST /call/upsert_batch`, under an `entity/project`.
+//!
+//! Credentials come from the environment the vendors' own SDKs read:
+//! `LANGSMITH_API_KEY` (or `LANGCHAIN_API_KEY`) and `LANGSMITH_ENDPOINT`, or
+//! `WANDB_API_KEY` and `WF_TRACE_SERVER_URL`.
+
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde_json::{json, Value};
+
+use super::trace_tree::{RunKind, SessionTrace, TraceRun};
+use crate::parsers::Usage;
+
+const LANGSMITH_ENDPOINT: &str = "https://api.smith.langchain.com";
+const WEAVE_SERVER: &str = "https://trace.wandb.ai";
+const DEFAULT_LANGSMITH_PROJECT: &str = "sentinel";
+/// Runs or call records sent per request
+const CHUNK: usize = 100;
+
+#[derive(Debug, thiserror::Error)]
+pub enum BridgeError {
+    #[error("{0} is not set")]
+    MissingKey(&'static str),
+    #[error("Weave export needs --project <entity>/<project>")]
+    WeaveProject,
+    #[error("request failed: {0}")]
+    Request(#[from] reqwest::Error),
+    #[error("{service} returned {status}: {body}")]
+    Api {
+        service: &'static str,
+        status: u16,
+        body: String,
+    },
+}
+
+/// Where the traces go
+pub enum Bridge {
+    LangSmith {
+        endpoint: String,
+        api_key: Option<String>,
+        project: String,
+    },
+    Weave {
+        server: String,
+        api_key: Option<String>,
+        /// entity/project
+        project: String,
+    },
+}
+
+fn env(name: &str) -> Option<String> {
+    std::env::var(name).ok().filter(|v| !v.is_empty())
+}
+
+impl Bridge {
+    pub fn langsmith(project: Option<String>) -> Self {
+        Self::LangSmith {
+            endpoint: env("LANGSMITH_ENDPOINT").unwrap_or_else(|| LANGSMITH_ENDPOINT.to_string()),
+            api_key: env("LANGSMITH_API_KEY").or_else(|| env("LANGCHAIN_API_KEY")),
+            project: project.unwrap_or_else(|| DEFAULT_LANGSMITH_PROJECT.to_string()),
+        }
+    }
+
+    pub fn weave(project: Option<String>) -> Result<Self, BridgeError> {
+        let project = project
+            .filter(|p| p.split('/').filter(|part| !part.is_empty()).count() == 2)
+            .ok_or(BridgeError::WeaveProject)?;
+        Ok(Self::Weave {
+            server: env("WF_TRACE_SERVER_URL").unwrap_or_else(|| WEAVE_SERVER.to_string()),
+            api_key: env("WANDB_API_KEY"),
+            project,
+        })
+    }
+
+    pub fn service(&self) -> &'static str {
+        match self {
+            Self::LangSmith { .. } => "LangSmith",
+            Self::Weave { .. } => "Weave",
+        }
+    }
+
+    pub fn project(&self) -> &str {
+        match self {
+            Self::LangSmith { project, .. } | Self::Weave { project, .. } => project,
+        }
+    }
+
+    /// What a trace is sent as: LangSmith runs, or Weave start and end records
+    pub fn records(&self, trace: &SessionTrace) -> Vec<Value> {
+        match self {
+            Self::LangSmith { project, .. } => langsmith_runs(trace, project),
+            Self::Weave { project, .. } => weave_calls(trace, project),
+        }
+    }
+
+    /// Send records, a chunk at a time
+    pub async fn push(&self, records: &[Value]) -> Result<(), BridgeError> {
+        let client = reqwest::Client::new();
+        for chunk in records.chunks(CHUNK) {
+            let request = match self {
+                Self::LangSmith {
+                    endpoint, api_key, ..
+                } => {
+                    let key = api_key
+                        .as_deref()
+                        .ok_or(BridgeError::MissingKey("LANGSMITH_API_KEY"))?;
+                    client
+                        .post(format!("{}/runs/batch", endpoint.trim_end_matches('/')))
+                        .header("x-api-key", key)
+                        .json(&json!({ "post": chunk }))
+                }
+                Self::Weave {
+                    server, api_key, ..
+                } => {
+                    let key = api_key
+                        .as_deref()
+                        .ok_or(BridgeError::MissingKey("WANDB_API_KEY"))?;
+                    client
+                        .post(format!(
+                            "{}/call/upsert_batch",
+                            server.trim_end_matches('/')
+                        ))
+                        .basic_auth("api", Some(key))
+                        .json(&json!({ "batch": chunk }))
+                }
+            };
+            let response = request.send().await?;
+            let status = response.status();
+            if !status.is_success() {
+                return Err(BridgeError::Api {
+                    service: self.service(),
+                    status: status.as_u16(),
+                    body: response.text().await.unwrap_or_default(),
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+fn timestamp(at: DateTime<Utc>) -> String {
+    at.to_rfc3339_opts(SecondsFormat::Micros, true)
+}
+
+/// Where a run came from; LangSmith prices model runs by `ls_model_name`
+fn metadata(trace: &SessionTrace, run: &TraceRun, prefix: &str) -> Value {
+    let mut metadata = json!({
+        "agent": trace.agent,
+        "session_id": trace.session_id,
+    });
+    metadata[format!("{}provider", prefix)] = json!(run.provider);
+    metadata[format!("{}model_name", prefix)] = json!(run.model);
+    metadata
+}
+
+/// Token counts as both tools read them
+fn usage_counts(usage: &Usage) -> Value {
+    let input = usage.input_tokens.unwrap_or(0);
+    let output = usage.output_tokens.unwrap_or(0);
+    json!({
+        "input_tokens": input,
+        "output_tokens": output,
+        "total_tokens": input + output,
+        "input_token_details": {
+            "cache_read": usage.cache_read_tokens.unwrap_or(0),
+            "cache_creation": usage.cache_creation_tokens.unwrap_or(0),
+        },
+    })
+}
+
+fn langsmith_runs(trace: &SessionTrace, project: &str) -> Vec<Value> {
+    let root = trace.root();
+    // Runs sort by their start time and id, each under its parent's
+    let order = |run: &TraceRun| format!("{}{}", run.start.format("%Y%m%dT%H%M%S%6fZ"), run.id);
+    let root_order = order(root);
+    trace
+        .runs
+        .iter()
+        .map(|run| {
+            let mut outputs = run.outputs.clone();
+            if let (Some(usage), Value::Object(map)) = (&run.usage, &mut outputs) {
+                map.insert("usage_metadata".to_string(), usage_counts(usage));
+            }
+            json!({
+                "id": run.id,
+                "trace_id": root.id,
+                "parent_run_id": run.parent,
+                "dotted_order": match run.parent {
+                    Some(_) => format!("{}.{}", root_order, order(run)),
+                    None => root_order.clone(),
+                },
+                "name": run.name,
+                "run_type": match run.kind {
+                    RunKind::Session => "chain",
+                    RunKind::Llm => "llm",
+                    RunKind::Tool => "tool",
+                },
+                "start_time": timestamp(run.start),
+                "end_time": timestamp(run.end),
+                "inputs": run.inputs,
+                "outputs": outputs,
+                "error": run.error,
+                "session_name": project,
+                "extra": { "metadata": metadata(trace, run, "ls_") },
+            })
+        })
+        .collect()
+}
+
+fn weave_calls(trace: &SessionTrace, project: &str) -> Vec<Value> {
+    let root = trace.root();
+    trace
+        .runs
+        .iter()
+        .flat_map(|run| {
+            let op = match run.kind {
+                RunKind::Session => "sentinel.session".to_string(),
+                RunKind::Llm => "sentinel.llm".to_string(),
+                RunKind::Tool => format!("sentinel.tool.{}", run.name),
+            };
+            let summary = match (&run.usage, &run.model) {
+                (Some(usage), Some(model)) => {
+                    let mut counts = usage_counts(usage);
+                    counts["requests"] = json!(1);
+                    json!({ "usage": { model: counts } })
+                }
+                _ => json!({}),
+            };
+            [
+                json!({"mode": "start", "req": {"start": {
+                    "project_id": project,
+                    "id": run.id,
+                    "trace_id": root.id,
+                    "parent_id": run.parent,
+                    "op_name": op,
+                    "display_name": run.name,
+                    "started_at": timestamp(run.start),
+                    "inputs": run.inputs,
+                    "attributes": metadata(trace, run, ""),
+                }}}),
+                json!({"mode": "end", "req": {"end": {
+                    "project_id": project,
+                    "id": run.id,
+                    "ended_at": timestamp(run.end),
+                    "output": run.outputs,
+                    "exception": run.error,
+                    "summary": summary,
+                }}}),
+            ]
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::export::trace_tree::{self, tests::session};
+
+    #[test]
+    fn test_records_nest_runs_under_the_session() {
+        let trace = trace_tree::build(&session()).unwrap();
+
+        let bridge = Bridge::langsmith(Some("agents".to_string()));
+        let runs = bridge.records(&trace);
+        assert_eq!(runs.len(), 4);
+        let root = &runs[0];
+        assert_eq!(root["run_type"], "chain");
+        assert_eq!(root["session_name"], "agents");
+        assert!(root["parent_run_id"].is_null());
+        let root_order = root["dotted_order"].as_str().unwrap();
+        assert!(root_order.starts_with("20231114T221320000000Z"));
+        let tool = &runs[2];
+        assert_eq!(tool["run_type"], "tool");
+        assert_eq!(tool["parent_run_id"], root["id"]);
+        assert_eq!(tool["trace_id"], root["id"]);
+        let tool_order = tool["dotted_order"].as_str().unwrap();
+        assert!(tool_order.starts_with(&format!("{}.20231114T221322", root_order)));
+
+        assert!(Bridge::weave(Some("agents".to_string())).is_err());
+        let bridge = Bridge::weave(Some("team/agents".to_string())).unwrap();
+        let calls = bridge.records(&trace);
+        assert_eq!(calls.len(), 8);
+        assert_eq!(calls[0]["req"]["start"]["op_name"], "sentinel.session");
+        assert_eq!(calls[1]["mode"], "end");
+        assert_eq!(calls[5]["req"]["end"]["output"]["output"], "src");
+    }
+}
@@ -0,0 +1,422 @@
+//! Chrome trace-event format.
+//!
+//! Each session becomes one process with a track per activity: whole turns,
+//! the upstream call (split at the first byte), tool execution between a
+//! `tool_use` response and the request carrying its result, and time spent
+//! waiting on the user. Timestamps are microseconds from the first event.
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use serde_json::json;
+
+use crate::event::{ObservabilityEvent, Payload};
+use crate::pricing;
+
+const TURNS: u32 = 1;
+const UPSTREAM: u32 = 2;
+const TOOLS: u32 = 3;
+const USER: u32 = 4;
+
+const TRACKS: &[(u32, &str)] = &[
+    (TURNS, "turns"),
+    (UPSTREAM, "upstream"),
+    (TOOLS, "tools"),
+    (USER, "user"),
+];
+
+#[derive(Debug, Serialize)]
+pub struct TraceEvent {
+    name: String,
+    cat: &'static str,
+    ph: &'static str,
+    ts: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    dur: Option<i64>,
+    pid: u32,
+    tid: u32,
+    /// Scope of instant events
+    #[serde(skip_serializing_if = "Option::is_none")]
+    s: Option<&'static str>,
+    #[serde(skip_serializing_if = "serde_json::Value::is_null")]
+    args: serde_json::Value,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Trace {
+    trace_events: Vec<TraceEvent>,
+    display_time_unit: &'static str,
+    other_data: serde_json::Value,
+}
+
+struct Builder {
+    origin: DateTime<Utc>,
+    events: Vec<TraceEvent>,
+}
+
+impl Builder {
+    fn micros(&self, at: DateTime<Utc>) -> i64 {
+        (at - self.origin).num_microseconds().unwrap_or(i64::MAX)
+    }
+
+    fn span(
+        &mut self,
+        tid: u32,
+        cat: &'static str,
+        name: String,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        args: serde_json::Value,
+    ) {
+        let ts = self.micros(start);
+        self.events.push(TraceEvent {
+            name,
+            cat,
+            ph: "X",
+            ts,
+            dur: Some((self.micros(end) - ts).max(0)),
+            pid: 1,
+            tid,
+            s: None,
+            args,
+        });
+    }
+
+    fn instant(
+        &mut self,
+        tid: u32,
+        cat: &'static str,
+        name: String,
+        at: DateTime<Utc>,
+        args: serde_json::Value,
+    ) {
+        self.events.push(TraceEvent {
+            name,
+            cat,
+            ph: "i",
+            ts: self.micros(at),
+            dur: None,
+            pid: 1,
+            tid,
+            s: Some("t"),
+            args,
+        });
+    }
+}
+
+/// What the previous response left the session waiting on
+struct LastResponse {
+    at: DateTime<Utc>,
+    tools: Vec<String>,
+}
+
+pub fn build(events: &[ObservabilityEvent]) -> Trace {
+    let origin = events.first().map(|e| e.timestamp).unwrap_or_else(Utc::now);
+    let mut b = Builder {
+        origin,
+        events: Vec::new(),
+    };
+
+    let label = events
+        .iter()
+        .find_map(|e| e.agent.clone().or_else(|| e.session_id.clone()))
+        .unwrap_or_else(|| "session".to_string());
+    b.events.push(TraceEvent {
+        name: "process_name".to_string(),
+        cat: "__metadata",
+        ph: "M",
+        ts: 0,
+        dur: None,
+        pid: 1,
+        tid: 0,
+        s: None,
+        args: json!({ "name": label }),
+    });
+    for (tid, name) in TRACKS {
+        b.events.push(TraceEvent {
+            name: "thread_name".to_string(),
+            cat: "__metadata",
+            ph: "M",
+            ts: 0,
+            dur: None,
+            pid: 1,
+            tid: *tid,
+            s: None,
+            args: json!({ "name": name }),
+        });
+    }
+
+    let mut request_at: Option<DateTime<Utc>> = None;
+    let mut last_response: Option<LastResponse> = None;
+    let mut turn = 0;
+
+    for event in events {
+        match &event.payload {
+            Payload::UserMessage(message) => {
+                if let Some(last) = last_response.take() {
+                    if last.tools.is_empty() {
+                        b.span(
+                            USER,
+                            "user",
+                            "waiting for user".to_string(),
+                            last.at,
+                            event.timestamp,
+                            json!(null),
+                        );
+                    } else {
+                        b.span(
+                            TOOLS,
+                            "tool",
+                            last.tools.join(", "),
+                            last.at,
+                            event.timestamp,
+                            json!(null),
+                        );
+                    }
+                }
+                request_at = Some(event.timestamp);
+                let preview: String = message.text.chars().take(200).collect();
+                b.instant(
+                    TURNS,
+                    "request",
+                    "request".to_string(),
+                    event.timestamp,
+                    json!({ "text": preview }),
+                );
+            }
+            Payload::AssistantResponse(response) => {
+                turn += 1;
+                let end = event.timestamp;
+                let upstream_start = response
+                    .latency_ms
+                    .map(|ms| end - chrono::Duration::milliseconds(ms as i64));
+                let start = request_at.take().or(upstream_start).unwrap_or(end);
+                let model = response
+                    .model
+                    .clone()
+                    .unwrap_or_else(|| "unknown model".to_string());
+                let cost = response
+                    .usage
+                    .as_ref()
+                    .and_then(|u| pricing::cost_usd(response.model.as_deref(), u));
+
+                b.span(
+                    TURNS,
+                    "turn",
+                    format!("turn {}", turn),
+                    start,
+                    end,
+                    json!({
+                        "model": model,
+                        "stop_reason": response.stop_reason,
+                        "usage": response.usage,
+                        "cost_usd": cost,
+                    }),
+                );
+
+                if let Some(upstream_start) = upstream_start {
+                    let first_byte = response
+                        .first_byte_ms
+                        .map(|ms| upstream_start + chrono::Duration::milliseconds(ms as i64))
+                        .unwrap_or(upstream_start);
+                    b.span(
+                        UPSTREAM,
+                        "upstream",
+                        format!("{} waiting", model),
+                        upstream_start,
+                        first_byte,
+                        json!({ "first_byte_ms": response.first_byte_ms }),
+                    );
+                    let name = if response.thinking.is_some() {
+                        format!("{} thinking + response", model)
+                    } else {
+                        format!("{} response", model)
+                    };
+                    b.span(
+                        UPSTREAM,
+                        "upstream",
+                        name,
+                        first_byte,
+                        end,
+                        json!({
+                            "thinking_chars": response.thinking.as_ref().map(|t| t.chars().count()),
+                            "text_chars": response.text.as_ref().map(|t| t.chars().count()),
+                            "output_tokens": response.usage.as_ref().and_then(|u| u.output_tokens),
+                        }),
+                    );
+                }
+
+                for call in &response.tool_calls {
+                    b.instant(
+                        TOOLS,
+                        "tool",
+                        format!("{} requested", call.name),
+                        end,
+                        json!({ "input": call.input }),
+                    );
+                }
+                last_response = Some(LastResponse {
+                    at: end,
+                    tools: response.tool_calls.iter().map(|c| c.name.clone()).collect(),
+                });
+            }
+            Payload::Error(error) => {
+                b.instant(
+                    TURNS,
+                    "error",
+                    format!("error: {}", error.message),
+                    event.timestamp,
+                    json!({ "status": error.status }),
+                );
+                request_at = None;
+            }
+            Payload::Alert(alert) => {
+                b.instant(
+                    TURNS,
+                    "alert",
+                    alert.kind.clone(),
+                    event.timestamp,
+                    json!({ "message": alert.message }),
+                );
+            }
+            Payload::Violation(violation) => {
+                b.instant(
+                    TOOLS,
+                    "violation",
+                    format!("violation: {}", violation.tool),
+                    event.timestamp,
+                    json!({ "source": violation.source, "message": violation.message, "blocked": violation.blocked }),
+                );
+            }
+            Payload::SessionClosed(closed) => {
+                b.instant(
+                    TURNS,
+                    "session",
+                    "session closed".to_string(),
+                    event.timestamp,
+                    json!({ "duration_secs": closed.duration_secs, "turns": closed.turns, "cost_usd": closed.cost_usd }),
+                );
+            }
+            Payload::StreamAborted(aborted) => {
+                b.instant(
+                    TURNS,
+                    "stream_aborted",
+                    "client disconnected".to_string(),
+                    event.timestamp,
+                    json!({ "received_bytes": aborted.received_bytes, "elapsed_ms": aborted.elapsed_ms }),
+                );
+            }
+            Payload::TurnSnapshot(snapshot) => {
+                b.instant(
+                    TURNS,
+                    "turn_snapshot",
+                    format!("turn over {} ms", snapshot.sla_ms),
+                    event.timestamp,
+                    json!({ "elapsed_ms": snapshot.elapsed_ms, "received_bytes": snapshot.received_bytes, "idle_ms": snapshot.idle_ms }),
+                );
+            }
+            Payload::Annotation(annotation) => {
+                b.instant(
+                    TURNS,
+                    "annotation",
+                    annotation.describe(),
+                    event.timestamp,
+                    json!({ "phase": annotation.phase, "message": annotation.message, "data": annotation.data }),
+                );
+            }
+            // Live-only; never recorded
+            Payload::StreamDelta(_) => {}
+        }
+    }
+
+    Trace {
+        trace_events: b.events,
+        display_time_unit: "ms",
+        other_data: json!({
+            "session": events.first().and_then(|e| e.session_id.clone()),
+            "agent": events.first().and_then(|e| e.agent.clone()),
+            "started_at": origin.to_rfc3339(),
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event::{AssistantResponse, UserMessage};
+    use crate::parsers::ToolCall;
+    use uuid::Uuid;
+
+    fn at(ms: i64, payload: Payload) -> ObservabilityEvent {
+        ObservabilityEvent {
+            seq: None,
+            id: Uuid::new_v4(),
+            timestamp: DateTime::from_timestamp_millis(1_700_000_000_000 + ms).unwrap(),
+            session_id: Some("s1".to_string()),
+            agent: Some("swift-fox".to_string()),
+            topic: None,
+            provider: None,
+            payload,
+        }
+    }
+
+    fn response(tools: &[&str]) -> Payload {
+        Payload::AssistantResponse(AssistantResponse {
+            streaming: true,
+            model: Some("claude-sonnet-4".to_string()),
+            message_id: None,
+            stop_reason: Some("tool_use".to_string()),
+            thinking: Some("hmm".to_string()),
+            text: None,
+            tool_calls: tools
+                .iter()
+                .map(|t| ToolCall {
+                    id: "t".to_string(),
+                    name: t.to_string(),
+                    input: json!({}),
+                })
+                .collect(),
+            usage: None,
+            first_byte_ms: Some(200),
+            first_token_ms: None,
+            latency_ms: Some(900),
+            idempotency_key: None,
+            request_id: None,
+            redo_of: None,
+            upstream_key: None,
+            project: None,
+            run_id: None,
+        })
+    }
+
+    fn user() -> Payload {
+        Payload::UserMessage(UserMessage {
+            text: "go".to_string(),
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn test_turns_upstream_and_tool_spans() {
+        let trace = build(&[
+            at(0, user()),
+            at(1_000, response(&["Bash"])),
+            at(4_000, user()),
+            at(5_000, response(&[])),
+        ]);
+        let spans: Vec<&TraceEvent> = trace.trace_events.iter().filter(|e| e.ph == "X").collect();
+
+        let turn = spans.iter().find(|e| e.name == "turn 1").unwrap();
+        assert_eq!((turn.ts, turn.dur), (0, Some(1_000_000)));
+
+        let waiting = spans.iter().find(|e| e.tid == UPSTREAM).unwrap();
+        assert_eq!((waiting.ts, waiting.dur), (100_000, Some(200_000)));
+        assert!(spans
+            .iter()
+            .any(|e| e.name.ends_with("thinking + response")));
+
+        let tool = spans.iter().find(|e| e.tid == TOOLS).unwrap();
+        assert_eq!(tool.name, "Bash");
+        assert_eq!((tool.ts, tool.dur), (1_000_000, Some(3_000_000)));
+    }
+}
@@ -0,0 +1,73 @@
+//! JSON Lines: one agent or event per line, read back by `sentinel import`.
+//!
+//! Each line is tagged with its `kind`. Events keep their ids and carry the
+//! raw body they were recorded with, so an import reproduces them exactly
+//! (apart from `seq`, which the importing database assigns). Agents come
+//! first so a reader can resolve names before the events that use them.
+
+use std::io::Write;
+
+use serde::{Deserialize, Serialize};
+
+use crate::agent::Agent;
+use crate::event::ObservabilityEvent;
+use crate::storage::RawCapture;
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Record {
+    Agent(Box<Agent>),
+    Event {
+        event: Box<ObservabilityEvent>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        raw: Option<RawCapture>,
+    },
+}
+
+pub fn write_record(out: &mut impl Write, record: &Record) -> std::io::Result<()> {
+    serde_json::to_writer(&mut *out, record)?;
+    out.write_all(b"\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event::{Alert, Payload};
+    use chrono::Utc;
+    use uuid::Uuid;
+
+    #[test]
+    fn test_event_record_round_trips_with_raw() {
+        let record = Record::Event {
+            event: Box::new(ObservabilityEvent {
+                seq: Some(7),
+                id: Uuid::new_v4(),
+                timestamp: Utc::now(),
+                session_id: Some("s1".to_string()),
+                agent: Some("calm-otter".to_string()),
+                topic: None,
+                provider: Some("anthropic".to_string()),
+                payload: Payload::Alert(Alert {
+                    kind: "test".to_string(),
+                    message: "hello".to_string(),
+                }),
+            }),
+            raw: Some(RawCapture {
+                body: "{}".to_string(),
+                original_bytes: 2,
+                truncated: false,
+            }),
+        };
+        let mut out = Vec::new();
+        write_record(&mut out, &record).unwrap();
+        let line = String::from_utf8(out).unwrap();
+        assert!(line.starts_with("{\"kind\":\"event\""));
+        assert!(line.ends_with("}\n"));
+
+        let Record::Event { event, raw } = serde_json::from_str(&line).unwrap() else {
+            panic!("expected an event record");
+        };
+        assert_eq!(event.agent.as_deref(), Some("calm-otter"));
+        assert_eq!(raw.unwrap().original_bytes, 2);
+    }
+}
@@ -0,0 +1,291 @@
+//! Markdown transcripts.
+//!
+//! A session read as a document: YAML front-matter (agent, sessions, dates,
+//! models, tokens, cost), then each turn under a role header. Thinking and
+//! tool results are collapsed in `<details>` blocks, tool call inputs are
+//! fenced JSON, and nothing is shortened, so the file can be committed to a
+//! repo's docs or dropped into an Obsidian vault as the whole record.
+
+use std::collections::{BTreeSet, HashMap};
+use std::fmt::Write;
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+use crate::event::{ObservabilityEvent, Payload};
+use crate::parsers::ToolStatus;
+use crate::pricing;
+
+#[derive(Debug, Serialize)]
+struct FrontMatter {
+    agent: Option<String>,
+    topic: Option<String>,
+    sessions: Vec<String>,
+    started: DateTime<Utc>,
+    ended: DateTime<Utc>,
+    models: Vec<String>,
+    turns: i64,
+    requests: i64,
+    input_tokens: i64,
+    output_tokens: i64,
+    cost_usd: f64,
+}
+
+fn front_matter(events: &[ObservabilityEvent]) -> Option<FrontMatter> {
+    let (first, last) = (events.first()?, events.last()?);
+    let mut models = BTreeSet::new();
+    let mut matter = FrontMatter {
+        agent: first.agent.clone(),
+        topic: events.iter().rev().find_map(|e| e.topic.clone()),
+        sessions: Vec::new(),
+        started: first.timestamp,
+        ended: last.timestamp,
+        models: Vec::new(),
+        turns: 0,
+        requests: 0,
+        input_tokens: 0,
+        output_tokens: 0,
+        cost_usd: 0.0,
+    };
+    for event in events {
+        if let Some(ref session) = event.session_id {
+            if !matter.sessions.contains(session) {
+                matter.sessions.push(session.clone());
+            }
+        }
+        match &event.payload {
+            Payload::UserMessage(_) => matter.turns += 1,
+            Payload::AssistantResponse(response) => {
+                matter.requests += 1;
+                models.extend(response.model.clone());
+                if let Some(ref usage) = response.usage {
+                    matter.input_tokens += usage.input_tokens.unwrap_or(0);
+                    matter.output_tokens += usage.output_tokens.unwrap_or(0);
+                    matter.cost_usd +=
+                        pricing::cost_usd(response.model.as_deref(), usage).unwrap_or(0.0);
+                }
+            }
+            Payload::Error(_) => matter.requests += 1,
+            Payload::Alert(_)
+            | Payload::Violation(_)
+            | Payload::SessionClosed(_)
+            | Payload::StreamDelta(_)
+            | Payload::StreamAborted(_)
+            | Payload::TurnSnapshot(_)
+            | Payload::Annotation(_) => {}
+        }
+    }
+    matter.models = models.into_iter().collect();
+    matter.cost_usd = (matter.cost_usd * 1e6).round() / 1e6;
+    Some(matter)
+}
+
+/// A fence longer than any run of backticks in `text`, so it can't close early
+fn fence(text: &str) -> String {
+    let longest = text.split(|c| c != '`').map(str::len).max().unwrap_or(0);
+    "`".repeat(longest.max(2) + 1)
+}
+
+fn push_fenced(out: &mut String, text: &str, lang: &str) {
+    let fence = fence(text);
+    let _ = writeln!(out, "{}{}\n{}\n{}\n", fence, lang, text.trim_end(), fence);
+}
+
+fn push_details(out: &mut String, summary: &str, body: impl FnOnce(&mut String)) {
+    let _ = writeln!(out, "<details>\n<summary>{}</summary>\n", summary);
+    body(out);
+    out.push_str("</details>\n\n");
+}
+
+pub fn render(events: &[ObservabilityEvent]) -> Result<String, serde_yaml::Error> {
+    let mut out = String::new();
+    let Some(matter) = front_matter(events) else {
+        return Ok(out);
+    };
+    let _ = write!(out, "---\n{}---\n\n", serde_yaml::to_string(&matter)?);
+    let title = matter
+        .topic
+        .as_deref()
+        .or(matter.agent.as_deref())
+        .unwrap_or("Transcript");
+    let _ = writeln!(out, "# {}\n", title);
+
+    // Tool names by call id, so results can say which tool produced them
+    let mut tools: HashMap<&str, &str> = HashMap::new();
+    let mut session: Option<&str> = None;
+    for (i, event) in events.iter().enumerate() {
+        if i == 0 || session != event.session_id.as_deref() {
+            session = event.session_id.as_deref();
+            let _ = writeln!(
+                out,
+                "## Session {}\n\nStarted {}\n",
+                session.unwrap_or("(none)"),
+                event.timestamp.format("%Y-%m-%d %H:%M:%S UTC")
+            );
+        }
+        let time = event.timestamp.format("%H:%M:%S");
+        match &event.payload {
+            Payload::UserMessage(msg) => {
+                let _ = writeln!(out, "### User · {}\n", time);
+                for result in &msg.tool_results {
+                    let tool = tools
+                        .get(result.tool_use_id.as_str())
+                        .copied()
+                        .unwrap_or("Tool");
+                    let status = match result.status() {
+                        ToolStatus::Succeeded => String::new(),
+                        status => format!(", {}", status.as_str()),
+                    };
+                    let summary = format!(
+                        "{} result (<code>{}</code>{})",
+                        tool, result.tool_use_id, status
+                    );
+                    push_details(&mut out, &summary, |out| {
+                        push_fenced(out, &result.content, "")
+                    });
+                }
+                if !msg.text.trim().is_empty() {
+                    let _ = writeln!(out, "{}\n", msg.text.trim_end());
+                }
+            }
+            Payload::AssistantResponse(resp) => {
+                let _ = writeln!(
+                    out,
+                    "### Assistant · {} · {}\n",
+                    time,
+                    resp.model.as_deref().unwrap_or("-")
+                );
+                if let Some(ref thinking) = resp.thinking {
+                    push_details(&mut out, "Thinking", |out| {
+                        let _ = writeln!(out, "{}\n", thinking.trim_end());
+                    });
+                }
+                if let Some(ref text) = resp.text {
+                    let _ = writeln!(out, "{}\n", text.trim_end());
+                }
+                for call in &resp.tool_calls {
+                    tools.insert(&call.id, &call.name);
+                    let _ = writeln!(out, "**{}** (`{}`)\n", call.name, call.id);
+                    let input = serde_json::to_string_pretty(&call.input)
+                        .unwrap_or_else(|_| call.input.to_string());
+                    push_fenced(&mut out, &input, "json");
+                }
+            }
+            Payload::Alert(alert) => {
+                let _ = writeln!(out, "> **{}** · {}: {}\n", time, alert.kind, alert.message);
+            }
+            Payload::Error(error) => {
+                let _ = writeln!(out, "> **{}** · error: {}\n", time, error.message);
+            }
+            Payload::Violation(violation) => {
+                let _ = writeln!(
+                    out,
+                    "> **{}** · {}: {}{}",
+                    time,
+                    violation.source,
+                    violation.message,
+                    if violation.blocked { " (blocked)" } else { "" }
+                );
+                for mismatch in &violation.mismatches {
+                    let _ = writeln!(
+                        out,
+                        "> - `{}`: {} (found `{}`)",
+                        mismatch.path, mismatch.message, mismatch.found
+                    );
+                }
+                out.push('\n');
+            }
+            Payload::SessionClosed(closed) => {
+                let _ = writeln!(
+                    out,
+                    "> **{}** · session closed after {}m {}s, {} turns, ${:.4}\n",
+                    time,
+                    closed.duration_secs / 60,
+                    closed.duration_secs % 60,
+                    closed.turns,
+                    closed.cost_usd
+                );
+            }
+            Payload::StreamAborted(aborted) => {
+                let _ = writeln!(
+                    out,
+                    "> **{}** · client disconnected after {} bytes\n",
+                    time, aborted.received_bytes
+                );
+            }
+            Payload::TurnSnapshot(snapshot) => {
+                let _ = writeln!(
+                    out,
+                    "> **{}** · turn still running after {} ms, {} bytes received\n",
+                    time, snapshot.elapsed_ms, snapshot.received_bytes
+                );
+            }
+            Payload::Annotation(annotation) => {
+                let _ = writeln!(out, "> **{}** · ◆ {}\n", time, annotation.describe());
+            }
+            Payload::StreamDelta(_) => {}
+        }
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event::{AssistantResponse, UserMessage};
+    use crate::parsers::{ToolCall, ToolResult};
+
+    #[test]
+    fn test_render_front_matter_and_turns() {
+        let events = vec![
+            ObservabilityEvent::for_tests(1, Payload::UserMessage(UserMessage {
+                    model: Some("claude-sonnet-4".to_string()),
+                    text: "Fix the failing test".to_string(),
+                    ..Default::default()
+                }),
+            ),
+            ObservabilityEvent::for_tests(4, Payload::AssistantResponse(AssistantResponse {
+                    streaming: true,
+                    model: Some("claude-sonnet-4".to_string()),
+                    stop_reason: Some("tool_use".to_string()),
+                    thinking: Some("Run the tests first".to_string()),
+                    text: Some("Let me look.".to_string()),
+                    tool_calls: vec![ToolCall {
+                        id: "t1".to_string(),
+                        name: "Bash".to_string(),
+                        input: serde_json::json!({"command": "cargo test"}),
+                    }],
+                    ..Default::default()
+                }),
+            ),
+            ObservabilityEvent::for_tests(9, Payload::UserMessage(UserMessage {
+                    model: Some("claude-sonnet-4".to_string()),
+                    text: String::new(),
+                    tool_results: vec![ToolResult {
+                        tool_use_id: "t1".to_string(),
+                        content: "```\ntest result: ok\n```".to_string(),
+                        is_error: false,
+                    }],
+                    ..Default::default()
+                }),
+            ),
+        ];
+
+        let markdown = render(&events).unwrap();
+        let (matter, body) = markdown
+            .strip_prefix("---\n")
+            .and_then(|rest| rest.split_once("---\n"))
+            .unwrap();
+        let matter: serde_yaml::Value = serde_yaml::from_str(matter).unwrap();
+        assert_eq!(matter["agent"].as_str(), Some("calm-otter"));
+        assert_eq!(matter["turns"].as_i64(), Some(2));
+        assert_eq!(matter["models"][0].as_str(), Some("claude-sonnet-4"));
+
+        assert!(body.contains("### Assistant · 10:00:04 · claude-sonnet-4"));
+        assert!(body.contains("<summary>Thinking</summary>\n\nRun the tests first"));
+        assert!(body.contains("```json\n{\n  \"command\": \"cargo test\"\n}\n```"));
+        // The result's own fence can't end the block it sits in
+        assert!(body.contains("<summary>Bash result (<code>t1</code>)</summary>"));
+        assert!(body.contains("````\n```\ntest result: ok\n```\n````"));
+    }
+}
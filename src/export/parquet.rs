@@ -0,0 +1,379 @@
+//! Parquet files for analytical queries over long recording histories.
+//!
+//! Events are flattened into typed columns (agent, model, tokens, cost,
+//! latency, tool names) so DuckDB or Spark can scan them without parsing
+//! payload JSON. With a partition, the output is a directory laid out
+//! hive-style (`day=2026-01-05/part-0.parquet`), so partition columns can
+//! be pruned: `read_parquet('out/*/*.parquet', hive_partitioning = true)`.
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use arrow_array::builder::{
+    Float64Builder, Int64Builder, ListBuilder, StringBuilder, TimestampMicrosecondBuilder,
+};
+use arrow_array::{ArrayRef, RecordBatch};
+use arrow_schema::{DataType, Field, Schema, SchemaRef, TimeUnit};
+use clap::ValueEnum;
+use parquet::arrow::ArrowWriter;
+use parquet::basic::Compression;
+use parquet::file::properties::WriterProperties;
+
+use crate::event::{ErrorKind, ObservabilityEvent, Payload};
+use crate::pricing;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum PartitionBy {
+    Day,
+    Month,
+}
+
+impl PartitionBy {
+    fn key(&self, event: &ObservabilityEvent) -> String {
+        match self {
+            PartitionBy::Day => format!("day={}", event.timestamp.format("%Y-%m-%d")),
+            PartitionBy::Month => format!("month={}", event.timestamp.format("%Y-%m")),
+        }
+    }
+}
+
+fn schema() -> SchemaRef {
+    let utf8 = |name| Field::new(name, DataType::Utf8, true);
+    let int = |name| Field::new(name, DataType::Int64, true);
+    Arc::new(Schema::new(vec![
+        Field::new("seq", DataType::Int64, true),
+        Field::new("id", DataType::Utf8, false),
+        Field::new(
+            "timestamp",
+            DataType::Timestamp(TimeUnit::Microsecond, Some("UTC".into())),
+            false,
+        ),
+        utf8("session_id"),
+        utf8("agent"),
+        utf8("topic"),
+        utf8("provider"),
+        Field::new("type", DataType::Utf8, false),
+        utf8("model"),
+        utf8("stop_reason"),
+        int("input_tokens"),
+        int("output_tokens"),
+        int("cache_read_tokens"),
+        int("cache_creation_tokens"),
+        Field::new("cost_usd", DataType::Float64, true),
+        int("first_byte_ms"),
+        int("latency_ms"),
+        Field::new(
+            "tool_names",
+            DataType::List(Arc::new(Field::new("item", DataType::Utf8, true))),
+            true,
+        ),
+        utf8("kind"),
+        utf8("message"),
+    ]))
+}
+
+/// Writes events to one file, or to a file per partition
+pub struct ParquetWriter {
+    output: PathBuf,
+    partition_by: Option<PartitionBy>,
+    schema: SchemaRef,
+    /// The partition being written and its open file
+    current: Option<(String, ArrowWriter<File>)>,
+    /// Files started per partition, for naming the next one
+    parts: HashMap<String, usize>,
+    rows: usize,
+}
+
+impl ParquetWriter {
+    /// `output` is a file, or with a partition a directory to create
+    pub fn new(output: &Path, partition_by: Option<PartitionBy>) -> Self {
+        Self {
+            output: output.to_path_buf(),
+            partition_by,
+            schema: schema(),
+            current: None,
+            parts: HashMap::new(),
+            rows: 0,
+        }
+    }
+
+    /// Append events, which should arrive roughly in time order: a partition
+    /// seen again after another one was started gets a new part file
+    pub fn write(
+        &mut self,
+        events: &[ObservabilityEvent],
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let mut start = 0;
+        while start < events.len() {
+            let key = self
+                .partition_by
+                .map(|p| p.key(&events[start]))
+                .unwrap_or_default();
+            let len = events[start..]
+                .iter()
+                .take_while(|e| self.partition_by.map(|p| p.key(e)).unwrap_or_default() == key)
+                .count();
+            let batch = to_batch(&self.schema, &events[start..start + len])?;
+            self.writer_for(&key)?.write(&batch)?;
+            self.rows += len;
+            start += len;
+        }
+        Ok(())
+    }
+
+    /// Close the open file and return how many events were written
+    pub fn finish(mut self) -> Result<usize, Box<dyn std::error::Error>> {
+        if let Some((_, writer)) = self.current.take() {
+            writer.close()?;
+        }
+        Ok(self.rows)
+    }
+
+    fn writer_for(
+        &mut self,
+        key: &str,
+    ) -> Result<&mut ArrowWriter<File>, Box<dyn std::error::Error>> {
+        if self.current.as_ref().is_some_and(|(k, _)| k != key) {
+            if let Some((_, writer)) = self.current.take() {
+                writer.close()?;
+            }
+        }
+        if self.current.is_none() {
+            let path = match self.partition_by {
+                Some(_) => {
+                    let dir = self.output.join(key);
+                    std::fs::create_dir_all(&dir)?;
+                    let part = self.parts.entry(key.to_string()).or_insert(0);
+                    let path = dir.join(format!("part-{}.parquet", part));
+                    *part += 1;
+                    path
+                }
+                None => self.output.clone(),
+            };
+            let properties = WriterProperties::builder()
+                .set_compression(Compression::SNAPPY)
+                .build();
+            let writer =
+                ArrowWriter::try_new(File::create(&path)?, self.schema.clone(), Some(properties))?;
+            self.current = Some((key.to_string(), writer));
+        }
+        Ok(&mut self.current.as_mut().expect("a writer was just opened").1)
+    }
+}
+
+fn to_batch(
+    schema: &SchemaRef,
+    events: &[ObservabilityEvent],
+) -> Result<RecordBatch, arrow_schema::ArrowError> {
+    let mut seq = Int64Builder::new();
+    let mut id = StringBuilder::new();
+    let mut timestamp = TimestampMicrosecondBuilder::new().with_timezone("UTC");
+    let mut session_id = StringBuilder::new();
+    let mut agent = StringBuilder::new();
+    let mut topic = StringBuilder::new();
+    let mut provider = StringBuilder::new();
+    let mut event_type = StringBuilder::new();
+    let mut model = StringBuilder::new();
+    let mut stop_reason = StringBuilder::new();
+    let mut input_tokens = Int64Builder::new();
+    let mut output_tokens = Int64Builder::new();
+    let mut cache_read_tokens = Int64Builder::new();
+    let mut cache_creation_tokens = Int64Builder::new();
+    let mut cost_usd = Float64Builder::new();
+    let mut first_byte_ms = Int64Builder::new();
+    let mut latency_ms = Int64Builder::new();
+    let mut tool_names = ListBuilder::new(StringBuilder::new());
+    let mut kind = StringBuilder::new();
+    let mut message = StringBuilder::new();
+
+    for event in events {
+        seq.append_option(event.seq);
+        id.append_value(event.id.to_string());
+        timestamp.append_value(event.timestamp.timestamp_micros());
+        session_id.append_option(event.session_id.as_deref());
+        agent.append_option(event.agent.as_deref());
+        topic.append_option(event.topic.as_deref());
+        provider.append_option(event.provider.as_deref());
+
+        let response = match &event.payload {
+            Payload::AssistantResponse(r) => Some(r),
+            _ => None,
+        };
+        let usage = response.and_then(|r| r.usage.as_ref());
+        event_type.append_value(match &event.payload {
+            Payload::UserMessage(_) => "user_message",
+            Payload::AssistantResponse(_) => "assistant_response",
+            Payload::Alert(_) => "alert",
+            Payload::Error(_) => "error",
+            Payload::Violation(_) => "violation",
+            Payload::SessionClosed(_) => "session_closed",
+            Payload::StreamDelta(_) => "stream_delta",
+            Payload::StreamAborted(_) => "stream_aborted",
+            Payload::TurnSnapshot(_) => "turn_snapshot",
+            Payload::Annotation(_) => "annotation",
+        });
+        model.append_option(match &event.payload {
+            Payload::UserMessage(m) => m.model.as_deref(),
+            Payload::AssistantResponse(r) => r.model.as_deref(),
+            Payload::TurnSnapshot(t) => t.model.as_deref(),
+            Payload::Alert(_)
+            | Payload::Error(_)
+            | Payload::Violation(_)
+            | Payload::SessionClosed(_)
+            | Payload::StreamDelta(_)
+            | Payload::StreamAborted(_)
+            | Payload::Annotation(_) => None,
+        });
+        stop_reason.append_option(response.and_then(|r| r.stop_reason.as_deref()));
+        input_tokens.append_option(usage.and_then(|u| u.input_tokens));
+        output_tokens.append_option(usage.and_then(|u| u.output_tokens));
+        cache_read_tokens.append_option(usage.and_then(|u| u.cache_read_tokens));
+        cache_creation_tokens.append_option(usage.and_then(|u| u.cache_creation_tokens));
+        cost_usd.append_option(
+            response.and_then(|r| pricing::cost_usd(r.model.as_deref(), r.usage.as_ref()?)),
+        );
+        first_byte_ms.append_option(response.and_then(|r| r.first_byte_ms).map(|ms| ms as i64));
+        latency_ms.append_option(response.and_then(|r| r.latency_ms).map(|ms| ms as i64));
+        match response {
+            Some(r) => {
+                for call in &r.tool_calls {
+                    tool_names.values().append_value(&call.name);
+                }
+                tool_names.append(true);
+            }
+            None => tool_names.append(false),
+        }
+        let (event_kind, event_message) = match &event.payload {
+            Payload::Alert(a) => (Some(a.kind.clone()), Some(a.message.as_str())),
+            Payload::Error(e) => (
+                Some(match e.kind {
+                    ErrorKind::Forward => "forward".to_string(),
+                    ErrorKind::Upstream => "upstream".to_string(),
+                    ErrorKind::Interrupted => "interrupted".to_string(),
+                }),
+                Some(e.message.as_str()),
+            ),
+            Payload::Violation(v) => (Some(v.source.clone()), Some(v.message.as_str())),
+            Payload::Annotation(a) => (a.phase.clone(), a.message.as_deref()),
+            Payload::UserMessage(_)
+            | Payload::AssistantResponse(_)
+            | Payload::SessionClosed(_)
+            | Payload::StreamDelta(_)
+            | Payload::StreamAborted(_)
+            | Payload::TurnSnapshot(_) => (None, None),
+        };
+        kind.append_option(event_kind);
+        message.append_option(event_message);
+    }
+
+    let columns: Vec<ArrayRef> = vec![
+        Arc::new(seq.finish()),
+        Arc::new(id.finish()),
+        Arc::new(timestamp.finish()),
+        Arc::new(session_id.finish()),
+        Arc::new(agent.finish()),
+        Arc::new(topic.finish()),
+        Arc::new(provider.finish()),
+        Arc::new(event_type.finish()),
+        Arc::new(model.finish()),
+        Arc::new(stop_reason.finish()),
+        Arc::new(input_tokens.finish()),
+        Arc::new(output_tokens.finish()),
+        Arc::new(cache_read_tokens.finish()),
+        Arc::new(cache_creation_tokens.finish()),
+        Arc::new(cost_usd.finish()),
+        Arc::new(first_byte_ms.finish()),
+        Arc::new(latency_ms.finish()),
+        Arc::new(tool_names.finish()),
+        Arc::new(kind.finish()),
+        Arc::new(message.finish()),
+    ];
+    RecordBatch::try_new(schema.clone(), columns)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event::{Alert, AssistantResponse};
+    use crate::parsers::{ToolCall, Usage};
+    use arrow_array::Array;
+    use chrono::{TimeZone, Utc};
+    use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+    use uuid::Uuid;
+
+    fn event(day: u32, payload: Payload) -> ObservabilityEvent {
+        ObservabilityEvent {
+            seq: Some(day as i64),
+            timestamp: Utc.with_ymd_and_hms(2026, 1, day, 10, 0, 0).unwrap(),
+            ..ObservabilityEvent::for_tests(0, payload)
+        }
+    }
+
+    #[test]
+    fn test_partitions_by_day_with_typed_columns() {
+        let response = Payload::AssistantResponse(AssistantResponse {
+            streaming: true,
+            model: Some("claude-sonnet-4".to_string()),
+            message_id: None,
+            stop_reason: Some("tool_use".to_string()),
+            thinking: None,
+            text: None,
+            tool_calls: vec![ToolCall {
+                id: "t1".to_string(),
+                name: "Bash".to_string(),
+                input: serde_json::json!({}),
+            }],
+            usage: Some(Usage {
+                input_tokens: Some(1200),
+                output_tokens: Some(40),
+                cache_read_tokens: None,
+                cache_creation_tokens: None,
+                cost_usd: Some(0.01),
+            }),
+            first_byte_ms: Some(300),
+            first_token_ms: None,
+            latency_ms: Some(900),
+            idempotency_key: None,
+            request_id: None,
+            redo_of: None,
+            upstream_key: None,
+            project: None,
+            run_id: None,
+        });
+        let alert = Payload::Alert(Alert {
+            kind: "slo_burn".to_string(),
+            message: "burning".to_string(),
+        });
+        let dir = std::env::temp_dir().join(format!("sentinel-parquet-{}", Uuid::new_v4()));
+
+        let mut writer = ParquetWriter::new(&dir, Some(PartitionBy::Day));
+        writer
+            .write(&[event(5, response.clone()), event(5, alert)])
+            .unwrap();
+        writer.write(&[event(6, response)]).unwrap();
+        assert_eq!(writer.finish().unwrap(), 3);
+
+        let file = File::open(dir.join("day=2026-01-05/part-0.parquet")).unwrap();
+        let batch = ParquetRecordBatchReaderBuilder::try_new(file)
+            .unwrap()
+            .build()
+            .unwrap()
+            .next()
+            .unwrap()
+            .unwrap();
+        assert_eq!(batch.num_rows(), 2);
+        let tokens = batch
+            .column_by_name("input_tokens")
+            .unwrap()
+            .as_any()
+            .downcast_ref::<arrow_array::Int64Array>()
+            .unwrap();
+        assert_eq!(tokens.value(0), 1200);
+        assert!(tokens.is_null(1));
+        assert!(dir.join("day=2026-01-06/part-0.parquet").exists());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
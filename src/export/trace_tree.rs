@@ -0,0 +1,299 @@
+//! A session as a tree of runs, the shape LLM tracing tools expect.
+//!
+//! The session is the root. Under it, each exchange is a model call from its
+//! request to its response, and each tool call runs from the response that
+//! asked for it to the request that carried its result back. Run ids come from
+//! event ids, so exporting a session again produces the same ids.
+
+use std::collections::HashMap;
+
+use chrono::{DateTime, Utc};
+use serde_json::{json, Value};
+use uuid::Uuid;
+
+use crate::event::{ObservabilityEvent, Payload};
+use crate::parsers::Usage;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RunKind {
+    Session,
+    Llm,
+    Tool,
+}
+
+#[derive(Debug)]
+pub struct TraceRun {
+    pub id: Uuid,
+    pub parent: Option<Uuid>,
+    pub kind: RunKind,
+    pub name: String,
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+    pub inputs: Value,
+    pub outputs: Value,
+    pub error: Option<String>,
+    pub model: Option<String>,
+    pub provider: Option<String>,
+    pub usage: Option<Usage>,
+}
+
+#[derive(Debug)]
+pub struct SessionTrace {
+    pub session_id: String,
+    pub agent: Option<String>,
+    /// The session's root run first, then the rest in the order they started
+    pub runs: Vec<TraceRun>,
+}
+
+impl SessionTrace {
+    pub fn root(&self) -> &TraceRun {
+        &self.runs[0]
+    }
+}
+
+/// An id derived from an event's, for runs that have no event of their own
+fn derived_id(id: Uuid, n: u128) -> Uuid {
+    let n = n + 1;
+    let bits = id.as_u128() ^ (n << 64) ^ n;
+    uuid::Builder::from_random_bytes(bits.to_be_bytes()).into_uuid()
+}
+
+/// One session's events as a trace; `None` when it holds no exchanges
+pub fn build(events: &[ObservabilityEvent]) -> Option<SessionTrace> {
+    let first = events.first()?;
+    let root_id = derived_id(first.id, 0);
+    let mut runs = Vec::new();
+    // Unanswered requests by event id, and the latest one, for responses
+    // recorded before they named their request
+    let mut requests: HashMap<Uuid, &ObservabilityEvent> = HashMap::new();
+    let mut latest_request: Option<&ObservabilityEvent> = None;
+    // Tool runs still waiting for their result, by tool_use_id
+    let mut open_tools: HashMap<String, usize> = HashMap::new();
+
+    for event in events {
+        match &event.payload {
+            Payload::UserMessage(message) => {
+                for result in &message.tool_results {
+                    if let Some(i) = open_tools.remove(&result.tool_use_id) {
+                        let run: &mut TraceRun = &mut runs[i];
+                        run.end = event.timestamp;
+                        run.outputs = json!({ "output": result.content });
+                        if result.is_error {
+                            run.error = Some(result.content.clone());
+                        }
+                    }
+                }
+                requests.insert(event.id, event);
+                latest_request = Some(event);
+            }
+            Payload::AssistantResponse(response) => {
+                let by_id = response.request_id.and_then(|id| requests.remove(&id));
+                let request = by_id.or(latest_request.take());
+                let outputs = json!({
+                    "text": response.text,
+                    "thinking": response.thinking,
+                    "tool_calls": response.tool_calls,
+                    "stop_reason": response.stop_reason,
+                });
+                runs.push(llm_run(
+                    root_id,
+                    event,
+                    request,
+                    outputs,
+                    None,
+                    response.model.clone(),
+                    response.usage.clone(),
+                ));
+                for (n, call) in response.tool_calls.iter().enumerate() {
+                    open_tools.insert(call.id.clone(), runs.len());
+                    runs.push(TraceRun {
+                        id: derived_id(event.id, n as u128 + 1),
+                        parent: Some(root_id),
+                        kind: RunKind::Tool,
+                        name: call.name.clone(),
+                        start: event.timestamp,
+                        end: event.timestamp,
+                        inputs: call.input.clone(),
+                        outputs: Value::Null,
+                        error: None,
+                        model: None,
+                        provider: None,
+                        usage: None,
+                    });
+                }
+            }
+            Payload::Error(error) => {
+                let request = latest_request.take();
+                let model = request.and_then(|r| match &r.payload {
+                    Payload::UserMessage(message) => message.model.clone(),
+                    _ => None,
+                });
+                runs.push(llm_run(
+                    root_id,
+                    event,
+                    request,
+                    Value::Null,
+                    Some(error.message.clone()),
+                    model,
+                    None,
+                ));
+            }
+            _ => {}
+        }
+    }
+    if runs.is_empty() {
+        return None;
+    }
+
+    let last = events.last().unwrap_or(first);
+    let prompt = events.iter().find_map(|e| match &e.payload {
+        Payload::UserMessage(message) if !message.text.is_empty() => Some(message.text.clone()),
+        _ => None,
+    });
+    let answer = events.iter().rev().find_map(|e| match &e.payload {
+        Payload::AssistantResponse(response) => response.text.clone(),
+        _ => None,
+    });
+    let session_id = first
+        .session_id
+        .clone()
+        .or_else(|| first.agent.clone())
+        .unwrap_or_default();
+    runs.insert(
+        0,
+        TraceRun {
+            id: root_id,
+            parent: None,
+            kind: RunKind::Session,
+            name: first.agent.clone().unwrap_or_else(|| "session".to_string()),
+            start: first.timestamp,
+            end: last.timestamp,
+            inputs: json!({ "session_id": session_id, "prompt": prompt }),
+            outputs: json!({ "text": answer }),
+            error: None,
+            model: None,
+            provider: first.provider.clone(),
+            usage: None,
+        },
+    );
+    Some(SessionTrace {
+        session_id,
+        agent: first.agent.clone(),
+        runs,
+    })
+}
+
+/// A model call from its request (when recorded) to its response or error
+fn llm_run(
+    root_id: Uuid,
+    event: &ObservabilityEvent,
+    request: Option<&ObservabilityEvent>,
+    outputs: Value,
+    error: Option<String>,
+    model: Option<String>,
+    usage: Option<Usage>,
+) -> TraceRun {
+    let inputs = match request.map(|r| &r.payload) {
+        Some(Payload::UserMessage(message)) => json!({
+            "messages": [{"role": "user", "content": message.text}],
+            "tool_results": message.tool_results,
+        }),
+        _ => Value::Null,
+    };
+    TraceRun {
+        id: event.id,
+        parent: Some(root_id),
+        kind: RunKind::Llm,
+        name: model.clone().unwrap_or_else(|| "model".to_string()),
+        start: request.map_or(event.timestamp, |r| r.timestamp),
+        end: event.timestamp,
+        inputs,
+        outputs,
+        error,
+        model,
+        provider: event.provider.clone(),
+        usage,
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::event::{AssistantResponse, UserMessage};
+    use crate::parsers::{ToolCall, ToolResult};
+
+    fn event(seconds: i64, payload: Payload) -> ObservabilityEvent {
+        ObservabilityEvent {
+            timestamp: DateTime::from_timestamp(1_700_000_000 + seconds, 0).unwrap(),
+            ..ObservabilityEvent::for_tests(0, payload)
+        }
+    }
+
+    fn request(text: &str, tool_results: Vec<ToolResult>) -> Payload {
+        Payload::UserMessage(UserMessage {
+            model: Some("claude-sonnet-4".to_string()),
+            text: text.to_string(),
+            tool_results,
+            ..Default::default()
+        })
+    }
+
+    fn response(text: &str, tool_calls: Vec<ToolCall>) -> Payload {
+        Payload::AssistantResponse(AssistantResponse {
+            streaming: true,
+            model: Some("claude-sonnet-4".to_string()),
+            text: Some(text.to_string()),
+            tool_calls,
+            ..Default::default()
+        })
+    }
+
+    /// A prompt, a Bash call and its result, and the answer
+    pub(crate) fn session() -> Vec<ObservabilityEvent> {
+        let call = ToolCall {
+            id: "t1".to_string(),
+            name: "Bash".to_string(),
+            input: json!({"command": "ls"}),
+        };
+        let result = ToolResult {
+            tool_use_id: "t1".to_string(),
+            content: "src".to_string(),
+            is_error: false,
+        };
+        vec![
+            event(0, request("list files", vec![])),
+            event(2, response("Looking.", vec![call])),
+            event(5, request("", vec![result])),
+            event(6, response("There's src.", vec![])),
+        ]
+    }
+
+    #[test]
+    fn test_session_becomes_a_tree_of_runs() {
+        let events = session();
+        let trace = build(&events).unwrap();
+        let kinds: Vec<RunKind> = trace.runs.iter().map(|r| r.kind).collect();
+        assert_eq!(
+            kinds,
+            [RunKind::Session, RunKind::Llm, RunKind::Tool, RunKind::Llm]
+        );
+        let root = trace.root();
+        assert_eq!(root.name, "calm-otter");
+        assert_eq!(root.inputs["prompt"], "list files");
+        assert_eq!(root.outputs["text"], "There's src.");
+        assert!(trace.runs[1..].iter().all(|r| r.parent == Some(root.id)));
+
+        let llm = &trace.runs[1];
+        assert_eq!(llm.id, events[1].id);
+        assert_eq!((llm.end - llm.start).num_seconds(), 2);
+        // The tool ran until its result was sent back
+        let tool = &trace.runs[2];
+        assert_eq!(tool.name, "Bash");
+        assert_eq!((tool.end - tool.start).num_seconds(), 3);
+        assert_eq!(tool.outputs["output"], "src");
+
+        // Run ids don't change from one export to the next
+        assert_eq!(build(&events).unwrap().runs[2].id, tool.id);
+        assert!(build(&events[..1]).is_none());
+    }
+}
@@ -0,0 +1,503 @@
+//! File changes in their own table, and `sentinel files`.
+//!
+//! Each Write, Edit, MultiEdit, and NotebookEdit call in a stored response
+//! gets a `file_changes` row in the same transaction as the event, with the
+//! path, the operation, and how many bytes and lines it adds and removes as
+//! far as the call's input tells. Who made it, when, and whether it was
+//! applied are on the call's `tool_calls` row, read alongside; a Write whose
+//! result there says it created the file is shown as a `create`.
+
+use std::path::Path;
+
+use chrono::{DateTime, Duration, Utc};
+use sqlx::{SqliteConnection, SqlitePool};
+
+use crate::derived;
+use crate::event::{ObservabilityEvent, Payload};
+use crate::parsers::ToolCall;
+use crate::storage::Storage;
+
+/// A change's operation, made a `create` when its Write's result (in `t`)
+/// says it made a new file, as Claude Code's "File created successfully" does
+const OPERATION: &str = "CASE WHEN f.operation = 'write' AND NOT t.failed \
+    AND t.result GLOB 'File created successfully*' THEN 'create' ELSE f.operation END";
+/// Changes with their calls
+const CHANGES: &str =
+    "file_changes f JOIN tool_calls t ON t.event_id = f.event_id AND t.tool_use_id = f.tool_use_id";
+
+/// One file change read from a tool call's input
+#[derive(Debug, PartialEq)]
+pub struct FileChange {
+    pub path: String,
+    /// "write", "edit", or "notebook_edit"; "create" once a write's result
+    /// says the file is new
+    pub operation: &'static str,
+    pub bytes_added: i64,
+    pub bytes_removed: i64,
+    pub lines_added: i64,
+    pub lines_removed: i64,
+}
+
+/// Lines added and removed going from `old` to `new`, leaving out the lines
+/// they start and end with in common
+fn line_delta(old: &str, new: &str) -> (i64, i64) {
+    let old: Vec<&str> = old.lines().collect();
+    let new: Vec<&str> = new.lines().collect();
+    let prefix = old.iter().zip(&new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    (
+        (new.len() - prefix - suffix) as i64,
+        (old.len() - prefix - suffix) as i64,
+    )
+}
+
+fn text<'a>(value: &'a serde_json::Value, field: &str) -> &'a str {
+    value.get(field).and_then(|v| v.as_str()).unwrap_or("")
+}
+
+/// The change a file-editing tool call makes; None for other tools. A Write
+/// replaces what was there, which its input doesn't say, so it only adds.
+pub fn file_change(call: &ToolCall) -> Option<FileChange> {
+    let input = &call.input;
+    let mut change = FileChange {
+        path: String::new(),
+        operation: "edit",
+        bytes_added: 0,
+        bytes_removed: 0,
+        lines_added: 0,
+        lines_removed: 0,
+    };
+    let mut edit = |old: &str, new: &str| {
+        let (added, removed) = line_delta(old, new);
+        change.bytes_added += new.len() as i64;
+        change.bytes_removed += old.len() as i64;
+        change.lines_added += added;
+        change.lines_removed += removed;
+    };
+    match call.name.as_str() {
+        "Write" => {
+            let content = text(input, "content");
+            change.operation = "write";
+            change.bytes_added = content.len() as i64;
+            change.lines_added = content.lines().count() as i64;
+        }
+        "Edit" => edit(text(input, "old_string"), text(input, "new_string")),
+        "MultiEdit" => {
+            for step in input.get("edits")?.as_array()? {
+                edit(text(step, "old_string"), text(step, "new_string"));
+            }
+        }
+        "NotebookEdit" => {
+            change.operation = "notebook_edit";
+            if text(input, "edit_mode") != "delete" {
+                let source = text(input, "new_source");
+                change.bytes_added = source.len() as i64;
+                change.lines_added = source.lines().count() as i64;
+            }
+        }
+        _ => return None,
+    }
+    let path = ["file_path", "notebook_path"]
+        .iter()
+        .map(|field| text(input, field))
+        .find(|path| !path.is_empty())?;
+    change.path = path.to_string();
+    Some(change)
+}
+
+/// Record the file changes of an event's tool calls; part of the event's
+/// insert transaction. Their results are recorded on `tool_calls`.
+pub async fn record(
+    conn: &mut SqliteConnection,
+    event: &ObservabilityEvent,
+) -> Result<(), sqlx::Error> {
+    let Payload::AssistantResponse(ref response) = event.payload else {
+        return Ok(());
+    };
+    for call in &response.tool_calls {
+        // Calls read from OTLP logs may carry no id to find their result by
+        if call.id.is_empty() {
+            continue;
+        }
+        let Some(change) = file_change(call) else {
+            continue;
+        };
+        sqlx::query(
+            r#"
+            INSERT OR IGNORE INTO file_changes
+                (event_id, tool_use_id, path, operation,
+                 bytes_added, bytes_removed, lines_added, lines_removed)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?)
+            "#,
+        )
+        .bind(event.id.to_string())
+        .bind(&call.id)
+        .bind(&change.path)
+        .bind(change.operation)
+        .bind(change.bytes_added)
+        .bind(change.bytes_removed)
+        .bind(change.lines_added)
+        .bind(change.lines_removed)
+        .execute(&mut *conn)
+        .await?;
+    }
+    Ok(())
+}
+
+/// Changes an agent made to one file; the deltas leave out failed changes
+#[derive(Debug, sqlx::FromRow)]
+pub struct FileSummary {
+    pub path: String,
+    pub changes: i64,
+    pub lines_added: i64,
+    pub lines_removed: i64,
+    pub bytes_added: i64,
+    pub bytes_removed: i64,
+    /// Changes whose result reported an error
+    pub failed: i64,
+    /// Whether one of the writes created it
+    pub created: bool,
+    pub last_changed: String,
+}
+
+/// One recorded change
+#[derive(Debug, sqlx::FromRow)]
+pub struct FileChangeRow {
+    pub session_id: Option<String>,
+    pub path: String,
+    pub operation: String,
+    pub lines_added: i64,
+    pub lines_removed: i64,
+    pub bytes_added: i64,
+    pub bytes_removed: i64,
+    pub timestamp: String,
+    /// None until the call's result comes back
+    pub failed: Option<bool>,
+}
+
+#[derive(Clone)]
+pub struct FileChangeStore {
+    pool: SqlitePool,
+}
+
+impl FileChangeStore {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool }
+    }
+
+    /// Create the table, rebuilding one that still copied its calls' columns;
+    /// true when it has to be filled from the edits recorded
+    pub async fn init_schema(&self) -> Result<bool, sqlx::Error> {
+        derived::create(
+            &self.pool,
+            "file_changes",
+            r#"
+            CREATE TABLE IF NOT EXISTS file_changes (
+                event_id TEXT NOT NULL,
+                tool_use_id TEXT NOT NULL,
+                path TEXT NOT NULL,
+                operation TEXT NOT NULL,
+                bytes_added INTEGER NOT NULL,
+                bytes_removed INTEGER NOT NULL,
+                lines_added INTEGER NOT NULL,
+                lines_removed INTEGER NOT NULL,
+                PRIMARY KEY (event_id, tool_use_id)
+            )
+            "#,
+            |columns| columns.iter().any(|column| column == "agent"),
+        )
+        .await
+    }
+
+    /// Every file the agent changed at or after `since`, latest first
+    pub async fn summary(
+        &self,
+        agent: &str,
+        since: DateTime<Utc>,
+    ) -> Result<Vec<FileSummary>, sqlx::Error> {
+        sqlx::query_as(&format!(
+            r#"
+            SELECT f.path,
+                   COUNT(*) AS changes,
+                   SUM(IIF(t.failed, 0, f.lines_added)) AS lines_added,
+                   SUM(IIF(t.failed, 0, f.lines_removed)) AS lines_removed,
+                   SUM(IIF(t.failed, 0, f.bytes_added)) AS bytes_added,
+                   SUM(IIF(t.failed, 0, f.bytes_removed)) AS bytes_removed,
+                   COALESCE(SUM(t.failed), 0) AS failed,
+                   MAX({} = 'create') AS created,
+                   MAX(t.timestamp) AS last_changed
+            FROM {}
+            WHERE t.agent = ? AND t.timestamp >= ?
+            GROUP BY f.path
+            ORDER BY last_changed DESC
+            "#,
+            OPERATION, CHANGES
+        ))
+        .bind(agent)
+        .bind(since.to_rfc3339())
+        .fetch_all(&self.pool)
+        .await
+    }
+
+    /// Each change the agent made at or after `since`, in order
+    pub async fn changes(
+        &self,
+        agent: &str,
+        since: DateTime<Utc>,
+    ) -> Result<Vec<FileChangeRow>, sqlx::Error> {
+        sqlx::query_as(&format!(
+            r#"
+            SELECT t.session_id, f.path, {} AS operation, f.lines_added, f.lines_removed,
+                   f.bytes_added, f.bytes_removed, t.timestamp, t.failed
+            FROM {}
+            WHERE t.agent = ? AND t.timestamp >= ?
+            ORDER BY t.timestamp, t.rowid
+            "#,
+            OPERATION, CHANGES
+        ))
+        .bind(agent)
+        .bind(since.to_rfc3339())
+        .fetch_all(&self.pool)
+        .await
+    }
+}
+
+/// A path shown relative to the agent's working directory when it's inside
+fn display_path(path: &str, working_directory: Option<&str>) -> String {
+    working_directory
+        .and_then(|dir| Path::new(path).strip_prefix(dir).ok())
+        .filter(|rest| !rest.as_os_str().is_empty())
+        .map(|rest| rest.display().to_string())
+        .unwrap_or_else(|| path.to_string())
+}
+
+fn time(timestamp: &str) -> String {
+    DateTime::parse_from_rfc3339(timestamp)
+        .map(|t| t.format("%Y-%m-%d %H:%M:%S").to_string())
+        .unwrap_or_else(|_| timestamp.to_string())
+}
+
+pub async fn run(
+    data_dir: &Path,
+    agent: &str,
+    since: Duration,
+    log: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let db_path = data_dir.join("sentinel.db");
+    if !db_path.exists() {
+        println!("No events found. Run 'sentinel start' first to capture some traffic.");
+        return Ok(());
+    }
+    let storage = Storage::new(&db_path).await?;
+    let agents = crate::agent::AgentStore::new(storage.pool());
+    agents.init_schema().await?;
+    let working_directory = agents
+        .find_by_name(agent)
+        .await?
+        .and_then(|agent| agent.working_directory);
+    let dir = working_directory.as_deref();
+    let store = FileChangeStore::new(storage.pool());
+    let from = Utc::now() - since;
+
+    if log {
+        let changes = store.changes(agent, from).await?;
+        if changes.is_empty() {
+            println!(
+                "{} changed no files since {}.",
+                agent,
+                from.format("%Y-%m-%d %H:%M")
+            );
+            return Ok(());
+        }
+        let mut session: Option<&str> = None;
+        for change in &changes {
+            if change.session_id.as_deref() != session {
+                session = change.session_id.as_deref();
+                println!("\nSession {}", session.unwrap_or("(none)"));
+            }
+            let outcome = match change.failed {
+                Some(true) => " (failed)",
+                Some(false) => "",
+                None => " (pending)",
+            };
+            println!(
+                "  {}  {:<13} {:>6} {:>6} {:>9}  {}{}",
+                time(&change.timestamp),
+                change.operation,
+                format!("+{}", change.lines_added),
+                format!("-{}", change.lines_removed),
+                format!("{:+}B", change.bytes_added - change.bytes_removed),
+                display_path(&change.path, dir),
+                outcome
+            );
+        }
+        return Ok(());
+    }
+
+    let files = store.summary(agent, from).await?;
+    if files.is_empty() {
+        println!(
+            "{} changed no files since {}.",
+            agent,
+            from.format("%Y-%m-%d %H:%M")
+        );
+        return Ok(());
+    }
+    if let Some(dir) = dir {
+        println!("Files changed by {} (in {})\n", agent, dir);
+    } else {
+        println!("Files changed by {}\n", agent);
+    }
+    println!(
+        "{:<48} {:>7} {:>7} {:>7} {:>10} {:>6}  last changed",
+        "file", "changes", "+lines", "-lines", "+/-bytes", "failed"
+    );
+    for file in &files {
+        let mut path = display_path(&file.path, dir);
+        if file.created {
+            path.push_str(" (new)");
+        }
+        println!(
+            "{:<48} {:>7} {:>7} {:>7} {:>10} {:>6}  {}",
+            path,
+            file.changes,
+            file.lines_added,
+            file.lines_removed,
+            format!("{:+}", file.bytes_added - file.bytes_removed),
+            file.failed,
+            time(&file.last_changed)
+        );
+    }
+    println!("\n({} files)", files.len());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event::{AssistantResponse, UserMessage};
+    use crate::parsers::ToolResult;
+    use chrono::TimeZone;
+    use serde_json::json;
+    use uuid::Uuid;
+
+    fn calls(calls: &[(&str, &str, serde_json::Value)]) -> Payload {
+        Payload::AssistantResponse(AssistantResponse {
+            streaming: true,
+            model: Some("claude-sonnet-4".to_string()),
+            stop_reason: Some("tool_use".to_string()),
+            tool_calls: calls
+                .iter()
+                .map(|(id, name, input)| ToolCall {
+                    id: id.to_string(),
+                    name: name.to_string(),
+                    input: input.clone(),
+                })
+                .collect(),
+            ..Default::default()
+        })
+    }
+
+    fn results(outcomes: &[(&str, &str, bool)]) -> Payload {
+        Payload::UserMessage(UserMessage {
+            text: String::new(),
+            tool_results: outcomes
+                .iter()
+                .map(|(id, content, is_error)| ToolResult {
+                    tool_use_id: id.to_string(),
+                    content: content.to_string(),
+                    is_error: *is_error,
+                })
+                .collect(),
+            ..Default::default()
+        })
+    }
+
+    #[tokio::test]
+    async fn test_ledger_records_changes_and_their_outcomes() {
+        assert_eq!(line_delta("a\nb\nc\n", "a\nB\nB2\nc\n"), (2, 1));
+        assert_eq!(line_delta("", "x\ny"), (2, 0));
+
+        let path = std::env::temp_dir().join(format!("sentinel-test-{}.db", Uuid::new_v4()));
+        let storage = Storage::new(&path).await.unwrap();
+        for (secs, payload) in [
+            (
+                1,
+                calls(&[
+                    (
+                        "t1",
+                        "Write",
+                        json!({"file_path": "/w/src/new.rs", "content": "fn a() {}\nfn b() {}\n"}),
+                    ),
+                    ("t2", "Read", json!({"file_path": "/w/src/lib.rs"})),
+                    ("", "Write", json!({"file_path": "/w/otlp.rs", "content": "x"})),
+                    (
+                        "t3",
+                        "Edit",
+                        json!({"file_path": "/w/src/lib.rs", "old_string": "x", "new_string": "y\nz"}),
+                    ),
+                ]),
+            ),
+            (
+                2,
+                results(&[
+                    ("t1", "File created successfully at: /w/src/new.rs", false),
+                    ("t2", "...", false),
+                    ("t3", "String to replace not found", true),
+                ]),
+            ),
+            (
+                3,
+                calls(&[(
+                    "t4",
+                    "MultiEdit",
+                    json!({"file_path": "/w/src/lib.rs", "edits": [
+                        {"old_string": "one", "new_string": "uno"},
+                        {"old_string": "two\nthree", "new_string": ""}
+                    ]}),
+                )]),
+            ),
+        ] {
+            let event = ObservabilityEvent::for_tests(secs, payload);
+            storage.insert_observability_event(&event).await.unwrap();
+        }
+
+        let store = FileChangeStore::new(storage.pool());
+        let since = Utc.with_ymd_and_hms(2026, 1, 1, 0, 0, 0).unwrap();
+        let files = store.summary("calm-otter", since).await.unwrap();
+        let lib = &files[0];
+        assert_eq!(lib.path, "/w/src/lib.rs");
+        assert_eq!((lib.changes, lib.failed, lib.created), (2, 1, false));
+        // The failed edit changed nothing
+        assert_eq!((lib.lines_added, lib.lines_removed), (1, 3));
+        assert_eq!((lib.bytes_added, lib.bytes_removed), (3, 12));
+        assert_eq!(files.len(), 2);
+        let new = &files[1];
+        assert_eq!(
+            (new.lines_added, new.bytes_added, new.created),
+            (2, 20, true)
+        );
+
+        let changes = store.changes("calm-otter", since).await.unwrap();
+        let log: Vec<(&str, Option<bool>)> = changes
+            .iter()
+            .map(|c| (c.operation.as_str(), c.failed))
+            .collect();
+        assert_eq!(
+            log,
+            [
+                ("create", Some(false)),
+                ("edit", Some(true)),
+                ("edit", None)
+            ]
+        );
+        assert_eq!(display_path("/w/src/lib.rs", Some("/w")), "src/lib.rs");
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,158 @@
+//! `sentinel fixtures <session> --out <dir>`: a session's exchanges as test
+//! fixtures for other projects.
+//!
+//! Each prompt whose request and response were recorded in full (start with
+//! `--full-fidelity`) is written in WireMock's layout: the bodies under
+//! `__files/` (`001-request.json`, `001-response.sse` or `.json`) and a stub
+//! under `mappings/` answering a `POST /v1/messages` with an equal body by
+//! the recorded response. Libraries without WireMock's format, like Rust's
+//! `wiremock`, can load the body files directly.
+//!
+//! Bodies are sanitized on the way out: `metadata` (which names the user and
+//! session) is dropped from requests, and every string runs through the
+//! built-in `--redact` detectors plus any `--redact-rules` bundle. A live
+//! request still carrying a redacted secret won't equal its stub's body.
+
+use std::path::Path;
+
+use serde_json::Value;
+
+use crate::redact::{self, Redactor};
+use crate::replay::{self, Exchange};
+use crate::storage::Storage;
+
+/// A JSON body with every string redacted, pretty-printed; other text
+/// redacted as it is
+fn sanitize_json(body: &str, redactor: &Redactor, drop: &[&str]) -> (Option<Value>, String) {
+    match serde_json::from_str::<Value>(body) {
+        Ok(mut value) => {
+            if let Some(fields) = value.as_object_mut() {
+                for field in drop {
+                    fields.remove(*field);
+                }
+            }
+            redact::rewrite_json(&mut value, &|s| redactor.text(s));
+            let pretty = serde_json::to_string_pretty(&value).unwrap_or_default();
+            (Some(value), pretty + "\n")
+        }
+        Err(_) => (None, redactor.text(body)),
+    }
+}
+
+/// The files for the `n`th exchange, by path under the output dir
+fn fixture(n: usize, exchange: &Exchange, redactor: &Redactor) -> Vec<(String, String)> {
+    let (request, request_body) = sanitize_json(&exchange.request, redactor, &["metadata"]);
+    let (response_file, content_type, response_body) = if exchange.streaming {
+        // Events keep their framing; redaction markers hold no quotes
+        let body = redactor.text(&exchange.response);
+        (format!("{:03}-response.sse", n), "text/event-stream", body)
+    } else {
+        let (_, body) = sanitize_json(&exchange.response, redactor, &[]);
+        (format!("{:03}-response.json", n), "application/json", body)
+    };
+
+    let mut matcher = serde_json::json!({
+        "method": "POST",
+        "urlPath": "/v1/messages",
+    });
+    if let Some(request) = request {
+        matcher["bodyPatterns"] = serde_json::json!([{
+            "equalToJson": request,
+            "ignoreExtraElements": true,
+        }]);
+    }
+    let mapping = serde_json::json!({
+        "name": format!("sentinel exchange {}", n),
+        "request": matcher,
+        "response": {
+            "status": 200,
+            "headers": {"content-type": content_type},
+            "bodyFileName": response_file,
+        },
+    });
+
+    vec![
+        (format!("__files/{:03}-request.json", n), request_body),
+        (format!("__files/{}", response_file), response_body),
+        (
+            format!("mappings/{:03}.json", n),
+            serde_json::to_string_pretty(&mapping).unwrap_or_default() + "\n",
+        ),
+    ]
+}
+
+pub async fn run(
+    data_dir: &Path,
+    session: &str,
+    out: &Path,
+    redact_rules: Option<&Path>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let db_path = data_dir.join("sentinel.db");
+    if !db_path.exists() {
+        return Err("No recorded traffic. Run 'sentinel start' first.".into());
+    }
+    let redactor = Redactor::new(true, &[], redact_rules)?;
+    let storage = Storage::new(&db_path).await?;
+    let (exchanges, skipped) = replay::exchanges(&storage, session).await?;
+
+    std::fs::create_dir_all(out.join("__files"))?;
+    std::fs::create_dir_all(out.join("mappings"))?;
+    for (n, exchange) in exchanges.iter().enumerate() {
+        for (path, contents) in fixture(n + 1, exchange, &redactor) {
+            std::fs::write(out.join(path), contents)?;
+        }
+    }
+    println!(
+        "Wrote {} exchanges from '{}' to {}",
+        exchanges.len(),
+        session,
+        out.display()
+    );
+    if skipped > 0 {
+        println!(
+            "Skipped {} without full, unencrypted bodies (record with --full-fidelity)",
+            skipped
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fixtures_are_sanitized_wiremock_stubs() {
+        let redactor = Redactor::new(true, &[], None).unwrap();
+        let exchange = Exchange {
+            request: serde_json::json!({
+                "model": "claude-sonnet-4",
+                "metadata": {"user_id": "u_session_calm-otter"},
+                "messages": [{"role": "user", "content": "mail alice@example.com the key sk-ant-REDACTED"}],
+            })
+            .to_string(),
+            response: "event: content_block_delta\ndata: {\"delta\":{\"text\":\"Sent to alice@example.com\"}}\n\n".to_string(),
+            streaming: true,
+        };
+        let files = fixture(7, &exchange, &redactor);
+        let paths: Vec<&str> = files.iter().map(|(p, _)| p.as_str()).collect();
+        assert_eq!(
+            paths,
+            ["__files/007-request.json", "__files/007-response.sse", "mappings/007.json"]
+        );
+        let everything: String = files.iter().map(|(_, c)| c.as_str()).collect();
+        assert!(!everything.contains("alice@") && !everything.contains("sk-ant"));
+        assert!(!everything.contains("calm-otter"));
+        assert_eq!(
+            files[1].1,
+            "event: content_block_delta\ndata: {\"delta\":{\"text\":\"Sent to [REDACTED:email]\"}}\n\n"
+        );
+
+        let mapping: Value = serde_json::from_str(&files[2].1).unwrap();
+        assert_eq!(mapping["response"]["bodyFileName"], "007-response.sse");
+        assert_eq!(mapping["response"]["headers"]["content-type"], "text/event-stream");
+        let request: Value = serde_json::from_str(&files[0].1).unwrap();
+        assert_eq!(mapping["request"]["bodyPatterns"][0]["equalToJson"], request);
+        assert_eq!(request["model"], "claude-sonnet-4");
+    }
+}
@@ -0,0 +1,237 @@
+//! Folding long tool results down to their first and last lines.
+//!
+//! A 2 MB test log or file dump stays whole in the event log, but views show
+//! its start and end with a marker saying how much was folded between them,
+//! and fetch the whole block from storage only when asked for it:
+//! `sentinel session <target> --expand <tool_use_id>` in the terminal, and
+//! `GET /api/events/:id/tool_results/:tool_use_id` for the dashboard, which
+//! asks for events with their tool results folded (`?fold=true`). Stored
+//! events are never folded; only the copies sent to a view are.
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::Json;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use uuid::Uuid;
+
+use crate::event::{ObservabilityEvent, Payload};
+use crate::parsers::ToolResult;
+use crate::proxy::ProxyState;
+
+/// Lines shown from the start of a folded block
+const HEAD_LINES: usize = 8;
+/// Lines shown from its end, where errors and summaries usually are
+const TAIL_LINES: usize = 4;
+/// Characters of one line shown, for minified JSON and the like
+const LINE_CHARS: usize = 240;
+
+/// A block cut down to its first and last lines
+#[derive(Debug, PartialEq)]
+pub struct Folded {
+    pub head: Vec<String>,
+    pub tail: Vec<String>,
+    /// Lines left out between head and tail
+    pub hidden_lines: usize,
+    /// Whether a line shown was cut short
+    pub clipped: bool,
+}
+
+impl Folded {
+    pub fn is_folded(&self) -> bool {
+        self.hidden_lines > 0 || self.clipped
+    }
+}
+
+/// What a tool result folded for the API held in full
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct FoldNote {
+    /// Lines of `content` before the fold
+    pub at: usize,
+    /// Lines left out at the fold
+    pub hidden_lines: usize,
+    pub lines: usize,
+    pub bytes: usize,
+}
+
+fn clip(line: &str) -> (String, bool) {
+    match line.char_indices().nth(LINE_CHARS) {
+        Some((cut, _)) => (format!("{}…", &line[..cut]), true),
+        None => (line.to_string(), false),
+    }
+}
+
+pub fn fold(text: &str) -> Folded {
+    let lines: Vec<&str> = text.trim_end().lines().collect();
+    let (head, tail, hidden_lines) = if lines.len() > HEAD_LINES + TAIL_LINES {
+        let tail_start = lines.len() - TAIL_LINES;
+        (
+            &lines[..HEAD_LINES],
+            &lines[tail_start..],
+            tail_start - HEAD_LINES,
+        )
+    } else {
+        (&lines[..], &lines[..0], 0)
+    };
+    let mut clipped = false;
+    let mut shown = |lines: &[&str]| -> Vec<String> {
+        lines
+            .iter()
+            .map(|line| {
+                let (line, cut) = clip(line);
+                clipped |= cut;
+                line
+            })
+            .collect()
+    };
+    Folded {
+        head: shown(head),
+        tail: shown(tail),
+        hidden_lines,
+        clipped,
+    }
+}
+
+/// A byte count as B, KB, or MB
+pub fn size(bytes: usize) -> String {
+    match bytes {
+        0..=1023 => format!("{} B", bytes),
+        1024..=1_048_575 => format!("{:.1} KB", bytes as f64 / 1024.0),
+        _ => format!("{:.1} MB", bytes as f64 / 1_048_576.0),
+    }
+}
+
+/// An event as a view is sent it, with its long tool results folded if it
+/// asked for that
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct ViewEvent {
+    #[serde(flatten)]
+    pub event: ObservabilityEvent,
+    /// What each folded tool result held in full, by `tool_use_id`
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub folds: BTreeMap<String, FoldNote>,
+}
+
+impl ViewEvent {
+    pub fn new(mut event: ObservabilityEvent, fold: bool) -> Self {
+        let folds = if fold {
+            fold_tool_results(&mut event)
+        } else {
+            BTreeMap::new()
+        };
+        Self { event, folds }
+    }
+}
+
+/// Fold the tool results an event carries, returning what each held in full
+fn fold_tool_results(event: &mut ObservabilityEvent) -> BTreeMap<String, FoldNote> {
+    let mut folds = BTreeMap::new();
+    let Payload::UserMessage(ref mut message) = event.payload else {
+        return folds;
+    };
+    for result in &mut message.tool_results {
+        let folded = fold(&result.content);
+        if !folded.is_folded() {
+            continue;
+        }
+        let note = FoldNote {
+            at: folded.head.len(),
+            hidden_lines: folded.hidden_lines,
+            lines: result.content.lines().count(),
+            bytes: result.content.len(),
+        };
+        folds.insert(result.tool_use_id.clone(), note);
+        let mut shown = folded.head;
+        shown.extend(folded.tail);
+        result.content = shown.join("\n");
+    }
+    folds
+}
+
+/// One tool result of a stored event, in full
+pub async fn tool_result_handler(
+    State(state): State<Arc<ProxyState>>,
+    Path((id, tool_use_id)): Path<(Uuid, String)>,
+) -> Result<Json<ToolResult>, (StatusCode, Json<Value>)> {
+    let event = state.storage.get_event(id).await.map_err(|e| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": e.to_string()})),
+        )
+    })?;
+    let result = match event.map(|event| event.payload) {
+        Some(Payload::UserMessage(message)) => message
+            .tool_results
+            .into_iter()
+            .find(|result| result.tool_use_id == tool_use_id),
+        _ => None,
+    };
+    result.map(Json).ok_or_else(|| {
+        (
+            StatusCode::NOT_FOUND,
+            Json(json!({"error": format!("no tool result {} in event {}", tool_use_id, id)})),
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fold_keeps_start_and_end() {
+        let log: String = (1..=100).map(|i| format!("line {}\n", i)).collect();
+        let folded = fold(&log);
+        assert_eq!(folded.head.len(), HEAD_LINES);
+        assert_eq!(folded.tail, ["line 97", "line 98", "line 99", "line 100"]);
+        assert_eq!(folded.hidden_lines, 88);
+
+        // One long line is cut rather than shown whole
+        let json = format!("{{\"data\": \"{}\"}}", "é".repeat(5000));
+        let folded = fold(&json);
+        assert!(folded.clipped && folded.hidden_lines == 0);
+        assert_eq!(folded.head[0].chars().count(), LINE_CHARS + 1);
+
+        assert!(!fold("short\noutput\n").is_folded());
+        assert!(fold("").head.is_empty());
+        assert_eq!(size(2_200_000), "2.1 MB");
+    }
+
+    #[test]
+    fn test_view_folds_a_copy_and_notes_it_beside_the_event() {
+        let log: String = (1..=100).map(|i| format!("line {}\n", i)).collect();
+        let result = |id: &str, content: &str| ToolResult {
+            tool_use_id: id.to_string(),
+            content: content.to_string(),
+            is_error: false,
+        };
+        let event = ObservabilityEvent {
+            seq: Some(1),
+            id: Uuid::new_v4(),
+            timestamp: chrono::Utc::now(),
+            session_id: None,
+            agent: None,
+            topic: None,
+            provider: None,
+            payload: Payload::UserMessage(crate::event::UserMessage {
+                tool_results: vec![result("t1", &log), result("t2", "ok")],
+                ..Default::default()
+            }),
+        };
+
+        let json = serde_json::to_value(ViewEvent::new(event.clone(), false)).unwrap();
+        assert!(json.get("folds").is_none());
+        assert_eq!(json["payload"]["tool_results"][0]["content"], log);
+
+        let json = serde_json::to_value(ViewEvent::new(event, true)).unwrap();
+        assert_eq!(json["seq"], 1);
+        assert_eq!(json["folds"]["t1"]["hidden_lines"], 88);
+        assert!(json["folds"].get("t2").is_none());
+        let shown = json["payload"]["tool_results"][0]["content"].as_str().unwrap();
+        assert_eq!(shown.lines().count(), HEAD_LINES + TAIL_LINES);
+    }
+}
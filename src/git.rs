@@ -0,0 +1,182 @@
+//! Git context of the repository an agent works in.
+//!
+//! When a request names a working directory on this machine, its remote,
+//! branch, and HEAD commit are read by running `git` there, and stored on the
+//! agent. Each commit an agent's session sees at HEAD is also kept, so a
+//! commit can be traced back to the sessions that were working when it was
+//! made (`sentinel agents --commit <sha>`).
+
+use std::collections::HashMap;
+use std::path::Path;
+use std::process::Stdio;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tokio::process::Command;
+
+/// How long one `git` call may take before it's given up on
+const GIT_TIMEOUT: Duration = Duration::from_secs(2);
+/// How long an agent's git context is trusted before `git` runs again
+const RECHECK: Duration = Duration::from_secs(10);
+
+/// Where in version control an agent is working
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct GitContext {
+    /// URL of the `origin` remote
+    pub remote: Option<String>,
+    /// Checked-out branch; `None` with a detached HEAD
+    pub branch: Option<String>,
+    /// Full sha of HEAD
+    pub commit: String,
+}
+
+impl GitContext {
+    /// `branch@short-sha`, or just the short sha when detached
+    pub fn describe(&self) -> String {
+        let short = &self.commit[..self.commit.len().min(7)];
+        match self.branch {
+            Some(ref branch) => format!("{}@{}", branch, short),
+            None => short.to_string(),
+        }
+    }
+}
+
+/// Standard output of a successful `git` call in `dir`, as it was printed
+pub(crate) async fn output(dir: &str, args: &[&str]) -> Option<String> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(args)
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .kill_on_drop(true)
+        .output();
+    let output = tokio::time::timeout(GIT_TIMEOUT, output).await.ok()?.ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    String::from_utf8(output.stdout).ok()
+}
+
+/// Trimmed output of a `git` call; `None` when it fails or prints nothing
+pub(crate) async fn git(dir: &str, args: &[&str]) -> Option<String> {
+    let out = output(dir, args).await?;
+    Some(out.trim().to_string()).filter(|out| !out.is_empty())
+}
+
+/// The git context of `dir`; `None` when it isn't in a repository on this
+/// machine, has no commits yet, or git isn't installed
+pub async fn resolve(dir: &str) -> Option<GitContext> {
+    if !Path::new(dir).is_dir() {
+        return None;
+    }
+    // One call for both: the sha, then the branch ("HEAD" when detached)
+    let head = git(dir, &["rev-parse", "HEAD", "--abbrev-ref", "HEAD"]).await?;
+    let mut lines = head.lines();
+    let commit = lines.next()?.to_string();
+    let branch = lines.next()?;
+    let remote = git(dir, &["config", "--get", "remote.origin.url"]).await;
+    Some(GitContext {
+        remote,
+        branch: (branch != "HEAD").then(|| branch.to_string()),
+        commit,
+    })
+}
+
+/// When each agent's working directory was last looked up, so an agent
+/// sending a request a second doesn't start two `git` processes for each
+#[derive(Debug, Default)]
+pub struct Lookups {
+    checked: Mutex<HashMap<(String, String), Instant>>,
+}
+
+impl Lookups {
+    /// Whether `dir` is due a lookup for `agent`, noting it as looked up if so
+    pub fn due(&self, agent: &str, dir: &str) -> bool {
+        let mut checked = self.checked.lock().unwrap_or_else(|e| e.into_inner());
+        let now = Instant::now();
+        let key = (agent.to_string(), dir.to_string());
+        if checked.get(&key).is_some_and(|at| now.duration_since(*at) < RECHECK) {
+            return false;
+        }
+        // Lookups past trusting are dropped, so only active agents are kept
+        checked.retain(|_, at| now.duration_since(*at) < RECHECK);
+        checked.insert(key, now);
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use uuid::Uuid;
+
+    fn run(dir: &Path, args: &[&str]) -> bool {
+        std::process::Command::new("git")
+            .arg("-C")
+            .arg(dir)
+            .args(args)
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .is_ok_and(|status| status.success())
+    }
+
+    #[tokio::test]
+    async fn test_resolves_branch_commit_and_remote() {
+        let dir = std::env::temp_dir().join(format!("sentinel-test-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(dir.join("src")).unwrap();
+        let path = dir.display().to_string();
+        assert_eq!(resolve(&path).await, None);
+        assert_eq!(resolve("/no/such/dir").await, None);
+        // Without git on the machine there is nothing more to check
+        if !run(&dir, &["init", "-q", "-b", "main"]) {
+            std::fs::remove_dir_all(&dir).unwrap();
+            return;
+        }
+        // No commits yet
+        assert_eq!(resolve(&path).await, None);
+
+        let commit = [
+            "-c",
+            "user.name=t",
+            "-c",
+            "user.email=t@t",
+            "commit",
+            "-q",
+            "--allow-empty",
+            "-m",
+            "first",
+        ];
+        assert!(run(&dir, &commit));
+        assert!(run(&dir, &["remote", "add", "origin", "git@example.com:me/app.git"]));
+        let git = resolve(&dir.join("src").display().to_string()).await.unwrap();
+        assert_eq!(git.branch.as_deref(), Some("main"));
+        assert_eq!(git.remote.as_deref(), Some("git@example.com:me/app.git"));
+        assert_eq!(git.commit.len(), 40);
+        assert_eq!(git.describe(), format!("main@{}", &git.commit[..7]));
+
+        assert!(run(&dir, &["checkout", "-q", "--detach"]));
+        let detached = resolve(&path).await.unwrap();
+        assert_eq!((detached.branch, detached.commit), (None, git.commit));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_lookups_are_due_once_per_agent_and_directory() {
+        let lookups = Lookups::default();
+        assert!(lookups.due("calm-otter", "/src/app"));
+        assert!(!lookups.due("calm-otter", "/src/app"));
+        assert!(lookups.due("calm-otter", "/src/lib"));
+        assert!(lookups.due("brave-heron", "/src/app"));
+
+        let past = Instant::now() - RECHECK;
+        for at in lookups.checked.lock().unwrap().values_mut() {
+            *at = past;
+        }
+        assert!(lookups.due("calm-otter", "/src/app"));
+        assert_eq!(lookups.checked.lock().unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,227 @@
+//! Recorded events as paginated JSON (`GET /api/events/history`).
+//!
+//! A page of stored events, newest first, beside the live stream on
+//! `/api/events`. Pages are filtered by `agent`, agent `tag`, `session`,
+//! `type` (a payload tag such as `assistant_response`), `project` and `run`
+//! (as sent in `x-sentinel-*` headers), and `since` and `until` (RFC 3339
+//! times, or a lookback such as `24h`), and hold up to `limit` events (100
+//! by default, at most 1000). A page that may have more after it carries
+//! `next_cursor`; passing it back as `cursor` gets the next, older page.
+//! With `fold=true`, long tool results are cut to their first and last lines
+//! (see `fold`).
+
+use std::sync::Arc;
+
+use axum::extract::{Query, State};
+use axum::http::{StatusCode, Uri};
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use chrono::{DateTime, Utc};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::fold::ViewEvent;
+use crate::leaderboard;
+use crate::proxy::ProxyState;
+use crate::storage::EventFilter;
+
+const DEFAULT_LIMIT: i64 = 100;
+const MAX_LIMIT: i64 = 1000;
+
+#[derive(Debug, Default, Deserialize)]
+pub struct HistoryQuery {
+    pub agent: Option<String>,
+    /// Only agents given this tag
+    pub tag: Option<String>,
+    /// x-sentinel-project of the exchanges
+    pub project: Option<String>,
+    /// x-sentinel-run-id of the exchanges
+    pub run: Option<String>,
+    pub session: Option<String>,
+    #[serde(rename = "type")]
+    pub event_type: Option<String>,
+    pub since: Option<String>,
+    pub until: Option<String>,
+    pub limit: Option<i64>,
+    /// `next_cursor` of the page before
+    pub cursor: Option<String>,
+    /// Fold long tool results
+    #[serde(default)]
+    pub fold: bool,
+}
+
+/// A page of `GET /api/events/history`
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct EventPage {
+    /// Newest first
+    pub events: Vec<ViewEvent>,
+    /// Where the next, older page starts; absent on the last page
+    pub next_cursor: Option<String>,
+}
+
+/// An RFC 3339 time, or a lookback like "24h" counted back from `now`
+fn parse_time(name: &str, value: &str, now: DateTime<Utc>) -> Result<DateTime<Utc>, String> {
+    if let Ok(time) = DateTime::parse_from_rfc3339(value) {
+        return Ok(time.with_timezone(&Utc));
+    }
+    leaderboard::parse_window(value)
+        .map(|window| now - window)
+        .map_err(|_| {
+            format!(
+                "invalid {} '{}': expected an RFC 3339 time or a lookback like 24h",
+                name, value
+            )
+        })
+}
+
+impl HistoryQuery {
+    /// The filter, the seq to read below, and the page size
+    fn page(&self, now: DateTime<Utc>) -> Result<(EventFilter, Option<i64>, i64), String> {
+        let time = |name, value: &Option<String>| {
+            value
+                .as_deref()
+                .map(|value| parse_time(name, value, now))
+                .transpose()
+        };
+        let filter = EventFilter {
+            agent: self.agent.clone(),
+            session_id: self.session.clone(),
+            payload_type: self.event_type.clone(),
+            since: time("since", &self.since)?,
+            until: time("until", &self.until)?,
+            tag: self.tag.clone(),
+            project: self.project.clone(),
+            run_id: self.run.clone(),
+        };
+        let before = self
+            .cursor
+            .as_deref()
+            .map(|cursor| {
+                cursor
+                    .parse::<i64>()
+                    .map_err(|_| format!("invalid cursor '{}'", cursor))
+            })
+            .transpose()?;
+        let limit = self.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+        Ok((filter, before, limit))
+    }
+}
+
+fn bad_request(message: String) -> Response {
+    (
+        StatusCode::BAD_REQUEST,
+        Json(serde_json::json!({ "error": message })),
+    )
+        .into_response()
+}
+
+/// A page of stored events
+pub async fn history_handler(State(state): State<Arc<ProxyState>>, uri: Uri) -> Response {
+    let query = match Query::<HistoryQuery>::try_from_uri(&uri) {
+        Ok(Query(query)) => query,
+        Err(e) => return bad_request(e.body_text()),
+    };
+    let (filter, before, limit) = match query.page(Utc::now()) {
+        Ok(page) => page,
+        Err(message) => return bad_request(message),
+    };
+    match state.storage.query_events(&filter, before, limit).await {
+        Ok(events) => {
+            let next_cursor = match events.last() {
+                Some(last) if events.len() as i64 == limit => last.seq.map(|seq| seq.to_string()),
+                _ => None,
+            };
+            Json(EventPage {
+                events: events
+                    .into_iter()
+                    .map(|event| ViewEvent::new(event, query.fold))
+                    .collect(),
+                next_cursor,
+            })
+            .into_response()
+        }
+        Err(e) => {
+            tracing::warn!("Failed to read event history: {}", e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(serde_json::json!({ "error": e.to_string() })),
+            )
+                .into_response()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event::{Alert, ObservabilityEvent, Payload};
+    use crate::storage::Storage;
+    use uuid::Uuid;
+
+    #[tokio::test]
+    async fn test_pages_walk_back_through_matching_events() {
+        let path = std::env::temp_dir().join(format!("sentinel-test-{}.db", Uuid::new_v4()));
+        let storage = Storage::new(&path).await.unwrap();
+        let now = Utc::now();
+        for i in 0..5 {
+            let agent = ["calm-otter", "swift-fox"][i as usize % 2];
+            storage
+                .insert_observability_event(&ObservabilityEvent {
+                    seq: None,
+                    id: Uuid::new_v4(),
+                    timestamp: now - chrono::Duration::hours(5 - i),
+                    session_id: None,
+                    agent: Some(agent.to_string()),
+                    topic: None,
+                    provider: None,
+                    payload: Payload::Alert(Alert {
+                        kind: "test".to_string(),
+                        message: i.to_string(),
+                    }),
+                })
+                .await
+                .unwrap();
+        }
+
+        let mut query = HistoryQuery {
+            agent: Some("calm-otter".to_string()),
+            event_type: Some("alert".to_string()),
+            limit: Some(2),
+            ..Default::default()
+        };
+        let (filter, before, limit) = query.page(now).unwrap();
+        let first = storage.query_events(&filter, before, limit).await.unwrap();
+        let seqs: Vec<_> = first.iter().filter_map(|e| e.seq).collect();
+        assert_eq!(seqs, vec![5, 3]);
+
+        query.cursor = Some("3".to_string());
+        let (filter, before, limit) = query.page(now).unwrap();
+        let second = storage.query_events(&filter, before, limit).await.unwrap();
+        assert_eq!(second.len(), 1);
+        assert_eq!(second[0].seq, Some(1));
+
+        // The last 150 minutes hold the events 1 and 2 hours old
+        let query = HistoryQuery {
+            since: Some("150m".to_string()),
+            ..Default::default()
+        };
+        let (filter, before, limit) = query.page(now).unwrap();
+        assert_eq!(
+            storage
+                .query_events(&filter, before, limit)
+                .await
+                .unwrap()
+                .len(),
+            2
+        );
+
+        let query = HistoryQuery {
+            cursor: Some("abc".to_string()),
+            ..Default::default()
+        };
+        assert!(query.page(now).is_err());
+
+        drop(storage);
+        let _ = std::fs::remove_file(&path);
+    }
+}
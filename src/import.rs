@@ -0,0 +1,154 @@
+//! `sentinel import`: load a `sentinel export --format jsonl` file.
+//!
+//! Events keep their ids, so importing the same file twice (or one that
+//! overlaps what's already recorded) adds nothing new. Agents are matched by
+//! session; one whose name is already taken by a different session is
+//! skipped with a warning and its events keep the name they were recorded
+//! under. Lines that don't parse are skipped and reported by line number.
+//! With `--anonymize`, a collector hashes home paths and names in what it's
+//! sent before storing it, as `sentinel export --anonymize` would have.
+
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+
+use crate::agent::AgentStore;
+use crate::anonymize::Anonymizer;
+use crate::export::jsonl::Record;
+use crate::storage::Storage;
+
+#[derive(Debug, Default, PartialEq)]
+struct Counts {
+    agents: usize,
+    events: usize,
+    skipped: usize,
+}
+
+async fn import(
+    storage: &Storage,
+    reader: impl BufRead,
+    anonymizer: Option<&Anonymizer>,
+) -> Result<Counts, Box<dyn std::error::Error>> {
+    let agents = AgentStore::new(storage.pool());
+    let mut counts = Counts::default();
+    for (n, line) in reader.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let record: Record = match serde_json::from_str(&line) {
+            Ok(record) => record,
+            Err(e) => {
+                tracing::warn!("Skipping line {}: {}", n + 1, e);
+                counts.skipped += 1;
+                continue;
+            }
+        };
+        match record {
+            Record::Agent(mut agent) => {
+                if let Some(anonymizer) = anonymizer {
+                    anonymizer.agent(&mut agent);
+                }
+                if agents.import(&agent).await? {
+                    counts.agents += 1;
+                    continue;
+                }
+                if agents
+                    .find_by_session_id(&agent.session_id)
+                    .await?
+                    .is_none()
+                {
+                    tracing::warn!(
+                        "Skipping agent '{}' on line {}: its id or name is already in use",
+                        agent.name,
+                        n + 1
+                    );
+                }
+                counts.skipped += 1;
+            }
+            Record::Event { mut event, mut raw } => {
+                if let Some(anonymizer) = anonymizer {
+                    anonymizer.event(&mut event);
+                    if let Some(ref mut raw) = raw {
+                        anonymizer.raw(raw);
+                    }
+                }
+                if storage.get_event(event.id).await?.is_some() {
+                    counts.skipped += 1;
+                    continue;
+                }
+                storage
+                    .insert_observability_event_with_raw(&event, raw.as_ref())
+                    .await?;
+                counts.events += 1;
+            }
+        }
+    }
+    Ok(counts)
+}
+
+pub async fn run(
+    data_dir: &Path,
+    file: &Path,
+    anonymizer: Option<Anonymizer>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    std::fs::create_dir_all(data_dir)?;
+    let storage = Storage::new(&data_dir.join("sentinel.db")).await?;
+    let anonymizer = anonymizer.as_ref();
+    let counts = if file == Path::new("-") {
+        import(&storage, std::io::stdin().lock(), anonymizer).await?
+    } else {
+        let input = std::fs::File::open(file)
+            .map_err(|e| format!("Failed to open {}: {}", file.display(), e))?;
+        import(&storage, BufReader::new(input), anonymizer).await?
+    };
+    println!(
+        "Imported {} agents and {} events ({} skipped)",
+        counts.agents, counts.events, counts.skipped
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event::{Alert, ObservabilityEvent, Payload};
+    use crate::export::jsonl::write_record;
+    use uuid::Uuid;
+
+    #[tokio::test]
+    async fn test_import_skips_known_events_and_bad_lines() {
+        let path = std::env::temp_dir().join(format!("sentinel-test-{}.db", Uuid::new_v4()));
+        let storage = Storage::new(&path).await.unwrap();
+        let event = ObservabilityEvent {
+            provider: None,
+            ..ObservabilityEvent::for_tests(0, Payload::Alert(Alert {
+                kind: "test".to_string(),
+                message: "hello".to_string(),
+            }))
+        };
+        let mut file = Vec::new();
+        write_record(
+            &mut file,
+            &Record::Event {
+                event: Box::new(event),
+                raw: None,
+            },
+        )
+        .unwrap();
+        file.extend_from_slice(b"not json\n");
+
+        let first = import(&storage, file.as_slice(), None).await.unwrap();
+        assert_eq!(
+            first,
+            Counts {
+                agents: 0,
+                events: 1,
+                skipped: 1
+            }
+        );
+        let again = import(&storage, file.as_slice(), None).await.unwrap();
+        assert_eq!((again.events, again.skipped), (0, 2));
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,152 @@
+//! `sentinel inspect --snapshot <file.db>`: read commands against someone
+//! else's database.
+//!
+//! A snapshot (a `sentinel.db` copied off another machine after an incident,
+//! say) is opened read-only and copied, with any monthly shards listed in its
+//! catalog, into a scratch data dir. The read command then runs against the
+//! copy, which is brought up to this version's schema like any data dir
+//! would be; the snapshot itself and this machine's own data dir are never
+//! written. The scratch dir is removed when the command finishes.
+
+use std::path::{Path, PathBuf};
+
+use sqlx::sqlite::SqliteConnectOptions;
+use sqlx::{ConnectOptions, SqliteConnection};
+use uuid::Uuid;
+
+#[derive(Debug, thiserror::Error)]
+pub enum InspectError {
+    #[error("no snapshot at {0}")]
+    NotFound(String),
+    #[error("{0} isn't a sentinel database")]
+    NotSentinel(String),
+    #[error("inspect only runs commands that read recorded data, like logs, session or export")]
+    NotReadOnly,
+    #[error("failed to read snapshot {path}: {source}")]
+    Read { path: String, source: sqlx::Error },
+    #[error("failed to make a scratch data dir: {0}")]
+    Scratch(std::io::Error),
+}
+
+/// A snapshot's working copy; removed on drop
+pub struct Snapshot {
+    dir: PathBuf,
+}
+
+impl Snapshot {
+    /// Copy the snapshot at `path` into a scratch data dir
+    pub async fn open(path: &Path) -> Result<Self, InspectError> {
+        if !path.is_file() {
+            return Err(InspectError::NotFound(path.display().to_string()));
+        }
+        let dir = std::env::temp_dir().join(format!("sentinel-inspect-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).map_err(InspectError::Scratch)?;
+        // Dropped on any error below, taking the partial copy with it
+        let snapshot = Self { dir };
+
+        let read = |source| InspectError::Read {
+            path: path.display().to_string(),
+            source,
+        };
+        let mut conn = connect(path).await.map_err(read)?;
+        let tables: Vec<String> = sqlx::query_scalar(
+            "SELECT name FROM sqlite_master WHERE type = 'table' AND name IN ('observability_events', 'event_shards')",
+        )
+        .fetch_all(&mut conn)
+        .await
+        .map_err(read)?;
+        if !tables.iter().any(|t| t == "observability_events") {
+            return Err(InspectError::NotSentinel(path.display().to_string()));
+        }
+        copy(&mut conn, &snapshot.dir.join("sentinel.db"))
+            .await
+            .map_err(read)?;
+
+        // Shards sit next to the database they're cataloged in
+        if tables.iter().any(|t| t == "event_shards") {
+            let files: Vec<String> = sqlx::query_scalar("SELECT file FROM event_shards")
+                .fetch_all(&mut conn)
+                .await
+                .map_err(read)?;
+            let from = path.parent().unwrap_or(Path::new("."));
+            for file in files {
+                let shard = from.join(&file);
+                if !shard.is_file() {
+                    continue;
+                }
+                let read = |source| InspectError::Read {
+                    path: shard.display().to_string(),
+                    source,
+                };
+                let mut conn = connect(&shard).await.map_err(read)?;
+                copy(&mut conn, &snapshot.dir.join(&file))
+                    .await
+                    .map_err(read)?;
+            }
+        }
+        Ok(snapshot)
+    }
+
+    /// The scratch data dir to run against
+    pub fn data_dir(&self) -> &Path {
+        &self.dir
+    }
+}
+
+impl Drop for Snapshot {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
+
+async fn connect(path: &Path) -> Result<SqliteConnection, sqlx::Error> {
+    SqliteConnectOptions::new()
+        .filename(path)
+        .read_only(true)
+        .connect()
+        .await
+}
+
+/// Write the connected database, committed WAL included, to `to`
+async fn copy(conn: &mut SqliteConnection, to: &Path) -> Result<(), sqlx::Error> {
+    sqlx::query("VACUUM INTO ?")
+        .bind(to.display().to_string())
+        .execute(conn)
+        .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::Storage;
+
+    #[tokio::test]
+    async fn test_snapshots_are_copied_without_being_written() {
+        let root = std::env::temp_dir().join(format!("sentinel-test-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&root).unwrap();
+        let path = root.join("incident.db");
+        let storage = Storage::new(&path).await.unwrap();
+        storage.pool().close().await;
+        let before = std::fs::read(&path).unwrap();
+
+        let snapshot = Snapshot::open(&path).await.unwrap();
+        let dir = snapshot.data_dir().to_path_buf();
+        // The copy is migrated like any data dir; the snapshot isn't
+        Storage::new(&dir.join("sentinel.db")).await.unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), before);
+        drop(snapshot);
+        assert!(!dir.exists());
+
+        std::fs::write(root.join("notes.db"), b"").unwrap();
+        assert!(matches!(
+            Snapshot::open(&root.join("notes.db")).await,
+            Err(InspectError::NotSentinel(_))
+        ));
+        assert!(matches!(
+            Snapshot::open(&root.join("missing.db")).await,
+            Err(InspectError::NotFound(_))
+        ));
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,460 @@
+//! Upstream Anthropic keys chosen per client or agent (`--upstream-keys`),
+//! with monthly budgets, and `sentinel keys`.
+//!
+//! ```yaml
+//! keys:
+//!   - name: research
+//!     api_key_env: ANTHROPIC_RESEARCH_KEY
+//!     virtual_keys: [sk-sentinel-alice]  # what clients send as x-api-key
+//!     agents: [calm-otter]               # or which agents use it
+//!     monthly_budget_usd: 200
+//!   - name: production
+//!     api_key_env: ANTHROPIC_PROD_KEY
+//!     default: true                      # everyone else
+//! ```
+//!
+//! A client's virtual key picks its upstream key first, then its agent, then
+//! the default. The client's credentials are replaced by the real key, which
+//! is never recorded; each response records the key's name. Requests that
+//! match no key and have no default are forwarded with their own credentials.
+//! Once a key's spend this month (UTC) reaches its budget, requests for it
+//! are refused until the month turns.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::Path;
+use std::sync::Mutex;
+
+use axum::http::HeaderMap;
+use chrono::{DateTime, Datelike, TimeZone, Utc};
+use serde::Deserialize;
+use sqlx::SqlitePool;
+
+use crate::event::Payload;
+use crate::pricing;
+
+#[derive(Debug, thiserror::Error)]
+pub enum KeysError {
+    #[error("failed to read {path}: {source}")]
+    Read {
+        path: String,
+        source: std::io::Error,
+    },
+    #[error("invalid upstream keys {path}: {source}")]
+    Parse {
+        path: String,
+        source: serde_yaml::Error,
+    },
+    #[error("invalid upstream keys {path}: {message}")]
+    Invalid { path: String, message: String },
+    #[error("upstream key '{name}': ${env} is not set")]
+    MissingEnv { name: String, env: String },
+}
+
+/// One upstream key as configured; the secret itself is resolved on start
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct KeyConfig {
+    pub name: String,
+    /// Environment variable holding the key
+    #[serde(default)]
+    api_key_env: Option<String>,
+    /// The key itself, for files kept out of version control
+    #[serde(default)]
+    api_key: Option<String>,
+    /// Keys clients send instead of a real one
+    #[serde(default)]
+    pub virtual_keys: Vec<String>,
+    #[serde(default)]
+    pub agents: Vec<String>,
+    /// Used for requests no other key claims
+    #[serde(default)]
+    pub default: bool,
+    #[serde(default)]
+    pub monthly_budget_usd: Option<f64>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct KeysFile {
+    keys: Vec<KeyConfig>,
+}
+
+pub fn load(path: &Path) -> Result<Vec<KeyConfig>, KeysError> {
+    let origin = path.display().to_string();
+    let text = std::fs::read_to_string(path).map_err(|source| KeysError::Read {
+        path: origin.clone(),
+        source,
+    })?;
+    parse(&text, &origin)
+}
+
+fn parse(yaml: &str, origin: &str) -> Result<Vec<KeyConfig>, KeysError> {
+    let file: KeysFile = serde_yaml::from_str(yaml).map_err(|source| KeysError::Parse {
+        path: origin.to_string(),
+        source,
+    })?;
+    let invalid = |message: String| KeysError::Invalid {
+        path: origin.to_string(),
+        message,
+    };
+
+    if file.keys.is_empty() {
+        return Err(invalid("no keys defined".to_string()));
+    }
+    let mut names = HashSet::new();
+    let mut virtual_keys = HashSet::new();
+    for key in &file.keys {
+        if !names.insert(key.name.as_str()) {
+            return Err(invalid(format!("key '{}' is listed twice", key.name)));
+        }
+        if key.api_key.is_some() == key.api_key_env.is_some() {
+            return Err(invalid(format!(
+                "key '{}' needs exactly one of api_key and api_key_env",
+                key.name
+            )));
+        }
+        if let Some(virtual_key) = key.virtual_keys.iter().find(|v| !virtual_keys.insert(*v)) {
+            return Err(invalid(format!(
+                "virtual key {}… belongs to more than one key",
+                virtual_key.chars().take(8).collect::<String>()
+            )));
+        }
+        if key.monthly_budget_usd.is_some_and(|budget| budget <= 0.0) {
+            return Err(invalid(format!(
+                "key '{}': monthly_budget_usd must be greater than 0",
+                key.name
+            )));
+        }
+    }
+    if file.keys.iter().filter(|k| k.default).count() > 1 {
+        return Err(invalid("only one key can be the default".to_string()));
+    }
+    Ok(file.keys)
+}
+
+/// A key ready to send upstream
+pub struct UpstreamKey {
+    pub name: String,
+    api_key: String,
+    virtual_keys: Vec<String>,
+    agents: Vec<String>,
+    default: bool,
+    monthly_budget_usd: Option<f64>,
+}
+
+impl UpstreamKey {
+    pub fn api_key(&self) -> &str {
+        &self.api_key
+    }
+}
+
+/// Spend per key name in the current month
+#[derive(Default)]
+struct MonthSpend {
+    month: Option<(i32, u32)>,
+    usd: HashMap<String, f64>,
+}
+
+/// The configured keys and what each has spent this month; empty when
+/// `--upstream-keys` isn't given
+#[derive(Default)]
+pub struct UpstreamKeys {
+    keys: Vec<UpstreamKey>,
+    spend: Mutex<MonthSpend>,
+}
+
+/// Start of the UTC month `at` falls in
+fn month_start(at: DateTime<Utc>) -> DateTime<Utc> {
+    Utc.with_ymd_and_hms(at.year(), at.month(), 1, 0, 0, 0)
+        .single()
+        .unwrap_or(at)
+}
+
+/// The credential a client sent, as `x-api-key` or a bearer token
+pub fn client_credential(headers: &HeaderMap) -> Option<&str> {
+    if let Some(key) = headers.get("x-api-key").and_then(|v| v.to_str().ok()) {
+        return Some(key);
+    }
+    let value = headers.get("authorization")?.to_str().ok()?;
+    // The scheme is case-insensitive
+    let (scheme, credential) = value.split_once(' ')?;
+    scheme
+        .eq_ignore_ascii_case("bearer")
+        .then(|| credential.trim_start())
+}
+
+impl UpstreamKeys {
+    /// Resolve each key's secret from its config or environment
+    pub fn new(configs: Vec<KeyConfig>) -> Result<Self, KeysError> {
+        let mut keys = Vec::new();
+        for config in configs {
+            let api_key = match (config.api_key, config.api_key_env) {
+                (Some(key), _) => key,
+                (None, Some(env)) => std::env::var(&env).map_err(|_| KeysError::MissingEnv {
+                    name: config.name.clone(),
+                    env,
+                })?,
+                (None, None) => unreachable!("parse requires api_key or api_key_env"),
+            };
+            keys.push(UpstreamKey {
+                name: config.name,
+                api_key,
+                virtual_keys: config.virtual_keys,
+                agents: config.agents,
+                default: config.default,
+                monthly_budget_usd: config.monthly_budget_usd,
+            });
+        }
+        Ok(Self {
+            keys,
+            spend: Mutex::new(MonthSpend::default()),
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    pub fn names(&self) -> Vec<&str> {
+        self.keys.iter().map(|k| k.name.as_str()).collect()
+    }
+
+    /// The key for a client's virtual key, else its agent's, else the default
+    pub fn select(&self, credential: Option<&str>, agent: Option<&str>) -> Option<&UpstreamKey> {
+        credential
+            .and_then(|c| {
+                self.keys
+                    .iter()
+                    .find(|k| k.virtual_keys.iter().any(|v| v == c))
+            })
+            .or_else(|| {
+                let agent = agent?;
+                self.keys
+                    .iter()
+                    .find(|k| k.agents.iter().any(|a| a == agent))
+            })
+            .or_else(|| self.keys.iter().find(|k| k.default))
+    }
+
+    /// This month's spend and budget, when the key has reached its budget
+    pub fn over_budget(&self, key: &UpstreamKey) -> Option<(f64, f64)> {
+        let budget = key.monthly_budget_usd?;
+        let spent = self.spent(&key.name);
+        (spent >= budget).then_some((spent, budget))
+    }
+
+    fn spent(&self, name: &str) -> f64 {
+        let mut spend = self.spend.lock().unwrap_or_else(|e| e.into_inner());
+        self.roll_month(&mut spend);
+        spend.usd.get(name).copied().unwrap_or(0.0)
+    }
+
+    fn roll_month(&self, spend: &mut MonthSpend) {
+        let now = Utc::now();
+        let month = Some((now.year(), now.month()));
+        if spend.month != month {
+            spend.month = month;
+            spend.usd.clear();
+        }
+    }
+
+    /// Count a response's cost; the alert message when it uses up the budget
+    pub fn record(&self, name: &str, cost_usd: f64) -> Option<String> {
+        let budget = self
+            .keys
+            .iter()
+            .find(|k| k.name == name)?
+            .monthly_budget_usd;
+        let mut spend = self.spend.lock().unwrap_or_else(|e| e.into_inner());
+        self.roll_month(&mut spend);
+        let spent = spend.usd.entry(name.to_string()).or_insert(0.0);
+        let before = *spent;
+        *spent += cost_usd;
+        let budget = budget?;
+        (before < budget && *spent >= budget).then(|| {
+            format!(
+                "Upstream key '{}' spent ${:.2} of its ${:.2} monthly budget; its requests are refused until next month",
+                name, spent, budget
+            )
+        })
+    }
+
+    /// Load this month's spend from recorded responses
+    pub async fn load_spend(&self, pool: &SqlitePool) -> Result<(), sqlx::Error> {
+        let usage = usage_since(pool, month_start(Utc::now())).await?;
+        let mut spend = self.spend.lock().unwrap_or_else(|e| e.into_inner());
+        self.roll_month(&mut spend);
+        spend.usd = usage
+            .into_iter()
+            .map(|(name, u)| (name, u.cost_usd))
+            .collect();
+        Ok(())
+    }
+}
+
+/// Requests and cost recorded against one key
+#[derive(Debug, Default)]
+pub struct KeyUsage {
+    pub requests: i64,
+    pub cost_usd: f64,
+    pub unpriced: i64,
+}
+
+#[derive(sqlx::FromRow)]
+struct KeyedPayload {
+    id: String,
+    payload: String,
+}
+
+/// Usage per key name of the responses recorded since `since`
+pub async fn usage_since(
+    pool: &SqlitePool,
+    since: DateTime<Utc>,
+) -> Result<BTreeMap<String, KeyUsage>, sqlx::Error> {
+    let rows: Vec<KeyedPayload> = sqlx::query_as(
+        r#"
+        SELECT id, payload
+        FROM observability_events
+        WHERE timestamp >= ? AND json_extract(payload, '$.upstream_key') IS NOT NULL
+        "#,
+    )
+    .bind(since.to_rfc3339())
+    .fetch_all(pool)
+    .await?;
+
+    let mut usage: BTreeMap<String, KeyUsage> = BTreeMap::new();
+    for row in rows {
+        let response = match serde_json::from_str(&row.payload) {
+            Ok(Payload::AssistantResponse(response)) => response,
+            Ok(_) => continue,
+            Err(e) => {
+                tracing::warn!("Skipping event {} in key spend: {}", row.id, e);
+                continue;
+            }
+        };
+        let Some(name) = response.upstream_key else {
+            continue;
+        };
+        let key = usage.entry(name).or_default();
+        key.requests += 1;
+        match response
+            .usage
+            .as_ref()
+            .and_then(|u| pricing::cost_usd(response.model.as_deref(), u))
+        {
+            Some(cost) => key.cost_usd += cost,
+            None => key.unpriced += 1,
+        }
+    }
+    Ok(usage)
+}
+
+pub async fn run(data_dir: &Path, config: Option<&Path>) -> Result<(), Box<dyn std::error::Error>> {
+    let configs = config.map(load).transpose()?.unwrap_or_default();
+    let db_path = data_dir.join("sentinel.db");
+    let from = month_start(Utc::now());
+    let mut usage = if db_path.exists() {
+        let storage = crate::storage::Storage::new(&db_path).await?;
+        usage_since(&storage.pool(), from).await?
+    } else {
+        BTreeMap::new()
+    };
+    if configs.is_empty() && usage.is_empty() {
+        println!("No requests sent with an upstream key this month.");
+        return Ok(());
+    }
+
+    println!("Upstream key spend since {}\n", from.format("%Y-%m-%d"));
+    println!(
+        "{:<20} {:>8} {:>12} {:>12} {:>12}",
+        "key", "requests", "spent", "budget", "left"
+    );
+    let mut rows: Vec<(String, KeyUsage, Option<f64>)> = configs
+        .iter()
+        .map(|c| {
+            let used = usage.remove(&c.name).unwrap_or_default();
+            (c.name.clone(), used, c.monthly_budget_usd)
+        })
+        .collect();
+    // Keys that were used this month but are no longer configured
+    rows.extend(usage.into_iter().map(|(name, used)| (name, used, None)));
+    let mut unpriced = 0;
+    for (name, used, budget) in &rows {
+        let (budget, left) = match budget {
+            Some(budget) => (
+                format!("${:.2}", budget),
+                format!("${:.2}", budget - used.cost_usd),
+            ),
+            None => ("-".to_string(), "-".to_string()),
+        };
+        println!(
+            "{:<20} {:>8} {:>12} {:>12} {:>12}",
+            name.chars().take(20).collect::<String>(),
+            used.requests,
+            format!("${:.4}", used.cost_usd),
+            budget,
+            left
+        );
+        unpriced += used.unpriced;
+    }
+    if unpriced > 0 {
+        println!(
+            "\n{} response(s) had no usage or an unknown model price and count as $0",
+            unpriced
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_select_and_budget() {
+        let configs = parse(
+            "keys:\n  - name: research\n    api_key: sk-research\n    virtual_keys: [sk-sentinel-alice]\n    agents: [calm-otter]\n    monthly_budget_usd: 10\n  - name: production\n    api_key: sk-prod\n    default: true\n",
+            "keys.yaml",
+        )
+        .unwrap();
+        let keys = UpstreamKeys::new(configs).unwrap();
+
+        let pick = |credential, agent| keys.select(credential, agent).map(|k| k.name.as_str());
+        assert_eq!(pick(Some("sk-sentinel-alice"), None), Some("research"));
+        assert_eq!(pick(Some("sk-other"), Some("calm-otter")), Some("research"));
+        assert_eq!(pick(None, Some("swift-fox")), Some("production"));
+        assert_eq!(keys.select(None, None).unwrap().api_key(), "sk-prod");
+
+        let research = keys.select(Some("sk-sentinel-alice"), None).unwrap();
+        assert!(keys.record("research", 6.0).is_none());
+        assert!(keys.over_budget(research).is_none());
+        assert!(keys.record("research", 5.0).is_some());
+        assert_eq!(keys.over_budget(research), Some((11.0, 10.0)));
+        assert!(keys.record("research", 1.0).is_none());
+
+        assert!(parse(
+            "keys:\n  - name: a\n    api_key: x\n    api_key_env: Y\n",
+            "x"
+        )
+        .is_err());
+        assert!(parse(
+            "keys:\n  - name: a\n    api_key: x\n    default: true\n  - name: b\n    api_key: y\n    default: true\n",
+            "x"
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_client_credential_reads_either_header() {
+        let credential = |name: &'static str, value: &'static str| {
+            let mut headers = HeaderMap::new();
+            headers.insert(name, value.parse().unwrap());
+            client_credential(&headers).map(str::to_string)
+        };
+        assert_eq!(credential("x-api-key", "sk-a").as_deref(), Some("sk-a"));
+        assert_eq!(credential("authorization", "Bearer sk-b").as_deref(), Some("sk-b"));
+        assert_eq!(credential("authorization", "bearer sk-c").as_deref(), Some("sk-c"));
+        assert_eq!(credential("authorization", "BEARER  sk-d").as_deref(), Some("sk-d"));
+        assert_eq!(credential("authorization", "Basic dXNlcg=="), None);
+    }
+}
@@ -0,0 +1,191 @@
+//! `sentinel run -- <command>`: a command with its API traffic recorded under
+//! an agent name.
+//!
+//! The command is started with `ANTHROPIC_BASE_URL` (and `ANTHROPIC_API_URL`,
+//! which some tools read instead) pointing at the proxy with the agent's name
+//! in the path, `http://127.0.0.1:9000/agent/<name>`. The proxy strips that
+//! prefix before routing and records the requests under the name, whichever
+//! client sent them. When nothing answers on the port, a proxy is started for
+//! as long as the command runs, logging to `run-proxy.log` in the data dir.
+
+use std::path::Path;
+use std::process::Stdio;
+use std::time::Duration;
+
+use crate::agent;
+use crate::profiles;
+
+/// Base URL path naming the agent, as in `/agent/<name>/v1/messages`
+pub const AGENT_PATH_PREFIX: &str = "/agent/";
+const MAX_NAME_LEN: usize = 64;
+/// How long a started proxy gets to answer its health check
+const STARTUP_TIMEOUT: Duration = Duration::from_secs(15);
+
+fn is_valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= MAX_NAME_LEN
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+}
+
+/// An agent name that can go in a URL path, for `--agent`
+pub fn parse_name(name: &str) -> Result<String, String> {
+    if is_valid_name(name) {
+        Ok(name.to_string())
+    } else {
+        Err(format!(
+            "'{}' must be 1-{} letters, digits, '-', '_', or '.'",
+            name, MAX_NAME_LEN
+        ))
+    }
+}
+
+/// The agent named by a `/agent/<name>` prefix, and the path after it
+pub fn split_agent_path(path: &str) -> (Option<&str>, &str) {
+    let Some(rest) = path.strip_prefix(AGENT_PATH_PREFIX) else {
+        return (None, path);
+    };
+    let (name, rest) = match rest.find('/') {
+        Some(i) => rest.split_at(i),
+        None => (rest, "/"),
+    };
+    if is_valid_name(name) {
+        (Some(name), rest)
+    } else {
+        (None, path)
+    }
+}
+
+async fn healthy(client: &reqwest::Client, base: &str) -> bool {
+    matches!(
+        client
+            .get(format!("{}/healthz", base))
+            .timeout(Duration::from_secs(1))
+            .send()
+            .await,
+        Ok(response) if response.status().is_success()
+    )
+}
+
+/// A proxy started for the command, stopped when dropped
+struct OwnedProxy(std::process::Child);
+
+impl Drop for OwnedProxy {
+    fn drop(&mut self) {
+        let _ = self.0.kill();
+        let _ = self.0.wait();
+    }
+}
+
+async fn start_proxy(
+    client: &reqwest::Client,
+    base: &str,
+    data_dir: &Path,
+    profile: Option<&str>,
+    port: u16,
+) -> Result<OwnedProxy, Box<dyn std::error::Error>> {
+    std::fs::create_dir_all(data_dir)?;
+    let log_path = data_dir.join("run-proxy.log");
+    let log = std::fs::File::create(&log_path)?;
+    let mut command = std::process::Command::new(std::env::current_exe()?);
+    command
+        .args(["start", "--port", &port.to_string()])
+        .args(profile.map(|profile| format!("--profile={}", profile)))
+        .stdin(Stdio::null())
+        .stdout(log.try_clone()?)
+        .stderr(log);
+    // Its own process group, so Ctrl-C meant for the command doesn't stop it
+    #[cfg(unix)]
+    std::os::unix::process::CommandExt::process_group(&mut command, 0);
+    let mut proxy = OwnedProxy(command.spawn()?);
+
+    let deadline = tokio::time::Instant::now() + STARTUP_TIMEOUT;
+    loop {
+        if let Some(status) = proxy.0.try_wait()? {
+            return Err(format!(
+                "The proxy exited with {} on startup; see {}",
+                status,
+                log_path.display()
+            )
+            .into());
+        }
+        if healthy(client, base).await {
+            return Ok(proxy);
+        }
+        if tokio::time::Instant::now() >= deadline {
+            return Err(format!(
+                "The proxy didn't come up on port {} within {}s; see {}",
+                port,
+                STARTUP_TIMEOUT.as_secs(),
+                log_path.display()
+            )
+            .into());
+        }
+        tokio::time::sleep(Duration::from_millis(200)).await;
+    }
+}
+
+/// Run `command` through the proxy on `port`, passing `profile` on to it
+/// and any proxy started for it; returns its exit code
+pub async fn run(
+    data_dir: &Path,
+    profile: Option<&str>,
+    port: u16,
+    name: Option<String>,
+    command: &[String],
+) -> Result<i32, Box<dyn std::error::Error>> {
+    let (program, args) = command
+        .split_first()
+        .ok_or("No command given; put it after --, e.g. sentinel run -- claude")?;
+    let base = format!("http://127.0.0.1:{}", port);
+    let client = reqwest::Client::new();
+    let proxy = if healthy(&client, &base).await {
+        None
+    } else {
+        eprintln!("No proxy on port {}; starting one", port);
+        Some(start_proxy(&client, &base, data_dir, profile, port).await?)
+    };
+
+    let name = name.unwrap_or_else(agent::generate_name);
+    let url = format!("{}{}{}", base, AGENT_PATH_PREFIX, name);
+    eprintln!("Recording {} as agent '{}' via {}", program, name, url);
+    let mut child = tokio::process::Command::new(program)
+        .args(args)
+        .env("ANTHROPIC_BASE_URL", &url)
+        .env("ANTHROPIC_API_URL", &url)
+        .envs(profile.map(|profile| (profiles::VAR, profile)))
+        .spawn()
+        .map_err(|e| format!("Failed to run {}: {}", program, e))?;
+    let status = loop {
+        tokio::select! {
+            status = child.wait() => break status?,
+            // The command gets Ctrl-C itself and decides what it means
+            _ = tokio::signal::ctrl_c() => {}
+        }
+    };
+    drop(proxy);
+    Ok(status.code().unwrap_or(1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_agent_path() {
+        assert_eq!(
+            split_agent_path("/agent/reviewer/v1/messages"),
+            (Some("reviewer"), "/v1/messages")
+        );
+        assert_eq!(split_agent_path("/agent/reviewer"), (Some("reviewer"), "/"));
+        assert_eq!(split_agent_path("/v1/messages"), (None, "/v1/messages"));
+        // Not a name this prefix could have come from, so left to routing
+        assert_eq!(
+            split_agent_path("/agent/a%20b/v1/messages"),
+            (None, "/agent/a%20b/v1/messages")
+        );
+        assert!(parse_name("backend.fox_2").is_ok());
+        assert!(parse_name("a/b").is_err());
+    }
+}
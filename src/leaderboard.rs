@@ -0,0 +1,375 @@
+//! `sentinel leaderboard`: rank agent setups by cost, turns, or success rate.
+//!
+//! Everything is per session, so a group that simply ran more sessions doesn't
+//! win on volume: cost and turns are averaged per session, and success rate is
+//! the share of sessions that finished cleanly. Grouped by tag, an agent's
+//! sessions count toward each of its tags.
+
+use std::collections::HashMap;
+use std::path::Path;
+
+use chrono::{DateTime, Duration, Utc};
+use clap::ValueEnum;
+
+use crate::agent::AgentStore;
+use crate::event::{ObservabilityEvent, Payload};
+use crate::pricing;
+use crate::storage::Storage;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Metric {
+    /// Average cost per session, cheapest first
+    Cost,
+    /// Average model turns per session, fewest first
+    Turns,
+    /// Share of sessions that ended cleanly, highest first
+    SuccessRate,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum GroupBy {
+    Model,
+    Agent,
+    Provider,
+    Topic,
+    /// Each of the agent's tags (`sentinel tag`)
+    Tag,
+}
+
+/// Tags by agent name, for grouping by tag
+pub type AgentTags = HashMap<String, Vec<String>>;
+
+impl GroupBy {
+    /// The groups an event counts toward; "(none)" when it has no key
+    fn keys(&self, event: &ObservabilityEvent, tags: &AgentTags) -> Vec<String> {
+        let none = || vec!["(none)".to_string()];
+        if *self == GroupBy::Tag {
+            return event
+                .agent
+                .as_ref()
+                .and_then(|agent| tags.get(agent))
+                .filter(|tags| !tags.is_empty())
+                .cloned()
+                .unwrap_or_else(none);
+        }
+        self.key(event).map_or_else(none, |key| vec![key])
+    }
+
+    fn key(&self, event: &ObservabilityEvent) -> Option<String> {
+        match self {
+            GroupBy::Model => match &event.payload {
+                Payload::UserMessage(m) => m.model.clone(),
+                Payload::AssistantResponse(r) => r.model.clone(),
+                Payload::Alert(_)
+                | Payload::Error(_)
+                | Payload::Violation(_)
+                | Payload::SessionClosed(_)
+                | Payload::StreamDelta(_)
+                | Payload::StreamAborted(_)
+                | Payload::TurnSnapshot(_)
+                | Payload::Annotation(_) => None,
+            },
+            GroupBy::Agent => event.agent.clone(),
+            GroupBy::Provider => event.provider.clone(),
+            GroupBy::Topic => event.topic.clone(),
+            GroupBy::Tag => None,
+        }
+    }
+
+    fn label(&self) -> &'static str {
+        match self {
+            GroupBy::Model => "model",
+            GroupBy::Agent => "agent",
+            GroupBy::Provider => "provider",
+            GroupBy::Topic => "topic",
+            GroupBy::Tag => "tag",
+        }
+    }
+}
+
+/// Parse a lookback like "90m", "24h", or "7d"
+pub fn parse_window(s: &str) -> Result<Duration, String> {
+    let invalid = || format!("invalid time range '{}': expected e.g. 90s, 90m, 24h, 7d", s);
+    let unit = s.chars().last().ok_or_else(invalid)?;
+    let amount: i64 = s
+        .strip_suffix(unit)
+        .and_then(|n| n.parse().ok())
+        .ok_or_else(invalid)?;
+    match unit {
+        's' => Ok(Duration::seconds(amount)),
+        'm' => Ok(Duration::minutes(amount)),
+        'h' => Ok(Duration::hours(amount)),
+        'd' => Ok(Duration::days(amount)),
+        'w' => Ok(Duration::weeks(amount)),
+        _ => Err(invalid()),
+    }
+}
+
+#[derive(Debug, Default)]
+struct SessionStats {
+    turns: u64,
+    cost: f64,
+    /// Responses whose cost couldn't be estimated
+    unpriced: u64,
+}
+
+#[derive(Debug, Default)]
+struct SessionOutcome {
+    errors: u64,
+    last_stop_reason: Option<String>,
+}
+
+impl SessionOutcome {
+    /// Ended on a normal stop with no failed requests along the way
+    fn succeeded(&self) -> bool {
+        self.errors == 0
+            && matches!(
+                self.last_stop_reason.as_deref(),
+                Some("end_turn" | "stop" | "stop_sequence")
+            )
+    }
+}
+
+#[derive(Debug)]
+pub struct Standing {
+    pub group: String,
+    pub sessions: usize,
+    pub turns_per_session: f64,
+    pub cost_per_session: f64,
+    pub success_rate: f64,
+    pub unpriced: u64,
+}
+
+pub fn rank(
+    events: &[ObservabilityEvent],
+    metric: Metric,
+    group_by: GroupBy,
+    tags: &AgentTags,
+) -> Vec<Standing> {
+    let mut per_group: HashMap<String, HashMap<String, SessionStats>> = HashMap::new();
+    let mut outcomes: HashMap<String, SessionOutcome> = HashMap::new();
+
+    for event in events {
+        let Some(ref session) = event.session_id else {
+            continue;
+        };
+        let outcome = outcomes.entry(session.clone()).or_default();
+        match &event.payload {
+            Payload::Error(_) => outcome.errors += 1,
+            Payload::AssistantResponse(r) => outcome.last_stop_reason = r.stop_reason.clone(),
+            Payload::UserMessage(_)
+            | Payload::Alert(_)
+            | Payload::Violation(_)
+            | Payload::SessionClosed(_)
+            | Payload::StreamDelta(_)
+            | Payload::StreamAborted(_)
+            | Payload::TurnSnapshot(_)
+            | Payload::Annotation(_) => {}
+        }
+
+        let Payload::AssistantResponse(ref response) = event.payload else {
+            continue;
+        };
+        let cost = response
+            .usage
+            .as_ref()
+            .and_then(|u| pricing::cost_usd(response.model.as_deref(), u));
+        for group in group_by.keys(event, tags) {
+            let stats = per_group
+                .entry(group)
+                .or_default()
+                .entry(session.clone())
+                .or_default();
+            stats.turns += 1;
+            match cost {
+                Some(cost) => stats.cost += cost,
+                None => stats.unpriced += 1,
+            }
+        }
+    }
+
+    let mut standings: Vec<Standing> = per_group
+        .into_iter()
+        .map(|(group, sessions)| {
+            let n = sessions.len() as f64;
+            let succeeded = sessions
+                .keys()
+                .filter(|s| outcomes.get(*s).is_some_and(|o| o.succeeded()))
+                .count();
+            Standing {
+                group,
+                sessions: sessions.len(),
+                turns_per_session: sessions.values().map(|s| s.turns as f64).sum::<f64>() / n,
+                cost_per_session: sessions.values().map(|s| s.cost).sum::<f64>() / n,
+                success_rate: succeeded as f64 / n,
+                unpriced: sessions.values().map(|s| s.unpriced).sum(),
+            }
+        })
+        .collect();
+
+    standings.sort_by(|a, b| {
+        let order = match metric {
+            Metric::Cost => a.cost_per_session.total_cmp(&b.cost_per_session),
+            Metric::Turns => a.turns_per_session.total_cmp(&b.turns_per_session),
+            Metric::SuccessRate => b.success_rate.total_cmp(&a.success_rate),
+        };
+        order.then_with(|| b.sessions.cmp(&a.sessions))
+    });
+    standings
+}
+
+pub async fn run(
+    data_dir: &Path,
+    metric: Metric,
+    group_by: GroupBy,
+    since: Duration,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let db_path = data_dir.join("sentinel.db");
+    if !db_path.exists() {
+        println!("No events found. Run 'sentinel start' first to capture some traffic.");
+        return Ok(());
+    }
+    let storage = Storage::new(&db_path).await?;
+    let from: DateTime<Utc> = Utc::now() - since;
+    let events = storage.get_events_since(from).await?;
+    let tags: AgentTags = if group_by == GroupBy::Tag {
+        let agents = AgentStore::new(storage.pool());
+        agents.init_schema().await?;
+        agents
+            .list_all()
+            .await?
+            .into_iter()
+            .map(|agent| (agent.name, agent.tags))
+            .collect()
+    } else {
+        AgentTags::new()
+    };
+
+    let standings = rank(&events, metric, group_by, &tags);
+    if standings.is_empty() {
+        println!("No sessions since {}.", from.format("%Y-%m-%d %H:%M"));
+        return Ok(());
+    }
+
+    let metric_label = match metric {
+        Metric::Cost => "cost per session",
+        Metric::Turns => "turns per session",
+        Metric::SuccessRate => "success rate",
+    };
+    println!(
+        "Leaderboard by {}, grouped by {} (since {})\n",
+        metric_label,
+        group_by.label(),
+        from.format("%Y-%m-%d %H:%M")
+    );
+    println!(
+        "{:>3}  {:<32} {:>8} {:>13} {:>12} {:>8}",
+        "#",
+        group_by.label(),
+        "sessions",
+        "turns/session",
+        "cost/session",
+        "success"
+    );
+    for (i, s) in standings.iter().enumerate() {
+        let group: String = s.group.chars().take(32).collect();
+        println!(
+            "{:>3}  {:<32} {:>8} {:>13.1} {:>12} {:>7.0}%",
+            i + 1,
+            group,
+            s.sessions,
+            s.turns_per_session,
+            format!("${:.4}", s.cost_per_session),
+            s.success_rate * 100.0
+        );
+    }
+
+    let unpriced: u64 = standings.iter().map(|s| s.unpriced).sum();
+    if unpriced > 0 {
+        println!(
+            "\n{} response(s) had no usage or an unknown model price and count as $0",
+            unpriced
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event::AssistantResponse;
+    use crate::parsers::Usage;
+    use uuid::Uuid;
+
+    fn response(session: &str, model: &str, stop: &str, output_tokens: i64) -> ObservabilityEvent {
+        ObservabilityEvent {
+            seq: None,
+            id: Uuid::new_v4(),
+            timestamp: Utc::now(),
+            session_id: Some(session.to_string()),
+            agent: None,
+            topic: None,
+            provider: Some("anthropic".to_string()),
+            payload: Payload::AssistantResponse(AssistantResponse {
+                streaming: true,
+                model: Some(model.to_string()),
+                stop_reason: Some(stop.to_string()),
+                usage: Some(Usage {
+                    input_tokens: Some(0),
+                    output_tokens: Some(output_tokens),
+                    cache_read_tokens: None,
+                    cache_creation_tokens: None,
+                    cost_usd: None,
+                }),
+                ..Default::default()
+            }),
+        }
+    }
+
+    #[test]
+    fn test_ranks_per_session() {
+        let events = vec![
+            response("a", "claude-sonnet-4", "tool_use", 1_000_000),
+            response("a", "claude-sonnet-4", "end_turn", 1_000_000),
+            response("b", "claude-haiku-4-5", "max_tokens", 1_000_000),
+        ];
+
+        let by_cost = rank(&events, Metric::Cost, GroupBy::Model, &AgentTags::new());
+        assert_eq!(by_cost[0].group, "claude-haiku-4-5");
+        assert!((by_cost[1].cost_per_session - 30.0).abs() < 1e-9);
+        assert_eq!(by_cost[1].turns_per_session, 2.0);
+
+        let by_success = rank(&events, Metric::SuccessRate, GroupBy::Model, &AgentTags::new());
+        assert_eq!(by_success[0].group, "claude-sonnet-4");
+        assert_eq!(by_success[0].success_rate, 1.0);
+        assert_eq!(by_success[1].success_rate, 0.0);
+
+        let mut events = events;
+        events[0].agent = Some("calm-otter".to_string());
+        events[1].agent = Some("calm-otter".to_string());
+        events[2].agent = Some("swift-fox".to_string());
+        let tags = AgentTags::from([(
+            "calm-otter".to_string(),
+            vec!["backend".to_string(), "nightly".to_string()],
+        )]);
+        let by_tag = rank(&events, Metric::Turns, GroupBy::Tag, &tags);
+        let groups: Vec<(&str, f64)> = by_tag
+            .iter()
+            .map(|s| (s.group.as_str(), s.turns_per_session))
+            .collect();
+        assert_eq!(groups.len(), 3);
+        assert_eq!(groups[0], ("(none)", 1.0));
+        assert!(groups[1..].contains(&("backend", 2.0)));
+        assert!(groups[1..].contains(&("nightly", 2.0)));
+    }
+
+    #[test]
+    fn test_parse_window() {
+        assert_eq!(parse_window("24h"), Ok(Duration::hours(24)));
+        assert_eq!(parse_window("7d"), Ok(Duration::days(7)));
+        assert_eq!(parse_window("90s"), Ok(Duration::seconds(90)));
+        assert!(parse_window("7").is_err());
+        assert!(parse_window("").is_err());
+        assert!(parse_window("déd").is_err());
+    }
+}
@@ -0,0 +1,182 @@
+//! Several listeners in one process, each with its own routing and policy.
+//!
+//! ```yaml
+//! listeners:
+//!   - port: 9000
+//!     upstream: anthropic        # every request goes here; routed by path and headers when omitted
+//!   - port: 9001
+//!     upstream: openai
+//!     upstream_url: https://llm-gateway.internal/openai  # instead of the provider's public API
+//!     sandbox: openai-sandbox.yaml
+//!     baselines: [reviewer.yaml]
+//!   - port: 9002
+//!     serve: api                 # dashboard API only; `proxy` serves no API; both by default
+//! ```
+//!
+//! Relative sandbox and baseline paths are resolved against the config file's
+//! directory. Storage, the live event stream, guardian approvals, stream
+//! journals, and SLO tracking are shared by every listener.
+
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+
+#[derive(Debug, thiserror::Error)]
+pub enum ListenersError {
+    #[error("failed to read {path}: {source}")]
+    Read {
+        path: String,
+        source: std::io::Error,
+    },
+    #[error("invalid listener config {path}: {source}")]
+    Parse {
+        path: String,
+        source: serde_yaml::Error,
+    },
+    #[error("invalid listener config {path}: {message}")]
+    Invalid { path: String, message: String },
+}
+
+/// What a listener serves
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Serve {
+    /// The proxy and the dashboard API
+    #[default]
+    All,
+    Proxy,
+    Api,
+}
+
+impl Serve {
+    pub fn proxy(self) -> bool {
+        self != Serve::Api
+    }
+
+    pub fn api(self) -> bool {
+        self != Serve::Proxy
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ListenerConfig {
+    pub port: u16,
+    /// Provider every request is forwarded to, e.g. `openai`
+    #[serde(default)]
+    pub upstream: Option<String>,
+    /// Base URL for that provider, or for Anthropic when no provider is pinned
+    #[serde(default)]
+    pub upstream_url: Option<String>,
+    #[serde(default)]
+    pub serve: Serve,
+    #[serde(default)]
+    pub sandbox: Option<PathBuf>,
+    #[serde(default)]
+    pub baselines: Vec<PathBuf>,
+}
+
+impl ListenerConfig {
+    /// The single listener `sentinel start` runs without a config file
+    pub fn single(port: u16) -> Self {
+        Self {
+            port,
+            upstream: None,
+            upstream_url: None,
+            serve: Serve::All,
+            sandbox: None,
+            baselines: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ListenersFile {
+    listeners: Vec<ListenerConfig>,
+}
+
+pub fn load(path: &Path) -> Result<Vec<ListenerConfig>, ListenersError> {
+    let origin = path.display().to_string();
+    let text = std::fs::read_to_string(path).map_err(|source| ListenersError::Read {
+        path: origin.clone(),
+        source,
+    })?;
+    let mut listeners = parse(&text, &origin)?;
+
+    let base = path.parent().unwrap_or(Path::new("."));
+    for listener in &mut listeners {
+        if let Some(ref mut sandbox) = listener.sandbox {
+            *sandbox = base.join(&*sandbox);
+        }
+        for baseline in &mut listener.baselines {
+            *baseline = base.join(&*baseline);
+        }
+    }
+    Ok(listeners)
+}
+
+fn parse(yaml: &str, origin: &str) -> Result<Vec<ListenerConfig>, ListenersError> {
+    let file: ListenersFile =
+        serde_yaml::from_str(yaml).map_err(|source| ListenersError::Parse {
+            path: origin.to_string(),
+            source,
+        })?;
+    let invalid = |message: String| ListenersError::Invalid {
+        path: origin.to_string(),
+        message,
+    };
+
+    if file.listeners.is_empty() {
+        return Err(invalid("no listeners defined".to_string()));
+    }
+    let mut ports = HashSet::new();
+    for listener in &file.listeners {
+        if !ports.insert(listener.port) {
+            return Err(invalid(format!("port {} is listed twice", listener.port)));
+        }
+        let has_policy = listener.sandbox.is_some() || !listener.baselines.is_empty();
+        if let Some(ref url) = listener.upstream_url {
+            if let Err(e) = reqwest::Url::parse(url) {
+                return Err(invalid(format!(
+                    "port {}: upstream_url {}: {}",
+                    listener.port, url, e
+                )));
+            }
+        }
+        let has_upstream = listener.upstream.is_some() || listener.upstream_url.is_some();
+        if !listener.serve.proxy() && (has_upstream || has_policy) {
+            return Err(invalid(format!(
+                "port {} only serves the API, so upstreams, sandbox, and baselines don't apply",
+                listener.port
+            )));
+        }
+    }
+    Ok(file.listeners)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_listeners() {
+        let listeners = parse(
+            "listeners:\n  - port: 9000\n    upstream: anthropic\n  - port: 9001\n    upstream: openai\n    sandbox: sb.yaml\n  - port: 9002\n    serve: api\n",
+            "listeners.yaml",
+        )
+        .unwrap();
+        assert_eq!(listeners.len(), 3);
+        assert_eq!(listeners[1].upstream.as_deref(), Some("openai"));
+        assert!(listeners[0].serve.proxy() && listeners[0].serve.api());
+        assert!(!listeners[2].serve.proxy());
+
+        assert!(parse("listeners:\n  - port: 9000\n  - port: 9000\n", "x").is_err());
+        assert!(parse(
+            "listeners:\n  - port: 9002\n    serve: api\n    upstream: openai\n",
+            "x"
+        )
+        .is_err());
+    }
+}
@@ -0,0 +1,238 @@
+//! Data-dir locking, so two proxies don't record into one database by accident.
+//!
+//! `sentinel start` takes an exclusive lock on `sentinel.lock` in the data
+//! dir for as long as it runs. The lock is an OS file lock, so it is released
+//! even if the process crashes. Instances started with `--shared` take a
+//! shared lock instead: they can run side by side (but not alongside an
+//! exclusive one) and register themselves in the database, heartbeating so
+//! each can see who else is recording.
+
+use std::fs::{File, OpenOptions, TryLockError};
+use std::io::Write;
+use std::path::Path;
+
+use chrono::{Duration, Utc};
+use sqlx::SqlitePool;
+use uuid::Uuid;
+
+const LOCK_FILE: &str = "sentinel.lock";
+/// How often shared instances refresh their registration
+pub const HEARTBEAT_SECS: u64 = 30;
+/// Registrations older than this belong to instances that are gone
+const INSTANCE_TTL_SECS: i64 = 3 * HEARTBEAT_SECS as i64;
+
+#[derive(Debug, thiserror::Error)]
+pub enum LockError {
+    #[error("failed to open {path}: {source}")]
+    Io {
+        path: String,
+        source: std::io::Error,
+    },
+    #[error("another sentinel ({holder}) is already recording into {data_dir}. Stop it, set SENTINEL_DATA_DIR to another directory, or run every instance with --shared")]
+    Held { data_dir: String, holder: String },
+    #[error("sentinel instances started with --shared are recording into {data_dir}; pass --shared to run alongside them")]
+    HeldShared { data_dir: String },
+}
+
+/// Held for the life of the proxy; dropping it releases the lock
+pub struct DataDirLock {
+    _file: File,
+}
+
+impl DataDirLock {
+    pub fn acquire(data_dir: &Path, shared: bool, ports: &[u16]) -> Result<Self, LockError> {
+        let path = data_dir.join(LOCK_FILE);
+        let io_error = |source| LockError::Io {
+            path: path.display().to_string(),
+            source,
+        };
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&path)
+            .map_err(io_error)?;
+
+        let locked = if shared {
+            file.try_lock_shared()
+        } else {
+            file.try_lock()
+        };
+        match locked {
+            Ok(()) => {}
+            Err(TryLockError::WouldBlock) => {
+                let data_dir = data_dir.display().to_string();
+                let holder = read_holder(&path).unwrap_or_else(|| "unknown process".to_string());
+                return Err(if !shared && holder.starts_with("shared") {
+                    LockError::HeldShared { data_dir }
+                } else {
+                    LockError::Held { data_dir, holder }
+                });
+            }
+            Err(TryLockError::Error(e)) => return Err(io_error(e)),
+        }
+
+        // Record who holds the lock for the error message other instances show
+        let ports: Vec<String> = ports.iter().map(|p| p.to_string()).collect();
+        let holder = format!(
+            "{}pid {}, port {}",
+            if shared { "shared, " } else { "" },
+            std::process::id(),
+            ports.join(",")
+        );
+        let written = file
+            .set_len(0)
+            .and_then(|()| file.write_all(holder.as_bytes()));
+        if let Err(e) = written {
+            tracing::warn!("Failed to record lock holder in {}: {}", path.display(), e);
+        }
+        Ok(Self { _file: file })
+    }
+}
+
+/// Ports of the sentinel holding the data dir's lock exclusively, if one is
+/// running (shared instances are in the registry instead)
+pub fn exclusive_ports(data_dir: &Path) -> Vec<u16> {
+    let path = data_dir.join(LOCK_FILE);
+    let Ok(file) = File::open(&path) else {
+        return Vec::new();
+    };
+    // Only an exclusive holder keeps a shared lock out
+    if !matches!(file.try_lock_shared(), Err(TryLockError::WouldBlock)) {
+        return Vec::new();
+    }
+    read_holder(&path)
+        .and_then(|holder| Some(parse_ports(holder.rsplit_once("port ")?.1)))
+        .unwrap_or_default()
+}
+
+/// Comma-separated ports, as lock holders and registrations list them
+pub fn parse_ports(ports: &str) -> Vec<u16> {
+    ports
+        .split(',')
+        .filter_map(|p| p.trim().parse().ok())
+        .collect()
+}
+
+fn read_holder(path: &Path) -> Option<String> {
+    let holder = std::fs::read_to_string(path).ok()?;
+    let holder = holder.trim();
+    if holder.is_empty() {
+        None
+    } else {
+        Some(holder.to_string())
+    }
+}
+
+#[derive(Debug, sqlx::FromRow)]
+pub struct Instance {
+    pub pid: i64,
+    pub ports: String,
+    pub started_at: String,
+}
+
+/// Instances sharing one data dir, coordinated through the database
+#[derive(Clone)]
+pub struct InstanceRegistry {
+    pool: SqlitePool,
+}
+
+impl InstanceRegistry {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool }
+    }
+
+    pub async fn init_schema(&self) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS instances (
+                id TEXT PRIMARY KEY,
+                pid INTEGER NOT NULL,
+                ports TEXT NOT NULL,
+                started_at TEXT NOT NULL,
+                last_seen_at TEXT NOT NULL
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    pub async fn register(&self, ports: &[u16]) -> Result<Uuid, sqlx::Error> {
+        let id = Uuid::new_v4();
+        let now = Utc::now().to_rfc3339();
+        let ports: Vec<String> = ports.iter().map(|p| p.to_string()).collect();
+        sqlx::query(
+            r#"
+            INSERT INTO instances (id, pid, ports, started_at, last_seen_at)
+            VALUES (?, ?, ?, ?, ?)
+            "#,
+        )
+        .bind(id.to_string())
+        .bind(std::process::id() as i64)
+        .bind(ports.join(","))
+        .bind(&now)
+        .bind(&now)
+        .execute(&self.pool)
+        .await?;
+        Ok(id)
+    }
+
+    pub async fn heartbeat(&self, id: Uuid) -> Result<(), sqlx::Error> {
+        sqlx::query("UPDATE instances SET last_seen_at = ? WHERE id = ?")
+            .bind(Utc::now().to_rfc3339())
+            .bind(id.to_string())
+            .execute(&self.pool)
+            .await?;
+        let cutoff = (Utc::now() - Duration::seconds(INSTANCE_TTL_SECS * 10)).to_rfc3339();
+        sqlx::query("DELETE FROM instances WHERE last_seen_at < ?")
+            .bind(cutoff)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    /// Instances that have heartbeated recently
+    pub async fn live(&self) -> Result<Vec<Instance>, sqlx::Error> {
+        let cutoff = (Utc::now() - Duration::seconds(INSTANCE_TTL_SECS)).to_rfc3339();
+        sqlx::query_as(
+            r#"
+            SELECT pid, ports, started_at
+            FROM instances
+            WHERE last_seen_at >= ?
+            ORDER BY started_at ASC
+            "#,
+        )
+        .bind(cutoff)
+        .fetch_all(&self.pool)
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_exclusive_and_shared_locks_exclude_each_other() {
+        let dir = std::env::temp_dir().join(format!("sentinel-lock-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let first = DataDirLock::acquire(&dir, false, &[9000]).unwrap();
+        let err = DataDirLock::acquire(&dir, false, &[9001]).err().unwrap();
+        assert!(err.to_string().contains("port 9000"));
+        assert!(DataDirLock::acquire(&dir, true, &[9001]).is_err());
+        drop(first);
+
+        let a = DataDirLock::acquire(&dir, true, &[9000]).unwrap();
+        let b = DataDirLock::acquire(&dir, true, &[9001]).unwrap();
+        assert!(matches!(
+            DataDirLock::acquire(&dir, false, &[9002]),
+            Err(LockError::HeldShared { .. })
+        ));
+        drop((a, b));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,12 +1,96 @@
 mod agent;
+mod anonymize;
+mod annotations;
+mod approvals;
+mod attribution;
+mod baseline;
+mod bodies;
+mod branches;
+mod budgets;
+mod bypass;
 mod cli;
+mod code;
+mod commands;
+mod compat;
+mod completions;
+mod config;
+mod container;
+mod copy;
+mod costs;
+mod derived;
+mod diagnostics;
+mod diff;
+mod doctor;
+mod email;
+mod encrypt;
+mod embeddings;
 mod event;
+mod export;
+mod files;
+mod fixtures;
+mod fold;
+mod git;
+mod history;
+mod import;
+mod inspect;
+mod keys;
+mod launcher;
+mod leaderboard;
+mod listeners;
+mod lock;
+mod mirror;
+mod notes;
+mod origin;
+mod otlp;
+mod overhead;
+mod palette;
 mod parsers;
+mod paths;
+mod policy;
+mod presence;
+mod pricing;
+mod profiles;
+mod projects;
 mod proxy;
+mod queue;
+mod reconcile;
+mod redact;
+mod redo;
+mod replay;
+mod resume;
+mod retry;
+mod rollup;
+mod routing;
+mod rpc;
+mod runs;
+mod sandbox;
+mod schema;
+mod scope;
+mod search;
+mod service;
+mod sessions;
+mod shards;
+mod similar;
+mod slo;
+mod spool;
 mod sse;
+mod static_cache;
 mod storage;
+mod streams;
+mod structured;
+mod subagents;
+mod tail;
+mod thinking;
+mod tools;
+mod transcript;
+mod waiting;
+mod watch;
+mod watchdog;
+mod withhold;
+mod worktree;
 
-#[tokio::main]
-async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    cli::run().await
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    // Shell completion requests are answered before any runtime starts
+    completions::complete();
+    tokio::runtime::Runtime::new()?.block_on(cli::run())
 }
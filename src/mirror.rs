@@ -0,0 +1,227 @@
+//! Request/response pairs mirrored to a directory (`--mirror-dir`).
+//!
+//! Each exchange the proxy forwards is written as one numbered JSON file
+//! (`000042-<event id>.json`) holding the forwarded request and the
+//! upstream's response, headers and bodies in full, so exact payloads can be
+//! handed to another tool or attached to a provider bug report. JSON bodies
+//! are kept as JSON; anything else (such as an event stream) as text.
+//! Credentials are left out and `--redact` applies, but `--max-raw-kb` does
+//! not. A request body over `--max-request-kb` is mirrored as the shortened
+//! copy the proxy kept, marked with `truncated_from_bytes`, the size that
+//! was sent. Numbering continues from the highest file already there.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use bytes::Bytes;
+use chrono::{DateTime, Utc};
+use reqwest::header::HeaderMap;
+use serde::Serialize;
+use tracing::warn;
+use uuid::Uuid;
+
+use crate::redact::Redactor;
+
+/// Headers never written out
+const CREDENTIAL_HEADERS: &[&str] = &["x-api-key", "authorization", "cookie", "set-cookie"];
+
+/// The request as it was forwarded upstream, and who sent it
+pub struct MirroredRequest {
+    /// Id the response is recorded under
+    pub event_id: Uuid,
+    pub session_id: Option<String>,
+    pub agent: Option<String>,
+    pub method: String,
+    pub url: String,
+    pub headers: HeaderMap,
+    pub body: Bytes,
+    /// Size of the body sent upstream, when `body` is only a shortened copy
+    pub truncated_from: Option<usize>,
+}
+
+#[derive(Serialize)]
+struct Exchange {
+    event_id: Uuid,
+    timestamp: DateTime<Utc>,
+    session_id: Option<String>,
+    agent: Option<String>,
+    request: RequestSide,
+    response: ResponseSide,
+}
+
+#[derive(Serialize)]
+struct RequestSide {
+    method: String,
+    url: String,
+    headers: BTreeMap<String, String>,
+    body: serde_json::Value,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    truncated_from_bytes: Option<usize>,
+}
+
+#[derive(Serialize)]
+struct ResponseSide {
+    status: u16,
+    headers: BTreeMap<String, String>,
+    body: serde_json::Value,
+}
+
+pub struct Mirror {
+    dir: PathBuf,
+    next: AtomicU64,
+}
+
+/// The number a mirror file starts with
+fn number_of(name: &str) -> Option<u64> {
+    name.split('-').next()?.parse().ok()
+}
+
+impl Mirror {
+    pub fn new(dir: &Path) -> std::io::Result<Self> {
+        std::fs::create_dir_all(dir)?;
+        let mut last = 0;
+        for entry in std::fs::read_dir(dir)? {
+            let name = entry?.file_name();
+            if let Some(n) = name.to_str().and_then(number_of) {
+                last = last.max(n);
+            }
+        }
+        Ok(Self {
+            dir: dir.to_path_buf(),
+            next: AtomicU64::new(last + 1),
+        })
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Write one exchange; failures are logged, never passed to the client
+    pub async fn write(
+        &self,
+        redactor: &Redactor,
+        request: &MirroredRequest,
+        status: u16,
+        response_headers: &HeaderMap,
+        response_body: &[u8],
+    ) {
+        let event_id = request.event_id;
+        let exchange = Exchange {
+            event_id,
+            timestamp: Utc::now(),
+            session_id: request.session_id.clone(),
+            agent: request.agent.clone(),
+            request: RequestSide {
+                method: request.method.clone(),
+                url: request.url.clone(),
+                headers: headers(&request.headers),
+                body: body(redactor, &request.body),
+                truncated_from_bytes: request.truncated_from,
+            },
+            response: ResponseSide {
+                status,
+                headers: headers(response_headers),
+                body: body(redactor, response_body),
+            },
+        };
+        let n = self.next.fetch_add(1, Ordering::Relaxed);
+        let path = self.dir.join(format!("{:06}-{}.json", n, event_id));
+        let json = match serde_json::to_vec_pretty(&exchange) {
+            Ok(json) => json,
+            Err(e) => {
+                warn!("Failed to serialize mirrored exchange {}: {}", event_id, e);
+                return;
+            }
+        };
+        if let Err(e) = tokio::fs::write(&path, json).await {
+            warn!("Failed to write mirror file {}: {}", path.display(), e);
+        }
+    }
+}
+
+fn headers(headers: &HeaderMap) -> BTreeMap<String, String> {
+    headers
+        .iter()
+        .filter(|(name, _)| !CREDENTIAL_HEADERS.contains(&name.as_str()))
+        .map(|(name, value)| {
+            (
+                name.to_string(),
+                String::from_utf8_lossy(value.as_bytes()).into_owned(),
+            )
+        })
+        .collect()
+}
+
+/// A body as JSON when it parses, otherwise as text
+fn body(redactor: &Redactor, bytes: &[u8]) -> serde_json::Value {
+    let bytes = redactor.bytes(bytes);
+    serde_json::from_slice(&bytes)
+        .unwrap_or_else(|_| serde_json::Value::String(String::from_utf8_lossy(&bytes).into_owned()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_writes_numbered_exchanges_without_credentials() {
+        let dir = std::env::temp_dir().join(format!("sentinel-mirror-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("000007-old.json"), "{}").unwrap();
+
+        let mirror = Mirror::new(&dir).unwrap();
+        let mut request_headers = HeaderMap::new();
+        request_headers.insert("x-api-key", "sk-secret".parse().unwrap());
+        request_headers.insert("anthropic-version", "2023-06-01".parse().unwrap());
+        let id = Uuid::new_v4();
+        let request = MirroredRequest {
+            event_id: id,
+            session_id: None,
+            agent: Some("calm-otter".to_string()),
+            method: "POST".to_string(),
+            url: "https://api.anthropic.com/v1/messages".to_string(),
+            headers: request_headers,
+            body: Bytes::from_static(br#"{"model":"claude-sonnet-4"}"#),
+            truncated_from: None,
+        };
+        mirror
+            .write(
+                &Redactor::default(),
+                &request,
+                200,
+                &HeaderMap::new(),
+                b"event: message_stop\n\n",
+            )
+            .await;
+
+        let written = std::fs::read_to_string(dir.join(format!("000008-{}.json", id))).unwrap();
+        let exchange: serde_json::Value = serde_json::from_str(&written).unwrap();
+        assert_eq!(exchange["request"]["body"]["model"], "claude-sonnet-4");
+        assert_eq!(
+            exchange["request"]["headers"]["anthropic-version"],
+            "2023-06-01"
+        );
+        assert!(exchange["request"]["headers"].get("x-api-key").is_none());
+        assert_eq!(exchange["response"]["body"], "event: message_stop\n\n");
+        assert!(exchange["request"].get("truncated_from_bytes").is_none());
+
+        // A spooled body's shortened copy says so
+        let spooled = MirroredRequest {
+            event_id: Uuid::new_v4(),
+            truncated_from: Some(20 * 1024 * 1024),
+            headers: HeaderMap::new(),
+            ..request
+        };
+        mirror
+            .write(&Redactor::default(), &spooled, 200, &HeaderMap::new(), b"{}")
+            .await;
+        let written =
+            std::fs::read_to_string(dir.join(format!("000009-{}.json", spooled.event_id)))
+                .unwrap();
+        let exchange: serde_json::Value = serde_json::from_str(&written).unwrap();
+        assert_eq!(exchange["request"]["truncated_from_bytes"], 20 * 1024 * 1024);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,227 @@
+//! Session notes for a Markdown knowledge base (`--notes-dir`).
+//!
+//! When a session closes, its transcript is written as a note under
+//! `sessions/`, in the same format as `sentinel export --format markdown`,
+//! and a line linking to it is added to that day's index (`2026-01-05.md`).
+//! Links are `[[wiki links]]`, so an Obsidian vault or any daily-notes setup
+//! pointed at the directory picks both up as they're written.
+
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Utc};
+use thiserror::Error;
+use tokio::io::AsyncWriteExt;
+use tokio::sync::broadcast::{self, error::RecvError};
+use tracing::{info, warn};
+
+use crate::event::{ObservabilityEvent, Payload, SessionClosed};
+use crate::export::markdown;
+use crate::sessions;
+use crate::storage::Storage;
+
+#[derive(Debug, Error)]
+pub enum NotesError {
+    #[error("{0}")]
+    Io(#[from] std::io::Error),
+    #[error("loading the session: {0}")]
+    Storage(#[from] sqlx::Error),
+    #[error("front-matter: {0}")]
+    Yaml(#[from] serde_yaml::Error),
+}
+
+pub struct NotesSink {
+    dir: PathBuf,
+    storage: Storage,
+}
+
+/// Characters that can't appear in a note name or wiki link
+fn safe_name(name: &str) -> String {
+    name.chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' | '[' | ']' | '#' | '^' => '-',
+            c => c,
+        })
+        .collect()
+}
+
+/// The session note's name, without `.md`, e.g. `2026-01-05-1000-calm-otter-1a2b3c4d`
+fn note_name(agent: Option<&str>, started_at: DateTime<Utc>, session_id: &str) -> String {
+    format!(
+        "{}-{}-{}",
+        started_at.format("%Y-%m-%d-%H%M"),
+        safe_name(agent.unwrap_or("agent")),
+        safe_name(&session_id.chars().take(8).collect::<String>())
+    )
+}
+
+/// The session's line in the daily index
+fn index_line(event: &ObservabilityEvent, closed: &SessionClosed, note: &str) -> String {
+    let agent = event.agent.as_deref().unwrap_or("agent");
+    let mut line = format!(
+        "- {}–{} [[sessions/{}|{}]] · {} turns · ${:.4}",
+        closed.started_at.format("%H:%M"),
+        closed.ended_at.format("%H:%M"),
+        note,
+        agent,
+        closed.turns,
+        closed.cost_usd
+    );
+    if closed.errors > 0 {
+        line.push_str(&format!(" · {} errors", closed.errors));
+    }
+    if let Some(ref topic) = event.topic {
+        line.push_str(&format!(" · {}", topic));
+    }
+    line.push('\n');
+    line
+}
+
+impl NotesSink {
+    pub fn new(dir: &Path, storage: Storage) -> std::io::Result<Self> {
+        std::fs::create_dir_all(dir.join("sessions"))?;
+        Ok(Self {
+            dir: dir.to_path_buf(),
+            storage,
+        })
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Write a note for each session as it closes
+    pub fn spawn(self, mut events: broadcast::Receiver<ObservabilityEvent>) {
+        tokio::spawn(async move {
+            loop {
+                let event = match events.recv().await {
+                    Ok(event) => event,
+                    Err(RecvError::Lagged(n)) => {
+                        warn!("Notes sink fell behind; {} event(s) not checked", n);
+                        continue;
+                    }
+                    Err(RecvError::Closed) => break,
+                };
+                if let Err(e) = self.write(&event).await {
+                    warn!("Failed to write session note for {}: {}", event.id, e);
+                }
+            }
+        });
+    }
+
+    /// Write the note for a `session_closed` event; other events are skipped
+    async fn write(&self, event: &ObservabilityEvent) -> Result<(), NotesError> {
+        let (Payload::SessionClosed(closed), Some(session_id)) =
+            (&event.payload, event.session_id.as_deref())
+        else {
+            return Ok(());
+        };
+        let events = self.storage.get_session_events(session_id).await?;
+        let end = events
+            .iter()
+            .position(|e| e.id == event.id)
+            .unwrap_or(events.len());
+        let session = sessions::since_last_close(&events[..end]);
+        if session.is_empty() {
+            return Ok(());
+        }
+
+        let note = note_name(event.agent.as_deref(), closed.started_at, session_id);
+        let path = self.dir.join("sessions").join(format!("{}.md", note));
+        tokio::fs::write(&path, markdown::render(session)?).await?;
+
+        let day = closed.started_at.format("%Y-%m-%d");
+        let mut index = tokio::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.dir.join(format!("{}.md", day)))
+            .await?;
+        let mut text = String::new();
+        if index.metadata().await?.len() == 0 {
+            text.push_str(&format!("# Agent sessions {}\n\n", day));
+        }
+        text.push_str(&index_line(event, closed, &note));
+        index.write_all(text.as_bytes()).await?;
+        // tokio finishes writes in the background unless flushed
+        index.flush().await?;
+        info!("Wrote session note {}", path.display());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event::UserMessage;
+    use chrono::TimeZone;
+    use uuid::Uuid;
+
+    fn event(minute: i64, payload: Payload) -> ObservabilityEvent {
+        ObservabilityEvent {
+            session_id: Some("1a2b3c4d-5e6f".to_string()),
+            ..ObservabilityEvent::for_tests(minute * 60, payload)
+        }
+    }
+
+    fn prompt(text: &str) -> Payload {
+        Payload::UserMessage(UserMessage {
+            text: text.to_string(),
+            ..Default::default()
+        })
+    }
+
+    fn closed(started: u32, ended: u32) -> Payload {
+        Payload::SessionClosed(SessionClosed {
+            started_at: Utc.with_ymd_and_hms(2026, 1, 5, 10, started, 0).unwrap(),
+            ended_at: Utc.with_ymd_and_hms(2026, 1, 5, 10, ended, 0).unwrap(),
+            duration_secs: i64::from(ended - started) * 60,
+            turns: 1,
+            requests: 0,
+            errors: 0,
+            input_tokens: 0,
+            output_tokens: 0,
+            cost_usd: 0.0,
+        })
+    }
+
+    #[tokio::test]
+    async fn test_closed_session_gets_a_note_and_an_index_line() {
+        let root = std::env::temp_dir().join(format!("sentinel-notes-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&root).unwrap();
+        let storage = Storage::new(&root.join("sentinel.db")).await.unwrap();
+        let first = event(40, closed(0, 1));
+        let second = event(59, closed(50, 51));
+        for event in [
+            event(0, prompt("Fix the failing test")),
+            first.clone(),
+            event(50, prompt("Now add a changelog entry")),
+            second.clone(),
+        ] {
+            storage.insert_observability_event(&event).await.unwrap();
+        }
+
+        let sink = NotesSink::new(&root.join("notes"), storage).unwrap();
+        sink.write(&first).await.unwrap();
+        sink.write(&second).await.unwrap();
+
+        let notes = root.join("notes");
+        let note =
+            std::fs::read_to_string(notes.join("sessions/2026-01-05-1050-calm-otter-1a2b3c4d.md"))
+                .unwrap();
+        assert!(note.starts_with("---\n"));
+        assert!(note.contains("Now add a changelog entry"));
+        // Only the turns since the session last closed
+        assert!(!note.contains("Fix the failing test"));
+
+        let index = std::fs::read_to_string(notes.join("2026-01-05.md")).unwrap();
+        assert_eq!(
+            index,
+            "# Agent sessions 2026-01-05\n\n\
+             - 10:00–10:01 [[sessions/2026-01-05-1000-calm-otter-1a2b3c4d|calm-otter]] \
+             · 1 turns · $0.0000\n\
+             - 10:50–10:51 [[sessions/2026-01-05-1050-calm-otter-1a2b3c4d|calm-otter]] \
+             · 1 turns · $0.0000\n"
+        );
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
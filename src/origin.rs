@@ -0,0 +1,124 @@
+//! Keeping other websites from driving the API through a browser.
+//!
+//! sentinel listens on localhost and asks for no credentials, so any page
+//! open in the user's browser can send it requests. Browsers say where those
+//! come from in `Origin` (and `Sec-Fetch-Site`), and won't send a JSON body
+//! to another origin without a CORS preflight, which sentinel never answers.
+//! Endpoints that change state check both: requests from another site are
+//! refused, and those with a body must send it as `application/json`.
+//!
+//! Only loopback origins and those allowed with `--allow-origin` count as
+//! this site. The `Host` header proves nothing: a DNS-rebinding page sets
+//! both it and its own origin to a name that resolves here.
+
+use axum::http::{header, HeaderMap, StatusCode};
+
+/// Whether a host is this machine
+fn is_loopback(host: &str) -> bool {
+    matches!(host, "localhost" | "127.0.0.1" | "[::1]")
+}
+
+/// A host without its port
+fn hostname(host: &str) -> &str {
+    match host.rfind(':') {
+        // An IPv6 literal's colons are inside its brackets
+        Some(colon) if !host[colon..].contains(']') => &host[..colon],
+        _ => host,
+    }
+}
+
+/// Whether a request came from a page on another site: one whose origin is
+/// neither loopback nor in `allowed` (full origins, e.g. `http://devbox:9000`)
+pub fn cross_site(headers: &HeaderMap, allowed: &[String]) -> bool {
+    let value = |name| headers.get(name).and_then(|v| v.to_str().ok());
+    let origin = value(header::ORIGIN.as_str());
+    if origin.is_some_and(|origin| {
+        allowed
+            .iter()
+            .any(|a| a.trim_end_matches('/').eq_ignore_ascii_case(origin))
+    }) {
+        return false;
+    }
+    if value("sec-fetch-site").is_some_and(|site| site == "cross-site") {
+        return true;
+    }
+    let Some(origin) = origin else {
+        // Not sent by a browser, or a same-origin GET
+        return false;
+    };
+    let Some(authority) = origin
+        .strip_prefix("http://")
+        .or_else(|| origin.strip_prefix("https://"))
+    else {
+        // "null": a sandboxed frame, a file, or a redirect
+        return true;
+    };
+    !is_loopback(hostname(authority))
+}
+
+/// Refuse a request from another site, or one whose body isn't declared as
+/// JSON when `json_body` is set
+pub fn check(
+    headers: &HeaderMap,
+    json_body: bool,
+    allowed: &[String],
+) -> Result<(), (StatusCode, &'static str)> {
+    if cross_site(headers, allowed) {
+        return Err((StatusCode::FORBIDDEN, "requests from other sites are refused"));
+    }
+    let json = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.split(';').next())
+        .is_some_and(|v| v.trim().eq_ignore_ascii_case("application/json"));
+    if json_body && !json {
+        return Err((
+            StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            "send the body as content-type: application/json",
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn headers(pairs: &[(&'static str, &'static str)]) -> HeaderMap {
+        pairs
+            .iter()
+            .map(|(name, value)| (header::HeaderName::from_static(name), value.parse().unwrap()))
+            .collect()
+    }
+
+    #[test]
+    fn test_other_sites_and_form_posts_are_refused() {
+        let json = ("content-type", "application/json; charset=utf-8");
+        assert!(check(&headers(&[json]), true, &[]).is_ok());
+        let local = headers(&[json, ("origin", "http://localhost:5173")]);
+        assert!(check(&local, true, &[]).is_ok());
+        assert!(check(&headers(&[json, ("origin", "http://[::1]:9000")]), true, &[]).is_ok());
+
+        let refused = check(&headers(&[json, ("origin", "https://evil.example")]), true, &[]);
+        assert_eq!(refused.unwrap_err().0, StatusCode::FORBIDDEN);
+        assert!(check(&headers(&[json, ("origin", "null")]), true, &[]).is_err());
+        assert!(check(&headers(&[("sec-fetch-site", "cross-site")]), false, &[]).is_err());
+        let lookalike = headers(&[("origin", "http://localhost.evil.example")]);
+        assert!(check(&lookalike, false, &[]).is_err());
+
+        // A rebound name matches its own Host, which proves nothing
+        let rebound = headers(&[json, ("origin", "http://evil.example:9000")]);
+        let mut rebound_host = rebound.clone();
+        rebound_host.insert(header::HOST, "evil.example:9000".parse().unwrap());
+        assert!(check(&rebound_host, true, &[]).is_err());
+
+        // Other hosts only when allowed
+        let devbox = headers(&[json, ("origin", "http://devbox:9000")]);
+        assert!(check(&devbox, true, &[]).is_err());
+        assert!(check(&devbox, true, &["http://devbox:9000/".to_string()]).is_ok());
+
+        let form = headers(&[("content-type", "application/x-www-form-urlencoded")]);
+        assert_eq!(check(&form, true, &[]).unwrap_err().0, StatusCode::UNSUPPORTED_MEDIA_TYPE);
+        assert!(check(&form, false, &[]).is_ok());
+    }
+}
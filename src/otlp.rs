@@ -0,0 +1,1203 @@
+//! OpenTelemetry GenAI spans (`POST /api/otlp/v1/traces`).
+//!
+//! Agents that don't go through the proxy (LangChain, the OpenAI Agents SDK,
+//! anything instrumented with the GenAI semantic conventions) can still be
+//! recorded by pointing their OTLP/HTTP trace exporter at sentinel:
+//! `OTEL_EXPORTER_OTLP_TRACES_ENDPOINT=http://localhost:9000/api/otlp/v1/traces`.
+//! Both OTLP encodings are accepted, protobuf and JSON, uncompressed, and
+//! must say which in `Content-Type`. A browser can send a body without one
+//! from any page, so those are refused, as are requests from other sites.
+//!
+//! Each model call span (`gen_ai.operation.name` of `chat`,
+//! `text_completion`, or `generate_content`, or one naming a model without an
+//! operation) becomes a `user_message` and an `assistant_response` event, or
+//! an `error` event for a span with an error status. The agent is
+//! `gen_ai.agent.name`, else the resource's `service.name`; the session is
+//! `gen_ai.conversation.id`, else the trace id. Prompts and replies are read
+//! from `gen_ai.input.messages` and `gen_ai.output.messages`, from the older
+//! `gen_ai.prompt.N.*` and `gen_ai.completion.N.*` attributes, or from
+//! `gen_ai.user.message` and `gen_ai.choice` span events, whichever the
+//! instrumentation sends. Other spans (tool executions, agent invocations,
+//! embeddings, HTTP calls) are ignored.
+//!
+//! Event ids come from the trace and span ids, so a batch the exporter sends
+//! twice is recorded once.
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use axum::body::Bytes;
+use axum::extract::State;
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use chrono::{DateTime, Utc};
+use serde_json::Value;
+use uuid::Uuid;
+
+use crate::event::{
+    AssistantResponse, ErrorEvent, ErrorKind, ObservabilityEvent, Payload, UserMessage,
+};
+use crate::launcher;
+use crate::origin;
+use crate::parsers::{ToolCall, ToolResult, Usage};
+use crate::pricing;
+use crate::proxy::ProxyState;
+
+/// `gen_ai.operation.name` values of a call to a model
+const MODEL_OPERATIONS: &[&str] = &["chat", "text_completion", "generate_content"];
+/// Deepest attribute values are nested in arrays and key-value lists. JSON
+/// bodies are held to serde_json's own recursion limit.
+const MAX_DEPTH: usize = 32;
+
+#[derive(Debug, thiserror::Error)]
+pub enum OtlpError {
+    #[error("invalid OTLP JSON: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("invalid OTLP protobuf: {0}")]
+    Protobuf(&'static str),
+    #[error("unsupported content type '{0}': send application/x-protobuf or application/json")]
+    ContentType(String),
+    #[error("no content type: send application/x-protobuf or application/json")]
+    NoContentType,
+    #[error("compressed bodies aren't supported: turn off the exporter's compression")]
+    Compressed,
+}
+
+/// Attribute values as JSON: strings, numbers, booleans, arrays, and objects
+/// for key-value lists
+type Attributes = BTreeMap<String, Value>;
+
+#[derive(Debug, Default)]
+struct SpanEvent {
+    name: String,
+    attributes: Attributes,
+}
+
+#[derive(Debug, Default)]
+struct Span {
+    /// Hex, as in OTLP/JSON
+    trace_id: String,
+    span_id: String,
+    start_ns: u64,
+    end_ns: u64,
+    attributes: Attributes,
+    events: Vec<SpanEvent>,
+    error: bool,
+    status_message: Option<String>,
+}
+
+/// The spans of one resource
+#[derive(Debug, Default)]
+struct ResourceSpans {
+    resource: Attributes,
+    spans: Vec<Span>,
+}
+
+/// A model call read from a span
+#[derive(Debug)]
+struct Exchange {
+    agent: Option<String>,
+    session_id: String,
+    provider: Option<String>,
+    request_id: Uuid,
+    response_id: Uuid,
+    started: DateTime<Utc>,
+    ended: DateTime<Utc>,
+    request: UserMessage,
+    response: Result<AssistantResponse, ErrorEvent>,
+}
+
+fn str_attr<'a>(attributes: &'a Attributes, key: &str) -> Option<&'a str> {
+    attributes.get(key)?.as_str().filter(|s| !s.is_empty())
+}
+
+fn int_attr(attributes: &Attributes, key: &str) -> Option<i64> {
+    match attributes.get(key)? {
+        Value::Number(n) => n.as_i64().or_else(|| n.as_f64().map(|f| f as i64)),
+        Value::String(s) => s.parse().ok(),
+        _ => None,
+    }
+}
+
+/// An attribute holding JSON, as a string or already structured
+fn json_attr(attributes: &Attributes, key: &str) -> Option<Value> {
+    match attributes.get(key)? {
+        Value::String(s) => serde_json::from_str(s).ok(),
+        other => Some(other.clone()),
+    }
+}
+
+/// Text of a JSON value: itself if it's a string, its JSON otherwise
+fn text_of(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+/// Tool call arguments, parsed when they were sent as a JSON string
+fn arguments(value: Option<&Value>) -> Value {
+    match value {
+        Some(Value::String(s)) => serde_json::from_str(s).unwrap_or(Value::String(s.clone())),
+        Some(other) => other.clone(),
+        None => Value::Object(Default::default()),
+    }
+}
+
+fn time(ns: u64) -> Option<DateTime<Utc>> {
+    (ns > 0).then(|| DateTime::from_timestamp_nanos(ns as i64))
+}
+
+/// A stable event id for one side of a span, so a resent span maps to the
+/// ids it was recorded under the first time
+fn span_event_id(span: &Span, side: u8) -> Uuid {
+    match (
+        u128::from_str_radix(&span.trace_id, 16),
+        u64::from_str_radix(&span.span_id, 16),
+    ) {
+        (Ok(trace), Ok(id)) => {
+            let bits = trace ^ ((id as u128) << 64) ^ side as u128;
+            uuid::Builder::from_random_bytes(bits.to_be_bytes()).into_uuid()
+        }
+        _ => Uuid::new_v4(),
+    }
+}
+
+/// An agent name from a free-form one like "Research Agent"
+fn agent_name(name: &str) -> Option<String> {
+    let name: String = name
+        .trim()
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.') {
+                c.to_ascii_lowercase()
+            } else {
+                '-'
+            }
+        })
+        .take(64)
+        .collect();
+    launcher::parse_name(&name).ok()
+}
+
+/// The prompt and returned tool results, from whichever convention the
+/// instrumentation follows
+fn request_content(span: &Span) -> (String, Vec<ToolResult>) {
+    let attributes = &span.attributes;
+    if let Some(Value::Array(messages)) = json_attr(attributes, "gen_ai.input.messages") {
+        let text = messages
+            .iter()
+            .rev()
+            .find(|m| m["role"] == "user")
+            .map(message_text)
+            .unwrap_or_default();
+        // Results come after the assistant turn that asked for them
+        let since_assistant = messages
+            .iter()
+            .rposition(|m| m["role"] == "assistant")
+            .map_or(0, |i| i + 1);
+        let tool_results = messages[since_assistant..]
+            .iter()
+            .flat_map(|m| m["parts"].as_array().into_iter().flatten())
+            .filter(|part| part["type"] == "tool_call_response")
+            .map(|part| ToolResult {
+                tool_use_id: part["id"].as_str().unwrap_or_default().to_string(),
+                content: text_of(&part["response"]),
+                is_error: false,
+            })
+            .collect();
+        return (text, tool_results);
+    }
+
+    // gen_ai.prompt.N.role and gen_ai.prompt.N.content
+    let mut last_user = None;
+    for n in 0.. {
+        let Some(role) = str_attr(attributes, &format!("gen_ai.prompt.{}.role", n)) else {
+            break;
+        };
+        if role == "user" {
+            last_user = str_attr(attributes, &format!("gen_ai.prompt.{}.content", n));
+        }
+    }
+    if let Some(text) = last_user {
+        return (text.to_string(), Vec::new());
+    }
+
+    let text = span
+        .events
+        .iter()
+        .rev()
+        .find(|e| e.name == "gen_ai.user.message")
+        .and_then(|e| e.attributes.get("content"))
+        .map(text_of)
+        .unwrap_or_default();
+    (text, Vec::new())
+}
+
+/// Text parts of a `gen_ai.*.messages` message joined, or its plain content
+fn message_text(message: &Value) -> String {
+    if let Some(content) = message["content"].as_str() {
+        return content.to_string();
+    }
+    message["parts"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter(|part| part["type"] == "text")
+        .filter_map(|part| part["content"].as_str())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Reply text, reasoning, tool calls, and finish reason
+struct ResponseContent {
+    text: Option<String>,
+    thinking: Option<String>,
+    tool_calls: Vec<ToolCall>,
+    finish_reason: Option<String>,
+}
+
+fn response_content(span: &Span) -> ResponseContent {
+    let attributes = &span.attributes;
+    let mut content = ResponseContent {
+        text: None,
+        thinking: None,
+        tool_calls: Vec::new(),
+        finish_reason: None,
+    };
+    let join = |parts: Vec<&str>| (!parts.is_empty()).then(|| parts.join("\n"));
+
+    if let Some(Value::Array(messages)) = json_attr(attributes, "gen_ai.output.messages") {
+        let parts: Vec<&Value> = messages
+            .iter()
+            .flat_map(|m| m["parts"].as_array().into_iter().flatten())
+            .collect();
+        let of_type = |kind: &str| {
+            parts
+                .iter()
+                .filter(|part| part["type"] == kind)
+                .filter_map(|part| part["content"].as_str())
+                .collect::<Vec<_>>()
+        };
+        content.text = join(of_type("text"));
+        content.thinking = join(of_type("reasoning"));
+        content.tool_calls = parts
+            .iter()
+            .filter(|part| part["type"] == "tool_call")
+            .map(|part| ToolCall {
+                id: part["id"].as_str().unwrap_or_default().to_string(),
+                name: part["name"].as_str().unwrap_or_default().to_string(),
+                input: arguments(part.get("arguments")),
+            })
+            .collect();
+        content.finish_reason = messages
+            .iter()
+            .find_map(|m| m["finish_reason"].as_str())
+            .map(String::from);
+    } else if str_attr(attributes, "gen_ai.completion.0.role").is_some()
+        || attributes.contains_key("gen_ai.completion.0.content")
+    {
+        content.text = str_attr(attributes, "gen_ai.completion.0.content").map(String::from);
+        content.finish_reason =
+            str_attr(attributes, "gen_ai.completion.0.finish_reason").map(String::from);
+        for n in 0.. {
+            let key = |field: &str| format!("gen_ai.completion.0.tool_calls.{}.{}", n, field);
+            let Some(name) = str_attr(attributes, &key("name")) else {
+                break;
+            };
+            content.tool_calls.push(ToolCall {
+                id: str_attr(attributes, &key("id"))
+                    .unwrap_or_default()
+                    .to_string(),
+                name: name.to_string(),
+                input: arguments(attributes.get(&key("arguments"))),
+            });
+        }
+    } else if let Some(choice) = span.events.iter().find(|e| e.name == "gen_ai.choice") {
+        let message = match choice.attributes.get("message") {
+            Some(Value::String(s)) => serde_json::from_str(s).unwrap_or(Value::Null),
+            Some(other) => other.clone(),
+            None => Value::Null,
+        };
+        content.text = message["content"].as_str().map(String::from);
+        content.finish_reason = str_attr(&choice.attributes, "finish_reason").map(String::from);
+    }
+
+    if content.finish_reason.is_none() {
+        content.finish_reason = match attributes.get("gen_ai.response.finish_reasons") {
+            Some(Value::Array(reasons)) => reasons.first().and_then(|r| r.as_str()),
+            Some(Value::String(reason)) => Some(reason.as_str()),
+            _ => None,
+        }
+        .map(String::from);
+    }
+    content
+}
+
+/// Token counts, in sentinel's convention for the model: the conventions
+/// count cached tokens inside input, which Anthropic's prices don't
+fn usage(attributes: &Attributes, model: Option<&str>) -> Option<Usage> {
+    let first = |keys: &[&str]| keys.iter().find_map(|key| int_attr(attributes, key));
+    let mut input = first(&["gen_ai.usage.input_tokens", "gen_ai.usage.prompt_tokens"]);
+    let output = first(&[
+        "gen_ai.usage.output_tokens",
+        "gen_ai.usage.completion_tokens",
+    ]);
+    let cache_read = first(&[
+        "gen_ai.usage.cache_read.input_tokens",
+        "gen_ai.usage.cache_read_input_tokens",
+    ]);
+    let cache_creation = first(&[
+        "gen_ai.usage.cache_creation.input_tokens",
+        "gen_ai.usage.cache_creation_input_tokens",
+    ]);
+    if input.is_none() && output.is_none() {
+        return None;
+    }
+    let cached = cache_read.unwrap_or(0) + cache_creation.unwrap_or(0);
+    if model
+        .and_then(pricing::price_for)
+        .is_some_and(|p| !p.input_includes_cache)
+    {
+        input = input.map(|i| (i - cached).max(0));
+    }
+    Some(Usage {
+        input_tokens: input,
+        output_tokens: output,
+        cache_read_tokens: cache_read,
+        cache_creation_tokens: cache_creation,
+        cost_usd: None,
+    })
+}
+
+/// The model call a span records, if it records one
+fn exchange(resource: &Attributes, span: &Span) -> Option<Exchange> {
+    let attributes = &span.attributes;
+    let operation = str_attr(attributes, "gen_ai.operation.name");
+    let model = str_attr(attributes, "gen_ai.response.model")
+        .or_else(|| str_attr(attributes, "gen_ai.request.model"));
+    match operation {
+        Some(operation) if !MODEL_OPERATIONS.contains(&operation) => return None,
+        None if model.is_none() => return None,
+        _ => {}
+    }
+
+    let agent = str_attr(attributes, "gen_ai.agent.name")
+        .or_else(|| {
+            str_attr(resource, "service.name").filter(|s| !s.starts_with("unknown_service"))
+        })
+        .and_then(agent_name);
+    let session_id = str_attr(attributes, "gen_ai.conversation.id")
+        .unwrap_or(&span.trace_id)
+        .to_string();
+    let provider = str_attr(attributes, "gen_ai.provider.name")
+        .or_else(|| str_attr(attributes, "gen_ai.system"))
+        .map(String::from);
+    let started = time(span.start_ns).unwrap_or_else(Utc::now);
+    let ended = time(span.end_ns).unwrap_or(started);
+    let request_id = span_event_id(span, 1);
+    let response_id = span_event_id(span, 2);
+
+    let (text, tool_results) = request_content(span);
+    let request = UserMessage {
+        model: str_attr(attributes, "gen_ai.request.model").map(String::from),
+        text,
+        tool_results,
+        response_id: Some(response_id),
+        ..Default::default()
+    };
+
+    let response = if span.error {
+        let message = span
+            .status_message
+            .clone()
+            .or_else(|| str_attr(attributes, "error.type").map(String::from))
+            .unwrap_or_else(|| "span ended with an error status".to_string());
+        Err(ErrorEvent {
+            kind: ErrorKind::Upstream,
+            cause: None,
+            status: int_attr(attributes, "http.response.status_code").map(|s| s as u16),
+            message,
+            body: None,
+        })
+    } else {
+        let content = response_content(span);
+        Ok(AssistantResponse {
+            streaming: false,
+            model: model.map(String::from),
+            message_id: str_attr(attributes, "gen_ai.response.id").map(String::from),
+            stop_reason: content.finish_reason,
+            thinking: content.thinking,
+            text: content.text,
+            tool_calls: content.tool_calls,
+            usage: usage(attributes, model),
+            first_byte_ms: None,
+            first_token_ms: None,
+            latency_ms: Some((ended - started).num_milliseconds().max(0) as u64),
+            idempotency_key: None,
+            request_id: Some(request_id),
+            redo_of: None,
+            upstream_key: None,
+            project: None,
+            run_id: None,
+        })
+    };
+
+    Some(Exchange {
+        agent,
+        session_id,
+        provider,
+        request_id,
+        response_id,
+        started,
+        ended,
+        request,
+        response,
+    })
+}
+
+/// Decode an export request in the encoding its content type names
+fn decode(headers: &HeaderMap, body: &[u8]) -> Result<Vec<ResourceSpans>, OtlpError> {
+    if headers
+        .get(header::CONTENT_ENCODING)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| !v.eq_ignore_ascii_case("identity"))
+    {
+        return Err(OtlpError::Compressed);
+    }
+    let content_type = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .ok_or(OtlpError::NoContentType)?;
+    let essence = content_type.split(';').next().unwrap_or_default().trim();
+    match essence {
+        "application/x-protobuf" | "application/protobuf" => protobuf::request(body),
+        "application/json" => json::request(&serde_json::from_slice(body)?),
+        _ => Err(OtlpError::ContentType(content_type.to_string())),
+    }
+}
+
+/// Record the model calls in an OTLP trace export
+pub async fn traces_handler(
+    State(state): State<Arc<ProxyState>>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    if let Err(refusal) = origin::check(&headers, false, &state.allowed_origins) {
+        return refusal.into_response();
+    }
+    let protobuf = !headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.starts_with("application/json"));
+    let resources = match decode(&headers, &body) {
+        Ok(resources) => resources,
+        Err(e) => {
+            let status = match e {
+                OtlpError::ContentType(_) | OtlpError::NoContentType | OtlpError::Compressed => {
+                    StatusCode::UNSUPPORTED_MEDIA_TYPE
+                }
+                OtlpError::Json(_) | OtlpError::Protobuf(_) => StatusCode::BAD_REQUEST,
+            };
+            return (status, Json(serde_json::json!({ "error": e.to_string() }))).into_response();
+        }
+    };
+
+    let mut recorded = 0;
+    for resource in &resources {
+        for exchange in resource
+            .spans
+            .iter()
+            .filter_map(|s| exchange(&resource.resource, s))
+        {
+            match record(&state, exchange).await {
+                Ok(true) => recorded += 1,
+                Ok(false) => {}
+                Err(e) => tracing::warn!("Failed to record OTLP span: {}", e),
+            }
+        }
+    }
+    if recorded > 0 {
+        tracing::info!("← OTLP: recorded {} model call(s)", recorded);
+    }
+
+    // An empty ExportTraceServiceResponse, in the encoding that was sent
+    if protobuf {
+        (
+            [(header::CONTENT_TYPE, "application/x-protobuf")],
+            Bytes::new(),
+        )
+            .into_response()
+    } else {
+        Json(serde_json::json!({})).into_response()
+    }
+}
+
+/// Store an exchange's events, unless an earlier export already did
+async fn record(state: &ProxyState, exchange: Exchange) -> Result<bool, sqlx::Error> {
+    if state
+        .storage
+        .get_event(exchange.response_id)
+        .await?
+        .is_some()
+    {
+        return Ok(false);
+    }
+    if let Some(ref name) = exchange.agent {
+        state
+            .agent_store
+            .get_or_create_named_agent(name, Some(&exchange.session_id), None)
+            .await?;
+    }
+    let event = |id, timestamp, payload| ObservabilityEvent {
+        seq: None,
+        id,
+        timestamp,
+        session_id: Some(exchange.session_id.clone()),
+        agent: exchange.agent.clone(),
+        topic: None,
+        provider: exchange.provider.clone(),
+        payload,
+    };
+    let request = event(
+        exchange.request_id,
+        exchange.started,
+        Payload::UserMessage(exchange.request.clone()),
+    );
+    let response = event(
+        exchange.response_id,
+        exchange.ended,
+        match exchange.response {
+            Ok(ref response) => Payload::AssistantResponse(response.clone()),
+            Err(ref error) => Payload::Error(error.clone()),
+        },
+    );
+    state.publish(request).await;
+    state.publish(response).await;
+    Ok(true)
+}
+
+/// OTLP/JSON: protobuf's JSON mapping, with hex trace and span ids
+mod json {
+    use super::*;
+
+    /// A field by its lowerCamelCase name, or the snake_case one
+    fn field<'a>(value: &'a Value, camel: &str, snake: &str) -> &'a Value {
+        match &value[camel] {
+            Value::Null => &value[snake],
+            found => found,
+        }
+    }
+
+    fn items<'a>(value: &'a Value, camel: &str, snake: &str) -> impl Iterator<Item = &'a Value> {
+        field(value, camel, snake).as_array().into_iter().flatten()
+    }
+
+    /// 64-bit integers are strings in the JSON mapping, but not always
+    fn uint(value: &Value) -> u64 {
+        match value {
+            Value::String(s) => s.parse().unwrap_or(0),
+            other => other.as_u64().unwrap_or(0),
+        }
+    }
+
+    fn any_value(value: &Value) -> Value {
+        if let Some(s) = field(value, "stringValue", "string_value").as_str() {
+            return Value::String(s.to_string());
+        }
+        match field(value, "intValue", "int_value") {
+            Value::Null => {}
+            Value::String(s) => return s.parse::<i64>().map_or(Value::Null, Value::from),
+            other => return other.clone(),
+        }
+        for (camel, snake) in [("doubleValue", "double_value"), ("boolValue", "bool_value")] {
+            match field(value, camel, snake) {
+                Value::Null => {}
+                other => return other.clone(),
+            }
+        }
+        let array = field(value, "arrayValue", "array_value");
+        if !array.is_null() {
+            return Value::Array(items(array, "values", "values").map(any_value).collect());
+        }
+        let list = field(value, "kvlistValue", "kvlist_value");
+        if !list.is_null() {
+            return Value::Object(attributes(list, "values").into_iter().collect());
+        }
+        Value::Null
+    }
+
+    fn attributes(value: &Value, name: &str) -> Attributes {
+        items(value, name, name)
+            .filter_map(|kv| Some((kv["key"].as_str()?.to_string(), any_value(&kv["value"]))))
+            .collect()
+    }
+
+    fn span(value: &Value) -> Span {
+        let id = |camel, snake| {
+            field(value, camel, snake)
+                .as_str()
+                .unwrap_or_default()
+                .to_ascii_lowercase()
+        };
+        let status = &value["status"];
+        Span {
+            trace_id: id("traceId", "trace_id"),
+            span_id: id("spanId", "span_id"),
+            start_ns: uint(field(value, "startTimeUnixNano", "start_time_unix_nano")),
+            end_ns: uint(field(value, "endTimeUnixNano", "end_time_unix_nano")),
+            attributes: attributes(value, "attributes"),
+            events: items(value, "events", "events")
+                .map(|event| SpanEvent {
+                    name: event["name"].as_str().unwrap_or_default().to_string(),
+                    attributes: attributes(event, "attributes"),
+                })
+                .collect(),
+            error: status["code"] == 2 || status["code"] == "STATUS_CODE_ERROR",
+            status_message: status["message"]
+                .as_str()
+                .filter(|m| !m.is_empty())
+                .map(String::from),
+        }
+    }
+
+    pub fn request(value: &Value) -> Result<Vec<ResourceSpans>, OtlpError> {
+        Ok(items(value, "resourceSpans", "resource_spans")
+            .map(|resource| ResourceSpans {
+                resource: attributes(&resource["resource"], "attributes"),
+                spans: items(resource, "scopeSpans", "scope_spans")
+                    .flat_map(|scope| items(scope, "spans", "spans"))
+                    .map(span)
+                    .collect(),
+            })
+            .collect())
+    }
+}
+
+/// Just enough of the protobuf wire format to read an
+/// ExportTraceServiceRequest
+mod protobuf {
+    use super::*;
+
+    enum Field<'a> {
+        Varint(u64),
+        Fixed64(u64),
+        Bytes(&'a [u8]),
+        Fixed32,
+    }
+
+    struct Reader<'a> {
+        buf: &'a [u8],
+    }
+
+    impl<'a> Reader<'a> {
+        fn new(buf: &'a [u8]) -> Self {
+            Self { buf }
+        }
+
+        fn varint(&mut self) -> Result<u64, OtlpError> {
+            let mut value = 0u64;
+            for shift in (0..64).step_by(7) {
+                let (&byte, rest) = self
+                    .buf
+                    .split_first()
+                    .ok_or(OtlpError::Protobuf("truncated varint"))?;
+                self.buf = rest;
+                value |= u64::from(byte & 0x7f) << shift;
+                if byte & 0x80 == 0 {
+                    return Ok(value);
+                }
+            }
+            Err(OtlpError::Protobuf("varint too long"))
+        }
+
+        fn take(&mut self, len: usize) -> Result<&'a [u8], OtlpError> {
+            if len > self.buf.len() {
+                return Err(OtlpError::Protobuf("truncated field"));
+            }
+            let (taken, rest) = self.buf.split_at(len);
+            self.buf = rest;
+            Ok(taken)
+        }
+
+        /// The next field number and value, until the message ends
+        fn next(&mut self) -> Result<Option<(u64, Field<'a>)>, OtlpError> {
+            if self.buf.is_empty() {
+                return Ok(None);
+            }
+            let key = self.varint()?;
+            let field = match key & 7 {
+                0 => Field::Varint(self.varint()?),
+                1 => {
+                    let bytes = self.take(8)?;
+                    Field::Fixed64(u64::from_le_bytes(bytes.try_into().unwrap_or_default()))
+                }
+                2 => {
+                    let len = self.varint()? as usize;
+                    Field::Bytes(self.take(len)?)
+                }
+                5 => {
+                    self.take(4)?;
+                    Field::Fixed32
+                }
+                _ => return Err(OtlpError::Protobuf("unsupported wire type")),
+            };
+            Ok(Some((key >> 3, field)))
+        }
+    }
+
+    fn string(bytes: &[u8]) -> String {
+        String::from_utf8_lossy(bytes).into_owned()
+    }
+
+    fn hex(bytes: &[u8]) -> String {
+        bytes.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    fn any_value(buf: &[u8], depth: usize) -> Result<Value, OtlpError> {
+        if depth > MAX_DEPTH {
+            return Err(OtlpError::Protobuf("attribute values nested too deeply"));
+        }
+        let mut reader = Reader::new(buf);
+        let mut value = Value::Null;
+        while let Some((number, field)) = reader.next()? {
+            value = match (number, field) {
+                (1, Field::Bytes(b)) => Value::String(string(b)),
+                (2, Field::Varint(v)) => Value::Bool(v != 0),
+                (3, Field::Varint(v)) => Value::from(v as i64),
+                (4, Field::Fixed64(v)) => Value::from(f64::from_bits(v)),
+                (5, Field::Bytes(b)) => {
+                    let mut values = Vec::new();
+                    let mut array = Reader::new(b);
+                    while let Some((number, field)) = array.next()? {
+                        if let (1, Field::Bytes(b)) = (number, field) {
+                            values.push(any_value(b, depth + 1)?);
+                        }
+                    }
+                    Value::Array(values)
+                }
+                (6, Field::Bytes(b)) => {
+                    let mut list = Attributes::new();
+                    let mut reader = Reader::new(b);
+                    while let Some((number, field)) = reader.next()? {
+                        if let (1, Field::Bytes(b)) = (number, field) {
+                            let (key, value) = key_value(b, depth + 1)?;
+                            list.insert(key, value);
+                        }
+                    }
+                    Value::Object(list.into_iter().collect())
+                }
+                (7, Field::Bytes(b)) => Value::String(hex(b)),
+                _ => value,
+            };
+        }
+        Ok(value)
+    }
+
+    fn key_value(buf: &[u8], depth: usize) -> Result<(String, Value), OtlpError> {
+        let mut reader = Reader::new(buf);
+        let (mut key, mut value) = (String::new(), Value::Null);
+        while let Some((number, field)) = reader.next()? {
+            match (number, field) {
+                (1, Field::Bytes(b)) => key = string(b),
+                (2, Field::Bytes(b)) => value = any_value(b, depth)?,
+                _ => {}
+            }
+        }
+        Ok((key, value))
+    }
+
+    fn insert(attributes: &mut Attributes, buf: &[u8]) -> Result<(), OtlpError> {
+        let (key, value) = key_value(buf, 0)?;
+        attributes.insert(key, value);
+        Ok(())
+    }
+
+    fn span(buf: &[u8]) -> Result<Span, OtlpError> {
+        let mut span = Span::default();
+        let mut reader = Reader::new(buf);
+        while let Some((number, field)) = reader.next()? {
+            match (number, field) {
+                (1, Field::Bytes(b)) => span.trace_id = hex(b),
+                (2, Field::Bytes(b)) => span.span_id = hex(b),
+                (7, Field::Fixed64(v)) => span.start_ns = v,
+                (8, Field::Fixed64(v)) => span.end_ns = v,
+                (9, Field::Bytes(b)) => insert(&mut span.attributes, b)?,
+                (11, Field::Bytes(b)) => {
+                    let mut event = SpanEvent::default();
+                    let mut reader = Reader::new(b);
+                    while let Some((number, field)) = reader.next()? {
+                        match (number, field) {
+                            (2, Field::Bytes(b)) => event.name = string(b),
+                            (3, Field::Bytes(b)) => insert(&mut event.attributes, b)?,
+                            _ => {}
+                        }
+                    }
+                    span.events.push(event);
+                }
+                (15, Field::Bytes(b)) => {
+                    let mut reader = Reader::new(b);
+                    while let Some((number, field)) = reader.next()? {
+                        match (number, field) {
+                            (2, Field::Bytes(b)) if !b.is_empty() => {
+                                span.status_message = Some(string(b))
+                            }
+                            (3, Field::Varint(code)) => span.error = code == 2,
+                            _ => {}
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        Ok(span)
+    }
+
+    pub fn request(buf: &[u8]) -> Result<Vec<ResourceSpans>, OtlpError> {
+        let mut resources = Vec::new();
+        let mut reader = Reader::new(buf);
+        while let Some((number, field)) = reader.next()? {
+            let (1, Field::Bytes(b)) = (number, field) else {
+                continue;
+            };
+            let mut resource = ResourceSpans::default();
+            let mut reader = Reader::new(b);
+            while let Some((number, field)) = reader.next()? {
+                match (number, field) {
+                    (1, Field::Bytes(b)) => {
+                        let mut reader = Reader::new(b);
+                        while let Some((number, field)) = reader.next()? {
+                            if let (1, Field::Bytes(b)) = (number, field) {
+                                insert(&mut resource.resource, b)?;
+                            }
+                        }
+                    }
+                    (2, Field::Bytes(b)) => {
+                        let mut reader = Reader::new(b);
+                        while let Some((number, field)) = reader.next()? {
+                            if let (2, Field::Bytes(b)) = (number, field) {
+                                resource.spans.push(span(b)?);
+                            }
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            resources.push(resource);
+        }
+        Ok(resources)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TRACE_ID: &str = "5b8efff798038103d269b633813fc60c";
+
+    fn export(attributes: Value, status: Value) -> Value {
+        serde_json::json!({
+            "resourceSpans": [{
+                "resource": {"attributes": [
+                    {"key": "service.name", "value": {"stringValue": "Research Agent"}}
+                ]},
+                "scopeSpans": [{"spans": [
+                    {
+                        "traceId": TRACE_ID,
+                        "spanId": "eee19b7ec3c1b174",
+                        "name": "chat claude-sonnet-4",
+                        "startTimeUnixNano": "1700000000000000000",
+                        "endTimeUnixNano": "1700000002500000000",
+                        "attributes": attributes,
+                        "status": status,
+                    },
+                    {
+                        "traceId": TRACE_ID,
+                        "spanId": "eee19b7ec3c1b175",
+                        "name": "execute_tool search",
+                        "attributes": [
+                            {"key": "gen_ai.operation.name", "value": {"stringValue": "execute_tool"}}
+                        ],
+                    }
+                ]}]
+            }]
+        })
+    }
+
+    fn attr(key: &str, value: Value) -> Value {
+        serde_json::json!({"key": key, "value": value})
+    }
+
+    #[test]
+    fn test_chat_spans_become_exchanges() {
+        let input = serde_json::json!([
+            {"role": "user", "parts": [{"type": "text", "content": "find flights"}]},
+            {"role": "assistant", "parts": [{"type": "tool_call", "id": "c1", "name": "search", "arguments": {}}]},
+            {"role": "tool", "parts": [{"type": "tool_call_response", "id": "c1", "response": "3 results"}]}
+        ]);
+        let output = serde_json::json!([{
+            "role": "assistant",
+            "parts": [
+                {"type": "text", "content": "Booking it."},
+                {"type": "tool_call", "id": "c2", "name": "book", "arguments": "{\"flight\": 2}"}
+            ],
+            "finish_reason": "tool_call"
+        }]);
+        let body = export(
+            serde_json::json!([
+                attr(
+                    "gen_ai.operation.name",
+                    serde_json::json!({"stringValue": "chat"})
+                ),
+                attr(
+                    "gen_ai.provider.name",
+                    serde_json::json!({"stringValue": "anthropic"})
+                ),
+                attr(
+                    "gen_ai.request.model",
+                    serde_json::json!({"stringValue": "claude-sonnet-4"})
+                ),
+                attr(
+                    "gen_ai.usage.input_tokens",
+                    serde_json::json!({"intValue": "1200"})
+                ),
+                attr(
+                    "gen_ai.usage.cache_read.input_tokens",
+                    serde_json::json!({"intValue": 200})
+                ),
+                attr(
+                    "gen_ai.usage.output_tokens",
+                    serde_json::json!({"intValue": 80})
+                ),
+                attr(
+                    "gen_ai.input.messages",
+                    serde_json::json!({"stringValue": input.to_string()})
+                ),
+                attr(
+                    "gen_ai.output.messages",
+                    serde_json::json!({"stringValue": output.to_string()})
+                ),
+            ]),
+            serde_json::json!({}),
+        );
+
+        let resources = json::request(&body).unwrap();
+        let exchanges: Vec<Exchange> = resources[0]
+            .spans
+            .iter()
+            .filter_map(|s| exchange(&resources[0].resource, s))
+            .collect();
+        assert_eq!(exchanges.len(), 1);
+        let exchange = &exchanges[0];
+        assert_eq!(exchange.agent.as_deref(), Some("research-agent"));
+        assert_eq!(exchange.session_id, TRACE_ID);
+        assert_eq!(exchange.provider.as_deref(), Some("anthropic"));
+        assert_eq!(exchange.request.text, "find flights");
+        assert_eq!(exchange.request.tool_results[0].content, "3 results");
+        assert_eq!(exchange.request.response_id, Some(exchange.response_id));
+
+        let response = exchange.response.as_ref().unwrap();
+        assert_eq!(response.text.as_deref(), Some("Booking it."));
+        assert_eq!(
+            response.tool_calls[0].input,
+            serde_json::json!({"flight": 2})
+        );
+        assert_eq!(response.stop_reason.as_deref(), Some("tool_call"));
+        assert_eq!(response.latency_ms, Some(2500));
+        let usage = response.usage.as_ref().unwrap();
+        // Anthropic prices input without the cache reads counted in it
+        assert_eq!(usage.input_tokens, Some(1000));
+        assert_eq!(usage.cache_read_tokens, Some(200));
+
+        // The same span always maps to the same ids
+        let again = json::request(&body).unwrap();
+        let again = exchange_of(&again);
+        assert_eq!(again.response_id, exchange.response_id);
+        assert_ne!(exchange.request_id, exchange.response_id);
+
+        let failed = export(
+            serde_json::json!([
+                attr(
+                    "gen_ai.request.model",
+                    serde_json::json!({"stringValue": "gpt-4o"})
+                ),
+                attr(
+                    "gen_ai.prompt.0.role",
+                    serde_json::json!({"stringValue": "user"})
+                ),
+                attr(
+                    "gen_ai.prompt.0.content",
+                    serde_json::json!({"stringValue": "hi"})
+                ),
+            ]),
+            serde_json::json!({"code": 2, "message": "rate limited"}),
+        );
+        let failed = json::request(&failed).unwrap();
+        let failed = exchange_of(&failed);
+        assert_eq!(failed.request.text, "hi");
+        assert_eq!(
+            failed.response.as_ref().unwrap_err().message,
+            "rate limited"
+        );
+    }
+
+    fn exchange_of(resources: &[ResourceSpans]) -> Exchange {
+        exchange(&resources[0].resource, &resources[0].spans[0]).unwrap()
+    }
+
+    /// Protobuf encoding helpers, just enough to write test requests
+    fn varint(mut v: u64, out: &mut Vec<u8>) {
+        while v >= 0x80 {
+            out.push(v as u8 | 0x80);
+            v >>= 7;
+        }
+        out.push(v as u8);
+    }
+
+    fn bytes_field(number: u64, bytes: &[u8], out: &mut Vec<u8>) {
+        varint(number << 3 | 2, out);
+        varint(bytes.len() as u64, out);
+        out.extend_from_slice(bytes);
+    }
+
+    fn key_value(key: &str, value: &[u8]) -> Vec<u8> {
+        let mut kv = Vec::new();
+        bytes_field(1, key.as_bytes(), &mut kv);
+        bytes_field(2, value, &mut kv);
+        kv
+    }
+
+    #[test]
+    fn test_protobuf_export_decodes() {
+        let mut string_value = Vec::new();
+        bytes_field(1, b"chat", &mut string_value);
+        let mut model = Vec::new();
+        bytes_field(1, b"gpt-4o", &mut model);
+        let mut tokens = Vec::new();
+        varint(3 << 3, &mut tokens);
+        varint(42, &mut tokens);
+
+        let mut span = Vec::new();
+        bytes_field(1, &[0xab; 16], &mut span);
+        bytes_field(2, &[0xcd; 8], &mut span);
+        varint(7 << 3 | 1, &mut span);
+        span.extend_from_slice(&1_700_000_000_000_000_000u64.to_le_bytes());
+        varint(8 << 3 | 1, &mut span);
+        span.extend_from_slice(&1_700_000_001_000_000_000u64.to_le_bytes());
+        bytes_field(
+            9,
+            &key_value("gen_ai.operation.name", &string_value),
+            &mut span,
+        );
+        bytes_field(9, &key_value("gen_ai.request.model", &model), &mut span);
+        bytes_field(
+            9,
+            &key_value("gen_ai.usage.output_tokens", &tokens),
+            &mut span,
+        );
+        // flags, a fixed32 the reader has to step over
+        varint(16 << 3 | 5, &mut span);
+        span.extend_from_slice(&[1, 0, 0, 0]);
+
+        let mut scope = Vec::new();
+        bytes_field(2, &span, &mut scope);
+        let mut resource = Vec::new();
+        bytes_field(2, &scope, &mut resource);
+        let mut request = Vec::new();
+        bytes_field(1, &resource, &mut request);
+
+        let resources = protobuf::request(&request).unwrap();
+        let exchange = exchange_of(&resources);
+        assert_eq!(exchange.session_id, "ab".repeat(16));
+        assert_eq!(exchange.agent, None);
+        let response = exchange.response.unwrap();
+        assert_eq!(response.model.as_deref(), Some("gpt-4o"));
+        assert_eq!(response.usage.unwrap().output_tokens, Some(42));
+        assert_eq!(response.latency_ms, Some(1000));
+
+        assert!(protobuf::request(&request[..request.len() - 3]).is_err());
+    }
+
+    /// A request with one span whose only attribute is an array nested
+    /// `depth` deep
+    fn nested_request(depth: usize) -> Vec<u8> {
+        let mut value = Vec::new();
+        bytes_field(1, b"bottom", &mut value);
+        for _ in 0..depth {
+            let mut array = Vec::new();
+            bytes_field(1, &value, &mut array);
+            value.clear();
+            bytes_field(5, &array, &mut value);
+        }
+        let mut span = Vec::new();
+        bytes_field(9, &key_value("deep", &value), &mut span);
+        let mut scope = Vec::new();
+        bytes_field(2, &span, &mut scope);
+        let mut resource = Vec::new();
+        bytes_field(2, &scope, &mut resource);
+        let mut request = Vec::new();
+        bytes_field(1, &resource, &mut request);
+        request
+    }
+
+    #[test]
+    fn test_deeply_nested_values_are_refused() {
+        let resources = protobuf::request(&nested_request(MAX_DEPTH)).unwrap();
+        let mut value = &resources[0].spans[0].attributes["deep"];
+        for _ in 0..MAX_DEPTH {
+            value = &value[0];
+        }
+        assert_eq!(value, "bottom");
+
+        assert!(matches!(
+            protobuf::request(&nested_request(1_000)),
+            Err(OtlpError::Protobuf(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_traces_are_recorded_once() {
+        let state = ProxyState::for_tests().await;
+        let body = export(
+            serde_json::json!([
+                attr("gen_ai.operation.name", serde_json::json!({"stringValue": "chat"})),
+                attr("gen_ai.request.model", serde_json::json!({"stringValue": "claude-sonnet-4"})),
+            ]),
+            serde_json::json!({}),
+        );
+        let mut headers = HeaderMap::new();
+        headers.insert(header::CONTENT_TYPE, "application/json".parse().unwrap());
+        let post = |headers: HeaderMap, body: Vec<u8>| {
+            traces_handler(State(state.clone()), headers, Bytes::from(body))
+        };
+
+        // Exporters retry, so a second export of the same span adds nothing
+        for _ in 0..2 {
+            let response = post(headers.clone(), body.to_string().into_bytes()).await;
+            assert_eq!(response.status(), StatusCode::OK);
+        }
+        let stored: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM observability_events")
+            .fetch_one(&state.storage.pool())
+            .await
+            .unwrap();
+        assert_eq!(stored, 2);
+        let agent = state.agent_store.find_by_name("research-agent").await.unwrap();
+        assert!(agent.is_some());
+
+        let mut protobuf = HeaderMap::new();
+        protobuf.insert(header::CONTENT_TYPE, "application/x-protobuf".parse().unwrap());
+        let response = post(protobuf, nested_request(1_000)).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        // A no-cors post from a page sends no content type, or another site's origin
+        let response = post(HeaderMap::new(), body.to_string().into_bytes()).await;
+        assert_eq!(response.status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+        let mut cross_site = headers.clone();
+        cross_site.insert(header::ORIGIN, "https://evil.example".parse().unwrap());
+        let response = post(cross_site, body.to_string().into_bytes()).await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        headers.insert(header::CONTENT_ENCODING, "gzip".parse().unwrap());
+        let response = post(headers, body.to_string().into_bytes()).await;
+        assert_eq!(response.status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+    }
+}
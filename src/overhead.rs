@@ -0,0 +1,242 @@
+//! Keeping sentinel's own cost per event under `--latency-budget-ms`.
+//!
+//! Each event's recording is timed: capturing its raw body, parsing it, and
+//! storing it, plus parsing a streamed response as it passes. When the
+//! average goes over the budget, recording is cut back a step at a time:
+//! first raw bodies are no longer kept, then recording moves off the
+//! response path onto the record queue, except for responses a blocking
+//! baseline or `--policy-enforce` might withhold. Once the average is back
+//! under half the budget it steps back up. Each change is logged and
+//! recorded as a `latency_budget` alert, and the current state is served at
+//! `/api/overhead`.
+
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::Json;
+use chrono::{DateTime, Utc};
+use schemars::JsonSchema;
+use serde::Serialize;
+use serde_json::{json, Value};
+
+use crate::proxy::ProxyState;
+
+pub const OVERHEAD_ALERT_KIND: &str = "latency_budget";
+/// Weight of the latest event in the moving average
+const ALPHA: f64 = 0.2;
+/// Events between changes, so each step is judged on events recorded under it
+const SETTLE: u32 = 20;
+
+/// How far recording is cut back
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Degradation {
+    #[default]
+    None,
+    /// Raw bodies aren't kept
+    SkipRaw,
+    /// Raw bodies aren't kept, and events are recorded after responding
+    Defer,
+}
+
+impl Degradation {
+    pub fn keeps_raw(self) -> bool {
+        self == Self::None
+    }
+
+    fn worse(self) -> Self {
+        match self {
+            Self::None => Self::SkipRaw,
+            _ => Self::Defer,
+        }
+    }
+
+    fn better(self) -> Self {
+        match self {
+            Self::Defer => Self::SkipRaw,
+            _ => Self::None,
+        }
+    }
+
+    fn describe(self) -> &'static str {
+        match self {
+            Self::None => "recording everything again",
+            Self::SkipRaw => "no longer keeping raw bodies",
+            Self::Defer => "no longer keeping raw bodies, and recording after responding",
+        }
+    }
+}
+
+struct Inner {
+    mean_ms: Option<f64>,
+    max_ms: f64,
+    level: Degradation,
+    since: Option<DateTime<Utc>>,
+    since_change: u32,
+    events: u64,
+    degraded_events: u64,
+}
+
+pub struct LatencyBudget {
+    budget: Duration,
+    inner: Mutex<Inner>,
+}
+
+/// Sentinel's cost per event against the budget, for `/api/overhead`
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct OverheadStats {
+    pub budget_ms: f64,
+    /// Moving average of recent events
+    pub mean_ms: f64,
+    pub max_ms: f64,
+    pub level: Degradation,
+    /// When the current level was reached, if it's degraded
+    pub since: Option<DateTime<Utc>>,
+    pub events: u64,
+    /// Events recorded at a degraded level
+    pub degraded_events: u64,
+}
+
+fn millis(d: Duration) -> f64 {
+    d.as_secs_f64() * 1000.0
+}
+
+impl LatencyBudget {
+    pub fn new(budget: Duration) -> Self {
+        Self {
+            budget,
+            inner: Mutex::new(Inner {
+                mean_ms: None,
+                max_ms: 0.0,
+                level: Degradation::None,
+                since: None,
+                since_change: 0,
+                events: 0,
+                degraded_events: 0,
+            }),
+        }
+    }
+
+    /// The level to record a new exchange at
+    pub fn level(&self) -> Degradation {
+        self.lock().level
+    }
+
+    /// Count what recording an event recorded at `level` took, returning a
+    /// message if the level changes
+    pub fn observe(&self, took: Duration, level: Degradation) -> Option<String> {
+        let budget = millis(self.budget);
+        let took = millis(took);
+        let mut inner = self.lock();
+        let mean = match inner.mean_ms {
+            Some(mean) => mean + ALPHA * (took - mean),
+            None => took,
+        };
+        inner.mean_ms = Some(mean);
+        inner.max_ms = inner.max_ms.max(took);
+        inner.events += 1;
+        if level != Degradation::None {
+            inner.degraded_events += 1;
+        }
+        inner.since_change += 1;
+        if inner.since_change < SETTLE {
+            return None;
+        }
+
+        let next = if mean > budget {
+            inner.level.worse()
+        } else if mean < budget / 2.0 {
+            inner.level.better()
+        } else {
+            inner.level
+        };
+        if next == inner.level {
+            return None;
+        }
+        let over = next > inner.level;
+        inner.level = next;
+        inner.since = (next != Degradation::None).then(Utc::now);
+        inner.since_change = 0;
+        Some(format!(
+            "Recording averages {:.1}ms per event, {} the {:.0}ms latency budget; {}",
+            mean,
+            if over { "over" } else { "back under" },
+            budget,
+            next.describe()
+        ))
+    }
+
+    pub fn stats(&self) -> OverheadStats {
+        let inner = self.lock();
+        OverheadStats {
+            budget_ms: millis(self.budget),
+            mean_ms: inner.mean_ms.unwrap_or(0.0),
+            max_ms: inner.max_ms,
+            level: inner.level,
+            since: inner.since,
+            events: inner.events,
+            degraded_events: inner.degraded_events,
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Inner> {
+        self.inner.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+pub async fn overhead_handler(
+    State(state): State<Arc<ProxyState>>,
+) -> Result<Json<OverheadStats>, (StatusCode, Json<Value>)> {
+    match &state.latency {
+        Some(latency) => Ok(Json(latency.stats())),
+        None => Err((
+            StatusCode::NOT_FOUND,
+            Json(json!({"error": "no budget set; start with --latency-budget-ms"})),
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_degrades_a_step_at_a_time_and_recovers() {
+        let budget = LatencyBudget::new(Duration::from_millis(10));
+        let slow = Duration::from_millis(30);
+        for _ in 0..SETTLE - 1 {
+            assert!(budget.observe(slow, Degradation::None).is_none());
+        }
+        let message = budget.observe(slow, Degradation::None).unwrap();
+        assert!(message.contains("over the 10ms"), "{}", message);
+        assert_eq!(budget.level(), Degradation::SkipRaw);
+
+        // Still slow after settling on the first step: take the next
+        for _ in 0..SETTLE - 1 {
+            assert!(budget.observe(slow, Degradation::SkipRaw).is_none());
+        }
+        assert!(budget.observe(slow, Degradation::SkipRaw).is_some());
+        assert_eq!(budget.level(), Degradation::Defer);
+        assert!(budget.stats().since.is_some());
+
+        // Between half the budget and the budget, it stays put
+        let fine = Duration::from_millis(7);
+        for _ in 0..SETTLE * 2 {
+            budget.observe(fine, Degradation::Defer);
+        }
+        assert_eq!(budget.level(), Degradation::Defer);
+
+        let fast = Duration::from_millis(1);
+        for _ in 0..SETTLE * 2 {
+            budget.observe(fast, Degradation::Defer);
+        }
+        assert_eq!(budget.level(), Degradation::None);
+        let stats = budget.stats();
+        assert_eq!(stats.events, SETTLE as u64 * 6);
+        assert_eq!(stats.degraded_events, SETTLE as u64 * 5);
+        assert_eq!(stats.max_ms, 30.0);
+        assert!(stats.since.is_none());
+    }
+}
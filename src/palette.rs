@@ -0,0 +1,351 @@
+//! `sentinel` with no arguments: an interactive command palette.
+//!
+//! Lists every subcommand with its description, filtered as you type. Picking
+//! one prompts for its required arguments in turn, offering the values they
+//! complete to (recorded agents, a value enum's variants) and otherwise taking
+//! free text, then hands the assembled command line back to be run as if it
+//! had been typed.
+
+use std::io;
+
+use clap::{Arg, Command};
+use clap_complete::ArgValueCandidates;
+use crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, Borders, List, ListItem, ListState, Paragraph};
+use ratatui::{DefaultTerminal, Frame};
+
+/// Subcommands not worth offering from the palette
+const HIDDEN: &[&str] = &["help"];
+
+#[derive(Debug, Clone, PartialEq)]
+struct Item {
+    value: String,
+    help: String,
+}
+
+/// A filterable list; Enter picks the highlighted item, or takes what was
+/// typed when nothing matches
+struct Picker {
+    title: String,
+    items: Vec<Item>,
+    query: String,
+    selected: usize,
+}
+
+enum Outcome {
+    Picked(String),
+    Cancelled,
+}
+
+impl Picker {
+    fn new(title: String, items: Vec<Item>) -> Self {
+        Self {
+            title,
+            items,
+            query: String::new(),
+            selected: 0,
+        }
+    }
+
+    /// Items containing every word of the query, in their name or description;
+    /// names starting with the query first, then names containing it
+    fn matches(&self) -> Vec<&Item> {
+        let words: Vec<String> = self
+            .query
+            .split_whitespace()
+            .map(str::to_lowercase)
+            .collect();
+        let query = self.query.trim().to_lowercase();
+        let mut matches: Vec<&Item> = self
+            .items
+            .iter()
+            .filter(|item| {
+                let text = format!("{} {}", item.value, item.help).to_lowercase();
+                words.iter().all(|word| text.contains(word))
+            })
+            .collect();
+        matches.sort_by_key(|item| {
+            let value = item.value.to_lowercase();
+            (!value.starts_with(&query), !value.contains(&query))
+        });
+        matches
+    }
+
+    fn key(&mut self, key: KeyCode) -> Option<Outcome> {
+        match key {
+            KeyCode::Esc => return Some(Outcome::Cancelled),
+            KeyCode::Enter => {
+                let matches = self.matches();
+                if let Some(item) = matches.get(self.selected) {
+                    return Some(Outcome::Picked(item.value.clone()));
+                }
+                let typed = self.query.trim();
+                if matches.is_empty() && !typed.is_empty() {
+                    return Some(Outcome::Picked(typed.to_string()));
+                }
+            }
+            KeyCode::Up => self.selected = self.selected.saturating_sub(1),
+            KeyCode::Down => {
+                let last = self.matches().len().saturating_sub(1);
+                self.selected = (self.selected + 1).min(last);
+            }
+            KeyCode::Backspace => {
+                self.query.pop();
+                self.selected = 0;
+            }
+            KeyCode::Char(c) => {
+                self.query.push(c);
+                self.selected = 0;
+            }
+            _ => {}
+        }
+        None
+    }
+}
+
+/// Every subcommand, with nested ones (`policy check`) listed in full
+fn commands(cli: &Command) -> Vec<Item> {
+    let mut items = Vec::new();
+    for cmd in cli.get_subcommands() {
+        if cmd.is_hide_set() || HIDDEN.contains(&cmd.get_name()) {
+            continue;
+        }
+        let nested: Vec<Item> = commands(cmd)
+            .into_iter()
+            .map(|item| Item {
+                value: format!("{} {}", cmd.get_name(), item.value),
+                help: item.help,
+            })
+            .collect();
+        if nested.is_empty() {
+            items.push(Item {
+                value: cmd.get_name().to_string(),
+                help: about(cmd),
+            });
+        }
+        items.extend(nested);
+    }
+    items
+}
+
+fn about(cmd: &Command) -> String {
+    cmd.get_about().map(|a| a.to_string()).unwrap_or_default()
+}
+
+/// Values to offer for an argument
+fn candidates(arg: &Arg) -> Vec<Item> {
+    if let Some(completer) = arg.get::<ArgValueCandidates>() {
+        return completer
+            .candidates()
+            .into_iter()
+            .map(|c| Item {
+                value: c.get_value().to_string_lossy().into_owned(),
+                help: c.get_help().map(|h| h.to_string()).unwrap_or_default(),
+            })
+            .collect();
+    }
+    arg.get_possible_values()
+        .into_iter()
+        .filter(|v| !v.is_hide_set())
+        .map(|v| Item {
+            value: v.get_name().to_string(),
+            help: v.get_help().map(|h| h.to_string()).unwrap_or_default(),
+        })
+        .collect()
+}
+
+/// Arguments that have to be given, in the order they're prompted for
+fn required(cmd: &Command) -> Vec<&Arg> {
+    let mut args: Vec<&Arg> = cmd
+        .get_arguments()
+        .filter(|a| a.is_required_set() && !a.is_hide_set())
+        .collect();
+    // Options first, then positionals by index, trailing `-- ...` last
+    args.sort_by_key(|a| (a.is_positional(), a.is_last_set(), a.get_index()));
+    args
+}
+
+/// The words of a command typed for a trailing `-- ...` argument, split the
+/// way a shell would; taken as one word if its quotes don't close
+fn words(value: &str) -> Vec<String> {
+    shlex::split(value).unwrap_or_else(|| vec![value.to_string()])
+}
+
+/// The command line picking `value` for `arg` adds; values are passed as
+/// they were typed, spaces and all, except a trailing command's words
+fn push_arg(argv: &mut Vec<String>, arg: &Arg, value: &str) {
+    if arg.is_last_set() {
+        argv.push("--".to_string());
+        argv.extend(words(value));
+    } else if arg.is_trailing_var_arg_set() {
+        argv.extend(words(value));
+    } else if let Some(long) = arg.get_long() {
+        argv.push(format!("--{}", long));
+        argv.push(value.to_string());
+    } else {
+        argv.push(value.to_string());
+    }
+}
+
+/// Let the user build a command line; None when they back out
+pub fn run(cli: Command) -> io::Result<Option<Vec<String>>> {
+    let mut terminal = ratatui::init();
+    let result = choose(&mut terminal, &cli);
+    ratatui::restore();
+    result
+}
+
+fn choose(terminal: &mut DefaultTerminal, cli: &Command) -> io::Result<Option<Vec<String>>> {
+    let title = format!("sentinel {}", cli.get_version().unwrap_or_default());
+    let mut picker = Picker::new(title.trim().to_string(), commands(cli));
+    let Some(picked) = pick(terminal, &mut picker)? else {
+        return Ok(None);
+    };
+    let mut argv = vec![cli.get_name().to_string()];
+    let mut cmd = cli;
+    for name in picked.split(' ') {
+        match cmd.find_subcommand(name) {
+            Some(sub) => cmd = sub,
+            None => return Ok(None),
+        }
+        argv.push(name.to_string());
+    }
+    for arg in required(cmd) {
+        let label = arg
+            .get_value_names()
+            .and_then(|names| names.first())
+            .map(|n| n.to_string())
+            .unwrap_or_else(|| arg.get_id().to_string().to_uppercase());
+        let help = arg.get_help().map(|h| format!(": {}", h)).unwrap_or_default();
+        let title = format!("{} <{}>{}", argv.join(" "), label, help);
+        let mut picker = Picker::new(title, candidates(arg));
+        let Some(value) = pick(terminal, &mut picker)? else {
+            return Ok(None);
+        };
+        push_arg(&mut argv, arg, &value);
+    }
+    Ok(Some(argv))
+}
+
+fn pick(terminal: &mut DefaultTerminal, picker: &mut Picker) -> io::Result<Option<String>> {
+    loop {
+        terminal.draw(|frame| draw(frame, picker))?;
+        let key = match event::read()? {
+            Event::Key(key) if key.kind == KeyEventKind::Press => key,
+            _ => continue,
+        };
+        if key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL) {
+            return Ok(None);
+        }
+        match picker.key(key.code) {
+            Some(Outcome::Picked(value)) => return Ok(Some(value)),
+            Some(Outcome::Cancelled) => return Ok(None),
+            None => {}
+        }
+    }
+}
+
+fn draw(frame: &mut Frame, picker: &Picker) {
+    let [input, list, footer] = Layout::vertical([
+        Constraint::Length(3),
+        Constraint::Min(1),
+        Constraint::Length(1),
+    ])
+    .areas(frame.area());
+
+    let prompt = Paragraph::new(Line::from(vec![
+        Span::styled("> ", Style::default().fg(Color::Cyan)),
+        Span::raw(picker.query.as_str()),
+    ]))
+    .block(Block::default().borders(Borders::ALL).title(picker.title.as_str()));
+    frame.render_widget(prompt, input);
+
+    let matches = picker.matches();
+    let width = matches.iter().map(|i| i.value.len()).max().unwrap_or(0);
+    let items: Vec<ListItem> = matches
+        .iter()
+        .map(|item| {
+            ListItem::new(Line::from(vec![
+                Span::styled(
+                    format!("{:width$}  ", item.value, width = width),
+                    Style::default().add_modifier(Modifier::BOLD),
+                ),
+                Span::styled(item.help.as_str(), Style::default().fg(Color::DarkGray)),
+            ]))
+        })
+        .collect();
+    let mut state = ListState::default().with_selected(Some(picker.selected));
+    let hint = if items.is_empty() && picker.items.is_empty() {
+        "type a value"
+    } else if items.is_empty() {
+        "no matches; Enter takes what you typed"
+    } else {
+        ""
+    };
+    let list_widget = List::new(items)
+        .block(Block::default().borders(Borders::ALL).title(hint))
+        .highlight_style(Style::default().bg(Color::DarkGray))
+        .highlight_symbol("▶ ");
+    frame.render_stateful_widget(list_widget, list, &mut state);
+
+    let keys = Paragraph::new("↑/↓ select  Enter run  Esc quit")
+        .style(Style::default().fg(Color::DarkGray));
+    frame.render_widget(keys, footer);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli;
+
+    #[test]
+    fn test_palette_filters_commands_and_prompts_required_arguments() {
+        let cli = cli::command();
+        let mut picker = Picker::new(String::new(), commands(&cli));
+        let values = |p: &Picker| -> Vec<String> {
+            p.matches().iter().map(|i| i.value.clone()).collect()
+        };
+        assert!(values(&picker).contains(&"policy check".to_string()));
+        assert!(!values(&picker).contains(&"policy".to_string()));
+        assert!(!values(&picker).contains(&"help".to_string()));
+
+        // Matches on words of the description too
+        for c in "bash exit".chars() {
+            picker.key(KeyCode::Char(c));
+        }
+        assert_eq!(values(&picker), ["commands"]);
+        assert!(matches!(picker.key(KeyCode::Enter), Some(Outcome::Picked(v)) if v == "commands"));
+        // The command named like the query comes before those describing it
+        let mut picker = Picker::new(String::new(), commands(&cli));
+        for c in "files".chars() {
+            picker.key(KeyCode::Char(c));
+        }
+        assert_eq!(values(&picker)[0], "files");
+        assert!(values(&picker).len() > 1);
+
+        // Free text when nothing is offered
+        let mut prompt = Picker::new(String::new(), Vec::new());
+        assert!(prompt.key(KeyCode::Enter).is_none());
+        for c in "toolu_1".chars() {
+            prompt.key(KeyCode::Char(c));
+        }
+        assert!(matches!(prompt.key(KeyCode::Enter), Some(Outcome::Picked(v)) if v == "toolu_1"));
+
+        let copy = cli.find_subcommand("copy").unwrap();
+        let ids: Vec<&str> = required(copy).iter().map(|a| a.get_id().as_str()).collect();
+        assert_eq!(ids, ["id"]);
+        let mut argv = vec!["sentinel".to_string(), "run".to_string()];
+        let run = cli.find_subcommand("run").unwrap();
+        let command = run.get_arguments().find(|a| a.get_id() == "command").unwrap();
+        push_arg(&mut argv, command, "claude -p 'fix the  bug'");
+        assert_eq!(argv, ["sentinel", "run", "--", "claude", "-p", "fix the  bug"]);
+        let mut argv = vec!["sentinel".to_string(), "search".to_string()];
+        let search = cli.find_subcommand("search").unwrap();
+        let query = required(search)[0];
+        push_arg(&mut argv, query, "auth  bug");
+        assert_eq!(argv[2], "auth  bug");
+    }
+}
@@ -3,8 +3,16 @@
 //! This module provides a trait-based abstraction for parsing LLM responses,
 //! allowing provider-specific implementations while keeping the proxy generic.
 
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
+
+mod gemini;
+mod openai;
+
+pub use gemini::GeminiParser;
+pub use openai::OpenAiParser;
+
 #[derive(Debug, Deserialize)]
 pub struct AnthropicRequest {
     pub model: String,
@@ -13,6 +21,8 @@ pub struct AnthropicRequest {
     pub system: Option<SystemContent>,
     #[serde(default)]
     pub metadata: Option<RequestMetadata>,
+    #[serde(default)]
+    pub thinking: Option<ThinkingConfig>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -21,7 +31,7 @@ pub struct Message {
     pub content: MessageContent,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Serialize)]
 #[serde(untagged)]
 pub enum MessageContent {
     Text(String),
@@ -41,7 +51,12 @@ pub enum ContentBlock {
     Text { text: String },
     Thinking { thinking: String },
     ToolUse { id: String, name: String, input: serde_json::Value },
-    ToolResult { tool_use_id: String, content: serde_json::Value },
+    ToolResult {
+        tool_use_id: String,
+        content: serde_json::Value,
+        #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+        is_error: bool,
+    },
 }
 
 #[derive(Debug, Deserialize)]
@@ -49,6 +64,16 @@ pub struct RequestMetadata {
     pub user_id: Option<String>,
 }
 
+/// Extended thinking as the request asks for it
+#[derive(Debug, Deserialize)]
+pub struct ThinkingConfig {
+    /// "enabled" or "disabled"
+    #[serde(rename = "type")]
+    pub kind: String,
+    #[serde(default)]
+    pub budget_tokens: Option<u64>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct ResponseMetadata {
     pub model: Option<String>,
@@ -60,9 +85,9 @@ pub struct ResponseMetadata {
 #[serde(tag = "type", rename_all = "snake_case")]
 pub enum SseEvent {
     MessageStart { message: SseMessageStart },
-    ContentBlockStart { index: usize, content_block: SseContentBlock },
-    ContentBlockDelta { index: usize, delta: SseDelta },
-    ContentBlockStop { index: usize },
+    ContentBlockStart { content_block: SseContentBlock },
+    ContentBlockDelta { delta: SseDelta },
+    ContentBlockStop,
     MessageDelta { delta: SseMessageDelta, usage: Option<Usage> },
     MessageStop,
     Ping,
@@ -77,28 +102,26 @@ pub struct SseMessageStart {
 #[derive(Debug, Deserialize)]
 #[serde(tag = "type", rename_all = "snake_case")]
 pub enum SseContentBlock {
-    Text { text: String },
-    #[serde(rename_all = "snake_case")]
-    Thinking {
-        thinking: String,
+    Text {
         #[serde(default)]
-        signature: String,
+        text: String,
     },
-    ToolUse {
-        id: String,
-        name: String,
+    Thinking {
         #[serde(default)]
-        input: serde_json::Value,
+        thinking: String,
     },
+    ToolUse { id: String, name: String },
 }
 
+// Variant names mirror the wire-format `type` tags
+#[allow(clippy::enum_variant_names)]
 #[derive(Debug, Deserialize)]
 #[serde(tag = "type", rename_all = "snake_case")]
 pub enum SseDelta {
     TextDelta { text: String },
     ThinkingDelta { thinking: String },
     InputJsonDelta { partial_json: String },
-    SignatureDelta { signature: String },
+    SignatureDelta,
 }
 
 #[derive(Debug, Deserialize)]
@@ -125,6 +148,25 @@ pub struct ParsedResponse {
     pub metadata: ResponseMetadata,
     pub is_topic_event: bool,
     pub topic: Option<String>,
+    /// Wire-format types the parser skipped, e.g. `content_block:server_tool_use`
+    #[serde(default)]
+    pub unrecognized: Vec<String>,
+}
+
+/// Content block types the Anthropic parser understands
+const KNOWN_BLOCK_TYPES: &[&str] = &["text", "thinking", "tool_use", "tool_result"];
+
+/// Name the part of an SSE event the parser didn't understand. Error events
+/// are part of the protocol and aren't reported.
+fn unrecognized_marker(event: &serde_json::Value) -> Option<String> {
+    let kind = event.get("type")?.as_str()?;
+    let nested = |field: &str| event[field]["type"].as_str().unwrap_or("unknown").to_string();
+    match kind {
+        "error" => None,
+        "content_block_start" => Some(format!("content_block:{}", nested("content_block"))),
+        "content_block_delta" => Some(format!("delta:{}", nested("delta"))),
+        _ => Some(format!("event:{}", kind)),
+    }
 }
 
 #[derive(Debug, Deserialize)]
@@ -141,14 +183,72 @@ fn parse_topic(text: &Option<String>) -> (bool, Option<String>) {
     (true, title)
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct ToolCall {
     pub id: String,
     pub name: String,
     pub input: serde_json::Value,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// A tool's output, carried back to the model in a user turn
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct ToolResult {
+    pub tool_use_id: String,
+    /// Text of the result; non-text parts such as images are left out
+    pub content: String,
+    /// Whether the client reported the tool as failed
+    #[serde(default)]
+    pub is_error: bool,
+}
+
+/// How a tool call turned out, judged from its result
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ToolStatus {
+    /// The tool ran and reported success
+    Succeeded,
+    /// The tool ran and reported a failure, e.g. a non-zero exit
+    Failed,
+    /// The tool never ran: its input was rejected, the user declined it, or
+    /// the turn was interrupted
+    Errored,
+}
+
+/// What Claude Code sends back, flagged as an error, for a call it didn't run
+const NOT_RUN_MARKERS: &[&str] = &[
+    "<tool_use_error>",
+    "InputValidationError",
+    "No such tool available",
+    "doesn't want to proceed with this tool use",
+    "[Request interrupted by user",
+];
+
+impl ToolResult {
+    pub fn status(&self) -> ToolStatus {
+        if !self.is_error {
+            ToolStatus::Succeeded
+        } else if NOT_RUN_MARKERS
+            .iter()
+            .any(|marker| self.content.contains(marker))
+        {
+            ToolStatus::Errored
+        } else {
+            ToolStatus::Failed
+        }
+    }
+}
+
+impl ToolStatus {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ToolStatus::Succeeded => "succeeded",
+            ToolStatus::Failed => "failed",
+            ToolStatus::Errored => "errored",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct Usage {
     pub input_tokens: Option<i64>,
     pub output_tokens: Option<i64>,
@@ -156,20 +256,76 @@ pub struct Usage {
     pub cache_read_tokens: Option<i64>,
     #[serde(alias = "cache_creation_input_tokens")]
     pub cache_creation_tokens: Option<i64>,
+    /// Cost reported by the upstream, when it reports one (e.g. OpenRouter)
+    #[serde(default)]
+    pub cost_usd: Option<f64>,
+}
+
+impl Usage {
+    /// Every token the response was billed for, cache reads and writes included
+    pub fn total_tokens(&self) -> i64 {
+        [
+            self.input_tokens,
+            self.output_tokens,
+            self.cache_read_tokens,
+            self.cache_creation_tokens,
+        ]
+        .iter()
+        .map(|n| n.unwrap_or(0))
+        .sum()
+    }
 }
 
 /// Trait for parsing LLM responses from different providers.
 pub trait ResponseParser: Send + Sync {
-    /// Parse a streaming response (SSE format)
-    fn parse_streaming(&self, raw: &str) -> ParsedResponse;
+    /// Start parsing a streaming response (SSE format) as it arrives
+    fn stream_parser(&self) -> Box<dyn StreamParser>;
+
+    /// Parse a whole streaming response
+    fn parse_streaming(&self, raw: &str) -> ParsedResponse {
+        let mut stream = self.stream_parser();
+        let mut deltas = Vec::new();
+        for line in raw.lines() {
+            stream.push_line(line, &mut deltas);
+            deltas.clear();
+        }
+        stream.finish()
+    }
 
     /// Parse a non-streaming JSON response
     fn parse_json(&self, json: &serde_json::Value) -> ParsedResponse;
 
+    /// Whether a non-streaming JSON body looks like a model response
+    fn is_response(&self, json: &serde_json::Value) -> bool;
+
     /// Provider name for identification
     fn provider(&self) -> &'static str;
 }
 
+/// Parses a streaming response one line at a time, so only what it adds up
+/// to is held rather than the stream itself
+pub trait StreamParser: Send {
+    /// The next line of the stream, without its line ending; what it added to
+    /// the response goes into `deltas`
+    fn push_line(&mut self, line: &str, deltas: &mut Vec<Delta>);
+
+    fn finish(self: Box<Self>) -> ParsedResponse;
+}
+
+/// A piece of a streaming response, as it was parsed
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Delta {
+    #[serde(rename = "text_delta")]
+    Text { text: String },
+    #[serde(rename = "thinking_delta")]
+    Thinking { thinking: String },
+    /// `index` counts the response's tool calls from 0
+    ToolUseStarted { index: usize, id: String, name: String },
+    #[serde(rename = "tool_input_delta")]
+    ToolInput { index: usize, partial_json: String },
+}
+
 /// Anthropic API response parser
 pub struct AnthropicParser;
 
@@ -177,67 +333,113 @@ impl AnthropicParser {
     pub fn new() -> Self {
         Self
     }
+}
 
-    fn parse_sse_events(&self, raw: &str) -> ParsedResponse {
-        let mut thinking = String::new();
-        let mut text = String::new();
-        let mut tool_calls = Vec::new();
-        let mut usage = None;
-        let mut metadata = ResponseMetadata::default();
-
-        let mut current_tool_id: Option<String> = None;
-        let mut current_tool_name: Option<String> = None;
-        let mut current_tool_input = String::new();
+/// An Anthropic message stream being parsed
+#[derive(Default)]
+struct AnthropicStream {
+    thinking: String,
+    text: String,
+    tool_calls: Vec<ToolCall>,
+    usage: Option<Usage>,
+    metadata: ResponseMetadata,
+    current_tool_id: Option<String>,
+    current_tool_name: Option<String>,
+    current_tool_input: String,
+    unrecognized: Vec<String>,
+}
 
-        for line in raw.lines() {
-            let Some(data) = line.strip_prefix("data: ") else { continue };
-            let Ok(event) = serde_json::from_str::<SseEvent>(data) else { continue };
+impl StreamParser for AnthropicStream {
+    fn push_line(&mut self, line: &str, deltas: &mut Vec<Delta>) {
+        let Some(data) = line.strip_prefix("data: ") else { return };
+        let Ok(event) = serde_json::from_str::<SseEvent>(data) else {
+            let marker = serde_json::from_str::<serde_json::Value>(data)
+                .ok()
+                .and_then(|event| unrecognized_marker(&event));
+            if let Some(marker) = marker {
+                if !self.unrecognized.contains(&marker) {
+                    self.unrecognized.push(marker);
+                }
+            }
+            return;
+        };
 
-            match event {
-                SseEvent::MessageStart { message } => {
-                    metadata.model = Some(message.model);
-                    metadata.message_id = Some(message.id);
+        let index = self.tool_calls.len();
+        let delta = match event {
+            SseEvent::MessageStart { message } => {
+                self.metadata.model = Some(message.model);
+                self.metadata.message_id = Some(message.id);
+                None
+            }
+            SseEvent::ContentBlockStart { content_block } => match content_block {
+                SseContentBlock::Text { text: t } => {
+                    self.text.push_str(&t);
+                    Some(Delta::Text { text: t })
                 }
-                SseEvent::ContentBlockStart { content_block, .. } => {
-                    if let SseContentBlock::ToolUse { id, name, .. } = content_block {
-                        current_tool_id = Some(id);
-                        current_tool_name = Some(name);
-                        current_tool_input.clear();
-                    }
+                SseContentBlock::Thinking { thinking: t } => {
+                    self.thinking.push_str(&t);
+                    Some(Delta::Thinking { thinking: t })
                 }
-                SseEvent::ContentBlockDelta { delta, .. } => match delta {
-                    SseDelta::ThinkingDelta { thinking: t } => thinking.push_str(&t),
-                    SseDelta::TextDelta { text: t } => text.push_str(&t),
-                    SseDelta::InputJsonDelta { partial_json } => current_tool_input.push_str(&partial_json),
-                    SseDelta::SignatureDelta { .. } => {}
-                },
-                SseEvent::ContentBlockStop { .. } => {
-                    if let (Some(id), Some(name)) = (current_tool_id.take(), current_tool_name.take()) {
-                        let input = serde_json::from_str(&current_tool_input).unwrap_or_default();
-                        tool_calls.push(ToolCall { id, name, input });
-                        current_tool_input.clear();
-                    }
+                SseContentBlock::ToolUse { id, name } => {
+                    self.current_tool_id = Some(id.clone());
+                    self.current_tool_name = Some(name.clone());
+                    self.current_tool_input.clear();
+                    Some(Delta::ToolUseStarted { index, id, name })
+                }
+            },
+            SseEvent::ContentBlockDelta { delta } => match delta {
+                SseDelta::ThinkingDelta { thinking: t } => {
+                    self.thinking.push_str(&t);
+                    Some(Delta::Thinking { thinking: t })
+                }
+                SseDelta::TextDelta { text: t } => {
+                    self.text.push_str(&t);
+                    Some(Delta::Text { text: t })
                 }
-                SseEvent::MessageDelta { delta, usage: u } => {
-                    metadata.stop_reason = delta.stop_reason;
-                    usage = u;
+                SseDelta::InputJsonDelta { partial_json } => {
+                    self.current_tool_input.push_str(&partial_json);
+                    Some(Delta::ToolInput { index, partial_json })
                 }
-                SseEvent::MessageStop | SseEvent::Ping => {}
+                SseDelta::SignatureDelta => None,
+            },
+            SseEvent::ContentBlockStop => {
+                if let (Some(id), Some(name)) = (self.current_tool_id.take(), self.current_tool_name.take()) {
+                    let input = serde_json::from_str(&self.current_tool_input).unwrap_or_default();
+                    self.tool_calls.push(ToolCall { id, name, input });
+                    self.current_tool_input.clear();
+                }
+                None
+            }
+            SseEvent::MessageDelta { delta, usage: u } => {
+                self.metadata.stop_reason = delta.stop_reason;
+                self.usage = u;
+                None
             }
+            SseEvent::MessageStop | SseEvent::Ping => None,
+        };
+        // Blocks open with empty text
+        match delta {
+            Some(Delta::Text { ref text }) if text.is_empty() => {}
+            Some(Delta::Thinking { ref thinking }) if thinking.is_empty() => {}
+            Some(delta) => deltas.push(delta),
+            None => {}
         }
+    }
 
-        let text = if text.is_empty() { None } else { Some(text) };
+    fn finish(self: Box<Self>) -> ParsedResponse {
+        let text = if self.text.is_empty() { None } else { Some(self.text) };
         let (is_topic_event, topic) = parse_topic(&text);
 
         ParsedResponse {
-            thinking: if thinking.is_empty() { None } else { Some(thinking) },
+            thinking: if self.thinking.is_empty() { None } else { Some(self.thinking) },
             text,
-            tool_calls,
-            usage,
+            tool_calls: self.tool_calls,
+            usage: self.usage,
             streaming: true,
-            metadata,
+            metadata: self.metadata,
             is_topic_event,
             topic,
+            unrecognized: self.unrecognized,
         }
     }
 }
@@ -249,13 +451,32 @@ impl Default for AnthropicParser {
 }
 
 impl ResponseParser for AnthropicParser {
-    fn parse_streaming(&self, raw: &str) -> ParsedResponse {
-        self.parse_sse_events(raw)
+    fn stream_parser(&self) -> Box<dyn StreamParser> {
+        Box::<AnthropicStream>::default()
     }
 
     fn parse_json(&self, json: &serde_json::Value) -> ParsedResponse {
-        let Ok(response) = serde_json::from_value::<AnthropicResponse>(json.clone()) else {
-            return ParsedResponse::default();
+        // Parse around block types we don't know rather than dropping the response
+        let mut json = json.clone();
+        let mut unrecognized = Vec::new();
+        if let Some(blocks) = json.get_mut("content").and_then(|c| c.as_array_mut()) {
+            blocks.retain(|block| {
+                let kind = block["type"].as_str().unwrap_or("unknown");
+                if KNOWN_BLOCK_TYPES.contains(&kind) {
+                    return true;
+                }
+                let marker = format!("content_block:{}", kind);
+                if !unrecognized.contains(&marker) {
+                    unrecognized.push(marker);
+                }
+                false
+            });
+        }
+        let Ok(response) = serde_json::from_value::<AnthropicResponse>(json) else {
+            return ParsedResponse {
+                unrecognized,
+                ..ParsedResponse::default()
+            };
         };
 
         let mut thinking = None;
@@ -288,19 +509,99 @@ impl ResponseParser for AnthropicParser {
                 stop_reason: response.stop_reason,
             },
             topic,
+            unrecognized,
         }
     }
 
+    fn is_response(&self, json: &serde_json::Value) -> bool {
+        json.get("content").is_some() || json.get("type").is_some()
+    }
+
     fn provider(&self) -> &'static str {
         "anthropic"
     }
 }
 
 impl AnthropicRequest {
+    /// Tokens the request lets the model think for, when thinking is enabled
+    pub fn thinking_budget(&self) -> Option<u64> {
+        self.thinking
+            .as_ref()
+            .filter(|thinking| thinking.kind == "enabled")
+            .and_then(|thinking| thinking.budget_tokens)
+    }
+
+    /// The conversation's opening user turn; a subagent's is its Task prompt
+    pub fn first_user_message_text(&self) -> Option<String> {
+        let user_msg = self.messages.iter().find(|m| m.role == "user")?;
+        Some(user_msg.content.text())
+    }
+
     pub fn last_user_message_text(&self) -> Option<String> {
         let user_msg = self.messages.iter().rev().find(|m| m.role == "user")?;
         Some(user_msg.content.text())
     }
+
+    /// Tool calls from the latest assistant turn, whose results this request
+    /// carries back to the model
+    pub fn last_tool_calls(&self) -> Vec<ToolCall> {
+        let Some(assistant) = self.messages.iter().rev().find(|m| m.role == "assistant") else {
+            return Vec::new();
+        };
+        let MessageContent::Blocks(ref blocks) = assistant.content else {
+            return Vec::new();
+        };
+        blocks
+            .iter()
+            .filter_map(|block| match block {
+                ContentBlock::ToolUse { id, name, input } => Some(ToolCall {
+                    id: id.clone(),
+                    name: name.clone(),
+                    input: input.clone(),
+                }),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Tool results the latest user turn carries back to the model
+    pub fn last_tool_results(&self) -> Vec<ToolResult> {
+        let Some(user_msg) = self.messages.iter().rev().find(|m| m.role == "user") else {
+            return Vec::new();
+        };
+        let MessageContent::Blocks(ref blocks) = user_msg.content else {
+            return Vec::new();
+        };
+        blocks
+            .iter()
+            .filter_map(|block| match block {
+                ContentBlock::ToolResult {
+                    tool_use_id,
+                    content,
+                    is_error,
+                } => Some(ToolResult {
+                    tool_use_id: tool_use_id.clone(),
+                    content: tool_result_text(content),
+                    is_error: *is_error,
+                }),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+/// A tool_result's content is a string or a list of content blocks
+fn tool_result_text(content: &serde_json::Value) -> String {
+    match content {
+        serde_json::Value::String(s) => s.clone(),
+        serde_json::Value::Array(parts) => parts
+            .iter()
+            .filter_map(|part| part.get("text").and_then(|t| t.as_str()))
+            .collect::<Vec<_>>()
+            .join("\n"),
+        serde_json::Value::Null => String::new(),
+        other => other.to_string(),
+    }
 }
 
 impl MessageContent {
@@ -348,6 +649,65 @@ data: {"type":"message_stop"}
         assert!(parsed.streaming);
     }
 
+    #[test]
+    fn test_stream_lines_yield_deltas() {
+        let mut stream = AnthropicParser::new().stream_parser();
+        let mut deltas = Vec::new();
+        for line in [
+            r#"data: {"type":"content_block_start","index":0,"content_block":{"type":"text","text":""}}"#,
+            r#"data: {"type":"content_block_delta","index":0,"delta":{"type":"text_delta","text":"Let me look"}}"#,
+            r#"data: {"type":"content_block_stop","index":0}"#,
+            r#"data: {"type":"content_block_start","index":1,"content_block":{"type":"tool_use","id":"t1","name":"Bash"}}"#,
+            r#"data: {"type":"content_block_delta","index":1,"delta":{"type":"input_json_delta","partial_json":"{\"command\":"}}"#,
+            r#"data: {"type":"content_block_delta","index":1,"delta":{"type":"input_json_delta","partial_json":"\"ls\"}"}}"#,
+            r#"data: {"type":"content_block_stop","index":1}"#,
+        ] {
+            stream.push_line(line, &mut deltas);
+        }
+        assert_eq!(deltas.len(), 4);
+        assert_eq!(
+            deltas[1],
+            Delta::ToolUseStarted {
+                index: 0,
+                id: "t1".to_string(),
+                name: "Bash".to_string()
+            }
+        );
+        assert_eq!(
+            serde_json::to_value(&deltas[3]).unwrap(),
+            serde_json::json!({"kind": "tool_input_delta", "index": 0, "partial_json": "\"ls\"}"})
+        );
+        let parsed = stream.finish();
+        assert_eq!(parsed.tool_calls[0].input["command"], "ls");
+    }
+
+    #[test]
+    fn test_unrecognized_types_are_reported() {
+        let parser = AnthropicParser::new();
+        let sse = r#"event: content_block_start
+data: {"type":"content_block_start","index":0,"content_block":{"type":"server_tool_use","id":"s1"}}
+
+event: content_block_delta
+data: {"type":"content_block_delta","index":1,"delta":{"type":"citations_delta","citation":{}}}
+
+event: error
+data: {"type":"error","error":{"type":"overloaded_error"}}
+"#;
+        let parsed = parser.parse_streaming(sse);
+        assert_eq!(
+            parsed.unrecognized,
+            vec!["content_block:server_tool_use", "delta:citations_delta"]
+        );
+
+        let json = serde_json::json!({
+            "id": "msg_1", "model": "claude-3", "stop_reason": "end_turn",
+            "content": [{"type": "redacted_thinking", "data": "x"}, {"type": "text", "text": "hi"}]
+        });
+        let parsed = parser.parse_json(&json);
+        assert_eq!(parsed.text.as_deref(), Some("hi"));
+        assert_eq!(parsed.unrecognized, vec!["content_block:redacted_thinking"]);
+    }
+
     #[test]
     fn test_parse_topic_new() {
         let text = Some(r#"{"isNewTopic": true, "title": "Fix auth bug"}"#.to_string());
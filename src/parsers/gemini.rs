@@ -0,0 +1,265 @@
+//! Google Gemini `generateContent` parser.
+//!
+//! `streamGenerateContent` sends a series of complete `GenerateContentResponse`
+//! chunks: as SSE `data:` lines with `?alt=sse`, otherwise as one JSON array
+//! streamed under `application/json`. Both are folded into one response.
+//! Thinking arrives as text parts flagged `"thought": true`, tool calls as
+//! `functionCall` parts, and usage in `usageMetadata`, where cached tokens are
+//! counted inside the prompt total.
+
+use serde_json::Value;
+
+use super::openai::{non_empty, str_field};
+use super::{
+    Delta, ParsedResponse, ResponseMetadata, ResponseParser, StreamParser, ToolCall, Usage,
+};
+
+pub struct GeminiParser;
+
+impl GeminiParser {
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+impl Default for GeminiParser {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Part keys the parser understands
+const KNOWN_PART_KEYS: &[&str] = &["text", "thought", "thoughtSignature", "functionCall"];
+
+/// Accumulates chunks of one response
+#[derive(Default)]
+struct Collector {
+    thinking: String,
+    text: String,
+    tool_calls: Vec<ToolCall>,
+    usage: Option<Usage>,
+    metadata: ResponseMetadata,
+    unrecognized: Vec<String>,
+}
+
+impl Collector {
+    fn push(&mut self, chunk: &Value) {
+        if self.metadata.message_id.is_none() {
+            self.metadata.message_id = str_field(chunk, "responseId");
+        }
+        if self.metadata.model.is_none() {
+            self.metadata.model = str_field(chunk, "modelVersion");
+        }
+        // Every chunk repeats the running totals; the last one is final
+        if let Some(usage) = chunk.get("usageMetadata").and_then(parse_usage) {
+            self.usage = Some(usage);
+        }
+
+        let Some(candidate) = chunk
+            .get("candidates")
+            .and_then(Value::as_array)
+            .and_then(|c| c.first())
+        else {
+            return;
+        };
+        if let Some(reason) = str_field(candidate, "finishReason") {
+            self.metadata.stop_reason = Some(reason);
+        }
+        let parts = candidate
+            .pointer("/content/parts")
+            .and_then(Value::as_array)
+            .map(Vec::as_slice)
+            .unwrap_or(&[]);
+        for part in parts {
+            self.push_part(part);
+        }
+    }
+
+    fn push_part(&mut self, part: &Value) {
+        if let Some(text) = part.get("text").and_then(Value::as_str) {
+            if part.get("thought").and_then(Value::as_bool) == Some(true) {
+                self.thinking.push_str(text);
+            } else {
+                self.text.push_str(text);
+            }
+        }
+        if let Some(call) = part.get("functionCall") {
+            self.tool_calls.push(ToolCall {
+                id: str_field(call, "id").unwrap_or_default(),
+                name: str_field(call, "name").unwrap_or_default(),
+                input: call
+                    .get("args")
+                    .cloned()
+                    .unwrap_or_else(|| Value::Object(Default::default())),
+            });
+        }
+        let Some(fields) = part.as_object() else {
+            return;
+        };
+        for key in fields.keys() {
+            if !KNOWN_PART_KEYS.contains(&key.as_str()) {
+                let marker = format!("part:{}", key);
+                if !self.unrecognized.contains(&marker) {
+                    self.unrecognized.push(marker);
+                }
+            }
+        }
+    }
+
+    fn finish(self, streaming: bool) -> ParsedResponse {
+        ParsedResponse {
+            thinking: non_empty(self.thinking),
+            text: non_empty(self.text),
+            tool_calls: self.tool_calls,
+            usage: self.usage,
+            streaming,
+            metadata: self.metadata,
+            is_topic_event: false,
+            topic: None,
+            unrecognized: self.unrecognized,
+        }
+    }
+}
+
+/// `alt=sse` streams carry one chunk per `data:` line
+impl StreamParser for Collector {
+    fn push_line(&mut self, line: &str, deltas: &mut Vec<Delta>) {
+        let Some(data) = line.strip_prefix("data:") else {
+            return;
+        };
+        let chunk = match serde_json::from_str::<Value>(data.trim()) {
+            Ok(chunk) => chunk,
+            Err(e) => {
+                tracing::warn!("Skipping unparseable Gemini chunk: {}", e);
+                return;
+            }
+        };
+        let (thinking, text, calls) = (self.thinking.len(), self.text.len(), self.tool_calls.len());
+        self.push(&chunk);
+
+        if self.thinking.len() > thinking {
+            deltas.push(Delta::Thinking {
+                thinking: self.thinking[thinking..].to_string(),
+            });
+        }
+        if self.text.len() > text {
+            deltas.push(Delta::Text {
+                text: self.text[text..].to_string(),
+            });
+        }
+        // Calls arrive whole, so each one's input comes in a single delta
+        for (index, call) in self.tool_calls.iter().enumerate().skip(calls) {
+            deltas.push(Delta::ToolUseStarted {
+                index,
+                id: call.id.clone(),
+                name: call.name.clone(),
+            });
+            deltas.push(Delta::ToolInput {
+                index,
+                partial_json: call.input.to_string(),
+            });
+        }
+    }
+
+    fn finish(self: Box<Self>) -> ParsedResponse {
+        Collector::finish(*self, true)
+    }
+}
+
+impl ResponseParser for GeminiParser {
+    fn stream_parser(&self) -> Box<dyn StreamParser> {
+        Box::<Collector>::default()
+    }
+
+    fn parse_json(&self, json: &Value) -> ParsedResponse {
+        let mut collector = Collector::default();
+        match json {
+            // streamGenerateContent without alt=sse
+            Value::Array(chunks) => {
+                for chunk in chunks {
+                    collector.push(chunk);
+                }
+                collector.finish(true)
+            }
+            chunk => {
+                collector.push(chunk);
+                collector.finish(false)
+            }
+        }
+    }
+
+    fn is_response(&self, json: &Value) -> bool {
+        match json {
+            Value::Array(chunks) => chunks
+                .first()
+                .is_some_and(|c| c.get("candidates").is_some()),
+            chunk => chunk.get("candidates").is_some(),
+        }
+    }
+
+    fn provider(&self) -> &'static str {
+        "gemini"
+    }
+}
+
+/// Gemini bills thinking as output, so thought tokens count toward it
+fn parse_usage(usage: &Value) -> Option<Usage> {
+    if !usage.is_object() {
+        return None;
+    }
+    let tokens = |key: &str| usage.get(key).and_then(Value::as_i64);
+    let output = match (tokens("candidatesTokenCount"), tokens("thoughtsTokenCount")) {
+        (None, None) => None,
+        (candidates, thoughts) => Some(candidates.unwrap_or(0) + thoughts.unwrap_or(0)),
+    };
+    Some(Usage {
+        input_tokens: tokens("promptTokenCount"),
+        output_tokens: output,
+        cache_read_tokens: tokens("cachedContentTokenCount"),
+        cache_creation_tokens: None,
+        cost_usd: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sse_stream_with_thoughts_and_function_call() {
+        let sse = r#"data: {"candidates":[{"content":{"parts":[{"text":"Need the file","thought":true}],"role":"model"}}],"usageMetadata":{"promptTokenCount":40},"modelVersion":"gemini-2.5-pro","responseId":"resp-1"}
+
+data: {"candidates":[{"content":{"parts":[{"text":"Reading "},{"text":"it now"}],"role":"model"}}],"modelVersion":"gemini-2.5-pro","responseId":"resp-1"}
+
+data: {"candidates":[{"content":{"parts":[{"functionCall":{"name":"read_file","args":{"path":"src/main.rs"}}},{"executableCode":{"code":"print(1)"}}],"role":"model"},"finishReason":"STOP"}],"usageMetadata":{"promptTokenCount":40,"candidatesTokenCount":12,"thoughtsTokenCount":30,"cachedContentTokenCount":32},"modelVersion":"gemini-2.5-pro","responseId":"resp-1"}
+"#;
+
+        let parsed = GeminiParser::new().parse_streaming(sse);
+        assert_eq!(parsed.thinking, Some("Need the file".to_string()));
+        assert_eq!(parsed.text, Some("Reading it now".to_string()));
+        assert_eq!(parsed.tool_calls[0].name, "read_file");
+        assert_eq!(parsed.tool_calls[0].input["path"], "src/main.rs");
+        assert_eq!(parsed.metadata.model, Some("gemini-2.5-pro".to_string()));
+        assert_eq!(parsed.metadata.stop_reason, Some("STOP".to_string()));
+        assert_eq!(parsed.unrecognized, vec!["part:executableCode".to_string()]);
+
+        let usage = parsed.usage.expect("usage should be parsed");
+        assert_eq!(usage.input_tokens, Some(40));
+        assert_eq!(usage.output_tokens, Some(42));
+        assert_eq!(usage.cache_read_tokens, Some(32));
+    }
+
+    #[test]
+    fn test_json_array_stream_is_merged() {
+        let parser = GeminiParser::new();
+        let json = serde_json::json!([
+            {"candidates": [{"content": {"parts": [{"text": "Hel"}]}}]},
+            {"candidates": [{"content": {"parts": [{"text": "lo"}]}, "finishReason": "STOP"}]}
+        ]);
+
+        assert!(parser.is_response(&json));
+        let parsed = parser.parse_json(&json);
+        assert_eq!(parsed.text, Some("Hello".to_string()));
+        assert!(parsed.streaming);
+    }
+}
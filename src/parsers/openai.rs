@@ -0,0 +1,467 @@
+//! OpenAI chat-completions parser.
+//!
+//! Gateways like OpenRouter and LiteLLM speak this format but decorate it:
+//! extra provider metadata, usage blocks that arrive in a trailing chunk with
+//! no choices, reasoning under `reasoning` or `reasoning_content`, token counts
+//! as floats, and cost reported inline. Mistral and DeepSeek are OpenAI-shaped
+//! too: Mistral's reasoning models put `thinking` chunks in the content array,
+//! and DeepSeek reports reasoning as `reasoning_content` and cache hits as
+//! `prompt_cache_hit_tokens`. Parsing walks `serde_json::Value`
+//! instead of strict structs so one unexpected field doesn't drop the whole
+//! response.
+
+use std::collections::BTreeMap;
+
+use serde_json::Value;
+
+use super::{
+    Delta, ParsedResponse, ResponseMetadata, ResponseParser, StreamParser, ToolCall, Usage,
+};
+
+pub struct OpenAiParser {
+    provider: &'static str,
+}
+
+impl OpenAiParser {
+    pub fn openai() -> Self {
+        Self { provider: "openai" }
+    }
+
+    pub fn openrouter() -> Self {
+        Self {
+            provider: "openrouter",
+        }
+    }
+
+    pub fn litellm() -> Self {
+        Self {
+            provider: "litellm",
+        }
+    }
+
+    pub fn mistral() -> Self {
+        Self {
+            provider: "mistral",
+        }
+    }
+
+    pub fn azure() -> Self {
+        Self { provider: "azure" }
+    }
+
+    pub fn deepseek() -> Self {
+        Self {
+            provider: "deepseek",
+        }
+    }
+}
+
+#[derive(Default)]
+struct PartialToolCall {
+    id: String,
+    name: String,
+    arguments: String,
+}
+
+/// A chat-completions stream being parsed
+#[derive(Default)]
+struct OpenAiStream {
+    thinking: String,
+    text: String,
+    tool_calls: BTreeMap<u64, PartialToolCall>,
+    usage: Option<Usage>,
+    metadata: ResponseMetadata,
+}
+
+impl StreamParser for OpenAiStream {
+    fn push_line(&mut self, line: &str, deltas: &mut Vec<Delta>) {
+        let Some(data) = line.strip_prefix("data:") else {
+            return;
+        };
+        let data = data.trim();
+        if data == "[DONE]" {
+            return;
+        }
+        let Ok(chunk) = serde_json::from_str::<Value>(data) else {
+            return;
+        };
+
+        if self.metadata.message_id.is_none() {
+            self.metadata.message_id = str_field(&chunk, "id");
+        }
+        if self.metadata.model.is_none() {
+            self.metadata.model = str_field(&chunk, "model");
+        }
+        // Gateways send usage on the final chunk, often with empty choices
+        if let Some(u) = chunk.get("usage").and_then(parse_usage) {
+            self.usage = Some(u);
+        }
+
+        let Some(choice) = first_choice(&chunk) else {
+            return;
+        };
+        if let Some(reason) = str_field(choice, "finish_reason") {
+            self.metadata.stop_reason = Some(reason);
+        }
+        let Some(delta) = choice.get("delta") else {
+            return;
+        };
+
+        if let Some(t) = reasoning_text(delta).filter(|t| !t.is_empty()) {
+            self.thinking.push_str(&t);
+            deltas.push(Delta::Thinking { thinking: t });
+        }
+        if let Some(t) = content_text(delta.get("content")).filter(|t| !t.is_empty()) {
+            self.text.push_str(&t);
+            deltas.push(Delta::Text { text: t });
+        }
+        for (position, call) in array_field(delta, "tool_calls").iter().enumerate() {
+            let index = call
+                .get("index")
+                .and_then(Value::as_u64)
+                .unwrap_or(position as u64);
+            let entry = self.tool_calls.entry(index).or_default();
+            if let Some(id) = str_field(call, "id") {
+                entry.id = id;
+            }
+            let Some(function) = call.get("function") else {
+                continue;
+            };
+            // The name comes once, in the call's first chunk
+            if let Some(name) = str_field(function, "name") {
+                if entry.name.is_empty() {
+                    deltas.push(Delta::ToolUseStarted {
+                        index: index as usize,
+                        id: entry.id.clone(),
+                        name: name.clone(),
+                    });
+                }
+                entry.name = name;
+            }
+            let partial = match function.get("arguments") {
+                Some(Value::String(s)) => s.clone(),
+                Some(other) => other.to_string(),
+                None => continue,
+            };
+            if !partial.is_empty() {
+                entry.arguments.push_str(&partial);
+                deltas.push(Delta::ToolInput {
+                    index: index as usize,
+                    partial_json: partial,
+                });
+            }
+        }
+    }
+
+    fn finish(self: Box<Self>) -> ParsedResponse {
+        let tool_calls = self
+            .tool_calls
+            .into_values()
+            .map(|call| ToolCall {
+                id: call.id,
+                name: call.name,
+                input: parse_arguments(&Value::String(call.arguments)),
+            })
+            .collect();
+
+        ParsedResponse {
+            thinking: non_empty(self.thinking),
+            text: non_empty(self.text),
+            tool_calls,
+            usage: self.usage,
+            streaming: true,
+            metadata: self.metadata,
+            is_topic_event: false,
+            topic: None,
+            unrecognized: Vec::new(),
+        }
+    }
+}
+
+impl ResponseParser for OpenAiParser {
+    fn stream_parser(&self) -> Box<dyn StreamParser> {
+        Box::<OpenAiStream>::default()
+    }
+
+    fn parse_json(&self, json: &Value) -> ParsedResponse {
+        let mut parsed = ParsedResponse {
+            usage: json.get("usage").and_then(parse_usage),
+            metadata: ResponseMetadata {
+                model: str_field(json, "model"),
+                message_id: str_field(json, "id"),
+                stop_reason: None,
+            },
+            ..ParsedResponse::default()
+        };
+
+        let Some(choice) = first_choice(json) else {
+            return parsed;
+        };
+        parsed.metadata.stop_reason = str_field(choice, "finish_reason");
+
+        let Some(message) = choice.get("message") else {
+            return parsed;
+        };
+        parsed.thinking = reasoning_text(message);
+        parsed.text = content_text(message.get("content"));
+        parsed.tool_calls = array_field(message, "tool_calls")
+            .iter()
+            .filter_map(|call| {
+                let function = call.get("function")?;
+                Some(ToolCall {
+                    id: str_field(call, "id").unwrap_or_default(),
+                    name: str_field(function, "name")?,
+                    input: function
+                        .get("arguments")
+                        .map(parse_arguments)
+                        .unwrap_or_default(),
+                })
+            })
+            .collect();
+
+        parsed
+    }
+
+    fn is_response(&self, json: &Value) -> bool {
+        json.get("choices").is_some()
+    }
+
+    fn provider(&self) -> &'static str {
+        self.provider
+    }
+}
+
+fn first_choice(json: &Value) -> Option<&Value> {
+    json.get("choices")?.as_array()?.first()
+}
+
+fn array_field<'a>(json: &'a Value, key: &str) -> &'a [Value] {
+    json.get(key)
+        .and_then(Value::as_array)
+        .map(Vec::as_slice)
+        .unwrap_or(&[])
+}
+
+pub(super) fn str_field(json: &Value, key: &str) -> Option<String> {
+    json.get(key)?.as_str().map(String::from)
+}
+
+pub(super) fn non_empty(s: String) -> Option<String> {
+    if s.is_empty() {
+        None
+    } else {
+        Some(s)
+    }
+}
+
+/// OpenRouter uses `reasoning`; LiteLLM and DeepSeek use `reasoning_content`;
+/// Mistral sends `thinking` parts inside the content array.
+fn reasoning_text(message: &Value) -> Option<String> {
+    let field = ["reasoning_content", "reasoning"]
+        .iter()
+        .find_map(|key| message.get(*key)?.as_str())
+        .filter(|s| !s.is_empty())
+        .map(String::from);
+    if field.is_some() {
+        return field;
+    }
+
+    let parts = message.get("content")?.as_array()?;
+    let thinking: Vec<String> = parts
+        .iter()
+        .filter(|p| p.get("type").and_then(Value::as_str) == Some("thinking"))
+        .filter_map(|p| match p.get("thinking")? {
+            Value::String(s) => Some(s.clone()),
+            Value::Array(chunks) => text_parts(chunks),
+            _ => None,
+        })
+        .collect();
+    non_empty(thinking.concat())
+}
+
+/// Content is usually a string, but some gateways return an array of parts.
+fn content_text(content: Option<&Value>) -> Option<String> {
+    match content? {
+        Value::String(s) if !s.is_empty() => Some(s.clone()),
+        Value::Array(parts) => text_parts(parts),
+        _ => None,
+    }
+}
+
+fn text_parts(parts: &[Value]) -> Option<String> {
+    let text: Vec<&str> = parts
+        .iter()
+        .filter(|p| p.get("type").and_then(Value::as_str) == Some("text"))
+        .filter_map(|p| p.get("text")?.as_str())
+        .collect();
+    non_empty(text.concat())
+}
+
+/// Tool arguments are a JSON-encoded string in the OpenAI spec; some
+/// providers send the object directly.
+fn parse_arguments(args: &Value) -> Value {
+    match args {
+        Value::String(s) if s.is_empty() => Value::Object(Default::default()),
+        Value::String(s) => match serde_json::from_str(s) {
+            Ok(v) => v,
+            Err(e) => {
+                tracing::warn!("Tool call arguments are not valid JSON: {}", e);
+                Value::String(s.clone())
+            }
+        },
+        other => other.clone(),
+    }
+}
+
+/// Normalize OpenAI-style and gateway usage blocks into `Usage`.
+pub(super) fn parse_usage(usage: &Value) -> Option<Usage> {
+    if !usage.is_object() {
+        return None;
+    }
+    let tokens = |keys: &[&str]| {
+        keys.iter()
+            .find_map(|k| lookup_number(usage, k))
+            .map(|n| n as i64)
+    };
+
+    Some(Usage {
+        input_tokens: tokens(&["prompt_tokens", "input_tokens"]),
+        output_tokens: tokens(&["completion_tokens", "output_tokens"]),
+        cache_read_tokens: tokens(&[
+            "prompt_tokens_details.cached_tokens",
+            "cache_read_input_tokens",
+            "prompt_cache_hit_tokens",
+        ]),
+        cache_creation_tokens: tokens(&["cache_creation_input_tokens"]),
+        cost_usd: lookup_number(usage, "cost"),
+    })
+}
+
+/// Read a number at a dotted path, accepting integers, floats, and numeric strings.
+fn lookup_number(json: &Value, path: &str) -> Option<f64> {
+    let value = path.split('.').try_fold(json, |v, key| v.get(key))?;
+    match value {
+        Value::Number(n) => n.as_f64(),
+        Value::String(s) => s.parse().ok(),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_openrouter_streaming_with_trailing_usage() {
+        let parser = OpenAiParser::openrouter();
+        let sse = r#": OPENROUTER PROCESSING
+
+data: {"id":"gen-1","provider":"Anthropic","model":"anthropic/claude-3.5-sonnet","choices":[{"index":0,"delta":{"role":"assistant","content":"","reasoning":"Thinking"},"finish_reason":null}]}
+
+data: {"id":"gen-1","choices":[{"index":0,"delta":{"content":"Hello"},"finish_reason":null}]}
+
+data: {"id":"gen-1","choices":[{"index":0,"delta":{"tool_calls":[{"index":0,"id":"call_1","type":"function","function":{"name":"get_weather","arguments":"{\"city\":"}}]}}]}
+
+data: {"id":"gen-1","choices":[{"index":0,"delta":{"tool_calls":[{"index":0,"function":{"arguments":"\"Paris\"}"}}]},"finish_reason":"tool_calls"}]}
+
+data: {"id":"gen-1","choices":[],"usage":{"prompt_tokens":12.0,"completion_tokens":7,"total_tokens":19,"cost":0.00042}}
+
+data: [DONE]
+"#;
+
+        let parsed = parser.parse_streaming(sse);
+        assert_eq!(parsed.thinking, Some("Thinking".to_string()));
+        assert_eq!(parsed.text, Some("Hello".to_string()));
+        assert_eq!(
+            parsed.metadata.model,
+            Some("anthropic/claude-3.5-sonnet".to_string())
+        );
+        assert_eq!(parsed.metadata.stop_reason, Some("tool_calls".to_string()));
+        assert_eq!(parsed.tool_calls.len(), 1);
+        assert_eq!(parsed.tool_calls[0].input["city"], "Paris");
+
+        let usage = parsed.usage.expect("usage should be parsed");
+        assert_eq!(usage.input_tokens, Some(12));
+        assert_eq!(usage.output_tokens, Some(7));
+        assert_eq!(usage.cost_usd, Some(0.00042));
+    }
+
+    #[test]
+    fn test_litellm_json_with_reasoning_content() {
+        let parser = OpenAiParser::litellm();
+        let json = serde_json::json!({
+            "id": "chatcmpl-1",
+            "model": "claude-3-5-sonnet",
+            "choices": [{
+                "index": 0,
+                "finish_reason": "stop",
+                "message": {
+                    "role": "assistant",
+                    "content": "Done",
+                    "reasoning_content": "Let me check"
+                }
+            }],
+            "usage": {
+                "prompt_tokens": 100,
+                "completion_tokens": 20,
+                "prompt_tokens_details": {"cached_tokens": 80},
+                "cache_creation_input_tokens": 0
+            }
+        });
+
+        let parsed = parser.parse_json(&json);
+        assert_eq!(parsed.text, Some("Done".to_string()));
+        assert_eq!(parsed.thinking, Some("Let me check".to_string()));
+        assert_eq!(parsed.metadata.stop_reason, Some("stop".to_string()));
+        let usage = parsed.usage.expect("usage should be parsed");
+        assert_eq!(usage.cache_read_tokens, Some(80));
+        assert_eq!(usage.cost_usd, None);
+    }
+
+    #[test]
+    fn test_mistral_thinking_chunks_and_object_arguments() {
+        let parser = OpenAiParser::mistral();
+        let json = serde_json::json!({
+            "id": "cmpl-1",
+            "model": "magistral-medium-latest",
+            "choices": [{
+                "index": 0,
+                "finish_reason": "tool_calls",
+                "message": {
+                    "role": "assistant",
+                    "content": [
+                        {"type": "thinking", "thinking": [{"type": "text", "text": "Need the file"}]},
+                        {"type": "text", "text": "Reading it now"}
+                    ],
+                    "tool_calls": [{
+                        "id": "abc123",
+                        "function": {"name": "read_file", "arguments": {"path": "src/main.rs"}}
+                    }]
+                }
+            }]
+        });
+
+        let parsed = parser.parse_json(&json);
+        assert_eq!(parsed.thinking, Some("Need the file".to_string()));
+        assert_eq!(parsed.text, Some("Reading it now".to_string()));
+        assert_eq!(parsed.tool_calls[0].input["path"], "src/main.rs");
+    }
+
+    #[test]
+    fn test_deepseek_streaming_reasoning_content() {
+        let parser = OpenAiParser::deepseek();
+        let sse = r#"data: {"id":"ds-1","model":"deepseek-reasoner","choices":[{"index":0,"delta":{"role":"assistant","content":null,"reasoning_content":"First, "},"finish_reason":null}]}
+
+data: {"id":"ds-1","choices":[{"index":0,"delta":{"content":null,"reasoning_content":"check inputs."},"finish_reason":null}]}
+
+data: {"id":"ds-1","choices":[{"index":0,"delta":{"content":"42","reasoning_content":null},"finish_reason":"stop"}],"usage":{"prompt_tokens":30,"completion_tokens":10,"prompt_cache_hit_tokens":24,"prompt_cache_miss_tokens":6}}
+
+data: [DONE]
+"#;
+
+        let parsed = parser.parse_streaming(sse);
+        assert_eq!(parsed.thinking, Some("First, check inputs.".to_string()));
+        assert_eq!(parsed.text, Some("42".to_string()));
+        assert_eq!(parsed.usage.and_then(|u| u.cache_read_tokens), Some(24));
+    }
+}
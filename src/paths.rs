@@ -0,0 +1,94 @@
+//! `sentinel paths`: where sentinel keeps its data, for mounting volumes.
+
+use std::path::Path;
+
+use serde::Serialize;
+
+use crate::container::in_container;
+
+#[derive(Debug, Serialize)]
+pub struct DataLayout {
+    /// Profile picked with --profile or SENTINEL_PROFILE
+    pub profile: Option<String>,
+    pub data_dir: String,
+    /// Whether the data dir came from SENTINEL_DATA_DIR, rather than a profile
+    pub data_dir_from_env: bool,
+    pub database: String,
+    /// SQLite's write-ahead log and shared memory files, which must live
+    /// alongside the database; mount the directory, not the file
+    pub database_sidecars: Vec<String>,
+    pub in_container: bool,
+}
+
+pub fn layout(data_dir: &Path, profile: Option<&str>) -> DataLayout {
+    let database = data_dir.join("sentinel.db");
+    DataLayout {
+        profile: profile.map(str::to_string),
+        data_dir: data_dir.display().to_string(),
+        data_dir_from_env: profile.is_none() && std::env::var_os("SENTINEL_DATA_DIR").is_some(),
+        database: database.display().to_string(),
+        database_sidecars: ["-wal", "-shm", "-journal"]
+            .iter()
+            .map(|suffix| format!("{}{}", database.display(), suffix))
+            .collect(),
+        in_container: in_container(),
+    }
+}
+
+pub fn run(
+    data_dir: &Path,
+    profile: Option<&str>,
+    json: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let layout = layout(data_dir, profile);
+    if json {
+        println!("{}", serde_json::to_string_pretty(&layout)?);
+        return Ok(());
+    }
+    if let Some(ref profile) = layout.profile {
+        println!("Profile:        {}", profile);
+    }
+    println!(
+        "Data directory: {}{}",
+        layout.data_dir,
+        if layout.data_dir_from_env {
+            " (SENTINEL_DATA_DIR)"
+        } else {
+            ""
+        }
+    );
+    println!("Database:       {}", layout.database);
+    println!("  plus {}", layout.database_sidecars.join(", "));
+    if layout.in_container {
+        println!(
+            "Running in a container: mount a volume at {}",
+            layout.data_dir
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_layout_names_the_database_and_its_sidecars() {
+        let layout = layout(Path::new("/data/profiles/work"), Some("work"));
+        assert_eq!(layout.profile.as_deref(), Some("work"));
+        // A profile's data dir is its own, whatever SENTINEL_DATA_DIR says
+        assert!(!layout.data_dir_from_env);
+        assert_eq!(layout.database, "/data/profiles/work/sentinel.db");
+        assert_eq!(
+            layout.database_sidecars,
+            [
+                "/data/profiles/work/sentinel.db-wal",
+                "/data/profiles/work/sentinel.db-shm",
+                "/data/profiles/work/sentinel.db-journal",
+            ]
+        );
+        let json = serde_json::to_value(&layout).unwrap();
+        assert_eq!(json["data_dir"], "/data/profiles/work");
+        assert!(json["in_container"].is_boolean());
+    }
+}
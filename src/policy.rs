@@ -0,0 +1,710 @@
+//! Policy-as-code bundles.
+//!
+//! A bundle is a versioned YAML file of guardrail (`block`, `confine`),
+//! `redact`, and `route` rules. `sentinel policy test` replays a recorded
+//! session through the bundle and reports what each rule would have done, so a
+//! bundle can be checked against real traffic before anything is enforced.
+//!
+//! `sentinel start --policy` then applies its guardrails live. Each tool call
+//! is checked as soon as its input has streamed in, and one that breaks a rule
+//! is recorded as a violation (and raised as an alert with `--policy-alert`).
+//! With `--policy-enforce` the stream is cut off there instead: the client
+//! gets an error event in place of the rest of the response, so the call
+//! never runs.
+//!
+//! ```yaml
+//! name: team-defaults
+//! version: "3"
+//! rules:
+//!   - name: no-recursive-delete
+//!     action: block
+//!     tool: Bash
+//!     pattern: 'rm\s+-rf'
+//!   - name: stay-in-repo
+//!     action: confine
+//!   - name: api-keys
+//!     action: redact
+//!     pattern: 'sk-[A-Za-z0-9_-]{20,}'
+//!   - name: haiku-via-openrouter
+//!     action: route
+//!     model: '^claude-3-haiku'
+//!     provider: openrouter
+//! ```
+
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+
+use regex::Regex;
+use serde::Deserialize;
+
+use crate::agent::AgentStore;
+use crate::event::{ObservabilityEvent, Payload, Violation};
+use crate::parsers::{Delta, ToolCall};
+use crate::scope;
+use crate::storage::Storage;
+
+pub const POLICY_ALERT_KIND: &str = "policy_violation";
+
+/// Tools that `confine` rules check when they don't name one
+const WRITE_TOOLS: &[&str] = &["Bash", "Edit", "MultiEdit", "NotebookEdit", "Write"];
+
+#[derive(Debug, thiserror::Error)]
+pub enum PolicyError {
+    #[error("failed to read {path}: {source}")]
+    Read {
+        path: String,
+        source: std::io::Error,
+    },
+    #[error("invalid policy bundle: {0}")]
+    Parse(#[from] serde_yaml::Error),
+    #[error("rule '{rule}': invalid pattern: {source}")]
+    Pattern { rule: String, source: regex::Error },
+    #[error("duplicate rule name '{0}'")]
+    DuplicateRule(String),
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct BundleFile {
+    name: String,
+    #[serde(deserialize_with = "version_string")]
+    version: String,
+    #[serde(default)]
+    description: Option<String>,
+    rules: Vec<RuleFile>,
+}
+
+/// Accept `version: 3` as well as `version: "3.1"`
+fn version_string<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<String, D::Error> {
+    match serde_yaml::Value::deserialize(deserializer)? {
+        serde_yaml::Value::String(s) => Ok(s),
+        serde_yaml::Value::Number(n) => Ok(n.to_string()),
+        other => Err(serde::de::Error::custom(format!(
+            "expected a version string or number, got {:?}",
+            other
+        ))),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case", deny_unknown_fields)]
+enum RuleFile {
+    /// Stop a tool call whose input matches
+    Block {
+        name: String,
+        /// Only apply to this tool; any tool when omitted
+        #[serde(default)]
+        tool: Option<String>,
+        pattern: String,
+    },
+    /// Stop a tool call that writes outside the agent's working directory
+    Confine {
+        name: String,
+        /// Only apply to this tool; file-writing tools and Bash when omitted
+        #[serde(default)]
+        tool: Option<String>,
+    },
+    /// Mask matching text in prompts, responses, and tool inputs
+    Redact { name: String, pattern: String },
+    /// Send requests for matching models to another provider
+    Route {
+        name: String,
+        model: String,
+        provider: String,
+    },
+}
+
+#[derive(Debug)]
+pub enum Rule {
+    Block {
+        name: String,
+        tool: Option<String>,
+        pattern: Regex,
+    },
+    Confine {
+        name: String,
+        tool: Option<String>,
+    },
+    Redact {
+        name: String,
+        pattern: Regex,
+    },
+    Route {
+        name: String,
+        model: Regex,
+        provider: String,
+    },
+}
+
+impl Rule {
+    pub fn name(&self) -> &str {
+        match self {
+            Rule::Block { name, .. }
+            | Rule::Confine { name, .. }
+            | Rule::Redact { name, .. }
+            | Rule::Route { name, .. } => name,
+        }
+    }
+
+    /// Why a tool call breaks this rule, if it is a guardrail the call breaks
+    fn breach(&self, call: &ToolCall, working_dir: Option<&str>) -> Option<String> {
+        match self {
+            Rule::Block { tool, pattern, .. } => {
+                if tool.as_ref().is_some_and(|t| *t != call.name) {
+                    return None;
+                }
+                let input = call.input.to_string();
+                let m = pattern.find(&input)?;
+                Some(format!("{} call matching '{}'", call.name, m.as_str()))
+            }
+            Rule::Confine { tool, .. } => {
+                let applies = match tool {
+                    Some(tool) => *tool == call.name,
+                    None => WRITE_TOOLS.contains(&call.name.as_str()),
+                };
+                if !applies {
+                    return None;
+                }
+                let dir = working_dir?;
+                let paths = if call.name == "Bash" {
+                    let command = call.input.get("command").and_then(|v| v.as_str())?;
+                    scope::bash_paths(command)
+                } else {
+                    scope::tool_paths(call)
+                };
+                let outside = paths.into_iter().find(|p| !scope::within(p, dir))?;
+                Some(format!(
+                    "{} call writing to {}, outside {}",
+                    call.name, outside, dir
+                ))
+            }
+            Rule::Redact { .. } | Rule::Route { .. } => None,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct PolicyBundle {
+    pub name: String,
+    pub version: String,
+    pub description: Option<String>,
+    pub rules: Vec<Rule>,
+}
+
+fn compile(rule: &str, pattern: &str) -> Result<Regex, PolicyError> {
+    Regex::new(pattern).map_err(|source| PolicyError::Pattern {
+        rule: rule.to_string(),
+        source,
+    })
+}
+
+impl PolicyBundle {
+    pub fn load(path: &Path) -> Result<Self, PolicyError> {
+        let text = std::fs::read_to_string(path).map_err(|source| PolicyError::Read {
+            path: path.display().to_string(),
+            source,
+        })?;
+        Self::parse(&text)
+    }
+
+    pub fn parse(yaml: &str) -> Result<Self, PolicyError> {
+        let file: BundleFile = serde_yaml::from_str(yaml)?;
+        let mut rules: Vec<Rule> = Vec::with_capacity(file.rules.len());
+        for rule in file.rules {
+            let rule = match rule {
+                RuleFile::Block {
+                    name,
+                    tool,
+                    pattern,
+                } => Rule::Block {
+                    pattern: compile(&name, &pattern)?,
+                    name,
+                    tool,
+                },
+                RuleFile::Confine { name, tool } => Rule::Confine { name, tool },
+                RuleFile::Redact { name, pattern } => Rule::Redact {
+                    pattern: compile(&name, &pattern)?,
+                    name,
+                },
+                RuleFile::Route {
+                    name,
+                    model,
+                    provider,
+                } => Rule::Route {
+                    model: compile(&name, &model)?,
+                    name,
+                    provider,
+                },
+            };
+            if rules.iter().any(|r| r.name() == rule.name()) {
+                return Err(PolicyError::DuplicateRule(rule.name().to_string()));
+            }
+            rules.push(rule);
+        }
+        Ok(Self {
+            name: file.name,
+            version: file.version,
+            description: file.description,
+            rules,
+        })
+    }
+
+    /// What every rule would do to one recorded event, from an agent working
+    /// in `working_dir`
+    pub fn evaluate(&self, event: &ObservabilityEvent, working_dir: Option<&str>) -> Vec<Finding> {
+        let mut findings = Vec::new();
+        let texts = event_texts(event);
+        let finding = |rule: &Rule, action, detail| Finding {
+            timestamp: event.timestamp,
+            agent: event.agent.clone(),
+            rule: rule.name().to_string(),
+            action,
+            detail,
+        };
+
+        for rule in &self.rules {
+            match rule {
+                Rule::Block { .. } | Rule::Confine { .. } => {
+                    let Payload::AssistantResponse(ref response) = event.payload else {
+                        continue;
+                    };
+                    for call in &response.tool_calls {
+                        if let Some(detail) = rule.breach(call, working_dir) {
+                            findings.push(finding(rule, Action::Block, detail));
+                        }
+                    }
+                }
+                Rule::Redact { pattern, .. } => {
+                    for (source, text) in &texts {
+                        let matches: Vec<&str> =
+                            pattern.find_iter(text).map(|m| m.as_str()).collect();
+                        if matches.is_empty() {
+                            continue;
+                        }
+                        let samples: Vec<String> = matches.iter().map(|m| mask(m)).collect();
+                        findings.push(finding(
+                            rule,
+                            Action::Redact,
+                            format!(
+                                "{} match(es) in {}: {}",
+                                matches.len(),
+                                source,
+                                samples.join(", ")
+                            ),
+                        ));
+                    }
+                }
+                Rule::Route {
+                    model, provider, ..
+                } => {
+                    let Payload::UserMessage(ref message) = event.payload else {
+                        continue;
+                    };
+                    let Some(ref requested) = message.model else {
+                        continue;
+                    };
+                    let current = event.provider.as_deref().unwrap_or("anthropic");
+                    if model.is_match(requested) && current != provider {
+                        findings.push(finding(
+                            rule,
+                            Action::Route,
+                            format!("{} via {} → {}", requested, current, provider),
+                        ));
+                    }
+                }
+            }
+        }
+        findings
+    }
+}
+
+/// Text of an event that redaction rules look at, labelled by where it came from
+fn event_texts(event: &ObservabilityEvent) -> Vec<(String, String)> {
+    match &event.payload {
+        Payload::UserMessage(message) => {
+            let mut texts = vec![("prompt".to_string(), message.text.clone())];
+            for result in &message.tool_results {
+                texts.push((format!("{} result", result.tool_use_id), result.content.clone()));
+            }
+            texts
+        }
+        Payload::AssistantResponse(response) => {
+            let mut texts = Vec::new();
+            if let Some(ref text) = response.text {
+                texts.push(("response".to_string(), text.clone()));
+            }
+            if let Some(ref thinking) = response.thinking {
+                texts.push(("thinking".to_string(), thinking.clone()));
+            }
+            for call in &response.tool_calls {
+                texts.push((format!("{} input", call.name), call.input.to_string()));
+            }
+            texts
+        }
+        Payload::Alert(_)
+        | Payload::Error(_)
+        | Payload::Violation(_)
+        | Payload::SessionClosed(_)
+        | Payload::StreamDelta(_)
+        | Payload::StreamAborted(_)
+        | Payload::TurnSnapshot(_)
+        | Payload::Annotation(_) => Vec::new(),
+    }
+}
+
+/// Enough of a match to recognize it without printing the secret
+fn mask(matched: &str) -> String {
+    let prefix: String = matched.chars().take(4).collect();
+    format!("{}… ({} chars)", prefix, matched.chars().count())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    Block,
+    Redact,
+    Route,
+}
+
+impl Action {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Action::Block => "block",
+            Action::Redact => "redact",
+            Action::Route => "route",
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Finding {
+    pub timestamp: chrono::DateTime<chrono::Utc>,
+    pub agent: Option<String>,
+    pub rule: String,
+    pub action: Action,
+    pub detail: String,
+}
+
+/// A bundle's guardrails applied to live traffic (`--policy`)
+pub struct PolicyGuard {
+    bundle: PolicyBundle,
+    /// Raise an alert for each violation as well as recording it
+    pub alert: bool,
+    /// Cut off the response carrying a violating call
+    pub enforce: bool,
+}
+
+impl PolicyGuard {
+    pub fn new(bundle: PolicyBundle, alert: bool, enforce: bool) -> Self {
+        Self {
+            bundle,
+            alert,
+            enforce,
+        }
+    }
+
+    pub fn bundle(&self) -> &PolicyBundle {
+        &self.bundle
+    }
+
+    /// The guardrails a tool call breaks, from an agent working in
+    /// `working_dir`
+    pub fn check(&self, call: &ToolCall, working_dir: Option<&str>) -> Vec<Violation> {
+        self.bundle
+            .rules
+            .iter()
+            .filter_map(|rule| {
+                let message = rule.breach(call, working_dir)?;
+                Some(Violation {
+                    source: format!("policy:{}", rule.name()),
+                    tool: call.name.clone(),
+                    tool_use_id: Some(call.id.clone()),
+                    message,
+                    blocked: self.enforce,
+                    mismatches: Vec::new(),
+                })
+            })
+            .collect()
+    }
+}
+
+/// Tool calls put back together from a response's deltas, so each can be
+/// checked the moment its input is complete rather than when the stream ends
+#[derive(Default)]
+pub struct StreamedCalls {
+    /// Id, name, and the input so far, by tool call index
+    pending: BTreeMap<usize, (String, String, String)>,
+}
+
+impl StreamedCalls {
+    /// Take in a delta; returns the call it completed, if any
+    pub fn push(&mut self, delta: &Delta) -> Option<ToolCall> {
+        let index = match delta {
+            Delta::ToolUseStarted { index, id, name } => {
+                self.pending
+                    .insert(*index, (id.clone(), name.clone(), String::new()));
+                *index
+            }
+            Delta::ToolInput {
+                index,
+                partial_json,
+            } => {
+                self.pending.get_mut(index)?.2.push_str(partial_json);
+                *index
+            }
+            Delta::Text { .. } | Delta::Thinking { .. } => return None,
+        };
+        // An object only parses once its closing brace is in
+        let (_, _, input) = self.pending.get(&index)?;
+        if !input.trim_end().ends_with('}') {
+            return None;
+        }
+        let input = serde_json::from_str::<serde_json::Value>(input).ok()?;
+        let (id, name, _) = self.pending.remove(&index)?;
+        Some(ToolCall { id, name, input })
+    }
+}
+
+/// `sentinel policy check`: parse and compile a bundle
+pub fn check(bundle_path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    let bundle = PolicyBundle::load(bundle_path)?;
+    println!(
+        "✓ {} v{}: {} rule(s)",
+        bundle.name,
+        bundle.version,
+        bundle.rules.len()
+    );
+    if let Some(ref description) = bundle.description {
+        println!("  {}", description);
+    }
+    for rule in &bundle.rules {
+        let action = match rule {
+            Rule::Block { .. } => "block",
+            Rule::Confine { .. } => "confine",
+            Rule::Redact { .. } => "redact",
+            Rule::Route { .. } => "route",
+        };
+        println!("  {:<7} {}", action, rule.name());
+    }
+    Ok(())
+}
+
+/// `sentinel policy test`: replay a recorded session through a bundle
+pub async fn test(
+    data_dir: &Path,
+    bundle_path: &Path,
+    against: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let bundle = PolicyBundle::load(bundle_path)?;
+
+    let db_path = data_dir.join("sentinel.db");
+    if !db_path.exists() {
+        return Err("No recorded traffic. Run 'sentinel start' first.".into());
+    }
+    let storage = Storage::new(&db_path).await?;
+
+    let events = storage.get_session_or_agent_events(against).await?;
+    if events.is_empty() {
+        return Err(format!("No session or agent named '{}'", against).into());
+    }
+
+    println!(
+        "Policy {} v{} ({} rules) against {} ({} events)\n",
+        bundle.name,
+        bundle.version,
+        bundle.rules.len(),
+        against,
+        events.len()
+    );
+
+    // Confine rules need each agent's working directory
+    let agents = AgentStore::new(storage.pool());
+    let mut working_dirs: HashMap<String, Option<String>> = HashMap::new();
+    for name in events.iter().filter_map(|e| e.agent.as_ref()) {
+        if !working_dirs.contains_key(name) {
+            let dir = agents
+                .find_by_name(name)
+                .await
+                .ok()
+                .flatten()
+                .and_then(|a| a.working_directory);
+            working_dirs.insert(name.clone(), dir);
+        }
+    }
+    let findings: Vec<Finding> = events
+        .iter()
+        .flat_map(|e| {
+            let working_dir = e
+                .agent
+                .as_ref()
+                .and_then(|a| working_dirs.get(a))
+                .and_then(|d| d.as_deref());
+            bundle.evaluate(e, working_dir)
+        })
+        .collect();
+    if findings.is_empty() {
+        println!("✓ Nothing would have been blocked, redacted, or rerouted.");
+        return Ok(());
+    }
+
+    for finding in &findings {
+        let indicator = match finding.action {
+            Action::Block => "✗",
+            Action::Redact => "~",
+            Action::Route => "↪",
+        };
+        println!(
+            "{} {} {:<7} {:<24} {}{}",
+            indicator,
+            finding.timestamp.format("%H:%M:%S"),
+            finding.action.as_str(),
+            finding.rule,
+            finding
+                .agent
+                .as_ref()
+                .map(|a| format!("[{}] ", a))
+                .unwrap_or_default(),
+            finding.detail
+        );
+    }
+
+    let count = |action| findings.iter().filter(|f| f.action == action).count();
+    println!(
+        "\n{} would be blocked, {} redacted, {} rerouted",
+        count(Action::Block),
+        count(Action::Redact),
+        count(Action::Route)
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event::{AssistantResponse, UserMessage};
+    use crate::parsers::ToolCall;
+
+    const BUNDLE: &str = r#"
+name: team-defaults
+version: 3
+rules:
+  - name: no-recursive-delete
+    action: block
+    tool: Bash
+    pattern: 'rm\s+-rf'
+  - name: api-keys
+    action: redact
+    pattern: 'sk-[A-Za-z0-9]{8,}'
+  - name: haiku-via-openrouter
+    action: route
+    model: '^claude-3-haiku'
+    provider: openrouter
+"#;
+
+    fn event(payload: Payload) -> ObservabilityEvent {
+        ObservabilityEvent {
+            timestamp: chrono::Utc::now(),
+            agent: Some("swift-fox".to_string()),
+            ..ObservabilityEvent::for_tests(0, payload)
+        }
+    }
+
+    #[test]
+    fn test_replay_finds_each_action() {
+        let bundle = PolicyBundle::parse(BUNDLE).unwrap();
+        assert_eq!(bundle.rules.len(), 3);
+
+        let prompt = event(Payload::UserMessage(UserMessage {
+            model: Some("claude-3-haiku-20240307".to_string()),
+            text: "use key sk-abcdefghijkl".to_string(),
+            ..Default::default()
+        }));
+        let findings = bundle.evaluate(&prompt, None);
+        assert_eq!(findings.len(), 2);
+        assert!(findings
+            .iter()
+            .any(|f| f.action == Action::Redact && !f.detail.contains("abcdefghijkl")));
+        assert!(findings.iter().any(|f| f.action == Action::Route));
+
+        let response = event(Payload::AssistantResponse(AssistantResponse {
+            streaming: true,
+            stop_reason: Some("tool_use".to_string()),
+            tool_calls: vec![ToolCall {
+                id: "t1".to_string(),
+                name: "Bash".to_string(),
+                input: serde_json::json!({"command": "rm -rf /tmp/build"}),
+            }],
+            ..Default::default()
+        }));
+        let findings = bundle.evaluate(&response, Some("/repo"));
+        assert_eq!(findings.len(), 1);
+        assert_eq!(findings[0].action, Action::Block);
+        assert_eq!(findings[0].rule, "no-recursive-delete");
+    }
+
+    #[test]
+    fn test_guard_checks_calls_as_their_input_completes() {
+        let bundle = PolicyBundle::parse(
+            "name: x\nversion: 1\nrules:\n  - {name: no-rm, action: block, tool: Bash, pattern: 'rm\\s+-rf'}\n  - {name: stay-in-repo, action: confine}\n",
+        )
+        .unwrap();
+        let guard = PolicyGuard::new(bundle, false, true);
+        let mut calls = StreamedCalls::default();
+
+        let started = |index, name: &str| Delta::ToolUseStarted {
+            index,
+            id: format!("toolu_{}", index),
+            name: name.to_string(),
+        };
+        let input = |index, partial_json: &str| Delta::ToolInput {
+            index,
+            partial_json: partial_json.to_string(),
+        };
+        assert!(calls.push(&started(0, "Bash")).is_none());
+        assert!(calls.push(&input(0, r#"{"command": "rm -rf bu"#)).is_none());
+        // A brace inside a string doesn't end the input
+        assert!(calls.push(&input(0, r#"ild/{x}"#)).is_none());
+        let call = calls.push(&input(0, r#"", "timeout": 5}"#)).unwrap();
+        assert_eq!(call.input["command"], "rm -rf build/{x}");
+
+        let violations = guard.check(&call, Some("/repo"));
+        assert_eq!(violations.len(), 1);
+        assert_eq!(violations[0].source, "policy:no-rm");
+        assert!(violations[0].blocked);
+
+        calls.push(&started(1, "Write"));
+        let call = calls
+            .push(&input(1, r#"{"file_path": "../elsewhere/notes.md", "content": "hi"}"#))
+            .unwrap();
+        let violations = guard.check(&call, Some("/repo"));
+        assert_eq!(violations.len(), 1);
+        assert!(violations[0].message.contains("../elsewhere/notes.md"));
+        // Without a working directory there is nothing to confine to
+        assert!(guard.check(&call, None).is_empty());
+
+        let inside = ToolCall {
+            id: "t".to_string(),
+            name: "Bash".to_string(),
+            input: serde_json::json!({"command": "cargo test > target/out.txt"}),
+        };
+        assert!(guard.check(&inside, Some("/repo")).is_empty());
+    }
+
+    #[test]
+    fn test_rejects_bad_bundles() {
+        let bad_pattern =
+            "name: x\nversion: '1'\nrules:\n  - {name: r, action: redact, pattern: '('}";
+        assert!(matches!(
+            PolicyBundle::parse(bad_pattern),
+            Err(PolicyError::Pattern { .. })
+        ));
+        let duplicate = "name: x\nversion: '1'\nrules:\n  - {name: r, action: redact, pattern: a}\n  - {name: r, action: redact, pattern: b}";
+        assert!(matches!(
+            PolicyBundle::parse(duplicate),
+            Err(PolicyError::DuplicateRule(_))
+        ));
+        assert!(
+            PolicyBundle::parse("name: x\nversion: '1'\nrules:\n  - {name: r, action: allow}")
+                .is_err()
+        );
+    }
+}
@@ -0,0 +1,230 @@
+//! Who is watching each agent in the dashboard.
+//!
+//! A dashboard tab that follows one agent's SSE stream joins that agent's
+//! viewer list for as long as the stream is open. Each viewer may set a
+//! cursor, the event they have selected, so people pair-debugging an
+//! incident can see what the others are looking at. Changes are pushed to
+//! every stream following the agent as `presence` messages, and the current
+//! list is at `/api/agents/{name}/viewers`.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::Json;
+use chrono::{DateTime, Utc};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
+use uuid::Uuid;
+
+use crate::proxy::ProxyState;
+
+/// Longest viewer id or name accepted from a client
+const MAX_FIELD_LEN: usize = 64;
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct Viewer {
+    /// Chosen by the client so it can address its own cursor
+    pub id: String,
+    pub name: String,
+    /// Event the viewer has selected
+    pub cursor: Option<Uuid>,
+    pub joined_at: DateTime<Utc>,
+    /// Tells a reconnected tab's entry apart from the one it replaced
+    #[serde(skip)]
+    #[schemars(skip)]
+    stream: Uuid,
+}
+
+pub struct Presence {
+    viewers: Mutex<HashMap<String, Vec<Viewer>>>,
+    /// Names of agents whose viewers changed
+    changes: broadcast::Sender<String>,
+}
+
+impl Presence {
+    pub fn new() -> Self {
+        Self {
+            viewers: Mutex::new(HashMap::new()),
+            changes: broadcast::channel(64).0,
+        }
+    }
+
+    /// Add a viewer to an agent until the returned guard is dropped
+    pub fn join(
+        self: &Arc<Self>,
+        agent: &str,
+        id: Option<&str>,
+        name: Option<&str>,
+    ) -> ViewerGuard {
+        let id = id
+            .map(|id| clip(id).to_string())
+            .filter(|id| !id.is_empty())
+            .unwrap_or_else(|| Uuid::new_v4().to_string());
+        let name = name
+            .map(|name| clip(name).to_string())
+            .filter(|name| !name.is_empty())
+            .unwrap_or_else(|| "anonymous".to_string());
+        let stream = Uuid::new_v4();
+        {
+            let mut viewers = self.lock();
+            let list = viewers.entry(agent.to_string()).or_default();
+            // A reconnecting tab replaces its old entry
+            list.retain(|v| v.id != id);
+            list.push(Viewer {
+                id,
+                name,
+                cursor: None,
+                joined_at: Utc::now(),
+                stream,
+            });
+        }
+        self.notify(agent);
+        ViewerGuard {
+            presence: self.clone(),
+            agent: agent.to_string(),
+            stream,
+        }
+    }
+
+    /// Returns false if no such viewer is watching the agent
+    pub fn set_cursor(&self, agent: &str, id: &str, cursor: Option<Uuid>) -> bool {
+        let found = {
+            let mut viewers = self.lock();
+            match viewers
+                .get_mut(agent)
+                .and_then(|list| list.iter_mut().find(|v| v.id == id))
+            {
+                Some(viewer) => {
+                    viewer.cursor = cursor;
+                    true
+                }
+                None => false,
+            }
+        };
+        if found {
+            self.notify(agent);
+        }
+        found
+    }
+
+    pub fn viewers(&self, agent: &str) -> Vec<Viewer> {
+        self.lock().get(agent).cloned().unwrap_or_default()
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<String> {
+        self.changes.subscribe()
+    }
+
+    fn leave(&self, agent: &str, stream: Uuid) {
+        {
+            let mut viewers = self.lock();
+            if let Some(list) = viewers.get_mut(agent) {
+                list.retain(|v| v.stream != stream);
+                if list.is_empty() {
+                    viewers.remove(agent);
+                }
+            }
+        }
+        self.notify(agent);
+    }
+
+    fn notify(&self, agent: &str) {
+        // No open streams means nobody to tell
+        let _ = self.changes.send(agent.to_string());
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<String, Vec<Viewer>>> {
+        self.viewers.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl Default for Presence {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Keeps a viewer listed while its stream is open
+pub struct ViewerGuard {
+    presence: Arc<Presence>,
+    agent: String,
+    stream: Uuid,
+}
+
+impl Drop for ViewerGuard {
+    fn drop(&mut self) {
+        self.presence.leave(&self.agent, self.stream);
+    }
+}
+
+fn clip(value: &str) -> &str {
+    let value = value.trim();
+    match value.char_indices().nth(MAX_FIELD_LEN) {
+        Some((end, _)) => &value[..end],
+        None => value,
+    }
+}
+
+pub async fn viewers_handler(
+    State(state): State<Arc<ProxyState>>,
+    Path(name): Path<String>,
+) -> Json<Vec<Viewer>> {
+    Json(state.presence.viewers(&name))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CursorUpdate {
+    pub event_id: Option<Uuid>,
+}
+
+pub async fn cursor_handler(
+    State(state): State<Arc<ProxyState>>,
+    Path((name, viewer)): Path<(String, String)>,
+    Json(update): Json<CursorUpdate>,
+) -> StatusCode {
+    if state.presence.set_cursor(&name, &viewer, update.event_id) {
+        StatusCode::NO_CONTENT
+    } else {
+        StatusCode::NOT_FOUND
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_viewers_leave_when_guard_drops() {
+        let presence = Arc::new(Presence::new());
+        let mut changes = presence.subscribe();
+
+        let stale = presence.join("calm-otter", Some("tab-1"), Some("alice"));
+        let alice = presence.join("calm-otter", Some("tab-1"), Some("alice"));
+        // The replaced stream closing late must not remove the reconnected tab
+        drop(stale);
+        let bob = presence.join("calm-otter", None, None);
+        assert_eq!(presence.viewers("calm-otter").len(), 2);
+
+        let event = Uuid::new_v4();
+        assert!(presence.set_cursor("calm-otter", "tab-1", Some(event)));
+        assert!(!presence.set_cursor("calm-otter", "tab-9", Some(event)));
+        assert_eq!(presence.viewers("calm-otter")[0].cursor, Some(event));
+
+        drop(alice);
+        let remaining = presence.viewers("calm-otter");
+        assert_eq!(remaining.len(), 1);
+        assert_eq!(remaining[0].name, "anonymous");
+        drop(bob);
+        assert!(presence.viewers("calm-otter").is_empty());
+
+        // join, join, leave, join, cursor, leave, leave
+        let mut notified = 0;
+        while changes.try_recv().is_ok() {
+            notified += 1;
+        }
+        assert_eq!(notified, 7);
+    }
+}
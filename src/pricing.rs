@@ -0,0 +1,136 @@
+//! Per-model token prices for cost estimates.
+//!
+//! Upstreams that report a cost (e.g. OpenRouter) are trusted as-is; for the
+//! rest, cost is estimated from token usage and list prices.
+
+use crate::parsers::Usage;
+
+/// List prices in USD per million tokens
+#[derive(Debug, Clone, Copy)]
+pub struct ModelPrice {
+    pub input: f64,
+    pub output: f64,
+    pub cache_read: f64,
+    pub cache_write: f64,
+    /// OpenAI-style usage counts cached tokens inside `input_tokens`;
+    /// Anthropic reports them separately
+    pub input_includes_cache: bool,
+}
+
+const fn anthropic(input: f64, output: f64) -> ModelPrice {
+    ModelPrice {
+        input,
+        output,
+        cache_read: input * 0.1,
+        cache_write: input * 1.25,
+        input_includes_cache: false,
+    }
+}
+
+const fn openai(input: f64, output: f64, cache_read: f64) -> ModelPrice {
+    ModelPrice {
+        input,
+        output,
+        cache_read,
+        cache_write: input,
+        input_includes_cache: true,
+    }
+}
+
+/// Matched by prefix, so more specific names come first
+const PRICES: &[(&str, ModelPrice)] = &[
+    ("claude-opus-4-5", anthropic(5.0, 25.0)),
+    ("claude-opus-4", anthropic(15.0, 75.0)),
+    ("claude-3-opus", anthropic(15.0, 75.0)),
+    ("claude-sonnet-4", anthropic(3.0, 15.0)),
+    ("claude-3-7-sonnet", anthropic(3.0, 15.0)),
+    ("claude-3-5-sonnet", anthropic(3.0, 15.0)),
+    ("claude-haiku-4-5", anthropic(1.0, 5.0)),
+    ("claude-3-5-haiku", anthropic(0.8, 4.0)),
+    ("claude-3-haiku", anthropic(0.25, 1.25)),
+    ("gpt-4o-mini", openai(0.15, 0.6, 0.075)),
+    ("gpt-4o", openai(2.5, 10.0, 1.25)),
+    ("gpt-4.1-nano", openai(0.1, 0.4, 0.025)),
+    ("gpt-4.1-mini", openai(0.4, 1.6, 0.1)),
+    ("gpt-4.1", openai(2.0, 8.0, 0.5)),
+    ("o4-mini", openai(1.1, 4.4, 0.275)),
+    ("o3-mini", openai(1.1, 4.4, 0.55)),
+    ("o3", openai(2.0, 8.0, 0.5)),
+    ("deepseek-chat", openai(0.27, 1.1, 0.07)),
+    ("deepseek-reasoner", openai(0.55, 2.19, 0.14)),
+    // Gemini also counts cached tokens inside the prompt total
+    ("gemini-2.5-pro", openai(1.25, 10.0, 0.31)),
+    ("gemini-2.5-flash-lite", openai(0.1, 0.4, 0.025)),
+    ("gemini-2.5-flash", openai(0.3, 2.5, 0.075)),
+];
+
+pub fn price_for(model: &str) -> Option<ModelPrice> {
+    // Gateways prefix the vendor, e.g. "anthropic/claude-sonnet-4"
+    let name = model.rsplit('/').next().unwrap_or(model);
+    PRICES
+        .iter()
+        .find(|(prefix, _)| name.starts_with(prefix))
+        .map(|(_, price)| *price)
+}
+
+/// Cost of one response in USD: the upstream's own figure when it reports
+/// one, otherwise an estimate. `None` when the model has no known price.
+pub fn cost_usd(model: Option<&str>, usage: &Usage) -> Option<f64> {
+    if let Some(cost) = usage.cost_usd {
+        return Some(cost);
+    }
+    let price = price_for(model?)?;
+
+    let cache_read = usage.cache_read_tokens.unwrap_or(0);
+    let cache_write = usage.cache_creation_tokens.unwrap_or(0);
+    let mut input = usage.input_tokens.unwrap_or(0);
+    if price.input_includes_cache {
+        input = (input - cache_read - cache_write).max(0);
+    }
+    let per_token = |tokens: i64, per_million: f64| tokens as f64 * per_million / 1_000_000.0;
+
+    Some(
+        per_token(input, price.input)
+            + per_token(usage.output_tokens.unwrap_or(0), price.output)
+            + per_token(cache_read, price.cache_read)
+            + per_token(cache_write, price.cache_write),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn usage(input: i64, output: i64, cache_read: i64) -> Usage {
+        Usage {
+            input_tokens: Some(input),
+            output_tokens: Some(output),
+            cache_read_tokens: Some(cache_read),
+            cache_creation_tokens: None,
+            cost_usd: None,
+        }
+    }
+
+    #[test]
+    fn test_estimates_by_provider_convention() {
+        // Anthropic: cache reads billed on top of input
+        let cost = cost_usd(
+            Some("claude-sonnet-4-20250514"),
+            &usage(1_000_000, 100_000, 1_000_000),
+        )
+        .unwrap();
+        assert!((cost - (3.0 + 1.5 + 0.3)).abs() < 1e-9);
+
+        // OpenAI: cached tokens are part of prompt tokens
+        let cost = cost_usd(Some("openai/gpt-4o"), &usage(1_000_000, 0, 400_000)).unwrap();
+        assert!((cost - (0.6 * 2.5 + 0.4 * 1.25)).abs() < 1e-9);
+
+        assert!(cost_usd(Some("mystery-model"), &usage(10, 10, 0)).is_none());
+
+        let reported = Usage {
+            cost_usd: Some(0.42),
+            ..usage(10, 10, 0)
+        };
+        assert_eq!(cost_usd(Some("mystery-model"), &reported), Some(0.42));
+    }
+}
@@ -0,0 +1,259 @@
+//! Profiles: named, isolated recorders (work, personal, experiments).
+//!
+//! Each profile is a directory under `~/.sentinel/profiles/` holding its own
+//! `config.toml` and, unless that config moves it, its database. `--profile
+//! <name>` (or `SENTINEL_PROFILE`) on any command reads that profile's
+//! config in place of `~/.sentinel/config.toml` and keeps its data in the
+//! profile's directory, before the command line is parsed, so every command
+//! sees only that profile's data; `sentinel run` passes the profile on to
+//! what it launches. An explicit profile wins over a `SENTINEL_DATA_DIR`
+//! already in the environment. `sentinel profiles create <name>` makes one.
+
+use std::path::{Path, PathBuf};
+
+use crate::config::{self, Config, Settings};
+
+/// Environment variable naming the profile, and the flag's fallback
+pub const VAR: &str = "SENTINEL_PROFILE";
+/// Longest profile name accepted
+const MAX_NAME_LEN: usize = 32;
+
+const CONFIG_TEMPLATE: &str = "\
+# Settings for the {name} profile, used with `sentinel --profile {name}`.
+# Takes the same keys as ~/.sentinel/config.toml; relative paths are
+# resolved against this directory, where the database lives by default.
+
+# port = 9000
+";
+
+#[derive(Debug, thiserror::Error)]
+pub enum ProfileError {
+    #[error("no profile named '{0}'. Create it with 'sentinel profiles create {0}'")]
+    NotFound(String),
+    #[error("profile '{0}' already exists")]
+    Exists(String),
+    #[error("{0}")]
+    InvalidName(String),
+    #[error("can't find the home directory to keep profiles in")]
+    NoHome,
+    #[error("failed to create profile '{name}': {source}")]
+    Create {
+        name: String,
+        source: std::io::Error,
+    },
+}
+
+/// A profile name: lowercase letters, digits, `-` and `_`
+pub fn parse_name(name: &str) -> Result<String, String> {
+    let valid = !name.is_empty()
+        && name.len() <= MAX_NAME_LEN
+        && name
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '_');
+    if !valid {
+        return Err(format!(
+            "profile names are 1 to {} lowercase letters, digits, '-' or '_', not '{}'",
+            MAX_NAME_LEN, name
+        ));
+    }
+    Ok(name.to_string())
+}
+
+/// Where profiles are kept
+pub fn root() -> Result<PathBuf, ProfileError> {
+    dirs::home_dir()
+        .map(|home| home.join(".sentinel").join("profiles"))
+        .ok_or(ProfileError::NoHome)
+}
+
+/// The profile asked for on the command line, or else `from_env`. Read
+/// ahead of clap, which needs the config's defaults before it parses. A
+/// shell asking for completions passes the line being completed after a
+/// `--` (`sentinel -- sentinel --profile work logs`), so when `completing`
+/// that's the line read.
+fn requested(
+    args: impl IntoIterator<Item = String>,
+    completing: bool,
+    from_env: Option<String>,
+) -> Option<String> {
+    let mut args = args.into_iter();
+    if completing {
+        args.by_ref().take_while(|arg| arg != "--").for_each(drop);
+        // The program name the shell put first
+        args.next();
+    }
+    while let Some(arg) = args.next() {
+        // What follows is another program's command line
+        if arg == "--" {
+            break;
+        }
+        if arg == "--profile" {
+            return args.next();
+        }
+        if let Some(name) = arg.strip_prefix("--profile=") {
+            return Some(name.to_string());
+        }
+    }
+    from_env.filter(|name| !name.is_empty())
+}
+
+/// Read the config, from the requested profile when there is one, into the
+/// settings the command line is parsed with; before it's parsed
+pub fn load_settings(completing: bool) -> Result<(), Box<dyn std::error::Error>> {
+    let profile = match requested(std::env::args().skip(1), completing, std::env::var(VAR).ok()) {
+        Some(name) => {
+            let name = parse_name(&name).map_err(ProfileError::InvalidName)?;
+            let dir = root()?.join(&name);
+            if !dir.join("config.toml").exists() {
+                return Err(ProfileError::NotFound(name).into());
+            }
+            Some(dir)
+        }
+        None => None,
+    };
+    let path = match profile {
+        Some(ref dir) => Some(dir.join("config.toml")),
+        None => config::path(),
+    };
+    let mut settings = match path {
+        Some(path) => Settings::new(&Config::load(&path)?),
+        None => Settings::default(),
+    };
+    if let Some(dir) = profile {
+        // The profile's config, or else its directory, decides
+        let data_dir = settings
+            .in_file("SENTINEL_DATA_DIR")
+            .unwrap_or_else(|| dir.display().to_string());
+        settings.pin("SENTINEL_DATA_DIR", data_dir);
+    }
+    settings.install();
+    Ok(())
+}
+
+/// Make a profile's directory and a starter config
+pub fn create(root: &Path, name: &str) -> Result<PathBuf, ProfileError> {
+    let dir = root.join(name);
+    if dir.exists() {
+        return Err(ProfileError::Exists(name.to_string()));
+    }
+    let config = CONFIG_TEMPLATE.replace("{name}", name);
+    std::fs::create_dir_all(&dir)
+        .and_then(|()| std::fs::write(dir.join("config.toml"), config))
+        .map_err(|source| ProfileError::Create {
+            name: name.to_string(),
+            source,
+        })?;
+    Ok(dir)
+}
+
+#[derive(Debug)]
+pub struct ProfileSummary {
+    pub name: String,
+    pub dir: PathBuf,
+    /// Size of its database in the profile directory, if it has recorded anything
+    pub db_bytes: Option<u64>,
+}
+
+/// Every profile, by name
+pub fn list(root: &Path) -> Vec<ProfileSummary> {
+    let Ok(entries) = std::fs::read_dir(root) else {
+        return Vec::new();
+    };
+    let mut profiles: Vec<ProfileSummary> = entries
+        .flatten()
+        .filter(|entry| entry.path().join("config.toml").exists())
+        .filter_map(|entry| {
+            let name = entry.file_name().into_string().ok()?;
+            let dir = entry.path();
+            let db_bytes = std::fs::metadata(dir.join("sentinel.db"))
+                .map(|m| m.len())
+                .ok();
+            Some(ProfileSummary { name, dir, db_bytes })
+        })
+        .collect();
+    profiles.sort_by(|a, b| a.name.cmp(&b.name));
+    profiles
+}
+
+pub fn run_list(active: Option<&str>) -> Result<(), Box<dyn std::error::Error>> {
+    let root = root()?;
+    let profiles = list(&root);
+    if profiles.is_empty() {
+        println!("No profiles yet. Create one with 'sentinel profiles create <name>'.");
+        return Ok(());
+    }
+    println!("{:<2}{:<20} {:>10}  directory", "", "profile", "database");
+    for profile in &profiles {
+        let marker = if active == Some(profile.name.as_str()) {
+            "*"
+        } else {
+            ""
+        };
+        let size = match profile.db_bytes {
+            Some(bytes) => format!("{:.1} MB", bytes as f64 / 1_048_576.0),
+            None => "-".to_string(),
+        };
+        println!(
+            "{:<2}{:<20} {:>10}  {}",
+            marker,
+            profile.name,
+            size,
+            profile.dir.display()
+        );
+    }
+    Ok(())
+}
+
+pub fn run_create(name: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let dir = create(&root()?, name)?;
+    println!("Created profile {} in {}", name, dir.display());
+    println!("  config: {}", dir.join("config.toml").display());
+    println!("Use it with: sentinel --profile {} start", name);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(line: &str) -> Vec<String> {
+        line.split_whitespace().map(str::to_string).collect()
+    }
+
+    #[test]
+    fn test_profiles_are_created_listed_and_picked_from_arguments() {
+        let ambient = || Some("personal".to_string());
+        assert_eq!(
+            requested(args("--profile work logs"), false, ambient()).as_deref(),
+            Some("work")
+        );
+        assert_eq!(requested(args("logs --profile=work"), false, None).as_deref(), Some("work"));
+        assert_eq!(requested(args("logs"), false, ambient()).as_deref(), Some("personal"));
+        assert_eq!(requested(args("logs"), false, Some(String::new())), None);
+        // A launched program's own --profile isn't ours
+        assert_eq!(requested(args("run -- aws --profile prod"), false, None), None);
+        // Completing, the line being completed follows the --
+        assert_eq!(
+            requested(args("-- sentinel --profile work files "), true, None).as_deref(),
+            Some("work")
+        );
+        assert_eq!(
+            requested(args("-- sentinel run -- aws --profile prod"), true, None),
+            None
+        );
+        assert!(parse_name("experiments_2").is_ok());
+        assert!(parse_name("../work").is_err());
+        assert!(parse_name("").is_err());
+
+        let root = std::env::temp_dir().join(format!("sentinel-test-{}", uuid::Uuid::new_v4()));
+        create(&root, "work").unwrap();
+        create(&root, "personal").unwrap();
+        assert!(matches!(create(&root, "work"), Err(ProfileError::Exists(_))));
+        std::fs::create_dir_all(root.join("stray")).unwrap();
+        let names: Vec<String> = list(&root).into_iter().map(|p| p.name).collect();
+        assert_eq!(names, ["personal", "work"]);
+        // The starter config parses
+        Config::load(&root.join("work").join("config.toml")).unwrap();
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
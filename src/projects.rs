@@ -0,0 +1,270 @@
+//! `sentinel projects`: sessions, tokens, and cost per project.
+//!
+//! An agent's project is where it ran: the git repository its working
+//! directory is in, or the directory itself outside of one. Agents that
+//! never sent a working directory are grouped as unknown. Unlike the
+//! x-sentinel-project header, this needs nothing from the client.
+
+use std::collections::HashMap;
+use std::path::Path;
+
+use chrono::{DateTime, Duration, Utc};
+use sqlx::SqlitePool;
+
+use crate::agent::{Agent, AgentStore};
+use crate::rollup::{RollupStore, StatsBy, UsageTotal};
+use crate::storage::Storage;
+
+/// Widest project path shown before it's cut from the left
+const PATH_WIDTH: usize = 40;
+
+/// Usage summed over the agents that ran in one project
+#[derive(Debug)]
+pub struct ProjectSummary {
+    /// Repository root or working directory; `None` for agents without one
+    pub root: Option<String>,
+    pub agents: usize,
+    pub sessions: i64,
+    pub requests: i64,
+    /// Input, output, and cache tokens
+    pub tokens: i64,
+    pub cost_usd: f64,
+    pub last_active: DateTime<Utc>,
+}
+
+/// The root of the git repository `working_directory` is in, or the
+/// directory itself when it isn't in one (or isn't on this machine)
+pub fn project_root(working_directory: &str) -> String {
+    let trimmed = working_directory.trim_end_matches('/');
+    let dir = if trimmed.is_empty() { "/" } else { trimmed };
+    Path::new(dir)
+        .ancestors()
+        // A worktree or submodule has a .git file rather than a directory
+        .find(|ancestor| ancestor.join(".git").exists())
+        .map(|root| root.display().to_string())
+        .unwrap_or_else(|| dir.to_string())
+}
+
+/// Sessions per agent since `since`, counting an agent without session ids
+/// as one
+async fn session_counts(
+    pool: &SqlitePool,
+    since: DateTime<Utc>,
+) -> Result<HashMap<String, i64>, sqlx::Error> {
+    let rows: Vec<(String, i64)> = sqlx::query_as(
+        r#"
+        SELECT agent, COUNT(DISTINCT COALESCE(session_id, '')) AS sessions
+        FROM observability_events
+        WHERE agent IS NOT NULL
+          AND timestamp >= ?
+        GROUP BY agent
+        "#,
+    )
+    .bind(since.to_rfc3339())
+    .fetch_all(pool)
+    .await?;
+    Ok(rows.into_iter().collect())
+}
+
+/// Group agents into projects, busiest (by cost, then requests) first
+fn summarize(
+    agents: &[Agent],
+    usage: &HashMap<&str, &UsageTotal>,
+    sessions: &HashMap<String, i64>,
+    root: impl Fn(&str) -> String,
+) -> Vec<ProjectSummary> {
+    let mut projects: HashMap<Option<String>, ProjectSummary> = HashMap::new();
+    for agent in agents {
+        let key = agent.working_directory.as_deref().map(&root);
+        let project = projects
+            .entry(key.clone())
+            .or_insert_with(|| ProjectSummary {
+                root: key,
+                agents: 0,
+                sessions: 0,
+                requests: 0,
+                tokens: 0,
+                cost_usd: 0.0,
+                last_active: agent.last_seen_at,
+            });
+        project.agents += 1;
+        project.sessions += sessions.get(&agent.name).copied().unwrap_or(0);
+        project.last_active = project.last_active.max(agent.last_seen_at);
+        if let Some(total) = usage.get(agent.name.as_str()) {
+            project.requests += total.requests;
+            project.tokens += total.input_tokens
+                + total.output_tokens
+                + total.cache_read_tokens
+                + total.cache_creation_tokens;
+            project.cost_usd += total.cost_usd;
+        }
+    }
+    let mut projects: Vec<ProjectSummary> = projects.into_values().collect();
+    projects.sort_by(|a, b| {
+        b.cost_usd
+            .total_cmp(&a.cost_usd)
+            .then(b.requests.cmp(&a.requests))
+            .then(b.last_active.cmp(&a.last_active))
+    });
+    projects
+}
+
+/// A path cut to `width` characters from the left, home shown as ~
+fn display_path(path: &str, width: usize) -> String {
+    let home = std::env::var("HOME").ok().filter(|home| !home.is_empty());
+    let path = match home
+        .as_deref()
+        .and_then(|home| Path::new(path).strip_prefix(home).ok())
+    {
+        Some(rest) if rest.as_os_str().is_empty() => "~".to_string(),
+        Some(rest) => format!("~/{}", rest.display()),
+        None => path.to_string(),
+    };
+    let len = path.chars().count();
+    if len <= width {
+        return path;
+    }
+    let tail: String = path.chars().skip(len - width.saturating_sub(3)).collect();
+    format!("...{}", tail)
+}
+
+pub async fn run(data_dir: &Path, since: Duration) -> Result<(), Box<dyn std::error::Error>> {
+    let db_path = data_dir.join("sentinel.db");
+    if !db_path.exists() {
+        println!("No events found. Run 'sentinel start' first to capture some traffic.");
+        return Ok(());
+    }
+    let storage = Storage::new(&db_path).await?;
+    let agent_store = AgentStore::new(storage.pool());
+    agent_store.init_schema().await?;
+    let from = Utc::now() - since;
+    let agents: Vec<Agent> = agent_store
+        .list_all()
+        .await?
+        .into_iter()
+        .filter(|agent| agent.last_seen_at >= from)
+        .collect();
+    if agents.is_empty() {
+        println!("No agents active since {}.", from.format("%Y-%m-%d %H:%M"));
+        return Ok(());
+    }
+
+    let totals = RollupStore::new(storage.pool())
+        .totals(from, StatsBy::Agent)
+        .await?;
+    let usage = totals
+        .iter()
+        .map(|total| (total.group_key.as_str(), total))
+        .collect();
+    let sessions = session_counts(&storage.pool(), from).await?;
+    let projects = summarize(&agents, &usage, &sessions, project_root);
+
+    println!("Projects active since {}\n", from.format("%Y-%m-%d %H:%M"));
+    println!(
+        "{:<40} {:>6} {:>8} {:>8} {:>12} {:>10}  last active",
+        "project", "agents", "sessions", "requests", "tokens", "cost"
+    );
+    for project in &projects {
+        let name = match project.root {
+            Some(ref root) => display_path(root, PATH_WIDTH),
+            None => "(unknown)".to_string(),
+        };
+        println!(
+            "{:<40} {:>6} {:>8} {:>8} {:>12} {:>10}  {}",
+            name,
+            project.agents,
+            project.sessions,
+            project.requests,
+            project.tokens,
+            format!("${:.4}", project.cost_usd),
+            project.last_active.format("%Y-%m-%d %H:%M")
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agent::AgentStatus;
+    use chrono::TimeZone;
+    use uuid::Uuid;
+
+    fn agent(name: &str, working_directory: Option<&str>, hour: u32) -> Agent {
+        let at = Utc.with_ymd_and_hms(2026, 1, 5, hour, 0, 0).unwrap();
+        Agent {
+            id: Uuid::new_v4(),
+            name: name.to_string(),
+            session_id: format!("{}-session", name),
+            working_directory: working_directory.map(str::to_string),
+            created_at: at,
+            last_seen_at: at,
+            status: AgentStatus::Inactive,
+            topic: None,
+            tags: Vec::new(),
+            project: None,
+            git: None,
+            parent_id: None,
+        }
+    }
+
+    fn usage(agent: &str, requests: i64, cost_usd: f64) -> UsageTotal {
+        UsageTotal {
+            group_key: agent.to_string(),
+            requests,
+            errors: 0,
+            input_tokens: requests * 100,
+            output_tokens: requests * 10,
+            cache_read_tokens: 0,
+            cache_creation_tokens: 0,
+            cost_usd,
+            unpriced: 0,
+        }
+    }
+
+    #[test]
+    fn test_agents_group_by_repository() {
+        let dir = std::env::temp_dir().join(format!("sentinel-test-{}", Uuid::new_v4()));
+        let repo = dir.join("repo");
+        std::fs::create_dir_all(repo.join(".git")).unwrap();
+        std::fs::create_dir_all(repo.join("crates/core")).unwrap();
+        let repo_root = repo.display().to_string();
+        assert_eq!(
+            project_root(&format!("{}/crates/core", repo_root)),
+            repo_root
+        );
+        assert_eq!(project_root(&format!("{}/", repo_root)), repo_root);
+        let elsewhere = dir.join("scratch").display().to_string();
+        assert_eq!(project_root(&elsewhere), elsewhere);
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let agents = vec![
+            agent("calm-otter", Some("/work/api"), 9),
+            agent("swift-fox", Some("/work/api/src"), 11),
+            agent("bold-hare", Some("/work/web"), 10),
+            agent("shy-owl", None, 8),
+        ];
+        let totals = [
+            usage("calm-otter", 3, 0.5),
+            usage("swift-fox", 2, 0.25),
+            usage("bold-hare", 1, 1.0),
+        ];
+        let usage = totals.iter().map(|t| (t.group_key.as_str(), t)).collect();
+        let sessions = [("calm-otter", 2), ("swift-fox", 1), ("bold-hare", 1)]
+            .into_iter()
+            .map(|(name, n)| (name.to_string(), n))
+            .collect();
+        let projects = summarize(&agents, &usage, &sessions, |dir| {
+            dir.split('/').take(3).collect::<Vec<_>>().join("/")
+        });
+
+        let roots: Vec<Option<&str>> = projects.iter().map(|p| p.root.as_deref()).collect();
+        assert_eq!(roots, [Some("/work/web"), Some("/work/api"), None]);
+        let api = &projects[1];
+        assert_eq!((api.agents, api.sessions, api.requests), (2, 3, 5));
+        assert_eq!(api.tokens, 550);
+        assert_eq!(api.cost_usd, 0.75);
+        assert_eq!(api.last_active, agents[1].last_seen_at);
+        assert_eq!(projects[2].requests, 0);
+    }
+}
@@ -1,30 +1,362 @@
 use axum::{
-    body::Body,
+    body::{Body, BodyDataStream},
     extract::State,
     http::{Request, StatusCode},
     response::Response,
 };
-use bytes::Bytes;
+use bytes::{Bytes, BytesMut};
 use futures::StreamExt;
 use reqwest::Client;
 use std::sync::Arc;
 use tracing::{info, warn};
 use uuid::Uuid;
 
-use crate::agent::{Agent, AgentStore};
-use crate::event::{ObservabilityEvent, Payload, UserMessage};
-use crate::parsers::{AnthropicRequest, ParsedResponse, ResponseParser};
-use crate::storage::Storage;
+use crate::agent::{Agent, AgentStore, AGENT_NAME_HEADER};
+use crate::annotations::Phases;
+use crate::attribution::{is_attribution_header, Attribution};
+use crate::branches::{self, BranchStore};
+use crate::budgets::{Budgets, Spend, BUDGET_ALERT_KIND};
+use crate::runs::RunStore;
+use crate::baseline::BaselineMonitor;
+use crate::bodies;
+use crate::approvals::{ApprovalGate, Decision, PendingApproval};
+use crate::compat::{CompatTracker, COMPAT_ALERT_KIND};
+use crate::encrypt::FieldCipher;
+use crate::diagnostics::{classify, error_chain, FailureCause};
+use crate::git;
+use crate::keys::{self, UpstreamKeys};
+use crate::launcher;
+use crate::mirror::{Mirror, MirroredRequest};
+use crate::event::{
+    Alert, AssistantResponse, ErrorEvent, ErrorKind, ObservabilityEvent, Payload, StreamAborted, StreamDelta,
+    UserMessage,
+    Violation,
+};
+use crate::parsers::{AnthropicRequest, Delta, ParsedResponse, ResponseParser, StreamParser, ToolCall};
+use crate::policy::{PolicyGuard, StreamedCalls, POLICY_ALERT_KIND};
+use crate::pricing;
+use crate::resume::{self, Resume, ResumePlan, StreamJournal, RESUME_HEADER};
+use crate::overhead::{Degradation, LatencyBudget, OVERHEAD_ALERT_KIND};
+use crate::presence::Presence;
+use crate::queue::RecordQueue;
+use crate::redact::Redactor;
+use crate::redo::REDO_HEADER;
+use crate::retry::{self, IDEMPOTENCY_HEADER};
+use crate::routing::Routes;
+use crate::slo::{is_upstream_error, SloTracker};
+use crate::spool;
+use crate::static_cache::{Cached, Served, StaticCache};
+use crate::storage::{CapturePolicy, RawCapture, Storage};
+use crate::structured::OutputContract;
+use crate::subagents;
+use crate::waiting::WaitingWatch;
+use crate::watchdog::{self, TurnRequest, TurnSla, TurnWatch, TURN_SLA_ALERT_KIND};
+use crate::withhold::Withholder;
+use crate::worktree::Worktrees;
+
+/// Session of the agent behind a request, sent with --correlation-headers
+pub const SESSION_HEADER: &str = "x-sentinel-session";
+/// Id the response is recorded under, sent with --correlation-headers
+pub const EVENT_ID_HEADER: &str = "x-sentinel-event-id";
+/// Request bodies larger than this are streamed upstream by default
+pub const DEFAULT_MAX_REQUEST_BYTES: usize = 8 * 1024 * 1024;
+/// Most a request body read whole for guardian mode, baselines, or
+/// withholding can hold; larger ones are refused with 413
+pub const MAX_SCANNED_REQUEST_BYTES: usize = 64 * 1024 * 1024;
 
-const ANTHROPIC_API_URL: &str = "https://api.anthropic.com";
+/// Most of an upstream error body recorded on its error event
+const MAX_ERROR_BODY: usize = 16 * 1024;
 
-#[derive(Clone)]
 pub struct ProxyState {
     pub storage: Storage,
     pub agent_store: AgentStore,
+    /// Runs named by x-sentinel-run-id
+    pub run_store: RunStore,
+    /// Each request's place in its conversation, for retries and forks
+    pub branches: BranchStore,
     pub http_client: Client,
-    pub parser: Arc<dyn ResponseParser>,
+    pub routes: Arc<Routes>,
     pub event_broadcaster: tokio::sync::broadcast::Sender<ObservabilityEvent>,
+    /// `stream_delta` events, kept apart so a fast stream can't crowd
+    /// recorded events out of a slow subscriber's buffer
+    pub deltas: tokio::sync::broadcast::Sender<ObservabilityEvent>,
+    pub slo: Arc<SloTracker>,
+    pub capture: CapturePolicy,
+    pub approvals: Arc<ApprovalGate>,
+    pub baselines: BaselineMonitor,
+    /// Guardrails checked on each tool call as it streams, with --policy
+    pub policy: Option<Arc<PolicyGuard>>,
+    pub journal: StreamJournal,
+    pub compat: Arc<CompatTracker>,
+    /// Send SESSION_HEADER and EVENT_ID_HEADER upstream and back to the client
+    pub correlation_headers: bool,
+    /// Origins besides loopback ones whose pages may change state (see `origin`)
+    pub allowed_origins: Vec<String>,
+    /// Times to retry a non-streaming request the upstream turned away
+    pub retries: u32,
+    pub presence: Arc<Presence>,
+    /// Phase each session was last put in by an orchestrator's heartbeats
+    pub phases: Arc<Phases>,
+    /// Applied to every event and raw body before it is stored
+    pub redactor: Arc<Redactor>,
+    /// Upstream Anthropic keys picked per client or agent
+    pub keys: Arc<UpstreamKeys>,
+    /// Token and cost budgets per agent, session, or day
+    pub budgets: Arc<Budgets>,
+    /// Where each exchange is also written as a JSON file, with --mirror-dir
+    pub mirror: Option<Arc<Mirror>>,
+    /// Request bodies larger than this are streamed upstream, not held
+    pub max_request_bytes: usize,
+    /// Secret files whose contents are replaced before forwarding
+    pub withholder: Arc<Withholder>,
+    /// Seals chosen content fields after redaction, with --encrypt-fields
+    pub cipher: Arc<FieldCipher>,
+    /// Turns still running past this are snapshotted, with --turn-sla
+    pub turn_sla: Option<TurnSla>,
+    /// Answer requests a usage cap stops with an assistant message saying
+    /// so, instead of an error, with --cap-message
+    pub cap_message: bool,
+    /// Notifies when an agent is left waiting on its user, with --notify-waiting
+    pub waiting: Option<Arc<WaitingWatch>>,
+    /// Record every exchange through `queue`, after responding
+    pub hot_path: bool,
+    /// Where recording goes instead of holding up responses, with --hot-path
+    /// or once over --latency-budget-ms
+    pub queue: Option<Arc<RecordQueue>>,
+    pub latency: Option<Arc<LatencyBudget>>,
+    /// Model lists kept for --static-cache-ttl, and served stale in outages
+    pub static_cache: Arc<StaticCache>,
+    /// Working trees snapshotted as sessions start, with --worktree-snapshots
+    pub worktrees: Option<Arc<Worktrees>>,
+    /// When agents' git context was last read, so it isn't on every request
+    pub git_lookups: Arc<git::Lookups>,
+}
+
+impl ProxyState {
+    /// Store an event and, once stored, broadcast it to live subscribers
+    pub(crate) async fn publish(&self, mut event: ObservabilityEvent) {
+        self.redactor.event(&mut event);
+        self.cipher.event(&mut event);
+        // Subscribers resume from the seq of the last event they got, so
+        // only stored events go out
+        match self.storage.insert_observability_event(&event).await {
+            Ok(seq) => {
+                event.seq = Some(seq);
+                let _ = self.event_broadcaster.send(event);
+            }
+            Err(e) => tracing::error!("Failed to store event: {}", e),
+        }
+    }
+
+    /// Store an event with the raw body it came from, and broadcast it
+    async fn publish_with_raw(&self, mut event: ObservabilityEvent, mut raw: RawCapture) {
+        self.redactor.event(&mut event);
+        self.cipher.event(&mut event);
+        self.cipher.raw(&mut raw);
+        match self
+            .storage
+            .insert_observability_event_with_raw(&event, Some(&raw))
+            .await
+        {
+            Ok(seq) => {
+                event.seq = Some(seq);
+                let _ = self.event_broadcaster.send(event);
+            }
+            Err(e) => tracing::error!("Failed to store event: {}", e),
+        }
+    }
+
+    /// How far recording a new exchange is cut back for --latency-budget-ms
+    fn degradation(&self) -> Degradation {
+        self.latency
+            .as_ref()
+            .map_or(Degradation::None, |latency| latency.level())
+    }
+
+    /// The queue an exchange recorded at `level` goes through, if it isn't
+    /// recorded before responding. A response that checks could withhold
+    /// (`blocking`) is always recorded first; only pure recording waits.
+    fn deferral(&self, level: Degradation, blocking: bool) -> Option<&Arc<RecordQueue>> {
+        self.queue
+            .as_ref()
+            .filter(|_| !blocking && (self.hot_path || level == Degradation::Defer))
+    }
+
+    /// Whether a response to `agent` could be withheld for a tool call in it
+    fn blocks(&self, agent: Option<&str>) -> bool {
+        self.baselines.blocks(agent) || self.policy.as_ref().is_some_and(|p| p.enforce)
+    }
+
+    /// Record an event now, or queue it behind the earlier ones under the
+    /// same key; `spent` is what it already took, counted with the rest
+    /// against --latency-budget-ms
+    async fn record<F>(
+        self: &Arc<Self>,
+        key: Option<&str>,
+        level: Degradation,
+        spent: std::time::Duration,
+        job: F,
+    ) where
+        F: std::future::Future<Output = ()> + Send + 'static,
+    {
+        let state = self.clone();
+        let timed = async move {
+            let start = std::time::Instant::now();
+            job.await;
+            state.record_overhead(spent + start.elapsed(), level).await;
+        };
+        match self.deferral(level, false) {
+            Some(queue) => queue.push(key, Box::pin(timed)).await,
+            None => timed.await,
+        }
+    }
+
+    /// Count what recording an event took toward --latency-budget-ms,
+    /// raising an alert if that changes how much is recorded
+    async fn record_overhead(&self, took: std::time::Duration, level: Degradation) {
+        let Some(ref latency) = self.latency else { return };
+        let Some(message) = latency.observe(took, level) else { return };
+        warn!("⚠ {}", message);
+        self.publish(ObservabilityEvent {
+            seq: None,
+            id: Uuid::new_v4(),
+            timestamp: chrono::Utc::now(),
+            session_id: None,
+            agent: None,
+            topic: None,
+            provider: None,
+            payload: Payload::Alert(Alert {
+                kind: OVERHEAD_ALERT_KIND.to_string(),
+                message,
+            }),
+        })
+        .await;
+    }
+
+    /// Follow a turn from when it is sent; with --turn-sla, one still running
+    /// past the limit is recorded as a snapshot, and alerted on if asked
+    fn watch_turn(
+        self: &Arc<Self>,
+        request: TurnRequest,
+        session_id: Option<String>,
+        agent: Option<&Agent>,
+        provider: &str,
+    ) -> TurnWatch {
+        let Some(sla) = self.turn_sla else {
+            return TurnWatch::unwatched();
+        };
+        let state = self.clone();
+        let agent_name = agent.map(|a| a.name.clone());
+        let topic = agent.and_then(|a| a.topic.clone());
+        let provider = provider.to_string();
+        TurnWatch::new(sla.limit, move |progress| async move {
+            let snapshot = progress.snapshot(&request, sla.limit);
+            let description = watchdog::describe(&snapshot);
+            warn!("⏱ {}", description);
+            let event = |payload| ObservabilityEvent {
+                seq: None,
+                id: Uuid::new_v4(),
+                timestamp: chrono::Utc::now(),
+                session_id: session_id.clone(),
+                agent: agent_name.clone(),
+                topic: topic.clone(),
+                provider: Some(provider.clone()),
+                payload,
+            };
+            state.publish(event(Payload::TurnSnapshot(snapshot))).await;
+            if sla.alert {
+                state
+                    .publish(event(Payload::Alert(Alert {
+                        kind: TURN_SLA_ALERT_KIND.to_string(),
+                        message: description,
+                    })))
+                    .await;
+            }
+        })
+    }
+
+    /// Count a request outcome toward the provider's SLO, raising an alert
+    /// event if its error budget is burning fast.
+    async fn record_outcome(&self, provider: &str, is_error: bool) {
+        let Some(message) = self.slo.record(provider, is_error) else { return };
+        warn!("{}", message);
+        self.publish(ObservabilityEvent {
+            seq: None,
+            id: Uuid::new_v4(),
+            timestamp: chrono::Utc::now(),
+            session_id: None,
+            agent: None,
+            topic: None,
+            provider: Some(provider.to_string()),
+            payload: Payload::Alert(Alert {
+                kind: "slo_burn".to_string(),
+                message,
+            }),
+        })
+        .await;
+    }
+}
+
+#[cfg(test)]
+impl ProxyState {
+    /// A state over a fresh database in the temp dir, with every option off
+    pub(crate) async fn for_tests() -> Arc<Self> {
+        use crate::approvals::GuardRules;
+
+        let path = std::env::temp_dir().join(format!("sentinel-test-{}.db", Uuid::new_v4()));
+        let storage = Storage::new(&path).await.unwrap();
+        let agent_store = AgentStore::new(storage.pool());
+        agent_store.init_schema().await.unwrap();
+        let run_store = RunStore::new(storage.pool());
+        run_store.init_schema().await.unwrap();
+        let branches = BranchStore::new(storage.pool());
+        branches.init_schema().await.unwrap();
+        let journal = StreamJournal::new(storage.pool());
+        journal.init_schema().await.unwrap();
+        let (event_broadcaster, _) = tokio::sync::broadcast::channel(100);
+        let (deltas, _) = tokio::sync::broadcast::channel(256);
+        Arc::new(Self {
+            storage,
+            agent_store,
+            run_store,
+            branches,
+            http_client: Client::new(),
+            routes: Arc::new(Routes::new()),
+            event_broadcaster,
+            deltas,
+            slo: Arc::new(SloTracker::new()),
+            capture: CapturePolicy { max_raw_bytes: None },
+            approvals: Arc::new(ApprovalGate::new(
+                GuardRules::default(),
+                std::time::Duration::from_secs(1),
+            )),
+            baselines: BaselineMonitor::new(Vec::new()),
+            policy: None,
+            journal,
+            compat: Arc::new(CompatTracker::new()),
+            correlation_headers: false,
+            allowed_origins: Vec::new(),
+            retries: 0,
+            presence: Arc::new(Presence::new()),
+            phases: Arc::new(Phases::default()),
+            redactor: Arc::new(Redactor::new(false, &[], None).unwrap()),
+            keys: Arc::new(UpstreamKeys::default()),
+            budgets: Arc::new(Budgets::default()),
+            mirror: None,
+            max_request_bytes: DEFAULT_MAX_REQUEST_BYTES,
+            withholder: Arc::new(Withholder::new(false, &[]).unwrap()),
+            cipher: Arc::new(FieldCipher::default()),
+            turn_sla: None,
+            cap_message: false,
+            waiting: None,
+            hot_path: false,
+            queue: None,
+            latency: None,
+            static_cache: Arc::new(StaticCache::new(std::time::Duration::ZERO)),
+            worktrees: None,
+            git_lookups: Arc::new(git::Lookups::default()),
+        })
+    }
 }
 
 pub async fn proxy_handler(
@@ -34,65 +366,254 @@ pub async fn proxy_handler(
     let method = req.method().clone();
     let uri = req.uri().clone();
     let headers = req.headers().clone();
+    // `sentinel run` names its agent in the base URL it hands the command
+    let (label, path) = launcher::split_agent_path(uri.path());
 
-    // Read request body
-    let body_bytes = match axum::body::to_bytes(req.into_body(), usize::MAX).await {
-        Ok(bytes) => bytes,
+    // Read the request body, up to the cap unless a policy must see all of it
+    let needs_whole_body = state.approvals.is_enabled()
+        || !state.baselines.is_empty()
+        || state.withholder.is_active();
+    let read = read_body(req.into_body(), state.max_request_bytes).await;
+    let (mut body_bytes, spooled) = match read {
+        Ok(RequestBody::Partial(prefix, rest)) if needs_whole_body => {
+            let cap = MAX_SCANNED_REQUEST_BYTES.max(state.max_request_bytes);
+            match read_rest(prefix, rest, cap).await {
+                Ok(Some(bytes)) => (bytes, None),
+                Ok(None) => {
+                    warn!(
+                        "Request body is over {} MB, too large to check whole; refused",
+                        cap / (1024 * 1024)
+                    );
+                    return Err(StatusCode::PAYLOAD_TOO_LARGE);
+                }
+                Err(e) => {
+                    warn!("Failed to read request body: {}", e);
+                    return Err(StatusCode::BAD_REQUEST);
+                }
+            }
+        }
+        Ok(RequestBody::Partial(prefix, rest)) => {
+            info!(
+                "Request body is over {} KB; spooling it to disk and keeping its strings short",
+                state.max_request_bytes / 1024
+            );
+            match spool::spool(prefix, rest, state.max_request_bytes).await {
+                Ok(spooled) => (spooled.copy.clone(), Some(spooled)),
+                Err(e) => {
+                    warn!("Failed to read request body: {}", e);
+                    return Err(StatusCode::BAD_REQUEST);
+                }
+            }
+        }
+        Ok(RequestBody::Whole(bytes)) => (bytes, None),
         Err(e) => {
             warn!("Failed to read request body: {}", e);
             return Err(StatusCode::BAD_REQUEST);
         }
     };
 
+    let request_bytes = spooled.as_ref().map_or(body_bytes.len(), |s| s.len);
+
+    // Build the forwarding URL
+    let resolved = state
+        .routes
+        .resolve(path, uri.query(), &headers, &body_bytes);
+    let forward_url = resolved.url;
+    let parser = resolved.upstream.parser.clone();
+    let model_override = resolved.model;
+    let parser_confident = resolved.confident;
+
+    // Model lists are answered from the cache, not recorded
+    if let Some(key) = state.static_cache.key(&method, path, &forward_url, &headers) {
+        return serve_static(&state, key, &forward_url, &headers, parser.provider()).await;
+    }
+
     // Parse request body for typed access
-    let request: Option<AnthropicRequest> = serde_json::from_slice(&body_bytes).ok();
+    let mut request: Option<AnthropicRequest> = serde_json::from_slice(&body_bytes).ok();
 
     let claude_session_id = extract_claude_session_id(&request);
     let working_dir = extract_working_directory(&request);
 
-    // Track agent if we have a Claude session_id
-    let agent = if let Some(ref session_id) = claude_session_id {
-        match state
-            .agent_store
-            .get_or_create_agent(session_id, working_dir.as_deref())
-            .await
-        {
-            Ok(agent) => Some(agent),
+    // Keep what tools read from secret files out of the model's context
+    let withheld = match state.withholder.withhold(&body_bytes, working_dir.as_deref()) {
+        Some(withheld) => {
+            body_bytes = withheld.body;
+            request = serde_json::from_slice(&body_bytes).ok();
+            withheld.files
+        }
+        None => Vec::new(),
+    };
+
+    // Clients can name their own agents, whatever names the rest
+    let label = label.or_else(|| {
+        let name = headers.get(AGENT_NAME_HEADER)?.to_str().ok()?;
+        match launcher::parse_name(name) {
+            Ok(_) => Some(name),
             Err(e) => {
-                warn!("Failed to track agent: {}", e);
+                warn!("Ignoring {} header: {}", AGENT_NAME_HEADER, e);
                 None
             }
         }
-    } else {
-        None
+    });
+
+    // Track agent if it's named or we have a Claude session_id
+    let tracked = match (label, claude_session_id.as_deref()) {
+        (Some(name), session_id) => Some(
+            state
+                .agent_store
+                .get_or_create_named_agent(name, session_id, working_dir.as_deref())
+                .await,
+        ),
+        (None, Some(session_id)) => Some(
+            state
+                .agent_store
+                .get_or_create_agent(session_id, working_dir.as_deref())
+                .await,
+        ),
+        (None, None) => None,
     };
+    let attribution = Attribution::from_headers(&headers);
+    let agent = match tracked {
+        Some(Ok(mut agent)) => {
+            if let Err(e) = state.agent_store.attribute(&mut agent, &attribution).await {
+                warn!("Failed to record agent attribution: {}", e);
+            }
+            // A new conversation may be a subagent another agent started;
+            // looked for without holding the request
+            let opening = request.as_ref().and_then(|r| r.first_user_message_text());
+            if let (true, Some(opening)) = (subagents::unlinked(&agent, chrono::Utc::now()), opening) {
+                let pool = state.storage.pool();
+                let mut agent = agent.clone();
+                tokio::spawn(async move {
+                    if let Err(e) = subagents::link(&pool, &mut agent, &opening).await {
+                        warn!("Failed to link subagent {}: {}", agent.name, e);
+                    }
+                });
+            }
+            Some(agent)
+        }
+        Some(Err(e)) => {
+            warn!("Failed to track agent: {}", e);
+            None
+        }
+        None => None,
+    };
+    // Follow where the agent is in its repository, without holding the request
+    if let Some(ref agent) = agent {
+        if let Some(dir) = working_dir.clone().or_else(|| agent.working_directory.clone()) {
+            if let Some(ref worktrees) = state.worktrees {
+                worktrees.start(agent, dir.clone());
+            }
+            if state.git_lookups.due(&agent.id.to_string(), &dir) {
+                let agent_store = state.agent_store.clone();
+                let agent = agent.clone();
+                tokio::spawn(async move {
+                    let Some(git) = git::resolve(&dir).await else {
+                        return;
+                    };
+                    if let Err(e) = agent_store.record_git(&agent, &git).await {
+                        warn!("Failed to record git context of {}: {}", agent.name, e);
+                    }
+                });
+            }
+        }
+    }
     let agent_name = agent.as_ref().map(|a| a.name.clone());
+    if attribution.run_id.is_some() {
+        if let Err(e) = state.run_store.record(&attribution, agent_name.as_deref()).await {
+            warn!("Failed to record run: {}", e);
+        }
+    }
 
     // Skip telemetry events - they're just metadata noise
-    let is_telemetry = uri.path().contains("event_logging");
+    let is_telemetry = path.contains("event_logging");
+    let request_messages = request.as_ref().map(|r| r.messages.len());
+    if let (Some(watch), Some(name), Some(messages), false) =
+        (&state.waiting, &agent_name, request_messages, is_telemetry)
+    {
+        watch.request(name, messages);
+    }
+
+    // The response is recorded under this id, so it can be announced up front
+    let event_id = Uuid::new_v4();
+    let redo_of = headers
+        .get(REDO_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse().ok());
 
     // Store and broadcast user message if present
+    let degradation = state.degradation();
+    let mut request_event_id = None;
     if !is_telemetry {
         if let Some(ref req) = request {
             if let Some(text) = req.last_user_message_text() {
-                let user_event = ObservabilityEvent {
+                let id = Uuid::new_v4();
+                request_event_id = Some(id);
+                let conversation = claude_session_id.clone().or_else(|| agent_name.clone());
+                let fingerprints = branches::fingerprints(req);
+                let mut user_event = ObservabilityEvent {
                     seq: None,
-                    id: Uuid::new_v4(),
+                    id,
                     timestamp: chrono::Utc::now(),
                     session_id: claude_session_id.clone(),
                     agent: agent_name.clone(),
                     topic: agent.as_ref().and_then(|a| a.topic.clone()),
+                    provider: Some(parser.provider().to_string()),
                     payload: Payload::UserMessage(UserMessage {
                         model: Some(req.model.clone()),
                         text,
+                        tool_results: req.last_tool_results(),
+                        response_id: Some(event_id),
+                        project: attribution.project.clone(),
+                        run_id: attribution.run_id.clone(),
+                        thinking_budget: req.thinking_budget(),
+                        branch: None,
                     }),
                 };
 
-                if let Err(e) = state.storage.insert_observability_event(&user_event).await {
-                    tracing::error!("Failed to store user message event: {}", e);
-                }
-
-                let _ = state.event_broadcaster.send(user_event);
+                // Kept so `sentinel redo` can send the request again
+                let key = claude_session_id.clone().or_else(|| agent_name.clone());
+                let recorder = state.clone();
+                let body = body_bytes.clone();
+                let spooled = spooled.is_some();
+                let job = async move {
+                    // Placed as it's recorded, in order with the conversation's other requests
+                    if let (Some(conversation), Payload::UserMessage(ref mut message)) =
+                        (conversation, &mut user_event.payload)
+                    {
+                        message.branch = recorder
+                            .branches
+                            .place(id, &conversation, &fingerprints)
+                            .await
+                            .unwrap_or_else(|e| {
+                                warn!("Failed to place request in its conversation: {}", e);
+                                None
+                            });
+                    }
+                    let raw = if degradation.keeps_raw() {
+                        let body = recorder.redactor.bytes(&body);
+                        if spooled {
+                            // Its strings were cut short, so it can't be redone
+                            RawCapture {
+                                original_bytes: request_bytes,
+                                truncated: true,
+                                ..recorder.capture.capture(&body)
+                            }
+                        } else if !recorder.cipher.is_active() && bodies::splittable(&body) {
+                            // Stored a part at a time, so whole bodies don't
+                            // repeat the history; sealed ones can't be split
+                            CapturePolicy { max_raw_bytes: None }.capture(&body)
+                        } else {
+                            recorder.capture.capture(&body)
+                        }
+                    } else {
+                        RawCapture::skipped(body.len())
+                    };
+                    recorder.publish_with_raw(user_event, raw).await;
+                };
+                state
+                    .record(key.as_deref(), degradation, Default::default(), job)
+                    .await;
             }
         }
     }
@@ -102,39 +623,384 @@ pub async fn proxy_handler(
         info!(
             "→ {} {}{} ({} bytes)",
             method,
-            uri.path(),
+            path,
             agent_info,
-            body_bytes.len()
+            request_bytes
         );
     }
 
-    // Build the forwarding URL
-    let forward_url = format!(
-        "{}{}",
-        ANTHROPIC_API_URL,
-        uri.path_and_query().map(|pq| pq.as_str()).unwrap_or("")
-    );
+    if !is_telemetry {
+        for file in withheld {
+            warn!("🔒 Withheld{} {} result: read {}", agent_info, file.tool, file.path);
+            state
+                .publish(ObservabilityEvent {
+                    seq: None,
+                    id: Uuid::new_v4(),
+                    timestamp: chrono::Utc::now(),
+                    session_id: claude_session_id.clone(),
+                    agent: agent_name.clone(),
+                    topic: agent.as_ref().and_then(|a| a.topic.clone()),
+                    provider: Some(parser.provider().to_string()),
+                    payload: Payload::Violation(Violation {
+                        source: "withhold".to_string(),
+                        tool: file.tool,
+                        tool_use_id: Some(file.tool_use_id),
+                        message: format!(
+                            "read secret file {}; its contents were withheld from the model",
+                            file.path
+                        ),
+                        blocked: false,
+                        mismatches: Vec::new(),
+                    }),
+                })
+                .await;
+        }
+    }
+
+    // Refuse to carry a blocked tool call's result back to the model
+    if !is_telemetry && !state.baselines.is_empty() {
+        if let Some(call) = request.as_ref().and_then(|r| state.baselines.blocked_call(r)) {
+            warn!("✋ Refusing request{}: {} call broke a blocking baseline", agent_info, call.name);
+            return error_response(
+                StatusCode::FORBIDDEN,
+                "permission_error",
+                &format!(
+                    "sentinel: {} call ({}) violated a blocking baseline; see `sentinel logs`",
+                    call.name, call.id
+                ),
+            );
+        }
+    }
+
+    // Guardian mode: hold requests that follow a flagged tool call
+    if !is_telemetry && state.approvals.is_enabled() {
+        if let Some(hold) = request.as_ref().and_then(|r| state.approvals.check(r)) {
+            let approval = PendingApproval {
+                id: Uuid::new_v4(),
+                created_at: chrono::Utc::now(),
+                agent: agent_name.clone(),
+                session_id: claude_session_id.clone(),
+                tool_name: hold.tool_name,
+                tool_input: hold.tool_input,
+                reason: hold.reason,
+            };
+            let tool_name = approval.tool_name.clone();
+            info!("⏸ Holding request{} for approval: {} ({})", agent_info, approval.reason, approval.id);
+            state
+                .publish(ObservabilityEvent {
+                    seq: None,
+                    id: Uuid::new_v4(),
+                    timestamp: chrono::Utc::now(),
+                    session_id: claude_session_id.clone(),
+                    agent: agent_name.clone(),
+                    topic: agent.as_ref().and_then(|a| a.topic.clone()),
+                    provider: Some(parser.provider().to_string()),
+                    payload: Payload::Alert(Alert {
+                        kind: "approval_pending".to_string(),
+                        message: format!(
+                            "{} call awaiting approval: {} ({})",
+                            tool_name, approval.reason, approval.id
+                        ),
+                    }),
+                })
+                .await;
+
+            match state.approvals.wait_for_decision(approval).await {
+                Decision::Approved => info!("▶ {} approved{}", tool_name, agent_info),
+                decision => {
+                    let verdict = if decision == Decision::TimedOut {
+                        "timed out waiting for approval"
+                    } else {
+                        "denied"
+                    };
+                    warn!("✋ {} {}{}", tool_name, verdict, agent_info);
+                    return error_response(
+                        StatusCode::FORBIDDEN,
+                        "permission_error",
+                        &format!("sentinel: {} call {} (guardian mode)", tool_name, verdict),
+                    );
+                }
+            }
+        }
+    }
+
+    // Under --cap-message a request over a budget is answered with an
+    // assistant message saying why, recorded as the request's response
+    let streaming_request = retry::is_streaming_request(&body_bytes);
+    let stop_message = |text: String| ObservabilityEvent {
+        seq: None,
+        id: event_id,
+        timestamp: chrono::Utc::now(),
+        session_id: claude_session_id.clone(),
+        agent: agent_name.clone(),
+        topic: agent.as_ref().and_then(|a| a.topic.clone()),
+        provider: Some(parser.provider().to_string()),
+        payload: Payload::AssistantResponse(AssistantResponse {
+            streaming: streaming_request,
+            model: Some(request.as_ref().map_or("unknown", |r| r.model.as_str()).to_string()),
+            stop_reason: Some("end_turn".to_string()),
+            text: Some(text),
+            project: attribution.project.clone(),
+            run_id: attribution.run_id.clone(),
+            ..Default::default()
+        }),
+    };
+
+    // Send with the upstream key this client or agent is entitled to
+    let upstream_key = if parser.provider() == "anthropic" && !state.keys.is_empty() {
+        state
+            .keys
+            .select(keys::client_credential(&headers), agent_name.as_deref())
+    } else {
+        None
+    };
+    if let Some(key) = upstream_key {
+        if let Some((spent, budget)) = state.keys.over_budget(key) {
+            warn!("✋ Refusing request{}: upstream key '{}' is over budget", agent_info, key.name);
+            let message = format!(
+                "sentinel: upstream key '{}' has spent ${:.2} of its ${:.2} monthly budget",
+                key.name, spent, budget
+            );
+            if state.cap_message {
+                let text = format!(
+                    "{}. Requests are refused until the budget resets next month.",
+                    message
+                );
+                return stop_message_response(&state, stop_message(text), !is_telemetry).await;
+            }
+            return error_response(StatusCode::FORBIDDEN, "permission_error", &message);
+        }
+    }
+    if let Some(refusal) = state
+        .budgets
+        .check(agent_name.as_deref(), claude_session_id.as_deref())
+    {
+        warn!("✋ Refusing request{}: {}", agent_info, refusal.message);
+        if state.cap_message {
+            let event = stop_message(refusal.message);
+            return stop_message_response(&state, event, !is_telemetry).await;
+        }
+        let mut response =
+            error_response(StatusCode::TOO_MANY_REQUESTS, "rate_limit_error", &refusal.message)?;
+        if let Some(secs) = refusal.retry_after {
+            response
+                .headers_mut()
+                .insert(axum::http::header::RETRY_AFTER, secs.into());
+        }
+        return Ok(response);
+    }
+
+    // Journal responses the client may ask for again: streams to resume
+    // (Anthropic only), whole responses to replay instead of re-billing
+    // Read before a resume rewrites the body; a body over the size cap isn't
+    // held whole, so it goes unchecked
+    let contract = if is_telemetry || spooled.is_some() {
+        None
+    } else {
+        OutputContract::from_body(&body_bytes)
+    };
+    let client_key = [RESUME_HEADER, IDEMPOTENCY_HEADER]
+        .iter()
+        .find_map(|name| headers.get(*name)?.to_str().ok())
+        .map(str::to_string);
+    let journal_key = client_key
+        .clone()
+        // Journals hold the raw stream, so nothing is journaled while
+        // redacting or encrypting
+        .filter(|_| !is_telemetry && !state.redactor.is_active() && !state.cipher.is_active())
+        .filter(|_| parser.provider() == "anthropic" || !streaming_request)
+        // Resuming rewrites the request body, so it has to be whole
+        .filter(|_| spooled.is_none());
+    let mut body_bytes = body_bytes;
+    let mut splice = None;
+    if let Some(ref key) = journal_key {
+        match state.journal.load(key).await {
+            Ok(Some(journal)) => match resume::plan(&journal) {
+                Resume::Replay(recorded, streaming) => {
+                    info!("↺ Replaying recorded response {}{}", key, agent_info);
+                    return replay_response(recorded, streaming);
+                }
+                Resume::Continue(plan) => match plan.continuation_body(&body_bytes) {
+                    Some(body) => {
+                        info!(
+                            "↺ Resuming stream {}{} after {} journaled bytes",
+                            key,
+                            agent_info,
+                            plan.journaled_bytes()
+                        );
+                        state
+                            .publish(ObservabilityEvent {
+                                seq: None,
+                                id: Uuid::new_v4(),
+                                timestamp: chrono::Utc::now(),
+                                session_id: claude_session_id.clone(),
+                                agent: agent_name.clone(),
+                                topic: agent.as_ref().and_then(|a| a.topic.clone()),
+                                provider: Some(parser.provider().to_string()),
+                                payload: Payload::Alert(Alert {
+                                    kind: "stream_resumed".to_string(),
+                                    message: format!(
+                                        "Stream {} resumed: {} journaled bytes replayed, rest continued upstream",
+                                        key,
+                                        plan.journaled_bytes()
+                                    ),
+                                }),
+                            })
+                            .await;
+                        body_bytes = body;
+                        splice = Some(plan);
+                    }
+                    None => warn!("Can't resume stream {}: request isn't a messages request", key),
+                },
+                Resume::Restart => info!("↺ Stream {} can't be continued; starting over", key),
+            },
+            Ok(None) => {}
+            Err(e) => warn!("Failed to read stream journal {}: {}", key, e),
+        }
+        if let Err(e) = state.journal.begin(key, streaming_request).await {
+            warn!("Failed to start stream journal {}: {}", key, e);
+        }
+    }
+
+    let mirror_request = state
+        .mirror
+        .as_ref()
+        .filter(|_| !is_telemetry)
+        .map(|_| MirroredRequest {
+            event_id,
+            session_id: claude_session_id.clone(),
+            agent: agent_name.clone(),
+            method: method.to_string(),
+            url: forward_url.clone(),
+            headers: headers.clone(),
+            body: body_bytes.clone(),
+            truncated_from: spooled.as_ref().map(|s| s.len),
+        });
 
     // Build forwarding request
     let mut forward_req = state.http_client.request(method, &forward_url);
 
-    // Copy headers (except host)
+    // Copy headers (except host and our own)
     for (name, value) in headers.iter() {
-        if name != "host" {
+        if name != "host" && !is_ours(&state, name.as_str(), upstream_key.is_some()) {
             forward_req = forward_req.header(name, value);
         }
     }
+    if let Some(key) = upstream_key {
+        forward_req = forward_req.header("x-api-key", key.api_key());
+    }
+
+    let correlation = if state.correlation_headers && !is_telemetry {
+        correlation_headers(claude_session_id.as_deref(), event_id)
+    } else {
+        Vec::new()
+    };
+    for (name, value) in &correlation {
+        forward_req = forward_req.header(*name, value);
+    }
+
+    // One key across every attempt, so a retry is never billed twice
+    let retries = if streaming_request || is_telemetry || spooled.is_some() {
+        0
+    } else {
+        state.retries
+    };
+    let idempotency_key = client_key.or_else(|| (retries > 0).then(|| Uuid::new_v4().to_string()));
+    if let Some(ref key) = idempotency_key {
+        forward_req = forward_req.header(IDEMPOTENCY_HEADER, key);
+    }
+    // A body too large to hold goes up from its spool file
+    let mut streamed_body = spooled.map(spool::Spooled::into_body);
 
-    // Send request
-    let response = match forward_req.body(body_bytes.to_vec()).send().await {
+    let watch = if is_telemetry {
+        TurnWatch::unwatched()
+    } else {
+        let turn = TurnRequest {
+            response_id: event_id,
+            model: model_override
+                .clone()
+                .or_else(|| request.as_ref().map(|r| r.model.clone())),
+            streaming: streaming_request,
+            request_bytes: request_bytes as u64,
+            messages: request_messages.map(|n| n as u64),
+            upstream_host: reqwest::Url::parse(&forward_url)
+                .ok()
+                .and_then(|url| url.host_str().map(str::to_string)),
+        };
+        state.watch_turn(turn, claude_session_id.clone(), agent.as_ref(), parser.provider())
+    };
+
+    // Send request, retrying what the upstream turned away unrun
+    let mut attempt = 0;
+    let (started, sent) = loop {
+        watch.progress().attempt();
+        let started = std::time::Instant::now();
+        let body = streamed_body
+            .take()
+            .unwrap_or_else(|| reqwest::Body::from(body_bytes.clone()));
+        let request = forward_req
+            .try_clone()
+            .expect("the body is added per attempt")
+            .body(body);
+        let sent = request.send().await;
+        let (reason, retry_after) = match sent {
+            Ok(ref resp) if retry::is_retryable_status(resp.status().as_u16()) => (
+                resp.status().to_string(),
+                resp.headers()
+                    .get("retry-after")
+                    .and_then(|v| v.to_str().ok())
+                    .map(str::to_string),
+            ),
+            Err(ref e) if retry::is_retryable_cause(classify(e)) => (classify(e).to_string(), None),
+            _ => break (started, sent),
+        };
+        attempt += 1;
+        let wait = match retry::backoff(attempt, retry_after.as_deref()) {
+            Some(wait) if attempt <= retries => wait,
+            _ => break (started, sent),
+        };
+        warn!(
+            "↻ Upstream turned request away ({}); retry {}/{} in {:?}",
+            reason, attempt, retries, wait
+        );
+        tokio::time::sleep(wait).await;
+    };
+    let response = match sent {
         Ok(resp) => resp,
         Err(e) => {
-            warn!("Failed to forward request: {}", e);
-            return Err(StatusCode::BAD_GATEWAY);
+            let cause = classify(&e);
+            let message = error_chain(&e);
+            warn!("Failed to forward request ({}): {}", cause, message);
+            state.record_outcome(parser.provider(), true).await;
+            state
+                .publish(ObservabilityEvent {
+                    seq: None,
+                    id: event_id,
+                    timestamp: chrono::Utc::now(),
+                    session_id: claude_session_id,
+                    agent: agent_name,
+                    topic: agent.as_ref().and_then(|a| a.topic.clone()),
+                    provider: Some(parser.provider().to_string()),
+                    payload: Payload::Error(ErrorEvent {
+                        kind: ErrorKind::Forward,
+                        cause: Some(cause),
+                        status: None,
+                        message: message.clone(),
+                        body: None,
+                    }),
+                })
+                .await;
+            return forward_failure_response(cause, &message);
         }
     };
 
+    let first_byte = started.elapsed();
     let status = response.status();
+    if !is_telemetry {
+        state
+            .record_outcome(parser.provider(), is_upstream_error(status.as_u16()))
+            .await;
+    }
     let response_headers = response.headers().clone();
 
     // Check if this is a streaming response
@@ -144,11 +1010,406 @@ pub async fn proxy_handler(
         .unwrap_or("");
 
     let is_streaming = content_type.contains("text/event-stream");
+    watch.progress().headers(is_streaming);
+
+    let ctx = ResponseContext {
+        parser,
+        parser_confident,
+        model_override,
+        event_id,
+        request_event_id,
+        redo_of,
+        upstream_key: upstream_key.map(|k| k.name.clone()),
+        attribution,
+        mirror_request,
+        correlation,
+        idempotency_key,
+        is_telemetry,
+        session_id: claude_session_id,
+        agent_name,
+        agent,
+        started,
+        first_byte,
+        first_token: None,
+        journal_key,
+        splice,
+        watch,
+        request_messages,
+        contract,
+        ended: None,
+        degradation,
+        parse_time: Default::default(),
+        api_version: headers
+            .get("anthropic-version")
+            .and_then(|v| v.to_str().ok())
+            .map(str::to_string),
+    };
 
     if is_streaming {
-        handle_streaming_response(state, response, status, response_headers, is_telemetry, claude_session_id, agent_name, agent).await
+        handle_streaming_response(state, response, status, response_headers, ctx).await
+    } else {
+        handle_regular_response(state, response, status, response_headers, ctx).await
+    }
+}
+
+/// A request body: whole, or the start of one over the size cap
+enum RequestBody {
+    Whole(Bytes),
+    /// Bytes read so far, and the rest still to come from the client
+    Partial(Bytes, BodyDataStream),
+}
+
+/// Read a body until it ends or grows past `cap` bytes
+async fn read_body(body: Body, cap: usize) -> Result<RequestBody, axum::Error> {
+    let mut stream = body.into_data_stream();
+    let mut read = BytesMut::new();
+    while let Some(chunk) = stream.next().await {
+        read.extend_from_slice(&chunk?);
+        if read.len() > cap {
+            return Ok(RequestBody::Partial(read.freeze(), stream));
+        }
+    }
+    Ok(RequestBody::Whole(read.freeze()))
+}
+
+/// The whole of a body over the cap, or None once it grows past `cap` bytes
+async fn read_rest(
+    prefix: Bytes,
+    mut rest: BodyDataStream,
+    cap: usize,
+) -> Result<Option<Bytes>, axum::Error> {
+    let mut read = BytesMut::from(&prefix[..]);
+    while let Some(chunk) = rest.next().await {
+        read.extend_from_slice(&chunk?);
+        if read.len() > cap {
+            return Ok(None);
+        }
+    }
+    Ok(Some(read.freeze()))
+}
+
+/// Headers kept back from the upstream: our own resume key and attribution, the
+/// idempotency and correlation headers we set, and credentials we replace
+fn is_ours(state: &ProxyState, name: &str, replacing_credentials: bool) -> bool {
+    name == RESUME_HEADER
+        || name == REDO_HEADER
+        || is_attribution_header(name)
+        || name == IDEMPOTENCY_HEADER
+        || (state.correlation_headers && (name == SESSION_HEADER || name == EVENT_ID_HEADER))
+        || (replacing_credentials && (name == "x-api-key" || name == "authorization"))
+}
+
+/// A cacheable GET: fresh from the cache, else fetched and cached, else the
+/// stale copy when the upstream can't answer
+async fn serve_static(
+    state: &ProxyState,
+    key: String,
+    forward_url: &str,
+    headers: &axum::http::HeaderMap,
+    provider: &str,
+) -> Result<Response<Body>, StatusCode> {
+    let cached = state.static_cache.get(&key);
+    if let Some((ref cached, true)) = cached {
+        return Ok(cached.response(Served::Hit));
+    }
+    let stale = cached.map(|(cached, _)| cached);
+
+    let upstream_key = if provider == "anthropic" && !state.keys.is_empty() {
+        state.keys.select(keys::client_credential(headers), None)
+    } else {
+        None
+    };
+    let mut forward_req = state.http_client.get(forward_url);
+    for (name, value) in headers.iter() {
+        // accept-encoding is left out so the cached copy is plain, whatever
+        // each client accepts
+        if name != "host"
+            && name != "accept-encoding"
+            && !is_ours(state, name.as_str(), upstream_key.is_some())
+        {
+            forward_req = forward_req.header(name, value);
+        }
+    }
+    if let Some(key) = upstream_key {
+        forward_req = forward_req.header("x-api-key", key.api_key());
+    }
+
+    let response = match forward_req.send().await {
+        Ok(response) => response,
+        Err(e) => {
+            if let Some(stale) = stale {
+                warn!("Upstream unreachable; serving a stale copy of {}: {}", forward_url, error_chain(&e));
+                return Ok(stale.response(Served::Stale));
+            }
+            return forward_failure_response(classify(&e), &error_chain(&e));
+        }
+    };
+    let status = response.status();
+    if let (true, Some(stale)) = (is_upstream_error(status.as_u16()), stale) {
+        warn!("Upstream returned {}; serving a stale copy of {}", status, forward_url);
+        return Ok(stale.response(Served::Stale));
+    }
+    let response_headers = response.headers().clone();
+    let body = match response.bytes().await {
+        Ok(body) => body,
+        Err(e) => return forward_failure_response(classify(&e), &error_chain(&e)),
+    };
+    if status.is_success() {
+        let cached = Cached::new(response_headers, body);
+        state.static_cache.put(key, cached.clone());
+        return Ok(cached.response(Served::Miss));
+    }
+    // Errors go back whole, retry-after and request-id included
+    let mut builder = Response::builder().status(status.as_u16());
+    for (name, value) in response_headers.iter() {
+        builder = builder.header(name, value);
+    }
+    builder.body(Body::from(body)).map_err(|e| {
+        warn!("Failed to build response: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })
+}
+
+/// Tell the client why the upstream couldn't be reached, in the Anthropic
+/// error shape so SDKs surface the message instead of a bare status.
+fn forward_failure_response(cause: FailureCause, message: &str) -> Result<Response<Body>, StatusCode> {
+    let status = match cause {
+        FailureCause::ConnectTimeout | FailureCause::Timeout => StatusCode::GATEWAY_TIMEOUT,
+        _ => StatusCode::BAD_GATEWAY,
+    };
+    error_response(
+        status,
+        "api_error",
+        &format!("sentinel: upstream unreachable ({}): {}. {}", cause, message, cause.hint()),
+    )
+}
+
+/// Headers naming the session (when the agent has one) and the response event
+fn correlation_headers(session_id: Option<&str>, event_id: Uuid) -> Vec<(&'static str, String)> {
+    let mut headers = vec![(EVENT_ID_HEADER, event_id.to_string())];
+    if let Some(session_id) = session_id {
+        headers.push((SESSION_HEADER, session_id.to_string()));
+    }
+    headers
+}
+
+/// Send a journaled response again as it was recorded
+fn replay_response(recorded: Bytes, streaming: bool) -> Result<Response<Body>, StatusCode> {
+    let content_type = if streaming {
+        "text/event-stream"
     } else {
-        handle_regular_response(state, response, status, response_headers, is_telemetry, claude_session_id, agent_name, agent).await
+        "application/json"
+    };
+    Response::builder()
+        .status(StatusCode::OK)
+        .header("content-type", content_type)
+        .body(Body::from(recorded))
+        .map_err(|e| {
+            warn!("Failed to build response: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })
+}
+
+/// An Anthropic-shaped error body, so clients surface the message as-is
+fn error_response(status: StatusCode, error_type: &str, message: &str) -> Result<Response<Body>, StatusCode> {
+    let body = serde_json::json!({
+        "type": "error",
+        "error": {
+            "type": error_type,
+            "message": message,
+        }
+    });
+
+    Response::builder()
+        .status(status)
+        .header("content-type", "application/json")
+        .body(Body::from(body.to_string()))
+        .map_err(|e| {
+            warn!("Failed to build response: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })
+}
+
+/// A whole assistant turn saying `text`, in the shape the request asked for,
+/// so a client that only expects messages shows it and ends the turn
+fn stop_message_body(model: &str, streaming: bool, text: &str) -> (&'static str, String) {
+    let id = format!("msg_sentinel_{}", Uuid::new_v4().simple());
+    let usage = serde_json::json!({"input_tokens": 0, "output_tokens": 0});
+    if !streaming {
+        let body = serde_json::json!({
+            "id": id,
+            "type": "message",
+            "role": "assistant",
+            "model": model,
+            "content": [{"type": "text", "text": text}],
+            "stop_reason": "end_turn",
+            "stop_sequence": null,
+            "usage": usage,
+        });
+        return ("application/json", body.to_string());
+    }
+    let events = [
+        serde_json::json!({"type": "message_start", "message": {
+            "id": id,
+            "type": "message",
+            "role": "assistant",
+            "model": model,
+            "content": [],
+            "stop_reason": null,
+            "stop_sequence": null,
+            "usage": usage,
+        }}),
+        serde_json::json!({"type": "content_block_start", "index": 0, "content_block": {"type": "text", "text": ""}}),
+        serde_json::json!({"type": "content_block_delta", "index": 0, "delta": {"type": "text_delta", "text": text}}),
+        serde_json::json!({"type": "content_block_stop", "index": 0}),
+        serde_json::json!({"type": "message_delta", "delta": {"stop_reason": "end_turn", "stop_sequence": null}, "usage": {"output_tokens": 0}}),
+        serde_json::json!({"type": "message_stop"}),
+    ];
+    let body = events
+        .iter()
+        .map(|event| format!("event: {}\ndata: {}\n\n", event["type"].as_str().unwrap_or_default(), event))
+        .collect();
+    ("text/event-stream", body)
+}
+
+/// Answer a refused request with `event`'s assistant message, recording it
+/// when `record` is set
+async fn stop_message_response(
+    state: &ProxyState,
+    event: ObservabilityEvent,
+    record: bool,
+) -> Result<Response<Body>, StatusCode> {
+    let Payload::AssistantResponse(ref response) = event.payload else {
+        return Err(StatusCode::INTERNAL_SERVER_ERROR);
+    };
+    let model = response.model.as_deref().unwrap_or("unknown");
+    let text = response.text.as_deref().unwrap_or_default();
+    let (content_type, body) = stop_message_body(model, response.streaming, text);
+    if record {
+        state.publish(event).await;
+    }
+    Response::builder()
+        .status(StatusCode::OK)
+        .header("content-type", content_type)
+        .body(Body::from(body))
+        .map_err(|e| {
+            warn!("Failed to build response: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })
+}
+
+/// Per-request context carried from the request side to response handling
+struct ResponseContext {
+    parser: Arc<dyn ResponseParser>,
+    /// False when the request didn't identify the provider, so the
+    /// response's shape should pick the parser
+    parser_confident: bool,
+    /// Model named by the route (e.g. an Azure deployment) rather than the body
+    model_override: Option<String>,
+    /// Id the response event is recorded under
+    event_id: Uuid,
+    /// Id of the user message event recorded for the request
+    request_event_id: Option<Uuid>,
+    /// Response a `sentinel redo` request re-asks for
+    redo_of: Option<Uuid>,
+    /// Name of the `--upstream-keys` key the request was sent with
+    upstream_key: Option<String>,
+    /// What the request's x-sentinel-* headers said
+    attribution: Attribution,
+    /// The forwarded request, when exchanges are mirrored to files
+    mirror_request: Option<MirroredRequest>,
+    /// Correlation headers to add to the response, if enabled
+    correlation: Vec<(&'static str, String)>,
+    /// Key sent upstream as `idempotency-key`, recorded on the response
+    idempotency_key: Option<String>,
+    is_telemetry: bool,
+    session_id: Option<String>,
+    agent_name: Option<String>,
+    agent: Option<Agent>,
+    /// When the request was forwarded upstream
+    started: std::time::Instant,
+    /// How long the upstream took to send response headers
+    first_byte: std::time::Duration,
+    /// How long until a streamed response carried its first content
+    first_token: Option<std::time::Duration>,
+    /// Idempotency key the streamed response is journaled under
+    journal_key: Option<String>,
+    /// An interrupted stream this response continues
+    splice: Option<ResumePlan>,
+    /// Progress for --turn-sla; dropped once the response is handled
+    watch: TurnWatch,
+    /// Messages the request carried, for --notify-waiting
+    request_messages: Option<usize>,
+    /// Output format or tool call the request declared, checked against the
+    /// response
+    contract: Option<OutputContract>,
+    /// When the response ended and how long it took, once it's queued for
+    /// recording
+    ended: Option<(chrono::DateTime<chrono::Utc>, std::time::Duration)>,
+    /// How far recording is cut back for --latency-budget-ms
+    degradation: Degradation,
+    /// Time spent parsing a streamed response as it passed
+    parse_time: std::time::Duration,
+    /// `anthropic-version` the client pinned, if any
+    api_version: Option<String>,
+}
+
+impl ResponseContext {
+    /// Hand the response over for recording later: stops following the turn
+    /// and notes when it ended, so time spent queued isn't counted
+    fn finish(&mut self) {
+        self.watch = TurnWatch::unwatched();
+        self.ended = Some((chrono::Utc::now(), self.started.elapsed()));
+    }
+
+    /// When the response ended and how long it took
+    fn ended(&self) -> (chrono::DateTime<chrono::Utc>, std::time::Duration) {
+        self.ended
+            .unwrap_or_else(|| (chrono::Utc::now(), self.started.elapsed()))
+    }
+
+    /// What the exchange's recording is ordered under
+    fn record_key(&self) -> Option<String> {
+        self.session_id.clone().or_else(|| self.agent_name.clone())
+    }
+
+    /// Write the exchange to --mirror-dir, if enabled
+    async fn mirror(
+        &self,
+        state: &ProxyState,
+        status: reqwest::StatusCode,
+        headers: &reqwest::header::HeaderMap,
+        body: &[u8],
+    ) {
+        if let (Some(mirror), Some(request)) = (&state.mirror, &self.mirror_request) {
+            mirror
+                .write(&state.redactor, request, status.as_u16(), headers, body)
+                .await;
+        }
+    }
+
+    /// Record a request that failed after reaching the upstream, under the
+    /// id its response would have had
+    async fn publish_error(&self, state: &ProxyState, error: ErrorEvent) {
+        state
+            .publish(ObservabilityEvent {
+                seq: None,
+                id: self.event_id,
+                timestamp: self.ended().0,
+                session_id: self.session_id.clone(),
+                agent: self.agent_name.clone(),
+                topic: self.agent.as_ref().and_then(|a| a.topic.clone()),
+                provider: Some(self.parser.provider().to_string()),
+                payload: Payload::Error(error),
+            })
+            .await;
+    }
+
+    fn apply_model_override(&self, mut parsed: ParsedResponse) -> ParsedResponse {
+        if let Some(ref model) = self.model_override {
+            parsed.metadata.model = Some(model.clone());
+        }
+        parsed
     }
 }
 
@@ -157,73 +1418,183 @@ async fn handle_streaming_response(
     response: reqwest::Response,
     status: reqwest::StatusCode,
     response_headers: reqwest::header::HeaderMap,
-    is_telemetry: bool,
-    claude_session_id: Option<String>,
-    agent_name: Option<String>,
-    agent: Option<Agent>,
+    mut ctx: ResponseContext,
 ) -> Result<Response<Body>, StatusCode> {
     let mut stream = response.bytes_stream();
     let (tx, rx) = tokio::sync::mpsc::channel::<Result<Bytes, std::io::Error>>(32);
 
-    let storage = state.storage.clone();
-    let parser = state.parser.clone();
-    let agent_store = state.agent_store.clone();
-    let event_broadcaster = state.event_broadcaster.clone();
+    let correlation = ctx.correlation.clone();
+    let mirror_headers = ctx
+        .mirror_request
+        .as_ref()
+        .map(|_| response_headers.clone());
+    let (replay, mut splice) = match ctx.splice.take() {
+        Some(plan) if status.is_success() => {
+            let (replay, splice) = plan.into_splice();
+            (Some(replay), Some(splice))
+        }
+        _ => (None, None),
+    };
+
+    // Raw bytes kept for capture and --mirror-dir; the rest is parsed as it
+    // passes and let go
+    let keep = match (&mirror_headers, state.capture.max_raw_bytes) {
+        (None, _) if !ctx.degradation.keeps_raw() => 0,
+        (None, Some(max)) => max.saturating_add(RAW_SLACK),
+        _ => usize::MAX,
+    };
 
-    // Spawn task to collect and forward chunks
+    // Spawn task to parse and forward chunks
     tokio::spawn(async move {
-        let mut response_chunks: Vec<Bytes> = Vec::new();
-
-        while let Some(chunk_result) = stream.next().await {
-            match chunk_result {
-                Ok(chunk) => {
-                    response_chunks.push(chunk.clone());
-                    if tx.send(Ok(chunk)).await.is_err() {
-                        break;
+        let detect = (!ctx.parser_confident).then_some(state.routes.as_ref());
+        let mut tracker = StreamTracker::new(ctx.parser.clone(), detect, keep);
+        let journal = JournalWriter::new(&state, ctx.journal_key.as_deref());
+
+        // Set when the client hangs up. A journaled stream is still read to
+        // its end, so the client can resume it or have it replayed; any
+        // other is cancelled (`aborted`) and nothing more is read
+        let journaled = journal.is_active();
+        let mut client_gone = false;
+        let mut aborted = false;
+        if let Some(replay) = replay {
+            journal.append(&replay);
+            tracker.push(&replay, ctx.started.elapsed());
+            client_gone = tx.send(Ok(replay)).await.is_err();
+        }
+
+        let mut finished = false;
+        let mut interrupted = None;
+        let mut calls = StreamedCalls::default();
+        // Set when --policy-enforce or a blocking baseline cut the stream off
+        // at a tool call
+        let mut tripped = None;
+        let enforcing = !ctx.is_telemetry && (state.policy.is_some() || !state.baselines.is_empty());
+        loop {
+            if client_gone && !journaled {
+                aborted = true;
+                break;
+            }
+            // Noticed while waiting on the upstream too, not only at the
+            // next send, so a long pause doesn't keep it running
+            let next = tokio::select! {
+                next = stream.next() => next,
+                _ = tx.closed(), if !client_gone => {
+                    client_gone = true;
+                    continue;
+                }
+            };
+            match next {
+                Some(Ok(chunk)) => {
+                    let chunk = match splice {
+                        Some(ref mut splice) => splice.push(&chunk),
+                        None => chunk,
+                    };
+                    if chunk.is_empty() {
+                        continue;
+                    }
+                    let parsing = std::time::Instant::now();
+                    let deltas = tracker.push(&chunk, ctx.started.elapsed());
+                    ctx.parse_time += parsing.elapsed();
+                    ctx.watch.progress().chunk(chunk.len(), tracker.first_token);
+                    if enforcing {
+                        let working_dir = ctx.agent.as_ref().and_then(|a| a.working_directory.as_deref());
+                        let violations = check_streamed(
+                            &state.baselines,
+                            state.policy.as_deref(),
+                            &mut calls,
+                            &deltas,
+                            ctx.agent_name.as_deref(),
+                            working_dir,
+                        );
+                        let blocked = violations.iter().find(|v| v.blocked).cloned();
+                        let topic = ctx.agent.as_ref().and_then(|a| a.topic.clone());
+                        publish_violations(&state, &ctx, topic, violations).await;
+                        if let Some(violation) = blocked {
+                            // The chunk completing the call is held back, so
+                            // the client never gets a call it could run
+                            let _ = tx.send(Ok(Bytes::from(policy_error_event(&violation)))).await;
+                            tripped = Some(violation);
+                            break;
+                        }
+                    }
+                    journal.append(&chunk);
+                    if !ctx.is_telemetry {
+                        publish_deltas(&state, &ctx, deltas);
+                    }
+                    if !client_gone {
+                        client_gone = tx.send(Ok(chunk)).await.is_err();
                     }
                 }
-                Err(e) => {
+                Some(Err(e)) => {
                     warn!("Error reading stream chunk: {}", e);
+                    interrupted = Some(error_chain(&e));
+                    break;
+                }
+                None => {
+                    finished = true;
                     break;
                 }
             }
         }
+        if client_gone && journaled && finished {
+            info!(
+                "Client disconnected mid-stream; read the rest ({} bytes) into its journal",
+                tracker.received
+            );
+        }
+        let aborted_after = aborted.then(|| ctx.started.elapsed());
+        if let Some(ref violation) = tripped {
+            drop(stream);
+            warn!("✋ Cut off stream at a {} call: {}", violation.tool, violation.message);
+        } else if aborted {
+            // Dropping the upstream response closes its connection, so the
+            // model stops generating (and billing) for nobody
+            drop(stream);
+            warn!(
+                "Client disconnected mid-stream after {} bytes; cancelled upstream",
+                tracker.received
+            );
+        }
+        if let Some(splice) = splice.filter(|_| !client_gone && tripped.is_none()) {
+            let rest = splice.finish();
+            if !rest.is_empty() && tx.send(Ok(rest.clone())).await.is_ok() {
+                tracker.push(&rest, ctx.started.elapsed());
+            }
+        }
+        if finished {
+            journal.finish();
+        }
 
         // Skip logging for telemetry responses
-        if is_telemetry {
+        if ctx.is_telemetry {
             return;
         }
 
-        // Log complete response after stream ends
-        let full_response: Vec<u8> = response_chunks
-            .iter()
-            .flat_map(|c| c.iter().copied())
-            .collect();
-        let response_text = String::from_utf8_lossy(&full_response);
-
-        // Parse the streaming response into structured data
-        let parsed = parser.parse_streaming(&response_text);
-
-        // Log a summary before consuming parsed
-        let text_preview = parsed.text.as_ref().map(|t| {
-            let preview: String = t.chars().take(50).collect();
-            if t.len() > 50 {
-                format!("{}...", preview)
-            } else {
-                preview
-            }
-        });
-
-        store_and_broadcast_response_event(
-            parsed, &agent, &agent_store, &storage, &event_broadcaster,
-            claude_session_id, agent_name,
-        ).await;
-
-        info!(
-            "← Streaming response complete ({} bytes) text={:?}",
-            full_response.len(),
-            text_preview
-        );
+        ctx.first_token = tracker.first_token;
+        if let Some(parser) = tracker.detected.take() {
+            ctx.parser = parser;
+        }
+        let stream = EndedStream {
+            status,
+            mirror_headers,
+            received: tracker.received,
+            kept: std::mem::take(&mut tracker.kept),
+            interrupted,
+            aborted_after,
+            parsed: tracker.finish(),
+        };
+        let (level, spent) = (ctx.degradation, ctx.parse_time);
+        // Its tool calls were checked as they streamed
+        if state.deferral(level, false).is_some() {
+            // The client's body ends here, not once the response is recorded
+            drop(tx);
+            ctx.finish();
+        }
+        let key = ctx.record_key();
+        let recorder = state.clone();
+        recorder
+            .record(key.as_deref(), level, spent, record_stream(state, ctx, stream))
+            .await;
     });
 
     // Build streaming response
@@ -234,6 +1605,9 @@ async fn handle_streaming_response(
     for (name, value) in response_headers.iter() {
         builder = builder.header(name, value);
     }
+    for (name, value) in &correlation {
+        builder = builder.header(*name, value);
+    }
 
     builder.body(body).map_err(|e| {
         warn!("Failed to build response: {}", e);
@@ -241,98 +1615,667 @@ async fn handle_streaming_response(
     })
 }
 
+/// Violations of the tool calls a stream's latest deltas complete, checked
+/// against baselines and --policy before the chunk carrying them is forwarded
+fn check_streamed(
+    baselines: &BaselineMonitor,
+    policy: Option<&PolicyGuard>,
+    calls: &mut StreamedCalls,
+    deltas: &[Delta],
+    agent: Option<&str>,
+    working_dir: Option<&str>,
+) -> Vec<Violation> {
+    let completed: Vec<ToolCall> = deltas.iter().filter_map(|delta| calls.push(delta)).collect();
+    let mut violations = baselines.check(agent, working_dir, &completed);
+    if let Some(policy) = policy {
+        for call in &completed {
+            violations.extend(policy.check(call, working_dir));
+        }
+    }
+    violations
+}
+
+/// A streamed response as it ended, before it's recorded
+struct EndedStream {
+    status: reqwest::StatusCode,
+    mirror_headers: Option<reqwest::header::HeaderMap>,
+    received: usize,
+    kept: Vec<u8>,
+    /// Why the upstream stream broke off, if it did
+    interrupted: Option<String>,
+    /// When the client hung up, if it did
+    aborted_after: Option<std::time::Duration>,
+    parsed: ParsedResponse,
+}
+
+/// Record a streamed response once it has ended
+async fn record_stream(state: Arc<ProxyState>, ctx: ResponseContext, stream: EndedStream) {
+    let EndedStream {
+        status,
+        mirror_headers,
+        received,
+        kept,
+        interrupted,
+        aborted_after,
+        parsed,
+    } = stream;
+    if let Some(ref headers) = mirror_headers {
+        ctx.mirror(&state, status, headers, &kept).await;
+    }
+    if let Some(message) = interrupted {
+        // What arrived is incomplete; the request is recorded as failed
+        ctx.publish_error(
+            &state,
+            ErrorEvent {
+                kind: ErrorKind::Interrupted,
+                cause: None,
+                status: Some(status.as_u16()),
+                message: format!("stream broke off after {} bytes: {}", received, message),
+                body: None,
+            },
+        )
+        .await;
+        return;
+    }
+    let parsed = ctx.apply_model_override(parsed);
+
+    // Log a summary before consuming parsed
+    // Sealed content stays out of the log too
+    let text_preview = parsed.text.as_ref().filter(|_| !state.cipher.is_active()).map(|t| {
+        let t = state.redactor.text(t);
+        let preview: String = t.chars().take(50).collect();
+        if t.len() > 50 {
+            format!("{}...", preview)
+        } else {
+            preview
+        }
+    });
+
+    let raw = if ctx.degradation.keeps_raw() {
+        state
+            .capture
+            .capture_start(&state.redactor.bytes(&kept), received)
+    } else {
+        RawCapture::skipped(received)
+    };
+    store_and_broadcast_response_event(&state, parsed, raw, &ctx).await;
+    if let Some(elapsed) = aborted_after {
+        state
+            .publish(ObservabilityEvent {
+                seq: None,
+                id: Uuid::new_v4(),
+                timestamp: ctx.ended().0,
+                session_id: ctx.session_id.clone(),
+                agent: ctx.agent_name.clone(),
+                topic: ctx.agent.as_ref().and_then(|a| a.topic.clone()),
+                provider: Some(ctx.parser.provider().to_string()),
+                payload: Payload::StreamAborted(StreamAborted {
+                    response_id: ctx.event_id,
+                    received_bytes: received as u64,
+                    elapsed_ms: elapsed.as_millis() as u64,
+                }),
+            })
+            .await;
+    }
+
+    info!(
+        "← Streaming response complete ({} bytes, {}) text={:?}",
+        received,
+        ctx.parser.provider(),
+        text_preview
+    );
+}
+
+/// Bytes kept past the capture cap, so a secret straddling it is still whole
+/// when redacted
+const RAW_SLACK: usize = 4096;
+
+/// Follows a streaming response as it arrives: parses each complete line,
+/// notes when content first appeared, and keeps the start of the raw stream
+struct StreamTracker<'a> {
+    parser: Box<dyn StreamParser>,
+    /// Routes to pick the parser from the first data line with, when the
+    /// request didn't say which provider it was for
+    detect: Option<&'a Routes>,
+    /// The parser picked that way
+    detected: Option<Arc<dyn ResponseParser>>,
+    /// An incomplete last line
+    partial: Vec<u8>,
+    kept: Vec<u8>,
+    keep: usize,
+    received: usize,
+    /// When the first chunk carrying text, thinking, or a tool call arrived
+    first_token: Option<std::time::Duration>,
+}
+
+impl<'a> StreamTracker<'a> {
+    fn new(parser: Arc<dyn ResponseParser>, detect: Option<&'a Routes>, keep: usize) -> Self {
+        Self {
+            parser: parser.stream_parser(),
+            detect,
+            detected: None,
+            partial: Vec::new(),
+            kept: Vec::new(),
+            keep,
+            received: 0,
+            first_token: None,
+        }
+    }
+
+    /// Take in a chunk; returns what its complete lines added to the response
+    fn push(&mut self, chunk: &[u8], at: std::time::Duration) -> Vec<Delta> {
+        self.received += chunk.len();
+        let room = self.keep.saturating_sub(self.kept.len());
+        self.kept.extend_from_slice(&chunk[..chunk.len().min(room)]);
+
+        let mut deltas = Vec::new();
+        let mut rest = chunk;
+        while let Some(end) = rest.iter().position(|&b| b == b'\n') {
+            self.partial.extend_from_slice(&rest[..end]);
+            rest = &rest[end + 1..];
+            let line = std::mem::take(&mut self.partial);
+            self.push_line(&line, &mut deltas);
+        }
+        self.partial.extend_from_slice(rest);
+
+        if self.first_token.is_none() && !deltas.is_empty() {
+            self.first_token = Some(at);
+        }
+        deltas
+    }
+
+    fn push_line(&mut self, line: &[u8], deltas: &mut Vec<Delta>) {
+        let line = String::from_utf8_lossy(line);
+        let line = line.strip_suffix('\r').unwrap_or(&line);
+        if let Some(routes) = self.detect {
+            let data = line
+                .strip_prefix("data:")
+                .and_then(|data| serde_json::from_str::<serde_json::Value>(data.trim()).ok());
+            if data.is_some() {
+                self.detect = None;
+                if let Some(parser) = routes.parser_for_stream(line) {
+                    self.parser = parser.stream_parser();
+                    self.detected = Some(parser);
+                }
+            }
+        }
+        self.parser.push_line(line, deltas);
+    }
+
+    fn finish(mut self) -> ParsedResponse {
+        if !self.partial.is_empty() {
+            let line = std::mem::take(&mut self.partial);
+            self.push_line(&line, &mut Vec::new());
+        }
+        self.parser.finish()
+    }
+}
+
+/// Send dashboards what a streaming response added, if any are listening
+fn publish_deltas(state: &ProxyState, ctx: &ResponseContext, deltas: Vec<Delta>) {
+    // A secret split across two deltas couldn't be masked, and sealed
+    // content shouldn't go out as it streams
+    if state.deltas.receiver_count() == 0
+        || state.redactor.is_active()
+        || state.cipher.is_active()
+    {
+        return;
+    }
+    for delta in deltas {
+        let _ = state.deltas.send(ObservabilityEvent {
+            seq: None,
+            id: Uuid::new_v4(),
+            timestamp: chrono::Utc::now(),
+            session_id: ctx.session_id.clone(),
+            agent: ctx.agent_name.clone(),
+            topic: ctx.agent.as_ref().and_then(|a| a.topic.clone()),
+            provider: Some(ctx.parser.provider().to_string()),
+            payload: Payload::StreamDelta(StreamDelta {
+                response_id: ctx.event_id,
+                delta,
+            }),
+        });
+    }
+}
+
+enum JournalWrite {
+    Chunk(Bytes),
+    Finish,
+}
+
+/// Appends a streamed response to its resume journal, if it has one. The
+/// inserts run in order on a task of their own, so forwarding a chunk never
+/// waits on the database.
+struct JournalWriter {
+    tx: Option<tokio::sync::mpsc::UnboundedSender<JournalWrite>>,
+}
+
+impl JournalWriter {
+    fn new(state: &ProxyState, key: Option<&str>) -> Self {
+        let Some(key) = key else {
+            return Self { tx: None };
+        };
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        let journal = state.journal.clone();
+        let key = key.to_string();
+        tokio::spawn(async move {
+            let mut seq = 0;
+            while let Some(write) = rx.recv().await {
+                match write {
+                    JournalWrite::Chunk(chunk) => {
+                        if let Err(e) = journal.append(&key, seq, &chunk).await {
+                            warn!("Failed to journal stream {}: {}", key, e);
+                        }
+                        seq += 1;
+                    }
+                    JournalWrite::Finish => {
+                        if let Err(e) = journal.finish(&key).await {
+                            warn!("Failed to finish stream journal {}: {}", key, e);
+                        }
+                    }
+                }
+            }
+        });
+        Self { tx: Some(tx) }
+    }
+
+    fn is_active(&self) -> bool {
+        self.tx.is_some()
+    }
+
+    fn append(&self, chunk: &Bytes) {
+        if let Some(ref tx) = self.tx {
+            let _ = tx.send(JournalWrite::Chunk(chunk.clone()));
+        }
+    }
+
+    /// Mark the journal complete once everything appended is written
+    fn finish(self) {
+        if let Some(ref tx) = self.tx {
+            let _ = tx.send(JournalWrite::Finish);
+        }
+    }
+}
+
 async fn handle_regular_response(
     state: Arc<ProxyState>,
     response: reqwest::Response,
     status: reqwest::StatusCode,
     response_headers: reqwest::header::HeaderMap,
-    is_telemetry: bool,
-    claude_session_id: Option<String>,
-    agent_name: Option<String>,
-    agent: Option<Agent>,
+    mut ctx: ResponseContext,
 ) -> Result<Response<Body>, StatusCode> {
     let response_bytes = match response.bytes().await {
         Ok(bytes) => bytes,
         Err(e) => {
             warn!("Failed to read response: {}", e);
+            if !ctx.is_telemetry {
+                ctx.publish_error(
+                    &state,
+                    ErrorEvent {
+                        kind: ErrorKind::Interrupted,
+                        cause: None,
+                        status: Some(status.as_u16()),
+                        message: format!("response broke off: {}", error_chain(&e)),
+                        body: None,
+                    },
+                )
+                .await;
+            }
             return Err(StatusCode::BAD_GATEWAY);
         }
     };
 
+    // Build response
+    let mut builder = Response::builder().status(status.as_u16());
+    for (name, value) in response_headers.iter() {
+        builder = builder.header(name, value);
+    }
+    for (name, value) in &ctx.correlation {
+        builder = builder.header(*name, value);
+    }
+    let client_response = builder.body(Body::from(response_bytes.clone())).map_err(|e| {
+        warn!("Failed to build response: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    });
+
+    let level = ctx.degradation;
+    let blocking = !ctx.is_telemetry && state.blocks(ctx.agent_name.as_deref());
+    match state.deferral(level, blocking) {
+        // Recorded after the client has it, so nothing is withheld
+        Some(_) => {
+            ctx.finish();
+            let key = ctx.record_key();
+            let recorder = state.clone();
+            let job = async move {
+                record_regular_response(
+                    &recorder,
+                    status,
+                    &response_headers,
+                    &response_bytes,
+                    &mut ctx,
+                )
+                .await;
+            };
+            state
+                .record(key.as_deref(), level, Default::default(), job)
+                .await;
+        }
+        None => {
+            let start = std::time::Instant::now();
+            let blocked = record_regular_response(
+                &state,
+                status,
+                &response_headers,
+                &response_bytes,
+                &mut ctx,
+            )
+            .await;
+            state.record_overhead(start.elapsed(), level).await;
+            if let Some(blocked) = blocked {
+                warn!("✋ Withholding response: {}", blocked.message);
+                return error_response(
+                    StatusCode::FORBIDDEN,
+                    "permission_error",
+                    &format!("sentinel: {} ({})", blocked.message, blocked.source),
+                );
+            }
+        }
+    }
+    client_response
+}
+
+/// Record a complete response and journal it, returning the violation that
+/// blocks it, if any
+async fn record_regular_response(
+    state: &ProxyState,
+    status: reqwest::StatusCode,
+    response_headers: &reqwest::header::HeaderMap,
+    response_bytes: &Bytes,
+    ctx: &mut ResponseContext,
+) -> Option<Violation> {
     let response_json: serde_json::Value =
-        serde_json::from_slice(&response_bytes).unwrap_or_default();
+        serde_json::from_slice(response_bytes).unwrap_or_default();
+
+    if !ctx.parser_confident {
+        if let Some(parser) = state.routes.parser_for_response(&response_json) {
+            ctx.parser = parser;
+        }
+    }
+
+    if !ctx.is_telemetry {
+        ctx.mirror(state, status, response_headers, response_bytes)
+            .await;
 
-    if !is_telemetry {
         // Parse the response if it looks like an LLM response
-        let parsed =
-            if response_json.get("content").is_some() || response_json.get("type").is_some() {
-                Some(state.parser.parse_json(&response_json))
-            } else {
-                None
-            };
+        let parsed = if ctx.parser.is_response(&response_json) {
+            Some(ctx.apply_model_override(ctx.parser.parse_json(&response_json)))
+        } else {
+            None
+        };
+
+        if parsed.is_none() && (status.is_client_error() || status.is_server_error()) {
+            ctx.publish_error(state, upstream_error(status, &response_json, response_bytes))
+                .await;
+        }
 
         if let Some(parsed) = parsed {
-            store_and_broadcast_response_event(
-                parsed, &agent, &state.agent_store, &state.storage, &state.event_broadcaster,
-                claude_session_id, agent_name,
-            ).await;
+            let raw = if ctx.degradation.keeps_raw() {
+                state.capture.capture(&state.redactor.bytes(response_bytes))
+            } else {
+                RawCapture::skipped(response_bytes.len())
+            };
+            let blocked = store_and_broadcast_response_event(state, parsed, raw, ctx).await;
+            if blocked.is_some() {
+                return blocked;
+            }
         }
 
         info!("← {} ({} bytes)", status, response_bytes.len());
     }
 
-    // Build response
-    let mut builder = Response::builder().status(status.as_u16());
-    for (name, value) in response_headers.iter() {
-        builder = builder.header(name, value);
+    if status.is_success() {
+        let journal = JournalWriter::new(state, ctx.journal_key.as_deref());
+        journal.append(response_bytes);
+        journal.finish();
     }
+    None
+}
 
-    builder.body(Body::from(response_bytes)).map_err(|e| {
-        warn!("Failed to build response: {}", e);
-        StatusCode::INTERNAL_SERVER_ERROR
-    })
+/// The error event for a 4xx or 5xx answer, with the upstream's own message
+/// when its body has one
+fn upstream_error(status: reqwest::StatusCode, json: &serde_json::Value, body: &[u8]) -> ErrorEvent {
+    let message = json["error"]["message"]
+        .as_str()
+        .or(json["error"].as_str())
+        .or(json["message"].as_str())
+        .map(str::to_string)
+        .unwrap_or_else(|| status.to_string());
+    let body = String::from_utf8_lossy(&body[..body.len().min(MAX_ERROR_BODY)]).into_owned();
+    ErrorEvent {
+        kind: ErrorKind::Upstream,
+        cause: None,
+        status: Some(status.as_u16()),
+        message,
+        body: (!body.is_empty()).then_some(body),
+    }
 }
 
+/// Store the response and any violations it caused, returning the first
+/// violation that blocks it
 async fn store_and_broadcast_response_event(
+    state: &ProxyState,
     parsed: ParsedResponse,
-    agent: &Option<Agent>,
-    agent_store: &AgentStore,
-    storage: &Storage,
-    event_broadcaster: &tokio::sync::broadcast::Sender<ObservabilityEvent>,
-    session_id: Option<String>,
-    agent_name: Option<String>,
-) {
-    // Resolve topic: update agent if new, otherwise use agent's current topic
+    mut raw: RawCapture,
+    ctx: &ResponseContext,
+) -> Option<Violation> {
+    // Resolve topic: update agent if new, otherwise use agent's current topic.
+    // Sealed once here under --encrypt-fields, so the agent and its events
+    // share one ciphertext
     let topic = if let Some(new_topic) = &parsed.topic {
-        if let Some(ref agent) = agent {
-            if let Err(e) = agent_store.update_topic(&agent.id, new_topic).await {
+        let new_topic = state.cipher.topic(new_topic);
+        if let Some(ref agent) = ctx.agent {
+            if let Err(e) = state.agent_store.update_topic(&agent.id, &new_topic).await {
                 tracing::error!("Failed to update agent topic: {}", e);
             }
         }
-        Some(new_topic.clone())
+        Some(new_topic)
     } else {
-        agent.as_ref().and_then(|a| a.topic.clone())
+        ctx.agent.as_ref().and_then(|a| a.topic.clone())
     };
 
+    let provider = ctx.parser.provider();
+    for message in state
+        .compat
+        .check(provider, ctx.api_version.as_deref(), &parsed.unrecognized)
+    {
+        warn!("⚠ {}", message);
+        state
+            .publish(ObservabilityEvent {
+                seq: None,
+                id: Uuid::new_v4(),
+                timestamp: chrono::Utc::now(),
+                session_id: ctx.session_id.clone(),
+                agent: ctx.agent_name.clone(),
+                topic: topic.clone(),
+                provider: Some(provider.to_string()),
+                payload: Payload::Alert(Alert {
+                    kind: COMPAT_ALERT_KIND.to_string(),
+                    message,
+                }),
+            })
+            .await;
+    }
+
     if parsed.is_topic_event {
-        return;
+        return None;
+    }
+
+    let mut response = AssistantResponse::from(parsed);
+    response.idempotency_key = ctx.idempotency_key.clone();
+    response.request_id = ctx.request_event_id;
+    response.redo_of = ctx.redo_of;
+    response.upstream_key = ctx.upstream_key.clone();
+    response.project = ctx.attribution.project.clone();
+    response.run_id = ctx.attribution.run_id.clone();
+    response.first_byte_ms = Some(ctx.first_byte.as_millis() as u64);
+    response.first_token_ms = ctx.first_token.map(|d| d.as_millis() as u64);
+    let (ended, latency) = ctx.ended();
+    response.latency_ms = Some(latency.as_millis() as u64);
+
+    if let Some(ref name) = ctx.upstream_key {
+        let cost = response
+            .usage
+            .as_ref()
+            .and_then(|u| pricing::cost_usd(response.model.as_deref(), u));
+        if let Some(message) = state.keys.record(name, cost.unwrap_or(0.0)) {
+            warn!("⚠ {}", message);
+            state
+                .publish(ObservabilityEvent {
+                    seq: None,
+                    id: Uuid::new_v4(),
+                    timestamp: chrono::Utc::now(),
+                    session_id: ctx.session_id.clone(),
+                    agent: ctx.agent_name.clone(),
+                    topic: topic.clone(),
+                    provider: Some(provider.to_string()),
+                    payload: Payload::Alert(Alert {
+                        kind: "key_budget_spent".to_string(),
+                        message,
+                    }),
+                })
+                .await;
+        }
+    }
+    if let (false, Some(usage)) = (state.budgets.is_empty(), response.usage.as_ref()) {
+        let spend = Spend::of(response.model.as_deref(), usage);
+        for message in
+            state
+                .budgets
+                .record(ctx.agent_name.as_deref(), ctx.session_id.as_deref(), spend)
+        {
+            warn!("⚠ {}", message);
+            state
+                .publish(ObservabilityEvent {
+                    seq: None,
+                    id: Uuid::new_v4(),
+                    timestamp: chrono::Utc::now(),
+                    session_id: ctx.session_id.clone(),
+                    agent: ctx.agent_name.clone(),
+                    topic: topic.clone(),
+                    provider: Some(provider.to_string()),
+                    payload: Payload::Alert(Alert {
+                        kind: BUDGET_ALERT_KIND.to_string(),
+                        message,
+                    }),
+                })
+                .await;
+        }
+    }
+
+    let working_dir = ctx.agent.as_ref().and_then(|a| a.working_directory.as_deref());
+    // Streamed calls were checked as they came in
+    let mut violations = if state.baselines.is_empty() || response.streaming {
+        Vec::new()
+    } else {
+        state
+            .baselines
+            .check(ctx.agent_name.as_deref(), working_dir, &response.tool_calls)
+    };
+    if let (Some(policy), false) = (&state.policy, response.streaming) {
+        for call in &response.tool_calls {
+            violations.extend(policy.check(call, working_dir));
+        }
+    }
+    if let Some(ref contract) = ctx.contract {
+        violations.extend(contract.check(&response));
+    }
+
+    if let (Some(watch), Some(name), Some(messages)) =
+        (&state.waiting, &ctx.agent_name, ctx.request_messages)
+    {
+        watch.response(name, topic.clone(), messages, &response);
     }
 
-    let event = ObservabilityEvent {
+    let mut event = ObservabilityEvent {
         seq: None,
-        id: Uuid::new_v4(),
-        timestamp: chrono::Utc::now(),
-        session_id,
-        agent: agent_name,
-        topic,
-        payload: Payload::AssistantResponse(parsed.into()),
+        id: ctx.event_id,
+        timestamp: ended,
+        session_id: ctx.session_id.clone(),
+        agent: ctx.agent_name.clone(),
+        topic: topic.clone(),
+        provider: Some(ctx.parser.provider().to_string()),
+        payload: Payload::AssistantResponse(response),
     };
+    state.redactor.event(&mut event);
+    state.cipher.event(&mut event);
+    state.cipher.raw(&mut raw);
 
-    if let Err(e) = storage.insert_observability_event(&event).await {
-        tracing::error!("Failed to store response event: {}", e);
+    match state
+        .storage
+        .insert_observability_event_with_raw(&event, Some(&raw))
+        .await
+    {
+        Ok(seq) => {
+            event.seq = Some(seq);
+            let _ = state.event_broadcaster.send(event);
+        }
+        Err(e) => tracing::error!("Failed to store response event: {}", e),
+    }
+
+    let blocked = violations.iter().find(|v| v.blocked).cloned();
+    publish_violations(state, ctx, topic, violations).await;
+    blocked
+}
+
+/// Record violations, raising an alert for policy ones with --policy-alert
+async fn publish_violations(
+    state: &ProxyState,
+    ctx: &ResponseContext,
+    topic: Option<String>,
+    violations: Vec<Violation>,
+) {
+    let alert = state.policy.as_ref().is_some_and(|p| p.alert);
+    for violation in violations {
+        warn!(
+            "⚠ {} [{}]: {}{}",
+            violation.source,
+            ctx.agent_name.as_deref().unwrap_or("unknown agent"),
+            violation.message,
+            if violation.blocked { " (blocking)" } else { "" }
+        );
+        let event = |payload| ObservabilityEvent {
+            seq: None,
+            id: Uuid::new_v4(),
+            timestamp: chrono::Utc::now(),
+            session_id: ctx.session_id.clone(),
+            agent: ctx.agent_name.clone(),
+            topic: topic.clone(),
+            provider: Some(ctx.parser.provider().to_string()),
+            payload,
+        };
+        let alert = (alert && violation.source.starts_with("policy:")).then(|| {
+            Payload::Alert(Alert {
+                kind: POLICY_ALERT_KIND.to_string(),
+                message: format!("{}: {}", violation.source, violation.message),
+            })
+        });
+        state.publish(event(Payload::Violation(violation))).await;
+        if let Some(alert) = alert {
+            state.publish(event(alert)).await;
+        }
     }
+}
 
-    let _ = event_broadcaster.send(event);
+/// The stream event a response cut off at a blocked tool call ends with
+fn policy_error_event(violation: &Violation) -> String {
+    let error = serde_json::json!({
+        "type": "error",
+        "error": {
+            "type": "permission_error",
+            "message": format!("sentinel: {} ({})", violation.message, violation.source),
+        },
+    });
+    format!("event: error\ndata: {}\n\n", error)
 }
 
 fn extract_working_directory(request: &Option<AnthropicRequest>) -> Option<String> {
@@ -394,3 +2337,300 @@ fn extract_claude_session_id(request: &Option<AnthropicRequest>) -> Option<Strin
     let (_, session) = user_id.rsplit_once("_session_")?;
     if session.is_empty() { None } else { Some(session.to_string()) }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parsers::AnthropicParser;
+    use std::time::Duration;
+
+    #[test]
+    fn test_upstream_error_keeps_status_message_and_body() {
+        let body = br#"{"type":"error","error":{"type":"overloaded_error","message":"Overloaded"}}"#;
+        let json = serde_json::from_slice(body).unwrap();
+        let error = upstream_error(reqwest::StatusCode::from_u16(529).unwrap(), &json, body);
+        assert!(matches!(error.kind, ErrorKind::Upstream));
+        assert_eq!(error.status, Some(529));
+        assert_eq!(error.message, "Overloaded");
+        assert_eq!(error.body.as_deref().map(str::as_bytes), Some(&body[..]));
+
+        // No JSON to read a message from
+        let error = upstream_error(
+            reqwest::StatusCode::BAD_GATEWAY,
+            &serde_json::Value::Null,
+            b"<html>bad gateway</html>",
+        );
+        assert_eq!(error.message, "502 Bad Gateway");
+        assert_eq!(error.body.as_deref(), Some("<html>bad gateway</html>"));
+    }
+
+    #[test]
+    fn test_first_token_is_first_chunk_with_content() {
+        let chunks = [
+            "event: message_start\ndata: {\"type\":\"message_start\",\"message\":{\"model\":\"claude-3\",\"id\":\"msg_1\"}}\n\n",
+            "event: content_block_start\ndata: {\"type\":\"content_block_start\",\"index\":0,\"content_block\":{\"type\":\"text\",\"text\":\"\"}}\n\n",
+            "event: content_block_delta\ndata: {\"type\":\"content_block_delta\",\"index\":0,\"delta\":{\"type\":\"text_delta\",\"text\":\"Hi\"}}\n\n",
+            "event: message_stop\ndata: {\"type\":\"message_stop\"}\n\n",
+        ];
+        let parser: Arc<dyn ResponseParser> = Arc::new(AnthropicParser::new());
+        let mut tracker = StreamTracker::new(parser, None, 64);
+        for (i, chunk) in chunks.iter().enumerate().take(2) {
+            tracker.push(chunk.as_bytes(), Duration::from_millis(100 * (i as u64 + 1)));
+        }
+        assert_eq!(tracker.first_token, None);
+
+        // Split mid-line, so the text only counts once its line is complete
+        let (start, end) = chunks[2].split_at(40);
+        assert!(tracker
+            .push(start.as_bytes(), Duration::from_millis(250))
+            .is_empty());
+        assert_eq!(
+            tracker.push(end.as_bytes(), Duration::from_millis(300)),
+            vec![Delta::Text {
+                text: "Hi".to_string()
+            }]
+        );
+        tracker.push(chunks[3].as_bytes(), Duration::from_millis(400));
+        assert_eq!(tracker.first_token, Some(Duration::from_millis(300)));
+
+        assert_eq!(tracker.kept.len(), 64);
+        assert_eq!(tracker.received, chunks.iter().map(|c| c.len()).sum::<usize>());
+        let parsed = tracker.finish();
+        assert_eq!(parsed.text.as_deref(), Some("Hi"));
+        assert_eq!(parsed.metadata.model.as_deref(), Some("claude-3"));
+    }
+
+    #[test]
+    fn test_stop_message_is_a_well_formed_turn() {
+        let text = "sentinel: over budget";
+        let (content_type, body) = stop_message_body("claude-sonnet-4", false, text);
+        assert_eq!(content_type, "application/json");
+        let json: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(json["content"][0]["text"], text);
+        assert_eq!(json["stop_reason"], "end_turn");
+
+        let (content_type, body) = stop_message_body("claude-sonnet-4", true, text);
+        assert_eq!(content_type, "text/event-stream");
+        let parser: Arc<dyn ResponseParser> = Arc::new(AnthropicParser::new());
+        let mut tracker = StreamTracker::new(parser, None, 0);
+        tracker.push(body.as_bytes(), Duration::ZERO);
+        let parsed = tracker.finish();
+        assert_eq!(parsed.text.as_deref(), Some(text));
+        assert_eq!(parsed.metadata.model.as_deref(), Some("claude-sonnet-4"));
+        assert_eq!(parsed.metadata.stop_reason.as_deref(), Some("end_turn"));
+    }
+
+    #[tokio::test]
+    async fn test_stop_message_is_recorded_as_the_response() {
+        let state = ProxyState::for_tests().await;
+        let event = ObservabilityEvent::for_tests(
+            0,
+            Payload::AssistantResponse(AssistantResponse {
+                model: Some("claude-sonnet-4".to_string()),
+                stop_reason: Some("end_turn".to_string()),
+                text: Some("sentinel: over budget".to_string()),
+                ..Default::default()
+            }),
+        );
+        let id = event.id;
+        let response = stop_message_response(&state, event, true).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let stored = state.storage.get_event(id).await.unwrap().unwrap();
+        let Payload::AssistantResponse(stored) = stored.payload else {
+            panic!("recorded as {:?}", stored.payload);
+        };
+        assert_eq!(stored.text.as_deref(), Some("sentinel: over budget"));
+    }
+
+    #[test]
+    fn test_streamed_call_outside_the_sandbox_is_caught_before_it_completes() {
+        let path = std::env::temp_dir().join(format!("sentinel-test-{}.yaml", Uuid::new_v4()));
+        std::fs::write(&path, "block: true\nscopes:\n  - paths: ['src/**']\n").unwrap();
+        let baselines = BaselineMonitor::new(crate::sandbox::load(&path).unwrap());
+        std::fs::remove_file(&path).unwrap();
+
+        let chunks = [
+            "event: content_block_start\ndata: {\"type\":\"content_block_start\",\"index\":0,\"content_block\":{\"type\":\"tool_use\",\"id\":\"toolu_1\",\"name\":\"Write\",\"input\":{}}}\n\n",
+            "event: content_block_delta\ndata: {\"type\":\"content_block_delta\",\"index\":0,\"delta\":{\"type\":\"input_json_delta\",\"partial_json\":\"{\\\"file_path\\\": \\\"/repo/.git/\"}}\n\n",
+            "event: content_block_delta\ndata: {\"type\":\"content_block_delta\",\"index\":0,\"delta\":{\"type\":\"input_json_delta\",\"partial_json\":\"config\\\", \\\"content\\\": \\\"\\\"}\"}}\n\n",
+        ];
+        let parser: Arc<dyn ResponseParser> = Arc::new(AnthropicParser::new());
+        let mut tracker = StreamTracker::new(parser, None, 0);
+        let mut calls = StreamedCalls::default();
+        let mut check = |chunk: &str| {
+            let deltas = tracker.push(chunk.as_bytes(), Duration::ZERO);
+            check_streamed(&baselines, None, &mut calls, &deltas, Some("calm-otter"), Some("/repo"))
+        };
+        assert!(check(chunks[0]).is_empty());
+        assert!(check(chunks[1]).is_empty());
+        // Caught on the chunk that completes the call, which is held back
+        let violations = check(chunks[2]);
+        assert_eq!(violations.len(), 1);
+        assert!(violations[0].blocked);
+        assert!(violations[0].message.contains("/repo/.git/config"));
+        assert!(policy_error_event(&violations[0]).starts_with("event: error\n"));
+    }
+
+    #[tokio::test]
+    async fn test_body_over_the_cap_keeps_its_start_and_the_rest() {
+        let chunks: Vec<Result<&'static [u8], std::io::Error>> =
+            vec![Ok(b"{\"model\":"), Ok(b"\"claude\","), Ok(b"\"x\":1}")];
+        let body = Body::from_stream(futures::stream::iter(chunks));
+        let Ok(RequestBody::Partial(prefix, rest)) = read_body(body, 12).await else {
+            panic!("body over the cap was read whole");
+        };
+        assert_eq!(&prefix[..], b"{\"model\":\"claude\",");
+        let whole = read_rest(prefix, rest, 64).await.unwrap().unwrap();
+        assert_eq!(&whole[..], b"{\"model\":\"claude\",\"x\":1}");
+
+        // Read whole only up to the hard cap
+        let chunks: Vec<Result<&'static [u8], std::io::Error>> =
+            vec![Ok(b"{\"model\":"), Ok(b"\"claude\","), Ok(b"\"x\":1}")];
+        let body = Body::from_stream(futures::stream::iter(chunks));
+        let Ok(RequestBody::Partial(prefix, rest)) = read_body(body, 12).await else {
+            panic!("body over the cap was read whole");
+        };
+        assert!(read_rest(prefix, rest, 20).await.unwrap().is_none());
+
+        let body = Body::from("{}");
+        assert!(matches!(
+            read_body(body, 12).await,
+            Ok(RequestBody::Whole(ref bytes)) if &bytes[..] == b"{}"
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_journaled_stream_is_read_to_its_end_after_the_client_hangs_up() {
+        // An upstream that holds the rest of its stream until told to go on
+        let (go_on, held) = tokio::sync::oneshot::channel::<()>();
+        let held = Arc::new(tokio::sync::Mutex::new(Some(held)));
+        let upstream = axum::Router::new().route(
+            "/v1/messages",
+            axum::routing::post(move || {
+                let held = held.clone();
+                async move {
+                    let held = held.lock().await.take();
+                    let chunks = futures::stream::once(async {
+                        Ok::<_, std::io::Error>("event: ping\ndata: {}\n\n")
+                    })
+                    .chain(futures::stream::once(async move {
+                        if let Some(held) = held {
+                            let _ = held.await;
+                        }
+                        Ok("event: message_stop\ndata: {\"type\":\"message_stop\"}\n\n")
+                    }));
+                    Response::builder()
+                        .header("content-type", "text/event-stream")
+                        .body(Body::from_stream(chunks))
+                        .unwrap()
+                }
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind(("127.0.0.1", 0)).await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, upstream).await });
+
+        let mut state = ProxyState::for_tests().await;
+        Arc::get_mut(&mut state).unwrap().routes = Arc::new(Routes::new().with_default_url(&url));
+        let request = Request::post("/v1/messages")
+            .header(IDEMPOTENCY_HEADER, "turn-1")
+            .header("content-type", "application/json")
+            .body(Body::from(
+                serde_json::json!({
+                    "model": "claude-sonnet-4",
+                    "stream": true,
+                    "messages": [{"role": "user", "content": "Hi"}],
+                })
+                .to_string(),
+            ))
+            .unwrap();
+        let response = proxy_handler(State(state.clone()), request).await.unwrap();
+        let mut body = response.into_body().into_data_stream();
+        let first = body.next().await.unwrap().unwrap();
+        assert!(first.starts_with(b"event: ping"));
+        drop(body);
+        go_on.send(()).unwrap();
+
+        let journal = tokio::time::timeout(Duration::from_secs(5), async {
+            loop {
+                match state.journal.load("turn-1").await.unwrap() {
+                    Some(journal) if journal.complete => return journal,
+                    _ => tokio::time::sleep(Duration::from_millis(20)).await,
+                }
+            }
+        })
+        .await
+        .expect("journal never completed");
+        assert!(String::from_utf8_lossy(&journal.data).contains("message_stop"));
+    }
+
+    #[tokio::test]
+    async fn test_cached_gets_keep_our_headers_from_the_upstream() {
+        // An upstream that answers with the names of the headers it got
+        let upstream = axum::Router::new().route(
+            "/v1/models",
+            axum::routing::get(|headers: axum::http::HeaderMap| async move {
+                let names: Vec<String> = headers.keys().map(|n| n.to_string()).collect();
+                axum::Json(names)
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind(("127.0.0.1", 0)).await.unwrap();
+        let url = format!("http://{}/v1/models", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, upstream).await });
+
+        let state = ProxyState::for_tests().await;
+        let mut headers = axum::http::HeaderMap::new();
+        headers.insert("x-api-key", "sk-test".parse().unwrap());
+        headers.insert(crate::agent::AGENT_NAME_HEADER, "calm-otter".parse().unwrap());
+        headers.insert(crate::attribution::PROJECT_HEADER, "billing".parse().unwrap());
+        headers.insert(IDEMPOTENCY_HEADER, "turn-1".parse().unwrap());
+        headers.insert(RESUME_HEADER, "turn-1".parse().unwrap());
+        let response = serve_static(&state, "models".to_string(), &url, &headers, "anthropic")
+            .await
+            .unwrap();
+        let body = axum::body::to_bytes(response.into_body(), 1024 * 1024).await.unwrap();
+        let names: Vec<String> = serde_json::from_slice(&body).unwrap();
+        assert!(names.contains(&"x-api-key".to_string()));
+        assert!(!names.iter().any(|n| n.starts_with("x-sentinel-")));
+        assert!(!names.contains(&IDEMPOTENCY_HEADER.to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_clients_name_their_agents_under_any_naming() {
+        let upstream = axum::Router::new().route(
+            "/v1/messages",
+            axum::routing::post(|| async {
+                axum::Json(serde_json::json!({
+                    "id": "msg_1",
+                    "type": "message",
+                    "role": "assistant",
+                    "model": "claude-sonnet-4",
+                    "content": [{"type": "text", "text": "Hi"}],
+                    "stop_reason": "end_turn",
+                    "usage": {"input_tokens": 1, "output_tokens": 1}
+                }))
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind(("127.0.0.1", 0)).await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, upstream).await });
+
+        let mut state = ProxyState::for_tests().await;
+        Arc::get_mut(&mut state).unwrap().routes = Arc::new(Routes::new().with_default_url(&url));
+        let request = Request::post("/v1/messages")
+            .header(AGENT_NAME_HEADER, "nightly-reviewer")
+            .header("content-type", "application/json")
+            .body(Body::from(
+                serde_json::json!({
+                    "model": "claude-sonnet-4",
+                    "messages": [{"role": "user", "content": "Hi"}],
+                })
+                .to_string(),
+            ))
+            .unwrap();
+        let response = proxy_handler(State(state.clone()), request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let agent = state.agent_store.find_by_name("nightly-reviewer").await.unwrap();
+        assert!(agent.is_some());
+    }
+}
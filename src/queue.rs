@@ -0,0 +1,251 @@
+//! Recording off the response path (`--hot-path`).
+//!
+//! Normally a response is parsed and stored before the proxy lets go of it.
+//! With `--hot-path`, the client gets each response as soon as the upstream
+//! finishes it, and the parsing and storing is queued here for a pool of
+//! workers. Each worker drains its own shard, and work is sharded by session
+//! (or agent), so one session's events are still recorded in order. A
+//! non-streamed response that a blocking baseline or `--policy-enforce` might
+//! withhold is recorded before it goes out all the same.
+//!
+//! A full queue either holds the next request until there is room (`block`,
+//! the default) or drops what it would have recorded (`drop`). How far
+//! recording lags behind, and anything dropped, is served at `/api/queue`.
+
+use std::collections::hash_map::DefaultHasher;
+use std::future::Future;
+use std::hash::{Hash, Hasher};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Instant;
+
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::Json;
+use clap::ValueEnum;
+use schemars::JsonSchema;
+use serde::Serialize;
+use serde_json::{json, Value};
+use tokio::sync::mpsc;
+
+use crate::proxy::ProxyState;
+
+pub const DEFAULT_WORKERS: usize = 4;
+pub const DEFAULT_CAPACITY: usize = 10_000;
+/// Dropped recordings are warned about on the first and then every this many
+const DROP_WARN_EVERY: u64 = 1000;
+
+/// Recording work for one exchange
+pub type Job = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+/// What happens to recording work when the queue is full
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Overflow {
+    /// Wait for room, slowing the proxy down to what recording keeps up with
+    #[default]
+    Block,
+    /// Drop the recording, keeping the proxy fast and the record incomplete
+    Drop,
+}
+
+struct Queued {
+    at: Instant,
+    job: Job,
+}
+
+#[derive(Default)]
+struct Counters {
+    enqueued: AtomicU64,
+    processed: AtomicU64,
+    dropped: AtomicU64,
+    lag_last_ms: AtomicU64,
+    lag_max_ms: AtomicU64,
+    lag_total_ms: AtomicU64,
+}
+
+pub struct RecordQueue {
+    shards: Vec<mpsc::Sender<Queued>>,
+    capacity: usize,
+    overflow: Overflow,
+    counters: Arc<Counters>,
+}
+
+/// How recording is keeping up, for `/api/queue`
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct QueueStats {
+    pub workers: usize,
+    pub capacity: usize,
+    pub overflow: Overflow,
+    /// Recordings waiting for a worker
+    pub depth: usize,
+    pub enqueued: u64,
+    pub processed: u64,
+    pub dropped: u64,
+    /// How long recordings waited for a worker
+    pub lag_ms: QueueLag,
+}
+
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct QueueLag {
+    pub last: u64,
+    pub max: u64,
+    pub mean: f64,
+}
+
+impl RecordQueue {
+    /// Spawn the workers; `capacity` is split between their shards
+    pub fn start(workers: usize, capacity: usize, overflow: Overflow) -> Self {
+        let workers = workers.max(1);
+        let per_shard = (capacity / workers).max(1);
+        let counters = Arc::new(Counters::default());
+        let shards = (0..workers)
+            .map(|_| {
+                let (tx, mut rx) = mpsc::channel::<Queued>(per_shard);
+                let counters = counters.clone();
+                tokio::spawn(async move {
+                    while let Some(queued) = rx.recv().await {
+                        let lag = queued.at.elapsed().as_millis() as u64;
+                        counters.lag_last_ms.store(lag, Ordering::Relaxed);
+                        counters.lag_max_ms.fetch_max(lag, Ordering::Relaxed);
+                        counters.lag_total_ms.fetch_add(lag, Ordering::Relaxed);
+                        queued.job.await;
+                        counters.processed.fetch_add(1, Ordering::Relaxed);
+                    }
+                });
+                tx
+            })
+            .collect();
+        Self {
+            shards,
+            capacity: per_shard * workers,
+            overflow,
+            counters,
+        }
+    }
+
+    /// Queue recording work; work pushed under the same key runs in order
+    pub async fn push(&self, key: Option<&str>, job: Job) {
+        let shard = &self.shards[self.shard(key)];
+        let queued = Queued {
+            at: Instant::now(),
+            job,
+        };
+        let sent = match self.overflow {
+            Overflow::Block => shard.send(queued).await.is_ok(),
+            Overflow::Drop => shard.try_send(queued).is_ok(),
+        };
+        if sent {
+            self.counters.enqueued.fetch_add(1, Ordering::Relaxed);
+            return;
+        }
+        let dropped = self.counters.dropped.fetch_add(1, Ordering::Relaxed);
+        if dropped.is_multiple_of(DROP_WARN_EVERY) {
+            tracing::warn!(
+                "Record queue full; dropped {} recording(s) so far",
+                dropped + 1
+            );
+        }
+    }
+
+    fn shard(&self, key: Option<&str>) -> usize {
+        let mut hasher = DefaultHasher::new();
+        key.hash(&mut hasher);
+        (hasher.finish() % self.shards.len() as u64) as usize
+    }
+
+    pub fn stats(&self) -> QueueStats {
+        let counters = &self.counters;
+        let processed = counters.processed.load(Ordering::Relaxed);
+        let total = counters.lag_total_ms.load(Ordering::Relaxed);
+        QueueStats {
+            workers: self.shards.len(),
+            capacity: self.capacity,
+            overflow: self.overflow,
+            depth: self
+                .shards
+                .iter()
+                .map(|shard| shard.max_capacity() - shard.capacity())
+                .sum(),
+            enqueued: counters.enqueued.load(Ordering::Relaxed),
+            processed,
+            dropped: counters.dropped.load(Ordering::Relaxed),
+            lag_ms: QueueLag {
+                last: counters.lag_last_ms.load(Ordering::Relaxed),
+                max: counters.lag_max_ms.load(Ordering::Relaxed),
+                mean: if processed == 0 {
+                    0.0
+                } else {
+                    total as f64 / processed as f64
+                },
+            },
+        }
+    }
+}
+
+pub async fn queue_handler(
+    State(state): State<Arc<ProxyState>>,
+) -> Result<Json<QueueStats>, (StatusCode, Json<Value>)> {
+    match &state.queue {
+        Some(queue) => Ok(Json(queue.stats())),
+        None => Err((
+            StatusCode::NOT_FOUND,
+            Json(json!({"error": "recording is inline; start with --hot-path to queue it"})),
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_one_key_records_in_order() {
+        let queue = RecordQueue::start(3, 30, Overflow::Block);
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        for n in 0..20 {
+            let seen = seen.clone();
+            queue
+                .push(
+                    Some("session-1"),
+                    Box::pin(async move {
+                        // Earlier jobs sleeping longer must still finish first
+                        tokio::time::sleep(Duration::from_millis(20 - n)).await;
+                        seen.lock().unwrap().push(n);
+                    }),
+                )
+                .await;
+        }
+        while queue.stats().processed < 20 {
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+        assert_eq!(*seen.lock().unwrap(), (0..20).collect::<Vec<_>>());
+        let stats = queue.stats();
+        assert_eq!((stats.enqueued, stats.dropped, stats.depth), (20, 0, 0));
+    }
+
+    #[tokio::test]
+    async fn test_full_queue_drops_when_asked() {
+        let queue = RecordQueue::start(1, 2, Overflow::Drop);
+        let (release, wait) = tokio::sync::oneshot::channel::<()>();
+        // Holds the worker, so the rest wait in the shard
+        queue
+            .push(
+                None,
+                Box::pin(async move {
+                    let _ = wait.await;
+                }),
+            )
+            .await;
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        for _ in 0..4 {
+            queue.push(None, Box::pin(async {})).await;
+        }
+        let stats = queue.stats();
+        assert_eq!((stats.enqueued, stats.dropped, stats.depth), (3, 2, 2));
+        let _ = release.send(());
+    }
+}
@@ -0,0 +1,495 @@
+//! `sentinel reconcile`: Anthropic's own usage and cost reports against what
+//! sentinel recorded.
+//!
+//! The Admin API's usage report gives the tokens the organization was billed
+//! for per UTC day and model, and its cost report what they cost. Two things
+//! are flagged when they're more than `--tolerance` off:
+//!
+//! - Gaps: a day and model whose billed tokens sentinel didn't record, which
+//!   is traffic that went to the API without passing through the proxy (or,
+//!   the other way round, traffic recorded on another organization's keys).
+//! - Drift: the price table estimating a model's billed tokens at something
+//!   other than what was charged for them, which puts every recorded cost off
+//!   by as much. It's checked on the billed tokens, so gaps don't count.
+//!
+//! Reading the reports takes an Admin API key (`sk-ant-admin...`), which only
+//! organization admins can create.
+
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use chrono::{DateTime, Duration, DurationRound, SecondsFormat, Utc};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+
+use crate::parsers::Usage;
+use crate::pricing;
+use crate::rollup::RollupStore;
+use crate::storage::Storage;
+
+pub const DEFAULT_ADMIN_URL: &str = "https://api.anthropic.com";
+const ANTHROPIC_VERSION: &str = "2023-06-01";
+/// Most daily buckets the reports return per page
+const PAGE_DAYS: &str = "31";
+
+#[derive(Debug, thiserror::Error)]
+pub enum ReconcileError {
+    #[error("admin API request failed: {0}")]
+    Request(#[from] reqwest::Error),
+    #[error("admin API answered {status} for {path}: {body}")]
+    Api {
+        status: u16,
+        path: String,
+        body: String,
+    },
+    #[error("cost report amount '{0}' isn't a number")]
+    Amount(String),
+}
+
+#[derive(Debug, Deserialize)]
+struct Page<T> {
+    data: Vec<Bucket<T>>,
+    #[serde(default)]
+    has_more: bool,
+    next_page: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Bucket<T> {
+    starting_at: DateTime<Utc>,
+    results: Vec<T>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct UsageResult {
+    model: Option<String>,
+    uncached_input_tokens: i64,
+    cache_creation: CacheCreation,
+    cache_read_input_tokens: i64,
+    output_tokens: i64,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct CacheCreation {
+    ephemeral_5m_input_tokens: i64,
+    ephemeral_1h_input_tokens: i64,
+}
+
+#[derive(Debug, Deserialize)]
+struct CostResult {
+    /// Decimal string, in cents
+    amount: String,
+    /// Absent for charges that aren't tokens, like web searches
+    #[serde(default)]
+    model: Option<String>,
+}
+
+/// Tokens of one kind or another, Anthropic's way: input excludes the cache
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+struct Tokens {
+    input: i64,
+    cache_creation: i64,
+    cache_read: i64,
+    output: i64,
+}
+
+impl Tokens {
+    fn total(&self) -> i64 {
+        self.input + self.cache_creation + self.cache_read + self.output
+    }
+
+    fn add(&mut self, other: Tokens) {
+        self.input += other.input;
+        self.cache_creation += other.cache_creation;
+        self.cache_read += other.cache_read;
+        self.output += other.output;
+    }
+
+    fn usage(&self) -> Usage {
+        Usage {
+            input_tokens: Some(self.input),
+            output_tokens: Some(self.output),
+            cache_read_tokens: Some(self.cache_read),
+            cache_creation_tokens: Some(self.cache_creation),
+            cost_usd: None,
+        }
+    }
+}
+
+/// Keyed by UTC day (`YYYY-MM-DD`) and model
+type Daily = BTreeMap<(String, String), Tokens>;
+
+/// One model over the whole range
+#[derive(Debug, PartialEq)]
+struct ModelRow {
+    model: String,
+    billed: Tokens,
+    recorded: Tokens,
+    billed_usd: Option<f64>,
+    /// The price table's figure for the billed tokens
+    estimated_usd: Option<f64>,
+}
+
+impl ModelRow {
+    /// How far the estimate is from the charge, as a fraction of the charge
+    fn drift(&self) -> Option<f64> {
+        let (billed, estimated) = (self.billed_usd?, self.estimated_usd?);
+        (billed > 0.0).then(|| (estimated - billed) / billed)
+    }
+}
+
+#[derive(Debug, PartialEq)]
+struct Gap {
+    day: String,
+    model: String,
+    billed: i64,
+    recorded: i64,
+}
+
+#[derive(Debug, PartialEq)]
+struct Report {
+    models: Vec<ModelRow>,
+    gaps: Vec<Gap>,
+}
+
+fn compare(
+    billed: &Daily,
+    recorded: &Daily,
+    costs: &BTreeMap<String, f64>,
+    tolerance: f64,
+) -> Report {
+    let mut models: BTreeMap<&str, (Tokens, Tokens)> = BTreeMap::new();
+    let mut gaps = Vec::new();
+    let keys: std::collections::BTreeSet<_> = billed.keys().chain(recorded.keys()).collect();
+    for key in keys {
+        let (day, model) = key;
+        let b = billed.get(key).copied().unwrap_or_default();
+        let r = recorded.get(key).copied().unwrap_or_default();
+        let totals = models.entry(model).or_default();
+        totals.0.add(b);
+        totals.1.add(r);
+        if (r.total() - b.total()).abs() as f64 > b.total() as f64 * tolerance {
+            gaps.push(Gap {
+                day: day.clone(),
+                model: model.clone(),
+                billed: b.total(),
+                recorded: r.total(),
+            });
+        }
+    }
+    let models = models
+        .into_iter()
+        .map(|(model, (billed, recorded))| ModelRow {
+            model: model.to_string(),
+            billed,
+            recorded,
+            billed_usd: costs.get(model).copied(),
+            estimated_usd: pricing::cost_usd(Some(model), &billed.usage()),
+        })
+        .collect();
+    Report { models, gaps }
+}
+
+/// Every page of a report's daily buckets
+async fn fetch<T: DeserializeOwned>(
+    client: &reqwest::Client,
+    base: &str,
+    key: &str,
+    path: &str,
+    query: &[(&str, String)],
+) -> Result<Vec<Bucket<T>>, ReconcileError> {
+    let url = format!("{}{}", base.trim_end_matches('/'), path);
+    let mut buckets = Vec::new();
+    let mut page: Option<String> = None;
+    loop {
+        let mut request = client
+            .get(&url)
+            .header("x-api-key", key)
+            .header("anthropic-version", ANTHROPIC_VERSION)
+            .query(query);
+        if let Some(ref page) = page {
+            request = request.query(&[("page", page)]);
+        }
+        let response = request.send().await?;
+        let status = response.status();
+        if !status.is_success() {
+            return Err(ReconcileError::Api {
+                status: status.as_u16(),
+                path: path.to_string(),
+                body: response.text().await.unwrap_or_default(),
+            });
+        }
+        let body: Page<T> = response.json().await?;
+        buckets.extend(body.data);
+        match body.next_page {
+            Some(next) if body.has_more => page = Some(next),
+            _ => break,
+        }
+    }
+    Ok(buckets)
+}
+
+fn day(at: DateTime<Utc>) -> String {
+    at.format("%Y-%m-%d").to_string()
+}
+
+async fn billed(
+    base: &str,
+    key: &str,
+    from: DateTime<Utc>,
+) -> Result<(Daily, BTreeMap<String, f64>), ReconcileError> {
+    let client = reqwest::Client::new();
+    let starting_at = from.to_rfc3339_opts(SecondsFormat::Secs, true);
+
+    let usage: Vec<Bucket<UsageResult>> = fetch(
+        &client,
+        base,
+        key,
+        "/v1/organizations/usage_report/messages",
+        &[
+            ("starting_at", starting_at.clone()),
+            ("bucket_width", "1d".to_string()),
+            ("group_by[]", "model".to_string()),
+            ("limit", PAGE_DAYS.to_string()),
+        ],
+    )
+    .await?;
+    let mut tokens = Daily::new();
+    for bucket in usage {
+        for result in bucket.results {
+            let model = result.model.unwrap_or_default();
+            tokens
+                .entry((day(bucket.starting_at), model))
+                .or_default()
+                .add(Tokens {
+                    input: result.uncached_input_tokens,
+                    cache_creation: result.cache_creation.ephemeral_5m_input_tokens
+                        + result.cache_creation.ephemeral_1h_input_tokens,
+                    cache_read: result.cache_read_input_tokens,
+                    output: result.output_tokens,
+                });
+        }
+    }
+
+    let cost: Vec<Bucket<CostResult>> = fetch(
+        &client,
+        base,
+        key,
+        "/v1/organizations/cost_report",
+        &[
+            ("starting_at", starting_at),
+            ("group_by[]", "description".to_string()),
+            ("limit", PAGE_DAYS.to_string()),
+        ],
+    )
+    .await?;
+    let mut costs: BTreeMap<String, f64> = BTreeMap::new();
+    for result in cost.into_iter().flat_map(|bucket| bucket.results) {
+        let Some(model) = result.model else {
+            continue;
+        };
+        let cents: f64 = result
+            .amount
+            .parse()
+            .map_err(|_| ReconcileError::Amount(result.amount.clone()))?;
+        *costs.entry(model).or_default() += cents / 100.0;
+    }
+    Ok((tokens, costs))
+}
+
+async fn recorded(storage: &Storage, from: DateTime<Utc>) -> Result<Daily, sqlx::Error> {
+    let mut tokens = Daily::new();
+    for total in RollupStore::new(storage.pool())
+        .totals_by_day_and_model(from)
+        .await?
+    {
+        // Other providers' models aren't on Anthropic's bill
+        if !total.usage.group_key.starts_with("claude") {
+            continue;
+        }
+        tokens.insert(
+            (total.day, total.usage.group_key),
+            Tokens {
+                input: total.usage.input_tokens,
+                cache_creation: total.usage.cache_creation_tokens,
+                cache_read: total.usage.cache_read_tokens,
+                output: total.usage.output_tokens,
+            },
+        );
+    }
+    Ok(tokens)
+}
+
+fn percent(fraction: f64) -> String {
+    format!("{:+.1}%", fraction * 100.0)
+}
+
+fn usd(amount: Option<f64>) -> String {
+    amount.map_or("-".to_string(), |a| format!("${:.2}", a))
+}
+
+pub async fn run(
+    data_dir: &Path,
+    admin_key: &str,
+    admin_url: &str,
+    since: Duration,
+    tolerance_percent: f64,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let db_path = data_dir.join("sentinel.db");
+    if !db_path.exists() {
+        return Err("No recorded traffic. Run 'sentinel start' first.".into());
+    }
+    let storage = Storage::new(&db_path).await?;
+    // The reports bucket by whole UTC days
+    let from = (Utc::now() - since)
+        .duration_trunc(Duration::days(1))
+        .unwrap_or(Utc::now() - since);
+    let tolerance = tolerance_percent / 100.0;
+
+    let (billed, costs) = billed(admin_url, admin_key, from).await?;
+    let recorded = recorded(&storage, from).await?;
+    let report = compare(&billed, &recorded, &costs, tolerance);
+    if report.models.is_empty() {
+        println!("No Claude usage billed or recorded since {}.", day(from));
+        return Ok(());
+    }
+
+    println!(
+        "Claude usage since {} (UTC), as billed and as recorded\n",
+        day(from)
+    );
+    println!(
+        "{:<32} {:>14} {:>14} {:>9} {:>12} {:>12} {:>8}",
+        "model", "billed tokens", "recorded", "coverage", "billed", "estimate", "drift"
+    );
+    for row in &report.models {
+        let coverage = match row.billed.total() {
+            0 => "-".to_string(),
+            billed => format!(
+                "{:.0}%",
+                row.recorded.total() as f64 / billed as f64 * 100.0
+            ),
+        };
+        let name: String = match row.model.as_str() {
+            "" => "(none)".to_string(),
+            name => name.chars().take(32).collect(),
+        };
+        println!(
+            "{:<32} {:>14} {:>14} {:>9} {:>12} {:>12} {:>8}",
+            name,
+            row.billed.total(),
+            row.recorded.total(),
+            coverage,
+            usd(row.billed_usd),
+            usd(row.estimated_usd),
+            row.drift().map_or("-".to_string(), percent)
+        );
+    }
+
+    if !report.gaps.is_empty() {
+        println!(
+            "\nGaps: days whose recorded tokens are over {}% off the billed ones",
+            tolerance_percent
+        );
+        for gap in &report.gaps {
+            let hint = if gap.recorded < gap.billed {
+                "traffic bypassed the proxy"
+            } else {
+                "recorded traffic billed elsewhere"
+            };
+            println!(
+                "  {} {}: {} billed, {} recorded ({})",
+                gap.day, gap.model, gap.billed, gap.recorded, hint
+            );
+        }
+    }
+    let drifting: Vec<&ModelRow> = report
+        .models
+        .iter()
+        .filter(|row| row.drift().is_some_and(|d| d.abs() > tolerance))
+        .collect();
+    if !drifting.is_empty() {
+        println!(
+            "\nDrift: the price table is over {}% off what these models' tokens were billed",
+            tolerance_percent
+        );
+        for row in &drifting {
+            println!(
+                "  {}: estimated {}, billed {} ({}); recorded costs are off by as much",
+                row.model,
+                usd(row.estimated_usd),
+                usd(row.billed_usd),
+                row.drift().map_or("-".to_string(), percent)
+            );
+        }
+    }
+    if report.gaps.is_empty() && drifting.is_empty() {
+        println!(
+            "\nNo gaps or cost drift over {}%. The reports can trail live traffic by a few minutes.",
+            tolerance_percent
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tokens(input: i64, output: i64) -> Tokens {
+        Tokens {
+            input,
+            output,
+            ..Default::default()
+        }
+    }
+
+    fn key(day: &str, model: &str) -> (String, String) {
+        (day.to_string(), model.to_string())
+    }
+
+    #[test]
+    fn test_compare_flags_gaps_per_day_and_drift_on_billed_tokens() {
+        let sonnet = "claude-sonnet-4-20250514";
+        let billed = Daily::from([
+            (key("2026-10-01", sonnet), tokens(1_000_000, 100_000)),
+            (key("2026-10-02", sonnet), tokens(1_000_000, 100_000)),
+        ]);
+        // The second day's traffic mostly went around the proxy
+        let recorded = Daily::from([
+            (key("2026-10-01", sonnet), tokens(990_000, 99_000)),
+            (key("2026-10-02", sonnet), tokens(100_000, 10_000)),
+        ]);
+        // Sonnet 4 is $3 in, $15 out: $9 for these tokens, billed at $10
+        let costs = BTreeMap::from([(sonnet.to_string(), 10.0)]);
+
+        let report = compare(&billed, &recorded, &costs, 0.05);
+        assert_eq!(
+            report.gaps,
+            vec![Gap {
+                day: "2026-10-02".to_string(),
+                model: sonnet.to_string(),
+                billed: 1_100_000,
+                recorded: 110_000,
+            }]
+        );
+        let row = &report.models[0];
+        assert_eq!(row.billed, tokens(2_000_000, 200_000));
+        assert!((row.estimated_usd.unwrap() - 9.0).abs() < 1e-9);
+        assert!((row.drift().unwrap() + 0.1).abs() < 1e-9);
+
+        let page: Page<UsageResult> = serde_json::from_str(
+            r#"{"data": [{"starting_at": "2026-10-01T00:00:00Z", "ending_at": "2026-10-02T00:00:00Z",
+                "results": [{"model": "claude-sonnet-4-20250514", "uncached_input_tokens": 5,
+                "cache_creation": {"ephemeral_5m_input_tokens": 2, "ephemeral_1h_input_tokens": 1},
+                "cache_read_input_tokens": 3, "output_tokens": 4, "server_tool_use": null}]}],
+                "has_more": false, "next_page": null}"#,
+        )
+        .unwrap();
+        let result = &page.data[0].results[0];
+        assert_eq!(result.cache_creation.ephemeral_1h_input_tokens, 1);
+        assert_eq!(result.output_tokens, 4);
+    }
+}
@@ -0,0 +1,253 @@
+//! `sentinel redo`: send a recorded request again, optionally edited.
+//!
+//! Prompts are recorded with the request body they came in (as their raw
+//! capture), so a turn can be re-asked through the running proxy. The new
+//! response is recorded like any other, with `redo_of` naming the response
+//! it re-asks for. Only Anthropic Messages requests can be redone, and only
+//! when the body was kept as sent: not redacted, encrypted, or cut to
+//! `--max-raw-kb` (prompt bodies are kept whole unless encrypted; see
+//! `bodies`). Credentials aren't recorded; the request is sent with
+//! `ANTHROPIC_API_KEY` or `ANTHROPIC_AUTH_TOKEN` from the environment.
+
+use std::path::Path;
+use std::process::Command;
+
+use uuid::Uuid;
+
+use crate::copy::find_event;
+use crate::encrypt;
+use crate::event::{ObservabilityEvent, Payload};
+use crate::parsers::{AnthropicParser, ParsedResponse, ResponseParser};
+use crate::storage::Storage;
+
+/// Names the response a request re-asks for; not forwarded upstream
+pub const REDO_HEADER: &str = "x-sentinel-redo-of";
+
+const ANTHROPIC_VERSION: &str = "2023-06-01";
+
+/// The prompt event to resend and the response it originally got
+async fn request_for(
+    storage: &Storage,
+    event: ObservabilityEvent,
+) -> Result<(ObservabilityEvent, Option<Uuid>), Box<dyn std::error::Error>> {
+    match event.payload {
+        Payload::UserMessage(ref message) => {
+            let response_id = message.response_id;
+            Ok((event, response_id))
+        }
+        Payload::AssistantResponse(ref response) => {
+            let Some(request_id) = response.request_id else {
+                return Err(format!("Response {} has no recorded request", event.id).into());
+            };
+            let request = storage.get_event(request_id).await?.ok_or_else(|| {
+                format!(
+                    "Request {} for response {} wasn't recorded",
+                    request_id, event.id
+                )
+            })?;
+            Ok((request, Some(event.id)))
+        }
+        _ => Err(format!("Event {} is neither a prompt nor a response", event.id).into()),
+    }
+}
+
+/// Open the body in $VISUAL or $EDITOR and read back the edited JSON
+fn edit(
+    body: &serde_json::Value,
+    id: Uuid,
+) -> Result<serde_json::Value, Box<dyn std::error::Error>> {
+    let editor = std::env::var("VISUAL")
+        .or_else(|_| std::env::var("EDITOR"))
+        .unwrap_or_else(|_| "vi".to_string());
+    let mut words = editor.split_whitespace();
+    let program = words.next().ok_or("$EDITOR is empty")?;
+
+    let path = std::env::temp_dir().join(format!("sentinel-redo-{}.json", id));
+    std::fs::write(&path, serde_json::to_string_pretty(body)?)?;
+    let status = Command::new(program)
+        .args(words)
+        .arg(&path)
+        .status()
+        .map_err(|e| format!("Failed to run {}: {}", editor, e))?;
+    let edited = std::fs::read_to_string(&path);
+    std::fs::remove_file(&path)?;
+    if !status.success() {
+        return Err(format!("{} exited with {}; nothing sent", editor, status).into());
+    }
+    serde_json::from_str(&edited?)
+        .map_err(|e| format!("Edited request isn't valid JSON: {}", e).into())
+}
+
+fn print_response(parsed: &ParsedResponse) {
+    if let Some(ref thinking) = parsed.thinking {
+        for line in thinking.lines() {
+            println!("│ {}", line);
+        }
+        println!();
+    }
+    if let Some(ref text) = parsed.text {
+        println!("{}", text.trim_end());
+    }
+    for call in &parsed.tool_calls {
+        println!("→ {} {}", call.name, call.input);
+    }
+}
+
+pub async fn run(
+    data_dir: &Path,
+    id: &str,
+    port: u16,
+    edit_first: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let db_path = data_dir.join("sentinel.db");
+    if !db_path.exists() {
+        return Err("No recorded traffic. Run 'sentinel start' first.".into());
+    }
+    let storage = Storage::new(&db_path).await?;
+    let (request, original) = request_for(&storage, find_event(&storage, id).await?).await?;
+    if request
+        .provider
+        .as_deref()
+        .is_some_and(|p| p != "anthropic")
+    {
+        return Err(format!(
+            "Request {} went to {}; only Anthropic requests can be redone",
+            request.id,
+            request.provider.as_deref().unwrap_or("-")
+        )
+        .into());
+    }
+    let raw = storage
+        .get_raw_capture(&request.id.to_string())
+        .await?
+        .filter(|raw| !raw.body.is_empty())
+        .ok_or_else(|| format!("Request {} was recorded without its body", request.id))?;
+    if encrypt::is_sealed(&raw.body) {
+        return Err(format!(
+            "Request {} was recorded with --encrypt-fields; its body can't be sent again",
+            request.id
+        )
+        .into());
+    }
+    if raw.body.contains("[REDACTED:") {
+        return Err(format!(
+            "Request {} was recorded with --redact; sending it again would send the redaction markers",
+            request.id
+        )
+        .into());
+    }
+    if raw.truncated {
+        return Err(format!(
+            "Request {} body was cut to {} of {} bytes; restart with --full-fidelity to redo requests this large",
+            request.id,
+            raw.body.len(),
+            raw.original_bytes
+        )
+        .into());
+    }
+    let mut body: serde_json::Value = serde_json::from_str(&raw.body)?;
+    if edit_first {
+        body = edit(&body, request.id)?;
+    }
+
+    let client = reqwest::Client::new();
+    let mut builder = client
+        .post(format!("http://127.0.0.1:{}/v1/messages", port))
+        .header("content-type", "application/json")
+        .header("anthropic-version", ANTHROPIC_VERSION)
+        .body(serde_json::to_vec(&body)?);
+    if let Some(original) = original {
+        builder = builder.header(REDO_HEADER, original.to_string());
+    }
+    builder = match (
+        std::env::var("ANTHROPIC_API_KEY"),
+        std::env::var("ANTHROPIC_AUTH_TOKEN"),
+    ) {
+        (Ok(key), _) => builder.header("x-api-key", key),
+        (_, Ok(token)) => builder.bearer_auth(token),
+        _ => {
+            return Err("Set ANTHROPIC_API_KEY or ANTHROPIC_AUTH_TOKEN to send the request".into())
+        }
+    };
+
+    eprintln!(
+        "Redoing request {}{} through the proxy on port {}",
+        request.id,
+        if edit_first { " (edited)" } else { "" },
+        port
+    );
+    let response = builder.send().await.map_err(|e| {
+        format!(
+            "Couldn't reach the proxy on port {} ({}); is 'sentinel start' running?",
+            port, e
+        )
+    })?;
+    let status = response.status();
+    let streaming = response
+        .headers()
+        .get("content-type")
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.starts_with("text/event-stream"));
+    let text = response.text().await?;
+    if !status.is_success() {
+        return Err(format!("Upstream answered {}: {}", status, text.trim()).into());
+    }
+
+    let parser = AnthropicParser::new();
+    let parsed = if streaming {
+        parser.parse_streaming(&text)
+    } else {
+        parser.parse_json(&serde_json::from_str(&text)?)
+    };
+    print_response(&parsed);
+    if let Some(original) = original {
+        eprintln!("\nRecorded as a redo of response {}", original);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event::UserMessage;
+    use crate::storage::CapturePolicy;
+
+    #[tokio::test]
+    async fn test_response_resolves_to_its_recorded_request() {
+        let path = std::env::temp_dir().join(format!("sentinel-test-{}.db", Uuid::new_v4()));
+        let storage = Storage::new(&path).await.unwrap();
+        let response_id = Uuid::new_v4();
+        let prompt = ObservabilityEvent {
+            ..ObservabilityEvent::for_tests(0, Payload::UserMessage(UserMessage {
+                model: Some("claude-sonnet-4".to_string()),
+                text: "hi".to_string(),
+                response_id: Some(response_id),
+                ..Default::default()
+            }))
+        };
+        let body = br#"{"model":"claude-sonnet-4","messages":[{"role":"user","content":"hi"}]}"#;
+        let raw = CapturePolicy {
+            max_raw_bytes: None,
+        }
+        .capture(body);
+        storage
+            .insert_observability_event_with_raw(&prompt, Some(&raw))
+            .await
+            .unwrap();
+
+        let (request, original) = request_for(&storage, prompt.clone()).await.unwrap();
+        assert_eq!((request.id, original), (prompt.id, Some(response_id)));
+        let stored = storage
+            .get_raw_capture(&request.id.to_string())
+            .await
+            .unwrap()
+            .unwrap();
+        // Joined back from its parts, with its keys in sorted order
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(&stored.body).unwrap(),
+            serde_json::from_slice::<serde_json::Value>(body).unwrap()
+        );
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,363 @@
+//! Replay mode (`sentinel start --replay <session>`): recorded responses
+//! served in place of the upstream.
+//!
+//! Every prompt in the session whose request and response bodies were kept in
+//! full becomes an entry in a cassette, keyed on the request body normalized
+//! as `--replay-match` says. The cassette is served on a local port that
+//! Anthropic traffic is forwarded to instead of the real API, so the rest of
+//! the proxy (recording, policy, the dashboard) works as usual; a request
+//! with no recorded match gets a `not_found_error` rather than reaching the
+//! network. A request recorded more than once is answered with each
+//! recorded response in turn, then the last one again.
+//!
+//! Record a session to replay with `--full-fidelity`, since bodies cut to
+//! `--max-raw-kb` can't be served, and without `--encrypt-fields`.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use axum::body::Bytes;
+use axum::extract::State;
+use axum::http::{header, Method, StatusCode, Uri};
+use axum::response::{IntoResponse, Response};
+use axum::Router;
+use clap::ValueEnum;
+use ring::digest::{digest, SHA256};
+use serde_json::Value;
+
+use crate::encrypt;
+use crate::event::Payload;
+use crate::parsers::AnthropicRequest;
+use crate::storage::{RawCapture, Storage};
+
+/// How closely a request has to match a recorded one to be answered
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum Match {
+    /// The whole body, apart from `metadata`
+    Exact,
+    /// The model, system prompt, tools, and messages, ignoring cache markers
+    /// and sampling settings
+    #[default]
+    Request,
+    /// Just the latest user message, whatever the model or history
+    Prompt,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum ReplayError {
+    #[error("no session or agent named '{0}'")]
+    NotFound(String),
+    #[error("nothing in '{key}' was recorded in full: {skipped} exchanges lacked full, unencrypted bodies (record with --full-fidelity)")]
+    Empty { key: String, skipped: usize },
+    #[error(transparent)]
+    Storage(#[from] sqlx::Error),
+}
+
+/// A prompt's request body and the response to it, both kept in full
+#[derive(Debug, Clone)]
+pub struct Exchange {
+    pub request: String,
+    pub response: String,
+    pub streaming: bool,
+}
+
+/// A recorded response body
+#[derive(Debug, Clone)]
+struct Recorded {
+    body: String,
+    streaming: bool,
+}
+
+/// Recorded responses by normalized request
+pub struct Cassette {
+    level: Match,
+    entries: HashMap<String, Vec<Recorded>>,
+    /// Times each entry has been served
+    played: Mutex<HashMap<String, usize>>,
+}
+
+/// `value` as JSON with object keys sorted, so equal bodies compare equal
+/// however their fields were ordered
+fn canonical(value: &Value) -> String {
+    match value {
+        Value::Object(fields) => {
+            let mut keys: Vec<&String> = fields.keys().collect();
+            keys.sort();
+            let fields: Vec<String> = keys
+                .into_iter()
+                .map(|k| format!("{}:{}", Value::String(k.clone()), canonical(&fields[k])))
+                .collect();
+            format!("{{{}}}", fields.join(","))
+        }
+        Value::Array(items) => {
+            let items: Vec<String> = items.iter().map(canonical).collect();
+            format!("[{}]", items.join(","))
+        }
+        _ => value.to_string(),
+    }
+}
+
+fn strip_cache_control(value: &mut Value) {
+    match value {
+        Value::Object(fields) => {
+            fields.remove("cache_control");
+            fields.values_mut().for_each(strip_cache_control);
+        }
+        Value::Array(items) => items.iter_mut().for_each(strip_cache_control),
+        _ => {}
+    }
+}
+
+/// The cassette key of a request body; None when it isn't JSON
+pub fn key(body: &[u8], level: Match) -> Option<String> {
+    let mut body: Value = serde_json::from_slice(body).ok()?;
+    let streaming = body.get("stream").and_then(Value::as_bool).unwrap_or(false);
+    let normalized = match level {
+        Match::Exact => {
+            body.as_object_mut()?.remove("metadata");
+            body
+        }
+        Match::Request => {
+            strip_cache_control(&mut body);
+            let field = |name: &str| body.get(name).cloned().unwrap_or(Value::Null);
+            serde_json::json!({
+                "model": field("model"),
+                "system": field("system"),
+                "tools": field("tools"),
+                "messages": field("messages"),
+                "stream": streaming,
+            })
+        }
+        Match::Prompt => {
+            let request: AnthropicRequest = serde_json::from_value(body).ok()?;
+            serde_json::json!({
+                "prompt": request.last_user_message_text(),
+                "stream": streaming,
+            })
+        }
+    };
+    let hash = digest(&SHA256, canonical(&normalized).as_bytes());
+    Some(hash.as_ref().iter().map(|b| format!("{:02x}", b)).collect())
+}
+
+/// A body kept whole and in the clear
+fn usable(raw: Option<RawCapture>) -> Option<String> {
+    raw.filter(|raw| !raw.truncated && !raw.body.is_empty() && !encrypt::is_sealed(&raw.body))
+        .map(|raw| raw.body)
+}
+
+/// A session or agent's exchanges with both bodies kept in full, in order,
+/// and how many others were skipped for lacking them
+pub async fn exchanges(storage: &Storage, session: &str) -> Result<(Vec<Exchange>, usize), ReplayError> {
+    let events = storage.get_session_or_agent_events(session).await?;
+    if events.is_empty() {
+        return Err(ReplayError::NotFound(session.to_string()));
+    }
+    let responses: HashMap<_, _> = events
+        .iter()
+        .filter_map(|event| match &event.payload {
+            Payload::AssistantResponse(response) => Some((event.id, response.streaming)),
+            _ => None,
+        })
+        .collect();
+
+    let mut exchanges = Vec::new();
+    let mut skipped = 0;
+    for event in &events {
+        let Payload::UserMessage(ref message) = event.payload else {
+            continue;
+        };
+        let Some((response_id, streaming)) = message
+            .response_id
+            .and_then(|id| responses.get(&id).map(|streaming| (id, *streaming)))
+        else {
+            continue;
+        };
+        let request = usable(storage.get_raw_capture(&event.id.to_string()).await?);
+        let response = usable(storage.get_raw_capture(&response_id.to_string()).await?);
+        let (Some(request), Some(response)) = (request, response) else {
+            skipped += 1;
+            continue;
+        };
+        exchanges.push(Exchange {
+            request,
+            response,
+            streaming,
+        });
+    }
+    if exchanges.is_empty() {
+        return Err(ReplayError::Empty {
+            key: session.to_string(),
+            skipped,
+        });
+    }
+    Ok((exchanges, skipped))
+}
+
+impl Cassette {
+    /// The replayable exchanges of a session or agent
+    pub async fn load(storage: &Storage, session: &str, level: Match) -> Result<Self, ReplayError> {
+        let (exchanges, mut skipped) = exchanges(storage, session).await?;
+        let mut entries: HashMap<String, Vec<Recorded>> = HashMap::new();
+        for exchange in exchanges {
+            let Some(key) = key(exchange.request.as_bytes(), level) else {
+                skipped += 1;
+                continue;
+            };
+            entries.entry(key).or_default().push(Recorded {
+                body: exchange.response,
+                streaming: exchange.streaming,
+            });
+        }
+        if entries.is_empty() {
+            return Err(ReplayError::Empty {
+                key: session.to_string(),
+                skipped,
+            });
+        }
+        if skipped > 0 {
+            tracing::warn!(
+                "Replay: {} exchanges in '{}' lack full, unencrypted bodies and won't be served",
+                skipped,
+                session
+            );
+        }
+        Ok(Self {
+            level,
+            entries,
+            played: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Recorded exchanges the cassette holds
+    pub fn len(&self) -> usize {
+        self.entries.values().map(Vec::len).sum()
+    }
+
+    /// The response to serve for a request body, if one was recorded
+    fn answer(&self, body: &[u8]) -> Option<Recorded> {
+        let key = key(body, self.level)?;
+        let recorded = self.entries.get(&key)?;
+        let mut played = self.played.lock().unwrap_or_else(|e| e.into_inner());
+        let times = played.entry(key).or_default();
+        let answer = recorded[(*times).min(recorded.len() - 1)].clone();
+        *times += 1;
+        Some(answer)
+    }
+}
+
+async fn serve_recorded(
+    State(cassette): State<Arc<Cassette>>,
+    method: Method,
+    uri: Uri,
+    body: Bytes,
+) -> Response {
+    let answer = (method == Method::POST && uri.path().ends_with("/v1/messages"))
+        .then(|| cassette.answer(&body))
+        .flatten();
+    match answer {
+        Some(recorded) => {
+            let content_type = if recorded.streaming {
+                "text/event-stream"
+            } else {
+                "application/json"
+            };
+            ([(header::CONTENT_TYPE, content_type)], recorded.body).into_response()
+        }
+        None => {
+            let error = serde_json::json!({
+                "type": "error",
+                "error": {
+                    "type": "not_found_error",
+                    "message": format!(
+                        "sentinel replay: no recorded response matches this {} {} (--replay-match {})",
+                        method,
+                        uri.path(),
+                        cassette.level.to_possible_value().map(|v| v.get_name().to_string()).unwrap_or_default()
+                    ),
+                },
+            });
+            (StatusCode::NOT_FOUND, axum::Json(error)).into_response()
+        }
+    }
+}
+
+/// Serve the cassette on a local port; the URL to forward Anthropic traffic to
+pub async fn serve(cassette: Cassette) -> std::io::Result<reqwest::Url> {
+    let listener = tokio::net::TcpListener::bind(("127.0.0.1", 0)).await?;
+    let addr = listener.local_addr()?;
+    let app = Router::new()
+        .fallback(serve_recorded)
+        .with_state(Arc::new(cassette));
+    tokio::spawn(async move {
+        if let Err(e) = axum::serve(listener, app).await {
+            tracing::error!("Replay server stopped: {}", e);
+        }
+    });
+    Ok(reqwest::Url::parse(&format!("http://{}", addr)).expect("socket address is a valid URL"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn body(value: Value) -> Vec<u8> {
+        serde_json::to_vec(&value).unwrap()
+    }
+
+    #[test]
+    fn test_requests_match_recorded_ones_as_strictly_as_asked() {
+        let recorded = body(serde_json::json!({
+            "model": "claude-sonnet-4",
+            "max_tokens": 100,
+            "metadata": {"user_id": "u_session_1"},
+            "messages": [{"role": "user", "content": [
+                {"type": "text", "text": "fix it", "cache_control": {"type": "ephemeral"}}
+            ]}],
+        }));
+        // Reordered, another session, the cache marker moved, a new max_tokens
+        let later = body(serde_json::json!({
+            "messages": [{"role": "user", "content": [{"type": "text", "text": "fix it"}]}],
+            "metadata": {"user_id": "u_session_2"},
+            "max_tokens": 200,
+            "model": "claude-sonnet-4",
+        }));
+        let other_model = body(serde_json::json!({
+            "model": "claude-opus-4",
+            "max_tokens": 100,
+            "messages": [{"role": "user", "content": "fix it"}],
+        }));
+        let streamed = body(serde_json::json!({
+            "model": "claude-opus-4",
+            "stream": true,
+            "messages": [{"role": "user", "content": "fix it"}],
+        }));
+
+        let same = |a: &[u8], b: &[u8], level| key(a, level).unwrap() == key(b, level).unwrap();
+        assert!(!same(&recorded, &later, Match::Exact));
+        assert!(same(&recorded, &later, Match::Request));
+        assert!(!same(&recorded, &other_model, Match::Request));
+        assert!(same(&recorded, &other_model, Match::Prompt));
+        assert!(!same(&other_model, &streamed, Match::Prompt));
+        assert!(key(b"not json", Match::Exact).is_none());
+
+        // Each recorded answer in turn, then the last again
+        let entries = HashMap::from([(
+            key(&recorded, Match::Request).unwrap(),
+            ["first", "second"]
+                .map(|body| Recorded {
+                    body: body.to_string(),
+                    streaming: false,
+                })
+                .to_vec(),
+        )]);
+        let cassette = Cassette {
+            level: Match::Request,
+            entries,
+            played: Mutex::new(HashMap::new()),
+        };
+        let answers: Vec<String> = (0..3).map(|_| cassette.answer(&later).unwrap().body).collect();
+        assert_eq!(answers, ["first", "second", "second"]);
+        assert!(cassette.answer(&other_model).is_none());
+    }
+}
@@ -0,0 +1,547 @@
+//! Best-effort resumption of interrupted Anthropic streams.
+//!
+//! A client that sends `x-sentinel-idempotency-key` has its streamed response
+//! journaled chunk by chunk. Re-sending the same request with the same key
+//! replays a finished response verbatim, or, when the stream was cut off (the
+//! proxy restarted, the connection dropped), replays what was journaled and
+//! completes the turn with a fresh upstream request that prefills the partial
+//! text. The continuation's events are renumbered to follow the journaled
+//! content blocks, so the client sees a single well-formed message.
+//!
+//! Only text can be continued: a stream interrupted inside a tool call or
+//! thinking block is started over instead.
+
+use bytes::Bytes;
+use chrono::{Duration, Utc};
+use sqlx::SqlitePool;
+
+use crate::storage::add_column_if_missing;
+
+pub const RESUME_HEADER: &str = "x-sentinel-idempotency-key";
+
+/// Journals older than this are pruned at startup
+const JOURNAL_TTL_HOURS: i64 = 24;
+
+#[derive(Debug, sqlx::FromRow)]
+struct JournalRow {
+    complete: bool,
+    streaming: bool,
+}
+
+#[derive(Debug, sqlx::FromRow)]
+struct ChunkRow {
+    data: Vec<u8>,
+}
+
+/// The response bytes sent so far for an idempotency key
+#[derive(Debug)]
+pub struct Journal {
+    pub complete: bool,
+    /// Whether the response is an SSE stream rather than a whole JSON body
+    pub streaming: bool,
+    pub data: Vec<u8>,
+}
+
+#[derive(Clone)]
+pub struct StreamJournal {
+    pool: SqlitePool,
+}
+
+impl StreamJournal {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool }
+    }
+
+    pub async fn init_schema(&self) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS stream_journals (
+                key TEXT PRIMARY KEY,
+                created_at TEXT NOT NULL,
+                complete INTEGER NOT NULL DEFAULT 0,
+                streaming INTEGER NOT NULL DEFAULT 1
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+        add_column_if_missing(&self.pool, "stream_journals", "streaming", "INTEGER NOT NULL DEFAULT 1")
+            .await?;
+
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS stream_journal_chunks (
+                key TEXT NOT NULL,
+                seq INTEGER NOT NULL,
+                data BLOB NOT NULL,
+                PRIMARY KEY (key, seq)
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        let cutoff = (Utc::now() - Duration::hours(JOURNAL_TTL_HOURS)).to_rfc3339();
+        sqlx::query(
+            r#"
+            DELETE FROM stream_journal_chunks
+            WHERE key IN (SELECT key FROM stream_journals WHERE created_at < ?)
+            "#,
+        )
+        .bind(&cutoff)
+        .execute(&self.pool)
+        .await?;
+        sqlx::query("DELETE FROM stream_journals WHERE created_at < ?")
+            .bind(&cutoff)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    pub async fn load(&self, key: &str) -> Result<Option<Journal>, sqlx::Error> {
+        let row: Option<JournalRow> =
+            sqlx::query_as("SELECT complete, streaming FROM stream_journals WHERE key = ?")
+                .bind(key)
+                .fetch_optional(&self.pool)
+                .await?;
+        let Some(row) = row else {
+            return Ok(None);
+        };
+        let chunks: Vec<ChunkRow> =
+            sqlx::query_as("SELECT data FROM stream_journal_chunks WHERE key = ? ORDER BY seq ASC")
+                .bind(key)
+                .fetch_all(&self.pool)
+                .await?;
+        Ok(Some(Journal {
+            complete: row.complete,
+            streaming: row.streaming,
+            data: chunks.into_iter().flat_map(|c| c.data).collect(),
+        }))
+    }
+
+    /// Start a new journal for the key, discarding any previous one
+    pub async fn begin(&self, key: &str, streaming: bool) -> Result<(), sqlx::Error> {
+        sqlx::query("DELETE FROM stream_journal_chunks WHERE key = ?")
+            .bind(key)
+            .execute(&self.pool)
+            .await?;
+        sqlx::query(
+            "INSERT OR REPLACE INTO stream_journals (key, created_at, complete, streaming) VALUES (?, ?, 0, ?)",
+        )
+        .bind(key)
+        .bind(Utc::now().to_rfc3339())
+        .bind(streaming)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    pub async fn append(&self, key: &str, seq: i64, data: &[u8]) -> Result<(), sqlx::Error> {
+        sqlx::query("INSERT INTO stream_journal_chunks (key, seq, data) VALUES (?, ?, ?)")
+            .bind(key)
+            .bind(seq)
+            .bind(data)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    pub async fn finish(&self, key: &str) -> Result<(), sqlx::Error> {
+        sqlx::query("UPDATE stream_journals SET complete = 1 WHERE key = ?")
+            .bind(key)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+}
+
+/// What to do with a request whose key already has a journal
+#[derive(Debug)]
+pub enum Resume {
+    /// The response finished; send it again as recorded, and whether it's
+    /// a stream
+    Replay(Bytes, bool),
+    /// Replay the journaled part and continue upstream from there
+    Continue(ResumePlan),
+    /// Nothing usable was journaled; forward the request as new
+    Restart,
+}
+
+#[derive(Debug)]
+pub struct ResumePlan {
+    /// Complete SSE events journaled before the interruption
+    replay: Bytes,
+    /// Assistant content to prefill the continuation request with
+    prefill: Vec<serde_json::Value>,
+    /// Index of the text block that was cut off mid-way, if any
+    open_block: Option<u64>,
+    /// Number of content blocks the journal started
+    block_count: u64,
+    /// Whitespace cut off the end of the open block's prefill, which the
+    /// client already has
+    trimmed: String,
+}
+
+impl ResumePlan {
+    /// The original request with the journaled text appended as an
+    /// assistant prefill, or None if the body isn't a messages request
+    pub fn continuation_body(&self, body: &[u8]) -> Option<Bytes> {
+        let mut request: serde_json::Value = serde_json::from_slice(body).ok()?;
+        let messages = request.get_mut("messages")?.as_array_mut()?;
+        messages.push(serde_json::json!({
+            "role": "assistant",
+            "content": self.prefill,
+        }));
+        serde_json::to_vec(&request).ok().map(Bytes::from)
+    }
+
+    pub fn journaled_bytes(&self) -> usize {
+        self.replay.len()
+    }
+
+    pub fn into_splice(self) -> (Bytes, Splice) {
+        let splice = Splice {
+            open_block: self.open_block,
+            block_count: self.block_count,
+            trimmed: self.trimmed,
+            buffer: Vec::new(),
+        };
+        (self.replay, splice)
+    }
+}
+
+struct JournalBlock {
+    text: String,
+    open: bool,
+}
+
+/// The events in an SSE body, as (event name, data) pairs. A trailing event
+/// without its blank line is incomplete and left out.
+fn sse_events(text: &str) -> Vec<(Option<&str>, &str)> {
+    let complete = match text.rfind("\n\n") {
+        Some(end) => &text[..end],
+        None => return Vec::new(),
+    };
+    complete
+        .split("\n\n")
+        .filter_map(|block| {
+            let mut name = None;
+            let mut data = None;
+            for line in block.lines() {
+                if let Some(value) = line.strip_prefix("event:") {
+                    name = Some(value.trim());
+                } else if let Some(value) = line.strip_prefix("data:") {
+                    data = Some(value.trim());
+                }
+            }
+            data.map(|data| (name, data))
+        })
+        .collect()
+}
+
+/// Decide how to resume from a journal
+pub fn plan(journal: &Journal) -> Resume {
+    if journal.complete {
+        return Resume::Replay(Bytes::copy_from_slice(&journal.data), journal.streaming);
+    }
+    // Only a stream can be picked up part way
+    if !journal.streaming {
+        return Resume::Restart;
+    }
+    let Some(end) = journal.data.windows(2).rposition(|w| w == b"\n\n") else {
+        return Resume::Restart;
+    };
+    let journaled = &journal.data[..end + 2];
+    let Ok(text) = std::str::from_utf8(journaled) else {
+        return Resume::Restart;
+    };
+
+    let mut started = false;
+    let mut blocks: Vec<JournalBlock> = Vec::new();
+    for (_, data) in sse_events(text) {
+        let Ok(event) = serde_json::from_str::<serde_json::Value>(data) else {
+            return Resume::Restart;
+        };
+        let index = event.get("index").and_then(|i| i.as_u64());
+        match event.get("type").and_then(|t| t.as_str()) {
+            Some("message_start") => started = true,
+            Some("content_block_start") => {
+                let block = &event["content_block"];
+                if block["type"] != "text" || index != Some(blocks.len() as u64) {
+                    return Resume::Restart;
+                }
+                blocks.push(JournalBlock {
+                    text: block["text"].as_str().unwrap_or_default().to_string(),
+                    open: true,
+                });
+            }
+            Some("content_block_delta") => {
+                let Some(block) = index.and_then(|i| blocks.get_mut(i as usize)) else {
+                    return Resume::Restart;
+                };
+                match event["delta"]["text"].as_str() {
+                    Some(text) if event["delta"]["type"] == "text_delta" => {
+                        block.text.push_str(text)
+                    }
+                    _ => return Resume::Restart,
+                }
+            }
+            Some("content_block_stop") => {
+                if let Some(block) = index.and_then(|i| blocks.get_mut(i as usize)) {
+                    block.open = false;
+                }
+            }
+            // The message finished; only the journal's completion flag was lost
+            Some("message_delta") | Some("message_stop") => {
+                return Resume::Replay(Bytes::copy_from_slice(journaled), true);
+            }
+            Some("ping") => {}
+            _ => return Resume::Restart,
+        }
+    }
+    if !started || blocks.is_empty() {
+        return Resume::Restart;
+    }
+
+    // The API rejects a prefill ending in whitespace. What's trimmed off an
+    // open block was already sent, so it's kept to drop from the
+    // continuation if the model writes it again
+    let mut prefill: Vec<String> = blocks.iter().map(|b| b.text.clone()).collect();
+    let mut trimmed = String::new();
+    if let Some(last) = prefill.last_mut() {
+        let kept = last.trim_end().len();
+        if blocks.last().is_some_and(|b| b.open) {
+            trimmed = last[kept..].to_string();
+        }
+        last.truncate(kept);
+    }
+    prefill.retain(|text| !text.is_empty());
+    if prefill.is_empty() {
+        return Resume::Restart;
+    }
+
+    Resume::Continue(ResumePlan {
+        replay: Bytes::copy_from_slice(journaled),
+        prefill: prefill
+            .into_iter()
+            .map(|text| serde_json::json!({"type": "text", "text": text}))
+            .collect(),
+        open_block: blocks.iter().position(|b| b.open).map(|i| i as u64),
+        block_count: blocks.len() as u64,
+        trimmed,
+    })
+}
+
+/// Rewrites a continuation stream to follow on from the journaled events:
+/// its `message_start` is dropped, its first block merges into a block that
+/// was cut off, and later blocks are renumbered after the journaled ones.
+pub struct Splice {
+    open_block: Option<u64>,
+    block_count: u64,
+    trimmed: String,
+    buffer: Vec<u8>,
+}
+
+impl Splice {
+    pub fn push(&mut self, chunk: &[u8]) -> Bytes {
+        self.buffer.extend_from_slice(chunk);
+        let Some(end) = self.buffer.windows(2).rposition(|w| w == b"\n\n") else {
+            return Bytes::new();
+        };
+        let complete: Vec<u8> = self.buffer.drain(..end + 2).collect();
+        let text = String::from_utf8_lossy(&complete);
+
+        let mut out = String::new();
+        for (name, data) in sse_events(&text) {
+            let Ok(mut event) = serde_json::from_str::<serde_json::Value>(data) else {
+                push_event(&mut out, name, data);
+                continue;
+            };
+            let kind = event
+                .get("type")
+                .and_then(|t| t.as_str())
+                .unwrap_or_default();
+            let index = event.get("index").and_then(|i| i.as_u64());
+            if kind == "message_start"
+                || (kind == "content_block_start" && index == Some(0) && self.open_block.is_some())
+            {
+                continue;
+            }
+            if kind == "content_block_delta" && index == Some(0) && !self.trimmed.is_empty() {
+                if let Some(text) = event["delta"]["text"].as_str() {
+                    let text = self.strip_trimmed(text);
+                    if text.is_empty() {
+                        continue;
+                    }
+                    event["delta"]["text"] = text.into();
+                }
+            }
+            if let Some(index) = index {
+                event["index"] = self.renumber(index).into();
+            }
+            push_event(&mut out, name, &event.to_string());
+        }
+        Bytes::from(out)
+    }
+
+    /// Whatever is left of an event the stream never finished
+    pub fn finish(self) -> Bytes {
+        Bytes::from(self.buffer)
+    }
+
+    /// The continuation's text without the leading whitespace the client
+    /// already got before the prefill was trimmed
+    fn strip_trimmed(&mut self, text: &str) -> String {
+        let matched: usize = self
+            .trimmed
+            .chars()
+            .zip(text.chars())
+            .take_while(|(a, b)| a == b)
+            .map(|(a, _)| a.len_utf8())
+            .sum();
+        if matched == text.len() {
+            self.trimmed.drain(..matched);
+        } else {
+            self.trimmed.clear();
+        }
+        text[matched..].to_string()
+    }
+
+    fn renumber(&self, index: u64) -> u64 {
+        match self.open_block {
+            Some(open) if index == 0 => open,
+            Some(_) => self.block_count + index - 1,
+            None => self.block_count + index,
+        }
+    }
+}
+
+fn push_event(out: &mut String, name: Option<&str>, data: &str) {
+    if let Some(name) = name {
+        out.push_str("event: ");
+        out.push_str(name);
+        out.push('\n');
+    }
+    out.push_str("data: ");
+    out.push_str(data);
+    out.push_str("\n\n");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(data: serde_json::Value) -> String {
+        format!(
+            "event: {}\ndata: {}\n\n",
+            data["type"].as_str().unwrap(),
+            data
+        )
+    }
+
+    #[test]
+    fn test_resume_prefills_and_renumbers_continuation() {
+        let journal = Journal {
+            complete: false,
+            streaming: true,
+            data: [
+                event(serde_json::json!({"type": "message_start", "message": {}})),
+                event(serde_json::json!({"type": "content_block_start", "index": 0, "content_block": {"type": "text", "text": ""}})),
+                event(serde_json::json!({"type": "content_block_delta", "index": 0, "delta": {"type": "text_delta", "text": "Hello, "}})),
+                "event: content_block_delta\ndata: {\"type\":\"content_bl".to_string(),
+            ]
+            .concat()
+            .into_bytes(),
+        };
+        let Resume::Continue(plan) = plan(&journal) else {
+            panic!("expected a continuation");
+        };
+
+        let body = plan
+            .continuation_body(
+                br#"{"model":"claude-sonnet-4","messages":[{"role":"user","content":"hi"}]}"#,
+            )
+            .unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["messages"][1]["role"], "assistant");
+        assert_eq!(body["messages"][1]["content"][0]["text"], "Hello,");
+
+        let (replay, mut splice) = plan.into_splice();
+        assert!(replay.ends_with(b"\n\n"));
+        assert!(!String::from_utf8_lossy(&replay).contains("content_bl\""));
+
+        let continuation = [
+            event(serde_json::json!({"type": "message_start", "message": {}})),
+            event(serde_json::json!({"type": "content_block_start", "index": 0, "content_block": {"type": "text", "text": ""}})),
+            event(serde_json::json!({"type": "content_block_delta", "index": 0, "delta": {"type": "text_delta", "text": " world"}})),
+            event(serde_json::json!({"type": "content_block_stop", "index": 0})),
+            event(serde_json::json!({"type": "content_block_start", "index": 1, "content_block": {"type": "text", "text": ""}})),
+            event(serde_json::json!({"type": "message_stop"})),
+        ]
+        .concat();
+        // Split mid-event to exercise buffering
+        let (a, b) = continuation.as_bytes().split_at(40);
+        let mut out = splice.push(a).to_vec();
+        out.extend_from_slice(&splice.push(b));
+        let out = String::from_utf8(out).unwrap();
+        // The space trimmed off the prefill already reached the client
+        assert!(out.contains(r#""text":"world""#));
+
+        let spliced: Vec<(String, Option<u64>)> = sse_events(&out)
+            .into_iter()
+            .map(|(_, data)| {
+                let event: serde_json::Value = serde_json::from_str(data).unwrap();
+                (
+                    event["type"].as_str().unwrap().to_string(),
+                    event["index"].as_u64(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            spliced,
+            vec![
+                ("content_block_delta".to_string(), Some(0)),
+                ("content_block_stop".to_string(), Some(0)),
+                ("content_block_start".to_string(), Some(1)),
+                ("message_stop".to_string(), None),
+            ]
+        );
+        assert!(splice.finish().is_empty());
+    }
+
+    #[test]
+    fn test_interrupted_tool_call_restarts() {
+        let journal = Journal {
+            complete: false,
+            streaming: true,
+            data: [
+                event(serde_json::json!({"type": "message_start", "message": {}})),
+                event(serde_json::json!({"type": "content_block_start", "index": 0, "content_block": {"type": "tool_use", "id": "t", "name": "Bash", "input": {}}})),
+            ]
+            .concat()
+            .into_bytes(),
+        };
+        assert!(matches!(plan(&journal), Resume::Restart));
+
+        let finished = Journal {
+            complete: true,
+            streaming: true,
+            data: event(serde_json::json!({"type": "message_stop"})).into_bytes(),
+        };
+        assert!(matches!(plan(&finished), Resume::Replay(_, true)));
+
+        // A whole JSON body has no event framing, but is replayed all the same
+        let body = br#"{"id":"msg_1","type":"message","content":[{"type":"text","text":"Hi"}]}"#;
+        let whole = Journal {
+            complete: true,
+            streaming: false,
+            data: body.to_vec(),
+        };
+        assert!(matches!(plan(&whole), Resume::Replay(ref b, false) if b[..] == body[..]));
+        let cut_off = Journal {
+            complete: false,
+            ..whole
+        };
+        assert!(matches!(plan(&cut_off), Resume::Restart));
+    }
+}
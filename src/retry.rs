@@ -0,0 +1,78 @@
+//! Retrying non-streaming requests the upstream turned away.
+//!
+//! With `--retries`, a non-streaming request that fails to connect or comes
+//! back 429, 503, or 529 is sent again with backoff. Those failures mean the
+//! upstream never ran the turn, so a retry can't bill it twice. Every attempt
+//! carries the same `idempotency-key` (the client's, or one generated for the
+//! request) so upstreams that honor the header dedupe anything that slipped
+//! through, and the key is recorded on the response event.
+//!
+//! Sentinel dedupes on its side too: a successful response to a request with
+//! a client-supplied key is journaled, and a repeat of that key is answered
+//! from the journal instead of going upstream again.
+
+use std::time::Duration;
+
+use crate::diagnostics::FailureCause;
+
+pub const IDEMPOTENCY_HEADER: &str = "idempotency-key";
+
+/// First retry waits this long; each later one doubles it
+const BASE_BACKOFF: Duration = Duration::from_millis(500);
+/// Longest `retry-after` honored before giving up on the retry
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(30);
+
+/// Statuses that mean the upstream rejected the request without running it
+pub fn is_retryable_status(status: u16) -> bool {
+    matches!(status, 429 | 503 | 529)
+}
+
+/// Failures that happen before the request reaches the upstream
+pub fn is_retryable_cause(cause: FailureCause) -> bool {
+    matches!(
+        cause,
+        FailureCause::Dns
+            | FailureCause::Tls
+            | FailureCause::ConnectTimeout
+            | FailureCause::ConnectionRefused
+            | FailureCause::Connect
+    )
+}
+
+/// How long to wait before retry number `attempt` (from 1). None when the
+/// upstream asked for a longer wait than is worth holding the client for.
+pub fn backoff(attempt: u32, retry_after: Option<&str>) -> Option<Duration> {
+    match retry_after.and_then(|v| v.trim().parse::<u64>().ok()) {
+        Some(secs) => {
+            let wait = Duration::from_secs(secs);
+            (wait <= MAX_RETRY_AFTER).then_some(wait)
+        }
+        None => Some(BASE_BACKOFF * 2u32.saturating_pow(attempt.saturating_sub(1))),
+    }
+}
+
+/// Whether a request body asks for a streamed response
+pub fn is_streaming_request(body: &[u8]) -> bool {
+    #[derive(serde::Deserialize)]
+    struct StreamFlag {
+        #[serde(default)]
+        stream: bool,
+    }
+    serde_json::from_slice::<StreamFlag>(body).is_ok_and(|r| r.stream)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backoff_doubles_and_honors_retry_after() {
+        assert_eq!(backoff(1, None), Some(Duration::from_millis(500)));
+        assert_eq!(backoff(3, None), Some(Duration::from_secs(2)));
+        assert_eq!(backoff(1, Some("4")), Some(Duration::from_secs(4)));
+        assert_eq!(backoff(1, Some("120")), None);
+
+        assert!(is_streaming_request(br#"{"model":"m","stream":true}"#));
+        assert!(!is_streaming_request(br#"{"model":"m"}"#));
+    }
+}
@@ -0,0 +1,391 @@
+//! Hourly usage rollups per agent and model, and `sentinel stats`.
+//!
+//! Every stored response or failed request also bumps its hour's row in
+//! `usage_rollups` in the same transaction, so totals over any range read a
+//! few rows per hour instead of every event's payload.
+
+use chrono::{DateTime, Duration, DurationRound, Utc};
+use clap::ValueEnum;
+use sqlx::{SqliteConnection, SqlitePool};
+
+use crate::derived;
+use crate::event::{ObservabilityEvent, Payload};
+use crate::pricing;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum StatsBy {
+    Agent,
+    Model,
+}
+
+impl StatsBy {
+    fn column(&self) -> &'static str {
+        match self {
+            StatsBy::Agent => "agent",
+            StatsBy::Model => "model",
+        }
+    }
+}
+
+/// Usage summed over a range for one agent or model
+#[derive(Debug, sqlx::FromRow)]
+pub struct UsageTotal {
+    /// Agent or model; empty when the events had none
+    pub group_key: String,
+    pub requests: i64,
+    pub errors: i64,
+    pub input_tokens: i64,
+    pub output_tokens: i64,
+    pub cache_read_tokens: i64,
+    pub cache_creation_tokens: i64,
+    pub cost_usd: f64,
+    /// Responses whose cost couldn't be estimated
+    pub unpriced: i64,
+}
+
+/// Usage summed over one UTC day for one model
+#[derive(Debug, sqlx::FromRow)]
+pub struct DailyModelTotal {
+    /// `YYYY-MM-DD`
+    pub day: String,
+    /// `group_key` is the model
+    #[sqlx(flatten)]
+    pub usage: UsageTotal,
+}
+
+/// What one event adds to its hour's row
+#[derive(Debug, Default, PartialEq)]
+struct Delta {
+    requests: i64,
+    errors: i64,
+    input_tokens: i64,
+    output_tokens: i64,
+    cache_read_tokens: i64,
+    cache_creation_tokens: i64,
+    cost_usd: f64,
+    unpriced: i64,
+}
+
+impl Delta {
+    /// None for events that aren't requests (prompts, alerts, violations)
+    fn of(event: &ObservabilityEvent) -> Option<(String, Self)> {
+        match &event.payload {
+            Payload::AssistantResponse(response) => {
+                let mut delta = Delta {
+                    requests: 1,
+                    ..Default::default()
+                };
+                if let Some(ref usage) = response.usage {
+                    delta.input_tokens = usage.input_tokens.unwrap_or(0);
+                    delta.output_tokens = usage.output_tokens.unwrap_or(0);
+                    delta.cache_read_tokens = usage.cache_read_tokens.unwrap_or(0);
+                    delta.cache_creation_tokens = usage.cache_creation_tokens.unwrap_or(0);
+                }
+                match response
+                    .usage
+                    .as_ref()
+                    .and_then(|u| pricing::cost_usd(response.model.as_deref(), u))
+                {
+                    Some(cost) => delta.cost_usd = cost,
+                    None => delta.unpriced = 1,
+                }
+                Some((response.model.clone().unwrap_or_default(), delta))
+            }
+            Payload::Error(_) => Some((
+                String::new(),
+                Delta {
+                    requests: 1,
+                    errors: 1,
+                    ..Default::default()
+                },
+            )),
+            Payload::UserMessage(_)
+            | Payload::Alert(_)
+            | Payload::Violation(_)
+            | Payload::SessionClosed(_)
+            | Payload::StreamDelta(_)
+            | Payload::StreamAborted(_)
+            | Payload::TurnSnapshot(_)
+            | Payload::Annotation(_) => None,
+        }
+    }
+}
+
+/// Start of the hour `at` falls in, as stored in the `hour` column
+fn hour_of(at: DateTime<Utc>) -> String {
+    at.duration_trunc(Duration::hours(1))
+        .unwrap_or(at)
+        .to_rfc3339()
+}
+
+/// Add an event to its hour's rollup; part of the event's insert transaction
+pub async fn record(
+    conn: &mut SqliteConnection,
+    event: &ObservabilityEvent,
+) -> Result<(), sqlx::Error> {
+    let Some((model, delta)) = Delta::of(event) else {
+        return Ok(());
+    };
+    sqlx::query(
+        r#"
+        INSERT INTO usage_rollups
+            (hour, agent, model, requests, errors, input_tokens, output_tokens,
+             cache_read_tokens, cache_creation_tokens, cost_usd, unpriced)
+        VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+        ON CONFLICT (hour, agent, model) DO UPDATE SET
+            requests = requests + excluded.requests,
+            errors = errors + excluded.errors,
+            input_tokens = input_tokens + excluded.input_tokens,
+            output_tokens = output_tokens + excluded.output_tokens,
+            cache_read_tokens = cache_read_tokens + excluded.cache_read_tokens,
+            cache_creation_tokens = cache_creation_tokens + excluded.cache_creation_tokens,
+            cost_usd = cost_usd + excluded.cost_usd,
+            unpriced = unpriced + excluded.unpriced
+        "#,
+    )
+    .bind(hour_of(event.timestamp))
+    .bind(event.agent.as_deref().unwrap_or(""))
+    .bind(model)
+    .bind(delta.requests)
+    .bind(delta.errors)
+    .bind(delta.input_tokens)
+    .bind(delta.output_tokens)
+    .bind(delta.cache_read_tokens)
+    .bind(delta.cache_creation_tokens)
+    .bind(delta.cost_usd)
+    .bind(delta.unpriced)
+    .execute(conn)
+    .await?;
+    Ok(())
+}
+
+#[derive(Clone)]
+pub struct RollupStore {
+    pool: SqlitePool,
+}
+
+impl RollupStore {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool }
+    }
+
+    /// Create the table; true when it's new and the hours already recorded
+    /// have to be summed into it
+    pub async fn init_schema(&self) -> Result<bool, sqlx::Error> {
+        // Absent agents and models are '' so they still take part in the key
+        derived::create(
+            &self.pool,
+            "usage_rollups",
+            r#"
+            CREATE TABLE IF NOT EXISTS usage_rollups (
+                hour TEXT NOT NULL,
+                agent TEXT NOT NULL,
+                model TEXT NOT NULL,
+                requests INTEGER NOT NULL,
+                errors INTEGER NOT NULL,
+                input_tokens INTEGER NOT NULL,
+                output_tokens INTEGER NOT NULL,
+                cache_read_tokens INTEGER NOT NULL,
+                cache_creation_tokens INTEGER NOT NULL,
+                cost_usd REAL NOT NULL,
+                unpriced INTEGER NOT NULL,
+                PRIMARY KEY (hour, agent, model)
+            )
+            "#,
+            |_| false,
+        )
+        .await
+    }
+
+    /// Totals per agent or model over the hours starting at or after `since`'s
+    pub async fn totals(
+        &self,
+        since: DateTime<Utc>,
+        by: StatsBy,
+    ) -> Result<Vec<UsageTotal>, sqlx::Error> {
+        sqlx::query_as(&format!(
+            r#"
+            SELECT {column} AS group_key,
+                   SUM(requests) AS requests,
+                   SUM(errors) AS errors,
+                   SUM(input_tokens) AS input_tokens,
+                   SUM(output_tokens) AS output_tokens,
+                   SUM(cache_read_tokens) AS cache_read_tokens,
+                   SUM(cache_creation_tokens) AS cache_creation_tokens,
+                   SUM(cost_usd) AS cost_usd,
+                   SUM(unpriced) AS unpriced
+            FROM usage_rollups
+            WHERE hour >= ?
+            GROUP BY {column}
+            ORDER BY cost_usd DESC, requests DESC
+            "#,
+            column = by.column()
+        ))
+        .bind(hour_of(since))
+        .fetch_all(&self.pool)
+        .await
+    }
+
+    /// Totals per UTC day and model, for comparing with a provider's reports
+    pub async fn totals_by_day_and_model(
+        &self,
+        since: DateTime<Utc>,
+    ) -> Result<Vec<DailyModelTotal>, sqlx::Error> {
+        // Hours are RFC 3339 in UTC, so the date is their first ten characters
+        sqlx::query_as(
+            r#"
+            SELECT substr(hour, 1, 10) AS day,
+                   model AS group_key,
+                   SUM(requests) AS requests,
+                   SUM(errors) AS errors,
+                   SUM(input_tokens) AS input_tokens,
+                   SUM(output_tokens) AS output_tokens,
+                   SUM(cache_read_tokens) AS cache_read_tokens,
+                   SUM(cache_creation_tokens) AS cache_creation_tokens,
+                   SUM(cost_usd) AS cost_usd,
+                   SUM(unpriced) AS unpriced
+            FROM usage_rollups
+            WHERE hour >= ?
+            GROUP BY day, model
+            ORDER BY day, model
+            "#,
+        )
+        .bind(hour_of(since))
+        .fetch_all(&self.pool)
+        .await
+    }
+}
+
+pub async fn run(
+    data_dir: &std::path::Path,
+    since: Duration,
+    by: StatsBy,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let db_path = data_dir.join("sentinel.db");
+    if !db_path.exists() {
+        println!("No events found. Run 'sentinel start' first to capture some traffic.");
+        return Ok(());
+    }
+    let storage = crate::storage::Storage::new(&db_path).await?;
+    let from = Utc::now() - since;
+    let totals = RollupStore::new(storage.pool()).totals(from, by).await?;
+    if totals.is_empty() {
+        println!("No requests since {}.", from.format("%Y-%m-%d %H:%M"));
+        return Ok(());
+    }
+
+    println!(
+        "Usage by {} since {}\n",
+        by.column(),
+        from.format("%Y-%m-%d %H:00")
+    );
+    println!(
+        "{:<32} {:>8} {:>7} {:>12} {:>12} {:>12} {:>12} {:>10}",
+        by.column(),
+        "requests",
+        "errors",
+        "input",
+        "output",
+        "cache read",
+        "cache write",
+        "cost"
+    );
+    for total in &totals {
+        let group: String = match total.group_key.as_str() {
+            "" => "(none)".to_string(),
+            key => key.chars().take(32).collect(),
+        };
+        println!(
+            "{:<32} {:>8} {:>7} {:>12} {:>12} {:>12} {:>12} {:>10}",
+            group,
+            total.requests,
+            total.errors,
+            total.input_tokens,
+            total.output_tokens,
+            total.cache_read_tokens,
+            total.cache_creation_tokens,
+            format!("${:.4}", total.cost_usd)
+        );
+    }
+
+    let unpriced: i64 = totals.iter().map(|t| t.unpriced).sum();
+    if unpriced > 0 {
+        println!(
+            "\n{} response(s) had no usage or an unknown model price and count as $0",
+            unpriced
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event::{AssistantResponse, ErrorEvent, ErrorKind};
+    use crate::parsers::Usage;
+    use crate::storage::Storage;
+    use chrono::TimeZone;
+    use uuid::Uuid;
+
+    fn response(input_tokens: i64) -> Payload {
+        Payload::AssistantResponse(AssistantResponse {
+            streaming: true,
+            model: Some("claude-sonnet-4".to_string()),
+            stop_reason: Some("end_turn".to_string()),
+            usage: Some(Usage {
+                input_tokens: Some(input_tokens),
+                output_tokens: Some(10),
+                cache_read_tokens: None,
+                cache_creation_tokens: None,
+                cost_usd: Some(0.5),
+            }),
+            ..Default::default()
+        })
+    }
+
+    #[tokio::test]
+    async fn test_inserts_keep_hourly_rollups() {
+        let path = std::env::temp_dir().join(format!("sentinel-test-{}.db", Uuid::new_v4()));
+        let storage = Storage::new(&path).await.unwrap();
+        storage
+            .insert_observability_event(&ObservabilityEvent::for_tests(5 * 60, response(100)))
+            .await
+            .unwrap();
+        storage
+            .insert_observability_event(&ObservabilityEvent::for_tests(50 * 60, response(200)))
+            .await
+            .unwrap();
+        let error = Payload::Error(ErrorEvent {
+            kind: ErrorKind::Forward,
+            cause: None,
+            status: None,
+            message: "connection refused".to_string(),
+            body: None,
+        });
+        storage
+            .insert_observability_event(&ObservabilityEvent::for_tests(55 * 60, error))
+            .await
+            .unwrap();
+
+        let since = Utc.with_ymd_and_hms(2026, 1, 5, 10, 30, 0).unwrap();
+        let totals = RollupStore::new(storage.pool())
+            .totals(since, StatsBy::Agent)
+            .await
+            .unwrap();
+        assert_eq!(totals.len(), 1);
+        let total = &totals[0];
+        assert_eq!(total.group_key, "calm-otter");
+        assert_eq!((total.requests, total.errors), (3, 1));
+        assert_eq!((total.input_tokens, total.output_tokens), (300, 20));
+        assert_eq!(total.cost_usd, 1.0);
+
+        let by_model = RollupStore::new(storage.pool())
+            .totals(since, StatsBy::Model)
+            .await
+            .unwrap();
+        assert_eq!(by_model[0].group_key, "claude-sonnet-4");
+        assert_eq!(by_model[1].group_key, "");
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,595 @@
+//! Upstream routing.
+//!
+//! Requests are routed by path prefix: `/openrouter/v1/chat/completions` is
+//! forwarded to OpenRouter as `/v1/chat/completions` and parsed with the
+//! OpenAI-compatible parser. Paths without a known prefix go to Anthropic.
+//!
+//! Unprefixed paths are ambiguous once several providers share shapes like
+//! `/v1/chat/completions`, so the provider is detected from the request's
+//! host, auth headers, path, and body fields before falling back to Anthropic.
+//! The host matches when it is a provider's own API host, or when its first
+//! label names the provider (`openai.localhost:9000`). When nothing identifies
+//! the provider, the response's shape picks the parser instead.
+//!
+//! Gemini's `/v1beta/models/{model}:streamGenerateContent` paths are
+//! recognized without a prefix, so Google SDKs pointed at sentinel work as-is.
+//!
+//! Azure OpenAI is registered under `/azure` only when
+//! `SENTINEL_AZURE_OPENAI_URL` points at a resource endpoint, since every
+//! Azure resource has its own host.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use axum::http::HeaderMap;
+use serde::de::IgnoredAny;
+use serde::Deserialize;
+
+use crate::config;
+use crate::parsers::{AnthropicParser, GeminiParser, OpenAiParser, ResponseParser};
+
+const ANTHROPIC_API_URL: &str = "https://api.anthropic.com";
+const OPENAI_API_URL: &str = "https://api.openai.com";
+const OPENROUTER_API_URL: &str = "https://openrouter.ai/api";
+const MISTRAL_API_URL: &str = "https://api.mistral.ai";
+const DEEPSEEK_API_URL: &str = "https://api.deepseek.com";
+const GEMINI_API_URL: &str = "https://generativelanguage.googleapis.com";
+/// LiteLLM is self-hosted; this is its default proxy address.
+const LITELLM_DEFAULT_URL: &str = "http://localhost:4000";
+/// Latest GA Azure OpenAI data-plane version, used when the client sends none
+const AZURE_DEFAULT_API_VERSION: &str = "2024-10-21";
+
+/// Where a request is forwarded and how its response is parsed
+pub struct Upstream {
+    pub base_url: String,
+    pub parser: Arc<dyn ResponseParser>,
+    pub azure: Option<AzureSettings>,
+}
+
+impl Upstream {
+    fn new(base_url: &str, parser: Arc<dyn ResponseParser>) -> Self {
+        Self {
+            base_url: base_url.trim_end_matches('/').to_string(),
+            parser,
+            azure: None,
+        }
+    }
+}
+
+/// Azure addresses models by deployment name in the path
+/// (`/openai/deployments/{name}/chat/completions`) and requires an
+/// `api-version` query parameter. Deployment names are arbitrary, so they are
+/// mapped back to canonical model names for cost and stats.
+pub struct AzureSettings {
+    pub api_version: String,
+    pub deployments: HashMap<String, String>,
+}
+
+impl AzureSettings {
+    fn from_env() -> Self {
+        let api_version = config::var("SENTINEL_AZURE_API_VERSION")
+            .unwrap_or_else(|| AZURE_DEFAULT_API_VERSION.to_string());
+        let deployments = config::var("SENTINEL_AZURE_DEPLOYMENTS")
+            .map(|spec| parse_deployments(&spec))
+            .unwrap_or_default();
+        Self {
+            api_version,
+            deployments,
+        }
+    }
+
+    /// Canonical model for the deployment in `path`, falling back to the
+    /// deployment name itself when it isn't mapped.
+    fn model_for_path(&self, path: &str) -> Option<String> {
+        let deployment = deployment_from_path(path)?;
+        Some(
+            self.deployments
+                .get(deployment)
+                .cloned()
+                .unwrap_or_else(|| deployment.to_string()),
+        )
+    }
+}
+
+/// Parse `deployment=model,deployment=model` pairs.
+fn parse_deployments(spec: &str) -> HashMap<String, String> {
+    let mut deployments = HashMap::new();
+    for pair in spec.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+        match pair.split_once('=') {
+            Some((deployment, model)) => {
+                deployments.insert(deployment.trim().to_string(), model.trim().to_string());
+            }
+            None => tracing::warn!(
+                "Ignoring Azure deployment mapping '{}': expected deployment=model",
+                pair
+            ),
+        }
+    }
+    deployments
+}
+
+fn deployment_from_path(path: &str) -> Option<&str> {
+    let rest = path.strip_prefix("/openai/deployments/")?;
+    let deployment = rest.split('/').next()?;
+    if deployment.is_empty() {
+        None
+    } else {
+        Some(deployment)
+    }
+}
+
+/// A request resolved to its upstream
+pub struct Resolved<'a> {
+    pub upstream: &'a Upstream,
+    pub url: String,
+    /// Canonical model name when the path, not the body, identifies the model
+    pub model: Option<String>,
+    /// False when nothing identified the provider and the default was assumed
+    pub confident: bool,
+}
+
+struct Route {
+    prefix: &'static str,
+    upstream: Upstream,
+}
+
+pub struct Routes {
+    routes: Vec<Route>,
+    default: Upstream,
+    /// Send everything to the default upstream without detecting the provider
+    pinned: bool,
+}
+
+impl Routes {
+    pub fn new() -> Self {
+        let litellm_url = config::var("SENTINEL_LITELLM_URL")
+            .unwrap_or_else(|| LITELLM_DEFAULT_URL.to_string());
+
+        let mut routes = vec![
+            Route {
+                prefix: "/openai",
+                upstream: Upstream::new(OPENAI_API_URL, Arc::new(OpenAiParser::openai())),
+            },
+            Route {
+                prefix: "/openrouter",
+                upstream: Upstream::new(OPENROUTER_API_URL, Arc::new(OpenAiParser::openrouter())),
+            },
+            Route {
+                prefix: "/mistral",
+                upstream: Upstream::new(MISTRAL_API_URL, Arc::new(OpenAiParser::mistral())),
+            },
+            Route {
+                prefix: "/deepseek",
+                upstream: Upstream::new(DEEPSEEK_API_URL, Arc::new(OpenAiParser::deepseek())),
+            },
+            Route {
+                prefix: "/gemini",
+                upstream: Upstream::new(GEMINI_API_URL, Arc::new(GeminiParser::new())),
+            },
+            Route {
+                prefix: "/litellm",
+                upstream: Upstream::new(&litellm_url, Arc::new(OpenAiParser::litellm())),
+            },
+        ];
+
+        if let Some(azure_url) = config::var("SENTINEL_AZURE_OPENAI_URL") {
+            let mut upstream = Upstream::new(&azure_url, Arc::new(OpenAiParser::azure()));
+            upstream.azure = Some(AzureSettings::from_env());
+            routes.push(Route {
+                prefix: "/azure",
+                upstream,
+            });
+        }
+
+        Self {
+            routes,
+            default: Upstream::new(ANTHROPIC_API_URL, Arc::new(AnthropicParser::new())),
+            pinned: false,
+        }
+    }
+
+    /// Point the default upstream somewhere else, such as a corporate
+    /// gateway or a staging endpoint
+    pub fn with_default_url(mut self, url: &str) -> Self {
+        self.default.base_url = url.trim_end_matches('/').to_string();
+        self
+    }
+
+    /// Routes that send every request to one provider, for a listener
+    /// dedicated to it. None if the provider isn't configured.
+    pub fn pinned(provider: &str) -> Option<Self> {
+        let mut all = Self::new();
+        let upstream = if all.default.parser.provider() == provider {
+            all.default
+        } else {
+            let index = all
+                .routes
+                .iter()
+                .position(|route| route.upstream.parser.provider() == provider)?;
+            all.routes.swap_remove(index).upstream
+        };
+        Some(Self {
+            routes: Vec::new(),
+            default: upstream,
+            pinned: true,
+        })
+    }
+
+    /// Pick the upstream for a request and build the URL to forward to.
+    pub fn resolve(
+        &self,
+        path: &str,
+        query: Option<&str>,
+        headers: &HeaderMap,
+        body: &[u8],
+    ) -> Resolved<'_> {
+        let (upstream, forward_path, confident) = match self.match_prefix(path) {
+            Some((upstream, rest)) => (upstream, rest, true),
+            None if self.pinned => (&self.default, path, true),
+            None => {
+                let detected = self
+                    .find_by_host(headers)
+                    .or_else(|| self.find_provider(detect_provider(path, headers, body)?));
+                match detected {
+                    Some(upstream) => (upstream, path, true),
+                    None => (&self.default, path, false),
+                }
+            }
+        };
+
+        let mut query = query.map(String::from);
+        let mut model = None;
+        if let Some(ref azure) = upstream.azure {
+            model = azure.model_for_path(forward_path);
+            if !has_query_param(query.as_deref(), "api-version") {
+                let param = format!("api-version={}", azure.api_version);
+                query = Some(match query {
+                    Some(q) if !q.is_empty() => format!("{}&{}", q, param),
+                    _ => param,
+                });
+            }
+        }
+
+        let url = match query {
+            Some(q) => format!("{}{}?{}", upstream.base_url, forward_path, q),
+            None => format!("{}{}", upstream.base_url, forward_path),
+        };
+
+        Resolved {
+            upstream,
+            url,
+            model,
+            confident,
+        }
+    }
+
+    /// The parser for a response body whose provider wasn't known up front
+    pub fn parser_for_response(
+        &self,
+        json: &serde_json::Value,
+    ) -> Option<Arc<dyn ResponseParser>> {
+        // Anthropic's check is the loosest, so it goes last
+        self.routes
+            .iter()
+            .map(|route| &route.upstream)
+            .chain(std::iter::once(&self.default))
+            .find(|upstream| upstream.parser.is_response(json))
+            .map(|upstream| upstream.parser.clone())
+    }
+
+    /// The parser for a streamed response, judged by its first event
+    pub fn parser_for_stream(&self, raw: &str) -> Option<Arc<dyn ResponseParser>> {
+        let first = raw
+            .lines()
+            .filter_map(|line| line.strip_prefix("data:"))
+            .find_map(|data| serde_json::from_str(data.trim()).ok())?;
+        self.parser_for_response(&first)
+    }
+
+    fn upstreams(&self) -> impl Iterator<Item = &Upstream> {
+        std::iter::once(&self.default).chain(self.routes.iter().map(|route| &route.upstream))
+    }
+
+    /// The upstream a request was addressed to by host: the provider's own
+    /// API host (DNS pointed at sentinel) or `{provider}.localhost`
+    fn find_by_host(&self, headers: &HeaderMap) -> Option<&Upstream> {
+        let host = headers.get("host")?.to_str().ok()?;
+        let host = host.split(':').next().unwrap_or(host);
+        if host == "localhost" || host.parse::<std::net::IpAddr>().is_ok() {
+            return None;
+        }
+        self.upstreams().find(|upstream| {
+            let base = upstream
+                .base_url
+                .split("://")
+                .nth(1)
+                .and_then(|rest| rest.split(['/', ':']).next());
+            base == Some(host) || host.split('.').next() == Some(upstream.parser.provider())
+        })
+    }
+
+    fn match_prefix<'a>(&self, path: &'a str) -> Option<(&Upstream, &'a str)> {
+        for route in &self.routes {
+            if let Some(rest) = path.strip_prefix(route.prefix) {
+                if rest.is_empty() || rest.starts_with('/') {
+                    return Some((&route.upstream, rest));
+                }
+            }
+        }
+        None
+    }
+
+    fn find_provider(&self, provider: &str) -> Option<&Upstream> {
+        self.upstreams()
+            .find(|upstream| upstream.parser.provider() == provider)
+    }
+}
+
+/// Top-level request fields that only one API family uses. Unknown fields are
+/// skipped without allocating.
+#[derive(Deserialize)]
+struct RequestShape {
+    system: Option<IgnoredAny>,
+    anthropic_version: Option<IgnoredAny>,
+    top_k: Option<IgnoredAny>,
+    stop_sequences: Option<IgnoredAny>,
+    thinking: Option<IgnoredAny>,
+    stream_options: Option<IgnoredAny>,
+    response_format: Option<IgnoredAny>,
+    max_completion_tokens: Option<IgnoredAny>,
+    n: Option<IgnoredAny>,
+    logprobs: Option<IgnoredAny>,
+    presence_penalty: Option<IgnoredAny>,
+    frequency_penalty: Option<IgnoredAny>,
+}
+
+impl RequestShape {
+    fn is_anthropic(&self) -> bool {
+        self.system.is_some()
+            || self.anthropic_version.is_some()
+            || self.top_k.is_some()
+            || self.stop_sequences.is_some()
+            || self.thinking.is_some()
+    }
+
+    fn is_openai(&self) -> bool {
+        self.stream_options.is_some()
+            || self.response_format.is_some()
+            || self.max_completion_tokens.is_some()
+            || self.n.is_some()
+            || self.logprobs.is_some()
+            || self.presence_penalty.is_some()
+            || self.frequency_penalty.is_some()
+    }
+}
+
+/// Detect the provider of an unprefixed request, strongest signal first:
+/// provider-specific headers, API key prefixes, the endpoint path, then
+/// request fields only one API family accepts.
+pub fn detect_provider(path: &str, headers: &HeaderMap, body: &[u8]) -> Option<&'static str> {
+    if headers.contains_key("anthropic-version") || headers.contains_key("x-api-key") {
+        return Some("anthropic");
+    }
+    if headers.contains_key("api-key") {
+        return Some("azure");
+    }
+    if headers.contains_key("x-goog-api-key") {
+        return Some("gemini");
+    }
+
+    let bearer = headers
+        .get("authorization")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "));
+    match bearer {
+        Some(key) if key.starts_with("sk-ant-") => return Some("anthropic"),
+        Some(key) if key.starts_with("sk-or-") => return Some("openrouter"),
+        _ => {}
+    }
+
+    if path.ends_with("/messages") || path.ends_with("/messages/count_tokens") {
+        return Some("anthropic");
+    }
+    if path.ends_with(":generateContent") || path.ends_with(":streamGenerateContent") {
+        return Some("gemini");
+    }
+    if path.ends_with("/chat/completions") || path.ends_with("/completions") {
+        return Some("openai");
+    }
+
+    let shape: RequestShape = serde_json::from_slice(body).ok()?;
+    if shape.is_anthropic() {
+        Some("anthropic")
+    } else if shape.is_openai() || bearer.is_some() {
+        Some("openai")
+    } else {
+        None
+    }
+}
+
+impl Default for Routes {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn has_query_param(query: Option<&str>, name: &str) -> bool {
+    query.is_some_and(|q| {
+        q.split('&')
+            .any(|pair| pair.split('=').next() == Some(name))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+
+    fn azure_routes() -> Routes {
+        let mut upstream = Upstream::new(
+            "https://example.openai.azure.com/",
+            Arc::new(OpenAiParser::azure()),
+        );
+        upstream.azure = Some(AzureSettings {
+            api_version: "2024-10-21".to_string(),
+            deployments: parse_deployments("prod-4o=gpt-4o, cheap=gpt-4o-mini"),
+        });
+        Routes {
+            routes: vec![Route {
+                prefix: "/azure",
+                upstream,
+            }],
+            default: Upstream::new(ANTHROPIC_API_URL, Arc::new(AnthropicParser::new())),
+            pinned: false,
+        }
+    }
+
+    #[test]
+    fn test_default_url_override() {
+        let routes = Routes::new().with_default_url("https://gateway.internal/anthropic/");
+        let resolved = routes.resolve("/v1/messages", None, &HeaderMap::new(), b"{}");
+        assert_eq!(
+            resolved.url,
+            "https://gateway.internal/anthropic/v1/messages"
+        );
+        assert_eq!(resolved.upstream.parser.provider(), "anthropic");
+    }
+
+    #[test]
+    fn test_pinned_routes_skip_detection() {
+        let routes = Routes::pinned("openai").unwrap();
+        let mut headers = HeaderMap::new();
+        headers.insert("anthropic-version", HeaderValue::from_static("2023-06-01"));
+        let resolved = routes.resolve("/v1/chat/completions", None, &headers, b"{}");
+        assert_eq!(resolved.url, "https://api.openai.com/v1/chat/completions");
+        assert_eq!(resolved.upstream.parser.provider(), "openai");
+        assert!(Routes::pinned("nonexistent").is_none());
+    }
+
+    #[test]
+    fn test_azure_adds_api_version_and_maps_deployment() {
+        let routes = azure_routes();
+        let resolved = routes.resolve(
+            "/azure/openai/deployments/prod-4o/chat/completions",
+            None,
+            &HeaderMap::new(),
+            b"{}",
+        );
+        assert_eq!(
+            resolved.url,
+            "https://example.openai.azure.com/openai/deployments/prod-4o/chat/completions?api-version=2024-10-21"
+        );
+        assert_eq!(resolved.model, Some("gpt-4o".to_string()));
+    }
+
+    #[test]
+    fn test_azure_keeps_client_api_version() {
+        let routes = azure_routes();
+        let resolved = routes.resolve(
+            "/azure/openai/deployments/unmapped/chat/completions",
+            Some("api-version=2025-01-01-preview"),
+            &HeaderMap::new(),
+            b"{}",
+        );
+        assert!(resolved.url.ends_with("?api-version=2025-01-01-preview"));
+        assert_eq!(resolved.model, Some("unmapped".to_string()));
+    }
+
+    #[test]
+    fn test_unprefixed_paths_go_to_default() {
+        let routes = azure_routes();
+        let resolved = routes.resolve("/v1/messages", Some("beta=true"), &HeaderMap::new(), b"{}");
+        assert_eq!(
+            resolved.url,
+            "https://api.anthropic.com/v1/messages?beta=true"
+        );
+        assert_eq!(resolved.model, None);
+    }
+
+    #[test]
+    fn test_detect_provider_from_headers() {
+        let mut headers = HeaderMap::new();
+        headers.insert("anthropic-version", HeaderValue::from_static("2023-06-01"));
+        assert_eq!(
+            detect_provider("/v1/chat/completions", &headers, b"{}"),
+            Some("anthropic")
+        );
+
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            "authorization",
+            HeaderValue::from_static("Bearer sk-or-v1-abc"),
+        );
+        assert_eq!(
+            detect_provider("/v1/chat/completions", &headers, b"{}"),
+            Some("openrouter")
+        );
+    }
+
+    #[test]
+    fn test_gemini_paths_are_detected() {
+        let routes = Routes::new();
+        let resolved = routes.resolve(
+            "/v1beta/models/gemini-2.5-pro:streamGenerateContent",
+            Some("alt=sse"),
+            &HeaderMap::new(),
+            br#"{"contents":[]}"#,
+        );
+        assert_eq!(
+            resolved.url,
+            "https://generativelanguage.googleapis.com/v1beta/models/gemini-2.5-pro:streamGenerateContent?alt=sse"
+        );
+        assert_eq!(resolved.upstream.parser.provider(), "gemini");
+    }
+
+    #[test]
+    fn test_detect_provider_from_host() {
+        let routes = Routes::new();
+        let mut headers = HeaderMap::new();
+        headers.insert("host", HeaderValue::from_static("openai.localhost:9000"));
+        let resolved = routes.resolve("/v1/responses", None, &headers, b"{}");
+        assert_eq!(resolved.url, "https://api.openai.com/v1/responses");
+        assert!(resolved.confident);
+
+        headers.insert("host", HeaderValue::from_static("api.deepseek.com"));
+        let resolved = routes.resolve("/v1/responses", None, &headers, b"{}");
+        assert_eq!(resolved.upstream.parser.provider(), "deepseek");
+
+        headers.insert("host", HeaderValue::from_static("localhost:9000"));
+        let resolved = routes.resolve("/v1/responses", None, &headers, b"{}");
+        assert_eq!(resolved.upstream.parser.provider(), "anthropic");
+        assert!(!resolved.confident);
+    }
+
+    #[test]
+    fn test_parser_from_response_shape() {
+        let routes = Routes::new();
+        let openai = serde_json::json!({"id": "c1", "choices": []});
+        assert_eq!(
+            routes.parser_for_response(&openai).unwrap().provider(),
+            "openai"
+        );
+
+        let stream = "event: message_start\ndata: {\"type\":\"message_start\",\"message\":{}}\n\n";
+        assert_eq!(
+            routes.parser_for_stream(stream).unwrap().provider(),
+            "anthropic"
+        );
+        assert!(routes.parser_for_stream("data: [DONE]\n").is_none());
+    }
+
+    #[test]
+    fn test_detect_provider_from_body_fields() {
+        let headers = HeaderMap::new();
+        let anthropic = br#"{"model":"m","system":"be brief","messages":[]}"#;
+        assert_eq!(
+            detect_provider("/proxy", &headers, anthropic),
+            Some("anthropic")
+        );
+
+        let openai = br#"{"model":"m","stream_options":{"include_usage":true},"messages":[]}"#;
+        assert_eq!(detect_provider("/proxy", &headers, openai), Some("openai"));
+
+        assert_eq!(detect_provider("/proxy", &headers, b"{}"), None);
+    }
+}
@@ -0,0 +1,373 @@
+//! JSON-RPC control interface for editor integrations (`POST /api/rpc`).
+//!
+//! One endpoint speaking JSON-RPC 2.0, so a VS Code or Neovim plugin needs
+//! no more than an HTTP client to embed sentinel's views. A client calls
+//! `initialize` with the protocol version it was written against and gets
+//! back the server's, or an error if they don't match; the other methods are
+//! `events.query`, `events.tail` (polling by `seq`), `approvals.list`, and
+//! `approvals.decide`. Calls must be sent as `application/json`, and none
+//! are taken from pages on other sites (see `origin`), so a website open in
+//! the user's browser can't approve a held request.
+
+use std::sync::Arc;
+
+use axum::body::Bytes;
+use axum::extract::State;
+use axum::http::{HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use uuid::Uuid;
+
+use crate::approvals::Decision;
+use crate::event::ObservabilityEvent;
+use crate::origin;
+use crate::proxy::ProxyState;
+
+/// Bumped when a method is removed or changes shape; new methods and fields
+/// don't change it
+pub const PROTOCOL_VERSION: u32 = 1;
+
+const METHODS: &[&str] = &[
+    "initialize",
+    "events.query",
+    "events.tail",
+    "approvals.list",
+    "approvals.decide",
+];
+
+/// Most events one call returns
+const MAX_LIMIT: i64 = 1000;
+const DEFAULT_LIMIT: i64 = 100;
+
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+const INTERNAL_ERROR: i64 = -32603;
+/// The client speaks a protocol version this server doesn't
+const UNSUPPORTED_VERSION: i64 = -32000;
+
+#[derive(Debug, Deserialize)]
+struct RpcRequest {
+    jsonrpc: String,
+    /// Absent for notifications, which get no response
+    #[serde(default)]
+    id: Option<Value>,
+    method: String,
+    #[serde(default)]
+    params: Value,
+}
+
+#[derive(Debug, Serialize)]
+struct RpcError {
+    code: i64,
+    message: String,
+}
+
+impl RpcError {
+    fn new(code: i64, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+        }
+    }
+}
+
+impl From<sqlx::Error> for RpcError {
+    fn from(e: sqlx::Error) -> Self {
+        Self::new(INTERNAL_ERROR, format!("storage: {}", e))
+    }
+}
+
+fn response(id: Value, result: Result<Value, RpcError>) -> Response {
+    let body = match result {
+        Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+        Err(error) => json!({ "jsonrpc": "2.0", "id": id, "error": error }),
+    };
+    Json(body).into_response()
+}
+
+fn params<T: DeserializeOwned + Default>(params: Value) -> Result<T, RpcError> {
+    if params.is_null() {
+        return Ok(T::default());
+    }
+    serde_json::from_value(params).map_err(|e| RpcError::new(INVALID_PARAMS, e.to_string()))
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct InitializeParams {
+    protocol_version: Option<u32>,
+}
+
+fn initialize(params: InitializeParams) -> Result<Value, RpcError> {
+    match params.protocol_version {
+        Some(PROTOCOL_VERSION) => Ok(json!({
+            "protocol_version": PROTOCOL_VERSION,
+            "server_version": env!("CARGO_PKG_VERSION"),
+            "methods": METHODS,
+        })),
+        Some(version) => Err(RpcError::new(
+            UNSUPPORTED_VERSION,
+            format!(
+                "protocol version {} is not supported; this server speaks {}",
+                version, PROTOCOL_VERSION
+            ),
+        )),
+        None => Err(RpcError::new(
+            INVALID_PARAMS,
+            "protocol_version is required",
+        )),
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct QueryParams {
+    agent: Option<String>,
+    session_id: Option<String>,
+    /// Text to search prompts and responses for
+    search: Option<String>,
+    limit: Option<i64>,
+}
+
+fn limit(limit: Option<i64>) -> i64 {
+    limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT)
+}
+
+async fn query(state: &ProxyState, params: QueryParams) -> Result<Value, RpcError> {
+    let limit = limit(params.limit);
+    let events = if let Some(ref session_id) = params.session_id {
+        state.storage.get_session_events(session_id).await?
+    } else if let Some(ref agent) = params.agent {
+        state.storage.get_agent_events(agent, limit).await?
+    } else if let Some(ref text) = params.search {
+        state.storage.search_events(text, limit).await?
+    } else {
+        let mut events = state.storage.get_recent_observability_events(limit).await?;
+        events.reverse();
+        events
+    };
+    Ok(json!({ "events": events }))
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct TailParams {
+    /// Last `seq` the client has; omitted on the first call, which returns
+    /// no events and the `seq` to continue from
+    after_seq: Option<i64>,
+    limit: Option<i64>,
+}
+
+async fn tail(state: &ProxyState, params: TailParams) -> Result<Value, RpcError> {
+    let events: Vec<ObservabilityEvent> = match params.after_seq {
+        Some(seq) => {
+            state
+                .storage
+                .get_events_after_seq(seq, limit(params.limit))
+                .await?
+        }
+        None => Vec::new(),
+    };
+    let latest_seq = match events.last() {
+        Some(event) => event.seq,
+        None if params.after_seq.is_none() => state
+            .storage
+            .get_recent_observability_events(1)
+            .await?
+            .first()
+            .and_then(|e| e.seq),
+        None => params.after_seq,
+    };
+    Ok(json!({ "events": events, "latest_seq": latest_seq.unwrap_or(0) }))
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct DecideParams {
+    id: Option<Uuid>,
+    /// "approve" or "deny"
+    decision: Option<String>,
+}
+
+fn decide(state: &ProxyState, params: DecideParams) -> Result<Value, RpcError> {
+    let id = params
+        .id
+        .ok_or_else(|| RpcError::new(INVALID_PARAMS, "id is required"))?;
+    let decision = match params.decision.as_deref() {
+        Some("approve") => Decision::Approved,
+        Some("deny") => Decision::Denied,
+        _ => {
+            return Err(RpcError::new(
+                INVALID_PARAMS,
+                "decision must be \"approve\" or \"deny\"",
+            ))
+        }
+    };
+    let decided = state.approvals.decide(id, decision);
+    if decided {
+        tracing::info!("Approval {} {:?} via RPC", id, decision);
+    }
+    Ok(json!({ "decided": decided }))
+}
+
+async fn call(state: &ProxyState, request: RpcRequest) -> Result<Value, RpcError> {
+    match request.method.as_str() {
+        "initialize" => initialize(params(request.params)?),
+        "events.query" => query(state, params(request.params)?).await,
+        "events.tail" => tail(state, params(request.params)?).await,
+        "approvals.list" => Ok(json!({ "approvals": state.approvals.pending() })),
+        "approvals.decide" => decide(state, params(request.params)?),
+        method => Err(RpcError::new(
+            METHOD_NOT_FOUND,
+            format!("unknown method '{}'", method),
+        )),
+    }
+}
+
+pub async fn rpc_handler(
+    State(state): State<Arc<ProxyState>>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    if let Err(refusal) = origin::check(&headers, true, &state.allowed_origins) {
+        return refusal.into_response();
+    }
+    let request: RpcRequest = match serde_json::from_slice::<Value>(&body) {
+        Ok(value) => match serde_json::from_value(value) {
+            Ok(request) => request,
+            Err(e) => {
+                return response(
+                    Value::Null,
+                    Err(RpcError::new(INVALID_REQUEST, e.to_string())),
+                )
+            }
+        },
+        Err(e) => return response(Value::Null, Err(RpcError::new(PARSE_ERROR, e.to_string()))),
+    };
+    if request.jsonrpc != "2.0" {
+        let id = request.id.unwrap_or(Value::Null);
+        return response(
+            id,
+            Err(RpcError::new(INVALID_REQUEST, "jsonrpc must be \"2.0\"")),
+        );
+    }
+    let id = request.id.clone();
+    let result = call(&state, request).await;
+    match id {
+        Some(id) => response(id, result),
+        None => StatusCode::NO_CONTENT.into_response(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::approvals::PendingApproval;
+    use crate::event::{Payload, UserMessage};
+    use axum::http::header;
+    use http_body_util::BodyExt;
+
+    async fn rpc(state: &Arc<ProxyState>, headers: HeaderMap, body: Value) -> (StatusCode, Value) {
+        let body = Bytes::from(body.to_string());
+        let response = rpc_handler(State(state.clone()), headers, body).await;
+        let status = response.status();
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        (status, serde_json::from_slice(&body).unwrap_or(Value::Null))
+    }
+
+    fn json_headers() -> HeaderMap {
+        HeaderMap::from_iter([(header::CONTENT_TYPE, "application/json".parse().unwrap())])
+    }
+
+    #[tokio::test]
+    async fn test_methods_answer_local_json_calls_only() {
+        let state = ProxyState::for_tests().await;
+        let call = |method: &str, params: Value| {
+            json!({ "jsonrpc": "2.0", "id": 1, "method": method, "params": params })
+        };
+
+        let (status, _) = rpc(&state, HeaderMap::new(), call("approvals.list", Value::Null)).await;
+        assert_eq!(status, StatusCode::UNSUPPORTED_MEDIA_TYPE);
+        let mut cross_site = json_headers();
+        cross_site.insert(header::ORIGIN, "https://evil.example".parse().unwrap());
+        let (status, _) = rpc(&state, cross_site, call("approvals.list", Value::Null)).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+
+        let (_, first) = rpc(&state, json_headers(), call("events.tail", json!({}))).await;
+        assert_eq!(first["result"]["latest_seq"], 0);
+        state
+            .storage
+            .insert_observability_event(&ObservabilityEvent {
+                seq: None,
+                id: Uuid::new_v4(),
+                timestamp: chrono::Utc::now(),
+                session_id: None,
+                agent: Some("calm-otter".to_string()),
+                topic: None,
+                provider: None,
+                payload: Payload::UserMessage(UserMessage {
+                    text: "Run the tests".to_string(),
+                    ..Default::default()
+                }),
+            })
+            .await
+            .unwrap();
+        let tail = call("events.tail", json!({"after_seq": 0}));
+        let (_, tail) = rpc(&state, json_headers(), tail).await;
+        assert_eq!(tail["result"]["events"].as_array().unwrap().len(), 1);
+        let query = call("events.query", json!({"search": "tests"}));
+        let (_, found) = rpc(&state, json_headers(), query).await;
+        assert_eq!(found["result"]["events"][0]["agent"], "calm-otter");
+
+        let approval = PendingApproval {
+            id: Uuid::new_v4(),
+            created_at: chrono::Utc::now(),
+            agent: None,
+            session_id: None,
+            tool_name: "Bash".to_string(),
+            tool_input: json!({"command": "rm -rf build"}),
+            reason: "Bash is a guarded tool".to_string(),
+        };
+        let id = approval.id;
+        let gate = state.clone();
+        let waiting = tokio::spawn(async move { gate.approvals.wait_for_decision(approval).await });
+        while state.approvals.pending().is_empty() {
+            tokio::task::yield_now().await;
+        }
+        let (_, listed) = rpc(&state, json_headers(), call("approvals.list", Value::Null)).await;
+        assert_eq!(listed["result"]["approvals"][0]["id"], id.to_string());
+        let decide = call("approvals.decide", json!({"id": id, "decision": "approve"}));
+        let (_, decided) = rpc(&state, json_headers(), decide).await;
+        assert_eq!(decided["result"]["decided"], true);
+        assert_eq!(waiting.await.unwrap(), Decision::Approved);
+
+        let (_, unknown) = rpc(&state, json_headers(), call("events.delete", Value::Null)).await;
+        assert_eq!(unknown["error"]["code"], METHOD_NOT_FOUND);
+        let notification = json!({ "jsonrpc": "2.0", "method": "approvals.list" });
+        let (status, _) = rpc(&state, json_headers(), notification).await;
+        assert_eq!(status, StatusCode::NO_CONTENT);
+    }
+
+    #[test]
+    fn test_initialize_checks_the_protocol_version() {
+        let ok = initialize(InitializeParams {
+            protocol_version: Some(PROTOCOL_VERSION),
+        })
+        .unwrap();
+        assert_eq!(ok["protocol_version"], PROTOCOL_VERSION);
+        assert!(ok["methods"]
+            .as_array()
+            .unwrap()
+            .contains(&json!("events.tail")));
+
+        let err = initialize(InitializeParams {
+            protocol_version: Some(PROTOCOL_VERSION + 1),
+        })
+        .unwrap_err();
+        assert_eq!(err.code, UNSUPPORTED_VERSION);
+
+        let err = params::<InitializeParams>(json!({ "protocol_version": "one" })).unwrap_err();
+        assert_eq!(err.code, INVALID_PARAMS);
+    }
+}
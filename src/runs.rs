@@ -0,0 +1,793 @@
+//! Runs: the agents one orchestration job launched, and `sentinel runs`.
+//!
+//! A script that starts a fleet of agents sends the same
+//! `x-sentinel-run-id` with each of their requests, and optionally
+//! `x-sentinel-run-started-by` and `x-sentinel-run-params` (see
+//! `attribution`). The first request of a run records it; every agent that
+//! sends the id joins it. Usage is rolled up per run and per agent within it
+//! from the responses, which carry the run id too; each is noted in
+//! `run_responses`, indexed by run, as it's stored.
+//!
+//! A run started by another names it in `x-sentinel-parent-run-id`. The cost
+//! of a run with everything under it (`/api/runs/:id/costs`,
+//! `sentinel costs --run`) adds up the sessions of all its descendant runs,
+//! so an orchestrated task that fanned out comes to a single number.
+
+use std::collections::{BTreeMap, HashSet};
+use std::path::Path;
+
+use chrono::{DateTime, Utc};
+use schemars::JsonSchema;
+use serde::Serialize;
+use sqlx::{SqliteConnection, SqlitePool};
+
+use crate::attribution::Attribution;
+use crate::derived;
+use crate::event::{ObservabilityEvent, Payload};
+use crate::parsers::Usage;
+use crate::pricing;
+use crate::storage::add_column_if_missing;
+
+/// One orchestration job's run
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct Run {
+    pub id: String,
+    pub started_by: Option<String>,
+    /// The run that started this one, from x-sentinel-parent-run-id
+    pub parent_id: Option<String>,
+    /// As sent in x-sentinel-run-params; the latest wins
+    pub parameters: Option<serde_json::Value>,
+    pub started_at: DateTime<Utc>,
+    pub last_seen_at: DateTime<Utc>,
+    /// Agents that sent the run id, sorted
+    pub agents: Vec<String>,
+}
+
+/// Responses recorded for a run, or one agent in it
+#[derive(Debug, Default, Clone, Serialize, JsonSchema)]
+pub struct RunUsage {
+    pub requests: i64,
+    pub input_tokens: i64,
+    pub output_tokens: i64,
+    pub cache_read_tokens: i64,
+    pub cache_creation_tokens: i64,
+    pub cost_usd: f64,
+    /// Responses whose cost couldn't be estimated
+    pub unpriced: i64,
+}
+
+impl RunUsage {
+    fn add(&mut self, response: &RunResponse) {
+        self.requests += 1;
+        if let Some(ref u) = response.usage {
+            self.input_tokens += u.input_tokens.unwrap_or(0);
+            self.output_tokens += u.output_tokens.unwrap_or(0);
+            self.cache_read_tokens += u.cache_read_tokens.unwrap_or(0);
+            self.cache_creation_tokens += u.cache_creation_tokens.unwrap_or(0);
+        }
+        match response
+            .usage
+            .as_ref()
+            .and_then(|u| pricing::cost_usd(response.model.as_deref(), u))
+        {
+            Some(cost) => self.cost_usd += cost,
+            None => self.unpriced += 1,
+        }
+    }
+}
+
+/// A run with its usage rolled up
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct RunSummary {
+    #[serde(flatten)]
+    pub run: Run,
+    pub sessions: usize,
+    pub total: RunUsage,
+    pub by_agent: BTreeMap<String, RunUsage>,
+}
+
+/// One agent session's share of a run's costs
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct SessionCost {
+    pub agent: Option<String>,
+    /// Absent for responses recorded without a session
+    pub session_id: Option<String>,
+    #[serde(flatten)]
+    pub usage: RunUsage,
+}
+
+/// A run's cost with all of its descendant runs'
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct RunCosts {
+    pub run_id: String,
+    /// The run and every run below it
+    pub runs: Vec<String>,
+    pub total: RunUsage,
+    pub by_run: BTreeMap<String, RunUsage>,
+    /// By agent and session
+    pub by_session: Vec<SessionCost>,
+}
+
+type RunRow = (
+    String,
+    Option<String>,
+    Option<String>,
+    Option<String>,
+    String,
+    String,
+    Option<String>,
+);
+
+fn row_to_run(row: RunRow) -> Option<Run> {
+    let (id, started_by, parent_id, parameters, started_at, last_seen_at, agents) = row;
+    let time = |t: &str| {
+        DateTime::parse_from_rfc3339(t)
+            .ok()
+            .map(|t| t.with_timezone(&Utc))
+    };
+    let mut agents: Vec<String> = agents
+        .map(|a| a.split(',').map(String::from).collect())
+        .unwrap_or_default();
+    agents.sort();
+    Some(Run {
+        id,
+        started_by,
+        parent_id,
+        parameters: parameters.and_then(|p| serde_json::from_str(&p).ok()),
+        started_at: time(&started_at)?,
+        last_seen_at: time(&last_seen_at)?,
+        agents,
+    })
+}
+
+/// Note a response that carries a run id in `run_responses`; part of the
+/// event's insert transaction
+pub async fn record(
+    conn: &mut SqliteConnection,
+    event: &ObservabilityEvent,
+) -> Result<(), sqlx::Error> {
+    let Payload::AssistantResponse(ref response) = event.payload else {
+        return Ok(());
+    };
+    let Some(ref run_id) = response.run_id else {
+        return Ok(());
+    };
+    sqlx::query(
+        r#"
+        INSERT OR IGNORE INTO run_responses
+            (event_id, run_id, agent, session_id, model, usage, timestamp)
+        VALUES (?, ?, ?, ?, ?, ?, ?)
+        "#,
+    )
+    .bind(event.id.to_string())
+    .bind(run_id)
+    .bind(event.agent.as_ref())
+    .bind(event.session_id.as_ref())
+    .bind(response.model.as_ref())
+    .bind(
+        response
+            .usage
+            .as_ref()
+            .and_then(|u| serde_json::to_string(u).ok()),
+    )
+    .bind(event.timestamp.to_rfc3339())
+    .execute(conn)
+    .await?;
+    Ok(())
+}
+
+#[derive(Clone)]
+pub struct RunStore {
+    pool: SqlitePool,
+}
+
+impl RunStore {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool }
+    }
+
+    /// Create the tables; true when `run_responses` is new and the responses
+    /// of runs already recorded have to be read into it
+    pub async fn init_schema(&self) -> Result<bool, sqlx::Error> {
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS runs (
+                id TEXT PRIMARY KEY,
+                started_by TEXT,
+                parent_id TEXT,
+                parameters TEXT,
+                started_at TEXT NOT NULL,
+                last_seen_at TEXT NOT NULL
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+        add_column_if_missing(&self.pool, "runs", "parent_id", "TEXT").await?;
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS run_agents (
+                run_id TEXT NOT NULL,
+                agent TEXT NOT NULL,
+                PRIMARY KEY (run_id, agent)
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+        let created = derived::create(
+            &self.pool,
+            "run_responses",
+            r#"
+            CREATE TABLE IF NOT EXISTS run_responses (
+                event_id TEXT PRIMARY KEY,
+                run_id TEXT NOT NULL,
+                agent TEXT,
+                session_id TEXT,
+                model TEXT,
+                usage TEXT,
+                timestamp TEXT NOT NULL
+            )
+            "#,
+            |_| false,
+        )
+        .await?;
+        sqlx::query("CREATE INDEX IF NOT EXISTS idx_run_responses_run ON run_responses(run_id)")
+            .execute(&self.pool)
+            .await?;
+        Ok(created)
+    }
+
+    /// Note a request of the run its headers name, if any, from `agent`
+    pub async fn record(
+        &self,
+        attribution: &Attribution,
+        agent: Option<&str>,
+    ) -> Result<(), sqlx::Error> {
+        let Some(ref id) = attribution.run_id else {
+            return Ok(());
+        };
+        let now = Utc::now().to_rfc3339();
+        let parent = attribution.parent_run_id.as_ref().filter(|p| *p != id);
+        sqlx::query(
+            r#"
+            INSERT INTO runs (id, started_by, parent_id, parameters, started_at, last_seen_at)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?5)
+            ON CONFLICT(id) DO UPDATE SET
+                last_seen_at = excluded.last_seen_at,
+                started_by = COALESCE(excluded.started_by, runs.started_by),
+                parent_id = COALESCE(excluded.parent_id, runs.parent_id),
+                parameters = COALESCE(excluded.parameters, runs.parameters)
+            "#,
+        )
+        .bind(id)
+        .bind(&attribution.started_by)
+        .bind(parent)
+        .bind(attribution.parameters.as_ref().map(|p| p.to_string()))
+        .bind(&now)
+        .execute(&self.pool)
+        .await?;
+        if let Some(agent) = agent {
+            sqlx::query("INSERT OR IGNORE INTO run_agents (run_id, agent) VALUES (?, ?)")
+                .bind(id)
+                .bind(agent)
+                .execute(&self.pool)
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// The latest runs, most recently started first, or just the one named
+    async fn runs(&self, id: Option<&str>, limit: i64) -> Result<Vec<Run>, sqlx::Error> {
+        let rows: Vec<RunRow> = sqlx::query_as(
+            r#"
+            SELECT id, started_by, parent_id, parameters, started_at, last_seen_at,
+                (SELECT group_concat(agent, ',') FROM run_agents WHERE run_id = runs.id)
+            FROM runs
+            WHERE ?1 IS NULL OR id = ?1
+            ORDER BY started_at DESC
+            LIMIT ?2
+            "#,
+        )
+        .bind(id)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rows.into_iter().filter_map(row_to_run).collect())
+    }
+
+    /// The latest runs with their usage rolled up
+    pub async fn summaries(&self, limit: i64) -> Result<Vec<RunSummary>, sqlx::Error> {
+        let runs = self.runs(None, limit).await?;
+        self.summarize(runs).await
+    }
+
+    pub async fn summary(&self, id: &str) -> Result<Option<RunSummary>, sqlx::Error> {
+        let runs = self.runs(Some(id), 1).await?;
+        Ok(self.summarize(runs).await?.pop())
+    }
+
+    /// The responses recorded with any of the run ids
+    async fn responses(&self, ids: &[&str]) -> Result<Vec<RunResponse>, sqlx::Error> {
+        let rows: Vec<RunResponseRow> = sqlx::query_as(
+            r#"
+            SELECT event_id, run_id, agent, session_id, model, usage
+            FROM run_responses
+            WHERE run_id IN (SELECT value FROM json_each(?))
+            "#,
+        )
+        .bind(serde_json::to_string(ids).unwrap_or_default())
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rows
+            .into_iter()
+            .map(|row| RunResponse {
+                usage: row.usage.and_then(|u| {
+                    serde_json::from_str(&u)
+                        .map_err(|e| {
+                            tracing::warn!("Skipping usage of {} in run usage: {}", row.event_id, e)
+                        })
+                        .ok()
+                }),
+                run_id: row.run_id,
+                agent: row.agent,
+                session_id: row.session_id,
+                model: row.model,
+            })
+            .collect())
+    }
+
+    async fn summarize(&self, runs: Vec<Run>) -> Result<Vec<RunSummary>, sqlx::Error> {
+        let ids: Vec<&str> = runs.iter().map(|r| r.id.as_str()).collect();
+        let responses = self.responses(&ids).await?;
+
+        let mut summaries: Vec<RunSummary> = runs
+            .into_iter()
+            .map(|run| RunSummary {
+                run,
+                sessions: 0,
+                total: RunUsage::default(),
+                by_agent: BTreeMap::new(),
+            })
+            .collect();
+        let mut sessions: Vec<HashSet<String>> = vec![HashSet::new(); summaries.len()];
+        for row in responses {
+            let Some(i) = summaries.iter().position(|s| s.run.id == row.run_id) else {
+                continue;
+            };
+            let summary = &mut summaries[i];
+            summary.total.add(&row);
+            summary
+                .by_agent
+                .entry(row.agent.clone().unwrap_or_default())
+                .or_default()
+                .add(&row);
+            if let Some(session) = row.session_id {
+                sessions[i].insert(session);
+            }
+        }
+        for (summary, sessions) in summaries.iter_mut().zip(sessions) {
+            summary.sessions = sessions.len();
+        }
+        Ok(summaries)
+    }
+
+    /// The run's id and those of every run below it, each once
+    async fn descendants(&self, id: &str) -> Result<Vec<String>, sqlx::Error> {
+        // UNION rather than UNION ALL, so a cycle of parents still ends
+        let ids: Vec<(String,)> = sqlx::query_as(
+            r#"
+            WITH RECURSIVE tree(id) AS (
+                SELECT id FROM runs WHERE id = ?
+                UNION
+                SELECT runs.id FROM runs JOIN tree ON runs.parent_id = tree.id
+            )
+            SELECT id FROM tree ORDER BY id
+            "#,
+        )
+        .bind(id)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(ids.into_iter().map(|(id,)| id).collect())
+    }
+
+    /// What a run and everything under it cost, by run and by session
+    pub async fn costs(&self, id: &str) -> Result<Option<RunCosts>, sqlx::Error> {
+        let runs = self.descendants(id).await?;
+        if runs.is_empty() {
+            return Ok(None);
+        }
+        let ids: Vec<&str> = runs.iter().map(String::as_str).collect();
+        let responses = self.responses(&ids).await?;
+
+        let mut costs = RunCosts {
+            run_id: id.to_string(),
+            total: RunUsage::default(),
+            by_run: runs
+                .iter()
+                .map(|r| (r.clone(), RunUsage::default()))
+                .collect(),
+            by_session: Vec::new(),
+            runs,
+        };
+        let mut sessions: BTreeMap<(Option<String>, Option<String>), RunUsage> = BTreeMap::new();
+        for row in responses {
+            costs.total.add(&row);
+            costs
+                .by_run
+                .entry(row.run_id.clone())
+                .or_default()
+                .add(&row);
+            sessions
+                .entry((row.agent.clone(), row.session_id.clone()))
+                .or_default()
+                .add(&row);
+        }
+        costs.by_session = sessions
+            .into_iter()
+            .map(|((agent, session_id), usage)| SessionCost {
+                agent,
+                session_id,
+                usage,
+            })
+            .collect();
+        Ok(Some(costs))
+    }
+}
+
+#[derive(sqlx::FromRow)]
+struct RunResponseRow {
+    event_id: String,
+    run_id: String,
+    agent: Option<String>,
+    session_id: Option<String>,
+    model: Option<String>,
+    usage: Option<String>,
+}
+
+struct RunResponse {
+    run_id: String,
+    agent: Option<String>,
+    session_id: Option<String>,
+    model: Option<String>,
+    usage: Option<Usage>,
+}
+
+/// Every token billed, cache reads and writes included
+fn tokens(usage: &RunUsage) -> i64 {
+    usage.input_tokens + usage.output_tokens + usage.cache_read_tokens + usage.cache_creation_tokens
+}
+
+/// `sentinel runs`: the latest runs, or one broken down by agent
+pub async fn run(
+    data_dir: &Path,
+    id: Option<&str>,
+    limit: i64,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let db_path = data_dir.join("sentinel.db");
+    if !db_path.exists() {
+        println!("No runs found. Run 'sentinel start' first to capture some traffic.");
+        return Ok(());
+    }
+    let storage = crate::storage::Storage::new(&db_path).await?;
+    let store = RunStore::new(storage.pool());
+    store.init_schema().await?;
+
+    let Some(id) = id else {
+        let summaries = store.summaries(limit).await?;
+        if summaries.is_empty() {
+            println!("No runs yet. Send x-sentinel-run-id with an agent's requests to group them.");
+            return Ok(());
+        }
+        println!(
+            "{:<24} {:<16} {:<16} {:>6} {:>8} {:>8} {:>12} {:>10}",
+            "RUN", "STARTED", "STARTED BY", "AGENTS", "SESSIONS", "REQUESTS", "TOKENS", "COST"
+        );
+        for summary in &summaries {
+            println!(
+                "{:<24} {:<16} {:<16} {:>6} {:>8} {:>8} {:>12} {:>10}",
+                summary.run.id.chars().take(24).collect::<String>(),
+                summary.run.started_at.format("%Y-%m-%d %H:%M"),
+                summary
+                    .run
+                    .started_by
+                    .as_deref()
+                    .unwrap_or("-")
+                    .chars()
+                    .take(16)
+                    .collect::<String>(),
+                summary.run.agents.len(),
+                summary.sessions,
+                summary.total.requests,
+                tokens(&summary.total),
+                format!("${:.2}", summary.total.cost_usd)
+            );
+        }
+        return Ok(());
+    };
+
+    let Some(summary) = store.summary(id).await? else {
+        return Err(format!("No run '{}'. Run 'sentinel runs' to list them.", id).into());
+    };
+    let run = &summary.run;
+    println!("Run {}", run.id);
+    if let Some(ref by) = run.started_by {
+        println!("  Started by: {}", by);
+    }
+    if let Some(ref parent) = run.parent_id {
+        println!("  Parent run: {}", parent);
+    }
+    println!(
+        "  Seen:       {} to {}",
+        run.started_at.format("%Y-%m-%d %H:%M:%S"),
+        run.last_seen_at.format("%Y-%m-%d %H:%M:%S")
+    );
+    if let Some(ref parameters) = run.parameters {
+        println!("  Parameters: {}", parameters);
+    }
+    println!(
+        "  {} agents, {} sessions, {} requests, {} tokens, ${:.2}\n",
+        run.agents.len(),
+        summary.sessions,
+        summary.total.requests,
+        tokens(&summary.total),
+        summary.total.cost_usd
+    );
+    println!(
+        "{:<24} {:>8} {:>12} {:>12} {:>10}",
+        "AGENT", "REQUESTS", "INPUT", "OUTPUT", "COST"
+    );
+    for agent in &run.agents {
+        let usage = summary.by_agent.get(agent).cloned().unwrap_or_default();
+        println!(
+            "{:<24} {:>8} {:>12} {:>12} {:>10}",
+            agent,
+            usage.requests,
+            usage.input_tokens,
+            usage.output_tokens,
+            format!("${:.2}", usage.cost_usd)
+        );
+    }
+    let unpriced = summary.total.unpriced;
+    if unpriced > 0 {
+        println!(
+            "\n{} response(s) had no usage or an unknown model price and count as $0",
+            unpriced
+        );
+    }
+    Ok(())
+}
+
+/// `sentinel costs --run`: what a run and its descendant runs cost, by
+/// session
+pub async fn costs(data_dir: &Path, id: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let db_path = data_dir.join("sentinel.db");
+    if !db_path.exists() {
+        println!("No runs found. Run 'sentinel start' first to capture some traffic.");
+        return Ok(());
+    }
+    let storage = crate::storage::Storage::new(&db_path).await?;
+    let store = RunStore::new(storage.pool());
+    store.init_schema().await?;
+    let Some(costs) = store.costs(id).await? else {
+        return Err(format!("No run '{}'. Run 'sentinel runs' to list them.", id).into());
+    };
+
+    println!(
+        "Run {}: ${:.4} over {} requests, {} tokens",
+        costs.run_id,
+        costs.total.cost_usd,
+        costs.total.requests,
+        tokens(&costs.total)
+    );
+    if costs.runs.len() > 1 {
+        println!("  Including {} descendant runs\n", costs.runs.len() - 1);
+        println!(
+            "{:<24} {:>8} {:>12} {:>12}",
+            "RUN", "REQUESTS", "TOKENS", "COST"
+        );
+        for (run, usage) in &costs.by_run {
+            println!(
+                "{:<24} {:>8} {:>12} {:>12}",
+                run.chars().take(24).collect::<String>(),
+                usage.requests,
+                tokens(usage),
+                format!("${:.4}", usage.cost_usd)
+            );
+        }
+    }
+    if !costs.by_session.is_empty() {
+        println!(
+            "\n{:<38} {:<24} {:>8} {:>12} {:>12}",
+            "SESSION", "AGENT", "REQUESTS", "TOKENS", "COST"
+        );
+        for cost in &costs.by_session {
+            println!(
+                "{:<38} {:<24} {:>8} {:>12} {:>12}",
+                cost.session_id.as_deref().unwrap_or("(none)"),
+                cost.agent
+                    .as_deref()
+                    .unwrap_or("-")
+                    .chars()
+                    .take(24)
+                    .collect::<String>(),
+                cost.usage.requests,
+                tokens(&cost.usage),
+                format!("${:.4}", cost.usage.cost_usd)
+            );
+        }
+    }
+    if costs.total.unpriced > 0 {
+        println!(
+            "\n{} response(s) had no usage or an unknown model price and count as $0",
+            costs.total.unpriced
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event::{AssistantResponse, ObservabilityEvent};
+    use crate::parsers::{ParsedResponse, Usage};
+    use crate::storage::Storage;
+    use uuid::Uuid;
+
+    async fn respond(storage: &Storage, agent: &str, session: &str, run_id: Option<&str>) {
+        let mut response = AssistantResponse::from(ParsedResponse::default());
+        response.model = Some("claude-sonnet-4".to_string());
+        response.usage = Some(Usage {
+            input_tokens: Some(100),
+            output_tokens: Some(10),
+            cache_read_tokens: Some(50),
+            cache_creation_tokens: None,
+            cost_usd: None,
+        });
+        response.run_id = run_id.map(String::from);
+        storage
+            .insert_observability_event(&ObservabilityEvent {
+                seq: None,
+                id: Uuid::new_v4(),
+                timestamp: Utc::now(),
+                session_id: Some(session.to_string()),
+                agent: Some(agent.to_string()),
+                topic: None,
+                provider: Some("anthropic".to_string()),
+                payload: Payload::AssistantResponse(response),
+            })
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_runs_group_agents_and_roll_up_usage() {
+        let path = std::env::temp_dir().join(format!("sentinel-test-{}.db", Uuid::new_v4()));
+        let storage = Storage::new(&path).await.unwrap();
+        let store = RunStore::new(storage.pool());
+        store.init_schema().await.unwrap();
+
+        let attribution = |started_by: Option<&str>| Attribution {
+            run_id: Some("nightly-7".to_string()),
+            started_by: started_by.map(String::from),
+            parameters: started_by.map(|_| serde_json::json!({"shards": 2})),
+            ..Default::default()
+        };
+        store
+            .record(&attribution(Some("ci")), Some("shard-1"))
+            .await
+            .unwrap();
+        store
+            .record(&attribution(None), Some("shard-2"))
+            .await
+            .unwrap();
+        store
+            .record(&Attribution::default(), Some("loner"))
+            .await
+            .unwrap();
+
+        for (agent, session, run_id) in [
+            ("shard-1", "s1", Some("nightly-7")),
+            ("shard-2", "s2", Some("nightly-7")),
+            ("shard-2", "s2", Some("nightly-7")),
+            ("shard-2", "s3", None),
+        ] {
+            respond(&storage, agent, session, run_id).await;
+        }
+
+        let summaries = store.summaries(10).await.unwrap();
+        assert_eq!(summaries.len(), 1);
+        let summary = &summaries[0];
+        assert_eq!(summary.run.started_by.as_deref(), Some("ci"));
+        assert_eq!(
+            summary.run.parameters,
+            Some(serde_json::json!({"shards": 2}))
+        );
+        assert_eq!(summary.run.agents, ["shard-1", "shard-2"]);
+        assert_eq!(summary.sessions, 2);
+        assert_eq!(summary.total.requests, 3);
+        assert_eq!(summary.by_agent["shard-2"].input_tokens, 200);
+        assert_eq!(tokens(&summary.by_agent["shard-2"]), 320);
+        assert!(summary.total.cost_usd > 0.0);
+        assert!(store.summary("other").await.unwrap().is_none());
+
+        drop(storage);
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[tokio::test]
+    async fn test_costs_add_up_descendant_runs() {
+        let path = std::env::temp_dir().join(format!("sentinel-test-{}.db", Uuid::new_v4()));
+        let storage = Storage::new(&path).await.unwrap();
+        let store = RunStore::new(storage.pool());
+        store.init_schema().await.unwrap();
+
+        // task -> plan -> (build, test); "other" is unrelated
+        for (run, parent) in [
+            ("task", None),
+            ("plan", Some("task")),
+            ("build", Some("plan")),
+            ("test", Some("plan")),
+            ("other", None),
+        ] {
+            let attribution = Attribution {
+                run_id: Some(run.to_string()),
+                parent_run_id: parent.map(String::from),
+                ..Default::default()
+            };
+            store.record(&attribution, Some(run)).await.unwrap();
+        }
+        // A later request without the header keeps the parent
+        let attribution = Attribution {
+            run_id: Some("build".to_string()),
+            ..Default::default()
+        };
+        store.record(&attribution, Some("build")).await.unwrap();
+
+        for (agent, session, run) in [
+            ("planner", "s1", "plan"),
+            ("builder", "s2", "build"),
+            ("builder", "s2", "build"),
+            ("tester", "s3", "test"),
+            ("stray", "s4", "other"),
+        ] {
+            respond(&storage, agent, session, Some(run)).await;
+        }
+
+        let costs = store.costs("task").await.unwrap().unwrap();
+        assert_eq!(costs.runs, ["build", "plan", "task", "test"]);
+        assert_eq!(costs.total.requests, 4);
+        assert_eq!(costs.total.input_tokens, 400);
+        assert_eq!(costs.by_run["build"].requests, 2);
+        assert_eq!(costs.by_run["task"].requests, 0);
+        let sessions: Vec<_> = costs
+            .by_session
+            .iter()
+            .map(|s| {
+                (
+                    s.agent.as_deref().unwrap(),
+                    s.session_id.as_deref().unwrap(),
+                    s.usage.requests,
+                )
+            })
+            .collect();
+        assert_eq!(
+            sessions,
+            [
+                ("builder", "s2", 2),
+                ("planner", "s1", 1),
+                ("tester", "s3", 1)
+            ]
+        );
+
+        let plan = store.costs("plan").await.unwrap().unwrap();
+        assert_eq!(plan.total.requests, 4);
+        let build = store.costs("build").await.unwrap().unwrap();
+        assert_eq!(build.total.requests, 2);
+        assert!((costs.total.cost_usd - 2.0 * build.total.cost_usd).abs() < 1e-9);
+        assert!(store.costs("missing").await.unwrap().is_none());
+
+        drop(storage);
+        let _ = std::fs::remove_file(&path);
+    }
+}
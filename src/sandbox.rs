@@ -0,0 +1,123 @@
+//! File-scope sandbox: where each agent may write.
+//!
+//! Edit, Write, and Bash calls that touch a path outside the agent's allowed
+//! globs are flagged, or with `block: true` refused. Non-streaming responses
+//! carrying such a call are replaced with an error before the agent sees them;
+//! streamed ones end with an error event in place of the chunk that completes
+//! the call, so the client never gets a call it could run. Paths are read the
+//! way the file tracker reads them (see `files::file_change`).
+//!
+//! ```yaml
+//! block: true
+//! scopes:
+//!   - agents: ["backend-*"]          # every agent when omitted
+//!     paths: ["src/**", "tests/**"]  # relative to the agent's working directory
+//!   - agents: ["docs-*"]
+//!     paths: ["docs/**", "*.md"]
+//! ```
+//!
+//! Every scope whose agents match applies, so an agent covered by two scopes
+//! may only write where both allow.
+
+use std::path::Path;
+
+use serde::Deserialize;
+
+use crate::baseline::{Baseline, BaselineError, BaselineFile};
+
+/// Tools that write files, and so are held to the sandbox
+const SANDBOXED_TOOLS: &[&str] = &["Edit", "MultiEdit", "Write", "NotebookEdit", "Bash"];
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SandboxFile {
+    #[serde(default)]
+    block: bool,
+    scopes: Vec<SandboxScope>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SandboxScope {
+    #[serde(default)]
+    agents: Option<Vec<String>>,
+    paths: Vec<String>,
+}
+
+/// Load a sandbox file as baselines that only check the paths of writing tools
+pub fn load(path: &Path) -> Result<Vec<Baseline>, BaselineError> {
+    let origin = path.display().to_string();
+    let text = std::fs::read_to_string(path).map_err(|source| BaselineError::Read {
+        path: origin.clone(),
+        source,
+    })?;
+    parse(&text, &origin)
+}
+
+fn parse(yaml: &str, origin: &str) -> Result<Vec<Baseline>, BaselineError> {
+    let file: SandboxFile = serde_yaml::from_str(yaml).map_err(|source| BaselineError::Parse {
+        path: origin.to_string(),
+        source,
+    })?;
+    file.scopes
+        .into_iter()
+        .map(|scope| {
+            let role = match scope.agents {
+                Some(ref agents) => agents.join(","),
+                None => "all agents".to_string(),
+            };
+            Baseline::from_file(
+                "sandbox",
+                BaselineFile {
+                    role,
+                    agents: scope.agents,
+                    tools: None,
+                    paths: Some(scope.paths),
+                    path_tools: Some(SANDBOXED_TOOLS.iter().map(|t| t.to_string()).collect()),
+                    block: file.block,
+                },
+                origin,
+            )
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::baseline::BaselineMonitor;
+    use crate::parsers::ToolCall;
+
+    fn call(name: &str, input: serde_json::Value) -> ToolCall {
+        ToolCall {
+            id: format!("toolu_{}", name),
+            name: name.to_string(),
+            input,
+        }
+    }
+
+    #[test]
+    fn test_sandbox_checks_only_writes() {
+        let baselines = parse(
+            "block: true\nscopes:\n  - agents: ['backend-*']\n    paths: ['src/**']\n",
+            "sandbox.yaml",
+        )
+        .unwrap();
+        let monitor = BaselineMonitor::new(baselines);
+        let cwd = Some("/repo");
+
+        let violations = monitor.check(
+            Some("backend-fox"),
+            cwd,
+            &[
+                call("Read", serde_json::json!({"file_path": "/etc/hosts"})),
+                call("Write", serde_json::json!({"file_path": "src/new.rs"})),
+                call("Bash", serde_json::json!({"command": "rm -rf /repo/.git"})),
+            ],
+        );
+        assert_eq!(violations.len(), 1);
+        assert_eq!(violations[0].tool, "Bash");
+        assert_eq!(violations[0].source, "sandbox:backend-*");
+        assert!(violations[0].blocked);
+    }
+}
@@ -0,0 +1,451 @@
+//! JSON Schema for the API payloads, and the fields actually observed in them.
+//!
+//! Schemas are derived from the Rust types with `schemars`, so clients that
+//! generate models from `/api/schema` stay in sync with what the server emits.
+//! The same schemas rendered as TypeScript declarations are served at
+//! `/api/schema.ts` and printed by `sentinel schema typescript`.
+//! `sentinel schema sample` complements that with how often each field is
+//! present in recorded events, since most fields are optional.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+
+use axum::{http::header, Json};
+use clap::ValueEnum;
+use schemars::schema_for;
+use serde::Serialize;
+use serde_json::{json, Value};
+
+use crate::agent::Agent;
+use crate::annotations::HeartbeatAck;
+use crate::event::ObservabilityEvent;
+use crate::history::EventPage;
+use crate::overhead::OverheadStats;
+use crate::parsers::ToolResult;
+use crate::queue::QueueStats;
+use crate::runs::{RunCosts, RunSummary};
+use crate::sse::SSeMessageEnvelope;
+use crate::storage::Storage;
+
+/// Schemas keyed by the shape each endpoint returns.
+pub fn api_schema() -> Value {
+    json!({
+        "sse_message": schema_for!(SSeMessageEnvelope),
+        "event_page": schema_for!(EventPage),
+        "event": schema_for!(ObservabilityEvent),
+        "agent": schema_for!(Agent),
+        "run": schema_for!(RunSummary),
+        "run_costs": schema_for!(RunCosts),
+        "queue_stats": schema_for!(QueueStats),
+        "overhead_stats": schema_for!(OverheadStats),
+        "tool_result": schema_for!(ToolResult),
+        "heartbeat_ack": schema_for!(HeartbeatAck),
+        "endpoints": {
+            "GET /api/events": "sse_message",
+            "GET /api/events/history": "event_page",
+            "GET /api/agents": "agent[]",
+            "GET /api/agents/:name/events": "event[]",
+            "GET /api/runs": "run[]",
+            "GET /api/runs/:id": "run",
+            "GET /api/runs/:id/costs": "run_costs",
+            "GET /api/queue": "queue_stats",
+            "GET /api/overhead": "overhead_stats",
+            "GET /api/events/:id/tool_results/:tool_use_id": "tool_result",
+            "POST /api/sessions/:id/heartbeat": "heartbeat_ack",
+            "POST /api/sessions/:id/annotations": "event",
+        },
+    })
+}
+
+pub async fn schema_handler() -> Json<Value> {
+    Json(api_schema())
+}
+
+pub async fn typescript_handler() -> ([(header::HeaderName, &'static str); 1], String) {
+    (
+        [(header::CONTENT_TYPE, "application/typescript; charset=utf-8")],
+        typescript(&api_schema()),
+    )
+}
+
+/// Names of the fields an object schema requires
+fn required(schema: &Value) -> BTreeSet<&str> {
+    schema
+        .get("required")
+        .and_then(Value::as_array)
+        .map(|names| names.iter().filter_map(Value::as_str).collect())
+        .unwrap_or_default()
+}
+
+/// The TypeScript type a schema describes, naming referenced definitions
+fn ts_type(schema: &Value) -> String {
+    let Some(fields) = schema.as_object() else {
+        // `true` accepts anything
+        return "unknown".to_string();
+    };
+    let union = |schemas: &[Value]| {
+        let types: Vec<String> = schemas.iter().map(ts_type).collect();
+        types.join(" | ")
+    };
+    if let Some(reference) = fields.get("$ref").and_then(Value::as_str) {
+        let name = reference.rsplit('/').next().unwrap_or(reference).to_string();
+        // An internally tagged variant: the definition plus its tag
+        return match fields.get("properties") {
+            Some(_) => format!("{} & {}", name, ts_object(schema)),
+            None => name,
+        };
+    }
+    if let Some(value) = fields.get("const") {
+        return value.to_string();
+    }
+    if let Some(Value::Array(values)) = fields.get("enum") {
+        let literals: Vec<String> = values.iter().map(Value::to_string).collect();
+        return literals.join(" | ");
+    }
+    if let Some(Value::Array(schemas)) = fields.get("oneOf").or_else(|| fields.get("anyOf")) {
+        return union(schemas);
+    }
+    if let Some(Value::Array(schemas)) = fields.get("allOf") {
+        let types: Vec<String> = schemas.iter().map(ts_type).collect();
+        return types.join(" & ");
+    }
+    match fields.get("type") {
+        Some(Value::Array(types)) => {
+            let schemas: Vec<Value> = types
+                .iter()
+                .map(|t| {
+                    let mut single = schema.clone();
+                    single["type"] = t.clone();
+                    single
+                })
+                .collect();
+            union(&schemas)
+        }
+        Some(Value::String(t)) => match t.as_str() {
+            "string" => "string".to_string(),
+            "integer" | "number" => "number".to_string(),
+            "boolean" => "boolean".to_string(),
+            "null" => "null".to_string(),
+            "array" => {
+                let item = fields.get("items").map_or("unknown".to_string(), ts_type);
+                if item.contains(' ') {
+                    format!("({})[]", item)
+                } else {
+                    format!("{}[]", item)
+                }
+            }
+            _ => ts_object(schema),
+        },
+        _ => "unknown".to_string(),
+    }
+}
+
+/// An object schema as a TypeScript object type
+fn ts_object(schema: &Value) -> String {
+    let Some(properties) = schema.get("properties").and_then(Value::as_object) else {
+        let values = schema
+            .get("additionalProperties")
+            .filter(|v| v.is_object())
+            .map_or("unknown".to_string(), ts_type);
+        return format!("Record<string, {}>", values);
+    };
+    let required = required(schema);
+    let fields: Vec<String> = properties
+        .iter()
+        .map(|(name, field)| {
+            let optional = if required.contains(name.as_str()) { "" } else { "?" };
+            format!("{}{}: {}", name, optional, ts_type(field))
+        })
+        .collect();
+    format!("{{ {} }}", fields.join("; "))
+}
+
+/// A doc comment from a schema's description, if it has one
+fn ts_doc(schema: &Value, indent: &str) -> String {
+    schema
+        .get("description")
+        .and_then(Value::as_str)
+        .map(|d| format!("{}/** {} */\n", indent, d.replace('\n', " ").replace("*/", "* /")))
+        .unwrap_or_default()
+}
+
+/// One named declaration: an interface for a plain object, else a type alias
+fn ts_declaration(name: &str, schema: &Value) -> String {
+    let doc = ts_doc(schema, "");
+    let plain_object = schema.get("properties").is_some()
+        && ["$ref", "oneOf", "anyOf", "allOf"]
+            .iter()
+            .all(|key| schema.get(*key).is_none());
+    if !plain_object {
+        // A union a variant per line
+        if let Some(Value::Array(variants)) = schema.get("oneOf").or_else(|| schema.get("anyOf")) {
+            let lines: Vec<String> = variants
+                .iter()
+                .map(|v| format!("\n  | {}", ts_type(v)))
+                .collect();
+            return format!("{}export type {} ={};\n", doc, name, lines.concat());
+        }
+        return format!("{}export type {} = {};\n", doc, name, ts_type(schema));
+    }
+    let required = required(schema);
+    let mut out = format!("{}export interface {} {{\n", doc, name);
+    for (field, field_schema) in schema["properties"].as_object().into_iter().flatten() {
+        let optional = if required.contains(field.as_str()) { "" } else { "?" };
+        out.push_str(&ts_doc(field_schema, "  "));
+        out.push_str(&format!("  {}{}: {};\n", field, optional, ts_type(field_schema)));
+    }
+    out.push_str("}\n");
+    out
+}
+
+/// TypeScript declarations for every schema in `api_schema`, each type
+/// declared once however many endpoints share it
+pub fn typescript(api: &Value) -> String {
+    let mut declarations: BTreeMap<String, String> = BTreeMap::new();
+    for (key, schema) in api.as_object().into_iter().flatten() {
+        if key == "endpoints" {
+            continue;
+        }
+        let definitions = schema.get("$defs").and_then(Value::as_object);
+        for (name, definition) in definitions.into_iter().flatten() {
+            declarations
+                .entry(name.clone())
+                .or_insert_with(|| ts_declaration(name, definition));
+        }
+        if let Some(name) = schema.get("title").and_then(Value::as_str) {
+            declarations
+                .entry(name.to_string())
+                .or_insert_with(|| ts_declaration(name, schema));
+        }
+    }
+    let mut out = String::from("// Generated by sentinel from /api/schema; don't edit by hand\n");
+    for declaration in declarations.values() {
+        out.push('\n');
+        out.push_str(declaration);
+    }
+    out
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum EventType {
+    Request,
+    Response,
+    Alert,
+    Error,
+    Violation,
+    SessionClosed,
+    StreamAborted,
+    TurnSnapshot,
+    Annotation,
+}
+
+impl EventType {
+    /// The payload's `type` tag
+    fn tag(self) -> &'static str {
+        match self {
+            EventType::Request => "user_message",
+            EventType::Response => "assistant_response",
+            EventType::Alert => "alert",
+            EventType::Error => "error",
+            EventType::Violation => "violation",
+            EventType::SessionClosed => "session_closed",
+            EventType::StreamAborted => "stream_aborted",
+            EventType::TurnSnapshot => "turn_snapshot",
+            EventType::Annotation => "annotation",
+        }
+    }
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct FieldStats {
+    /// Events with this field present and not null
+    pub count: usize,
+    /// JSON types seen for the field
+    pub types: BTreeSet<&'static str>,
+}
+
+fn json_type(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+/// Every field path in a value, e.g. `tool_calls[].input.command`
+fn collect_paths(value: &Value, prefix: &str, out: &mut BTreeMap<String, BTreeSet<&'static str>>) {
+    match value {
+        Value::Object(map) => {
+            for (key, child) in map {
+                let path = if prefix.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{}.{}", prefix, key)
+                };
+                if !child.is_null() {
+                    out.entry(path.clone()).or_default().insert(json_type(child));
+                }
+                collect_paths(child, &path, out);
+            }
+        }
+        Value::Array(items) => {
+            let path = format!("{}[]", prefix);
+            for item in items {
+                collect_paths(item, &path, out);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// How many payloads contain each field. A field repeated inside an array
+/// counts once per event.
+pub fn field_frequencies(payloads: &[Value]) -> BTreeMap<String, FieldStats> {
+    let mut stats: BTreeMap<String, FieldStats> = BTreeMap::new();
+    for payload in payloads {
+        let mut paths = BTreeMap::new();
+        collect_paths(payload, "", &mut paths);
+        for (path, types) in paths {
+            let entry = stats.entry(path).or_default();
+            entry.count += 1;
+            entry.types.extend(types);
+        }
+    }
+    stats
+}
+
+pub async fn sample(
+    data_dir: &Path,
+    event_type: EventType,
+    limit: i64,
+    as_json: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let db_path = data_dir.join("sentinel.db");
+    if !db_path.exists() {
+        return Err("No recorded traffic. Run 'sentinel start' first.".into());
+    }
+    let storage = Storage::new(&db_path).await?;
+    // Sampled as stored rather than as read back, so fields this version
+    // drops or fails to parse still show
+    let payloads: Vec<Value> = storage
+        .get_recent_payloads_of_type(event_type.tag(), limit)
+        .await?
+        .iter()
+        .filter_map(|payload| serde_json::from_str(payload).ok())
+        .collect();
+    let stats = field_frequencies(&payloads);
+
+    if as_json {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&json!({
+                "type": event_type.tag(),
+                "events": payloads.len(),
+                "fields": stats,
+            }))?
+        );
+        return Ok(());
+    }
+    if payloads.is_empty() {
+        println!("No {} events recorded.", event_type.tag());
+        return Ok(());
+    }
+    println!(
+        "Fields in the {} most recent {} events:\n",
+        payloads.len(),
+        event_type.tag()
+    );
+    for (path, field) in &stats {
+        let types: Vec<&str> = field.types.iter().copied().collect();
+        println!(
+            "{:>6.1}%  {:>6}  {}  ({})",
+            100.0 * field.count as f64 / payloads.len() as f64,
+            field.count,
+            path,
+            types.join("|")
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Every `$ref` in a schema, e.g. `#/$defs/Payload`
+    fn refs<'a>(value: &'a Value, out: &mut Vec<&'a str>) {
+        match value {
+            Value::Object(fields) => {
+                if let Some(Value::String(reference)) = fields.get("$ref") {
+                    out.push(reference);
+                }
+                fields.values().for_each(|v| refs(v, out));
+            }
+            Value::Array(items) => items.iter().for_each(|v| refs(v, out)),
+            _ => {}
+        }
+    }
+
+    #[test]
+    fn test_schemas_resolve_and_cover_every_payload() {
+        let api = api_schema();
+        for (key, schema) in api.as_object().unwrap() {
+            if key == "endpoints" {
+                continue;
+            }
+            let mut found = Vec::new();
+            refs(schema, &mut found);
+            for reference in found {
+                let name = reference.strip_prefix("#/$defs/").unwrap();
+                assert!(schema["$defs"].get(name).is_some(), "{} has no {}", key, name);
+            }
+        }
+        for (endpoint, shape) in api["endpoints"].as_object().unwrap() {
+            let shape = shape.as_str().unwrap().trim_end_matches("[]");
+            assert!(api.get(shape).is_some(), "{} returns unknown {}", endpoint, shape);
+        }
+
+        let tags: Vec<&str> = api["event"]["$defs"]["Payload"]["oneOf"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .filter_map(|variant| variant["properties"]["type"]["const"].as_str())
+            .collect();
+        for event_type in EventType::value_variants() {
+            assert!(tags.contains(&event_type.tag()), "no schema for {}", event_type.tag());
+        }
+    }
+
+    #[test]
+    fn test_typescript_declares_each_type_once() {
+        let ts = typescript(&api_schema());
+        assert_eq!(ts.matches("export interface ObservabilityEvent {").count(), 1);
+        assert!(ts.contains("  payload: Payload;\n"));
+        assert!(ts.contains("export type Payload =\n  | UserMessage & { type: \"user_message\" }"));
+        assert!(ts.contains("export type AgentStatus = \"active\" | \"inactive\";"));
+
+        let field = json!({"type": "array", "items": {"type": ["string", "null"]}});
+        assert_eq!(ts_type(&field), "(string | null)[]");
+        let map = json!({"type": "object", "additionalProperties": {"$ref": "#/$defs/RunUsage"}});
+        assert_eq!(ts_type(&map), "Record<string, RunUsage>");
+    }
+
+    #[test]
+    fn test_field_frequencies_count_once_per_event() {
+        let payloads = vec![
+            json!({"type": "assistant_response", "text": "hi", "usage": null,
+                   "tool_calls": [{"name": "Bash"}, {"name": "Read"}]}),
+            json!({"type": "assistant_response", "text": null, "usage": {"input_tokens": 3},
+                   "tool_calls": []}),
+        ];
+        let stats = field_frequencies(&payloads);
+        assert_eq!(stats["type"].count, 2);
+        assert_eq!(stats["text"].count, 1);
+        assert_eq!(stats["usage.input_tokens"].count, 1);
+        assert_eq!(stats["tool_calls[].name"].count, 1);
+        assert!(stats["tool_calls"].types.contains("array"));
+    }
+}
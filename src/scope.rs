@@ -0,0 +1,266 @@
+//! File paths touched by tool calls, and glob scopes to check them against.
+
+use std::path::{Component, Path, PathBuf};
+
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+
+use crate::files;
+use crate::parsers::ToolCall;
+
+/// Tool input fields that name a file or directory
+const PATH_FIELDS: &[&str] = &["file_path", "notebook_path", "path"];
+
+/// Shell commands whose arguments are files they create, modify, or remove
+const WRITE_COMMANDS: &[&str] = &[
+    "rm", "rmdir", "mv", "cp", "touch", "mkdir", "tee", "ln", "chmod", "chown", "truncate",
+    "install",
+];
+
+/// Paths a tool call reads or writes, as written in its input
+pub fn tool_paths(call: &ToolCall) -> Vec<String> {
+    // File edits name their target as the file tracker reads it
+    if let Some(change) = files::file_change(call) {
+        return vec![change.path];
+    }
+    let mut paths: Vec<String> = PATH_FIELDS
+        .iter()
+        .filter_map(|field| call.input.get(field).and_then(|v| v.as_str()))
+        .filter(|p| !p.is_empty())
+        .map(str::to_string)
+        .collect();
+    if call.name == "Bash" {
+        if let Some(command) = call.input.get("command").and_then(|v| v.as_str()) {
+            paths.extend(bash_paths(command));
+        }
+    }
+    paths
+}
+
+/// Files a shell command writes to, with `~` expanded to this machine's home
+pub fn bash_paths(command: &str) -> Vec<String> {
+    bash_targets(command).iter().map(|path| expand_home(path)).collect()
+}
+
+/// Files a shell command writes to, as it names them: redirection targets and
+/// the arguments of common file-writing commands. This is a best-effort
+/// reading of the command line, not a shell parser; anything a script or
+/// interpreter writes is missed.
+pub fn bash_targets(command: &str) -> Vec<String> {
+    let mut paths = Vec::new();
+    let mut writing = false;
+    let mut at_command = true;
+    let mut tokens = shell_tokens(command).into_iter().peekable();
+    while let Some(token) = tokens.next() {
+        match token.as_str() {
+            ";" | "&&" | "||" | "|" | "&" | "\n" => {
+                writing = false;
+                at_command = true;
+            }
+            ">" | ">>" | "&>" => {
+                if let Some(target) = tokens.next() {
+                    if target != "/dev/null" {
+                        paths.push(target);
+                    }
+                }
+            }
+            "<" | ">&" => {
+                tokens.next();
+            }
+            _ if at_command => {
+                // Skip `VAR=value` prefixes and `sudo` to find the command itself
+                if token.contains('=') || token == "sudo" {
+                    continue;
+                }
+                let name = token.rsplit('/').next().unwrap_or(&token);
+                writing = WRITE_COMMANDS.contains(&name);
+                at_command = false;
+            }
+            _ if writing && !token.starts_with('-') => paths.push(token),
+            _ => {}
+        }
+    }
+    paths
+}
+
+/// Every word of a shell command that isn't an operator or a flag; any of
+/// them may be a file the command reads
+pub fn bash_words(command: &str) -> Vec<String> {
+    shell_tokens(command)
+        .into_iter()
+        .filter(|t| !t.starts_with('-') && !t.chars().all(|c| ";|&<>\n".contains(c)))
+        .map(|t| expand_home(&t))
+        .collect()
+}
+
+/// Split a command line into words and operators, honoring quotes
+fn shell_tokens(command: &str) -> Vec<String> {
+    let mut tokens = Vec::new();
+    let mut current = String::new();
+    let mut quote: Option<char> = None;
+    let mut chars = command.chars().peekable();
+
+    let flush = |current: &mut String, tokens: &mut Vec<String>| {
+        if !current.is_empty() {
+            tokens.push(std::mem::take(current));
+        }
+    };
+
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), c) => current.push(c),
+            (None, '\'' | '"') => quote = Some(c),
+            (None, '\\') => {
+                if let Some(next) = chars.next() {
+                    current.push(next);
+                }
+            }
+            (None, '\n') => {
+                flush(&mut current, &mut tokens);
+                tokens.push("\n".to_string());
+            }
+            (None, c) if c.is_whitespace() => flush(&mut current, &mut tokens),
+            (None, ';' | '|' | '&' | '<' | '>') => {
+                // `2>` redirects a file descriptor; the digit isn't a word
+                if c == '>' && current.chars().all(|d| d.is_ascii_digit()) {
+                    current.clear();
+                }
+                flush(&mut current, &mut tokens);
+                let mut op = c.to_string();
+                if let Some(&next) = chars.peek() {
+                    if (next == c && c != ';' && c != '<')
+                        || (c == '&' && next == '>')
+                        || (c == '>' && next == '&')
+                    {
+                        op.push(next);
+                        chars.next();
+                    }
+                }
+                tokens.push(op);
+            }
+            (None, c) => current.push(c),
+        }
+    }
+    flush(&mut current, &mut tokens);
+    tokens
+}
+
+fn expand_home(path: &str) -> String {
+    match (path.strip_prefix("~/"), std::env::var("HOME")) {
+        (Some(rest), Ok(home)) => format!("{}/{}", home.trim_end_matches('/'), rest),
+        _ => path.to_string(),
+    }
+}
+
+/// Lexically resolve `.` and `..` so `src/../../etc` can't sneak past a glob
+fn normalize(path: &Path) -> PathBuf {
+    let mut out = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                if !out.pop() {
+                    out.push("..");
+                }
+            }
+            other => out.push(other),
+        }
+    }
+    out
+}
+
+/// Whether a path, relative to `dir` unless absolute, is `dir` or under it
+pub fn within(path: &str, dir: &str) -> bool {
+    let dir = normalize(Path::new(dir));
+    normalize(&dir.join(path)).starts_with(&dir)
+}
+
+/// A set of globs, relative to the agent's working directory unless absolute
+#[derive(Debug, Clone)]
+pub struct PathScope {
+    patterns: Vec<String>,
+    globs: GlobSet,
+}
+
+impl PathScope {
+    pub fn new(patterns: &[String]) -> Result<Self, globset::Error> {
+        let mut builder = GlobSetBuilder::new();
+        for pattern in patterns {
+            // `*` stays within a directory; `**` crosses them
+            builder.add(GlobBuilder::new(pattern).literal_separator(true).build()?);
+        }
+        Ok(Self {
+            patterns: patterns.to_vec(),
+            globs: builder.build()?,
+        })
+    }
+
+    pub fn patterns(&self) -> &[String] {
+        &self.patterns
+    }
+
+    pub fn contains(&self, path: &str, working_dir: Option<&str>) -> bool {
+        let path = Path::new(path);
+        let absolute = match working_dir {
+            Some(dir) if path.is_relative() => normalize(&Path::new(dir).join(path)),
+            _ => normalize(path),
+        };
+        if self.globs.is_match(&absolute) {
+            return true;
+        }
+        match working_dir.map(|d| normalize(Path::new(d))) {
+            Some(dir) => absolute
+                .strip_prefix(&dir)
+                .is_ok_and(|relative| self.globs.is_match(relative)),
+            None => path.is_relative() && self.globs.is_match(normalize(path)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scope_resolves_against_working_dir() {
+        let scope = PathScope::new(&["src/**".to_string(), "/tmp/*".to_string()]).unwrap();
+        let cwd = Some("/home/me/project");
+
+        assert!(scope.contains("/home/me/project/src/main.rs", cwd));
+        assert!(scope.contains("src/deep/mod.rs", cwd));
+        assert!(scope.contains("/tmp/scratch", cwd));
+        assert!(!scope.contains("/tmp/nested/scratch", cwd));
+        assert!(!scope.contains("/home/me/project/Cargo.toml", cwd));
+        assert!(!scope.contains("src/../../other/secret", cwd));
+        assert!(!scope.contains("/etc/passwd", cwd));
+
+        assert!(within("src/lib.rs", "/home/me/project"));
+        assert!(within("/home/me/project/./x", "/home/me/project/"));
+        assert!(!within("../other/x", "/home/me/project"));
+        assert!(!within("/home/me/project-2/x", "/home/me/project"));
+    }
+
+    #[test]
+    fn test_tool_paths() {
+        let call = ToolCall {
+            id: "t".to_string(),
+            name: "Edit".to_string(),
+            input: serde_json::json!({"file_path": "/a/b.rs", "old_string": "x"}),
+        };
+        assert_eq!(tool_paths(&call), vec!["/a/b.rs"]);
+    }
+
+    #[test]
+    fn test_bash_paths() {
+        assert_eq!(
+            bash_paths("cargo build 2>&1 | tee /tmp/build.log && echo done > 'out dir/x.txt'"),
+            vec!["/tmp/build.log", "out dir/x.txt"]
+        );
+        assert_eq!(
+            bash_paths("FOO=1 sudo rm -rf ../other 2>&1; mv a.rs src/b.rs"),
+            vec!["../other", "a.rs", "src/b.rs"]
+        );
+        assert!(bash_paths("grep -r 's/a/b/' src > /dev/null").is_empty());
+        assert!(bash_paths("cat /etc/passwd").is_empty());
+    }
+}
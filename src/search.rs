@@ -0,0 +1,99 @@
+//! `sentinel search`: find past messages and responses by text or meaning.
+
+use std::path::Path;
+
+use crate::embeddings::{cosine_similarity, embeddable_text, EmbeddingClient, EmbeddingStore};
+use crate::event::ObservabilityEvent;
+use crate::storage::Storage;
+
+const SNIPPET_CHARS: usize = 160;
+
+pub async fn run(
+    data_dir: &Path,
+    query: &str,
+    semantic: bool,
+    limit: usize,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let db_path = data_dir.join("sentinel.db");
+    if !db_path.exists() {
+        println!("No events found. Run 'sentinel start' first to capture some traffic.");
+        return Ok(());
+    }
+    let storage = Storage::new(&db_path).await?;
+
+    let hits: Vec<(Option<f32>, ObservabilityEvent)> = if semantic {
+        semantic_search(&storage, query, limit).await?
+    } else {
+        storage
+            .search_events(query, limit as i64)
+            .await?
+            .into_iter()
+            .map(|e| (None, e))
+            .collect()
+    };
+
+    if hits.is_empty() {
+        println!("No matches.");
+        return Ok(());
+    }
+    for (score, event) in &hits {
+        print_hit(*score, event);
+    }
+    Ok(())
+}
+
+async fn semantic_search(
+    storage: &Storage,
+    query: &str,
+    limit: usize,
+) -> Result<Vec<(Option<f32>, ObservabilityEvent)>, Box<dyn std::error::Error>> {
+    let client = EmbeddingClient::from_env()?;
+    let store = EmbeddingStore::new(storage.pool());
+    store.init_schema().await?;
+
+    let indexed = store.backfill(&client).await?;
+    if indexed > 0 {
+        eprintln!("Indexed {} new events with {}", indexed, client.model);
+    }
+
+    let Some(query_vector) = client.embed(&[query.to_string()]).await?.pop() else {
+        return Ok(Vec::new());
+    };
+    let mut scored: Vec<(f32, uuid::Uuid)> = store
+        .all(&client.model)
+        .await?
+        .into_iter()
+        .map(|e| (cosine_similarity(&query_vector, &e.vector), e.event_id))
+        .collect();
+    scored.sort_by(|a, b| b.0.total_cmp(&a.0));
+    scored.truncate(limit);
+
+    let mut hits = Vec::with_capacity(scored.len());
+    for (score, id) in scored {
+        match storage.get_event(id).await? {
+            Some(event) => hits.push((Some(score), event)),
+            None => tracing::warn!("Embedding refers to missing event {}", id),
+        }
+    }
+    Ok(hits)
+}
+
+fn print_hit(score: Option<f32>, event: &ObservabilityEvent) {
+    let text = embeddable_text(event).unwrap_or_default();
+    let snippet: String = text
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .chars()
+        .take(SNIPPET_CHARS)
+        .collect();
+    let score = score.map(|s| format!("{:.2} ", s)).unwrap_or_default();
+    println!(
+        "{}{} [{}] {}",
+        score,
+        event.timestamp.format("%Y-%m-%d %H:%M"),
+        event.agent.as_deref().unwrap_or("unknown"),
+        event.session_id.as_deref().unwrap_or("-"),
+    );
+    println!("    {}", snippet);
+}
@@ -0,0 +1,278 @@
+//! `sentinel service`: run the proxy at login as a user-level service.
+//!
+//! On Linux this writes a systemd user unit and enables it with
+//! `systemctl --user`; on macOS it writes a launchd agent plist and loads it
+//! with `launchctl`. The unit runs this same binary with `start`, the chosen
+//! port, and the current data directory.
+
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+const UNIT_NAME: &str = "sentinel.service";
+const LAUNCHD_LABEL: &str = "dev.sentinel.proxy";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Manager {
+    Systemd,
+    Launchd,
+}
+
+impl Manager {
+    fn current() -> Result<Self, Box<dyn std::error::Error>> {
+        if cfg!(target_os = "macos") {
+            Ok(Manager::Launchd)
+        } else if cfg!(target_os = "linux") {
+            Ok(Manager::Systemd)
+        } else {
+            Err("sentinel service supports systemd (Linux) and launchd (macOS) only".into())
+        }
+    }
+
+    fn unit_path(self) -> Result<PathBuf, Box<dyn std::error::Error>> {
+        let home = dirs::home_dir().ok_or("Can't find the home directory")?;
+        Ok(match self {
+            Manager::Systemd => home.join(".config/systemd/user").join(UNIT_NAME),
+            Manager::Launchd => home
+                .join("Library/LaunchAgents")
+                .join(format!("{}.plist", LAUNCHD_LABEL)),
+        })
+    }
+}
+
+/// The command line the service runs
+fn service_command(exe: &Path, port: u16, extra: &[String]) -> Vec<String> {
+    let mut command = vec![
+        exe.display().to_string(),
+        "start".to_string(),
+        "--port".to_string(),
+        port.to_string(),
+    ];
+    command.extend(extra.iter().cloned());
+    command
+}
+
+/// A value for a unit file line. systemd expands `%` specifiers in every
+/// line, and `$` variables in `ExecStart=` (`exec`) but not `Environment=`,
+/// so those are doubled to stay literal.
+fn systemd_quote(arg: &str, exec: bool) -> String {
+    let mut arg = arg.replace('%', "%%");
+    if exec {
+        arg = arg.replace('$', "$$");
+    }
+    if arg.is_empty() || arg.contains(|c: char| c.is_whitespace() || c == '"' || c == '\\') {
+        format!("\"{}\"", arg.replace('\\', "\\\\").replace('"', "\\\""))
+    } else {
+        arg
+    }
+}
+
+fn systemd_unit(command: &[String], data_dir: &Path) -> String {
+    let exec: Vec<String> = command.iter().map(|a| systemd_quote(a, true)).collect();
+    format!(
+        "[Unit]
+Description=Sentinel flight recorder for AI agents
+After=network-online.target
+
+[Service]
+ExecStart={}
+Environment={}
+Restart=on-failure
+RestartSec=2
+
+[Install]
+WantedBy=default.target
+",
+        exec.join(" "),
+        systemd_quote(&format!("SENTINEL_DATA_DIR={}", data_dir.display()), false)
+    )
+}
+
+fn xml_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn launchd_plist(command: &[String], data_dir: &Path) -> String {
+    let args: String = command
+        .iter()
+        .map(|a| format!("        <string>{}</string>\n", xml_escape(a)))
+        .collect();
+    let log = data_dir.join("service.log");
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+<dict>
+    <key>Label</key>
+    <string>{label}</string>
+    <key>ProgramArguments</key>
+    <array>
+{args}    </array>
+    <key>EnvironmentVariables</key>
+    <dict>
+        <key>SENTINEL_DATA_DIR</key>
+        <string>{data_dir}</string>
+    </dict>
+    <key>RunAtLoad</key>
+    <true/>
+    <key>KeepAlive</key>
+    <true/>
+    <key>StandardOutPath</key>
+    <string>{log}</string>
+    <key>StandardErrorPath</key>
+    <string>{log}</string>
+</dict>
+</plist>
+"#,
+        label = LAUNCHD_LABEL,
+        args = args,
+        data_dir = xml_escape(&data_dir.display().to_string()),
+        log = xml_escape(&log.display().to_string()),
+    )
+}
+
+/// Run a service manager command, failing with its output if it fails
+fn run_manager(program: &str, args: &[&str]) -> Result<(), Box<dyn std::error::Error>> {
+    let output = Command::new(program)
+        .args(args)
+        .output()
+        .map_err(|e| format!("Failed to run {}: {}", program, e))?;
+    if !output.status.success() {
+        return Err(format!(
+            "{} {} failed: {}",
+            program,
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        )
+        .into());
+    }
+    Ok(())
+}
+
+pub fn install(
+    data_dir: &Path,
+    port: u16,
+    extra: &[String],
+    dry_run: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let manager = Manager::current()?;
+    let exe = std::env::current_exe()?;
+    let command = service_command(&exe, port, extra);
+    let contents = match manager {
+        Manager::Systemd => systemd_unit(&command, data_dir),
+        Manager::Launchd => launchd_plist(&command, data_dir),
+    };
+    let path = manager.unit_path()?;
+    if dry_run {
+        println!("# {}", path.display());
+        print!("{}", contents);
+        return Ok(());
+    }
+
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::create_dir_all(data_dir)?;
+    std::fs::write(&path, contents)?;
+    println!("Wrote {}", path.display());
+
+    match manager {
+        Manager::Systemd => {
+            run_manager("systemctl", &["--user", "daemon-reload"])?;
+            run_manager("systemctl", &["--user", "enable", "--now", UNIT_NAME])?;
+        }
+        Manager::Launchd => {
+            let path = path.display().to_string();
+            // Reloading picks up a changed plist; unloading one that isn't loaded is fine to fail
+            let _ = run_manager("launchctl", &["unload", &path]);
+            run_manager("launchctl", &["load", "-w", &path])?;
+        }
+    }
+    println!("Sentinel will now run at login on port {}", port);
+    Ok(())
+}
+
+pub fn uninstall() -> Result<(), Box<dyn std::error::Error>> {
+    let manager = Manager::current()?;
+    let path = manager.unit_path()?;
+    if !path.exists() {
+        println!(
+            "No sentinel service installed ({} not found)",
+            path.display()
+        );
+        return Ok(());
+    }
+    match manager {
+        Manager::Systemd => run_manager("systemctl", &["--user", "disable", "--now", UNIT_NAME])?,
+        Manager::Launchd => {
+            run_manager("launchctl", &["unload", "-w", &path.display().to_string()])?
+        }
+    }
+    std::fs::remove_file(&path)?;
+    if manager == Manager::Systemd {
+        run_manager("systemctl", &["--user", "daemon-reload"])?;
+    }
+    println!("Removed {}", path.display());
+    Ok(())
+}
+
+pub fn status() -> Result<(), Box<dyn std::error::Error>> {
+    let manager = Manager::current()?;
+    let path = manager.unit_path()?;
+    if !path.exists() {
+        println!("Not installed. Run 'sentinel service install'.");
+        return Ok(());
+    }
+    println!("Installed: {}", path.display());
+    // Both managers print their own status; a non-zero exit just means stopped
+    let status = match manager {
+        Manager::Systemd => Command::new("systemctl")
+            .args(["--user", "status", "--no-pager", UNIT_NAME])
+            .status()?,
+        Manager::Launchd => Command::new("launchctl")
+            .args(["list", LAUNCHD_LABEL])
+            .status()?,
+    };
+    if !status.success() {
+        println!("Service is not running.");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unit_files_quote_arguments() {
+        let command = service_command(
+            Path::new("/opt/my tools/sentinel"),
+            9100,
+            &["--guard-tool".to_string(), "Bash".to_string()],
+        );
+        let data_dir = Path::new("/home/me/.sentinel");
+
+        let unit = systemd_unit(&command, data_dir);
+        assert!(unit.contains(
+            "ExecStart=\"/opt/my tools/sentinel\" start --port 9100 --guard-tool Bash\n"
+        ));
+        assert!(unit.contains("Environment=SENTINEL_DATA_DIR=/home/me/.sentinel\n"));
+
+        let plist = launchd_plist(&command, data_dir);
+        assert!(plist.contains("<string>/opt/my tools/sentinel</string>"));
+        assert!(plist.contains("<string>9100</string>"));
+        assert!(plist.contains("<string>/home/me/.sentinel/service.log</string>"));
+
+        // systemd would expand these
+        let command = service_command(
+            Path::new("/opt/sentinel"),
+            9100,
+            &["--redact-rules".to_string(), "/srv/$HOME/100%.yaml".to_string()],
+        );
+        let unit = systemd_unit(&command, Path::new("/data/%h/$USER"));
+        assert!(unit.contains("--redact-rules /srv/$$HOME/100%%.yaml\n"));
+        assert!(unit.contains("Environment=SENTINEL_DATA_DIR=/data/%%h/$USER\n"));
+    }
+}
@@ -0,0 +1,231 @@
+//! Closing idle sessions (`--close-idle-after`).
+//!
+//! Agents whose last request is older than the threshold are marked inactive
+//! and get a `session_closed` event summing up the session since it last
+//! closed: when it started and ended, how many turns it took, and its
+//! requests, errors, tokens, and cost. An agent that comes back starts a new
+//! session, which closes again the next time it goes quiet.
+
+use std::sync::Arc;
+use std::time::Duration as StdDuration;
+
+use chrono::{Duration, Utc};
+use tokio::sync::broadcast;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use crate::agent::{Agent, AgentStore};
+use crate::event::{ObservabilityEvent, Payload, SessionClosed};
+use crate::pricing;
+use crate::storage::Storage;
+use crate::worktree::Worktrees;
+
+/// Default quiet time before a session is closed
+pub const DEFAULT_IDLE_AFTER: &str = "30m";
+
+/// Longest wait between checks for idle agents
+const MAX_CHECK_EVERY: StdDuration = StdDuration::from_secs(60);
+
+/// A session's events since its last `session_closed`
+pub fn since_last_close(events: &[ObservabilityEvent]) -> &[ObservabilityEvent] {
+    let start = events
+        .iter()
+        .rposition(|e| matches!(e.payload, Payload::SessionClosed(_)))
+        .map_or(0, |i| i + 1);
+    &events[start..]
+}
+
+/// Totals for a session's events since its last `session_closed`; None if
+/// nothing happened since
+fn summarize(events: &[ObservabilityEvent]) -> Option<SessionClosed> {
+    let events = since_last_close(events);
+    let (first, last) = (events.first()?, events.last()?);
+
+    let mut closed = SessionClosed {
+        started_at: first.timestamp,
+        ended_at: last.timestamp,
+        duration_secs: (last.timestamp - first.timestamp).num_seconds(),
+        turns: 0,
+        requests: 0,
+        errors: 0,
+        input_tokens: 0,
+        output_tokens: 0,
+        cost_usd: 0.0,
+    };
+    for event in events {
+        match &event.payload {
+            Payload::UserMessage(_) => closed.turns += 1,
+            Payload::AssistantResponse(response) => {
+                closed.requests += 1;
+                if let Some(ref usage) = response.usage {
+                    closed.input_tokens += usage.input_tokens.unwrap_or(0);
+                    closed.output_tokens += usage.output_tokens.unwrap_or(0);
+                    closed.cost_usd +=
+                        pricing::cost_usd(response.model.as_deref(), usage).unwrap_or(0.0);
+                }
+            }
+            Payload::Error(_) => {
+                closed.requests += 1;
+                closed.errors += 1;
+            }
+            Payload::Alert(_)
+            | Payload::Violation(_)
+            | Payload::SessionClosed(_)
+            | Payload::StreamDelta(_)
+            | Payload::StreamAborted(_)
+            | Payload::TurnSnapshot(_)
+            | Payload::Annotation(_) => {}
+        }
+    }
+    Some(closed)
+}
+
+pub struct SessionCloser {
+    storage: Storage,
+    agents: AgentStore,
+    broadcaster: broadcast::Sender<ObservabilityEvent>,
+    idle_after: Duration,
+    worktrees: Option<Arc<Worktrees>>,
+}
+
+impl SessionCloser {
+    pub fn new(
+        storage: Storage,
+        broadcaster: broadcast::Sender<ObservabilityEvent>,
+        idle_after: Duration,
+    ) -> Self {
+        Self {
+            agents: AgentStore::new(storage.pool()),
+            storage,
+            broadcaster,
+            idle_after,
+            worktrees: None,
+        }
+    }
+
+    /// Also snapshot each closing session's working tree
+    pub fn with_worktrees(mut self, worktrees: Option<Arc<Worktrees>>) -> Self {
+        self.worktrees = worktrees;
+        self
+    }
+
+    /// Check for idle agents in the background, a few times per threshold
+    pub fn spawn(self) {
+        let every = (self.idle_after / 4)
+            .to_std()
+            .unwrap_or(MAX_CHECK_EVERY)
+            .clamp(StdDuration::from_secs(1), MAX_CHECK_EVERY);
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(every);
+            loop {
+                interval.tick().await;
+                if let Err(e) = self.close_idle().await {
+                    warn!("Failed to close idle sessions: {}", e);
+                }
+            }
+        });
+    }
+
+    async fn close_idle(&self) -> Result<(), sqlx::Error> {
+        for agent in self.agents.idle_since(Utc::now() - self.idle_after).await? {
+            // Another instance sharing the data dir may have closed it first
+            if !self
+                .agents
+                .mark_inactive(&agent.id, agent.last_seen_at)
+                .await?
+            {
+                continue;
+            }
+            self.close(&agent).await?;
+        }
+        Ok(())
+    }
+
+    async fn close(&self, agent: &Agent) -> Result<(), sqlx::Error> {
+        if let Some(ref worktrees) = self.worktrees {
+            if let Err(e) = worktrees.end(agent).await {
+                warn!("Failed to snapshot {}'s working tree: {}", agent.name, e);
+            }
+        }
+        let events = self.storage.get_session_events(&agent.session_id).await?;
+        let Some(closed) = summarize(&events) else {
+            return Ok(());
+        };
+        info!(
+            "Session of '{}' closed after {}s idle ({} turns)",
+            agent.name,
+            (Utc::now() - closed.ended_at).num_seconds(),
+            closed.turns
+        );
+        let mut event = ObservabilityEvent {
+            seq: None,
+            id: Uuid::new_v4(),
+            timestamp: Utc::now(),
+            session_id: Some(agent.session_id.clone()),
+            agent: Some(agent.name.clone()),
+            topic: agent.topic.clone(),
+            provider: None,
+            payload: Payload::SessionClosed(closed),
+        };
+        event.seq = Some(self.storage.insert_observability_event(&event).await?);
+        let _ = self.broadcaster.send(event);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event::{AssistantResponse, UserMessage};
+    use crate::parsers::Usage;
+
+    fn prompt() -> Payload {
+        Payload::UserMessage(UserMessage {
+            text: "go".to_string(),
+            ..Default::default()
+        })
+    }
+
+    fn response() -> Payload {
+        Payload::AssistantResponse(AssistantResponse {
+            model: Some("claude-sonnet-4".to_string()),
+            usage: Some(Usage {
+                input_tokens: Some(100),
+                output_tokens: Some(10),
+                cache_read_tokens: None,
+                cache_creation_tokens: None,
+                cost_usd: Some(0.25),
+            }),
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn test_summary_covers_events_since_the_last_close() {
+        let mut events = vec![
+            ObservabilityEvent::for_tests(0, prompt()),
+            ObservabilityEvent::for_tests(60, response()),
+        ];
+        let first = summarize(&events).unwrap();
+        assert_eq!(
+            (first.turns, first.requests, first.duration_secs),
+            (1, 1, 60)
+        );
+
+        events.push(ObservabilityEvent::for_tests(40 * 60, Payload::SessionClosed(first)));
+        assert!(summarize(&events).is_none());
+
+        events.extend([
+            ObservabilityEvent::for_tests(50 * 60, prompt()),
+            ObservabilityEvent::for_tests(52 * 60, response()),
+            ObservabilityEvent::for_tests(53 * 60, prompt()),
+            ObservabilityEvent::for_tests(55 * 60, response()),
+        ]);
+        let second = summarize(&events).unwrap();
+        assert_eq!(second.started_at, events[3].timestamp);
+        assert_eq!((second.turns, second.requests), (2, 2));
+        assert_eq!((second.input_tokens, second.output_tokens), (200, 20));
+        assert_eq!(second.duration_secs, 300);
+        assert_eq!(second.cost_usd, 0.5);
+    }
+}
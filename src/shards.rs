@@ -0,0 +1,365 @@
+//! Monthly event shards (`--shard-by-month`) and `sentinel shards`.
+//!
+//! When sharding is on, events are written to `sentinel-YYYY-MM.db` next to
+//! `sentinel.db` instead of its `observability_events` table, and the
+//! `event_shards` catalog in `sentinel.db` lists the files. Every pooled
+//! connection attaches the catalog's shards and gets a temp view named
+//! `observability_events` over the main table and all of them. That view
+//! shadows the main table, so reads need no changes. Archiving or pruning a
+//! month means moving or deleting its file. Missing files are skipped.
+//!
+//! SQLite attaches at most 10 databases per connection, so only the 10
+//! latest months are queried. Older files stay in the catalog and on disk,
+//! and opening the storage warns about them until they're archived.
+
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use chrono::{NaiveDate, Utc};
+use sqlx::{Connection, SqliteConnection, SqlitePool};
+use tokio::sync::Mutex;
+use tracing::{info, warn};
+
+use crate::storage::{Storage, EVENTS_TABLE};
+
+/// SQLite's default SQLITE_MAX_ATTACHED
+pub const MAX_ATTACHED: usize = 10;
+
+const EVENT_COLUMNS: &str =
+    "seq, id, timestamp, session_id, agent, topic, provider, payload, raw, raw_bytes, raw_truncated";
+
+/// Shard state shared by a storage handle and its pool's connection hooks
+pub struct Shards {
+    dir: PathBuf,
+    enabled: AtomicBool,
+    /// Month whose shard is known to exist, so inserts skip the catalog
+    current: Mutex<Option<String>>,
+}
+
+#[derive(Debug, sqlx::FromRow)]
+pub struct ShardEntry {
+    pub month: String,
+    pub file: String,
+}
+
+impl Shards {
+    pub fn new(dir: &Path) -> Self {
+        Self {
+            dir: dir.to_path_buf(),
+            enabled: AtomicBool::new(false),
+            current: Mutex::new(None),
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+
+    pub fn set_enabled(&self) {
+        self.enabled.store(true, Ordering::Relaxed);
+    }
+
+    /// Schema name the month being written is attached as, creating its
+    /// shard first if this is the month's first event
+    pub async fn current_schema(&self, pool: &SqlitePool) -> Result<String, sqlx::Error> {
+        let month = Utc::now().format("%Y-%m").to_string();
+        let mut current = self.current.lock().await;
+        if current.as_deref() != Some(month.as_str()) {
+            self.create(pool, &month).await?;
+            *current = Some(month.clone());
+        }
+        Ok(schema_name(&month))
+    }
+
+    async fn create(&self, pool: &SqlitePool, month: &str) -> Result<(), sqlx::Error> {
+        let file = file_name(month);
+        let url = format!("sqlite:{}?mode=rwc", self.dir.join(&file).display());
+        let mut conn = SqliteConnection::connect(&url).await?;
+        sqlx::query(EVENTS_TABLE).execute(&mut conn).await?;
+        sqlx::query(
+            "CREATE INDEX IF NOT EXISTS idx_obs_events_agent ON observability_events(agent)",
+        )
+        .execute(&mut conn)
+        .await?;
+
+        // Continue from the highest seq anywhere, so seq stays unique and
+        // increasing across files
+        let last_seq: i64 =
+            sqlx::query_scalar("SELECT COALESCE(MAX(seq), 0) FROM observability_events")
+                .fetch_one(pool)
+                .await?;
+        sqlx::query(
+            r#"
+            INSERT INTO sqlite_sequence (name, seq)
+            SELECT 'observability_events', ?
+            WHERE NOT EXISTS (SELECT 1 FROM sqlite_sequence WHERE name = 'observability_events')
+            "#,
+        )
+        .bind(last_seq)
+        .execute(&mut conn)
+        .await?;
+        conn.close().await?;
+
+        let added = sqlx::query(
+            "INSERT OR IGNORE INTO event_shards (month, file, created_at) VALUES (?, ?, ?)",
+        )
+        .bind(month)
+        .bind(&file)
+        .bind(Utc::now().to_rfc3339())
+        .execute(pool)
+        .await?;
+        if added.rows_affected() > 0 {
+            info!("Recording events for {} into {}", month, file);
+        }
+        Ok(())
+    }
+
+    /// Months whose shards are on disk but older than the latest
+    /// [`MAX_ATTACHED`], so left out of every read
+    pub async fn unqueried(&self, pool: &SqlitePool) -> Result<Vec<String>, sqlx::Error> {
+        if !self.enabled() {
+            return Ok(Vec::new());
+        }
+        let entries: Vec<ShardEntry> =
+            sqlx::query_as("SELECT month, file FROM main.event_shards ORDER BY month DESC")
+                .fetch_all(pool)
+                .await?;
+        Ok(entries
+            .into_iter()
+            .filter(|e| self.dir.join(&e.file).exists())
+            .skip(MAX_ATTACHED)
+            .map(|e| e.month)
+            .collect())
+    }
+
+    /// Attach the catalog's latest shards to a connection and point the
+    /// `observability_events` view at them; cheap when nothing changed
+    pub async fn attach(&self, conn: &mut SqliteConnection) -> Result<(), sqlx::Error> {
+        if !self.enabled() {
+            return Ok(());
+        }
+        let entries: Vec<ShardEntry> = sqlx::query_as(
+            "SELECT month, file FROM main.event_shards ORDER BY month DESC",
+        )
+        .fetch_all(&mut *conn)
+        .await?;
+        let mut wanted = Vec::new();
+        let mut skipped = Vec::new();
+        for entry in entries {
+            if NaiveDate::parse_from_str(&format!("{}-01", entry.month), "%Y-%m-%d").is_err() {
+                skipped.push(format!("{} (not a YYYY-MM month)", entry.month));
+            } else if !self.dir.join(&entry.file).exists() {
+                skipped.push(format!("{} ({} is gone)", entry.month, entry.file));
+            } else if wanted.len() == MAX_ATTACHED {
+                skipped.push(format!(
+                    "{} (older than the latest {})",
+                    entry.month, MAX_ATTACHED
+                ));
+            } else {
+                wanted.push(entry);
+            }
+        }
+
+        let attached: Vec<String> = sqlx::query_scalar(
+            "SELECT name FROM pragma_database_list WHERE name LIKE 'shard\\_%' ESCAPE '\\'",
+        )
+        .fetch_all(&mut *conn)
+        .await?;
+        let mut wanted_names: Vec<String> = wanted.iter().map(|e| schema_name(&e.month)).collect();
+        let mut attached_sorted = attached.clone();
+        wanted_names.sort();
+        attached_sorted.sort();
+        if wanted_names == attached_sorted {
+            return Ok(());
+        }
+        for shard in &skipped {
+            warn!("Not querying event shard {}", shard);
+        }
+
+        sqlx::query("DROP VIEW IF EXISTS temp.observability_events")
+            .execute(&mut *conn)
+            .await?;
+        for name in &attached {
+            sqlx::query(&format!("DETACH DATABASE {}", name))
+                .execute(&mut *conn)
+                .await?;
+        }
+        if wanted.is_empty() {
+            return Ok(());
+        }
+        let mut selects = vec![format!(
+            "SELECT {} FROM main.observability_events",
+            EVENT_COLUMNS
+        )];
+        for entry in &wanted {
+            let name = schema_name(&entry.month);
+            sqlx::query(&format!("ATTACH DATABASE ? AS {}", name))
+                .bind(self.dir.join(&entry.file).display().to_string())
+                .execute(&mut *conn)
+                .await?;
+            selects.push(format!(
+                "SELECT {} FROM {}.observability_events",
+                EVENT_COLUMNS, name
+            ));
+        }
+        sqlx::query(&format!(
+            "CREATE TEMP VIEW observability_events AS {}",
+            selects.join(" UNION ALL ")
+        ))
+        .execute(&mut *conn)
+        .await?;
+        Ok(())
+    }
+}
+
+/// Create the catalog, turning sharding on for this data dir from now on
+pub async fn init_catalog(pool: &SqlitePool) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS event_shards (
+            month TEXT PRIMARY KEY,
+            file TEXT NOT NULL,
+            created_at TEXT NOT NULL
+        )
+        "#,
+    )
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// Whether the data dir has a shard catalog
+pub async fn catalog_exists(pool: &SqlitePool) -> Result<bool, sqlx::Error> {
+    sqlx::query_scalar(
+        "SELECT EXISTS(SELECT 1 FROM main.sqlite_master WHERE type = 'table' AND name = 'event_shards')",
+    )
+    .fetch_one(pool)
+    .await
+}
+
+fn schema_name(month: &str) -> String {
+    format!("shard_{}", month.replace('-', "_"))
+}
+
+fn file_name(month: &str) -> String {
+    format!("sentinel-{}.db", month)
+}
+
+pub async fn run(data_dir: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    let db_path = data_dir.join("sentinel.db");
+    if !db_path.exists() {
+        return Err("No recorded traffic. Run 'sentinel start' first.".into());
+    }
+    let storage = Storage::new(&db_path).await?;
+    if !catalog_exists(&storage.pool()).await? {
+        println!("Events aren't sharded. Start with --shard-by-month to record each month into its own file.");
+        return Ok(());
+    }
+    let entries: Vec<ShardEntry> =
+        sqlx::query_as("SELECT month, file FROM event_shards ORDER BY month DESC")
+            .fetch_all(&storage.pool())
+            .await?;
+    if entries.is_empty() {
+        println!("No shards yet; the first event recorded creates this month's.");
+        return Ok(());
+    }
+
+    println!("{:<8} {:<24} {:>10}  status", "month", "file", "size");
+    let mut present = 0;
+    for entry in &entries {
+        let status = match std::fs::metadata(data_dir.join(&entry.file)) {
+            Ok(meta) => {
+                present += 1;
+                let size = format!("{:.1} MB", meta.len() as f64 / (1024.0 * 1024.0));
+                let status = if present <= MAX_ATTACHED {
+                    "queried"
+                } else {
+                    "not queried (archive it)"
+                };
+                (size, status)
+            }
+            Err(_) => ("-".to_string(), "missing"),
+        };
+        println!(
+            "{:<8} {:<24} {:>10}  {}",
+            entry.month, entry.file, status.0, status.1
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event::{Alert, ObservabilityEvent, Payload};
+    use uuid::Uuid;
+
+    fn alert(message: &str) -> ObservabilityEvent {
+        ObservabilityEvent {
+            seq: None,
+            id: Uuid::new_v4(),
+            timestamp: Utc::now(),
+            session_id: None,
+            agent: Some("calm-otter".to_string()),
+            topic: None,
+            provider: None,
+            payload: Payload::Alert(Alert {
+                kind: "test".to_string(),
+                message: message.to_string(),
+            }),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_sharded_inserts_read_back_through_view() {
+        let dir = std::env::temp_dir().join(format!("sentinel-shards-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let storage = Storage::new(&dir.join("sentinel.db")).await.unwrap();
+        let before = storage
+            .insert_observability_event(&alert("before"))
+            .await
+            .unwrap();
+        storage.enable_monthly_shards().await.unwrap();
+        let after = storage
+            .insert_observability_event(&alert("after"))
+            .await
+            .unwrap();
+        assert!(after > before);
+
+        let month = Utc::now().format("%Y-%m").to_string();
+        assert!(dir.join(file_name(&month)).exists());
+        let in_main: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM main.observability_events")
+            .fetch_one(&storage.pool())
+            .await
+            .unwrap();
+        assert_eq!(in_main, 1);
+        let events = storage.get_recent_observability_events(10).await.unwrap();
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].seq, Some(after));
+
+        // A reopened handle finds the catalog and attaches the shard itself
+        let reopened = Storage::new(&dir.join("sentinel.db")).await.unwrap();
+        assert_eq!(reopened.count_events().await.unwrap(), 2);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_months_past_the_attach_limit_are_reported() {
+        let dir = std::env::temp_dir().join(format!("sentinel-shards-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let storage = Storage::new(&dir.join("sentinel.db")).await.unwrap();
+        storage.enable_monthly_shards().await.unwrap();
+        let shards = Shards::new(&dir);
+        shards.set_enabled();
+        for month in 1..=12 {
+            let month = format!("2025-{:02}", month);
+            shards.create(&storage.pool(), &month).await.unwrap();
+        }
+
+        let unqueried = shards.unqueried(&storage.pool()).await.unwrap();
+        assert_eq!(unqueried, vec!["2025-02", "2025-01"]);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
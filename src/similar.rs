@@ -0,0 +1,259 @@
+//! `sentinel similar`: find past sessions that worked on a similar task.
+//!
+//! Sessions are compared by what the agent did, using tool-sequence
+//! fingerprints (which tools, in which order), or by what was said, using the
+//! combined direction of their transcript embeddings when an embedding endpoint is set up.
+
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+
+use chrono::{DateTime, Duration, Utc};
+use clap::ValueEnum;
+
+use crate::embeddings::{cosine_similarity, EmbeddingClient, EmbeddingError, EmbeddingStore};
+use crate::event::{ObservabilityEvent, Payload};
+use crate::storage::Storage;
+
+const TASK_CHARS: usize = 100;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum SimilarBy {
+    /// Embeddings when SENTINEL_EMBEDDINGS_URL is set, tool sequences otherwise
+    Auto,
+    Tools,
+    Embeddings,
+}
+
+#[derive(Debug)]
+pub struct SessionSummary {
+    pub session_id: String,
+    pub agent: Option<String>,
+    pub started: DateTime<Utc>,
+    /// First prompt, which usually states the task
+    pub task: Option<String>,
+    pub turns: usize,
+    pub tools: Vec<String>,
+    pub last_stop_reason: Option<String>,
+}
+
+pub fn summarize(events: &[ObservabilityEvent]) -> Vec<SessionSummary> {
+    let mut order: Vec<String> = Vec::new();
+    let mut sessions: HashMap<String, SessionSummary> = HashMap::new();
+
+    for event in events {
+        let Some(ref session_id) = event.session_id else {
+            continue;
+        };
+        let summary = sessions.entry(session_id.clone()).or_insert_with(|| {
+            order.push(session_id.clone());
+            SessionSummary {
+                session_id: session_id.clone(),
+                agent: event.agent.clone(),
+                started: event.timestamp,
+                task: None,
+                turns: 0,
+                tools: Vec::new(),
+                last_stop_reason: None,
+            }
+        });
+        match &event.payload {
+            Payload::UserMessage(message) if summary.task.is_none() => {
+                summary.task = Some(message.text.clone());
+            }
+            Payload::AssistantResponse(response) => {
+                summary.turns += 1;
+                summary
+                    .tools
+                    .extend(response.tool_calls.iter().map(|c| c.name.clone()));
+                summary.last_stop_reason = response.stop_reason.clone();
+            }
+            _ => {}
+        }
+    }
+
+    order
+        .into_iter()
+        .filter_map(|id| sessions.remove(&id))
+        .collect()
+}
+
+/// Tools used plus consecutive tool pairs, so "read then edit" and
+/// "edit then test" count as different workflows
+pub fn fingerprint(tools: &[String]) -> HashSet<String> {
+    let mut features: HashSet<String> = tools.iter().cloned().collect();
+    for pair in tools.windows(2) {
+        features.insert(format!("{}>{}", pair[0], pair[1]));
+    }
+    features
+}
+
+pub fn jaccard(a: &HashSet<String>, b: &HashSet<String>) -> f32 {
+    let union = a.union(b).count();
+    if union == 0 {
+        return 0.0;
+    }
+    a.intersection(b).count() as f32 / union as f32
+}
+
+fn by_tools(target: &SessionSummary, others: &[SessionSummary]) -> Vec<(f32, usize)> {
+    let target_print = fingerprint(&target.tools);
+    others
+        .iter()
+        .enumerate()
+        .map(|(i, s)| (jaccard(&target_print, &fingerprint(&s.tools)), i))
+        .collect()
+}
+
+async fn by_embeddings(
+    storage: &Storage,
+    client: &EmbeddingClient,
+    target: &SessionSummary,
+    others: &[SessionSummary],
+) -> Result<Vec<(f32, usize)>, Box<dyn std::error::Error>> {
+    let store = EmbeddingStore::new(storage.pool());
+    store.init_schema().await?;
+    let indexed = store.backfill(client).await?;
+    if indexed > 0 {
+        eprintln!("Indexed {} new events with {}", indexed, client.model);
+    }
+
+    // Cosine similarity ignores magnitude, so summing works as well as averaging
+    let mut sums: HashMap<String, Vec<f32>> = HashMap::new();
+    for embedding in store.all(&client.model).await? {
+        let Some(session_id) = embedding.session_id else {
+            continue;
+        };
+        let sum = sums
+            .entry(session_id)
+            .or_insert_with(|| vec![0.0; embedding.vector.len()]);
+        if sum.len() != embedding.vector.len() {
+            tracing::warn!("Skipping embedding with mismatched dimensions");
+            continue;
+        }
+        for (s, v) in sum.iter_mut().zip(&embedding.vector) {
+            *s += v;
+        }
+    }
+    let Some(target_vector) = sums.get(&target.session_id) else {
+        return Err(format!("Session {} has no embeddable messages", target.session_id).into());
+    };
+    Ok(others
+        .iter()
+        .enumerate()
+        .filter_map(|(i, s)| {
+            let vector = sums.get(&s.session_id)?;
+            Some((cosine_similarity(target_vector, vector), i))
+        })
+        .collect())
+}
+
+pub async fn run(
+    data_dir: &Path,
+    session: &str,
+    by: SimilarBy,
+    since: Duration,
+    limit: usize,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let db_path = data_dir.join("sentinel.db");
+    if !db_path.exists() {
+        return Err("No recorded traffic. Run 'sentinel start' first.".into());
+    }
+    let storage = Storage::new(&db_path).await?;
+
+    let target_events = storage.get_session_or_agent_events(session).await?;
+    let Some(target) = summarize(&target_events).into_iter().last() else {
+        return Err(format!("No session or agent named '{}'", session).into());
+    };
+    let others: Vec<SessionSummary> =
+        summarize(&storage.get_events_since(Utc::now() - since).await?)
+            .into_iter()
+            .filter(|s| s.session_id != target.session_id)
+            .collect();
+
+    let client = match by {
+        SimilarBy::Tools => None,
+        SimilarBy::Embeddings => Some(EmbeddingClient::from_env()?),
+        SimilarBy::Auto => match EmbeddingClient::from_env() {
+            Ok(client) => Some(client),
+            Err(EmbeddingError::NotConfigured) => None,
+            Err(e) => return Err(e.into()),
+        },
+    };
+    let (method, mut scored) = match client {
+        Some(ref client) => (
+            "transcript embeddings",
+            by_embeddings(&storage, client, &target, &others).await?,
+        ),
+        None => {
+            if target.tools.is_empty() {
+                return Err(format!(
+                    "Session {} made no tool calls to compare; set SENTINEL_EMBEDDINGS_URL to compare by content",
+                    target.session_id
+                )
+                .into());
+            }
+            ("tool sequences", by_tools(&target, &others))
+        }
+    };
+    scored.retain(|(score, _)| *score > 0.0);
+    scored.sort_by(|a, b| b.0.total_cmp(&a.0));
+    scored.truncate(limit);
+
+    println!(
+        "Sessions similar to {} by {}:\n  {}\n",
+        target.session_id,
+        method,
+        snippet(target.task.as_deref())
+    );
+    if scored.is_empty() {
+        println!("No similar sessions found.");
+        return Ok(());
+    }
+    for (score, i) in scored {
+        let s = &others[i];
+        println!(
+            "{:.2}  {}  {} [{}]  {} turns, ended {}",
+            score,
+            s.started.format("%Y-%m-%d %H:%M"),
+            s.session_id,
+            s.agent.as_deref().unwrap_or("unknown"),
+            s.turns,
+            s.last_stop_reason.as_deref().unwrap_or("-")
+        );
+        println!("      {}", snippet(s.task.as_deref()));
+    }
+    Ok(())
+}
+
+fn snippet(text: Option<&str>) -> String {
+    let text = text.unwrap_or("(no prompt)");
+    let line: String = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    let mut short: String = line.chars().take(TASK_CHARS).collect();
+    if line.chars().count() > TASK_CHARS {
+        short.push('…');
+    }
+    short
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tools(names: &[&str]) -> Vec<String> {
+        names.iter().map(|n| n.to_string()).collect()
+    }
+
+    #[test]
+    fn test_fingerprint_respects_order() {
+        let read_then_edit = fingerprint(&tools(&["Read", "Edit", "Bash"]));
+        let same = fingerprint(&tools(&["Read", "Edit", "Bash"]));
+        let reversed = fingerprint(&tools(&["Bash", "Edit", "Read"]));
+        let unrelated = fingerprint(&tools(&["WebFetch"]));
+
+        assert_eq!(jaccard(&read_then_edit, &same), 1.0);
+        let partial = jaccard(&read_then_edit, &reversed);
+        assert!(partial > 0.0 && partial < 1.0);
+        assert_eq!(jaccard(&read_then_edit, &unrelated), 0.0);
+        assert_eq!(jaccard(&HashSet::new(), &HashSet::new()), 0.0);
+    }
+}
@@ -0,0 +1,241 @@
+//! Availability SLO tracking per upstream provider.
+//!
+//! Each forwarded request is counted into per-minute buckets. Error rates over
+//! rolling windows are compared against the SLO's error budget; when both the
+//! short and long windows burn budget fast, an alert is raised. This separates
+//! "my agent is broken" from "the API is having a bad day".
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+
+use axum::extract::State;
+use axum::Json;
+use chrono::{DateTime, Duration, Utc};
+use schemars::JsonSchema;
+use serde::Serialize;
+
+use crate::proxy::ProxyState;
+
+/// Default availability objective (99%)
+const DEFAULT_TARGET: f64 = 0.99;
+/// Burn rate that would exhaust a 30-day budget in about two days
+const FAST_BURN_RATE: f64 = 14.4;
+/// Don't alert on a handful of requests
+const MIN_REQUESTS_FOR_ALERT: u64 = 10;
+const ALERT_COOLDOWN_MINUTES: i64 = 15;
+/// Longest window tracked, in minutes
+const RETENTION_MINUTES: i64 = 24 * 60;
+
+const WINDOWS: &[(&str, i64)] = &[("5m", 5), ("1h", 60), ("24h", RETENTION_MINUTES)];
+
+#[derive(Debug, Clone, Copy, Default)]
+struct Bucket {
+    minute: i64,
+    requests: u64,
+    errors: u64,
+}
+
+#[derive(Default)]
+struct ProviderHistory {
+    buckets: VecDeque<Bucket>,
+    last_alert: Option<DateTime<Utc>>,
+}
+
+impl ProviderHistory {
+    fn record(&mut self, minute: i64, is_error: bool) {
+        match self.buckets.back_mut() {
+            Some(bucket) if bucket.minute == minute => {
+                bucket.requests += 1;
+                bucket.errors += u64::from(is_error);
+            }
+            _ => self.buckets.push_back(Bucket {
+                minute,
+                requests: 1,
+                errors: u64::from(is_error),
+            }),
+        }
+        while self
+            .buckets
+            .front()
+            .is_some_and(|b| b.minute <= minute - RETENTION_MINUTES)
+        {
+            self.buckets.pop_front();
+        }
+    }
+
+    fn totals(&self, now_minute: i64, window_minutes: i64) -> (u64, u64) {
+        self.buckets
+            .iter()
+            .filter(|b| b.minute > now_minute - window_minutes)
+            .fold((0, 0), |(r, e), b| (r + b.requests, e + b.errors))
+    }
+}
+
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct WindowStats {
+    pub window: String,
+    pub requests: u64,
+    pub errors: u64,
+    pub error_rate: f64,
+    /// Error rate relative to the budget; 1.0 spends it exactly on schedule
+    pub burn_rate: f64,
+}
+
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct ProviderSlo {
+    pub provider: String,
+    pub target: f64,
+    /// Fraction of the 24h error budget left (negative when overspent)
+    pub budget_remaining: f64,
+    pub windows: Vec<WindowStats>,
+}
+
+pub struct SloTracker {
+    target: f64,
+    providers: Mutex<HashMap<String, ProviderHistory>>,
+}
+
+impl SloTracker {
+    pub fn new() -> Self {
+        let target = match std::env::var("SENTINEL_SLO_TARGET") {
+            Ok(value) => match value.parse::<f64>() {
+                Ok(t) if t > 0.0 && t < 1.0 => t,
+                _ => {
+                    tracing::warn!(
+                        "Ignoring SENTINEL_SLO_TARGET={}: expected a fraction like 0.995",
+                        value
+                    );
+                    DEFAULT_TARGET
+                }
+            },
+            Err(_) => DEFAULT_TARGET,
+        };
+        Self {
+            target,
+            providers: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn budget(&self) -> f64 {
+        1.0 - self.target
+    }
+
+    /// Count a request outcome. Returns an alert message when the error budget
+    /// is burning fast in both the 5m and 1h windows.
+    pub fn record(&self, provider: &str, is_error: bool) -> Option<String> {
+        let now = Utc::now();
+        let minute = now.timestamp() / 60;
+        let budget = self.budget();
+
+        let mut providers = self.providers.lock().unwrap_or_else(|e| e.into_inner());
+        let history = providers.entry(provider.to_string()).or_default();
+        history.record(minute, is_error);
+
+        if !is_error {
+            return None;
+        }
+        let (short_requests, short_errors) = history.totals(minute, 5);
+        let (long_requests, long_errors) = history.totals(minute, 60);
+        if short_requests < MIN_REQUESTS_FOR_ALERT {
+            return None;
+        }
+        let short_burn = short_errors as f64 / short_requests as f64 / budget;
+        let long_burn = long_errors as f64 / long_requests as f64 / budget;
+        if short_burn < FAST_BURN_RATE || long_burn < FAST_BURN_RATE {
+            return None;
+        }
+        if history
+            .last_alert
+            .is_some_and(|t| now - t < Duration::minutes(ALERT_COOLDOWN_MINUTES))
+        {
+            return None;
+        }
+        history.last_alert = Some(now);
+
+        Some(format!(
+            "{} error budget burning fast: {}/{} requests failed in the last 5m ({:.1}x burn), {}/{} in the last hour ({:.1}x)",
+            provider, short_errors, short_requests, short_burn, long_errors, long_requests, long_burn
+        ))
+    }
+
+    pub fn snapshot(&self) -> Vec<ProviderSlo> {
+        let minute = Utc::now().timestamp() / 60;
+        let budget = self.budget();
+        let providers = self.providers.lock().unwrap_or_else(|e| e.into_inner());
+
+        let mut slos: Vec<ProviderSlo> = providers
+            .iter()
+            .map(|(provider, history)| {
+                let windows: Vec<WindowStats> = WINDOWS
+                    .iter()
+                    .map(|(label, minutes)| {
+                        let (requests, errors) = history.totals(minute, *minutes);
+                        let error_rate = if requests == 0 {
+                            0.0
+                        } else {
+                            errors as f64 / requests as f64
+                        };
+                        WindowStats {
+                            window: label.to_string(),
+                            requests,
+                            errors,
+                            error_rate,
+                            burn_rate: error_rate / budget,
+                        }
+                    })
+                    .collect();
+                let day = windows.last().map(|w| w.burn_rate).unwrap_or(0.0);
+                ProviderSlo {
+                    provider: provider.clone(),
+                    target: self.target,
+                    budget_remaining: 1.0 - day,
+                    windows,
+                }
+            })
+            .collect();
+        slos.sort_by(|a, b| a.provider.cmp(&b.provider));
+        slos
+    }
+}
+
+impl Default for SloTracker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub async fn slo_handler(State(state): State<Arc<ProxyState>>) -> Json<Vec<ProviderSlo>> {
+    Json(state.slo.snapshot())
+}
+
+/// Whether an upstream status counts against availability. Client errors
+/// (4xx) are the caller's problem; 5xx including Anthropic's 529 overloaded
+/// are the provider's.
+pub fn is_upstream_error(status: u16) -> bool {
+    status >= 500
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_alerts_once_on_fast_burn() {
+        let tracker = SloTracker {
+            target: 0.99,
+            providers: Mutex::new(HashMap::new()),
+        };
+        for _ in 0..8 {
+            assert!(tracker.record("anthropic", false).is_none());
+        }
+        assert!(tracker.record("anthropic", true).is_none());
+        let alert = tracker.record("anthropic", true);
+        assert!(alert.is_some_and(|a| a.contains("anthropic")));
+        // Cooldown suppresses repeats
+        assert!(tracker.record("anthropic", true).is_none());
+
+        let snapshot = tracker.snapshot();
+        assert_eq!(snapshot[0].windows[0].requests, 11);
+        assert_eq!(snapshot[0].windows[0].errors, 3);
+    }
+}
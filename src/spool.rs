@@ -0,0 +1,235 @@
+//! Request bodies over `--max-request-kb`.
+//!
+//! Bodies that size are mostly attachments: base64 images and documents.
+//! Rather than hold one, the proxy writes it to a temp file as it arrives
+//! and sends the file upstream byte for byte. Alongside it keeps a copy with
+//! its long strings cut short, which parses like the body did: the prompt,
+//! its session, and whether it asks for a stream are all still there, only
+//! the attachments' contents aren't.
+
+use axum::body::BodyDataStream;
+use bytes::{Bytes, BytesMut};
+use futures::StreamExt;
+use std::path::PathBuf;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use uuid::Uuid;
+
+/// Longest string kept whole in the copy
+const MAX_STRING_BYTES: usize = 16 * 1024;
+/// Size of the chunks the file is sent upstream in
+const CHUNK_BYTES: usize = 64 * 1024;
+/// Marks the byte after a backslash, before it's known how long the escape is
+const ESCAPE_START: u8 = u8::MAX;
+
+/// Copies JSON a chunk at a time, cutting every string value at
+/// `max_string` bytes and stopping once the copy reaches `max_total`
+struct Elider {
+    copy: BytesMut,
+    max_string: usize,
+    max_total: usize,
+    /// Whether each open container is an object, innermost last
+    containers: Vec<bool>,
+    /// Whether the next string is an object key, which is never cut
+    expect_key: bool,
+    in_string: bool,
+    in_key: bool,
+    /// Bytes of an escape still to come
+    escape: u8,
+    /// Bytes of the current string copied
+    string_len: usize,
+    cutting: bool,
+}
+
+impl Elider {
+    fn new(max_string: usize, max_total: usize) -> Self {
+        Self {
+            copy: BytesMut::new(),
+            max_string,
+            max_total,
+            containers: Vec::new(),
+            expect_key: false,
+            in_string: false,
+            in_key: false,
+            escape: 0,
+            string_len: 0,
+            cutting: false,
+        }
+    }
+
+    fn push(&mut self, chunk: &[u8]) {
+        for &byte in chunk {
+            if self.copy.len() > self.max_total {
+                return;
+            }
+            let keep = if !self.in_string {
+                match byte {
+                    b'{' | b'[' => {
+                        self.containers.push(byte == b'{');
+                        self.expect_key = byte == b'{';
+                    }
+                    b'}' | b']' => {
+                        self.containers.pop();
+                    }
+                    b',' => self.expect_key = self.containers.last() == Some(&true),
+                    b'"' => {
+                        self.in_string = true;
+                        self.in_key = self.expect_key;
+                        self.expect_key = false;
+                        self.string_len = 0;
+                        self.cutting = false;
+                    }
+                    _ => {}
+                }
+                true
+            } else if self.escape > 0 {
+                self.escape = match self.escape {
+                    ESCAPE_START if byte == b'u' => 4,
+                    ESCAPE_START => 0,
+                    n => n - 1,
+                };
+                !self.cutting
+            } else if byte == b'"' {
+                self.in_string = false;
+                true
+            } else {
+                // Cut between characters, never inside an escape or a
+                // multi-byte character, so the copy stays valid JSON
+                let boundary = !(0x80..0xC0).contains(&byte);
+                if boundary && !self.in_key && self.string_len >= self.max_string {
+                    self.cutting = true;
+                }
+                if byte == b'\\' {
+                    self.escape = ESCAPE_START;
+                }
+                self.string_len += 1;
+                !self.cutting
+            };
+            if keep {
+                self.copy.extend_from_slice(&[byte]);
+            }
+        }
+    }
+}
+
+/// A body written out to a temp file, deleted once it has been sent
+pub struct Spooled {
+    /// The body with its long strings cut short, for parsing
+    pub copy: Bytes,
+    /// Length of the body as it was sent
+    pub len: usize,
+    file: SpoolFile,
+}
+
+struct SpoolFile {
+    path: PathBuf,
+    file: Option<tokio::fs::File>,
+}
+
+impl Drop for SpoolFile {
+    fn drop(&mut self) {
+        // Closed first, as Windows won't delete an open file
+        drop(self.file.take());
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+/// Write a body over the cap to a temp file: `prefix` is what was read of
+/// it, `rest` the remainder still to come from the client
+pub async fn spool(
+    prefix: Bytes,
+    mut rest: BodyDataStream,
+    max_copy: usize,
+) -> std::io::Result<Spooled> {
+    let path = std::env::temp_dir().join(format!("sentinel-body-{}", Uuid::new_v4()));
+    let mut file = SpoolFile {
+        file: Some(tokio::fs::File::create(&path).await?),
+        path,
+    };
+    let out = file.file.as_mut().expect("spool file is open");
+    let mut elider = Elider::new(MAX_STRING_BYTES, max_copy);
+    let mut len = 0;
+    let mut chunk = prefix;
+    loop {
+        elider.push(&chunk);
+        out.write_all(&chunk).await?;
+        len += chunk.len();
+        match rest.next().await {
+            Some(next) => chunk = next.map_err(std::io::Error::other)?,
+            None => break,
+        }
+    }
+    out.flush().await?;
+    file.file = Some(tokio::fs::File::open(&file.path).await?);
+    Ok(Spooled {
+        copy: elider.copy.freeze(),
+        len,
+        file,
+    })
+}
+
+impl Spooled {
+    /// The body as it was sent, read back from the file
+    pub fn into_body(self) -> reqwest::Body {
+        let chunks = futures::stream::try_unfold(self.file, |mut spool| async move {
+            let mut buf = BytesMut::zeroed(CHUNK_BYTES);
+            let file = spool.file.as_mut().expect("spool file is open");
+            let n = file.read(&mut buf).await?;
+            if n == 0 {
+                return Ok::<_, std::io::Error>(None);
+            }
+            buf.truncate(n);
+            Ok(Some((buf.freeze(), spool)))
+        });
+        reqwest::Body::wrap_stream(chunks)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Body;
+
+    #[test]
+    fn test_copy_cuts_long_strings_and_stays_valid_json() {
+        let body = serde_json::json!({
+            "stream": true,
+            "messages": [{"role": "user", "content": [
+                {"type": "image", "source": {"data": "A".repeat(100)}},
+                {"type": "text", "text": "é\"\\ \u{1} résumé"},
+            ]}],
+        })
+        .to_string();
+        for max_string in 0..20 {
+            let mut elider = Elider::new(max_string, usize::MAX);
+            // Fed a byte at a time, so every cut lands mid-chunk somewhere
+            for byte in body.as_bytes().chunks(1) {
+                elider.push(byte);
+            }
+            let copy: serde_json::Value = serde_json::from_slice(&elider.copy)
+                .unwrap_or_else(|e| panic!("cut at {} isn't JSON: {}", max_string, e));
+            assert_eq!(copy["stream"], true);
+            let data = copy["messages"][0]["content"][0]["source"]["data"].as_str().unwrap();
+            assert!(data.len() <= max_string);
+        }
+
+        let mut elider = Elider::new(4, 10);
+        elider.push(body.as_bytes());
+        assert!(elider.copy.len() <= 11);
+    }
+
+    #[tokio::test]
+    async fn test_spooled_body_is_sent_as_it_came() {
+        let chunks: Vec<Result<&'static [u8], std::io::Error>> =
+            vec![Ok(b"\"bbbbbbbbbbbbbbbbbbbbbbbb\""), Ok(b",\"stream\":true}")];
+        let rest = Body::from_stream(futures::stream::iter(chunks)).into_data_stream();
+        let spooled = spool(Bytes::from_static(b"{\"a\":"), rest, 1024).await.unwrap();
+        let path = spooled.file.path.clone();
+        assert_eq!(spooled.len, 46);
+        assert!(crate::retry::is_streaming_request(&spooled.copy));
+
+        let sent = spooled.into_body();
+        let sent: Vec<u8> = http_body_util::BodyExt::collect(sent).await.unwrap().to_bytes().into();
+        assert_eq!(sent, b"{\"a\":\"bbbbbbbbbbbbbbbbbbbbbbbb\",\"stream\":true}");
+        assert!(!path.exists());
+    }
+}
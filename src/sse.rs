@@ -1,71 +1,251 @@
-use std::{convert::Infallible, sync::Arc, time::Duration};
+//! Live events for the dashboard and `sentinel tail` (`GET /api/events`).
+//!
+//! Each stored event goes out with its `seq` as the SSE id. A client that
+//! reconnects with `Last-Event-ID` (browsers send it on their own) or
+//! `?since_seq=` first gets every stored event after that seq, then the live
+//! ones. A subscriber that falls behind the broadcast is caught up the same
+//! way from storage; only if that fails is it sent `resync_required`, naming
+//! the last seq it got.
+
+use std::{collections::HashMap, convert::Infallible, sync::Arc, time::Duration};
 
 use axum::{
     extract::{Query, State},
+    http::HeaderMap,
     response::sse::{Event, KeepAlive, Sse},
 };
 use futures_util::Stream;
 use serde::Deserialize;
 use tokio::sync::broadcast::error::RecvError;
 
-use crate::event::ObservabilityEvent;
+use crate::event::{ObservabilityEvent, ResponseProgress};
+use crate::fold::ViewEvent;
+use crate::presence::Viewer;
 use crate::proxy::ProxyState;
 
 #[derive(Debug, Deserialize)]
 pub struct SseQuery {
     pub agent: Option<String>,
+    /// Only agents given this tag
+    pub tag: Option<String>,
+    /// Id the viewer addresses its cursor with; generated if absent
+    pub viewer_id: Option<String>,
+    /// Name shown to other viewers of the agent
+    pub viewer: Option<String>,
+    /// Replay stored events after this seq before the live ones
+    pub since_seq: Option<i64>,
+    /// Fold long tool results; `GET /api/events/:id/tool_results/:tool_use_id`
+    /// serves one whole
+    #[serde(default)]
+    pub fold: bool,
 }
 
-#[derive(Debug, Clone, serde::Serialize)]
+/// Stored events read per query while replaying
+const REPLAY_PAGE: i64 = 500;
+/// How long an agent's tags are trusted before they're looked up again
+const TAG_RECHECK: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone, serde::Serialize, schemars::JsonSchema)]
 #[serde(tag = "type", content = "payload", rename_all = "snake_case")]
 pub enum SSeMessageEnvelope {
     ObservabilityEvent {
-        event: ObservabilityEvent,
+        event: Box<ViewEvent>,
     },
 
+    /// Events were missed and couldn't be replayed; reconnect with
+    /// `since_seq=latest_seq` or reload
     ResyncRequired {
         events_dropped: u64,
+        /// The last seq this stream delivered
         latest_seq: u64,
     },
+
+    /// Content a streaming response added since the last of these for it
+    ResponseProgress {
+        progress: ResponseProgress,
+    },
+
+    /// Everyone currently watching the followed agent
+    Presence {
+        agent: String,
+        viewers: Vec<Viewer>,
+    },
 }
 
 impl From<ObservabilityEvent> for SSeMessageEnvelope {
     fn from(event: ObservabilityEvent) -> Self {
-        SSeMessageEnvelope::ObservabilityEvent { event }
+        SSeMessageEnvelope::ObservabilityEvent {
+            event: Box::new(ViewEvent::new(event, false)),
+        }
+    }
+}
+
+/// Which events a stream carries
+struct Filter {
+    agent: Option<String>,
+    tag: Option<String>,
+    /// Whether each agent seen has the tag, and when that was looked up
+    tagged: HashMap<String, (bool, std::time::Instant)>,
+}
+
+impl Filter {
+    async fn matches(&mut self, state: &ProxyState, event: &ObservabilityEvent) -> bool {
+        if self
+            .agent
+            .as_deref()
+            .is_some_and(|agent| event.agent.as_deref() != Some(agent))
+        {
+            return false;
+        }
+        let (Some(tag), Some(agent)) = (&self.tag, &event.agent) else {
+            return self.tag.is_none();
+        };
+        if let Some((tagged, at)) = self.tagged.get(agent) {
+            if at.elapsed() < TAG_RECHECK {
+                return *tagged;
+            }
+        }
+        let tagged = state
+            .agent_store
+            .has_tag(agent, tag)
+            .await
+            .unwrap_or_else(|e| {
+                tracing::warn!("Failed to look up tags of {}: {}", agent, e);
+                false
+            });
+        self.tagged
+            .insert(agent.clone(), (tagged, std::time::Instant::now()));
+        tagged
     }
 }
 
 pub async fn sse_handler(
     State(state): State<Arc<ProxyState>>,
     Query(query): Query<SseQuery>,
+    headers: HeaderMap,
 ) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+    let resume = query.since_seq.or_else(|| {
+        headers
+            .get("last-event-id")?
+            .to_str()
+            .ok()?
+            .trim()
+            .parse()
+            .ok()
+    });
+    // Subscribe before reading storage, so nothing falls between the two
     let mut event_receiver = state.event_broadcaster.subscribe();
+    let mut presence_changes = state.presence.subscribe();
+    let mut delta_receiver = state.deltas.subscribe();
     let agent_filter = query.agent;
+    let fold = query.fold;
+    let mut filter = Filter {
+        agent: agent_filter.clone(),
+        tag: query.tag,
+        tagged: HashMap::new(),
+    };
+    // Following one agent makes this stream one of its viewers
+    let viewer = agent_filter.as_deref().map(|agent| {
+        state
+            .presence
+            .join(agent, query.viewer_id.as_deref(), query.viewer.as_deref())
+    });
 
     let stream = async_stream::stream! {
+        let _viewer = viewer;
+        if let Some(ref agent) = agent_filter {
+            yield Ok(message(&presence_message(&state, agent)));
+        }
+        // The last seq sent, where a replay after falling behind starts
+        let mut delivered = match resume {
+            Some(seq) => seq,
+            None => state.storage.latest_seq().await.unwrap_or_else(|e| {
+                tracing::warn!("Failed to read the latest seq: {}", e);
+                0
+            }),
+        };
+        let mut replay_from = resume;
+        let mut dropped = 0;
+        // Live events at or below this were already replayed
+        let mut replayed_to = 0;
         loop {
-            match event_receiver.recv().await {
-                Ok(event) => {
-                    if let Some(ref filter) = agent_filter {
-                        if event.agent.as_deref() != Some(filter.as_str()) {
+            if let Some(mut after) = replay_from.take() {
+                loop {
+                    let page = match state.storage.get_events_after_seq(after, REPLAY_PAGE).await {
+                        Ok(page) => page,
+                        Err(e) => {
+                            tracing::warn!("Failed to replay events after seq {}: {}", after, e);
+                            let msg = SSeMessageEnvelope::ResyncRequired {
+                                events_dropped: dropped,
+                                latest_seq: after.max(0) as u64,
+                            };
+                            yield Ok(message(&msg));
+                            break;
+                        }
+                    };
+                    let last_page = (page.len() as i64) < REPLAY_PAGE;
+                    for event in page {
+                        after = after.max(event.seq.unwrap_or(after));
+                        if filter.matches(&state, &event).await {
+                            let event = Box::new(ViewEvent::new(event, fold));
+                            yield Ok(message(&SSeMessageEnvelope::ObservabilityEvent { event }));
+                        }
+                    }
+                    if last_page {
+                        break;
+                    }
+                }
+                replayed_to = after;
+                delivered = delivered.max(after);
+                dropped = 0;
+            }
+            let next = tokio::select! {
+                received = event_receiver.recv() => Next::Event(received.map(Box::new)),
+                changed = presence_changes.recv(), if agent_filter.is_some() => Next::Presence(changed),
+                delta = delta_receiver.recv() => Next::Delta(delta.map(Box::new)),
+            };
+            match next {
+                Next::Event(Ok(event)) => {
+                    if let Some(seq) = event.seq {
+                        if seq <= replayed_to {
                             continue;
                         }
+                        delivered = delivered.max(seq);
+                    }
+                    if !filter.matches(&state, &event).await {
+                        continue;
                     }
-                    let msg = SSeMessageEnvelope::from(event);
-                    let json = serde_json::to_string(&msg).unwrap_or_default();
-                    yield Ok(Event::default()
-                        .event("message")
-                        .data(json));
+                    let event = Box::new(ViewEvent::new(*event, fold));
+                    yield Ok(message(&SSeMessageEnvelope::ObservabilityEvent { event }));
                 },
-                Err(RecvError::Lagged(n)) => {
-                    let msg = SSeMessageEnvelope::ResyncRequired{ events_dropped: n, latest_seq: 0 };
-                    let json = serde_json::to_string(&msg).unwrap_or_default();
-                    yield Ok(Event::default()
-                        .event("message")
-                        .data(json));
+                // Only stored events are broadcast, so all it missed can be
+                // replayed
+                Next::Event(Err(RecvError::Lagged(n))) => {
+                    dropped = n;
+                    replay_from = Some(delivered);
                     continue;
                 },
-                Err(RecvError::Closed) => {break},
+                Next::Event(Err(RecvError::Closed)) => {break},
+                // Sent apart from recorded events, so clients following
+                // those don't see each piece of a response go by
+                Next::Delta(Ok(event)) => {
+                    let Some(progress) = ResponseProgress::of(&event) else { continue };
+                    if !filter.matches(&state, &event).await {
+                        continue;
+                    }
+                    yield Ok(message(&SSeMessageEnvelope::ResponseProgress { progress }));
+                },
+                // Progress is best effort; the recorded response carries it all
+                Next::Delta(Err(RecvError::Lagged(_))) => continue,
+                Next::Delta(Err(RecvError::Closed)) => break,
+                Next::Presence(changed) => {
+                    let Some(ref agent) = agent_filter else { continue };
+                    // After a lag we can't tell what changed, so resend anyway
+                    if matches!(changed, Ok(ref changed) if changed != agent) {
+                        continue;
+                    }
+                    yield Ok(message(&presence_message(&state, agent)));
+                },
             }
         }
     };
@@ -76,3 +256,121 @@ pub async fn sse_handler(
             .text("keep-alive"),
     )
 }
+
+enum Next {
+    Event(Result<Box<ObservabilityEvent>, RecvError>),
+    Presence(Result<String, RecvError>),
+    Delta(Result<Box<ObservabilityEvent>, RecvError>),
+}
+
+fn presence_message(state: &ProxyState, agent: &str) -> SSeMessageEnvelope {
+    SSeMessageEnvelope::Presence {
+        agent: agent.to_string(),
+        viewers: state.presence.viewers(agent),
+    }
+}
+
+fn message(envelope: &SSeMessageEnvelope) -> Event {
+    let json = serde_json::to_string(envelope).unwrap_or_default();
+    let event = Event::default().event("message").data(json);
+    // Progress and presence carry no id, which leaves the client's
+    // Last-Event-ID where it was
+    match envelope {
+        SSeMessageEnvelope::ObservabilityEvent { event: stored } => match stored.event.seq {
+            Some(seq) => event.id(seq.to_string()),
+            None => event,
+        },
+        _ => event,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event::{Payload, UserMessage};
+    use axum::body::BodyDataStream;
+    use axum::response::IntoResponse;
+    use futures::StreamExt;
+
+    async fn publish(state: &ProxyState, text: &str) {
+        state
+            .publish(ObservabilityEvent {
+                seq: None,
+                id: uuid::Uuid::new_v4(),
+                timestamp: chrono::Utc::now(),
+                session_id: Some("s1".to_string()),
+                agent: Some("calm-otter".to_string()),
+                topic: None,
+                provider: Some("anthropic".to_string()),
+                payload: Payload::UserMessage(UserMessage {
+                    text: text.to_string(),
+                    ..Default::default()
+                }),
+            })
+            .await;
+    }
+
+    async fn stream(
+        state: &Arc<ProxyState>,
+        since_seq: Option<i64>,
+        headers: HeaderMap,
+    ) -> BodyDataStream {
+        let query = SseQuery {
+            agent: None,
+            tag: None,
+            viewer_id: None,
+            viewer: None,
+            since_seq,
+            fold: false,
+        };
+        let sse = sse_handler(State(state.clone()), Query(query), headers).await;
+        sse.into_response().into_body().into_data_stream()
+    }
+
+    /// The ids of the events a stream sends, read until it has sent `last`
+    async fn ids_until(body: &mut BodyDataStream, last: i64) -> Vec<i64> {
+        let mut ids = Vec::new();
+        let mut pending = String::new();
+        while ids.last() != Some(&last) {
+            let chunk = tokio::time::timeout(Duration::from_secs(5), body.next())
+                .await
+                .expect("stream stalled")
+                .expect("stream ended")
+                .unwrap();
+            pending.push_str(std::str::from_utf8(&chunk).unwrap());
+            while let Some(end) = pending.find("\n\n") {
+                let frame: String = pending.drain(..end + 2).collect();
+                ids.extend(
+                    frame
+                        .lines()
+                        .filter_map(|line| line.strip_prefix("id: ")?.parse::<i64>().ok()),
+                );
+            }
+        }
+        ids
+    }
+
+    #[tokio::test]
+    async fn test_stream_replays_what_was_missed_and_what_it_fell_behind_on() {
+        let state = ProxyState::for_tests().await;
+        for text in ["one", "two", "three"] {
+            publish(&state, text).await;
+        }
+
+        let mut body = stream(&state, Some(1), HeaderMap::new()).await;
+        assert_eq!(ids_until(&mut body, 3).await, vec![2, 3]);
+
+        // More than the broadcast holds goes out before the stream reads any
+        for n in 0..150 {
+            publish(&state, &format!("event {}", n)).await;
+        }
+        let ids = ids_until(&mut body, 153).await;
+        assert_eq!(ids, (4..=153).collect::<Vec<_>>());
+
+        // A reconnecting browser names the last id it got instead
+        let mut headers = HeaderMap::new();
+        headers.insert("last-event-id", "151".parse().unwrap());
+        let mut body = stream(&state, None, headers).await;
+        assert_eq!(ids_until(&mut body, 153).await, vec![152, 153]);
+    }
+}
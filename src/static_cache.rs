@@ -0,0 +1,211 @@
+//! Cached static upstream responses (`--static-cache-ttl`).
+//!
+//! Model lists (`GET /v1/models`, `/v1/models/<id>`, and Gemini's
+//! `/v1beta/models`) change rarely but are fetched by every agent on startup.
+//! Successful responses are kept in memory per URL, credential, and API
+//! version, and served from there until the TTL runs out, without a round
+//! trip upstream. When the upstream is unreachable or failing, the last copy
+//! is served however old it is, marked stale. Responses say which they are in
+//! `x-sentinel-cache` (`hit`, `miss`, or `stale`) and how old they are in
+//! `age`. Requests go upstream without `accept-encoding`, so every client can
+//! read the one copy, and at most `MAX_ENTRIES` responses are kept.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use axum::body::Body;
+use axum::http::{HeaderMap, Method, Response, StatusCode};
+use bytes::Bytes;
+use ring::digest::{Context, SHA256};
+
+/// Paths whose GET responses are cached, with anything beneath them
+const STATIC_PATHS: &[&str] = &["/v1/models", "/v1beta/models"];
+/// Request headers that change what the upstream answers
+const VARY: &[&str] = &["x-api-key", "authorization", "anthropic-version", "anthropic-beta"];
+/// Response header saying where a cached response came from
+pub const CACHE_HEADER: &str = "x-sentinel-cache";
+/// Responses kept; the oldest is let go to make room for another
+const MAX_ENTRIES: usize = 256;
+/// Upstream headers a cached response's own replace
+const REPLACED: &[&str] = &["age", "warning", CACHE_HEADER];
+
+/// How a response was served
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Served {
+    /// From the cache, within the TTL
+    Hit,
+    /// Fetched upstream, and cached
+    Miss,
+    /// From the cache past its TTL, because the upstream failed
+    Stale,
+}
+
+impl Served {
+    fn as_str(self) -> &'static str {
+        match self {
+            Served::Hit => "hit",
+            Served::Miss => "miss",
+            Served::Stale => "stale",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Cached {
+    /// The upstream's response headers
+    headers: HeaderMap,
+    body: Bytes,
+    fetched: Instant,
+}
+
+impl Cached {
+    pub fn new(headers: HeaderMap, body: Bytes) -> Self {
+        Self {
+            headers,
+            body,
+            fetched: Instant::now(),
+        }
+    }
+
+    /// The response to send the client
+    pub fn response(&self, served: Served) -> Response<Body> {
+        let mut builder = Response::builder().status(StatusCode::OK);
+        for (name, value) in self.headers.iter() {
+            if !REPLACED.contains(&name.as_str()) {
+                builder = builder.header(name, value);
+            }
+        }
+        builder = builder
+            .header(CACHE_HEADER, served.as_str())
+            .header("age", self.fetched.elapsed().as_secs());
+        if served == Served::Stale {
+            builder = builder.header("warning", "110 - \"Response is Stale\"");
+        }
+        builder
+            .body(Body::from(self.body.clone()))
+            .expect("cached response headers are valid")
+    }
+}
+
+/// Static responses by request, for as long as the proxy runs
+#[derive(Debug)]
+pub struct StaticCache {
+    ttl: Duration,
+    entries: Mutex<HashMap<String, Cached>>,
+}
+
+impl StaticCache {
+    /// A cache keeping responses fresh for `ttl`; off when it's zero
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// The cache key of a request, if its response is cached
+    pub fn key(&self, method: &Method, path: &str, url: &str, headers: &HeaderMap) -> Option<String> {
+        let path = path.trim_end_matches('/');
+        let cacheable = !self.ttl.is_zero()
+            && method == Method::GET
+            && STATIC_PATHS.iter().any(|p| {
+                path.strip_prefix(p)
+                    .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+            });
+        if !cacheable {
+            return None;
+        }
+        // Hashed, so credentials aren't held in the clear
+        let mut context = Context::new(&SHA256);
+        context.update(url.as_bytes());
+        for name in VARY {
+            context.update(b"\n");
+            if let Some(value) = headers.get(*name) {
+                context.update(value.as_bytes());
+            }
+        }
+        Some(
+            context
+                .finish()
+                .as_ref()
+                .iter()
+                .map(|b| format!("{:02x}", b))
+                .collect(),
+        )
+    }
+
+    /// The cached response, and whether it's still within the TTL
+    pub fn get(&self, key: &str) -> Option<(Cached, bool)> {
+        let entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        let cached = entries.get(key)?;
+        Some((cached.clone(), cached.fetched.elapsed() < self.ttl))
+    }
+
+    pub fn put(&self, key: String, cached: Cached) {
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        if entries.len() >= MAX_ENTRIES && !entries.contains_key(&key) {
+            let oldest = entries
+                .iter()
+                .min_by_key(|(_, cached)| cached.fetched)
+                .map(|(key, _)| key.clone());
+            if let Some(oldest) = oldest {
+                entries.remove(&oldest);
+            }
+        }
+        entries.insert(key, cached);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+
+    #[test]
+    fn test_model_lists_are_cached_per_credential_until_the_ttl() {
+        let cache = StaticCache::new(Duration::from_millis(50));
+        let url = "https://api.anthropic.com/v1/models?limit=100";
+        let mut headers = HeaderMap::new();
+        headers.insert("x-api-key", HeaderValue::from_static("sk-team-a"));
+        let key = |path: &str, headers: &HeaderMap| cache.key(&Method::GET, path, url, headers);
+
+        assert!(key("/v1/models", &headers).is_some());
+        assert!(key("/v1/models/claude-sonnet-4/", &headers).is_some());
+        assert!(key("/v1beta/models", &headers).is_some());
+        assert!(key("/v1/messages", &headers).is_none());
+        assert!(key("/v1/models_extra", &headers).is_none());
+        assert!(cache
+            .key(&Method::POST, "/v1/models", url, &headers)
+            .is_none());
+        assert!(StaticCache::new(Duration::ZERO)
+            .key(&Method::GET, "/v1/models", url, &headers)
+            .is_none());
+        let mut other = headers.clone();
+        other.insert("x-api-key", HeaderValue::from_static("sk-team-b"));
+        assert_ne!(key("/v1/models", &headers), key("/v1/models", &other));
+
+        let key = key("/v1/models", &headers).unwrap();
+        assert!(cache.get(&key).is_none());
+        let mut upstream = HeaderMap::new();
+        upstream.insert("request-id", HeaderValue::from_static("req_01"));
+        upstream.insert("age", HeaderValue::from_static("9999"));
+        cache.put(key.clone(), Cached::new(upstream, Bytes::from_static(b"{\"data\":[]}")));
+        assert!(cache.get(&key).unwrap().1);
+        std::thread::sleep(Duration::from_millis(60));
+        let (stale, fresh) = cache.get(&key).unwrap();
+        assert!(!fresh);
+        let response = stale.response(Served::Stale);
+        assert_eq!(response.headers()[CACHE_HEADER], "stale");
+        assert!(response.headers().contains_key("warning"));
+        assert_eq!(response.headers()["request-id"], "req_01");
+        assert_eq!(response.headers().get_all("age").iter().count(), 1);
+
+        // Full, the oldest response makes room
+        for n in 0..MAX_ENTRIES {
+            cache.put(n.to_string(), Cached::new(HeaderMap::new(), Bytes::new()));
+        }
+        assert!(cache.get(&key).is_none());
+        assert_eq!(cache.entries.lock().unwrap().len(), MAX_ENTRIES);
+    }
+}
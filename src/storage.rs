@@ -1,11 +1,123 @@
+use std::sync::Arc;
+
 use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
 use sqlx::{sqlite::SqlitePoolOptions, SqlitePool};
+use uuid::Uuid;
+
+use crate::bodies;
+use crate::derived::Derived;
+use crate::event::{ObservabilityEvent, Payload};
+use crate::shards::{self, Shards};
+
+/// The events table, in `sentinel.db` and in each monthly shard
+pub const EVENTS_TABLE: &str = r#"
+    CREATE TABLE IF NOT EXISTS observability_events (
+        seq INTEGER PRIMARY KEY AUTOINCREMENT,
+        id TEXT UNIQUE NOT NULL,
+        timestamp TEXT NOT NULL,
+        session_id TEXT,
+        agent TEXT,
+        topic TEXT,
+        provider TEXT,
+        payload TEXT NOT NULL,
+        raw TEXT,
+        raw_bytes INTEGER,
+        raw_truncated INTEGER
+    )
+"#;
+
+/// Default cap on the raw upstream body kept per event
+pub const DEFAULT_MAX_RAW_BYTES: usize = 64 * 1024;
+
+/// How much of the raw upstream body to keep alongside parsed fields.
+/// Parsed fields are always stored in full; only `raw` is capped.
+#[derive(Debug, Clone, Copy)]
+pub struct CapturePolicy {
+    /// `None` keeps everything (full fidelity)
+    pub max_raw_bytes: Option<usize>,
+}
+
+impl CapturePolicy {
+    pub fn capture(&self, raw: &[u8]) -> RawCapture {
+        self.capture_start(raw, raw.len())
+    }
+
+    /// Capture a body of `original_bytes` of which only the start was kept
+    pub fn capture_start(&self, raw: &[u8], original_bytes: usize) -> RawCapture {
+        let text = String::from_utf8_lossy(raw);
+        let Some(max) = self.max_raw_bytes.filter(|max| text.len() > *max) else {
+            return RawCapture {
+                body: text.into_owned(),
+                original_bytes,
+                truncated: false,
+            };
+        };
 
-use crate::event::ObservabilityEvent;
+        // Cut on a char boundary at or below the cap
+        let cut = text
+            .char_indices()
+            .map(|(i, _)| i)
+            .take_while(|i| *i <= max)
+            .last()
+            .unwrap_or(0);
+        let mut body = text[..cut].to_string();
+        body.push_str(&format!(
+            "\n[sentinel: truncated, kept {} of {} bytes; run with --full-fidelity to keep everything]",
+            cut, original_bytes
+        ));
+        RawCapture {
+            body,
+            original_bytes,
+            truncated: true,
+        }
+    }
+}
+
+/// Raw upstream body as stored
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct RawCapture {
+    #[sqlx(rename = "raw")]
+    pub body: String,
+    #[sqlx(rename = "raw_bytes", try_from = "i64")]
+    pub original_bytes: usize,
+    #[sqlx(rename = "raw_truncated")]
+    pub truncated: bool,
+}
+
+impl RawCapture {
+    /// A body of `original_bytes` that wasn't kept
+    pub fn skipped(original_bytes: usize) -> Self {
+        Self {
+            body: String::new(),
+            original_bytes,
+            truncated: true,
+        }
+    }
+}
+
+/// Which events to read; unset fields match everything
+#[derive(Debug, Default)]
+pub struct EventFilter {
+    pub agent: Option<String>,
+    pub session_id: Option<String>,
+    /// The payload's `type` tag, e.g. "assistant_response"
+    pub payload_type: Option<String>,
+    pub since: Option<DateTime<Utc>>,
+    /// Exclusive
+    pub until: Option<DateTime<Utc>>,
+    /// Only agents given this tag
+    pub tag: Option<String>,
+    /// Exchanges sent with this x-sentinel-project
+    pub project: Option<String>,
+    /// Exchanges sent with this x-sentinel-run-id
+    pub run_id: Option<String>,
+}
 
 #[derive(Clone)]
 pub struct Storage {
     pool: SqlitePool,
+    shards: Arc<Shards>,
 }
 
 impl Storage {
@@ -14,36 +126,62 @@ impl Storage {
         self.pool.clone()
     }
 
+    /// Check the database answers, for health probes
+    pub async fn ping(&self) -> Result<(), sqlx::Error> {
+        sqlx::query("SELECT 1").execute(&self.pool).await?;
+        Ok(())
+    }
+
     pub async fn new(db_path: &std::path::Path) -> Result<Self, sqlx::Error> {
         let db_url = format!("sqlite:{}?mode=rwc", db_path.display());
 
+        let dir = db_path.parent().unwrap_or(std::path::Path::new("."));
+        let shards = Arc::new(Shards::new(dir));
+        let on_connect = shards.clone();
+        let on_acquire = shards.clone();
+        // Shards are attached per connection; re-checked on every acquire so
+        // a month added by this or another process is picked up
         let pool = SqlitePoolOptions::new()
             .max_connections(5)
+            .after_connect(move |conn, _| {
+                let shards = on_connect.clone();
+                Box::pin(async move { shards.attach(conn).await })
+            })
+            .before_acquire(move |conn, _| {
+                let shards = on_acquire.clone();
+                Box::pin(async move { shards.attach(conn).await.map(|_| true) })
+            })
             .connect(&db_url)
             .await?;
 
-        let storage = Self { pool };
+        let storage = Self { pool, shards };
         storage.init_schema().await?;
+        if shards::catalog_exists(&storage.pool).await? {
+            storage.shards.set_enabled();
+            let unqueried = storage.shards.unqueried(&storage.pool).await?;
+            if let Some(newest) = unqueried.first() {
+                eprintln!(
+                    "Events from {} and earlier aren't queried: only the latest {} monthly \
+                     shards can be attached. Archive the older files (see 'sentinel shards').",
+                    newest,
+                    shards::MAX_ATTACHED
+                );
+            }
+        }
 
         Ok(storage)
     }
 
+    /// Record new events into monthly shard files from now on; events
+    /// already recorded stay in `sentinel.db`
+    pub async fn enable_monthly_shards(&self) -> Result<(), sqlx::Error> {
+        shards::init_catalog(&self.pool).await?;
+        self.shards.set_enabled();
+        Ok(())
+    }
+
     async fn init_schema(&self) -> Result<(), sqlx::Error> {
-        sqlx::query(
-            r#"
-            CREATE TABLE IF NOT EXISTS observability_events (
-                seq INTEGER PRIMARY KEY AUTOINCREMENT,
-                id TEXT UNIQUE NOT NULL,
-                timestamp TEXT NOT NULL,
-                session_id TEXT,
-                agent TEXT,
-                topic TEXT,
-                payload TEXT NOT NULL
-            )
-            "#,
-        )
-        .execute(&self.pool)
-        .await?;
+        sqlx::query(EVENTS_TABLE).execute(&self.pool).await?;
 
         sqlx::query(
             r#"
@@ -53,38 +191,102 @@ impl Storage {
         .execute(&self.pool)
         .await?;
 
-        // Migration: add topic column if missing (existing databases)
-        sqlx::query(
-            r#"ALTER TABLE observability_events ADD COLUMN topic TEXT"#,
-        )
-        .execute(&self.pool)
-        .await
-        .ok();
+        // Migrations for databases created before these columns existed
+        add_column_if_missing(&self.pool, "observability_events", "topic", "TEXT").await?;
+        add_column_if_missing(&self.pool, "observability_events", "provider", "TEXT").await?;
+        add_column_if_missing(&self.pool, "observability_events", "raw", "TEXT").await?;
+        add_column_if_missing(&self.pool, "observability_events", "raw_bytes", "INTEGER").await?;
+        add_column_if_missing(&self.pool, "observability_events", "raw_truncated", "INTEGER").await?;
+
+        bodies::init_schema(&self.pool).await?;
+
+        let mut backfill = Vec::new();
+        for table in Derived::ALL {
+            if table.init_schema(&self.pool).await? {
+                backfill.push(table);
+            }
+        }
+        if !backfill.is_empty() {
+            self.backfill(&backfill).await?;
+        }
 
         Ok(())
     }
 
+    /// Fill tables derived from events that were recorded before they existed
+    async fn backfill(&self, tables: &[Derived]) -> Result<(), sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+        let mut last_seq = 0;
+        loop {
+            let events = self.get_events_after_seq(last_seq, 10_000).await?;
+            let Some(seq) = events.last().and_then(|e| e.seq) else {
+                break;
+            };
+            for event in &events {
+                for table in tables {
+                    table.record(&mut tx, event).await?;
+                }
+            }
+            last_seq = seq;
+        }
+        tx.commit().await
+    }
+
     pub async fn insert_observability_event(
         &self,
         event: &ObservabilityEvent,
+    ) -> Result<i64, sqlx::Error> {
+        self.insert_observability_event_with_raw(event, None).await
+    }
+
+    /// Insert an event along with the raw upstream body it was parsed from
+    pub async fn insert_observability_event_with_raw(
+        &self,
+        event: &ObservabilityEvent,
+        raw: Option<&RawCapture>,
     ) -> Result<i64, sqlx::Error> {
         let payload_json =
             serde_json::to_string(&event.payload).unwrap_or_else(|_| "{}".to_string());
 
-        let result = sqlx::query(
+        // The view over shards isn't insertable, so name the month's table
+        let table = if self.shards.enabled() {
+            format!("{}.observability_events", self.shards.current_schema(&self.pool).await?)
+        } else {
+            "observability_events".to_string()
+        };
+
+        let mut tx = self.pool.begin().await?;
+        // Prompts repeat their conversation, so their bodies share its parts
+        let body = match raw {
+            Some(raw) if !raw.truncated && matches!(event.payload, Payload::UserMessage(_)) => {
+                Some(bodies::store(&mut tx, &raw.body).await?)
+            }
+            _ => raw.map(|r| r.body.clone()),
+        };
+        let result = sqlx::query(&format!(
             r#"
-            INSERT INTO observability_events (id, timestamp, session_id, agent, topic, payload)
-            VALUES (?, ?, ?, ?, ?, ?)
+            INSERT INTO {}
+                (id, timestamp, session_id, agent, topic, provider, payload, raw, raw_bytes, raw_truncated)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
             "#,
-        )
+            table
+        ))
         .bind(event.id.to_string())
         .bind(event.timestamp.to_rfc3339())
         .bind(event.session_id.as_ref())
         .bind(event.agent.as_ref())
         .bind(event.topic.as_ref())
+        .bind(event.provider.as_ref())
         .bind(payload_json)
-        .execute(&self.pool)
+        .bind(body)
+        .bind(raw.map(|r| r.original_bytes as i64))
+        .bind(raw.map(|r| r.truncated))
+        .execute(&mut *tx)
         .await?;
+        for table in Derived::ALL {
+            table.record(&mut tx, event).await?;
+        }
+        tx.commit().await?;
 
         Ok(result.last_insert_rowid())
     }
@@ -93,37 +295,383 @@ impl Storage {
         &self,
         limit: i64,
     ) -> Result<Vec<ObservabilityEvent>, sqlx::Error> {
-        let rows: Vec<(i64, String, String, Option<String>, Option<String>, Option<String>, String)> =
-            sqlx::query_as(
+        let rows: Vec<EventRow> = sqlx::query_as(
+            r#"
+            SELECT seq, id, timestamp, session_id, agent, topic, provider, payload
+            FROM observability_events
+            ORDER BY seq DESC
+            LIMIT ?
+            "#,
+        )
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows_to_events(rows))
+    }
+
+    /// Most recent events whose payload has the given `type` tag
+    /// The payload JSON of the latest events of a type, as stored: fields
+    /// no longer read, or of a shape that no longer parses, are kept
+    pub async fn get_recent_payloads_of_type(
+        &self,
+        payload_type: &str,
+        limit: i64,
+    ) -> Result<Vec<String>, sqlx::Error> {
+        sqlx::query_scalar(
+            r#"
+            SELECT payload
+            FROM observability_events
+            WHERE json_extract(payload, '$.type') = ?
+            ORDER BY seq DESC
+            LIMIT ?
+            "#,
+        )
+        .bind(payload_type)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await
+    }
+
+    pub async fn get_raw_capture(&self, event_id: &str) -> Result<Option<RawCapture>, sqlx::Error> {
+        let raw: Option<RawCapture> = sqlx::query_as(
+            r#"
+            SELECT raw, raw_bytes, raw_truncated
+            FROM observability_events
+            WHERE id = ? AND raw IS NOT NULL
+            "#,
+        )
+        .bind(event_id)
+        .fetch_optional(&self.pool)
+        .await?;
+        let Some(mut raw) = raw else {
+            return Ok(None);
+        };
+        raw.body = bodies::join(&self.pool, raw.body).await?;
+        Ok(Some(raw))
+    }
+
+    /// Delete events recorded before `before`, in every attached shard, with
+    /// what was derived from them; usage rollups are kept. The number of
+    /// events deleted.
+    pub async fn prune(&self, before: DateTime<Utc>) -> Result<u64, sqlx::Error> {
+        let before = before.to_rfc3339();
+        let mut conn = self.pool.acquire().await?;
+        let schemas: Vec<(i64, String, Option<String>)> = sqlx::query_as("PRAGMA database_list")
+            .fetch_all(&mut *conn)
+            .await?;
+        let mut deleted = 0;
+        for (_, schema, _) in schemas.iter().filter(|(_, name, _)| name != "temp") {
+            let result = sqlx::query(&format!(
+                "DELETE FROM \"{}\".observability_events WHERE timestamp < ?",
+                schema
+            ))
+            .bind(&before)
+            .execute(&mut *conn)
+            .await?;
+            deleted += result.rows_affected();
+        }
+        // Commands and file changes go with their calls, which know when
+        // they were made
+        for table in ["commands", "file_changes"] {
+            sqlx::query(&format!(
                 r#"
-                SELECT seq, id, timestamp, session_id, agent, topic, payload
-                FROM observability_events
-                ORDER BY seq DESC
-                LIMIT ?
+                DELETE FROM main.{} WHERE event_id IN
+                    (SELECT event_id FROM main.tool_calls WHERE timestamp < ?)
                 "#,
-            )
-            .bind(limit)
-            .fetch_all(&self.pool)
+                table
+            ))
+            .bind(&before)
+            .execute(&mut *conn)
             .await?;
+        }
+        for table in ["tool_calls", "code_blocks", "run_responses"] {
+            sqlx::query(&format!("DELETE FROM main.{} WHERE timestamp < ?", table))
+                .bind(&before)
+                .execute(&mut *conn)
+                .await?;
+        }
+        bodies::prune(&mut conn, &before).await?;
+        Ok(deleted)
+    }
 
-        let events = rows
-            .into_iter()
-            .filter_map(|(seq, id, timestamp, session_id, agent, topic, payload)| {
-                Some(ObservabilityEvent {
-                    seq: Some(seq),
-                    id: id.parse().ok()?,
-                    timestamp: DateTime::parse_from_rfc3339(&timestamp)
-                        .ok()?
-                        .with_timezone(&Utc),
-                    session_id,
-                    agent,
-                    topic,
-                    payload: serde_json::from_str(&payload).ok()?,
-                })
-            })
-            .collect();
+    pub async fn count_events(&self) -> Result<i64, sqlx::Error> {
+        sqlx::query_scalar("SELECT COUNT(*) FROM observability_events")
+            .fetch_one(&self.pool)
+            .await
+    }
 
-        Ok(events)
+    /// Forward failures since `since`, grouped by classified cause
+    pub async fn forward_failure_counts(
+        &self,
+        since: DateTime<Utc>,
+    ) -> Result<Vec<FailureCauseCount>, sqlx::Error> {
+        sqlx::query_as(
+            r#"
+            SELECT json_extract(payload, '$.cause') AS cause,
+                   COUNT(*) AS count,
+                   MAX(timestamp) AS last_seen
+            FROM observability_events
+            WHERE json_extract(payload, '$.type') = 'error'
+              AND json_extract(payload, '$.kind') = 'forward'
+              AND timestamp >= ?
+            GROUP BY cause
+            ORDER BY count DESC
+            "#,
+        )
+        .bind(since.to_rfc3339())
+        .fetch_all(&self.pool)
+        .await
+    }
+
+    /// Model and usage of every response since `since`, oldest first
+    pub async fn response_usage(
+        &self,
+        since: DateTime<Utc>,
+    ) -> Result<Vec<ResponseUsage>, sqlx::Error> {
+        sqlx::query_as(
+            r#"
+            SELECT session_id,
+                   agent,
+                   json_extract(payload, '$.model') AS model,
+                   json_extract(payload, '$.usage') AS usage
+            FROM observability_events
+            WHERE json_extract(payload, '$.type') = 'assistant_response'
+              AND timestamp >= ?
+            ORDER BY seq ASC
+            "#,
+        )
+        .bind(since.to_rfc3339())
+        .fetch_all(&self.pool)
+        .await
+    }
+
+    /// Alerts of one kind since `since`, grouped by message
+    pub async fn alert_counts(
+        &self,
+        kind: &str,
+        since: DateTime<Utc>,
+    ) -> Result<Vec<AlertCount>, sqlx::Error> {
+        sqlx::query_as(
+            r#"
+            SELECT json_extract(payload, '$.message') AS message,
+                   COUNT(*) AS count,
+                   MAX(timestamp) AS last_seen
+            FROM observability_events
+            WHERE json_extract(payload, '$.type') = 'alert'
+              AND json_extract(payload, '$.kind') = ?
+              AND timestamp >= ?
+            GROUP BY message
+            ORDER BY last_seen DESC
+            "#,
+        )
+        .bind(kind)
+        .bind(since.to_rfc3339())
+        .fetch_all(&self.pool)
+        .await
+    }
+
+    pub async fn get_events_since(
+        &self,
+        since: DateTime<Utc>,
+    ) -> Result<Vec<ObservabilityEvent>, sqlx::Error> {
+        let rows: Vec<EventRow> = sqlx::query_as(
+            r#"
+            SELECT seq, id, timestamp, session_id, agent, topic, provider, payload
+            FROM observability_events
+            WHERE timestamp >= ?
+            ORDER BY seq ASC
+            "#,
+        )
+        .bind(since.to_rfc3339())
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows_to_events(rows))
+    }
+
+    /// The seq of the newest event, or 0 when there are none
+    pub async fn latest_seq(&self) -> Result<i64, sqlx::Error> {
+        sqlx::query_scalar("SELECT COALESCE(MAX(seq), 0) FROM observability_events")
+            .fetch_one(&self.pool)
+            .await
+    }
+
+    /// Up to `limit` events recorded after `seq`, oldest first
+    pub async fn get_events_after_seq(
+        &self,
+        seq: i64,
+        limit: i64,
+    ) -> Result<Vec<ObservabilityEvent>, sqlx::Error> {
+        let rows: Vec<EventRow> = sqlx::query_as(
+            r#"
+            SELECT seq, id, timestamp, session_id, agent, topic, provider, payload
+            FROM observability_events
+            WHERE seq > ?
+            ORDER BY seq ASC
+            LIMIT ?
+            "#,
+        )
+        .bind(seq)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows_to_events(rows))
+    }
+
+    /// Up to `limit` events matching `filter` with a seq below `before`,
+    /// newest first
+    pub async fn query_events(
+        &self,
+        filter: &EventFilter,
+        before: Option<i64>,
+        limit: i64,
+    ) -> Result<Vec<ObservabilityEvent>, sqlx::Error> {
+        // Only mentioned when asked for, since the agents table may not exist
+        let tagged = if filter.tag.is_some() {
+            "AND agent IN (SELECT a.name FROM agents a JOIN agent_tags t ON t.agent_id = a.id WHERE t.tag = ?10)"
+        } else {
+            ""
+        };
+        let sql = format!(
+            r#"
+            SELECT seq, id, timestamp, session_id, agent, topic, provider, payload
+            FROM observability_events
+            WHERE (?1 IS NULL OR agent = ?1)
+              AND (?2 IS NULL OR session_id = ?2)
+              AND (?3 IS NULL OR json_extract(payload, '$.type') = ?3)
+              AND (?4 IS NULL OR timestamp >= ?4)
+              AND (?5 IS NULL OR timestamp < ?5)
+              AND (?6 IS NULL OR seq < ?6)
+              AND (?8 IS NULL OR json_extract(payload, '$.project') = ?8)
+              AND (?9 IS NULL OR json_extract(payload, '$.run_id') = ?9)
+              {}
+            ORDER BY seq DESC
+            LIMIT ?7
+            "#,
+            tagged
+        );
+        let mut query = sqlx::query_as(&sql)
+            .bind(&filter.agent)
+            .bind(&filter.session_id)
+            .bind(&filter.payload_type)
+            .bind(filter.since.map(|t| t.to_rfc3339()))
+            .bind(filter.until.map(|t| t.to_rfc3339()))
+            .bind(before)
+            .bind(limit)
+            .bind(&filter.project)
+            .bind(&filter.run_id);
+        if let Some(ref tag) = filter.tag {
+            query = query.bind(tag);
+        }
+        let rows: Vec<EventRow> = query.fetch_all(&self.pool).await?;
+
+        Ok(rows_to_events(rows))
+    }
+
+    pub async fn get_session_events(
+        &self,
+        session_id: &str,
+    ) -> Result<Vec<ObservabilityEvent>, sqlx::Error> {
+        let rows: Vec<EventRow> = sqlx::query_as(
+            r#"
+            SELECT seq, id, timestamp, session_id, agent, topic, provider, payload
+            FROM observability_events
+            WHERE session_id = ?
+            ORDER BY seq ASC
+            "#,
+        )
+        .bind(session_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows_to_events(rows))
+    }
+
+    pub async fn get_event(&self, id: Uuid) -> Result<Option<ObservabilityEvent>, sqlx::Error> {
+        let row: Option<EventRow> = sqlx::query_as(
+            r#"
+            SELECT seq, id, timestamp, session_id, agent, topic, provider, payload
+            FROM observability_events
+            WHERE id = ?
+            "#,
+        )
+        .bind(id.to_string())
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(row.and_then(|row| rows_to_events(vec![row]).pop()))
+    }
+
+    /// Events whose id starts with `prefix`, at most `limit` of them
+    pub async fn find_events_by_id_prefix(
+        &self,
+        prefix: &str,
+        limit: i64,
+    ) -> Result<Vec<ObservabilityEvent>, sqlx::Error> {
+        let prefix = prefix.trim().to_ascii_lowercase();
+        let rows: Vec<EventRow> = sqlx::query_as(
+            r#"
+            SELECT seq, id, timestamp, session_id, agent, topic, provider, payload
+            FROM observability_events
+            WHERE substr(id, 1, length(?1)) = ?1
+            ORDER BY seq ASC
+            LIMIT ?2
+            "#,
+        )
+        .bind(prefix)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows_to_events(rows))
+    }
+
+    /// Most recent messages and responses with a string value containing
+    /// `query`, case-insensitively. Keys and `type` tags aren't matched.
+    pub async fn search_events(
+        &self,
+        query: &str,
+        limit: i64,
+    ) -> Result<Vec<ObservabilityEvent>, sqlx::Error> {
+        let escaped = query
+            .replace('\\', "\\\\")
+            .replace('%', "\\%")
+            .replace('_', "\\_");
+        let rows: Vec<EventRow> = sqlx::query_as(
+            r#"
+            SELECT seq, id, timestamp, session_id, agent, topic, provider, payload
+            FROM observability_events
+            WHERE json_extract(payload, '$.type') IN ('user_message', 'assistant_response')
+              AND EXISTS (
+                  SELECT 1 FROM json_tree(payload)
+                  WHERE json_tree.type = 'text'
+                    AND json_tree.key IS NOT 'type'
+                    AND json_tree.value LIKE ? ESCAPE '\'
+              )
+            ORDER BY seq DESC
+            LIMIT ?
+            "#,
+        )
+        .bind(format!("%{}%", escaped))
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows_to_events(rows))
+    }
+
+    /// Events for a session id, or failing that, every session of an agent
+    pub async fn get_session_or_agent_events(
+        &self,
+        key: &str,
+    ) -> Result<Vec<ObservabilityEvent>, sqlx::Error> {
+        let events = self.get_session_events(key).await?;
+        if !events.is_empty() {
+            return Ok(events);
+        }
+        self.get_agent_events(key, i64::MAX).await
     }
 
     pub async fn get_agent_events(
@@ -131,38 +679,219 @@ impl Storage {
         agent: &str,
         limit: i64,
     ) -> Result<Vec<ObservabilityEvent>, sqlx::Error> {
-        let rows: Vec<(i64, String, String, Option<String>, Option<String>, Option<String>, String)> =
-            sqlx::query_as(
-                r#"
-                SELECT seq, id, timestamp, session_id, agent, topic, payload
-                FROM observability_events
-                WHERE agent = ?
-                ORDER BY seq ASC
-                LIMIT ?
-                "#,
-            )
-            .bind(agent)
-            .bind(limit)
-            .fetch_all(&self.pool)
-            .await?;
+        let rows: Vec<EventRow> = sqlx::query_as(
+            r#"
+            SELECT seq, id, timestamp, session_id, agent, topic, provider, payload
+            FROM observability_events
+            WHERE agent = ?
+            ORDER BY seq ASC
+            LIMIT ?
+            "#,
+        )
+        .bind(agent)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
 
-        let events = rows
-            .into_iter()
-            .filter_map(|(seq, id, timestamp, session_id, agent, topic, payload)| {
-                Some(ObservabilityEvent {
-                    seq: Some(seq),
-                    id: id.parse().ok()?,
-                    timestamp: DateTime::parse_from_rfc3339(&timestamp)
-                        .ok()?
-                        .with_timezone(&Utc),
-                    session_id,
-                    agent,
-                    topic,
-                    payload: serde_json::from_str(&payload).ok()?,
+        Ok(rows_to_events(rows))
+    }
+}
+
+/// One recorded response's model and usage
+#[derive(sqlx::FromRow)]
+pub struct ResponseUsage {
+    pub session_id: Option<String>,
+    pub agent: Option<String>,
+    pub model: Option<String>,
+    /// The payload's `usage` object as JSON, if it had one
+    pub usage: Option<String>,
+}
+
+#[derive(sqlx::FromRow)]
+pub struct FailureCauseCount {
+    pub cause: Option<String>,
+    pub count: i64,
+    pub last_seen: String,
+}
+
+#[derive(sqlx::FromRow)]
+pub struct AlertCount {
+    pub message: String,
+    pub count: i64,
+    pub last_seen: String,
+}
+
+/// Add a column to an existing table, for databases created before it existed
+pub async fn add_column_if_missing(
+    pool: &SqlitePool,
+    table: &str,
+    column: &str,
+    definition: &str,
+) -> Result<(), sqlx::Error> {
+    let columns: Vec<String> = sqlx::query_scalar("SELECT name FROM pragma_table_info(?)")
+        .bind(table)
+        .fetch_all(pool)
+        .await?;
+    if columns.iter().any(|c| c == column) {
+        return Ok(());
+    }
+
+    sqlx::query(&format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, definition))
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+#[derive(sqlx::FromRow)]
+pub(crate) struct EventRow {
+    seq: i64,
+    id: String,
+    timestamp: String,
+    session_id: Option<String>,
+    agent: Option<String>,
+    topic: Option<String>,
+    provider: Option<String>,
+    payload: String,
+}
+
+fn row_to_event(row: EventRow) -> Result<ObservabilityEvent, String> {
+    Ok(ObservabilityEvent {
+        seq: Some(row.seq),
+        id: row.id.parse().map_err(|e| format!("invalid id: {}", e))?,
+        timestamp: DateTime::parse_from_rfc3339(&row.timestamp)
+            .map_err(|e| format!("invalid timestamp: {}", e))?
+            .with_timezone(&Utc),
+        session_id: row.session_id,
+        agent: row.agent,
+        topic: row.topic,
+        provider: row.provider,
+        payload: serde_json::from_str(&row.payload)
+            .map_err(|e| format!("invalid payload: {}", e))?,
+    })
+}
+
+pub(crate) fn rows_to_events(rows: Vec<EventRow>) -> Vec<ObservabilityEvent> {
+    let mut events = Vec::with_capacity(rows.len());
+    for row in rows {
+        let seq = row.seq;
+        match row_to_event(row) {
+            Ok(event) => events.push(event),
+            Err(e) => tracing::warn!("Skipping unparseable event row seq={}: {}", seq, e),
+        }
+    }
+    events
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_capture_truncates_on_char_boundary() {
+        let policy = CapturePolicy {
+            max_raw_bytes: Some(5),
+        };
+        // 'é' is two bytes, straddling the 5-byte cap
+        let capture = policy.capture("abcdé-rest".as_bytes());
+        assert!(capture.truncated);
+        assert_eq!(capture.original_bytes, 11);
+        assert!(capture.body.starts_with("abcd\n[sentinel: truncated, kept 4 of 11"));
+    }
+
+    #[test]
+    fn test_full_fidelity_keeps_everything() {
+        let policy = CapturePolicy {
+            max_raw_bytes: None,
+        };
+        let capture = policy.capture(b"0123456789");
+        assert!(!capture.truncated);
+        assert_eq!(capture.body, "0123456789");
+    }
+
+    #[tokio::test]
+    async fn test_search_treats_wildcards_literally() {
+        use crate::event::{Payload, UserMessage};
+
+        let path = std::env::temp_dir().join(format!("sentinel-test-{}.db", Uuid::new_v4()));
+        let storage = Storage::new(&path).await.unwrap();
+        for text in ["fix the auth bug", "100% done", "1000 done"] {
+            storage
+                .insert_observability_event(&ObservabilityEvent {
+                    seq: None,
+                    id: Uuid::new_v4(),
+                    timestamp: Utc::now(),
+                    session_id: Some("s1".to_string()),
+                    agent: None,
+                    topic: None,
+                    provider: None,
+                    payload: Payload::UserMessage(UserMessage {
+                        text: text.to_string(),
+                        ..Default::default()
+                    }),
                 })
-            })
-            .collect();
+                .await
+                .unwrap();
+        }
+
+        assert_eq!(storage.search_events("AUTH", 10).await.unwrap().len(), 1);
+        assert_eq!(storage.search_events("100%", 10).await.unwrap().len(), 1);
+        // Only values match, not the keys or type tags around them
+        assert!(storage.search_events("text", 10).await.unwrap().is_empty());
+        assert!(storage.search_events("user_message", 10).await.unwrap().is_empty());
+        assert_eq!(storage.get_session_or_agent_events("s1").await.unwrap().len(), 3);
+        // Where a reconnecting event stream picks up
+        assert_eq!(storage.latest_seq().await.unwrap(), 3);
+        assert_eq!(storage.get_events_after_seq(1, 10).await.unwrap().len(), 2);
+
+        // Payloads are sampled as stored, including ones that no longer parse
+        sqlx::query(
+            "INSERT INTO observability_events (id, timestamp, payload)
+             VALUES (?, ?, '{\"type\":\"user_message\",\"text\":7,\"cwd\":\"/src\"}')",
+        )
+        .bind(Uuid::new_v4().to_string())
+        .bind(Utc::now().to_rfc3339())
+        .execute(&storage.pool)
+        .await
+        .unwrap();
+        let payloads = storage.get_recent_payloads_of_type("user_message", 10).await.unwrap();
+        assert_eq!(payloads.len(), 4);
+        assert!(payloads[0].contains("\"cwd\""));
+        assert_eq!(storage.get_session_or_agent_events("s1").await.unwrap().len(), 3);
+
+        drop(storage);
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[tokio::test]
+    async fn test_prune_deletes_events_past_retention() {
+        use crate::event::UserMessage;
+
+        let path = std::env::temp_dir().join(format!("sentinel-test-{}.db", Uuid::new_v4()));
+        let storage = Storage::new(&path).await.unwrap();
+        for days in [120, 1] {
+            storage
+                .insert_observability_event(&ObservabilityEvent {
+                    seq: None,
+                    id: Uuid::new_v4(),
+                    timestamp: Utc::now() - chrono::Duration::days(days),
+                    session_id: Some("s1".to_string()),
+                    agent: None,
+                    topic: None,
+                    provider: None,
+                    payload: Payload::UserMessage(UserMessage {
+                        text: format!("{} days ago", days),
+                        ..Default::default()
+                    }),
+                })
+                .await
+                .unwrap();
+        }
+
+        let deleted = storage.prune(Utc::now() - chrono::Duration::days(90)).await.unwrap();
+        assert_eq!(deleted, 1);
+        assert_eq!(storage.count_events().await.unwrap(), 1);
 
-        Ok(events)
+        drop(storage);
+        let _ = std::fs::remove_file(&path);
     }
 }
@@ -0,0 +1,258 @@
+//! Per-agent text streams (`sentinel start --tee-streams`).
+//!
+//! The text of every streaming response is appended, as it arrives, to
+//! `<data dir>/streams/<agent>.txt` (named for the session when the agent
+//! isn't), for `tail -f` or a tmux pane to follow without an API client.
+//! Each response starts on a line of its own with the time it began. A FIFO
+//! made at that path (`mkfifo ~/.sentinel/streams/swift-fox.txt`) is written
+//! to instead of a file while something reads it; text arriving with no
+//! reader, or faster than the reader keeps up, is dropped rather than
+//! holding anything up. An agent's file is closed once it has gone quiet
+//! for a while, and opened again when its next response starts.
+//!
+//! Text comes from the live deltas dashboards get, so nothing is teed while
+//! `--redact` or `--encrypt-fields` turn those off.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use tokio::io::AsyncWriteExt;
+use tokio::sync::broadcast::{self, error::RecvError};
+use tracing::warn;
+use uuid::Uuid;
+
+use crate::event::{ObservabilityEvent, Payload};
+use crate::parsers::Delta;
+
+/// Longest a FIFO's reader is waited on for each piece of text
+#[cfg(unix)]
+const PIPE_WAIT: std::time::Duration = std::time::Duration::from_millis(50);
+
+/// How long an agent's stream is kept open after its last text
+const CLOSE_AFTER: Duration = Duration::from_secs(10 * 60);
+
+/// Where an agent's text goes
+enum Out {
+    File(tokio::fs::File),
+    #[cfg(unix)]
+    Pipe(tokio::net::unix::pipe::Sender),
+    /// A FIFO nothing was reading when the response started
+    Unread,
+}
+
+struct Sink {
+    out: Out,
+    /// The response being written
+    response: Uuid,
+    /// When text was last written
+    written: Instant,
+}
+
+/// Close the streams of agents that have gone quiet, so one that ran once
+/// doesn't hold its file open for the life of the proxy
+fn close_idle(sinks: &mut HashMap<String, Sink>, now: Instant) {
+    sinks.retain(|_, sink| now.duration_since(sink.written) < CLOSE_AFTER);
+}
+
+pub struct StreamTee {
+    dir: PathBuf,
+}
+
+/// File name for an agent or session, kept to characters safe in a path
+fn file_name(name: &str) -> String {
+    let name: String = name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.') {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    format!("{}.txt", name)
+}
+
+impl StreamTee {
+    pub fn new(dir: &Path) -> std::io::Result<Self> {
+        std::fs::create_dir_all(dir)?;
+        Ok(Self {
+            dir: dir.to_path_buf(),
+        })
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Open an agent's stream, a FIFO if one is there and being read
+    async fn open(&self, name: &str) -> std::io::Result<Out> {
+        let path = self.dir.join(file_name(name));
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::FileTypeExt;
+            let fifo = tokio::fs::metadata(&path)
+                .await
+                .is_ok_and(|m| m.file_type().is_fifo());
+            if fifo {
+                return match tokio::net::unix::pipe::OpenOptions::new().open_sender(&path) {
+                    Ok(sender) => Ok(Out::Pipe(sender)),
+                    // ENXIO, the same on Linux and macOS: no reader yet
+                    Err(e) if e.raw_os_error() == Some(6) => Ok(Out::Unread),
+                    Err(e) => Err(e),
+                };
+            }
+        }
+        let file = tokio::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .await?;
+        Ok(Out::File(file))
+    }
+
+    async fn write(&self, sinks: &mut HashMap<String, Sink>, event: &ObservabilityEvent) {
+        let Payload::StreamDelta(ref delta) = event.payload else {
+            return;
+        };
+        let Delta::Text { ref text } = delta.delta else {
+            return;
+        };
+        let Some(name) = event.agent.as_deref().or(event.session_id.as_deref()) else {
+            return;
+        };
+
+        let mut out = String::new();
+        let started = sinks.get(name).is_none_or(|s| s.response != delta.response_id);
+        if started {
+            close_idle(sinks, Instant::now());
+            // Files stay open between responses; FIFOs are tried again
+            let reopen = match sinks.get(name).map(|s| &s.out) {
+                Some(Out::File(_)) => false,
+                #[cfg(unix)]
+                Some(Out::Pipe(_)) => false,
+                _ => true,
+            };
+            if reopen {
+                match self.open(name).await {
+                    Ok(opened) => {
+                        sinks.insert(
+                            name.to_string(),
+                            Sink {
+                                out: opened,
+                                response: delta.response_id,
+                                written: Instant::now(),
+                            },
+                        );
+                    }
+                    Err(e) => {
+                        warn!("Can't tee {}'s stream to {}: {}", name, self.dir.display(), e);
+                        sinks.remove(name);
+                        return;
+                    }
+                }
+            }
+            let time = event.timestamp.with_timezone(&chrono::Local).format("%H:%M:%S");
+            out.push_str(&format!("\n── {}\n", time));
+        }
+        out.push_str(text);
+
+        let Some(sink) = sinks.get_mut(name) else {
+            return;
+        };
+        sink.response = delta.response_id;
+        sink.written = Instant::now();
+        let failed = match sink.out {
+            Out::File(ref mut file) => match file.write_all(out.as_bytes()).await {
+                // Flushed, so followers see text as it arrives
+                Ok(()) => file.flush().await.err(),
+                Err(e) => Some(e),
+            },
+            #[cfg(unix)]
+            Out::Pipe(ref mut sender) => {
+                match tokio::time::timeout(PIPE_WAIT, sender.write_all(out.as_bytes())).await {
+                    Ok(result) => result.err(),
+                    // The reader is behind; this much of the text is lost to it
+                    Err(_) => None,
+                }
+            }
+            Out::Unread => None,
+        };
+        if let Some(e) = failed {
+            if e.kind() != std::io::ErrorKind::BrokenPipe {
+                warn!("Stopped teeing {}'s stream: {}", name, e);
+            }
+            // Opened again when the next response starts
+            sink.out = Out::Unread;
+        }
+    }
+
+    /// Tee text deltas as they are published
+    pub fn spawn(self, mut deltas: broadcast::Receiver<ObservabilityEvent>) {
+        tokio::spawn(async move {
+            let mut sinks = HashMap::new();
+            loop {
+                let event = match deltas.recv().await {
+                    Ok(event) => event,
+                    Err(RecvError::Lagged(n)) => {
+                        warn!("Stream tee fell behind; {} delta(s) not written", n);
+                        continue;
+                    }
+                    Err(RecvError::Closed) => break,
+                };
+                self.write(&mut sinks, &event).await;
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event::StreamDelta;
+
+    fn delta(agent: &str, response: Uuid, text: &str) -> ObservabilityEvent {
+        ObservabilityEvent {
+            session_id: Some("0b7c1e52-session".to_string()),
+            agent: Some(agent.to_string()),
+            ..ObservabilityEvent::for_tests(0, Payload::StreamDelta(StreamDelta {
+                response_id: response,
+                delta: Delta::Text {
+                    text: text.to_string(),
+                },
+            }))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_each_agents_text_is_appended_to_its_own_file() {
+        let dir = std::env::temp_dir().join(format!("sentinel-test-{}", Uuid::new_v4()));
+        let tee = StreamTee::new(&dir).unwrap();
+        let mut sinks = HashMap::new();
+        let (first, second) = (Uuid::new_v4(), Uuid::new_v4());
+        for event in [
+            delta("swift-fox", first, "Hello"),
+            delta("calm-otter", Uuid::new_v4(), "Other"),
+            delta("swift-fox", first, ", world."),
+            delta("swift-fox", second, "Again."),
+        ] {
+            tee.write(&mut sinks, &event).await;
+        }
+        close_idle(&mut sinks, Instant::now());
+        assert_eq!(sinks.len(), 2);
+        close_idle(&mut sinks, Instant::now() + CLOSE_AFTER);
+        assert!(sinks.is_empty());
+
+        let written = std::fs::read_to_string(dir.join("swift-fox.txt")).unwrap();
+        let lines: Vec<&str> = written.lines().collect();
+        assert_eq!(lines.len(), 5);
+        assert!(lines[1].starts_with("── ") && lines[3].starts_with("── "));
+        assert_eq!((lines[2], lines[4]), ("Hello, world.", "Again."));
+        assert!(std::fs::read_to_string(dir.join("calm-otter.txt"))
+            .unwrap()
+            .ends_with("\nOther"));
+        assert_eq!(file_name("a/../b c"), "a_.._b_c.txt");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,416 @@
+//! Checking structured outputs against the schemas their requests declared.
+//!
+//! A request can ask for JSON output matching a schema (Anthropic's
+//! `output_format`, OpenAI's `response_format`, Gemini's
+//! `responseJsonSchema`), or force a tool call (`tool_choice`) whose input
+//! must match the tool's schema. Responses that don't hold to that are
+//! recorded as violations listing each place the output strayed from the
+//! schema, so a broken integration shows up as more than a parse error in
+//! the agent's own logs.
+
+use std::collections::HashMap;
+
+use serde_json::Value;
+
+use crate::event::{AssistantResponse, SchemaMismatch, Violation};
+
+/// Mismatches listed on one violation; the rest are only counted
+const MAX_MISMATCHES: usize = 10;
+/// Characters of an offending value kept
+const FOUND_CHARS: usize = 120;
+
+/// What the response text must be
+#[derive(Debug, Clone)]
+struct TextContract {
+    source: &'static str,
+    /// Schema it must match; `None` when any JSON will do
+    schema: Option<Value>,
+}
+
+/// Which tool calls the request forced
+#[derive(Debug, Clone, PartialEq)]
+enum Forced {
+    /// This tool, by name
+    Tool(String),
+    /// Some tool
+    Any,
+}
+
+/// What a request declared its response must look like
+#[derive(Debug, Clone)]
+pub struct OutputContract {
+    text: Option<TextContract>,
+    source: &'static str,
+    forced: Option<Forced>,
+    /// Input schemas of the tools calls are checked against: every tool when
+    /// a call is forced, and otherwise those marked `strict`
+    tools: HashMap<String, Value>,
+}
+
+fn json_schema_format(format: &Value, source: &'static str) -> Option<TextContract> {
+    match format.get("type")?.as_str()? {
+        // Anthropic puts the schema on the format, OpenAI under json_schema
+        "json_schema" => Some(TextContract {
+            source,
+            schema: format
+                .get("schema")
+                .or_else(|| format.pointer("/json_schema/schema"))
+                .cloned(),
+        }),
+        "json_object" => Some(TextContract {
+            source,
+            schema: None,
+        }),
+        _ => None,
+    }
+}
+
+fn forced(choice: &Value) -> Option<Forced> {
+    // OpenAI also takes a bare string
+    if let Some(choice) = choice.as_str() {
+        return (choice == "required").then_some(Forced::Any);
+    }
+    match choice.get("type")?.as_str()? {
+        "tool" => Some(Forced::Tool(choice.get("name")?.as_str()?.to_string())),
+        "function" => Some(Forced::Tool(
+            choice
+                .pointer("/function/name")
+                .or_else(|| choice.get("name"))?
+                .as_str()?
+                .to_string(),
+        )),
+        "any" => Some(Forced::Any),
+        _ => None,
+    }
+}
+
+impl OutputContract {
+    /// The contract a request body declares, if any. Bodies that mention
+    /// none of the fields involved aren't parsed.
+    pub fn from_body(body: &[u8]) -> Option<Self> {
+        let mentions = |field: &[u8]| body.windows(field.len()).any(|w| w == field);
+        if ![
+            &b"\"output_format\""[..],
+            b"\"response_format\"",
+            b"\"tool_choice\"",
+            b"\"strict\"",
+            b"\"generationConfig\"",
+        ]
+        .iter()
+        .any(|field| mentions(field))
+        {
+            return None;
+        }
+        let request: Value = serde_json::from_slice(body).ok()?;
+
+        let text = if let Some(format) = request.get("output_format") {
+            json_schema_format(format, "schema:output_format")
+        } else if let Some(format) = request.get("response_format") {
+            json_schema_format(format, "schema:response_format")
+        } else if let Some(config) = request.get("generationConfig") {
+            let schema = config
+                .get("responseJsonSchema")
+                .or_else(|| config.get("responseSchema"))
+                .cloned();
+            let json =
+                config.get("responseMimeType").and_then(Value::as_str) == Some("application/json");
+            (json || schema.is_some()).then_some(TextContract {
+                source: "schema:generationConfig",
+                schema,
+            })
+        } else {
+            None
+        };
+
+        let forced = request.get("tool_choice").and_then(forced);
+        let mut tools = HashMap::new();
+        for tool in request
+            .get("tools")
+            .and_then(Value::as_array)
+            .into_iter()
+            .flatten()
+        {
+            // Anthropic tools are flat; OpenAI ones sit under `function`
+            let tool = tool.get("function").unwrap_or(tool);
+            let (Some(name), Some(schema)) = (
+                tool.get("name").and_then(Value::as_str),
+                tool.get("input_schema").or_else(|| tool.get("parameters")),
+            ) else {
+                continue;
+            };
+            if forced.is_some() || tool.get("strict").and_then(Value::as_bool) == Some(true) {
+                tools.insert(name.to_string(), schema.clone());
+            }
+        }
+
+        if text.is_none() && forced.is_none() && tools.is_empty() {
+            return None;
+        }
+        Some(OutputContract {
+            text,
+            source: if forced.is_some() {
+                "schema:tool_choice"
+            } else {
+                "schema:strict"
+            },
+            forced,
+            tools,
+        })
+    }
+
+    /// Ways `response` breaks the contract
+    pub fn check(&self, response: &AssistantResponse) -> Vec<Violation> {
+        let mut violations = Vec::new();
+        let violation =
+            |source: &str, tool: &str, tool_use_id: Option<&str>, message: String| Violation {
+                source: source.to_string(),
+                tool: tool.to_string(),
+                tool_use_id: tool_use_id.map(str::to_string),
+                message,
+                blocked: false,
+                mismatches: Vec::new(),
+            };
+
+        // Text is only held to the format when there is some: a turn that
+        // calls tools instead has nothing to check
+        if let (Some(contract), Some(text)) = (&self.text, response.text.as_deref()) {
+            let cut_off = if response.stop_reason.as_deref() == Some("max_tokens") {
+                " (the response hit max_tokens)"
+            } else {
+                ""
+            };
+            match serde_json::from_str::<Value>(text.trim()) {
+                Err(e) => violations.push(violation(
+                    contract.source,
+                    "",
+                    None,
+                    format!("Response text isn't valid JSON: {}{}", e, cut_off),
+                )),
+                Ok(output) => {
+                    if let Some(mismatches) =
+                        contract.schema.as_ref().and_then(|s| validate(s, &output))
+                    {
+                        violations.push(Violation {
+                            mismatches: mismatches.iter().take(MAX_MISMATCHES).cloned().collect(),
+                            ..violation(
+                                contract.source,
+                                "",
+                                None,
+                                format!(
+                                    "Response text doesn't match the declared schema ({}){}",
+                                    summarize(&mismatches),
+                                    cut_off
+                                ),
+                            )
+                        });
+                    }
+                }
+            }
+        }
+
+        match self.forced {
+            Some(Forced::Tool(ref name))
+                if !response.tool_calls.iter().any(|c| &c.name == name) =>
+            {
+                violations.push(violation(
+                    self.source,
+                    name,
+                    None,
+                    format!("Forced tool {} wasn't called", name),
+                ))
+            }
+            Some(Forced::Any) if response.tool_calls.is_empty() => violations.push(violation(
+                self.source,
+                "",
+                None,
+                "A tool call was required but none was made".to_string(),
+            )),
+            _ => {}
+        }
+        for call in &response.tool_calls {
+            let Some(mismatches) = self
+                .tools
+                .get(&call.name)
+                .and_then(|s| validate(s, &call.input))
+            else {
+                continue;
+            };
+            violations.push(Violation {
+                mismatches: mismatches.iter().take(MAX_MISMATCHES).cloned().collect(),
+                ..violation(
+                    self.source,
+                    &call.name,
+                    Some(&call.id),
+                    format!(
+                        "{} input doesn't match its schema ({})",
+                        call.name,
+                        summarize(&mismatches)
+                    ),
+                )
+            });
+        }
+        violations
+    }
+}
+
+/// Where `instance` strays from `schema`; `None` when it matches, or when
+/// the schema itself can't be compiled (the upstream would have refused it)
+fn validate(schema: &Value, instance: &Value) -> Option<Vec<SchemaMismatch>> {
+    let validator = jsonschema::validator_for(schema).ok()?;
+    let mismatches: Vec<SchemaMismatch> = validator
+        .iter_errors(instance)
+        .map(|error| {
+            let found = error.instance.to_string();
+            SchemaMismatch {
+                path: error.instance_path.to_string(),
+                message: error.to_string(),
+                found: match found.char_indices().nth(FOUND_CHARS) {
+                    Some((cut, _)) => format!("{}…", &found[..cut]),
+                    None => found,
+                },
+            }
+        })
+        .collect();
+    (!mismatches.is_empty()).then_some(mismatches)
+}
+
+/// "3 mismatches; first at /age: ..."
+fn summarize(mismatches: &[SchemaMismatch]) -> String {
+    let first = &mismatches[0];
+    let at = if first.path.is_empty() {
+        "/"
+    } else {
+        &first.path
+    };
+    match mismatches.len() {
+        1 => format!("at {}: {}", at, first.message),
+        n => format!("{} mismatches; first at {}: {}", n, at, first.message),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parsers::ToolCall;
+    use serde_json::json;
+
+    fn response(text: Option<&str>, tool_calls: Vec<ToolCall>) -> AssistantResponse {
+        AssistantResponse {
+            stop_reason: Some("end_turn".to_string()),
+            text: text.map(str::to_string),
+            tool_calls,
+            ..Default::default()
+        }
+    }
+
+    fn body(value: Value) -> Vec<u8> {
+        serde_json::to_vec(&value).unwrap()
+    }
+
+    #[test]
+    fn test_checks_text_against_output_schema() {
+        let schema = json!({
+            "type": "object",
+            "properties": {"name": {"type": "string"}, "age": {"type": "integer"}},
+            "required": ["name", "age"],
+        });
+        let anthropic = body(json!({
+            "model": "claude-sonnet-4-5",
+            "messages": [],
+            "output_format": {"type": "json_schema", "schema": schema},
+        }));
+        let contract = OutputContract::from_body(&anthropic).unwrap();
+        assert!(contract
+            .check(&response(Some(r#"{"name": "Ada", "age": 36}"#), vec![]))
+            .is_empty());
+
+        let violations = contract.check(&response(Some(r#"{"age": "36"}"#), vec![]));
+        assert_eq!(violations.len(), 1);
+        let v = &violations[0];
+        assert_eq!(v.source, "schema:output_format");
+        assert_eq!(v.mismatches.len(), 2);
+        assert!(v
+            .message
+            .starts_with("Response text doesn't match the declared schema (2 mismatches"));
+        let age = v.mismatches.iter().find(|m| m.path == "/age").unwrap();
+        assert_eq!(age.found, "\"36\"");
+
+        let violations = contract.check(&response(Some("Sure! Here it is:"), vec![]));
+        assert!(violations[0]
+            .message
+            .starts_with("Response text isn't valid JSON"));
+
+        // OpenAI's json_object only asks for JSON
+        let openai = body(json!({"response_format": {"type": "json_object"}, "messages": []}));
+        let contract = OutputContract::from_body(&openai).unwrap();
+        assert!(contract.check(&response(Some("[1, 2]"), vec![])).is_empty());
+        assert_eq!(contract.check(&response(Some("nope"), vec![])).len(), 1);
+
+        // Nothing declared
+        assert!(OutputContract::from_body(&body(json!({"messages": []}))).is_none());
+        assert!(
+            OutputContract::from_body(&body(json!({"response_format": {"type": "text"}})))
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn test_checks_forced_tool_calls() {
+        let request = body(json!({
+            "messages": [],
+            "tool_choice": {"type": "tool", "name": "record"},
+            "tools": [{
+                "name": "record",
+                "input_schema": {
+                    "type": "object",
+                    "properties": {"score": {"type": "number", "maximum": 10}},
+                    "required": ["score"],
+                },
+            }],
+        }));
+        let contract = OutputContract::from_body(&request).unwrap();
+        let call = |input: Value| ToolCall {
+            id: "toolu_1".to_string(),
+            name: "record".to_string(),
+            input,
+        };
+        assert!(contract
+            .check(&response(None, vec![call(json!({"score": 7}))]))
+            .is_empty());
+
+        let violations = contract.check(&response(None, vec![call(json!({"score": 12}))]));
+        assert_eq!(violations.len(), 1);
+        assert_eq!(
+            (violations[0].source.as_str(), violations[0].tool.as_str()),
+            ("schema:tool_choice", "record")
+        );
+        assert_eq!(violations[0].tool_use_id.as_deref(), Some("toolu_1"));
+        assert_eq!(violations[0].mismatches[0].path, "/score");
+
+        let violations = contract.check(&response(Some("I'd rather not."), vec![]));
+        assert_eq!(violations[0].message, "Forced tool record wasn't called");
+
+        // OpenAI: "required", with a strict function
+        let request = body(json!({
+            "tool_choice": "required",
+            "tools": [{"type": "function", "function": {
+                "name": "lookup",
+                "strict": true,
+                "parameters": {"type": "object", "required": ["q"]},
+            }}],
+        }));
+        let contract = OutputContract::from_body(&request).unwrap();
+        assert_eq!(contract.check(&response(Some("hi"), vec![])).len(), 1);
+        let lookup = ToolCall {
+            id: "call_1".to_string(),
+            name: "lookup".to_string(),
+            input: json!({}),
+        };
+        assert_eq!(
+            contract.check(&response(None, vec![lookup]))[0]
+                .mismatches
+                .len(),
+            1
+        );
+    }
+}
@@ -0,0 +1,391 @@
+//! Subagents: sessions started by another agent's Task tool.
+//!
+//! Claude Code runs a Task (or Agent) tool call as a new conversation, which
+//! sentinel first sees as an unrelated agent. While a new agent is young, its
+//! requests are checked against Task calls other agents have made whose
+//! results haven't come back yet. A call whose `prompt` opens the new
+//! conversation is its parent; failing that, a lone call made just before
+//! the agent appeared is taken, and the link is marked as by timing. The
+//! parent is kept in the agent's `parent_id`, with the call that spawned it,
+//! and `sentinel agents --tree` draws the hierarchy.
+
+use std::collections::HashMap;
+use std::fmt::Write;
+
+use chrono::{DateTime, Duration, Utc};
+use sqlx::SqlitePool;
+use uuid::Uuid;
+
+use crate::agent::{Agent, AgentStatus};
+
+/// Tools that start a subagent
+const SPAWNING_TOOLS: &[&str] = &["Task", "Agent"];
+/// How long after it appears an agent is still checked for a parent
+const LINK_WINDOW_SECS: i64 = 120;
+/// Furthest back a Task call can be from a subagent it started
+const SPAWN_WINDOW_SECS: i64 = 600;
+/// Furthest back a Task call can be to be linked by timing alone
+const TIMING_WINDOW_SECS: i64 = 30;
+
+/// How a subagent was tied to its parent
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LinkedBy {
+    /// Its opening message is the Task call's prompt
+    Prompt,
+    /// The only Task call made just before it appeared
+    Timing,
+}
+
+impl LinkedBy {
+    fn as_str(self) -> &'static str {
+        match self {
+            LinkedBy::Prompt => "prompt",
+            LinkedBy::Timing => "timing",
+        }
+    }
+}
+
+/// A Task call still waiting for its subagent's result
+#[derive(Debug, sqlx::FromRow)]
+struct PendingTask {
+    tool_use_id: String,
+    parent_id: String,
+    input: String,
+    timestamp: String,
+}
+
+impl PendingTask {
+    fn prompt(&self) -> Option<String> {
+        let input: serde_json::Value = serde_json::from_str(&self.input).ok()?;
+        let prompt = input.get("prompt")?.as_str()?.trim();
+        (!prompt.is_empty()).then(|| prompt.to_string())
+    }
+
+    fn at(&self) -> Option<DateTime<Utc>> {
+        DateTime::parse_from_rfc3339(&self.timestamp)
+            .ok()
+            .map(|t| t.with_timezone(&Utc))
+    }
+}
+
+/// Whether `agent` may still be linked to a parent
+pub fn unlinked(agent: &Agent, now: DateTime<Utc>) -> bool {
+    agent.parent_id.is_none() && now - agent.created_at <= Duration::seconds(LINK_WINDOW_SECS)
+}
+
+/// The Task call that started an agent first seen at `created_at` with
+/// `opening` as its first message
+fn pick<'a>(
+    pending: &'a [PendingTask],
+    opening: &str,
+    created_at: DateTime<Utc>,
+) -> Option<(&'a PendingTask, LinkedBy)> {
+    let opening = opening.trim();
+    if let Some(task) = pending
+        .iter()
+        .find(|task| task.prompt().is_some_and(|prompt| opening.contains(&prompt)))
+    {
+        return Some((task, LinkedBy::Prompt));
+    }
+    let recent: Vec<&PendingTask> = pending
+        .iter()
+        .filter(|task| {
+            task.at().is_some_and(|at| {
+                at <= created_at && created_at - at <= Duration::seconds(TIMING_WINDOW_SECS)
+            })
+        })
+        .collect();
+    match recent.as_slice() {
+        [task] => Some((task, LinkedBy::Timing)),
+        _ => None,
+    }
+}
+
+/// Link a young agent to the agent whose Task call started it, if one did;
+/// true when it was linked
+pub async fn link(
+    pool: &SqlitePool,
+    agent: &mut Agent,
+    opening: &str,
+) -> Result<bool, sqlx::Error> {
+    let placeholders = vec!["?"; SPAWNING_TOOLS.len()].join(", ");
+    let sql = format!(
+        r#"
+        SELECT t.tool_use_id, p.id AS parent_id, t.input, t.timestamp
+        FROM tool_calls t
+        JOIN agents p ON p.name = t.agent
+        WHERE t.name IN ({})
+          AND t.status IS NULL
+          AND t.agent != ?
+          AND t.timestamp >= ?
+          AND NOT EXISTS (SELECT 1 FROM agents c WHERE c.parent_tool_use_id = t.tool_use_id)
+        ORDER BY t.timestamp
+        "#,
+        placeholders
+    );
+    let mut query = sqlx::query_as::<_, PendingTask>(&sql);
+    for tool in SPAWNING_TOOLS {
+        query = query.bind(*tool);
+    }
+    let since = agent.created_at - Duration::seconds(SPAWN_WINDOW_SECS);
+    let pending = query
+        .bind(&agent.name)
+        .bind(since.to_rfc3339())
+        .fetch_all(pool)
+        .await?;
+    let Some((task, linked_by)) = pick(&pending, opening, agent.created_at) else {
+        return Ok(false);
+    };
+    let Ok(parent_id) = task.parent_id.parse::<Uuid>() else {
+        return Ok(false);
+    };
+    sqlx::query(
+        "UPDATE agents SET parent_id = ?, parent_tool_use_id = ?, parent_linked_by = ? WHERE id = ? AND parent_id IS NULL",
+    )
+    .bind(parent_id.to_string())
+    .bind(&task.tool_use_id)
+    .bind(linked_by.as_str())
+    .bind(agent.id.to_string())
+    .execute(pool)
+    .await?;
+    agent.parent_id = Some(parent_id);
+    tracing::info!(
+        "Agent '{}' is a subagent started by {} (linked by {})",
+        agent.name,
+        task.tool_use_id,
+        linked_by.as_str()
+    );
+    Ok(true)
+}
+
+/// What a subagent was started to do
+#[derive(Debug, Default, Clone)]
+pub struct Spawn {
+    pub subagent_type: Option<String>,
+    pub description: Option<String>,
+    /// Linked by timing alone, so possibly wrong
+    pub by_timing: bool,
+}
+
+/// A subagent with the input of the Task call that started it
+#[derive(sqlx::FromRow)]
+struct SpawnRow {
+    id: String,
+    input: Option<String>,
+    parent_linked_by: Option<String>,
+}
+
+/// The Task call behind each subagent, by agent id
+pub async fn spawns(pool: &SqlitePool) -> Result<HashMap<Uuid, Spawn>, sqlx::Error> {
+    let rows: Vec<SpawnRow> = sqlx::query_as(
+        r#"
+        SELECT a.id, t.input, a.parent_linked_by
+        FROM agents a
+        LEFT JOIN tool_calls t ON t.tool_use_id = a.parent_tool_use_id
+        WHERE a.parent_id IS NOT NULL
+        "#,
+    )
+    .fetch_all(pool)
+    .await?;
+    let mut spawns = HashMap::with_capacity(rows.len());
+    for row in rows {
+        let id = match row.id.parse() {
+            Ok(id) => id,
+            Err(e) => {
+                tracing::warn!("Skipping subagent row with invalid id '{}': {}", row.id, e);
+                continue;
+            }
+        };
+        // An input that doesn't parse only costs the spawn its description
+        let input: serde_json::Value = match row.input.as_deref().map(serde_json::from_str) {
+            Some(Ok(input)) => input,
+            Some(Err(e)) => {
+                tracing::warn!("Unparseable Task input for subagent {}: {}", id, e);
+                serde_json::Value::Null
+            }
+            None => serde_json::Value::Null,
+        };
+        let field = |name: &str| input.get(name).and_then(|v| v.as_str()).map(String::from);
+        let spawn = Spawn {
+            subagent_type: field("subagent_type"),
+            description: field("description"),
+            by_timing: row.parent_linked_by.as_deref() == Some(LinkedBy::Timing.as_str()),
+        };
+        spawns.insert(id, spawn);
+    }
+    Ok(spawns)
+}
+
+/// Whether following `agent`'s parents from `agent` comes back around to it
+fn in_cycle(agent: &Agent, parents: &HashMap<Uuid, Uuid>) -> bool {
+    let mut seen = std::collections::HashSet::new();
+    let mut at = agent.id;
+    while let Some(&parent) = parents.get(&at) {
+        if parent == agent.id {
+            return true;
+        }
+        if !seen.insert(parent) {
+            // A loop further up, which its own agents break
+            return false;
+        }
+        at = parent;
+    }
+    false
+}
+
+/// Agents drawn under the agents that started them, most recent first.
+/// Agents whose parents lead back to themselves (two subagents linked to
+/// each other by timing) are drawn as roots, so none are left out.
+pub fn render_tree(agents: &[Agent], spawns: &HashMap<Uuid, Spawn>, now: DateTime<Utc>) -> String {
+    let ids: std::collections::HashSet<Uuid> = agents.iter().map(|a| a.id).collect();
+    let parents: HashMap<Uuid, Uuid> = agents
+        .iter()
+        .filter_map(|a| Some((a.id, a.parent_id.filter(|id| ids.contains(id))?)))
+        .collect();
+    let mut children: HashMap<Uuid, Vec<&Agent>> = HashMap::new();
+    let mut roots = Vec::new();
+    for agent in agents {
+        match parents.get(&agent.id) {
+            Some(parent) if !in_cycle(agent, &parents) => {
+                children.entry(*parent).or_default().push(agent)
+            }
+            _ => roots.push(agent),
+        }
+    }
+    let mut out = String::new();
+    for root in roots {
+        push_agent(&mut out, root, &children, spawns, now, "", "");
+    }
+    out
+}
+
+fn push_agent(
+    out: &mut String,
+    agent: &Agent,
+    children: &HashMap<Uuid, Vec<&Agent>>,
+    spawns: &HashMap<Uuid, Spawn>,
+    now: DateTime<Utc>,
+    branch: &str,
+    indent: &str,
+) {
+    let status = if now - agent.last_seen_at > Duration::minutes(5) {
+        AgentStatus::Inactive
+    } else {
+        AgentStatus::Active
+    };
+    let label = format!("{}{}", branch, agent.name);
+    let _ = write!(
+        out,
+        "{:<36} {:<10} {}",
+        label,
+        status,
+        agent.last_seen_at.format("%Y-%m-%d %H:%M")
+    );
+    if let Some(spawn) = spawns.get(&agent.id) {
+        let what: Vec<&str> = [spawn.subagent_type.as_deref(), spawn.description.as_deref()]
+            .into_iter()
+            .flatten()
+            .collect();
+        if !what.is_empty() {
+            let _ = write!(out, "  {}", what.join(": "));
+        }
+        if spawn.by_timing {
+            out.push_str(" (linked by timing)");
+        }
+    }
+    out.push('\n');
+    let Some(kids) = children.get(&agent.id) else {
+        return;
+    };
+    for (i, child) in kids.iter().enumerate() {
+        let last = i + 1 == kids.len();
+        let branch = format!("{}{}", indent, if last { "└─ " } else { "├─ " });
+        let indent = format!("{}{}", indent, if last { "   " } else { "│  " });
+        push_agent(out, child, children, spawns, now, &branch, &indent);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn task(id: &str, prompt: &str, secs: u32) -> PendingTask {
+        PendingTask {
+            tool_use_id: id.to_string(),
+            parent_id: Uuid::new_v4().to_string(),
+            input: serde_json::json!({"description": "d", "prompt": prompt}).to_string(),
+            timestamp: Utc.with_ymd_and_hms(2026, 1, 5, 10, 0, secs).unwrap().to_rfc3339(),
+        }
+    }
+
+    fn agent(name: &str, parent_id: Option<Uuid>) -> Agent {
+        let now = Utc::now();
+        Agent {
+            id: Uuid::new_v4(),
+            name: name.to_string(),
+            session_id: name.to_string(),
+            working_directory: None,
+            created_at: now,
+            last_seen_at: now,
+            status: AgentStatus::Active,
+            topic: None,
+            tags: Vec::new(),
+            project: None,
+            git: None,
+            parent_id,
+        }
+    }
+
+    #[test]
+    fn test_subagents_link_by_prompt_then_timing_and_render_as_a_tree() {
+        let at = |secs| Utc.with_ymd_and_hms(2026, 1, 5, 10, 0, secs).unwrap();
+        let pending = [
+            task("t1", "Find every caller of parse_name", 0),
+            task("t2", "Summarize the README", 1),
+        ];
+        let opening = "<system-reminder>...</system-reminder>\nSummarize the README";
+        let (found, by) = pick(&pending, opening, at(50)).unwrap();
+        assert_eq!((found.tool_use_id.as_str(), by), ("t2", LinkedBy::Prompt));
+        // Two calls just before and no prompt to tell them apart
+        assert!(pick(&pending, "something else", at(5)).is_none());
+        let (found, by) = pick(&pending[..1], "something else", at(5)).unwrap();
+        assert_eq!((found.tool_use_id.as_str(), by), ("t1", LinkedBy::Timing));
+        assert!(pick(&pending[..1], "something else", at(45)).is_none());
+
+        let root = agent("calm-otter", None);
+        let child = agent("swift-fox", Some(root.id));
+        let grandchild = agent("blue-owl", Some(child.id));
+        let sibling = agent("red-hawk", Some(root.id));
+        let spawns = HashMap::from([(
+            child.id,
+            Spawn {
+                subagent_type: Some("Explore".to_string()),
+                description: Some("Find callers".to_string()),
+                by_timing: true,
+            },
+        )]);
+        let agents = [root, child, grandchild, sibling];
+        let tree = render_tree(&agents, &spawns, Utc::now());
+        let names: Vec<String> = tree
+            .lines()
+            .map(|line| line.chars().take(36).collect::<String>())
+            .collect();
+        let names: Vec<&str> = names.iter().map(|n| n.trim_end()).collect();
+        assert_eq!(
+            names,
+            ["calm-otter", "├─ swift-fox", "│  └─ blue-owl", "└─ red-hawk"]
+        );
+        assert!(tree.contains("Explore: Find callers (linked by timing)"));
+
+        // Linked to each other, both are still drawn
+        let mut first = agent("green-elk", None);
+        let second = agent("gray-wolf", Some(first.id));
+        first.parent_id = Some(second.id);
+        let under = agent("tan-deer", Some(second.id));
+        let tree = render_tree(&[first, second, under], &HashMap::new(), Utc::now());
+        assert_eq!(tree.lines().count(), 3);
+        assert!(tree.contains("gray-wolf") && tree.contains("green-elk"));
+        assert!(tree.contains("└─ tan-deer"));
+    }
+}
@@ -0,0 +1,232 @@
+//! `sentinel tail`: follow traffic as it is recorded.
+//!
+//! Subscribes to a running proxy's `/api/events` stream and prints one line
+//! per event. When no proxy answers on the port (say it runs elsewhere but
+//! shares the data dir), it polls the database for new events instead.
+//! `sentinel watch` follows the same feed.
+
+use std::path::Path;
+use std::time::Duration;
+
+use futures::StreamExt;
+use tokio::sync::mpsc;
+
+use crate::event::{ObservabilityEvent, Payload};
+use crate::storage::Storage;
+
+const PREVIEW_CHARS: usize = 60;
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+pub async fn run(
+    data_dir: &Path,
+    port: u16,
+    agent: Option<String>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut events = follow(data_dir, port, agent).await?;
+    while let Some(event) = events.recv().await {
+        println!("{}", format_line(&event));
+    }
+    eprintln!("Sentinel closed the stream");
+    Ok(())
+}
+
+/// New events as they are recorded: from the proxy on `port` if one answers,
+/// otherwise by polling the database
+pub async fn follow(
+    data_dir: &Path,
+    port: u16,
+    agent: Option<String>,
+) -> Result<mpsc::Receiver<ObservabilityEvent>, Box<dyn std::error::Error>> {
+    let mut url = reqwest::Url::parse(&format!("http://127.0.0.1:{}/api/events", port))?;
+    if let Some(ref agent) = agent {
+        url.query_pairs_mut().append_pair("agent", agent);
+    }
+    let (tx, rx) = mpsc::channel(256);
+    match reqwest::get(url).await.and_then(|r| r.error_for_status()) {
+        Ok(response) => {
+            eprintln!("Following sentinel on port {} (Ctrl-C to stop)", port);
+            tokio::spawn(async move {
+                if let Err(e) = follow_stream(response, tx).await {
+                    tracing::warn!("Lost the event stream: {}", e);
+                }
+            });
+        }
+        Err(e) => {
+            eprintln!(
+                "No sentinel answering on port {} ({}); watching the database instead",
+                port, e
+            );
+            let db_path = data_dir.join("sentinel.db");
+            if !db_path.exists() {
+                return Err("No database yet. Run 'sentinel start' first.".into());
+            }
+            let storage = Storage::new(&db_path).await?;
+            tokio::spawn(async move {
+                if let Err(e) = poll_database(storage, agent.as_deref(), tx).await {
+                    tracing::warn!("Stopped polling the database: {}", e);
+                }
+            });
+        }
+    }
+    Ok(rx)
+}
+
+async fn follow_stream(
+    response: reqwest::Response,
+    tx: mpsc::Sender<ObservabilityEvent>,
+) -> Result<(), reqwest::Error> {
+    let mut stream = response.bytes_stream();
+    let mut buffer = String::new();
+    while let Some(chunk) = stream.next().await {
+        buffer.push_str(&String::from_utf8_lossy(&chunk?));
+        // SSE messages end with a blank line
+        while let Some(end) = buffer.find("\n\n") {
+            let message: String = buffer.drain(..end + 2).collect();
+            let data: String = message
+                .lines()
+                .filter_map(|line| line.strip_prefix("data:"))
+                .map(str::trim_start)
+                .collect();
+            if let Some(event) = envelope_event(&data) {
+                if tx.send(event).await.is_err() {
+                    // Nobody is reading anymore
+                    return Ok(());
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The event in an `/api/events` message; None for keep-alives and the
+/// stream's other message types
+fn envelope_event(data: &str) -> Option<ObservabilityEvent> {
+    let mut envelope: serde_json::Value = serde_json::from_str(data).ok()?;
+    if envelope["type"] != "observability_event" {
+        return None;
+    }
+    match serde_json::from_value(envelope["payload"]["event"].take()) {
+        Ok(event) => Some(event),
+        Err(e) => {
+            tracing::warn!("Skipping unreadable event from the stream: {}", e);
+            None
+        }
+    }
+}
+
+async fn poll_database(
+    storage: Storage,
+    agent: Option<&str>,
+    tx: mpsc::Sender<ObservabilityEvent>,
+) -> Result<(), sqlx::Error> {
+    // Start from now, like the live stream does
+    let mut last_seq = storage
+        .get_recent_observability_events(1)
+        .await?
+        .first()
+        .and_then(|e| e.seq)
+        .unwrap_or(0);
+    loop {
+        for event in storage.get_events_after_seq(last_seq, i64::MAX).await? {
+            last_seq = event.seq.unwrap_or(last_seq);
+            let wanted = agent.is_none() || event.agent.as_deref() == agent;
+            if wanted && tx.send(event).await.is_err() {
+                return Ok(());
+            }
+        }
+        tokio::time::sleep(POLL_INTERVAL).await;
+    }
+}
+
+/// One line summing up an event: time, agent, and what happened
+pub fn format_line(event: &ObservabilityEvent) -> String {
+    let time = event
+        .timestamp
+        .with_timezone(&chrono::Local)
+        .format("%H:%M:%S");
+    let agent = event.agent.as_deref().unwrap_or("-");
+    let detail = match &event.payload {
+        Payload::UserMessage(msg) => {
+            format!(
+                "→ {}  {}",
+                msg.model.as_deref().unwrap_or("-"),
+                preview(&msg.text)
+            )
+        }
+        Payload::AssistantResponse(resp) => {
+            let mut parts = vec![format!("← {}", resp.model.as_deref().unwrap_or("-"))];
+            if let Some(ref usage) = resp.usage {
+                parts.push(format!(
+                    "{} in / {} out",
+                    usage.input_tokens.unwrap_or(0),
+                    usage.output_tokens.unwrap_or(0)
+                ));
+            }
+            if !resp.tool_calls.is_empty() {
+                let names: Vec<&str> = resp.tool_calls.iter().map(|c| c.name.as_str()).collect();
+                parts.push(format!("tools: {}", names.join(", ")));
+            }
+            if let Some(ref text) = resp.text {
+                parts.push(preview(text));
+            }
+            parts.join("  ")
+        }
+        Payload::Alert(alert) => format!("! {}: {}", alert.kind, alert.message),
+        Payload::Error(error) => format!("✗ {}", error.message),
+        Payload::Violation(violation) => format!(
+            "⚠ {}: {}{}",
+            violation.source,
+            violation.message,
+            if violation.blocked { " (blocked)" } else { "" }
+        ),
+        Payload::SessionClosed(closed) => format!(
+            "■ session closed after {}m {}s, {} turns, ${:.4}",
+            closed.duration_secs / 60,
+            closed.duration_secs % 60,
+            closed.turns,
+            closed.cost_usd
+        ),
+        Payload::StreamDelta(_) => "… writing".to_string(),
+        Payload::StreamAborted(aborted) => format!(
+            "✂ client disconnected after {} bytes",
+            aborted.received_bytes
+        ),
+        Payload::TurnSnapshot(snapshot) => format!(
+            "⏱ turn still running after {:.1}s, {} bytes received",
+            snapshot.elapsed_ms as f64 / 1000.0,
+            snapshot.received_bytes
+        ),
+        Payload::Annotation(annotation) => format!("◆ {}", annotation.describe()),
+    };
+    format!("{} {:<15} {}", time, agent, detail)
+}
+
+fn preview(text: &str) -> String {
+    let line = text.lines().next().unwrap_or("");
+    let preview: String = line.chars().take(PREVIEW_CHARS).collect();
+    if preview.len() < text.len() {
+        format!("\"{}...\"", preview)
+    } else {
+        format!("\"{}\"", preview)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reads_events_from_stream_messages() {
+        let data = r#"{"type":"observability_event","payload":{"event":{"seq":null,"id":"7c9e6679-7425-40de-944b-e07fc1f90ae7","timestamp":"2026-01-05T10:00:00Z","session_id":null,"agent":"calm-otter","topic":null,"provider":"anthropic","payload":{"type":"assistant_response","streaming":true,"model":"claude-sonnet-4","message_id":null,"stop_reason":null,"thinking":null,"text":"Running the tests","tool_calls":[{"id":"t1","name":"Bash","input":{}}],"usage":{"input_tokens":1200,"output_tokens":40,"cache_read_tokens":null,"cache_creation_tokens":null}}}}}"#;
+
+        let event = envelope_event(data).expect("event should parse");
+        let line = format_line(&event);
+        assert!(line.contains("calm-otter"));
+        assert!(line
+            .contains("← claude-sonnet-4  1200 in / 40 out  tools: Bash  \"Running the tests\""));
+
+        let presence = r#"{"type":"presence","payload":{"agent":"calm-otter","viewers":[]}}"#;
+        assert!(envelope_event(presence).is_none());
+        assert!(envelope_event("keep-alive").is_none());
+    }
+}
@@ -0,0 +1,292 @@
+//! `sentinel thinking`: extended-thinking budgets against the thinking done.
+//!
+//! Each request records its `thinking.budget_tokens`; its response shows how
+//! much of that the model used. Output tokens include the thinking (even when
+//! the response only carries a summary of it), so thinking is estimated as
+//! output tokens less the visible text and tool input. Sessions that keep
+//! running into their budget, or keep leaving most of it unused, are flagged
+//! with a budget to try instead.
+
+use std::collections::HashMap;
+use std::path::Path;
+
+use chrono::{DateTime, Duration, Utc};
+use uuid::Uuid;
+
+use crate::event::{AssistantResponse, ObservabilityEvent, Payload};
+use crate::storage::Storage;
+
+/// Rough characters per token of visible output
+const CHARS_PER_TOKEN: usize = 4;
+/// A turn using this share of its budget ran into it
+const MAXED: f64 = 0.9;
+/// A turn using less than this share left most of its budget unused
+const WASTED: f64 = 0.25;
+/// Share of a session's turns that must agree for it to be flagged
+const CONSISTENT: f64 = 0.5;
+/// Turns a session needs before it's judged at all
+const MIN_TURNS: usize = 3;
+/// The smallest budget the API accepts
+const MIN_BUDGET: u64 = 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verdict {
+    /// Most turns ran into the budget
+    Maxed,
+    /// Most turns used little of it
+    Wasted,
+    Fits,
+}
+
+/// One session's thinking budgets and use
+#[derive(Debug)]
+pub struct SessionThinking {
+    pub session: String,
+    pub agent: Option<String>,
+    /// Turns sent with a budget
+    pub turns: usize,
+    pub mean_budget: f64,
+    /// Estimated thinking tokens per turn
+    pub mean_used: f64,
+    /// Turns that ran into their budget
+    pub maxed: usize,
+    /// Turns that used little of it
+    pub wasted: usize,
+    pub verdict: Verdict,
+    /// Budget to try instead, for a flagged session
+    pub suggested: Option<u64>,
+}
+
+/// Budget and estimated thinking of one turn
+type Turn = (u64, u64);
+
+/// Thinking tokens a response is estimated to have used
+fn thinking_used(response: &AssistantResponse) -> u64 {
+    let visible = response.text.as_deref().map_or(0, str::len)
+        + response
+            .tool_calls
+            .iter()
+            .map(|call| call.input.to_string().len())
+            .sum::<usize>();
+    match response.usage.as_ref().and_then(|u| u.output_tokens) {
+        Some(output) => (output.max(0) as u64).saturating_sub((visible / CHARS_PER_TOKEN) as u64),
+        // Without usage, all there is to go on is the thinking text itself
+        None => (response.thinking.as_deref().map_or(0, str::len) / CHARS_PER_TOKEN) as u64,
+    }
+}
+
+/// Up to the next multiple of 1024, and at least the API minimum
+fn round_budget(tokens: u64) -> u64 {
+    tokens.div_ceil(MIN_BUDGET).max(1) * MIN_BUDGET
+}
+
+pub fn analyze(events: &[ObservabilityEvent]) -> Vec<SessionThinking> {
+    // Budgets by the id their response is recorded under
+    let mut budgets: HashMap<Uuid, u64> = HashMap::new();
+    // Agent and turns, by session
+    let mut sessions: HashMap<String, (Option<String>, Vec<Turn>)> = HashMap::new();
+    for event in events {
+        match &event.payload {
+            Payload::UserMessage(message) => {
+                if let (Some(budget), Some(id)) = (message.thinking_budget, message.response_id) {
+                    budgets.insert(id, budget);
+                }
+            }
+            Payload::AssistantResponse(response) => {
+                let (Some(budget), Some(session)) = (budgets.remove(&event.id), &event.session_id)
+                else {
+                    continue;
+                };
+                let entry = sessions
+                    .entry(session.clone())
+                    .or_insert_with(|| (event.agent.clone(), Vec::new()));
+                entry.1.push((budget, thinking_used(response)));
+            }
+            _ => {}
+        }
+    }
+
+    let mut summaries: Vec<SessionThinking> = sessions
+        .into_iter()
+        .map(|(session, (agent, turns))| {
+            let n = turns.len();
+            let ratio = |&(budget, used): &Turn| used as f64 / budget.max(1) as f64;
+            let maxed = turns.iter().filter(|t| ratio(t) >= MAXED).count();
+            let wasted = turns.iter().filter(|t| ratio(t) < WASTED).count();
+            let consistent = |count: usize| n >= MIN_TURNS && count as f64 >= n as f64 * CONSISTENT;
+            let (verdict, suggested) = if consistent(maxed) {
+                let largest = turns.iter().map(|&(budget, _)| budget).max().unwrap_or(0);
+                (Verdict::Maxed, Some(round_budget(largest * 2)))
+            } else if consistent(wasted) {
+                // Enough for nine turns in ten
+                let mut used: Vec<u64> = turns.iter().map(|&(_, used)| used).collect();
+                used.sort_unstable();
+                let p90 = used[((n * 9).div_ceil(10)).saturating_sub(1)];
+                (Verdict::Wasted, Some(round_budget(p90)))
+            } else {
+                (Verdict::Fits, None)
+            };
+            SessionThinking {
+                session,
+                agent,
+                turns: n,
+                mean_budget: turns.iter().map(|&(b, _)| b as f64).sum::<f64>() / n as f64,
+                mean_used: turns.iter().map(|&(_, u)| u as f64).sum::<f64>() / n as f64,
+                maxed,
+                wasted,
+                verdict,
+                suggested,
+            }
+        })
+        .collect();
+    summaries.sort_by(|a, b| {
+        (a.verdict == Verdict::Fits)
+            .cmp(&(b.verdict == Verdict::Fits))
+            .then(b.turns.cmp(&a.turns))
+    });
+    summaries
+}
+
+pub async fn run(data_dir: &Path, since: Duration) -> Result<(), Box<dyn std::error::Error>> {
+    let db_path = data_dir.join("sentinel.db");
+    if !db_path.exists() {
+        println!("No events found. Run 'sentinel start' first to capture some traffic.");
+        return Ok(());
+    }
+    let storage = Storage::new(&db_path).await?;
+    let from: DateTime<Utc> = Utc::now() - since;
+    let sessions = analyze(&storage.get_events_since(from).await?);
+    if sessions.is_empty() {
+        println!(
+            "No requests with a thinking budget since {}.",
+            from.format("%Y-%m-%d %H:%M")
+        );
+        return Ok(());
+    }
+
+    println!(
+        "Thinking budgets by session (since {})\n",
+        from.format("%Y-%m-%d %H:%M")
+    );
+    println!(
+        "{:<38} {:<15} {:>5} {:>8} {:>8} {:>5} {:>6} {:>6}  verdict",
+        "session", "agent", "turns", "budget", "used", "use", "maxed", "wasted"
+    );
+    for s in &sessions {
+        let verdict = match (s.verdict, s.suggested) {
+            (Verdict::Maxed, Some(budget)) => format!("maxed; try {}", budget),
+            (Verdict::Wasted, Some(budget)) => format!("wasted; try {}", budget),
+            _ => "-".to_string(),
+        };
+        println!(
+            "{:<38} {:<15} {:>5} {:>8.0} {:>8.0} {:>4.0}% {:>6} {:>6}  {}",
+            s.session,
+            s.agent.as_deref().unwrap_or("-"),
+            s.turns,
+            s.mean_budget,
+            s.mean_used,
+            s.mean_used / s.mean_budget.max(1.0) * 100.0,
+            s.maxed,
+            s.wasted,
+            verdict
+        );
+    }
+    println!(
+        "\nused is estimated: output tokens less visible text and tool input (~{} chars per token)",
+        CHARS_PER_TOKEN
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event::UserMessage;
+    use crate::parsers::Usage;
+
+    fn turn(session: &str, budget: u64, output_tokens: i64, text: &str) -> [ObservabilityEvent; 2] {
+        let response_id = Uuid::new_v4();
+        let event = |id, payload| ObservabilityEvent {
+            seq: None,
+            id,
+            timestamp: Utc::now(),
+            session_id: Some(session.to_string()),
+            agent: Some("calm-otter".to_string()),
+            topic: None,
+            provider: None,
+            payload,
+        };
+        [
+            event(
+                Uuid::new_v4(),
+                Payload::UserMessage(UserMessage {
+                    text: "go".to_string(),
+                    response_id: Some(response_id),
+                    thinking_budget: Some(budget),
+                    ..Default::default()
+                }),
+            ),
+            event(
+                response_id,
+                Payload::AssistantResponse(AssistantResponse {
+                    streaming: true,
+                    stop_reason: Some("end_turn".to_string()),
+                    thinking: Some("...".to_string()),
+                    text: Some(text.to_string()),
+                    usage: Some(Usage {
+                        input_tokens: Some(1000),
+                        output_tokens: Some(output_tokens),
+                        cache_read_tokens: None,
+                        cache_creation_tokens: None,
+                        cost_usd: None,
+                    }),
+                    ..Default::default()
+                }),
+            ),
+        ]
+    }
+
+    #[test]
+    fn test_flags_sessions_that_max_or_waste_their_budget() {
+        let answer = "x".repeat(400); // ~100 tokens of visible text
+        let mut events = Vec::new();
+        // s1 thinks right up to its budget every time
+        for _ in 0..3 {
+            events.extend(turn("s1", 4096, 4100, &answer));
+        }
+        // s2 barely thinks
+        for used in [200, 300, 2500, 100] {
+            events.extend(turn("s2", 16_000, used + 100, &answer));
+        }
+        // s3 is in between, and s4 too short to judge
+        for used in [1000, 3000, 1500] {
+            events.extend(turn("s3", 4096, used + 100, &answer));
+        }
+        events.extend(turn("s4", 4096, 4196, &answer));
+        // A request without a budget isn't counted
+        let [mut request, response] = turn("s4", 4096, 100, &answer);
+        if let Payload::UserMessage(ref mut message) = request.payload {
+            message.thinking_budget = None;
+        }
+        events.extend([request, response]);
+
+        let sessions = analyze(&events);
+        let by_id: HashMap<&str, &SessionThinking> =
+            sessions.iter().map(|s| (s.session.as_str(), s)).collect();
+        let s1 = by_id["s1"];
+        assert_eq!(
+            (s1.verdict, s1.maxed, s1.suggested),
+            (Verdict::Maxed, 3, Some(8192))
+        );
+        assert_eq!(s1.mean_used, 4000.0);
+        let s2 = by_id["s2"];
+        assert_eq!(
+            (s2.verdict, s2.wasted, s2.suggested),
+            (Verdict::Wasted, 4, Some(3072))
+        );
+        assert_eq!(by_id["s3"].verdict, Verdict::Fits);
+        assert_eq!((by_id["s4"].turns, by_id["s4"].verdict), (1, Verdict::Fits));
+        // Flagged sessions come first
+        assert!(sessions[..2].iter().all(|s| s.verdict != Verdict::Fits));
+    }
+}
@@ -0,0 +1,391 @@
+//! Tool calls in their own table, and `sentinel tools`.
+//!
+//! Each stored response's tool calls get a `tool_calls` row in the same
+//! transaction as the event, so tool analytics don't have to parse every
+//! payload. When a later user turn carries a call's result back, the row
+//! records how the call turned out (succeeded, failed, or errored without
+//! running), the start of the result, and the event that carried it.
+
+use chrono::{DateTime, Duration, Utc};
+use sqlx::{SqliteConnection, SqlitePool};
+
+use crate::derived;
+use crate::event::{ObservabilityEvent, Payload};
+use crate::storage::Storage;
+
+/// Characters of a call's input shown in recent invocations
+const INPUT_CHARS: usize = 80;
+/// Characters of a result kept with its call; the event has all of it
+const RESULT_CHARS: usize = 2000;
+
+/// Calls to one tool over a range
+#[derive(Debug, sqlx::FromRow)]
+pub struct ToolUsage {
+    pub name: String,
+    pub calls: i64,
+    pub agents: i64,
+    /// Calls whose result has been seen
+    pub completed: i64,
+    /// Calls whose result was an error, whether or not the tool ran
+    pub failed: i64,
+    /// Of those, calls the tool never ran: rejected input, declined, interrupted
+    pub errored: i64,
+}
+
+impl ToolUsage {
+    /// Share of calls with a result that failed; None before any result
+    pub fn failure_rate(&self) -> Option<f64> {
+        (self.completed > 0).then(|| self.failed as f64 / self.completed as f64)
+    }
+}
+
+/// One recorded call
+#[derive(Debug, sqlx::FromRow)]
+pub struct ToolInvocation {
+    /// Empty when the event had no agent
+    pub agent: String,
+    pub name: String,
+    pub input: String,
+    pub timestamp: String,
+    /// succeeded, failed, or errored; None until the call's result comes back
+    pub status: Option<String>,
+}
+
+/// Record an event's tool calls, or the outcome of calls whose results it
+/// carries; part of the event's insert transaction
+pub async fn record(
+    conn: &mut SqliteConnection,
+    event: &ObservabilityEvent,
+) -> Result<(), sqlx::Error> {
+    match &event.payload {
+        Payload::AssistantResponse(response) => {
+            for call in &response.tool_calls {
+                sqlx::query(
+                    r#"
+                    INSERT OR IGNORE INTO tool_calls
+                        (event_id, tool_use_id, session_id, agent, name, input, timestamp)
+                    VALUES (?, ?, ?, ?, ?, ?, ?)
+                    "#,
+                )
+                .bind(event.id.to_string())
+                .bind(&call.id)
+                .bind(event.session_id.as_ref())
+                .bind(event.agent.as_deref().unwrap_or(""))
+                .bind(&call.name)
+                .bind(call.input.to_string())
+                .bind(event.timestamp.to_rfc3339())
+                .execute(&mut *conn)
+                .await?;
+            }
+        }
+        Payload::UserMessage(message) => {
+            for result in &message.tool_results {
+                // Calls read from OTLP logs may carry no id to match on
+                if result.tool_use_id.is_empty() {
+                    continue;
+                }
+                let kept: String = result.content.chars().take(RESULT_CHARS).collect();
+                sqlx::query(
+                    r#"
+                    UPDATE tool_calls
+                    SET failed = ?, status = ?, result = ?, result_bytes = ?,
+                        result_event_id = ?
+                    WHERE tool_use_id = ? AND session_id IS ?
+                    "#,
+                )
+                .bind(result.is_error)
+                .bind(result.status().as_str())
+                .bind(kept)
+                .bind(result.content.len() as i64)
+                .bind(event.id.to_string())
+                .bind(&result.tool_use_id)
+                .bind(event.session_id.as_ref())
+                .execute(&mut *conn)
+                .await?;
+            }
+        }
+        Payload::Alert(_)
+        | Payload::Error(_)
+        | Payload::Violation(_)
+        | Payload::SessionClosed(_)
+        | Payload::StreamDelta(_)
+        | Payload::StreamAborted(_)
+        | Payload::TurnSnapshot(_)
+        | Payload::Annotation(_) => {}
+    }
+    Ok(())
+}
+
+#[derive(Clone)]
+pub struct ToolCallStore {
+    pool: SqlitePool,
+}
+
+impl ToolCallStore {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool }
+    }
+
+    /// Create the table, rebuilding one from before results were kept on
+    /// it; true when the calls already recorded have to be read into it
+    pub async fn init_schema(&self) -> Result<bool, sqlx::Error> {
+        let created = derived::create(
+            &self.pool,
+            "tool_calls",
+            r#"
+            CREATE TABLE IF NOT EXISTS tool_calls (
+                event_id TEXT NOT NULL,
+                tool_use_id TEXT NOT NULL,
+                session_id TEXT,
+                agent TEXT NOT NULL,
+                name TEXT NOT NULL,
+                input TEXT NOT NULL,
+                timestamp TEXT NOT NULL,
+                failed INTEGER,
+                status TEXT,
+                result TEXT,
+                result_bytes INTEGER,
+                result_event_id TEXT,
+                PRIMARY KEY (event_id, tool_use_id)
+            )
+            "#,
+            |columns| !columns.iter().any(|column| column == "result_event_id"),
+        )
+        .await?;
+        sqlx::query("CREATE INDEX IF NOT EXISTS idx_tool_calls_use_id ON tool_calls(tool_use_id)")
+            .execute(&self.pool)
+            .await?;
+        sqlx::query(
+            "CREATE INDEX IF NOT EXISTS idx_tool_calls_agent ON tool_calls(agent, timestamp)",
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(created)
+    }
+
+    /// Per-tool counts for calls at or after `since`, most used first
+    pub async fn usage(
+        &self,
+        since: DateTime<Utc>,
+        agent: Option<&str>,
+    ) -> Result<Vec<ToolUsage>, sqlx::Error> {
+        sqlx::query_as(
+            r#"
+            SELECT name,
+                   COUNT(*) AS calls,
+                   COUNT(DISTINCT agent) AS agents,
+                   COUNT(failed) AS completed,
+                   COALESCE(SUM(failed), 0) AS failed,
+                   COALESCE(SUM(status = 'errored'), 0) AS errored
+            FROM tool_calls
+            WHERE timestamp >= ? AND (? IS NULL OR agent = ?)
+            GROUP BY name
+            ORDER BY calls DESC, name
+            "#,
+        )
+        .bind(since.to_rfc3339())
+        .bind(agent)
+        .bind(agent)
+        .fetch_all(&self.pool)
+        .await
+    }
+
+    /// The latest `per_agent` calls of each agent at or after `since`
+    pub async fn recent(
+        &self,
+        since: DateTime<Utc>,
+        agent: Option<&str>,
+        per_agent: i64,
+    ) -> Result<Vec<ToolInvocation>, sqlx::Error> {
+        sqlx::query_as(
+            r#"
+            SELECT agent, name, input, timestamp, status
+            FROM (
+                SELECT *, ROW_NUMBER() OVER (PARTITION BY agent ORDER BY timestamp DESC) AS n
+                FROM tool_calls
+                WHERE timestamp >= ? AND (? IS NULL OR agent = ?)
+            )
+            WHERE n <= ?
+            ORDER BY agent, timestamp DESC
+            "#,
+        )
+        .bind(since.to_rfc3339())
+        .bind(agent)
+        .bind(agent)
+        .bind(per_agent)
+        .fetch_all(&self.pool)
+        .await
+    }
+}
+
+pub async fn run(
+    data_dir: &std::path::Path,
+    since: Duration,
+    agent: Option<&str>,
+    recent: i64,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let db_path = data_dir.join("sentinel.db");
+    if !db_path.exists() {
+        println!("No events found. Run 'sentinel start' first to capture some traffic.");
+        return Ok(());
+    }
+    let storage = Storage::new(&db_path).await?;
+    let store = ToolCallStore::new(storage.pool());
+    let from = Utc::now() - since;
+    let usage = store.usage(from, agent).await?;
+    if usage.is_empty() {
+        println!("No tool calls since {}.", from.format("%Y-%m-%d %H:%M"));
+        return Ok(());
+    }
+
+    println!("Tool calls since {}\n", from.format("%Y-%m-%d %H:%M"));
+    println!(
+        "{:<28} {:>7} {:>7} {:>8} {:>8} {:>8}",
+        "tool", "calls", "agents", "failed", "errored", "rate"
+    );
+    for tool in &usage {
+        let rate = match tool.failure_rate() {
+            Some(rate) => format!("{:.1}%", rate * 100.0),
+            None => "-".to_string(),
+        };
+        let name: String = tool.name.chars().take(28).collect();
+        println!(
+            "{:<28} {:>7} {:>7} {:>8} {:>8} {:>8}",
+            name, tool.calls, tool.agents, tool.failed, tool.errored, rate
+        );
+    }
+
+    if recent <= 0 {
+        return Ok(());
+    }
+    let mut current: Option<&str> = None;
+    let invocations = store.recent(from, agent, recent).await?;
+    for call in &invocations {
+        if current != Some(call.agent.as_str()) {
+            current = Some(call.agent.as_str());
+            let name = if call.agent.is_empty() {
+                "(no agent)"
+            } else {
+                call.agent.as_str()
+            };
+            println!("\nRecent calls by {}", name);
+        }
+        let time = DateTime::parse_from_rfc3339(&call.timestamp)
+            .map(|t| t.format("%m-%d %H:%M:%S").to_string())
+            .unwrap_or_else(|_| call.timestamp.clone());
+        let outcome = match call.status.as_deref() {
+            Some("succeeded") => "ok",
+            Some(status) => status,
+            None => "pending",
+        };
+        let input: String = call.input.chars().take(INPUT_CHARS).collect();
+        let ellipsis = if input.len() < call.input.len() {
+            "…"
+        } else {
+            ""
+        };
+        println!(
+            "  {} {:<7} {} {}{}",
+            time, outcome, call.name, input, ellipsis
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event::{AssistantResponse, UserMessage};
+    use crate::parsers::{ToolCall, ToolResult};
+    use chrono::TimeZone;
+    use uuid::Uuid;
+
+    fn calls(ids: &[(&str, &str)]) -> Payload {
+        Payload::AssistantResponse(AssistantResponse {
+            streaming: true,
+            model: Some("claude-sonnet-4".to_string()),
+            stop_reason: Some("tool_use".to_string()),
+            tool_calls: ids
+                .iter()
+                .map(|(id, name)| ToolCall {
+                    id: id.to_string(),
+                    name: name.to_string(),
+                    input: serde_json::json!({"command": "ls"}),
+                })
+                .collect(),
+            ..Default::default()
+        })
+    }
+
+    fn results(outcomes: &[(&str, &str, bool)]) -> Payload {
+        Payload::UserMessage(UserMessage {
+            text: String::new(),
+            tool_results: outcomes
+                .iter()
+                .map(|(id, content, is_error)| ToolResult {
+                    tool_use_id: id.to_string(),
+                    content: content.to_string(),
+                    is_error: *is_error,
+                })
+                .collect(),
+            ..Default::default()
+        })
+    }
+
+    #[tokio::test]
+    async fn test_tool_calls_record_outcomes() {
+        let path = std::env::temp_dir().join(format!("sentinel-test-{}.db", Uuid::new_v4()));
+        let storage = Storage::new(&path).await.unwrap();
+        let not_found = "<tool_use_error>File does not exist.</tool_use_error>";
+        let outcomes = results(&[("t1", "Exit code 1", true), ("t2", not_found, true)]);
+        let answered = ObservabilityEvent::for_tests(2, outcomes);
+        for event in [
+            ObservabilityEvent::for_tests(1, calls(&[("t1", "Bash"), ("t2", "Read")])),
+            answered.clone(),
+            ObservabilityEvent::for_tests(3, calls(&[("t3", "Bash")])),
+            ObservabilityEvent::for_tests(4, results(&[("t3", "src\nCargo.toml", false)])),
+            ObservabilityEvent::for_tests(5, calls(&[("t4", "Bash")])),
+            // Another session's result, under an id this one used too
+            ObservabilityEvent {
+                session_id: Some("s2".to_string()),
+                ..ObservabilityEvent::for_tests(6, results(&[("t4", "done", false)]))
+            },
+        ] {
+            storage.insert_observability_event(&event).await.unwrap();
+        }
+
+        let store = ToolCallStore::new(storage.pool());
+        let since = Utc.with_ymd_and_hms(2026, 1, 1, 0, 0, 0).unwrap();
+        let usage = store.usage(since, None).await.unwrap();
+        assert_eq!(usage[0].name, "Bash");
+        assert_eq!(
+            (usage[0].calls, usage[0].completed, usage[0].failed),
+            (3, 2, 1)
+        );
+        assert_eq!(usage[0].failure_rate(), Some(0.5));
+        assert_eq!(usage[0].errored, 0);
+        // The read never ran, so it errored rather than failed
+        assert_eq!(
+            (usage[1].name.as_str(), usage[1].failed, usage[1].errored),
+            ("Read", 1, 1)
+        );
+
+        let recent = store.recent(since, Some("calm-otter"), 2).await.unwrap();
+        let names: Vec<(&str, Option<&str>)> = recent
+            .iter()
+            .map(|c| (c.name.as_str(), c.status.as_deref()))
+            .collect();
+        assert_eq!(names, vec![("Bash", None), ("Bash", Some("succeeded"))]);
+
+        let (result, result_event_id): (String, String) = sqlx::query_as(
+            "SELECT result, result_event_id FROM tool_calls WHERE tool_use_id = 't1'",
+        )
+        .fetch_one(&storage.pool())
+        .await
+        .unwrap();
+        assert_eq!(result, "Exit code 1");
+        assert_eq!(result_event_id, answered.id.to_string());
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,337 @@
+//! `sentinel session`: a recorded session read back as a conversation.
+//!
+//! Stitches the session's user turns, the tool results they carried, and the
+//! assistant's thinking, text, and tool calls into one chronological
+//! transcript. Long tool inputs are shortened, and long tool results folded
+//! to their first and last lines; `--expand <tool_use_id>` prints one in
+//! full from the event log. A retried turn is marked rather than repeated,
+//! and an edited or rewound turn opens a numbered branch. Sessions recorded
+//! with `--worktree-snapshots` end with their working tree's net change.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write;
+use std::path::Path;
+
+use crate::event::{BranchKind, ObservabilityEvent, Payload};
+use crate::fold;
+use crate::parsers::ToolStatus;
+use crate::storage::Storage;
+use crate::worktree::{self, Worktrees};
+
+/// Characters of a tool call's input shown before it is cut off
+const INPUT_CHARS: usize = 200;
+
+pub async fn run(
+    data_dir: &Path,
+    target: &str,
+    expand: Option<&str>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let db_path = data_dir.join("sentinel.db");
+    if !db_path.exists() {
+        return Err("No recorded traffic. Run 'sentinel start' first.".into());
+    }
+    let storage = Storage::new(&db_path).await?;
+    let events = storage.get_session_or_agent_events(target).await?;
+    if events.is_empty() {
+        return Err(format!("No session or agent named '{}'", target).into());
+    }
+    match expand {
+        Some(id) => {
+            let result = events
+                .iter()
+                .filter_map(|event| match &event.payload {
+                    Payload::UserMessage(msg) => Some(&msg.tool_results),
+                    _ => None,
+                })
+                .flatten()
+                .find(|result| result.tool_use_id == id)
+                .ok_or_else(|| format!("No tool result {} in '{}'", id, target))?;
+            println!("{}", result.content.trim_end());
+        }
+        None => {
+            let mut out = render(&events, target);
+            let worktrees = Worktrees::new(storage.pool());
+            // Each session once, in the order they first appear
+            let mut seen = HashSet::new();
+            let sessions = events
+                .iter()
+                .filter_map(|e| e.session_id.as_deref())
+                .filter(|session| seen.insert(*session));
+            for session in sessions {
+                let snapshots = worktrees.for_session(session).await?;
+                worktree::report(&mut out, &snapshots, &events);
+            }
+            print!("{}", out);
+        }
+    }
+    Ok(())
+}
+
+fn render(events: &[ObservabilityEvent], target: &str) -> String {
+    let mut out = String::new();
+    // Tool names by call id, so results can say which tool produced them
+    let mut tools: HashMap<&str, &str> = HashMap::new();
+    let mut session: Option<&str> = None;
+    // When each event happened, for pointing branches back at their origin
+    let mut times = HashMap::new();
+    let mut branches = 0;
+
+    for event in events {
+        if session != event.session_id.as_deref() || out.is_empty() {
+            session = event.session_id.as_deref();
+            if !out.is_empty() {
+                out.push('\n');
+            }
+            let _ = writeln!(
+                out,
+                "═══ Session {} · agent {} · started {}",
+                session.unwrap_or("(none)"),
+                event.agent.as_deref().unwrap_or("-"),
+                event.timestamp.format("%Y-%m-%d %H:%M:%S UTC")
+            );
+        }
+        let time = event.timestamp.format("%H:%M:%S");
+        times.insert(event.id, event.timestamp);
+        match &event.payload {
+            Payload::UserMessage(msg) => {
+                let origin = |from| {
+                    times
+                        .get(from)
+                        .map(|t: &chrono::DateTime<chrono::Utc>| t.format("%H:%M:%S").to_string())
+                        .unwrap_or_else(|| "an earlier turn".to_string())
+                };
+                match msg.branch {
+                    Some(ref branch) if branch.kind == BranchKind::Retry => {
+                        // The same request again; only the answer is new
+                        let from = origin(&branch.from);
+                        let _ = writeln!(out, "\n[{}] User · retry of {}", time, from);
+                        continue;
+                    }
+                    Some(ref branch) => {
+                        branches += 1;
+                        let _ = writeln!(
+                            out,
+                            "\n⑂ Branch {} from {}, keeping the first {} message{}",
+                            branches,
+                            origin(&branch.from),
+                            branch.shared,
+                            if branch.shared == 1 { "" } else { "s" }
+                        );
+                        let _ = writeln!(out, "\n[{}] User", time);
+                    }
+                    None => {
+                        let _ = writeln!(out, "\n[{}] User", time);
+                    }
+                }
+                for result in &msg.tool_results {
+                    let tool = tools
+                        .get(result.tool_use_id.as_str())
+                        .copied()
+                        .unwrap_or("tool");
+                    let status = match result.status() {
+                        ToolStatus::Succeeded => String::new(),
+                        status => format!(", {}", status.as_str()),
+                    };
+                    let _ = writeln!(
+                        out,
+                        "  ↳ {} result ({}{})",
+                        tool, result.tool_use_id, status
+                    );
+                    push_folded(&mut out, &result.content, &result.tool_use_id, target);
+                }
+                if !msg.text.trim().is_empty() {
+                    push_indented(&mut out, &msg.text, "  ");
+                }
+            }
+            Payload::AssistantResponse(resp) => {
+                let _ = writeln!(
+                    out,
+                    "\n[{}] Assistant · {}",
+                    time,
+                    resp.model.as_deref().unwrap_or("-")
+                );
+                if let Some(ref thinking) = resp.thinking {
+                    out.push_str("  (thinking)\n");
+                    push_indented(&mut out, thinking, "  │ ");
+                }
+                if let Some(ref text) = resp.text {
+                    push_indented(&mut out, text, "  ");
+                }
+                for call in &resp.tool_calls {
+                    tools.insert(&call.id, &call.name);
+                    let input = call.input.to_string();
+                    let shown: String = input.chars().take(INPUT_CHARS).collect();
+                    let ellipsis = if shown.len() < input.len() { "…" } else { "" };
+                    let _ = writeln!(out, "  → {} {}{}", call.name, shown, ellipsis);
+                }
+            }
+            Payload::Alert(alert) => {
+                let _ = writeln!(out, "\n[{}] ! {}: {}", time, alert.kind, alert.message);
+            }
+            Payload::Error(error) => {
+                let _ = writeln!(out, "\n[{}] ✗ {}", time, error.message);
+            }
+            Payload::Violation(violation) => {
+                let _ = writeln!(
+                    out,
+                    "\n[{}] ⚠ {}: {}{}",
+                    time,
+                    violation.source,
+                    violation.message,
+                    if violation.blocked { " (blocked)" } else { "" }
+                );
+            }
+            Payload::SessionClosed(closed) => {
+                let _ = writeln!(
+                    out,
+                    "\n[{}] ■ Session closed after {}m {}s, {} turns, ${:.4}",
+                    time,
+                    closed.duration_secs / 60,
+                    closed.duration_secs % 60,
+                    closed.turns,
+                    closed.cost_usd
+                );
+            }
+            Payload::StreamAborted(aborted) => {
+                let _ = writeln!(
+                    out,
+                    "\n[{}] ✂ Client disconnected after {} bytes",
+                    time, aborted.received_bytes
+                );
+            }
+            Payload::TurnSnapshot(snapshot) => {
+                let _ = writeln!(
+                    out,
+                    "\n[{}] ⏱ Turn still running after {:.1}s, {} bytes received",
+                    time,
+                    snapshot.elapsed_ms as f64 / 1000.0,
+                    snapshot.received_bytes
+                );
+            }
+            Payload::Annotation(annotation) => {
+                let _ = writeln!(out, "\n[{}] ◆ {}", time, annotation.describe());
+            }
+            Payload::StreamDelta(_) => {}
+        }
+    }
+    out
+}
+
+fn push_indented(out: &mut String, text: &str, indent: &str) {
+    for line in text.trim_end().lines() {
+        out.push_str(indent);
+        out.push_str(line);
+        out.push('\n');
+    }
+}
+
+/// A tool result's first and last lines, with a marker saying how much was
+/// folded and how to see all of it
+fn push_folded(out: &mut String, content: &str, tool_use_id: &str, target: &str) {
+    let folded = fold::fold(content);
+    let expand = format!(
+        "({} in all; sentinel session {} --expand {})",
+        fold::size(content.len()),
+        target,
+        tool_use_id
+    );
+    for line in &folded.head {
+        let _ = writeln!(out, "    {}", line);
+    }
+    if folded.hidden_lines > 0 {
+        let _ = writeln!(out, "    … {} lines folded {}", folded.hidden_lines, expand);
+    }
+    for line in &folded.tail {
+        let _ = writeln!(out, "    {}", line);
+    }
+    if folded.clipped && folded.hidden_lines == 0 {
+        let _ = writeln!(out, "    … long lines cut short {}", expand);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event::{AssistantResponse, Branch, UserMessage};
+    use crate::parsers::{ToolCall, ToolResult};
+
+    #[test]
+    fn test_render_pairs_tool_calls_with_results() {
+        let events = vec![
+            ObservabilityEvent::for_tests(1, Payload::UserMessage(UserMessage {
+                    model: Some("claude-sonnet-4".to_string()),
+                    text: "Fix the failing test".to_string(),
+                    ..Default::default()
+                }),
+            ),
+            ObservabilityEvent::for_tests(4, Payload::AssistantResponse(AssistantResponse {
+                    streaming: true,
+                    model: Some("claude-sonnet-4".to_string()),
+                    stop_reason: Some("tool_use".to_string()),
+                    thinking: Some("Run the tests first".to_string()),
+                    text: Some("Let me look.".to_string()),
+                    tool_calls: vec![ToolCall {
+                        id: "t1".to_string(),
+                        name: "Bash".to_string(),
+                        input: serde_json::json!({"command": "cargo test"}),
+                    }],
+                    ..Default::default()
+                }),
+            ),
+            ObservabilityEvent::for_tests(9, Payload::UserMessage(UserMessage {
+                    model: Some("claude-sonnet-4".to_string()),
+                    text: String::new(),
+                    tool_results: vec![ToolResult {
+                        tool_use_id: "t1".to_string(),
+                        content: (1..=20).map(|i| format!("line {}\n", i)).collect(),
+                        is_error: false,
+                    }],
+                    response_id: None,
+                    project: None,
+                    run_id: None,
+                    thinking_budget: None,
+                    branch: None,
+                }),
+            ),
+        ];
+
+        let transcript = render(&events, "calm-otter");
+        assert!(transcript.starts_with("═══ Session s1 · agent calm-otter"));
+        assert!(transcript.contains("[10:00:01] User\n  Fix the failing test\n"));
+        assert!(transcript.contains("  (thinking)\n  │ Run the tests first\n  Let me look.\n"));
+        assert!(transcript.contains("  → Bash {\"command\":\"cargo test\"}\n"));
+        assert!(transcript.contains("  ↳ Bash result (t1)\n    line 1\n"));
+        assert!(transcript.contains(
+            "    line 8\n    … 8 lines folded (151 B in all; \
+             sentinel session calm-otter --expand t1)\n    line 17\n"
+        ));
+        assert!(transcript.ends_with("    line 20\n"));
+    }
+
+    #[test]
+    fn test_render_marks_retries_and_numbers_branches() {
+        let user = |text: &str, branch: Option<Branch>| {
+            Payload::UserMessage(UserMessage {
+                text: text.to_string(),
+                branch,
+                ..Default::default()
+            })
+        };
+        let first = ObservabilityEvent::for_tests(1, user("Fix the failing test", None));
+        let branch = |kind, shared| Some(Branch { kind, from: first.id, shared });
+        let retry = user("Fix the failing test", branch(BranchKind::Retry, 1));
+        let fork = user("Now fix the flaky one", branch(BranchKind::Fork, 1));
+        let events = vec![
+            ObservabilityEvent::for_tests(2, retry),
+            ObservabilityEvent::for_tests(3, fork),
+        ];
+        let events: Vec<ObservabilityEvent> = std::iter::once(first).chain(events).collect();
+
+        let transcript = render(&events, "calm-otter");
+        assert!(transcript.contains("[10:00:02] User · retry of 10:00:01\n\n⑂"));
+        assert!(transcript.contains(
+            "⑂ Branch 1 from 10:00:01, keeping the first 1 message\n\n\
+             [10:00:03] User\n  Now fix the flaky one\n"
+        ));
+    }
+}
@@ -0,0 +1,267 @@
+//! Desktop notifications for agents waiting on their user (`--notify-waiting`).
+//!
+//! An agent is taken to be waiting when its last response ended the turn with
+//! a question, or stopped to call a tool Claude Code asks permission for, and
+//! nothing has followed it for the given time. Then a notification names the
+//! agent, its topic, and what it is waiting on. A request that continues the
+//! conversation (more messages than the one answered) means the user is back
+//! and cancels it; the short side requests clients send in between (titles,
+//! topics) don't.
+//!
+//! A Bash call Claude Code was allowed to run sends nothing until it
+//! finishes, so a notification for one waits out the command's timeout too:
+//! a build still going isn't taken for a permission prompt.
+//!
+//! Notifications go through the desktop's notification service (D-Bus on
+//! Linux and BSD, Notification Center on macOS, toasts on Windows). Where
+//! there is none, the waiting agent is only logged.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::event::AssistantResponse;
+use crate::parsers::ToolCall;
+
+/// Tools Claude Code asks before running, by default
+const PERMISSION_TOOLS: &[&str] = &[
+    "Bash",
+    "Edit",
+    "MultiEdit",
+    "NotebookEdit",
+    "WebFetch",
+    "Write",
+];
+/// Longest question quoted in a notification
+const MAX_QUESTION_LEN: usize = 160;
+/// How long Claude Code lets a Bash command run when the call doesn't say
+const BASH_TIMEOUT: Duration = Duration::from_secs(2 * 60);
+
+/// What an agent is waiting on
+#[derive(Debug, Clone, PartialEq)]
+pub enum Waiting {
+    /// The question the turn ended with
+    Answer(String),
+    /// The tool it wants to run, and how long the tool may run for if it
+    /// was allowed to
+    Permission(String, Duration),
+}
+
+impl Waiting {
+    /// A response's, if it leaves the agent waiting on its user
+    pub fn after(response: &AssistantResponse) -> Option<Self> {
+        match response.stop_reason.as_deref()? {
+            "end_turn" => {
+                let text = response.text.as_deref()?;
+                let last = text.lines().map(str::trim).rfind(|l| !l.is_empty())?;
+                last.ends_with('?').then(|| {
+                    let question: String = last.chars().take(MAX_QUESTION_LEN).collect();
+                    Self::Answer(question)
+                })
+            }
+            "tool_use" => response
+                .tool_calls
+                .iter()
+                .find(|call| PERMISSION_TOOLS.contains(&call.name.as_str()))
+                .map(|call| Self::Permission(call.name.clone(), runs_for(call))),
+            _ => None,
+        }
+    }
+
+    fn describe(&self) -> String {
+        match self {
+            Self::Answer(question) => format!("Asked: {}", question),
+            Self::Permission(tool, _) => {
+                format!("May be waiting for permission to run {}", tool)
+            }
+        }
+    }
+
+    /// How long to wait for a follow-up before notifying
+    fn delay(&self, delay: Duration) -> Duration {
+        match self {
+            Self::Answer(_) => delay,
+            Self::Permission(_, runs_for) => delay.max(*runs_for),
+        }
+    }
+}
+
+/// Longest a tool call can keep the agent quiet once it's allowed to run
+fn runs_for(call: &ToolCall) -> Duration {
+    if call.name != "Bash" || call.input["run_in_background"] == true {
+        return Duration::ZERO;
+    }
+    call.input["timeout"]
+        .as_u64()
+        .map(Duration::from_millis)
+        .unwrap_or(BASH_TIMEOUT)
+}
+
+struct Pending {
+    token: u64,
+    /// Messages in the request the response answered
+    messages: usize,
+}
+
+pub struct WaitingWatch {
+    delay: Duration,
+    pending: Mutex<HashMap<String, Pending>>,
+    next_token: AtomicU64,
+}
+
+impl WaitingWatch {
+    pub fn new(delay: Duration) -> Self {
+        Self {
+            delay,
+            pending: Mutex::new(HashMap::new()),
+            next_token: AtomicU64::new(0),
+        }
+    }
+
+    /// A request from an agent, carrying this many messages
+    pub fn request(&self, agent: &str, messages: usize) {
+        let mut pending = self.lock();
+        if pending.get(agent).is_some_and(|p| messages > p.messages) {
+            pending.remove(agent);
+        }
+    }
+
+    /// A response to a request of this many messages: notify if the agent is
+    /// still waiting on it once the delay has passed
+    pub fn response(
+        self: &Arc<Self>,
+        agent: &str,
+        topic: Option<String>,
+        messages: usize,
+        response: &AssistantResponse,
+    ) {
+        let Some(waiting) = Waiting::after(response) else {
+            self.lock().remove(agent);
+            return;
+        };
+        let token = self.next_token.fetch_add(1, Ordering::Relaxed);
+        self.lock()
+            .insert(agent.to_string(), Pending { token, messages });
+
+        let watch = self.clone();
+        let agent = agent.to_string();
+        tokio::spawn(async move {
+            tokio::time::sleep(waiting.delay(watch.delay)).await;
+            if !watch.take(&agent, token) {
+                return;
+            }
+            let title = format!("{} is waiting for you", agent);
+            let body = match topic {
+                Some(topic) => format!("{}\n{}", topic, waiting.describe()),
+                None => waiting.describe(),
+            };
+            tracing::info!("🔔 {}: {}", title, waiting.describe());
+            if let Err(e) = tokio::task::spawn_blocking(move || notify(&title, &body)).await {
+                tracing::warn!("Failed to send desktop notification: {}", e);
+            }
+        });
+    }
+
+    /// Whether the notification with this token is still due, clearing it
+    fn take(&self, agent: &str, token: u64) -> bool {
+        let mut pending = self.lock();
+        if pending.get(agent).is_some_and(|p| p.token == token) {
+            pending.remove(agent);
+            true
+        } else {
+            false
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<String, Pending>> {
+        self.pending.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+fn notify(title: &str, body: &str) {
+    let shown = notify_rust::Notification::new()
+        .appname("sentinel")
+        .summary(title)
+        .body(body)
+        .show();
+    if let Err(e) = shown {
+        tracing::warn!("Desktop notification failed: {}", e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn response(stop_reason: &str, text: &str, tools: &[&str]) -> AssistantResponse {
+        AssistantResponse {
+            streaming: true,
+            stop_reason: Some(stop_reason.to_string()),
+            text: Some(text.to_string()),
+            tool_calls: tools
+                .iter()
+                .map(|name| ToolCall {
+                    id: "t1".to_string(),
+                    name: name.to_string(),
+                    input: serde_json::json!({}),
+                })
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_questions_and_permission_tools_leave_agent_waiting() {
+        let asked = response(
+            "end_turn",
+            "Done with the parser.\n\nShould I update the tests too?\n",
+            &[],
+        );
+        assert_eq!(
+            Waiting::after(&asked),
+            Some(Waiting::Answer(
+                "Should I update the tests too?".to_string()
+            ))
+        );
+        assert_eq!(
+            Waiting::after(&response("end_turn", "All done.", &[])),
+            None
+        );
+        let bash = Waiting::after(&response("tool_use", "", &["Read", "Bash"])).unwrap();
+        assert_eq!(bash, Waiting::Permission("Bash".to_string(), BASH_TIMEOUT));
+        assert_eq!(Waiting::after(&response("tool_use", "", &["Read"])), None);
+
+        // A Bash call that may still be running isn't notified before its timeout
+        let delay = Duration::from_secs(30);
+        assert_eq!(bash.delay(delay), BASH_TIMEOUT);
+        let mut call = ToolCall {
+            id: "t1".to_string(),
+            name: "Bash".to_string(),
+            input: serde_json::json!({"command": "cargo build", "timeout": 10_000}),
+        };
+        assert_eq!(runs_for(&call), Duration::from_secs(10));
+        call.input["run_in_background"] = true.into();
+        assert_eq!(runs_for(&call), Duration::ZERO);
+        let edit = Waiting::after(&response("tool_use", "", &["Edit"])).unwrap();
+        assert_eq!(edit.delay(delay), delay);
+    }
+
+    #[tokio::test]
+    async fn test_continuing_request_cancels_notification() {
+        let watch = Arc::new(WaitingWatch::new(Duration::from_secs(60)));
+        let asked = response("end_turn", "Proceed?", &[]);
+        watch.response("calm-otter", None, 3, &asked);
+        // A side request with fewer messages leaves it pending
+        watch.request("calm-otter", 1);
+        assert!(watch.lock().contains_key("calm-otter"));
+        watch.request("calm-otter", 5);
+        assert!(!watch.lock().contains_key("calm-otter"));
+
+        watch.response("calm-otter", None, 5, &asked);
+        let token = watch.lock()["calm-otter"].token;
+        // A later response that doesn't wait replaces it
+        watch.response("calm-otter", None, 7, &response("end_turn", "Done.", &[]));
+        assert!(!watch.take("calm-otter", token));
+    }
+}
@@ -0,0 +1,382 @@
+//! `sentinel watch`: a terminal dashboard of live agents.
+//!
+//! The left pane lists every agent with its status, model, what it is doing
+//! right now, and its token usage; the right pane is the selected agent's
+//! event feed. It starts from the most recent recorded events and then
+//! follows the same live feed as `sentinel tail`.
+
+use std::collections::{HashSet, VecDeque};
+use std::path::Path;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, Borders, List, ListItem, ListState, Paragraph};
+use ratatui::{DefaultTerminal, Frame};
+use tokio::sync::mpsc;
+use uuid::Uuid;
+
+use crate::agent::AgentStore;
+use crate::event::{ObservabilityEvent, Payload};
+use crate::storage::Storage;
+use crate::tail;
+
+/// Recorded events loaded before following the live feed
+const BACKFILL_EVENTS: i64 = 1000;
+/// Events kept per agent for the feed
+const FEED_LEN: usize = 500;
+/// Agents quiet for longer than this are shown as inactive
+const INACTIVE_AFTER: chrono::Duration = chrono::Duration::minutes(5);
+/// How often the screen redraws when nothing happens, so statuses age
+const REDRAW_EVERY: Duration = Duration::from_secs(1);
+
+pub async fn run(data_dir: &Path, port: u16) -> Result<(), Box<dyn std::error::Error>> {
+    // Follow first so nothing recorded during the backfill is missed
+    let mut events = tail::follow(data_dir, port, None).await?;
+
+    let mut dashboard = Dashboard::default();
+    let db_path = data_dir.join("sentinel.db");
+    if db_path.exists() {
+        let storage = Storage::new(&db_path).await?;
+        let agent_store = AgentStore::new(storage.pool());
+        agent_store.init_schema().await?;
+        for agent in agent_store.list_all().await? {
+            dashboard.agent_mut(&agent.name).last_seen = Some(agent.last_seen_at);
+        }
+        let mut recent = storage
+            .get_recent_observability_events(BACKFILL_EVENTS)
+            .await?;
+        recent.reverse();
+        for event in recent {
+            let id = event.id;
+            dashboard.apply(event);
+            dashboard.backfilled.insert(id);
+        }
+        dashboard
+            .agents
+            .sort_by_key(|a| std::cmp::Reverse(a.last_seen));
+    }
+
+    let mut terminal = ratatui::init();
+    let mut keys = spawn_key_reader();
+    let result = event_loop(&mut terminal, &mut dashboard, &mut events, &mut keys).await;
+    ratatui::restore();
+    result
+}
+
+async fn event_loop(
+    terminal: &mut DefaultTerminal,
+    dashboard: &mut Dashboard,
+    events: &mut mpsc::Receiver<ObservabilityEvent>,
+    keys: &mut mpsc::Receiver<KeyCode>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut redraw = tokio::time::interval(REDRAW_EVERY);
+    let mut connected = true;
+    loop {
+        terminal.draw(|frame| draw(frame, dashboard, connected))?;
+        tokio::select! {
+            received = events.recv(), if connected => match received {
+                Some(event) => dashboard.apply(event),
+                None => connected = false,
+            },
+            key = keys.recv() => match key {
+                Some(KeyCode::Char('q')) | Some(KeyCode::Esc) | None => return Ok(()),
+                Some(KeyCode::Up) | Some(KeyCode::Char('k')) => dashboard.select(-1),
+                Some(KeyCode::Down) | Some(KeyCode::Char('j')) => dashboard.select(1),
+                Some(KeyCode::PageUp) => dashboard.scroll_by(10),
+                Some(KeyCode::PageDown) => dashboard.scroll_by(-10),
+                Some(KeyCode::End) => dashboard.scroll = 0,
+                Some(_) => {}
+            },
+            _ = redraw.tick() => {}
+        }
+    }
+}
+
+/// Reads keystrokes on a blocking thread; closes the channel on Ctrl-C
+fn spawn_key_reader() -> mpsc::Receiver<KeyCode> {
+    let (tx, rx) = mpsc::channel(16);
+    std::thread::spawn(move || loop {
+        let key = match event::read() {
+            Ok(Event::Key(key)) if key.kind == KeyEventKind::Press => key,
+            Ok(_) => continue,
+            Err(e) => {
+                tracing::warn!("Failed to read the terminal: {}", e);
+                return;
+            }
+        };
+        if key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL) {
+            return;
+        }
+        if tx.blocking_send(key.code).is_err() {
+            return;
+        }
+    });
+    rx
+}
+
+#[derive(Default)]
+struct Dashboard {
+    agents: Vec<AgentView>,
+    selected: usize,
+    /// Lines scrolled up from the newest event in the feed
+    scroll: usize,
+    /// Events already shown from the backfill, in case the live feed repeats them
+    backfilled: HashSet<Uuid>,
+}
+
+#[derive(Default)]
+struct AgentView {
+    name: String,
+    model: Option<String>,
+    activity: String,
+    requests: u64,
+    input_tokens: i64,
+    output_tokens: i64,
+    last_seen: Option<DateTime<Utc>>,
+    feed: VecDeque<ObservabilityEvent>,
+}
+
+impl Dashboard {
+    fn apply(&mut self, event: ObservabilityEvent) {
+        if self.backfilled.remove(&event.id) {
+            return;
+        }
+        let Some(name) = event.agent.clone() else {
+            return;
+        };
+        let agent = self.agent_mut(&name);
+        agent.last_seen = Some(event.timestamp);
+        match &event.payload {
+            Payload::UserMessage(msg) => {
+                agent.requests += 1;
+                agent.model = msg.model.clone().or(agent.model.take());
+                agent.activity = "waiting on the model".to_string();
+            }
+            Payload::AssistantResponse(resp) => {
+                agent.model = resp.model.clone().or(agent.model.take());
+                if let Some(ref usage) = resp.usage {
+                    agent.input_tokens += usage.input_tokens.unwrap_or(0);
+                    agent.output_tokens += usage.output_tokens.unwrap_or(0);
+                }
+                agent.activity = match resp.tool_calls.last() {
+                    Some(call) => format!("running {}", call.name),
+                    None => "idle".to_string(),
+                };
+            }
+            Payload::Alert(alert) => agent.activity = format!("alert: {}", alert.kind),
+            Payload::Error(_) => agent.activity = "request failed".to_string(),
+            Payload::Violation(violation) if violation.blocked => {
+                agent.activity = format!("blocked: {}", violation.tool)
+            }
+            Payload::Violation(_) => {}
+            Payload::SessionClosed(closed) => {
+                // Recorded once the agent has gone quiet; it wasn't seen then
+                agent.last_seen = Some(closed.ended_at);
+                agent.activity = "session closed".to_string();
+            }
+            Payload::StreamDelta(_) => agent.activity = "writing".to_string(),
+            Payload::StreamAborted(_) => agent.activity = "disconnected".to_string(),
+            Payload::TurnSnapshot(snapshot) => {
+                agent.activity = format!("slow turn ({}s)", snapshot.elapsed_ms / 1000)
+            }
+            Payload::Annotation(annotation) => {
+                if let Some(ref phase) = annotation.phase {
+                    agent.activity = phase.clone();
+                }
+            }
+        }
+        agent.feed.push_back(event);
+        if agent.feed.len() > FEED_LEN {
+            agent.feed.pop_front();
+        }
+    }
+
+    fn agent_mut(&mut self, name: &str) -> &mut AgentView {
+        let index = match self.agents.iter().position(|a| a.name == name) {
+            Some(index) => index,
+            None => {
+                self.agents.push(AgentView {
+                    name: name.to_string(),
+                    ..Default::default()
+                });
+                self.agents.len() - 1
+            }
+        };
+        &mut self.agents[index]
+    }
+
+    fn select(&mut self, step: isize) {
+        if self.agents.is_empty() {
+            return;
+        }
+        let last = self.agents.len() - 1;
+        self.selected = self.selected.saturating_add_signed(step).min(last);
+        self.scroll = 0;
+    }
+
+    fn scroll_by(&mut self, lines: isize) {
+        let max = self
+            .agents
+            .get(self.selected)
+            .map_or(0, |a| a.feed.len().saturating_sub(1));
+        self.scroll = self.scroll.saturating_add_signed(lines).min(max);
+    }
+}
+
+impl AgentView {
+    fn is_active(&self, now: DateTime<Utc>) -> bool {
+        self.last_seen
+            .is_some_and(|seen| now - seen <= INACTIVE_AFTER)
+    }
+}
+
+fn draw(frame: &mut Frame, dashboard: &Dashboard, connected: bool) {
+    let [main, footer] =
+        Layout::vertical([Constraint::Min(3), Constraint::Length(1)]).areas(frame.area());
+    let [left, right] =
+        Layout::horizontal([Constraint::Percentage(40), Constraint::Percentage(60)]).areas(main);
+    let now = Utc::now();
+
+    let items: Vec<ListItem> = dashboard
+        .agents
+        .iter()
+        .map(|agent| {
+            let (dot, color) = if agent.is_active(now) {
+                ("●", Color::Green)
+            } else {
+                ("○", Color::DarkGray)
+            };
+            ListItem::new(vec![
+                Line::from(vec![
+                    Span::styled(format!("{} ", dot), Style::default().fg(color)),
+                    Span::styled(
+                        agent.name.clone(),
+                        Style::default().add_modifier(Modifier::BOLD),
+                    ),
+                    Span::raw(format!("  {}", agent.model.as_deref().unwrap_or("-"))),
+                ]),
+                Line::from(format!(
+                    "  {}  ·  {} req  ·  {} in / {} out",
+                    if agent.activity.is_empty() {
+                        "-"
+                    } else {
+                        &agent.activity
+                    },
+                    agent.requests,
+                    agent.input_tokens,
+                    agent.output_tokens
+                )),
+            ])
+        })
+        .collect();
+    let mut list_state = ListState::default().with_selected(Some(dashboard.selected));
+    frame.render_stateful_widget(
+        List::new(items)
+            .block(Block::default().borders(Borders::ALL).title(" Agents "))
+            .highlight_style(Style::default().bg(Color::DarkGray)),
+        left,
+        &mut list_state,
+    );
+
+    let (title, lines) = match dashboard.agents.get(dashboard.selected) {
+        Some(agent) => {
+            // Newest at the bottom, lifted by the scroll offset
+            let visible = right.height.saturating_sub(2) as usize;
+            let end = agent.feed.len().saturating_sub(dashboard.scroll);
+            let start = end.saturating_sub(visible);
+            let lines: Vec<Line> = agent
+                .feed
+                .range(start..end)
+                .map(|event| Line::from(tail::format_line(event)))
+                .collect();
+            (format!(" {} ", agent.name), lines)
+        }
+        None => (
+            " Events ".to_string(),
+            vec![Line::from("No agents yet. Waiting for traffic…")],
+        ),
+    };
+    frame.render_widget(
+        Paragraph::new(lines).block(Block::default().borders(Borders::ALL).title(title)),
+        right,
+    );
+
+    let status = if connected { "live" } else { "feed closed" };
+    frame.render_widget(
+        Paragraph::new(format!(
+            " {}  ·  ↑/↓ agent  ·  PgUp/PgDn scroll  ·  End newest  ·  q quit",
+            status
+        ))
+        .style(Style::default().fg(Color::DarkGray)),
+        footer,
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event::{AssistantResponse, UserMessage};
+    use crate::parsers::{ToolCall, Usage};
+
+    fn event(agent: &str, payload: Payload) -> ObservabilityEvent {
+        ObservabilityEvent {
+            timestamp: Utc::now(),
+            session_id: None,
+            agent: Some(agent.to_string()),
+            ..ObservabilityEvent::for_tests(0, payload)
+        }
+    }
+
+    #[test]
+    fn test_apply_tracks_activity_and_tokens() {
+        let mut dashboard = Dashboard::default();
+        dashboard.apply(event(
+            "calm-otter",
+            Payload::UserMessage(UserMessage {
+                model: Some("claude-sonnet-4".to_string()),
+                text: "Fix the tests".to_string(),
+                ..Default::default()
+            }),
+        ));
+        assert_eq!(dashboard.agents[0].activity, "waiting on the model");
+
+        let response = event(
+            "calm-otter",
+            Payload::AssistantResponse(AssistantResponse {
+                streaming: true,
+                model: Some("claude-sonnet-4".to_string()),
+                stop_reason: Some("tool_use".to_string()),
+                tool_calls: vec![ToolCall {
+                    id: "t1".to_string(),
+                    name: "Bash".to_string(),
+                    input: serde_json::json!({}),
+                }],
+                usage: Some(Usage {
+                    input_tokens: Some(1200),
+                    output_tokens: Some(40),
+                    cache_read_tokens: None,
+                    cache_creation_tokens: None,
+                    cost_usd: None,
+                }),
+                ..Default::default()
+            }),
+        );
+        // The live feed repeating a backfilled event doesn't count it twice
+        let id = response.id;
+        dashboard.apply(response.clone());
+        dashboard.backfilled.insert(id);
+        dashboard.apply(response);
+
+        let agent = &dashboard.agents[0];
+        assert_eq!(agent.activity, "running Bash");
+        assert_eq!(agent.requests, 1);
+        assert_eq!((agent.input_tokens, agent.output_tokens), (1200, 40));
+        assert_eq!(agent.feed.len(), 2);
+        assert!(dashboard.backfilled.is_empty());
+        assert!(agent.is_active(Utc::now()));
+    }
+}
@@ -0,0 +1,244 @@
+//! Turn-duration watchdog (`--turn-sla`).
+//!
+//! Every forwarded request is watched from the moment it goes upstream. One
+//! that is still going when the limit passes gets a `turn_snapshot` event
+//! recording what it was doing: the request it carried, how far the response
+//! got, and how long the upstream has taken so far. That is the state worth
+//! having when a turn hangs for ten minutes and then finishes or fails as if
+//! nothing happened. With `--turn-sla-alert` an alert is raised too, which
+//! goes wherever alerts go (the dashboard, `--smtp-url`).
+//!
+//! A turn is snapshotted once; it is recorded as usual when it ends.
+
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::event::{TurnPhase, TurnSnapshot};
+
+pub const TURN_SLA_ALERT_KIND: &str = "turn_sla_exceeded";
+
+/// What `--turn-sla` and `--turn-sla-alert` ask for
+#[derive(Debug, Clone, Copy)]
+pub struct TurnSla {
+    pub limit: Duration,
+    /// Raise an alert as well as recording the snapshot
+    pub alert: bool,
+}
+
+/// What the snapshot says about the request, fixed once it is sent
+#[derive(Debug, Clone)]
+pub struct TurnRequest {
+    pub response_id: uuid::Uuid,
+    pub model: Option<String>,
+    pub streaming: bool,
+    pub request_bytes: u64,
+    pub messages: Option<u64>,
+    pub upstream_host: Option<String>,
+}
+
+#[derive(Debug, Default)]
+struct Progress {
+    attempts: u32,
+    first_byte: Option<Duration>,
+    first_token: Option<Duration>,
+    streaming: bool,
+    received: u64,
+    chunks: u64,
+    last_chunk: Option<Duration>,
+}
+
+/// How far a turn has got, updated as it goes and read by its watchdog
+pub struct TurnProgress {
+    started: Instant,
+    progress: Mutex<Progress>,
+}
+
+impl TurnProgress {
+    pub fn new() -> Self {
+        Self {
+            started: Instant::now(),
+            progress: Mutex::default(),
+        }
+    }
+
+    fn update(&self, f: impl FnOnce(&mut Progress)) {
+        if let Ok(mut progress) = self.progress.lock() {
+            f(&mut progress);
+        }
+    }
+
+    /// A request (or a retry of it) was sent
+    pub fn attempt(&self) {
+        self.update(|p| p.attempts += 1);
+    }
+
+    /// Response headers arrived
+    pub fn headers(&self, streaming: bool) {
+        let at = self.started.elapsed();
+        self.update(|p| {
+            p.first_byte = Some(at);
+            p.streaming = streaming;
+        });
+    }
+
+    /// A chunk of a streamed response arrived; `first_token` is when content
+    /// first appeared, once it has
+    pub fn chunk(&self, len: usize, first_token: Option<Duration>) {
+        let at = self.started.elapsed();
+        self.update(|p| {
+            p.received += len as u64;
+            p.chunks += 1;
+            p.last_chunk = Some(at);
+            p.first_token = first_token;
+        });
+    }
+
+    /// The turn as it stands, `limit` into it or later
+    pub fn snapshot(&self, request: &TurnRequest, limit: Duration) -> TurnSnapshot {
+        let elapsed = self.started.elapsed();
+        let p = self.progress.lock().unwrap_or_else(|e| e.into_inner());
+        let phase = match (p.first_byte, p.streaming) {
+            (None, _) => TurnPhase::WaitingForHeaders,
+            (Some(_), true) => TurnPhase::Streaming,
+            (Some(_), false) => TurnPhase::ReadingBody,
+        };
+        let ms = |d: Duration| d.as_millis() as u64;
+        let quiet_since = p.last_chunk.or(p.first_byte).unwrap_or_default();
+        TurnSnapshot {
+            response_id: request.response_id,
+            sla_ms: ms(limit),
+            elapsed_ms: ms(elapsed),
+            phase,
+            model: request.model.clone(),
+            streaming: request.streaming,
+            request_bytes: request.request_bytes,
+            messages: request.messages,
+            upstream_host: request.upstream_host.clone(),
+            attempts: p.attempts,
+            first_byte_ms: p.first_byte.map(ms),
+            first_token_ms: p.first_token.map(ms),
+            received_bytes: p.received,
+            chunks: p.chunks,
+            idle_ms: ms(elapsed.saturating_sub(quiet_since)),
+        }
+    }
+}
+
+/// A turn's progress, and its watchdog while one is set; dropping it when
+/// the turn ends stops the watchdog
+pub struct TurnWatch {
+    progress: Arc<TurnProgress>,
+    task: Option<tokio::task::JoinHandle<()>>,
+}
+
+impl TurnWatch {
+    /// Progress tracking without a watchdog
+    pub fn unwatched() -> Self {
+        Self {
+            progress: Arc::new(TurnProgress::new()),
+            task: None,
+        }
+    }
+
+    /// Call `overrun` with the progress if the turn outlasts `limit`
+    pub fn new<F, Fut>(limit: Duration, overrun: F) -> Self
+    where
+        F: FnOnce(Arc<TurnProgress>) -> Fut,
+        Fut: std::future::Future<Output = ()> + Send + 'static,
+    {
+        let progress = Arc::new(TurnProgress::new());
+        let fire = overrun(progress.clone());
+        let task = tokio::spawn(async move {
+            tokio::time::sleep(limit).await;
+            // Detached, so a turn ending meanwhile doesn't cut the snapshot off
+            tokio::spawn(fire);
+        });
+        Self {
+            progress,
+            task: Some(task),
+        }
+    }
+
+    pub fn progress(&self) -> &TurnProgress {
+        &self.progress
+    }
+}
+
+impl Drop for TurnWatch {
+    fn drop(&mut self) {
+        if let Some(ref task) = self.task {
+            task.abort();
+        }
+    }
+}
+
+/// One line on a snapshot, for the log and the alert
+pub fn describe(snapshot: &TurnSnapshot) -> String {
+    let secs = |ms: u64| format!("{:.1}s", ms as f64 / 1000.0);
+    let progress = match snapshot.phase {
+        TurnPhase::WaitingForHeaders => "no response yet".to_string(),
+        TurnPhase::ReadingBody => format!(
+            "headers after {}, body still coming",
+            secs(snapshot.first_byte_ms.unwrap_or_default())
+        ),
+        TurnPhase::Streaming => format!(
+            "{} bytes in {} chunks, last {} ago",
+            snapshot.received_bytes,
+            snapshot.chunks,
+            secs(snapshot.idle_ms)
+        ),
+    };
+    format!(
+        "Turn {} ({}) still running after {} (limit {}): {}",
+        snapshot.response_id,
+        snapshot.model.as_deref().unwrap_or("unknown model"),
+        secs(snapshot.elapsed_ms),
+        secs(snapshot.sla_ms),
+        progress
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_watchdog_snapshots_a_turn_that_overruns_and_not_one_that_ends() {
+        let request = TurnRequest {
+            response_id: uuid::Uuid::new_v4(),
+            model: Some("claude-sonnet-4".to_string()),
+            streaming: true,
+            request_bytes: 2048,
+            messages: Some(12),
+            upstream_host: Some("api.anthropic.com".to_string()),
+        };
+        let limit = Duration::from_millis(50);
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+
+        let fired = |tx: tokio::sync::mpsc::UnboundedSender<TurnSnapshot>| {
+            let request = request.clone();
+            move |progress: Arc<TurnProgress>| async move {
+                let _ = tx.send(progress.snapshot(&request, limit));
+            }
+        };
+        let watch = TurnWatch::new(limit, fired(tx.clone()));
+        watch.progress().attempt();
+        watch.progress().headers(true);
+        watch.progress().chunk(100, None);
+        watch.progress().chunk(60, Some(Duration::from_millis(1)));
+
+        let snapshot = rx.recv().await.unwrap();
+        assert_eq!(snapshot.phase, TurnPhase::Streaming);
+        assert_eq!((snapshot.received_bytes, snapshot.chunks), (160, 2));
+        assert_eq!(snapshot.attempts, 1);
+        assert_eq!(snapshot.first_token_ms, Some(1));
+        assert!(snapshot.elapsed_ms >= 50);
+        assert!(describe(&snapshot).contains("160 bytes in 2 chunks"));
+        drop(watch);
+
+        // Ended inside the limit, so nothing fires
+        drop(TurnWatch::new(limit, fired(tx)));
+        tokio::time::sleep(limit * 2).await;
+        assert!(rx.try_recv().is_err());
+    }
+}
@@ -0,0 +1,561 @@
+//! Working tree snapshots around sessions (`--worktree-snapshots`).
+//!
+//! When a session makes its first request from a directory in a git
+//! repository, the repository's HEAD, `git status`, and uncommitted changes
+//! are recorded; when the session closes (`--close-idle-after`), they are
+//! recorded again, with the changes diffed against the HEAD it started at so
+//! commits made along the way count. `sentinel session` sets the net change
+//! beside the files the session's tool calls said they edited: files changed
+//! without a tool call (by a Bash command, say), and files a call claimed but
+//! that came out the same.
+
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::fmt::Write;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use chrono::{DateTime, Utc};
+use sqlx::SqlitePool;
+use tracing::warn;
+
+use crate::agent::Agent;
+use crate::event::{ObservabilityEvent, Payload};
+use crate::files;
+use crate::git;
+
+/// Git's empty tree, to diff against in a repository without commits
+const EMPTY_TREE: &str = "4b825dc642cb6eb9a060e54bf8d69288fbee4904";
+
+const START: &str = "start";
+const END: &str = "end";
+
+/// A repository as a session found or left it
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct Snapshot {
+    pub session_id: String,
+    /// `start` or `end`
+    pub phase: String,
+    pub taken_at: String,
+    /// Top of the repository
+    pub dir: String,
+    /// Full sha of HEAD; None before the first commit
+    pub head: Option<String>,
+    /// `git status --porcelain`
+    pub status: String,
+    /// `git diff --numstat`: uncommitted changes at the start, and
+    /// everything since the start's HEAD at the end
+    pub numstat: String,
+}
+
+/// Take a snapshot of the repository `dir` is in; None outside of one
+async fn take(dir: &str, base: Option<&str>) -> Option<(String, Option<String>, String, String)> {
+    if !Path::new(dir).is_dir() {
+        return None;
+    }
+    let top = git::git(dir, &["rev-parse", "--show-toplevel"]).await?;
+    let head = git::git(&top, &["rev-parse", "HEAD"]).await;
+    let status = git::output(&top, &["status", "--porcelain"]).await.unwrap_or_default();
+    let base = base.or(head.as_deref()).unwrap_or(EMPTY_TREE);
+    let numstat = git::output(&top, &["diff", "--numstat", base])
+        .await
+        .unwrap_or_default();
+    Some((top, head, status, numstat))
+}
+
+/// Snapshots taken and read back; sessions whose start is already recorded
+/// are kept in memory so most requests skip the database
+pub struct Worktrees {
+    pool: SqlitePool,
+    started: Mutex<HashSet<String>>,
+}
+
+impl Worktrees {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self {
+            pool,
+            started: Mutex::new(HashSet::new()),
+        }
+    }
+
+    pub async fn init_schema(&self) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS worktree_snapshots (
+                session_id TEXT NOT NULL,
+                agent TEXT NOT NULL,
+                phase TEXT NOT NULL,
+                taken_at TEXT NOT NULL,
+                dir TEXT NOT NULL,
+                head TEXT,
+                status TEXT NOT NULL,
+                numstat TEXT NOT NULL
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+        sqlx::query(
+            "CREATE INDEX IF NOT EXISTS idx_worktree_snapshots_session
+             ON worktree_snapshots(session_id, taken_at)",
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Every snapshot of a session, in order; none in a database that has
+    /// never taken one
+    pub async fn for_session(&self, session_id: &str) -> Result<Vec<Snapshot>, sqlx::Error> {
+        let exists: bool = sqlx::query_scalar(
+            "SELECT EXISTS (SELECT 1 FROM sqlite_master
+             WHERE type = 'table' AND name = 'worktree_snapshots')",
+        )
+        .fetch_one(&self.pool)
+        .await?;
+        if !exists {
+            return Ok(Vec::new());
+        }
+        sqlx::query_as(
+            r#"
+            SELECT session_id, phase, taken_at, dir, head, status, numstat
+            FROM worktree_snapshots
+            WHERE session_id = ?
+            ORDER BY taken_at, rowid
+            "#,
+        )
+        .bind(session_id)
+        .fetch_all(&self.pool)
+        .await
+    }
+
+    async fn latest(&self, session_id: &str) -> Result<Option<Snapshot>, sqlx::Error> {
+        sqlx::query_as(
+            r#"
+            SELECT session_id, phase, taken_at, dir, head, status, numstat
+            FROM worktree_snapshots
+            WHERE session_id = ?
+            ORDER BY taken_at DESC, rowid DESC
+            LIMIT 1
+            "#,
+        )
+        .bind(session_id)
+        .fetch_optional(&self.pool)
+        .await
+    }
+
+    async fn record(
+        &self,
+        agent: &Agent,
+        phase: &str,
+        base: Option<&str>,
+        dir: &str,
+    ) -> Result<bool, sqlx::Error> {
+        let Some((top, head, status, numstat)) = take(dir, base).await else {
+            return Ok(false);
+        };
+        sqlx::query(
+            r#"
+            INSERT INTO worktree_snapshots
+                (session_id, agent, phase, taken_at, dir, head, status, numstat)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?)
+            "#,
+        )
+        .bind(&agent.session_id)
+        .bind(&agent.name)
+        .bind(phase)
+        .bind(Utc::now().to_rfc3339())
+        .bind(top)
+        .bind(head)
+        .bind(status)
+        .bind(numstat)
+        .execute(&self.pool)
+        .await?;
+        Ok(true)
+    }
+
+    /// Snapshot the repository in `dir` if the agent's session hasn't been
+    /// since it last closed, in the background
+    pub fn start(self: &Arc<Self>, agent: &Agent, dir: String) {
+        if !self
+            .started
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(agent.session_id.clone())
+        {
+            return;
+        }
+        let worktrees = self.clone();
+        let agent = agent.clone();
+        tokio::spawn(async move {
+            let started = match worktrees.latest(&agent.session_id).await {
+                Ok(latest) => latest.is_some_and(|s| s.phase == START),
+                Err(e) => {
+                    warn!("Failed to read working tree snapshots of {}: {}", agent.name, e);
+                    return;
+                }
+            };
+            if started {
+                return;
+            }
+            // Outside a repository nothing is recorded, and it's tried
+            // again only once the session closes and comes back
+            if let Err(e) = worktrees.record(&agent, START, None, &dir).await {
+                warn!("Failed to snapshot {}'s working tree: {}", agent.name, e);
+            }
+        });
+    }
+
+    /// Snapshot the repository a closing session started in, against the
+    /// HEAD it started at
+    pub async fn end(&self, agent: &Agent) -> Result<(), sqlx::Error> {
+        self.started
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(&agent.session_id);
+        let Some(start) = self.latest(&agent.session_id).await? else {
+            return Ok(());
+        };
+        if start.phase != START {
+            return Ok(());
+        }
+        let base = start.head.as_deref().unwrap_or(EMPTY_TREE);
+        self.record(agent, END, Some(base), &start.dir).await?;
+        Ok(())
+    }
+}
+
+/// A path as git prints it: in double quotes with C-style escapes when it
+/// has unusual characters, such as spaces or non-ASCII ones
+fn unquote(path: &str) -> String {
+    let Some(quoted) = path.strip_prefix('"').and_then(|p| p.strip_suffix('"')) else {
+        return path.to_string();
+    };
+    let mut bytes = Vec::with_capacity(quoted.len());
+    let mut chars = quoted.bytes().peekable();
+    while let Some(b) = chars.next() {
+        if b != b'\\' {
+            bytes.push(b);
+            continue;
+        }
+        match chars.next() {
+            Some(b'n') => bytes.push(b'\n'),
+            Some(b't') => bytes.push(b'\t'),
+            // Bytes outside ASCII, as three octal digits
+            Some(d @ b'0'..=b'3') => {
+                let mut byte = d - b'0';
+                for _ in 0..2 {
+                    if let Some(d) = chars.next_if(|d| (b'0'..=b'7').contains(d)) {
+                        byte = byte * 8 + (d - b'0');
+                    }
+                }
+                bytes.push(byte);
+            }
+            Some(other) => bytes.push(other),
+            None => {}
+        }
+    }
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+/// The new name in a `--numstat` rename, `old => new` or with what the two
+/// share outside braces, `src/{old.rs => new.rs}`
+fn renamed(path: &str) -> String {
+    let Some((old, new)) = path.split_once(" => ") else {
+        return path.to_string();
+    };
+    match (old.rsplit_once('{'), new.split_once('}')) {
+        (Some((prefix, _)), Some((new, suffix))) => {
+            // An empty side leaves a doubled separator, `src/{ => lib}/a.rs`
+            format!("{}{}{}", prefix, new, suffix).replace("//", "/")
+        }
+        _ => new.to_string(),
+    }
+}
+
+/// Lines added and removed per path in `git diff --numstat`; None for a
+/// binary file
+fn numstat(numstat: &str) -> BTreeMap<String, Option<(u64, u64)>> {
+    numstat
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.splitn(3, '\t');
+            let (added, removed, path) = (fields.next()?, fields.next()?, fields.next()?);
+            let lines = added.parse().ok().zip(removed.parse().ok());
+            Some((renamed(&unquote(path)), lines))
+        })
+        .collect()
+}
+
+/// Untracked paths in `git status --porcelain`
+fn untracked(status: &str) -> BTreeSet<String> {
+    status
+        .lines()
+        .filter_map(|line| line.strip_prefix("?? "))
+        .map(unquote)
+        .collect()
+}
+
+/// Every path `git status --porcelain` lists, the new name of a rename
+fn dirty(status: &str) -> BTreeSet<String> {
+    status
+        .lines()
+        .filter_map(|line| line.get(3..))
+        .map(|path| unquote(path.rsplit(" -> ").next().unwrap_or(path)))
+        .collect()
+}
+
+/// Files the session's tool calls changed between `from` and `to`, relative
+/// to the repository at `dir`
+fn claimed(
+    events: &[ObservabilityEvent],
+    dir: &str,
+    from: &str,
+    to: Option<&str>,
+) -> BTreeSet<String> {
+    let parse = |t: &str| DateTime::parse_from_rfc3339(t).ok().map(|t| t.with_timezone(&Utc));
+    let (from, to) = (parse(from), to.and_then(parse));
+    let within = |e: &&ObservabilityEvent| {
+        from.is_none_or(|from| e.timestamp >= from) && to.is_none_or(|to| e.timestamp <= to)
+    };
+    events
+        .iter()
+        .filter(within)
+        .filter_map(|e| match &e.payload {
+            Payload::AssistantResponse(response) => Some(&response.tool_calls),
+            _ => None,
+        })
+        .flatten()
+        .filter_map(files::file_change)
+        .map(|change| {
+            Path::new(&change.path)
+                .strip_prefix(dir)
+                .map(|p| p.display().to_string())
+                .unwrap_or(change.path)
+        })
+        .collect()
+}
+
+fn short(head: Option<&str>) -> &str {
+    head.map_or("(no commits)", |h| &h[..h.len().min(7)])
+}
+
+/// The net change each start and end pair shows, against what the
+/// session's tool calls claimed
+pub fn report(out: &mut String, snapshots: &[Snapshot], events: &[ObservabilityEvent]) {
+    let mut snapshots = snapshots.iter().peekable();
+    while let Some(start) = snapshots.next() {
+        if start.phase != START {
+            continue;
+        }
+        let end = snapshots.next_if(|s| s.phase == END);
+        let events: Vec<ObservabilityEvent> = events
+            .iter()
+            .filter(|e| e.session_id.as_deref() == Some(start.session_id.as_str()))
+            .cloned()
+            .collect();
+        let ended_at = end.map(|e| e.taken_at.as_str());
+        let claimed = claimed(&events, &start.dir, &start.taken_at, ended_at);
+        let dirty_at_start = dirty(&start.status);
+
+        let Some(end) = end else {
+            let _ = writeln!(
+                out,
+                "\n── Working tree {} at {}; the session hasn't closed, \
+                 so there's no net change yet",
+                start.dir,
+                short(start.head.as_deref())
+            );
+            if !dirty_at_start.is_empty() {
+                let _ = writeln!(out, "  Uncommitted at start: {}", join(&dirty_at_start));
+            }
+            continue;
+        };
+        let _ = writeln!(
+            out,
+            "\n── Working tree {}: {} at start → {} when closed",
+            start.dir,
+            short(start.head.as_deref()),
+            short(end.head.as_deref())
+        );
+
+        let mut changed: BTreeMap<String, String> = numstat(&end.numstat)
+            .into_iter()
+            .map(|(path, lines)| {
+                let lines = lines.map_or("binary".to_string(), |(a, r)| format!("+{} -{}", a, r));
+                (path, lines)
+            })
+            .collect();
+        let new_untracked = untracked(&end.status);
+        let old_untracked = untracked(&start.status);
+        for path in new_untracked.difference(&old_untracked) {
+            changed.insert(path.clone(), "untracked".to_string());
+        }
+        if changed.is_empty() {
+            let _ = writeln!(out, "  Net change: none");
+        } else {
+            let (added, removed) = numstat(&end.numstat)
+                .values()
+                .flatten()
+                .fold((0, 0), |(a, r), (x, y)| (a + x, r + y));
+            let _ = writeln!(
+                out,
+                "  Net change: {} file{}, +{} -{}",
+                changed.len(),
+                if changed.len() == 1 { "" } else { "s" },
+                added,
+                removed
+            );
+            let width = changed.keys().map(|p| p.len()).max().unwrap_or(0);
+            for (path, lines) in &changed {
+                let mut notes = Vec::new();
+                if !claimed.contains(path) {
+                    notes.push("no tool call");
+                }
+                if dirty_at_start.contains(path) {
+                    notes.push("uncommitted at start");
+                }
+                let notes = if notes.is_empty() {
+                    String::new()
+                } else {
+                    format!("  ({})", notes.join(", "))
+                };
+                let _ = writeln!(out, "    {:<width$}  {}{}", path, lines, notes, width = width);
+            }
+        }
+        let unchanged: BTreeSet<String> = claimed
+            .into_iter()
+            .filter(|path| !changed.contains_key(path) && !Path::new(path).is_absolute())
+            .collect();
+        if !unchanged.is_empty() {
+            let _ = writeln!(out, "  Claimed by tool calls, but unchanged: {}", join(&unchanged));
+        }
+    }
+}
+
+fn join(paths: &BTreeSet<String>) -> String {
+    paths.iter().map(String::as_str).collect::<Vec<_>>().join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event::AssistantResponse;
+    use crate::parsers::ToolCall;
+    use std::process::{Command, Stdio};
+    use uuid::Uuid;
+
+    fn run(dir: &Path, args: &[&str]) -> bool {
+        Command::new("git")
+            .arg("-C")
+            .arg(dir)
+            .args(["-c", "user.name=t", "-c", "user.email=t@t"])
+            .args(args)
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .is_ok_and(|status| status.success())
+    }
+
+    fn edit(dir: &Path, file: &str) -> ObservabilityEvent {
+        ObservabilityEvent {
+            timestamp: Utc::now(),
+            ..ObservabilityEvent::for_tests(0, Payload::AssistantResponse(AssistantResponse {
+                tool_calls: vec![ToolCall {
+                    id: "toolu_1".to_string(),
+                    name: "Edit".to_string(),
+                    input: serde_json::json!({
+                        "file_path": dir.join(file).display().to_string(),
+                        "old_string": "a",
+                        "new_string": "b",
+                    }),
+                }],
+                ..Default::default()
+            }))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_net_change_is_set_beside_what_tool_calls_claimed() {
+        let root = std::env::temp_dir().join(format!("sentinel-test-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&root).unwrap();
+        if !run(&root, &["init", "-q", "-b", "main"]) {
+            std::fs::remove_dir_all(&root).unwrap();
+            eprintln!("skipping test_net_change_is_set_beside_what_tool_calls_claimed: no git");
+            return;
+        }
+        let root = root.canonicalize().unwrap();
+        std::fs::write(root.join("lib.rs"), "a\n").unwrap();
+        std::fs::write(root.join("notes.md"), "draft\n").unwrap();
+        assert!(run(&root, &["add", "."]));
+        assert!(run(&root, &["commit", "-q", "-m", "first"]));
+        std::fs::write(root.join("notes.md"), "draft\nmore\n").unwrap();
+
+        let pool = SqlitePool::connect("sqlite::memory:").await.unwrap();
+        let worktrees = Worktrees::new(pool);
+        // Reading a database that never took a snapshot leaves it as it was
+        assert!(worktrees.for_session("s1").await.unwrap().is_empty());
+        worktrees.init_schema().await.unwrap();
+        let agent = Agent {
+            id: Uuid::new_v4(),
+            name: "calm-otter".to_string(),
+            session_id: "s1".to_string(),
+            working_directory: None,
+            created_at: Utc::now(),
+            last_seen_at: Utc::now(),
+            status: crate::agent::AgentStatus::Active,
+            topic: None,
+            tags: Vec::new(),
+            project: None,
+            git: None,
+            parent_id: None,
+        };
+        let dir = root.display().to_string();
+        assert!(worktrees.record(&agent, START, None, &dir).await.unwrap());
+        assert!(!worktrees.record(&agent, START, None, "/no/such/dir").await.unwrap());
+
+        // The agent edits and commits lib.rs; a Bash command touches Cargo.lock
+        let events = vec![edit(&root, "lib.rs"), edit(&root, "main.rs")];
+        std::fs::write(root.join("lib.rs"), "b\n").unwrap();
+        assert!(run(&root, &["commit", "-q", "-am", "edit"]));
+        std::fs::write(root.join("Cargo.lock"), "lock\n").unwrap();
+        worktrees.end(&agent).await.unwrap();
+
+        let snapshots = worktrees.for_session("s1").await.unwrap();
+        assert_eq!(snapshots.len(), 2);
+        let mut out = String::new();
+        report(&mut out, &snapshots, &events);
+        assert!(out.contains("  Net change: 3 files, +2 -1\n"), "{}", out);
+        assert!(out.contains("    Cargo.lock  untracked  (no tool call)\n"), "{}", out);
+        assert!(out.contains("    lib.rs      +1 -1\n"), "{}", out);
+        assert!(
+            out.contains("    notes.md    +1 -0  (no tool call, uncommitted at start)\n"),
+            "{}",
+            out
+        );
+        assert!(out.ends_with("  Claimed by tool calls, but unchanged: main.rs\n"), "{}", out);
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_quoted_and_renamed_paths_are_read_as_named() {
+        let numstat = numstat(
+            "1\t0\tsrc/{old.rs => new.rs}\n\
+             2\t1\tsrc/{ => util}/io.rs\n\
+             -\t-\tlogo.png => assets/logo.png\n\
+             3\t3\t\"docs/caf\\303\\251 menu.md\"\n",
+        );
+        let paths: Vec<&str> = numstat.keys().map(String::as_str).collect();
+        assert_eq!(
+            paths,
+            ["assets/logo.png", "docs/café menu.md", "src/new.rs", "src/util/io.rs"]
+        );
+        assert_eq!(numstat["assets/logo.png"], None);
+
+        let status = "R  old.rs -> \"new \\\"name\\\".rs\"\n?? \"my notes.md\"\n M lib.rs\n";
+        assert_eq!(
+            dirty(status).into_iter().collect::<Vec<_>>(),
+            ["lib.rs", "my notes.md", "new \"name\".rs"]
+        );
+        assert_eq!(untracked(status).into_iter().collect::<Vec<_>>(), ["my notes.md"]);
+    }
+}
@@ -10,6 +10,9 @@ interface Agent {
   last_seen_at: string;
   status: 'active' | 'inactive';
   topic: string | null;
+  tags: string[];
+  project: string | null;
+  parent_id: string | null;
 }
 
 function timeAgo(dateStr: string): string {
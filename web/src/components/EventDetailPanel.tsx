@@ -1,5 +1,5 @@
-import { useMemo } from 'react';
-import type { ObservabilityEvent } from '../hooks/useSSE';
+import { useMemo, useState } from 'react';
+import type { FoldNote, ObservabilityEvent, ToolResult } from '../hooks/useSSE';
 
 interface EventDetailPanelProps {
   event: ObservabilityEvent;
@@ -11,9 +11,98 @@ const PANEL_WIDTH = 400;
 const PANEL_MAX_HEIGHT = 500;
 const VIEWPORT_PADDING = 16;
 
+function formatSize(bytes: number): string {
+  if (bytes < 1024) return `${bytes} B`;
+  if (bytes < 1024 * 1024) return `${(bytes / 1024).toFixed(1)} KB`;
+  return `${(bytes / (1024 * 1024)).toFixed(1)} MB`;
+}
+
+// A tool result, folded to its first and last lines until expanded
+function ToolResultBlock({
+  eventId,
+  result,
+  folded,
+}: {
+  eventId: string;
+  result: ToolResult;
+  folded?: FoldNote;
+}) {
+  const [full, setFull] = useState<string | null>(null);
+  const [loading, setLoading] = useState(false);
+  const lines = result.content.split('\n');
+
+  const expand = () => {
+    setLoading(true);
+    fetch(
+      `/api/events/${encodeURIComponent(eventId)}/tool_results/${encodeURIComponent(result.tool_use_id)}`
+    )
+      .then((res) => res.json())
+      .then((data: ToolResult) => setFull(data.content))
+      .catch((err) => console.error('Failed to fetch tool result:', err))
+      .finally(() => setLoading(false));
+  };
+
+  const preStyle = {
+    margin: 0,
+    whiteSpace: 'pre-wrap' as const,
+    wordBreak: 'break-word' as const,
+    fontSize: '11px',
+    color: result.is_error ? '#f87171' : '#888',
+    lineHeight: '1.4',
+  };
+
+  return (
+    <div
+      style={{
+        padding: '12px',
+        backgroundColor: '#252525',
+        borderRadius: '6px',
+        marginBottom: '8px',
+        maxHeight: '300px',
+        overflow: 'auto',
+      }}
+    >
+      {full !== null || !folded ? (
+        <pre style={preStyle}>{full ?? result.content}</pre>
+      ) : (
+        <>
+          <pre style={preStyle}>{lines.slice(0, folded.at).join('\n')}</pre>
+          <button
+            onClick={expand}
+            disabled={loading}
+            style={{
+              margin: '6px 0',
+              background: 'none',
+              border: '1px dashed #555',
+              borderRadius: '4px',
+              color: '#a78bfa',
+              cursor: 'pointer',
+              fontFamily: 'monospace',
+              fontSize: '11px',
+              padding: '2px 8px',
+            }}
+          >
+            {loading
+              ? 'Loading…'
+              : folded.hidden_lines > 0
+                ? `Expand ${folded.hidden_lines} more lines (${formatSize(folded.bytes)} in all)`
+                : `Expand long lines (${formatSize(folded.bytes)} in all)`}
+          </button>
+          {folded.at < lines.length && (
+            <pre style={preStyle}>{lines.slice(folded.at).join('\n')}</pre>
+          )}
+        </>
+      )}
+    </div>
+  );
+}
+
 export function EventDetailPanel({ event, position, onClose }: EventDetailPanelProps) {
   const payload = event.payload;
   const isUserMessage = payload.type === 'user_message';
+  const isAnnotation = payload.type === 'annotation';
+  const title = isUserMessage ? 'Request' : isAnnotation ? 'Annotation' : 'Response';
+  const titleColor = isUserMessage ? '#4ade80' : isAnnotation ? '#f472b6' : '#60a5fa';
 
   // Clamp position to keep panel within viewport
   const clampedPosition = useMemo(() => {
@@ -73,8 +162,8 @@ export function EventDetailPanel({ event, position, onClose }: EventDetailPanelP
         }}
       >
         <div style={{ fontFamily: 'monospace', fontSize: '13px' }}>
-          <span style={{ color: isUserMessage ? '#4ade80' : '#60a5fa', fontWeight: 'bold' }}>
-            {isUserMessage ? 'Request' : 'Response'}
+          <span style={{ color: titleColor, fontWeight: 'bold' }}>
+            {title}
           </span>
           <span style={{ color: '#888', marginLeft: '12px' }}>
             {new Date(event.timestamp).toLocaleString()}
@@ -131,6 +220,49 @@ export function EventDetailPanel({ event, position, onClose }: EventDetailPanelP
           </div>
         )}
 
+        {payload.type === 'annotation' && (
+          <div>
+            <div style={{ color: '#f472b6', marginBottom: '8px', fontWeight: 'bold' }}>
+              {payload.phase ? `Phase: ${payload.phase}` : 'Note'}
+            </div>
+            {(payload.message || payload.data != null) && (
+              <pre
+                style={{
+                  margin: 0,
+                  padding: '12px',
+                  backgroundColor: '#252525',
+                  borderRadius: '6px',
+                  whiteSpace: 'pre-wrap',
+                  wordBreak: 'break-word',
+                  maxHeight: '300px',
+                  overflow: 'auto',
+                  lineHeight: '1.5',
+                }}
+              >
+                {[payload.message, payload.data != null && JSON.stringify(payload.data, null, 2)]
+                  .filter(Boolean)
+                  .join('\n\n')}
+              </pre>
+            )}
+          </div>
+        )}
+
+        {payload.type === 'user_message' && payload.tool_results.length > 0 && (
+          <div>
+            <div style={{ color: '#a78bfa', marginBottom: '8px', fontWeight: 'bold' }}>
+              Tool results ({payload.tool_results.length})
+            </div>
+            {payload.tool_results.map((result) => (
+              <ToolResultBlock
+                key={result.tool_use_id}
+                eventId={event.id}
+                result={result}
+                folded={event.folds?.[result.tool_use_id]}
+              />
+            ))}
+          </div>
+        )}
+
         {payload.type === 'assistant_response' && payload.thinking && (
           <div>
             <div style={{ color: '#f59e0b', marginBottom: '8px', fontWeight: 'bold' }}>
@@ -35,9 +35,11 @@ const COLLAPSED_TOPIC_HEIGHT = TOPIC_HEADER_HEIGHT + TOPIC_PADDING;
 interface EventFlowInnerProps {
   events: ObservabilityEvent[];
   followLatest: boolean;
+  viewerCursors?: Map<string, string[]>;
+  onSelect?: (eventId: string | null) => void;
 }
 
-function EventFlowInner({ events, followLatest }: EventFlowInnerProps) {
+function EventFlowInner({ events, followLatest, viewerCursors, onSelect }: EventFlowInnerProps) {
   const [selectedEventId, setSelectedEventId] = useState<string | null>(null);
   const [collapsedTopics, setCollapsedTopics] = useState<Set<string>>(new Set());
   const [viewport, setViewport] = useState<Viewport>({ x: 0, y: 0, zoom: 1 });
@@ -231,7 +233,11 @@ function EventFlowInner({ events, followLatest }: EventFlowInnerProps) {
           id: nodeId,
           type: 'event',
           position: { x: -NODE_WIDTH / 2, y: yOffset },
-          data: { event, isLatest: event.id === awaitingInputEventId } as EventNodeData,
+          data: {
+            event,
+            isLatest: event.id === awaitingInputEventId,
+            viewers: viewerCursors?.get(event.id),
+          } as EventNodeData,
           draggable: false,
         });
 
@@ -334,7 +340,11 @@ function EventFlowInner({ events, followLatest }: EventFlowInnerProps) {
               position: { x: TOPIC_PADDING, y: topicYOffset },
               parentId: topicGroupId,
               extent: 'parent',
-              data: { event, isLatest: event.id === awaitingInputEventId } as EventNodeData,
+              data: {
+                event,
+                isLatest: event.id === awaitingInputEventId,
+                viewers: viewerCursors?.get(event.id),
+              } as EventNodeData,
               draggable: false,
             });
 
@@ -362,7 +372,7 @@ function EventFlowInner({ events, followLatest }: EventFlowInnerProps) {
     }
 
     return { nodes, edges, totalHeight: yOffset };
-  }, [groupedEvents, events, collapsedTopics]);
+  }, [groupedEvents, events, collapsedTopics, viewerCursors]);
 
   // Filter nodes and edges to only visible ones (virtualization)
   const { visibleNodes, visibleEdges } = useMemo(() => {
@@ -449,6 +459,11 @@ function EventFlowInner({ events, followLatest }: EventFlowInnerProps) {
     }
   }, [events, nodes, followLatest, setCenter, getAbsolutePosition]);
 
+  // Let the agent's other viewers see which event this one has open
+  useEffect(() => {
+    onSelect?.(selectedEventId);
+  }, [selectedEventId, onSelect]);
+
   const selectedEvent = useMemo(() => {
     if (!selectedEventId) return null;
     return events.find((e) => e.id === selectedEventId) ?? null;
@@ -542,12 +557,19 @@ function EventFlowInner({ events, followLatest }: EventFlowInnerProps) {
 interface EventFlowProps {
   events: ObservabilityEvent[];
   followLatest: boolean;
+  viewerCursors?: Map<string, string[]>;
+  onSelect?: (eventId: string | null) => void;
 }
 
-export function EventFlow({ events, followLatest }: EventFlowProps) {
+export function EventFlow({ events, followLatest, viewerCursors, onSelect }: EventFlowProps) {
   return (
     <ReactFlowProvider>
-      <EventFlowInner events={events} followLatest={followLatest} />
+      <EventFlowInner
+        events={events}
+        followLatest={followLatest}
+        viewerCursors={viewerCursors}
+        onSelect={onSelect}
+      />
     </ReactFlowProvider>
   );
 }
@@ -1,12 +1,28 @@
-import { useState, useEffect } from 'react';
+import { useState, useEffect, useMemo, useCallback } from 'react';
 import { useSSE } from '../hooks/useSSE';
 import { EventFlow } from './EventFlow';
+import { LiveResponses } from './LiveResponses';
 import type { ObservabilityEvent } from '../hooks/useSSE';
 
 interface EventListProps {
   agentName?: string;
 }
 
+// One id per tab, so a reload keeps its place among the agent's viewers
+function viewerIdentity(): { id: string; name: string } {
+  let id = sessionStorage.getItem('sentinel-viewer-id');
+  if (!id) {
+    id = crypto.randomUUID();
+    sessionStorage.setItem('sentinel-viewer-id', id);
+  }
+  let name = localStorage.getItem('sentinel-viewer-name');
+  if (!name) {
+    name = `guest-${id.slice(0, 4)}`;
+    localStorage.setItem('sentinel-viewer-name', name);
+  }
+  return { id, name };
+}
+
 export function EventList({ agentName }: EventListProps) {
   const [initialEvents, setInitialEvents] = useState<
     ObservabilityEvent[] | undefined
@@ -20,7 +36,7 @@ export function EventList({ agentName }: EventListProps) {
       return;
     }
 
-    fetch(`/api/agents/${encodeURIComponent(agentName)}/events`)
+    fetch(`/api/agents/${encodeURIComponent(agentName)}/events?fold=true`)
       .then((res) => res.json())
       .then((data: ObservabilityEvent[]) => {
         setInitialEvents(data);
@@ -31,10 +47,44 @@ export function EventList({ agentName }: EventListProps) {
       });
   }, [agentName]);
 
+  const [viewer] = useState(viewerIdentity);
   const sseUrl = agentName
-    ? `/api/events?agent=${encodeURIComponent(agentName)}`
-    : '/api/events';
-  const { events, connected, error, clearEvents } = useSSE(sseUrl, initialEvents);
+    ? `/api/events?fold=true&agent=${encodeURIComponent(agentName)}&viewer_id=${encodeURIComponent(viewer.id)}&viewer=${encodeURIComponent(viewer.name)}`
+    : '/api/events?fold=true';
+  const { events, viewers, live, connected, error, clearEvents } = useSSE(
+    sseUrl,
+    initialEvents
+  );
+
+  const otherViewers = useMemo(
+    () => viewers.filter((v) => v.id !== viewer.id),
+    [viewers, viewer.id]
+  );
+
+  // Where everyone else is looking: event id -> viewer names
+  const viewerCursors = useMemo(() => {
+    const cursors = new Map<string, string[]>();
+    for (const v of otherViewers) {
+      if (!v.cursor) continue;
+      cursors.set(v.cursor, [...(cursors.get(v.cursor) ?? []), v.name]);
+    }
+    return cursors;
+  }, [otherViewers]);
+
+  const shareCursor = useCallback(
+    (eventId: string | null) => {
+      if (!agentName) return;
+      fetch(
+        `/api/agents/${encodeURIComponent(agentName)}/viewers/${encodeURIComponent(viewer.id)}/cursor`,
+        {
+          method: 'POST',
+          headers: { 'Content-Type': 'application/json' },
+          body: JSON.stringify({ event_id: eventId }),
+        }
+      ).catch((err) => console.error('Failed to share cursor:', err));
+    },
+    [agentName, viewer.id]
+  );
 
   return (
     <div
@@ -68,6 +118,14 @@ export function EventList({ agentName }: EventListProps) {
           <span style={{ color: '#666', fontSize: '12px' }}>
             {events.length} event(s)
           </span>
+          {otherViewers.length > 0 && (
+            <span
+              title={otherViewers.map((v) => v.name).join(', ')}
+              style={{ color: '#38bdf8', fontSize: '12px' }}
+            >
+              ◉ {otherViewers.length} also watching
+            </span>
+          )}
         </div>
         <div style={{ display: 'flex', gap: '12px', alignItems: 'center' }}>
           <span
@@ -114,6 +172,8 @@ export function EventList({ agentName }: EventListProps) {
         </div>
       )}
 
+      <LiveResponses live={live} />
+
       <div style={{ flex: 1, position: 'relative' }}>
         {events.length === 0 ? (
           <div style={{
@@ -126,7 +186,12 @@ export function EventList({ agentName }: EventListProps) {
             Waiting for events...
           </div>
         ) : (
-          <EventFlow events={events} followLatest={followLatest} />
+          <EventFlow
+            events={events}
+            followLatest={followLatest}
+            viewerCursors={viewerCursors}
+            onSelect={shareCursor}
+          />
         )}
       </div>
     </div>
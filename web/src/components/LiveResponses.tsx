@@ -0,0 +1,65 @@
+import type { LiveResponse } from '../hooks/useSSE';
+
+interface LiveResponsesProps {
+  live: Record<string, LiveResponse>;
+}
+
+// How much of a response's latest text to show while it streams
+const TAIL_CHARS = 240;
+
+function tail(text: string): string {
+  const trimmed = text.trimEnd();
+  return trimmed.length > TAIL_CHARS ? `…${trimmed.slice(-TAIL_CHARS)}` : trimmed;
+}
+
+// Responses still being written, until their recorded events arrive
+export function LiveResponses({ live }: LiveResponsesProps) {
+  const responses = Object.entries(live);
+  if (responses.length === 0) {
+    return null;
+  }
+
+  return (
+    <div
+      style={{
+        borderBottom: '1px solid #333',
+        padding: '8px 16px',
+        display: 'flex',
+        flexDirection: 'column',
+        gap: '6px',
+        flexShrink: 0,
+        maxHeight: '30vh',
+        overflowY: 'auto',
+      }}
+    >
+      {responses.map(([id, response]) => (
+        <div key={id} style={{ fontSize: '12px' }}>
+          <div style={{ display: 'flex', gap: '8px', alignItems: 'center', color: '#4ade80' }}>
+            <span>● streaming</span>
+            <span style={{ color: '#fff' }}>{response.agent ?? 'unknown agent'}</span>
+            {response.thinking && !response.text && (
+              <span style={{ color: '#a78bfa' }}>thinking…</span>
+            )}
+            {response.tool_calls.length > 0 && (
+              <span style={{ color: '#f59e0b' }}>
+                {response.tool_calls.join(', ')}
+              </span>
+            )}
+          </div>
+          {response.text && (
+            <div
+              style={{
+                color: '#ccc',
+                whiteSpace: 'pre-wrap',
+                fontFamily: 'monospace',
+                marginTop: '2px',
+              }}
+            >
+              {tail(response.text)}
+            </div>
+          )}
+        </div>
+      ))}
+    </div>
+  );
+}
@@ -5,6 +5,8 @@ import type { ObservabilityEvent } from '../../hooks/useSSE';
 export interface EventNodeData extends Record<string, unknown> {
   event: ObservabilityEvent;
   isLatest?: boolean;
+  /** Other dashboard viewers who have this event selected */
+  viewers?: string[];
 }
 
 function truncate(text: string, maxLen: number): string {
@@ -17,15 +19,19 @@ interface EventNodeProps {
 }
 
 function EventNodeComponent({ data }: EventNodeProps) {
-  const { event, isLatest } = data;
+  const { event, isLatest, viewers } = data;
   const payload = event.payload;
   const isUserMessage = payload.type === 'user_message';
-  const arrow = isUserMessage ? '→' : '←';
-  const color = isUserMessage ? '#4ade80' : '#60a5fa';
+  const isAnnotation = payload.type === 'annotation';
+  const kind = isUserMessage ? 'request' : isAnnotation ? 'annotation' : 'response';
+  const arrow = isUserMessage ? '→' : isAnnotation ? '◆' : '←';
+  const color = isUserMessage ? '#4ade80' : isAnnotation ? '#f472b6' : '#60a5fa';
   const time = new Date(event.timestamp).toLocaleTimeString();
 
   const agent = event.agent;
-  const model = payload.model;
+  const model = 'model' in payload ? payload.model : null;
+  // The phase an orchestrator put the session in
+  const phase = isAnnotation ? payload.phase : null;
   const usage = payload.type === 'assistant_response' ? payload.usage : null;
 
   // Detect "awaiting input" state: latest response with pending tool calls
@@ -37,6 +43,10 @@ function EventNodeComponent({ data }: EventNodeProps) {
   let summary = '';
   if (payload.type === 'user_message') {
     summary = truncate(payload.text, 60);
+  } else if (payload.type === 'annotation') {
+    summary = truncate(payload.message ?? '', 60);
+  } else if (payload.type !== 'assistant_response') {
+    summary = '';
   } else if (payload.text) {
     summary = truncate(payload.text, 60);
   } else if (payload.tool_calls.length > 0) {
@@ -48,7 +58,7 @@ function EventNodeComponent({ data }: EventNodeProps) {
       <Handle type="target" position={Position.Top} style={{ visibility: 'hidden' }} />
       <div
         className="event-node"
-        data-type={isUserMessage ? 'request' : 'response'}
+        data-type={kind}
         style={{
           padding: '10px 14px',
           backgroundColor: '#1e1e1e',
@@ -64,7 +74,20 @@ function EventNodeComponent({ data }: EventNodeProps) {
         <div style={{ display: 'flex', gap: '8px', alignItems: 'center', flexWrap: 'wrap' }}>
           <span style={{ color: '#888' }}>{time}</span>
           <span style={{ color, fontWeight: 'bold' }}>{arrow}</span>
-          <span style={{ color }}>{isUserMessage ? 'request' : 'response'}</span>
+          <span style={{ color }}>{kind}</span>
+          {phase && (
+            <span
+              style={{
+                color: '#f472b6',
+                backgroundColor: 'rgba(244, 114, 182, 0.15)',
+                padding: '1px 6px',
+                borderRadius: '4px',
+                fontSize: '11px',
+              }}
+            >
+              {phase}
+            </span>
+          )}
           {isAwaitingInput && (
             <span
               className="awaiting-input-badge"
@@ -93,6 +116,20 @@ function EventNodeComponent({ data }: EventNodeProps) {
               {agent}
             </span>
           )}
+          {viewers && viewers.length > 0 && (
+            <span
+              title={`Selected by ${viewers.join(', ')}`}
+              style={{
+                color: '#38bdf8',
+                backgroundColor: 'rgba(56, 189, 248, 0.15)',
+                padding: '1px 6px',
+                borderRadius: '4px',
+                fontSize: '11px',
+              }}
+            >
+              ◉ {viewers.join(', ')}
+            </span>
+          )}
           {model && (
             <span
               style={{
@@ -11,12 +11,39 @@ export interface Usage {
   output_tokens: number | null;
   cache_read_tokens: number | null;
   cache_creation_tokens: number | null;
+  cost_usd: number | null;
 }
 
 export interface UserMessage {
   type: 'user_message';
   model: string | null;
   text: string;
+  tool_results: ToolResult[];
+  response_id?: string | null;
+  project?: string | null;
+  run_id?: string | null;
+  branch?: Branch;
+}
+
+export interface Branch {
+  kind: 'retry' | 'fork';
+  /** Request event the history is shared with */
+  from: string;
+  /** Messages shared with it */
+  shared: number;
+}
+
+export interface ToolResult {
+  tool_use_id: string;
+  content: string;
+  is_error?: boolean;
+}
+
+export interface FoldNote {
+  at: number;
+  hidden_lines: number;
+  lines: number;
+  bytes: number;
 }
 
 export interface AssistantResponse {
@@ -29,9 +56,96 @@ export interface AssistantResponse {
   text: string | null;
   tool_calls: ToolCall[];
   usage: Usage | null;
+  first_byte_ms?: number | null;
+  first_token_ms?: number | null;
+  latency_ms?: number | null;
+  idempotency_key?: string | null;
+  request_id?: string | null;
+  redo_of?: string | null;
+  upstream_key?: string | null;
+  project?: string | null;
+  run_id?: string | null;
+}
+
+export interface Alert {
+  type: 'alert';
+  kind: string;
+  message: string;
+}
+
+export interface Violation {
+  type: 'violation';
+  source: string;
+  tool: string;
+  tool_use_id: string | null;
+  message: string;
+  blocked: boolean;
+  mismatches?: SchemaMismatch[];
 }
 
-export type Payload = UserMessage | AssistantResponse;
+export interface SchemaMismatch {
+  path: string;
+  message: string;
+  found: string;
+}
+
+export interface SessionClosed {
+  type: 'session_closed';
+  started_at: string;
+  ended_at: string;
+  duration_secs: number;
+  turns: number;
+  requests: number;
+  errors: number;
+  input_tokens: number;
+  output_tokens: number;
+  cost_usd: number;
+}
+
+export interface StreamAborted {
+  type: 'stream_aborted';
+  response_id: string;
+  received_bytes: number;
+  elapsed_ms: number;
+}
+
+// A turn still running past --turn-sla, as it stood then
+export interface TurnSnapshot {
+  type: 'turn_snapshot';
+  response_id: string;
+  sla_ms: number;
+  elapsed_ms: number;
+  phase: 'waiting_for_headers' | 'streaming' | 'reading_body';
+  model: string | null;
+  streaming: boolean;
+  request_bytes: number;
+  messages: number | null;
+  upstream_host: string | null;
+  attempts: number;
+  first_byte_ms: number | null;
+  first_token_ms: number | null;
+  received_bytes: number;
+  chunks: number;
+  idle_ms: number;
+}
+
+// A phase change or note an orchestrator posted to the session
+export interface Annotation {
+  type: 'annotation';
+  phase: string | null;
+  message: string | null;
+  data?: unknown;
+}
+
+export type Payload =
+  | UserMessage
+  | AssistantResponse
+  | Alert
+  | Violation
+  | SessionClosed
+  | StreamAborted
+  | TurnSnapshot
+  | Annotation;
 
 export interface ObservabilityEvent {
   seq: number | null;
@@ -40,14 +154,45 @@ export interface ObservabilityEvent {
   session_id: string | null;
   agent: string | null;
   topic: string | null;
+  provider: string | null;
   payload: Payload;
+  // What each tool result folded to its first and last lines held in full,
+  // by tool_use_id
+  folds?: Record<string, FoldNote>;
+}
+
+export interface Viewer {
+  id: string;
+  name: string;
+  cursor: string | null;
+  joined_at: string;
+}
+
+// What a streaming response added since its last progress message
+export interface ResponseProgress {
+  event_id: string;
+  session_id: string | null;
+  agent: string | null;
+  text: string;
+  thinking: string;
+  tool_calls: string[];
+}
+
+// A streaming response so far, until its event arrives
+export interface LiveResponse {
+  agent: string | null;
+  text: string;
+  thinking: string;
+  tool_calls: string[];
 }
 
 interface SSeMessageEnvelope {
-  type: 'observability_event' | 'resync_required';
+  type: 'observability_event' | 'resync_required' | 'presence' | 'response_progress';
   payload:
     | { event: ObservabilityEvent }
-    | { events_dropped: number; latest_seq: number };
+    | { events_dropped: number; latest_seq: number }
+    | { agent: string; viewers: Viewer[] }
+    | { progress: ResponseProgress };
 }
 
 export function useSSE(url: string, initialEvents?: ObservabilityEvent[]) {
@@ -63,6 +208,8 @@ export function useSSE(url: string, initialEvents?: ObservabilityEvent[]) {
     }
     return ids;
   });
+  const [viewers, setViewers] = useState<Viewer[]>([]);
+  const [live, setLive] = useState<Record<string, LiveResponse>>({});
   const [connected, setConnected] = useState(false);
   const [error, setError] = useState<string | null>(null);
 
@@ -107,8 +254,34 @@ export function useSSE(url: string, initialEvents?: ObservabilityEvent[]) {
           }
           seenIds.add(event.id);
           setEvents((prev) => [...prev, event]);
+          setLive((prev) => {
+            if (!(event.id in prev)) return prev;
+            const rest = { ...prev };
+            delete rest[event.id];
+            return rest;
+          });
         } else if (envelope.type === 'resync_required') {
           console.warn('Events dropped, resync required:', envelope.payload);
+        } else if (envelope.type === 'presence') {
+          const { viewers } = envelope.payload as { viewers: Viewer[] };
+          setViewers(viewers);
+        } else if (envelope.type === 'response_progress') {
+          const { progress } = envelope.payload as { progress: ResponseProgress };
+          if (seenIds.has(progress.event_id)) {
+            return;
+          }
+          setLive((prev) => {
+            const soFar = prev[progress.event_id];
+            return {
+              ...prev,
+              [progress.event_id]: {
+                agent: progress.agent,
+                text: (soFar?.text ?? '') + progress.text,
+                thinking: (soFar?.thinking ?? '') + progress.thinking,
+                tool_calls: [...(soFar?.tool_calls ?? []), ...progress.tool_calls],
+              },
+            };
+          });
         }
       } catch (err) {
         console.error('Failed to parse event:', err);
@@ -122,5 +295,5 @@ export function useSSE(url: string, initialEvents?: ObservabilityEvent[]) {
     };
   }, [url, seenIds]);
 
-  return { events, connected, error, clearEvents };
+  return { events, viewers, live, connected, error, clearEvents };
 }